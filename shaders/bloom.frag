@@ -0,0 +1,48 @@
+#version 450
+
+layout(location = 0) in vec2    fragUV;
+
+layout(binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Bloom {
+    vec2    texel;          // size of a texel of the source
+    float   threshold;
+    uint    mode;           // 0 bright pass and downsample, 1 downsample, 2 upsample
+} bloom;
+
+layout(location = 0) out vec4   outColor;
+
+vec3 tap(vec2 offset) {
+    return texture(source, fragUV + offset * bloom.texel).rgb;
+}
+
+// 13 taps around the pixel weighted as five overlapping boxes, which keep bright specks from flickering
+// as they move across the texels (Jimenez 2014)
+vec3 downsample() {
+    vec3 corners = tap(vec2(-2, -2)) + tap(vec2(2, -2)) + tap(vec2(-2, 2)) + tap(vec2(2, 2));
+    vec3 edges = tap(vec2(0, -2)) + tap(vec2(-2, 0)) + tap(vec2(2, 0)) + tap(vec2(0, 2));
+    vec3 inner = tap(vec2(-1, -1)) + tap(vec2(1, -1)) + tap(vec2(-1, 1)) + tap(vec2(1, 1));
+    return tap(vec2(0)) * 0.125 + corners * 0.03125 + edges * 0.0625 + inner * 0.125;
+}
+
+// a 3x3 tent, for the blur to widen as it comes back up the levels
+vec3 upsample() {
+    vec3 sum = tap(vec2(0)) * 4.0;
+    sum += (tap(vec2(-1, 0)) + tap(vec2(1, 0)) + tap(vec2(0, -1)) + tap(vec2(0, 1))) * 2.0;
+    sum += tap(vec2(-1, -1)) + tap(vec2(1, -1)) + tap(vec2(-1, 1)) + tap(vec2(1, 1));
+    return sum / 16.0;
+}
+
+void main() {
+    if (bloom.mode == 2) {
+        outColor = vec4(upsample(), 1.0);
+        return;
+    }
+    vec3 color = downsample();
+    if (bloom.mode == 0) {
+        // only what is brighter than the threshold, by how much brighter
+        float brightness = max(color.r, max(color.g, color.b));
+        color *= max(brightness - bloom.threshold, 0.0) / max(brightness, 1e-4);
+    }
+    outColor = vec4(color, 1.0);
+}
@@ -0,0 +1,25 @@
+#version 450
+
+layout(binding = 0) uniform sampler2D image;
+
+layout(push_constant) uniform DebugConstants {
+    vec2    range;          // values shown black and white
+    vec2    projection;     // the projection's (2, 2) and (2, 3) entries
+    uint    mode;           // 0 colors as they are, 1 the first channel, 2 depth buffer distances
+} debug;
+
+layout(location = 0) in vec2    fragUV;
+
+layout(location = 0) out vec4   outColor;
+
+void main() {
+    vec4 texel = texture(image, fragUV);
+    if (debug.mode == 0) {
+        outColor = vec4(texel.rgb, 1.0);
+        return;
+    }
+    // the depth buffer back into distances in front of the eye, where the projection put them
+    float value = debug.mode == 2 ? debug.projection.y / (texel.r + debug.projection.x) : texel.r;
+    float gray = clamp((value - debug.range.x) / max(debug.range.y - debug.range.x, 1e-6), 0.0, 1.0);
+    outColor = vec4(vec3(gray), 1.0);
+}
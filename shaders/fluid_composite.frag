@@ -0,0 +1,77 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+    vec3    baseLight;
+	float   ambientStrength;
+    vec3    lightPos;
+    float   specularStrength;
+    vec3    viewPos;
+} ubo;
+
+layout(binding = 1) uniform sampler2D depthImage;
+layout(binding = 2) uniform sampler2D thicknessImage;
+layout(binding = 3) uniform sampler2D sceneImage;
+
+layout(push_constant) uniform CompositeConstants {
+    vec3    absorption;     // per meter of fluid
+    float   indexOfRefraction;
+} composite;
+
+layout(location = 0) in vec2    fragUV;
+
+layout(location = 0) out vec4   outColor;
+
+// the eye-space point a pixel of the depth image shows
+vec3 eyePos(vec2 uv, float d) {
+    vec2 ndc = uv * 2.0 - 1.0;
+    return vec3(ndc.x * d / ubo.proj[0][0], ndc.y * d / ubo.proj[1][1], -d);
+}
+
+void main() {
+    float d = texture(depthImage, fragUV).r;
+    if (d <= 0.0) {
+        outColor = texture(sceneImage, fragUV);
+        return;
+    }
+    vec2 texel = 1.0 / vec2(textureSize(depthImage, 0));
+    vec3 pos = eyePos(fragUV, d);
+    // differences toward the neighbor on the same side of any edge, the one nearer in depth
+    vec3 ddx = eyePos(fragUV + vec2(texel.x, 0.0), texture(depthImage, fragUV + vec2(texel.x, 0.0)).r) - pos;
+    vec3 ddx2 = pos - eyePos(fragUV - vec2(texel.x, 0.0), texture(depthImage, fragUV - vec2(texel.x, 0.0)).r);
+    if (abs(ddx2.z) < abs(ddx.z)) {
+        ddx = ddx2;
+    }
+    vec3 ddy = eyePos(fragUV + vec2(0.0, texel.y), texture(depthImage, fragUV + vec2(0.0, texel.y)).r) - pos;
+    vec3 ddy2 = pos - eyePos(fragUV - vec2(0.0, texel.y), texture(depthImage, fragUV - vec2(0.0, texel.y)).r);
+    if (abs(ddy2.z) < abs(ddy.z)) {
+        ddy = ddy2;
+    }
+    vec3 norm = normalize(cross(ddy, ddx));
+    vec3 viewDir = normalize(-pos);
+    if (dot(norm, viewDir) < 0.0) {
+        norm = -norm;
+    }
+    vec3 lightDir = normalize((ubo.view * vec4(ubo.lightPos, 1.0)).xyz - pos);
+    vec3 diffuse = max(dot(norm, lightDir), 0.0) * ubo.baseLight;
+    vec3 halfwayDir = normalize(lightDir + viewDir);
+    float spec = pow(max(dot(norm, halfwayDir), 0.0), 64);
+    vec3 specular = ubo.specularStrength * spec * ubo.baseLight;
+    vec3 reflected = (ubo.ambientStrength + diffuse) * ubo.baseLight;
+    // the scene behind, seen along the ray bent through the surface as far as the fluid is thick, and
+    // dimmed by Beer-Lambert's law on the way
+    float thickness = texture(thicknessImage, fragUV).r;
+    vec3 bent = refract(-viewDir, norm, 1.0 / composite.indexOfRefraction);
+    vec2 uv = fragUV;
+    if (bent != vec3(0.0)) {
+        vec4 clip = ubo.proj * vec4(pos + bent * thickness, 1.0);
+        uv = clamp(clip.xy / clip.w * 0.5 + 0.5, 0.0, 1.0);
+    }
+    vec3 transmitted = texture(sceneImage, uv).rgb * exp(-composite.absorption * thickness);
+    // Schlick's Fresnel term: grazing views see more reflection and less of what lies behind
+    float f0 = pow((composite.indexOfRefraction - 1.0) / (composite.indexOfRefraction + 1.0), 2.0);
+    float fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(norm, viewDir), 0.0), 5.0);
+    outColor = vec4(mix(transmitted, reflected, fresnel) + specular, 1.0);
+}
@@ -0,0 +1,30 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+    vec3    baseLight;
+	float   ambientStrength;
+    vec3    lightPos;
+    float   specularStrength;
+    vec3    viewPos;
+} ubo;
+
+layout(location = 0) in vec3    fragCenter;
+layout(location = 1) in float   fragRadius;
+
+layout(location = 0) out float  outDepth;       // distance in front of the eye, 0 for none
+
+void main() {
+    // the near half of the particle's sphere, so that neighbors overlap as the fluid would
+    vec2 xy = 2.0 * gl_PointCoord - 1.0;
+    float r2 = dot(xy, xy);
+    if (r2 > 1.0) {
+        discard;
+    }
+    vec3 eye = fragCenter + vec3(0.0, 0.0, sqrt(1.0 - r2) * fragRadius);
+    vec4 clip = ubo.proj * vec4(eye, 1.0);
+    gl_FragDepth = clip.z / clip.w;
+    outDepth = -eye.z;
+}
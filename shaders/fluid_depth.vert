@@ -0,0 +1,38 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+    vec3    baseLight;
+	float   ambientStrength;
+    vec3    lightPos;
+    float   specularStrength;
+    vec3    viewPos;
+} ubo;
+
+layout(push_constant) uniform DepthConstants {
+    float   radius;         // of a particle at its phase's rest mass
+    float   viewportHeight;
+    vec2    pointSizeRange;
+    vec4    phaseMasses;
+} depth;
+
+// one particle per vertex, straight out of the particle buffer
+layout(location = 0) in vec3    inPos;
+layout(location = 1) in uint    inPhase;
+layout(location = 7) in float   inMass;
+
+layout(location = 0) out vec3   fragCenter;     // in eye space
+layout(location = 1) out float  fragRadius;
+
+void main() {
+    vec4 eye = ubo.view * vec4(inPos, 1.0);
+    gl_Position = ubo.proj * eye;
+    // sized like the particle sprites, so the surface covers the particles it is made of
+    float radius = depth.radius * pow(inMass / max(depth.phaseMasses[min(inPhase, 3u)], 1e-12), 1.0 / 3.0);
+    float size = radius * abs(ubo.proj[1][1]) * depth.viewportHeight / gl_Position.w;
+    gl_PointSize = clamp(size, depth.pointSizeRange.x, depth.pointSizeRange.y);
+    fragCenter = eye.xyz;
+    fragRadius = radius;
+}
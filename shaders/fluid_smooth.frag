@@ -0,0 +1,43 @@
+#version 450
+
+layout(binding = 1) uniform sampler2D depthImage;
+
+layout(push_constant) uniform SmoothConstants {
+    float   filterRadius;   // in meters
+    float   depthFalloff;   // in meters
+    float   pixelsPerMeter; // at a meter from the eye
+    float   maxPixels;
+} smoothing;
+
+layout(location = 0) in vec2    fragUV;
+
+layout(location = 0) out float  outDepth;
+
+void main() {
+    float d = texture(depthImage, fragUV).r;
+    if (d <= 0.0) {
+        outDepth = 0.0;
+        return;
+    }
+    // a bilateral filter: neighbors count less the farther away they are on screen and in depth, so
+    // the bumps between particles flatten while the silhouette against what lies behind stays sharp
+    int radius = int(min(smoothing.filterRadius * smoothing.pixelsPerMeter / d, smoothing.maxPixels));
+    vec2 texel = 1.0 / vec2(textureSize(depthImage, 0));
+    float spatial = 1.0 / max(float(radius * radius) / 4.0, 1.0);
+    float range = 1.0 / (smoothing.depthFalloff * smoothing.depthFalloff);
+    float sum = 0.0;
+    float weights = 0.0;
+    for (int y = -radius; y <= radius; y++) {
+        for (int x = -radius; x <= radius; x++) {
+            float s = texture(depthImage, fragUV + vec2(x, y) * texel).r;
+            if (s <= 0.0) {
+                continue;
+            }
+            float diff = s - d;
+            float w = exp(-float(x * x + y * y) * spatial - diff * diff * range);
+            sum += s * w;
+            weights += w;
+        }
+    }
+    outDepth = sum / weights;
+}
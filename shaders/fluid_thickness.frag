@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec3    fragCenter;
+layout(location = 1) in float   fragRadius;
+
+layout(location = 0) out float  outThickness;   // added up over the particles in front of the scene
+
+void main() {
+    // the length of the view ray through the particle's sphere
+    vec2 xy = 2.0 * gl_PointCoord - 1.0;
+    float r2 = dot(xy, xy);
+    if (r2 > 1.0) {
+        discard;
+    }
+    outThickness = 2.0 * sqrt(1.0 - r2) * fragRadius;
+}
@@ -0,0 +1,18 @@
+#version 450
+
+layout(location = 0) in vec2    fragCorner;
+layout(location = 1) in float   fragFade;
+
+layout(location = 0) out vec4   outColor;
+
+// brightness a fresh particle adds at its center
+const float INTENSITY = 0.35;
+
+void main() {
+    float r2 = dot(fragCorner, fragCorner);
+    if (r2 > 1.0) {
+        discard;
+    }
+    // blended additively, so overlapping foam piles up towards white
+    outColor = vec4(vec3(INTENSITY * fragFade * (1.0 - r2)), 0.0);
+}
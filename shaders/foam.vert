@@ -0,0 +1,43 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+    vec3    baseLight;
+	float   ambientStrength;
+    vec3    lightPos;
+    float   specularStrength;
+    vec3    viewPos;
+} ubo;
+
+layout(push_constant) uniform FoamDraw {
+    float   radius;     // of a foam particle; spray is drawn smaller
+    float   lifetime;
+} draw;
+
+// one foam particle per instance
+layout(location = 0) in vec3    inPos;
+layout(location = 1) in float   inLife;
+layout(location = 2) in uint    inKind;
+
+layout(location = 0) out vec2   fragCorner;
+layout(location = 1) out float  fragFade;
+
+const uint KIND_SPRAY = 0;
+const vec2 CORNERS[6] = vec2[](vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+    vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0));
+
+void main() {
+    fragCorner = CORNERS[gl_VertexIndex];
+    fragFade = clamp(inLife / draw.lifetime, 0.0, 1.0);
+    // dead particles collapse to a point outside the view volume
+    if (inLife <= 0.0) {
+        gl_Position = vec4(0.0, 0.0, 2.0, 1.0);
+        return;
+    }
+    // a camera-facing square, in the frame the fluid is simulated in, which the world frame is
+    float radius = inKind == KIND_SPRAY ? 0.6 * draw.radius : draw.radius;
+    vec4 center = ubo.view * vec4(inPos, 1.0);
+    gl_Position = ubo.proj * (center + vec4(radius * fragCorner, 0.0, 0.0));
+}
@@ -0,0 +1,33 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+} ubo;
+
+layout(push_constant) uniform GizmoConstants {
+    vec4    colors[3];      // of the X, Y and Z arrows; the first's w is the arrows' length
+} gizmo;
+
+layout(location = 0) out vec3   fragColor;
+
+void main() {
+    // ten vertices an axis: the shaft, then four lines from the tip back to the rim of the head
+    int axis = gl_VertexIndex / 10;
+    int k = gl_VertexIndex % 10;
+    float len = gizmo.colors[0].w;
+    vec3 pos = vec3(0.0);
+    if (k == 1 || (k >= 2 && k % 2 == 0)) {
+        pos[axis] = len;
+    } else if (k >= 2) {
+        int side = (k - 2) / 2;
+        pos[axis] = 0.8 * len;
+        pos[(axis + 1 + side / 2) % 3] = (side % 2 == 0 ? 0.06 : -0.06) * len;
+    }
+    // turned by the camera's rotation alone and drawn flat, y flipped as the projection flips it
+    vec3 eye = mat3(ubo.view) * pos;
+    gl_Position = vec4(eye.x, -eye.y, 0.5, 1.0);
+    // sRGB to linear, as the mesh shader does it
+    fragColor = pow(gizmo.colors[axis].rgb, vec3(2.2));
+}
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location = 0) in float   fragScalar;
+
+layout(set = 2, binding = 0) uniform sampler1D colormap;
+
+layout(location = 0) out vec4   outColor;
+
+void main() {
+    outColor = vec4(texture(colormap, fragScalar).rgb, 1.0);
+}
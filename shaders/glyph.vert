@@ -0,0 +1,42 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+} ubo;
+
+#include "particle.glsl"
+
+// the solver's set: the latest particles and the counter buffer, which starts with their count
+layout(std430, set = 1, binding = 0) readonly buffer Particles {
+    Particle particles[];
+};
+
+layout(std430, set = 1, binding = 3) readonly buffer Counter {
+    uint    liveCount;
+};
+
+layout(push_constant) uniform GlyphConstants {
+    float   scale;      // seconds of travel a glyph spans
+    uint    stride;     // every how many particles one gets a glyph
+    vec2    speedRange; // speeds at the ends of the colormap
+} glyph;
+
+layout(location = 0) out float  fragScalar;
+
+void main() {
+    // one line per instance, from the particle at vertex 0 to where its velocity takes it at vertex 1
+    uint index = gl_InstanceIndex * glyph.stride;
+    if (index >= liveCount) {
+        // both ends outside the clip volume, so nothing is drawn
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        fragScalar = 0.0;
+        return;
+    }
+    Particle p = particles[index];
+    vec3 pos = p.pos + float(gl_VertexIndex) * glyph.scale * p.vel;
+    gl_Position = ubo.proj * ubo.view * vec4(pos, 1.0);
+    float span = max(glyph.speedRange.y - glyph.speedRange.x, 1e-6);
+    fragScalar = clamp((length(p.vel) - glyph.speedRange.x) / span, 0.0, 1.0);
+}
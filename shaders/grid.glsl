@@ -0,0 +1,50 @@
+// Lookups in the neighbor grid the counting sort builds. The including shader declares sim, the
+// Counter block and the CellCounts and CellStarts buffers first.
+
+// grid cell holding p; cells are at least h wide, so the 27 around a particle cover its kernel support
+ivec3 cellCoord(vec3 p) {
+    return clamp(ivec3(floor((p - sim.gridOrigin.xyz) / sim.gridCell.xyz)), ivec3(0), ivec3(sim.gridDims.xyz) - 1);
+}
+
+uint cellIndex(ivec3 c) {
+    return (uint(c.z) * sim.gridDims.y + uint(c.y)) * sim.gridDims.x + uint(c.x);
+}
+
+const uint BOUNDARY_PERIODIC = 1;
+
+// neighbor cell coordinates wrap around periodic axes
+ivec3 wrapCell(ivec3 c) {
+    for (int a = 0; a < 3; a++) {
+        if (sim.boundaryKinds[a] == BOUNDARY_PERIODIC) {
+            c[a] = (c[a] + int(sim.gridDims[a])) % int(sim.gridDims[a]);
+        }
+    }
+    return c;
+}
+
+// a - b, taking the nearest periodic image of b along periodic axes
+vec3 separation(vec3 a, vec3 b) {
+    vec3 r = a - b;
+    vec3 extent = sim.domainMax.xyz - sim.domainMin.xyz;
+    for (int k = 0; k < 3; k++) {
+        if (sim.boundaryKinds[k] == BOUNDARY_PERIODIC) {
+            r[k] -= extent[k] * round(r[k] / extent[k]);
+        }
+    }
+    return r;
+}
+
+// particle index range of the n-th of the 27 cells around `cell`; n == 27 gives the particles appended
+// since the last sort, which the grid does not cover yet
+uvec2 neighborRange(ivec3 cell, int n) {
+    if (n == 27) {
+        return uvec2(sortedCount, liveCount);
+    }
+    ivec3 c = wrapCell(cell + ivec3(n % 3, n / 3 % 3, n / 9) - 1);
+    if (any(lessThan(c, ivec3(0))) || any(greaterThanEqual(c, ivec3(sim.gridDims.xyz)))) {
+        return uvec2(0);
+    }
+    uint id = cellIndex(c);
+    // a reset leaves the grid stale until the next sort, with nothing in it
+    return min(uvec2(cellStarts[id], cellStarts[id] + cellCounts[id]), uvec2(sortedCount));
+}
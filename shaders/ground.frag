@@ -0,0 +1,35 @@
+#version 450
+
+layout(push_constant) uniform GroundConstants {
+    vec4    color;          // w is the plane's height
+    vec4    lineColor;      // w is the minor line spacing
+    vec4    majorColor;     // w is the distance the grid fades out over
+    uint    majorEvery;
+} ground;
+
+layout(location = 0) in vec3    fragPos;
+layout(location = 1) in vec3    eyePos;
+
+layout(location = 0) out vec4   outColor;
+
+// how much of the pixel the grid lines `spacing` apart cover, a pixel wide however far they are
+float gridLines(vec2 pos, float spacing) {
+    vec2 coord = pos / spacing;
+    vec2 dist = abs(fract(coord - 0.5) - 0.5) / fwidth(coord);
+    return 1.0 - min(min(dist.x, dist.y), 1.0);
+}
+
+void main() {
+    float spacing = ground.lineColor.w;
+    float minor = gridLines(fragPos.xz, spacing);
+    float major = gridLines(fragPos.xz, spacing * float(max(ground.majorEvery, 1u)));
+    vec3 color = mix(ground.color.rgb, ground.lineColor.rgb, minor);
+    color = mix(color, ground.majorColor.rgb, major);
+    // faded into the background with distance, where the lines would alias into noise
+    float fade = 1.0 - smoothstep(0.25, 1.0, length(fragPos - eyePos) / ground.majorColor.w);
+    if (fade <= 0.0) {
+        discard;
+    }
+    // sRGB to linear, as the mesh shader does it
+    outColor = vec4(pow(color, vec3(2.2)), fade);
+}
@@ -0,0 +1,35 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+    vec3    baseLight;
+	float   ambientStrength;
+    vec3    lightPos;
+    float   specularStrength;
+    vec3    viewPos;
+    mat4    invView;
+    mat4    invProj;
+} ubo;
+
+layout(push_constant) uniform GroundConstants {
+    vec4    color;          // w is the plane's height
+    vec4    lineColor;      // w is the minor line spacing
+    vec4    majorColor;     // w is the distance the grid fades out over
+    uint    majorEvery;
+} ground;
+
+layout(location = 0) out vec3   fragPos;
+layout(location = 1) out vec3   eyePos;
+
+void main() {
+    // a quad as wide as the fade reaches, kept under the camera so its edge is never seen
+    vec3 eye = ubo.invView[3].xyz;
+    vec2 corner = vec2((gl_VertexIndex & 1) == 0 ? -1.0 : 1.0, (gl_VertexIndex & 2) == 0 ? -1.0 : 1.0);
+    vec3 pos = vec3(eye.x, ground.color.w, eye.z) + ground.majorColor.w * vec3(corner.x, 0.0, corner.y);
+    fragPos = pos;
+    eyePos = eye;
+    // the plane lies in the frame the fluid is simulated in, which the world frame is
+    gl_Position = ubo.proj * ubo.view * vec4(pos, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 450
+
+layout(location = 0) in vec2    fragUV;
+
+layout(set = 0, binding = 0) uniform sampler1D colormap;
+
+layout(location = 0) out vec4   outColor;
+
+void main() {
+    // a dark frame a pixel and a half wide, around the colormap from left to right
+    vec2 edge = min(fragUV, 1.0 - fragUV) / fwidth(fragUV);
+    if (min(edge.x, edge.y) < 1.5) {
+        outColor = vec4(pow(vec3(0.05), vec3(2.2)), 1.0);
+        return;
+    }
+    // as the particles sample it, so the bar matches their unlit colors
+    outColor = vec4(texture(colormap, fragUV.x).rgb, 1.0);
+}
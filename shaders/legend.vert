@@ -0,0 +1,13 @@
+#version 450
+
+layout(push_constant) uniform LegendConstants {
+    vec4 rect;      // corners in normalized device coordinates, the colormap's low end at xy
+} legend;
+
+layout(location = 0) out vec2   fragUV;
+
+void main() {
+    // the bar's four corners as a strip, from the vertex indices alone
+    fragUV = vec2(gl_VertexIndex & 1, gl_VertexIndex >> 1);
+    gl_Position = vec4(mix(legend.rect.xy, legend.rect.zw, fragUV), 0.0, 1.0);
+}
@@ -0,0 +1,17 @@
+#version 450
+
+layout(location = 0) in float   fragScalar;
+
+layout(set = 2, binding = 0) uniform sampler1D colormap;
+
+layout(push_constant) uniform OccupancyConstants {
+    uint    maxCells;
+    float   full;
+    float   opacity;
+} occupancy;
+
+layout(location = 0) out vec4   outColor;
+
+void main() {
+    outColor = vec4(texture(colormap, fragScalar).rgb, occupancy.opacity);
+}
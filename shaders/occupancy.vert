@@ -0,0 +1,50 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+} ubo;
+
+struct OccupiedCell {
+    vec3    min;        // low corner of the cell
+    uint    count;      // particles in it
+};
+
+// the solver's set: the occupied cells the occupancy pass compacted
+layout(std430, set = 1, binding = 28) readonly buffer Occupancy {
+    uint    vertexCount;
+    uint    instanceCount;
+    uint    firstVertex;
+    uint    firstInstance;
+    vec4    cellSize;
+    OccupiedCell cells[];
+};
+
+layout(push_constant) uniform OccupancyConstants {
+    uint    maxCells;
+    float   full;       // count at the top of the colormap
+    float   opacity;
+} occupancy;
+
+layout(location = 0) out float  fragScalar;
+
+// the cube's twelve triangles, as corners numbered by their x, y and z bits
+const uint CORNERS[36] = uint[36](
+    0, 2, 1, 1, 2, 3,   4, 5, 6, 5, 7, 6,   0, 1, 4, 1, 5, 4,
+    2, 6, 3, 3, 6, 7,   0, 4, 2, 2, 4, 6,   1, 3, 5, 3, 7, 5);
+
+void main() {
+    // cells past the cap were counted but never written
+    if (gl_InstanceIndex >= occupancy.maxCells) {
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        fragScalar = 0.0;
+        return;
+    }
+    OccupiedCell cell = cells[gl_InstanceIndex];
+    uint corner = CORNERS[gl_VertexIndex];
+    vec3 pos = cell.min + cellSize.xyz * vec3(corner & 1u, (corner >> 1) & 1u, (corner >> 2) & 1u);
+    fragScalar = clamp(float(cell.count) / occupancy.full, 0.0, 1.0);
+    // the grid lies in the frame the fluid is simulated in, which the world frame is
+    gl_Position = ubo.proj * ubo.view * vec4(pos, 1.0);
+}
@@ -0,0 +1,19 @@
+// Weighted blended order-independent transparency: the specialization constants and color targets the
+// fragment shaders share, and the weight each fragment adds to the accumulation target with.
+
+// true in the pipelines drawing into the order-independent transparency targets, which weigh each
+// fragment by its depth in units of OIT_DEPTH_SCALE meters
+layout(constant_id = 0) const bool OIT = false;
+layout(constant_id = 1) const float OIT_DEPTH_SCALE = 1.0;
+
+layout(location = 0) out vec4   outColor;
+layout(location = 1) out float  outReveal;      // written only into the transparency targets
+
+// the premultiplied color weighted for the accumulation target, nearer fragments weighing more (McGuire
+// and Bavoil's equation 7), and the alpha the revealage falls by
+void weigh(vec4 color) {
+    float z = 1.0 / gl_FragCoord.w / OIT_DEPTH_SCALE;
+    float w = color.a * clamp(10.0 / (1e-5 + z * z + pow(z / 40.0, 6.0)), 1e-2, 3e3);
+    outColor = vec4(color.rgb * color.a, color.a) * w;
+    outReveal = color.a;
+}
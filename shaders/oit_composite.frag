@@ -0,0 +1,19 @@
+#version 450
+
+layout(location = 0) in vec2    fragUV;
+
+layout(binding = 0) uniform sampler2D accumImage;
+layout(binding = 1) uniform sampler2D revealImage;
+
+layout(location = 0) out vec4   outColor;
+
+void main() {
+    // the share of the opaque scene the transparent fragments leave, 1 where there are none
+    float reveal = texture(revealImage, fragUV).r;
+    if (reveal >= 1.0) {
+        discard;
+    }
+    // their weighted average color, blended over the scene by what they hide of it
+    vec4 accum = texture(accumImage, fragUV);
+    outColor = vec4(accum.rgb / clamp(accum.a, 1e-4, 5e4), 1.0 - reveal);
+}
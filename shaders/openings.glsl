@@ -0,0 +1,35 @@
+// The inflow and outflow boxes the host writes per frame, and where a particle lies through them. The
+// including shader declares sim first.
+
+// inflow and outflow boxes, MAX_OPENINGS per frame in flight, each as its min corner (w: kind, 0 for
+// none), max corner and flow: the inflow velocity or the direction out of the outflow
+layout(std430, binding = 21) readonly buffer Openings {
+    vec4 openings[];
+};
+
+const uint MAX_OPENINGS = 8;
+const float OPENING_INFLOW = 1.0;
+const float OPENING_OUTFLOW = 2.0;
+
+uint openingBase(uint o) {
+    return (sim.frame * MAX_OPENINGS + o) * 3;
+}
+
+// how far p is through opening o along its flow, 0 at the upstream face and 1 at the downstream one;
+// kind is the opening's, or 0 when p lies beside the box across the flow
+float throughOpening(uint o, vec3 p, out float kind) {
+    uint base = openingBase(o);
+    vec3 lo = openings[base].xyz;
+    vec3 hi = openings[base + 1].xyz;
+    vec3 flow = openings[base + 2].xyz;
+    kind = openings[base].w;
+    int a = abs(flow.x) >= max(abs(flow.y), abs(flow.z)) ? 0 : (abs(flow.y) >= abs(flow.z) ? 1 : 2);
+    vec3 beside = max(lo - p, p - hi);
+    beside[a] = 0.0;
+    if (kind == 0.0 || any(greaterThan(beside, vec3(0.0)))) {
+        kind = 0.0;
+        return 0.0;
+    }
+    float s = (p[a] - lo[a]) / (hi[a] - lo[a]);
+    return flow[a] > 0.0 ? s : 1.0 - s;
+}
@@ -0,0 +1,36 @@
+#version 450
+
+layout(location = 0) in vec3    fragColor;
+layout(location = 1) in vec3    fragLightDir;   // in eye space
+layout(location = 2) in vec3    fragBaseLight;
+layout(location = 3) in float   ambientStrength;
+layout(location = 4) in float   specularStrength;
+layout(location = 5) in float   fragScalar;
+layout(location = 6) in float   fragAlpha;
+#include "sprite_coord.glsl"
+
+layout(set = 1, binding = 0) uniform sampler1D colormap;
+
+#include "oit.glsl"
+
+void main() {
+    // the point as the near half of a sphere, seen from the front
+    vec2 xy = 2.0 * SPRITE_COORD - 1.0;
+    float r2 = dot(xy, xy);
+    if (r2 > 1.0) {
+        discard;
+    }
+    vec3 norm = vec3(xy.x, -xy.y, sqrt(1.0 - r2));
+    vec3 lightDir = normalize(fragLightDir);
+    vec3 diffuse = max(dot(norm, lightDir), 0.0) * fragBaseLight;
+    vec3 halfwayDir = normalize(lightDir + vec3(0.0, 0.0, 1.0));
+    float spec = pow(max(dot(norm, halfwayDir), 0.0), 32);
+    vec3 specular = specularStrength * spec * fragBaseLight;
+    vec3 lightColor = (ambientStrength + diffuse + specular) * fragBaseLight;
+    // the colormap decodes its color to linear as it is sampled, as the vertex shader does the others
+    vec3 color = fragScalar < 0.0 ? fragColor : texture(colormap, fragScalar).rgb;
+    outColor = vec4(color * lightColor, fragAlpha);
+    if (OIT) {
+        weigh(outColor);
+    }
+}
@@ -0,0 +1,14 @@
+// The particle as the solver's storage buffers hold it, laid out like `Particle` on the CPU.
+
+struct Particle {
+    vec3    pos;
+    float   density;
+    vec3    vel;
+    float   pressure;
+    vec3    force;
+    float   mass;
+    uint    phase;
+    uint    flags;      // bit 0: on the free surface, as of the last surface pass
+    uint    id;         // names the particle for its whole life, whatever buffer slot it is in
+    float   divergence; // SPH divergence of the velocity, as of the last divergence pass
+};
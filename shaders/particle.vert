@@ -0,0 +1,133 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+    vec3    baseLight;
+	float   ambientStrength;
+    vec3    lightPos;
+    float   specularStrength;
+    vec3    viewPos;
+} ubo;
+
+layout(push_constant) uniform SpriteConstants {
+    vec4    phaseColors[4];     // w is the phase's rest mass
+    vec4    surfaceTint;    // w 1 to tint the surface particles
+    vec4    selectedColor;  // w: opacity of the particles
+    float   pixelRadius;    // of a particle at its phase's rest mass, times the viewport's height
+    float   fullDensity;    // at which a particle is drawn at full opacity; 0 for opaque ones
+    float   viewportHeight; // in pixels
+    uint    selected;
+    uint    scalar;         // 0 none, 1 density, 2 speed, 3 pressure, 4 divergence
+    uint    pad;
+    vec2    scalarRange;    // values at the ends of the colormap
+} sprite;
+
+// the smallest and largest point the device draws
+#define SIM_PARAMS_SET 2
+#include "sim_params.glsl"
+#include "particle.glsl"
+
+#ifdef SPHERES
+// one quad per instance, made up from the particle of its slot; the solver's set holds the latest
+// particles and the counter buffer, which starts with their count
+layout(std430, set = 3, binding = 0) readonly buffer Particles {
+    Particle particles[];
+};
+
+layout(std430, set = 3, binding = 3) readonly buffer Counter {
+    uint    liveCount;
+};
+#else
+// one particle per vertex, straight out of the particle buffer
+layout(location = 0) in vec3    inPos;
+layout(location = 1) in uint    inPhase;
+layout(location = 2) in uint    inFlags;
+layout(location = 3) in uint    inId;
+layout(location = 4) in float   inDensity;
+layout(location = 5) in vec3    inVel;
+layout(location = 6) in float   inPressure;
+layout(location = 7) in float   inMass;
+layout(location = 8) in float   inDivergence;
+#endif
+layout(location = 9) in vec4    inHighlight;    // color of a neighbor of the selected particle, a 0 for none
+
+layout(location = 0) out vec3   fragColor;
+layout(location = 1) out vec3   fragLightDir;
+layout(location = 2) out vec3   fragBaseLight;
+layout(location = 3) out float  ambientStrength;
+layout(location = 4) out float  specularStrength;
+layout(location = 5) out float  fragScalar;     // where on the colormap, negative to keep fragColor
+layout(location = 6) out float  fragAlpha;
+#ifdef SPHERES
+layout(location = 7) out vec2   fragCorner;     // where on the quad, as gl_PointCoord is on a point
+#endif
+
+const uint SURFACE = 1;
+
+// the same depth whether drawn for the depth pre-pass or shaded over it
+invariant gl_Position;
+
+void main() {
+#ifdef SPHERES
+    if (gl_InstanceIndex >= liveCount) {
+        // all four corners outside the clip volume, so nothing is drawn
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        return;
+    }
+    Particle p = particles[gl_InstanceIndex];
+#else
+    Particle p = Particle(inPos, inDensity, inVel, inPressure, vec3(0.0), inMass, inPhase, inFlags, inId,
+        inDivergence);
+#endif
+    // the particles live in the frame the fluid is simulated in, which the world frame is
+    vec4 eye = ubo.view * vec4(p.pos, 1.0);
+    gl_Position = ubo.proj * eye;
+    // as large as the particle's share of the fluid, which goes with the cube root of its mass
+    uint phase = min(p.phase, 3u);
+    float radius = sprite.pixelRadius * pow(p.mass / max(sprite.phaseColors[phase].w, 1e-12), 1.0 / 3.0);
+    float size = radius * abs(ubo.proj[1][1]) / gl_Position.w;
+#ifdef SPHERES
+    // the diameter the radius covers on screen, however far past the largest point: the corners of a
+    // triangle strip spread around the center at the center's depth
+    vec2 corner = vec2(gl_VertexIndex & 1, gl_VertexIndex >> 1);
+    fragCorner = corner;
+    size = max(size, sim.pointSize.x);
+    vec2 halfExtent = size / sprite.viewportHeight * vec2(abs(ubo.proj[0][0] / ubo.proj[1][1]), 1.0);
+    gl_Position.xy += (2.0 * corner - 1.0) * halfExtent * gl_Position.w;
+#else
+    // the diameter the radius covers on screen, within what the device can draw
+    gl_PointSize = clamp(size, sim.pointSize.x, sim.pointSize.y);
+#endif
+    vec3 color = sprite.phaseColors[phase].rgb;
+    if (sprite.surfaceTint.w > 0.0 && (p.flags & SURFACE) != 0) {
+        color = sprite.surfaceTint.rgb;
+    }
+    fragScalar = -1.0;
+    if (sprite.scalar != 0) {
+        float value = sprite.scalar == 1 ? p.density : sprite.scalar == 2 ? length(p.vel)
+            : sprite.scalar == 3 ? p.pressure : p.divergence;
+        float span = max(sprite.scalarRange.y - sprite.scalarRange.x, 1e-6);
+        fragScalar = clamp((value - sprite.scalarRange.x) / span, 0.0, 1.0);
+    }
+    if (inHighlight.a > 0.0) {
+        color = inHighlight.rgb;
+        fragScalar = -1.0;
+    }
+    if (p.id == sprite.selected) {
+        color = sprite.selectedColor.rgb;
+        fragScalar = -1.0;
+    }
+    // translucent particles thin out where the fluid does
+    fragAlpha = sprite.selectedColor.w;
+    if (sprite.fullDensity > 0.0) {
+        fragAlpha *= clamp(p.density / sprite.fullDensity, 0.0, 1.0);
+    }
+    // sRGB to linear, as for the meshes
+    fragColor = pow(color, vec3(2.2));
+    fragLightDir = normalize(mat3(ubo.view) * (ubo.lightPos - p.pos));
+    fragBaseLight = ubo.baseLight;
+    ambientStrength = ubo.ambientStrength;
+    specularStrength = ubo.specularStrength;
+}
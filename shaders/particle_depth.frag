@@ -0,0 +1,11 @@
+#version 450
+
+#include "sprite_coord.glsl"
+
+void main() {
+    // the disc particle.frag and particle_far.frag draw, for its depth alone
+    vec2 xy = 2.0 * SPRITE_COORD - 1.0;
+    if (dot(xy, xy) > 1.0) {
+        discard;
+    }
+}
@@ -0,0 +1,26 @@
+#version 450
+
+layout(location = 0) in vec3    fragColor;
+layout(location = 1) in vec3    fragLightDir;   // in eye space
+layout(location = 2) in vec3    fragBaseLight;
+layout(location = 3) in float   ambientStrength;
+layout(location = 4) in float   specularStrength;
+layout(location = 5) in float   fragScalar;
+layout(location = 6) in float   fragAlpha;
+#include "sprite_coord.glsl"
+
+layout(set = 1, binding = 0) uniform sampler1D colormap;
+
+layout(location = 0) out vec4   outColor;
+
+void main() {
+    // the point as a flat disc, lit as the sphere's center facing the camera
+    vec2 xy = 2.0 * SPRITE_COORD - 1.0;
+    if (dot(xy, xy) > 1.0) {
+        discard;
+    }
+    vec3 lightDir = normalize(fragLightDir);
+    vec3 lightColor = (ambientStrength + max(lightDir.z, 0.0) * fragBaseLight) * fragBaseLight;
+    vec3 color = fragScalar < 0.0 ? fragColor : texture(colormap, fragScalar).rgb;
+    outColor = vec4(color * lightColor, fragAlpha);
+}
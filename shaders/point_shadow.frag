@@ -0,0 +1,14 @@
+#version 450
+
+layout(push_constant) uniform Face {
+    mat4    viewProj;
+    vec3    light;
+    float   far;
+} face;
+
+layout(location = 0) in vec3    worldPos;
+
+void main() {
+    // the distance from the light rather than the face's depth, to compare along any direction
+    gl_FragDepth = length(worldPos - face.light) / face.far;
+}
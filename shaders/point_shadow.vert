@@ -0,0 +1,26 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+} ubo;
+
+layout(set = 1, binding = 0) uniform ObjectConstants {
+    mat4    transform;
+} obj;
+
+layout(push_constant) uniform Face {
+    mat4    viewProj;
+    vec3    light;
+    float   far;
+} face;
+
+layout(location = 0) in vec3    inPos;
+
+layout(location = 0) out vec3   worldPos;
+
+void main() {
+    // the mesh as one face of the cube about the light sees it
+    vec4 pos = ubo.model * obj.transform * vec4(inPos, 1.0);
+    worldPos = pos.xyz;
+    gl_Position = face.viewProj * pos;
+}
@@ -0,0 +1,109 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+    vec3    baseLight;
+	float   ambientStrength;
+    vec3    lightPos;
+    float   specularStrength;
+    vec3    viewPos;
+    mat4    invView;
+    mat4    invProj;
+} ubo;
+
+// volume fraction at the reconstruction grid's nodes
+layout(binding = 1) uniform sampler3D field;
+
+layout(push_constant) uniform RaymarchConstants {
+    vec4    origin;         // w is the node spacing
+    uvec4   dims;           // w is the samples along the longest ray
+    vec4    color;          // w is the iso level
+    uint    refinements;
+} march;
+
+layout(location = 0) in vec2    fragUV;
+
+layout(location = 0) out vec4   outColor;
+
+// the field interpolated between the eight nodes around p, which lies in the frame the fluid is
+// simulated in
+float fieldAt(vec3 p) {
+    ivec3 dims = ivec3(march.dims.xyz);
+    vec3 g = clamp((p - march.origin.xyz) / march.origin.w, vec3(0.0), vec3(dims - 1));
+    ivec3 lo = min(ivec3(floor(g)), max(dims - 2, ivec3(0)));
+    vec3 f = g - vec3(lo);
+    float c[8];
+    for (int k = 0; k < 8; k++) {
+        ivec3 node = min(lo + ivec3(k & 1, (k >> 1) & 1, (k >> 2) & 1), dims - 1);
+        c[k] = texelFetch(field, node, 0).r;
+    }
+    vec4 x = mix(vec4(c[0], c[2], c[4], c[6]), vec4(c[1], c[3], c[5], c[7]), f.x);
+    vec2 y = mix(x.xz, x.yw, f.y);
+    return mix(y.x, y.y, f.z);
+}
+
+void main() {
+    // the pixel's ray, from the eye through the far plane
+    vec4 farPoint = ubo.invProj * vec4(fragUV * 2.0 - 1.0, 1.0, 1.0);
+    vec3 eye = (ubo.invView * vec4(0.0, 0.0, 0.0, 1.0)).xyz;
+    vec3 dir = normalize((ubo.invView * vec4(farPoint.xyz / farPoint.w, 0.0)).xyz);
+
+    // where it runs through the grid
+    vec3 lo = march.origin.xyz;
+    vec3 hi = lo + march.origin.w * vec3(march.dims.xyz - 1u);
+    vec3 inv = 1.0 / dir;
+    vec3 t0 = (lo - eye) * inv;
+    vec3 t1 = (hi - eye) * inv;
+    vec3 tmin = min(t0, t1);
+    vec3 tmax = max(t0, t1);
+    float enter = max(max(max(tmin.x, tmin.y), tmin.z), 0.0);
+    float leave = min(min(tmax.x, tmax.y), tmax.z);
+    if (enter >= leave) {
+        discard;
+    }
+
+    // step until the field rises past the iso level, then bisect the last step
+    float iso = march.color.w;
+    float dt = length(hi - lo) / float(max(march.dims.w, 1u));
+    float t = enter;
+    float prev = t;
+    bool hit = fieldAt(eye + t * dir) >= iso;
+    while (!hit && t < leave) {
+        prev = t;
+        t = min(t + dt, leave);
+        hit = fieldAt(eye + t * dir) >= iso;
+    }
+    if (!hit) {
+        discard;
+    }
+    float a = prev;
+    for (uint k = 0u; k < march.refinements && t > a; k++) {
+        float mid = 0.5 * (a + t);
+        if (fieldAt(eye + mid * dir) >= iso) {
+            t = mid;
+        } else {
+            a = mid;
+        }
+    }
+    vec3 pos = eye + t * dir;
+
+    // the field falls outward, so the normal points down its gradient
+    float e = 0.5 * march.origin.w;
+    vec3 gradient = vec3(
+        fieldAt(pos + vec3(e, 0.0, 0.0)) - fieldAt(pos - vec3(e, 0.0, 0.0)),
+        fieldAt(pos + vec3(0.0, e, 0.0)) - fieldAt(pos - vec3(0.0, e, 0.0)),
+        fieldAt(pos + vec3(0.0, 0.0, e)) - fieldAt(pos - vec3(0.0, 0.0, e)));
+    vec3 norm = length(gradient) > 0.0 ? -normalize(gradient) : -dir;
+    vec3 lightDir = normalize(ubo.lightPos - pos);
+    vec3 diffuse = max(dot(norm, lightDir), 0.0) * ubo.baseLight;
+    vec3 halfwayDir = normalize(lightDir - dir);
+    float spec = pow(max(dot(norm, halfwayDir), 0.0), 32);
+    vec3 specular = ubo.specularStrength * spec * ubo.baseLight;
+    vec3 lightColor = (ubo.ambientStrength + diffuse + specular) * ubo.baseLight;
+    // sRGB to linear, as for the meshes
+    outColor = vec4(pow(march.color.rgb, vec3(2.2)) * lightColor, 1.0);
+    vec4 clip = ubo.proj * ubo.view * vec4(pos, 1.0);
+    gl_FragDepth = clip.z / clip.w;
+}
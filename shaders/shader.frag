@@ -8,20 +8,177 @@ layout(location = 4) in vec3    lightPos;
 layout(location = 5) in vec3    fragPos;
 layout(location = 6) in float   specularStrength;
 layout(location = 7) in vec3    viewPos;
+layout(location = 8) in vec2    fragTexCoord;
+layout(location = 9) flat in uint textured;
+layout(location = 10) in vec4   lightSpacePos;
+layout(location = 11) in vec4   fragTangent;
+layout(location = 12) flat in uint instance;
 
-layout(location = 0) out vec4   outColor;
+// the length of the uniform's lights, defined as the mesh pipelines are built to match the app's
+#ifndef MAX_LIGHTS
+#define MAX_LIGHTS 8u
+#endif
+
+struct Light {
+    vec3    position;
+    uint    kind;               // 0 directional, 1 point, 2 spot
+    vec3    direction;
+    float   cosInner;           // cosines of the spot's inner and outer angles
+    vec3    color;
+    float   cosOuter;
+    vec3    attenuation;        // constant, linear and quadratic
+};
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+    vec3    baseLight;
+	float   ambientStrength;
+    vec3    lightPos;
+    float   specularStrength;
+    vec3    viewPos;
+    mat4    invView;
+    mat4    invProj;
+    mat4    lightViewProj;
+    float   shadowBias;
+    float   shadowNormalOffset;
+    uint    shadows;            // 1 to look up the shadow map
+    uint    pointShadows;       // 1 to look up the cube shadow map instead
+    float   pointShadowFar;
+    float   pointShadowBias;
+    uint    lightCount;         // the first is the key light, which casts the shadows
+    Light   lights[MAX_LIGHTS];
+    uint    shadingDebug;       // 0 the shading, 1 the albedo, 2 the normals, 3 the light alone
+} ubo;
+
+layout(binding = 1) uniform sampler2D meshTexture;
+layout(binding = 3) uniform sampler2DShadow shadowMap;
+layout(binding = 4) uniform samplerCubeShadow pointShadowMap;
+layout(binding = 5) uniform sampler2D normalMaps[16];   // NORMAL_MAP_SLOTS
+layout(binding = 6) uniform sampler2D ambientOcclusion; // 1 where unoccluded, as everywhere without it
+
+struct Object {
+    mat4    transform;
+    vec3    diffuse;
+    uint    textured;
+    vec3    ambient;
+    float   shininess;
+    vec3    specular;
+    int     normalMap;          // slot of normalMaps, or -1 for none
+    float   opacity;
+};
+
+layout(set = 1, binding = 0) uniform ObjectConstants {
+    Object  drawn;
+};
+
+layout(std430, set = 1, binding = 1) readonly buffer ObjectBlocks {
+    Object  blocks[];
+};
+
+// true in the pipelines of the indirect draws, which find their constants by instance
+layout(constant_id = 2) const bool INDIRECT = false;
+
+Object obj;
+
+// the features the pipeline is specialized for, ShaderConfig; what is off is compiled out
+layout(constant_id = 3) const bool SHADOWS = true;
+layout(constant_id = 4) const bool NORMAL_MAPPING = true;
+layout(constant_id = 5) const uint LIGHTS = 8;
+
+#include "oit.glsl"
+
+// whether the cube shadow map sees the point lit from the light, nearer than its nearest caster
+float pointLit() {
+    vec3 toPoint = fragPos - lightPos;
+    float dist = length(toPoint);
+    if (dist >= ubo.pointShadowFar) {
+        return 1.0;
+    }
+    return texture(pointShadowMap, vec4(toPoint, (dist - ubo.pointShadowBias) / ubo.pointShadowFar));
+}
+
+// how much of the light reaches the point: through the cube shadow map, or the fraction of the 3x3
+// shadow map texels around it that see it lit
+float lit() {
+    if (!SHADOWS) {
+        return 1.0;
+    }
+    if (ubo.pointShadows != 0) {
+        return pointLit();
+    }
+    vec3 p = lightSpacePos.xyz / lightSpacePos.w;
+    if (ubo.shadows == 0 || p.z > 1.0) {
+        return 1.0;
+    }
+    vec2 uv = p.xy * 0.5 + 0.5;
+    vec2 texel = 1.0 / vec2(textureSize(shadowMap, 0));
+    float sum = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            sum += texture(shadowMap, vec3(uv + vec2(x, y) * texel, p.z - ubo.shadowBias));
+        }
+    }
+    return sum / 9.0;
+}
+
+// the interpolated normal, turned by the normal map where the draw has one and the mesh tangents
+vec3 surfaceNormal() {
+    vec3 n = normalize(fragNormal);
+    if (!NORMAL_MAPPING || obj.normalMap < 0 || fragTangent.w == 0.0) {
+        return n;
+    }
+    vec3 t = normalize(fragTangent.xyz - n * dot(n, fragTangent.xyz));
+    vec3 b = cross(n, t) * fragTangent.w;
+    vec3 mapped = texture(normalMaps[obj.normalMap], fragTexCoord).xyz * 2.0 - 1.0;
+    return normalize(mat3(t, b, n) * mapped);
+}
+
+// the light's color where it reaches the point, after its falloff and cone, and the direction to it
+vec3 radiance(Light light, out vec3 lightDir) {
+    if (light.kind == 0) {
+        lightDir = -normalize(light.direction);
+        return light.color;
+    }
+    vec3 toLight = light.position - fragPos;
+    float dist = length(toLight);
+    lightDir = toLight / dist;
+    vec3 a = light.attenuation;
+    float falloff = 1.0 / max(a.x + a.y * dist + a.z * dist * dist, 1e-4);
+    if (light.kind == 2) {
+        float cosAngle = dot(-lightDir, normalize(light.direction));
+        falloff *= clamp((cosAngle - light.cosOuter) / max(light.cosInner - light.cosOuter, 1e-4), 0.0, 1.0);
+    }
+    return light.color * falloff;
+}
 
 void main() {
-    // diffusion
-    vec3 norm = normalize(fragNormal);
-    vec3 lightDir = normalize(lightPos - fragPos);
-    vec3 diffuse = max(dot(norm, lightDir), 0.0) * fragBaseLight;
-    // specular
+    obj = INDIRECT ? blocks[instance] : drawn;
+    vec3 norm = surfaceNormal();
     vec3 viewDir = normalize(viewPos - fragPos);
-    vec3 halfwayDir = normalize(lightDir + viewDir);
-    vec3 reflectDir = reflect(-lightDir, norm);
-    float spec = pow(max(dot(norm, halfwayDir), 0.0), 32);
-    vec3 specular = specularStrength * spec * fragBaseLight;
-    vec3 lightColor = (ambientStrength + diffuse + specular) * fragBaseLight;
-    outColor = vec4(fragColor * lightColor, 1.0);
+    float occlusion = texelFetch(ambientOcclusion, ivec2(gl_FragCoord.xy), 0).r;
+    // the ambient color is sRGB like the diffuse one, and decoded the same way
+    vec3 lightColor = pow(obj.ambient, vec3(2.2)) * ambientStrength * occlusion;
+    for (uint i = 0; i < min(ubo.lightCount, min(LIGHTS, MAX_LIGHTS)); i++) {
+        vec3 lightDir;
+        vec3 light = radiance(ubo.lights[i], lightDir);
+        // diffusion and specular
+        float diffuse = max(dot(norm, lightDir), 0.0);
+        vec3 halfwayDir = normalize(lightDir + viewDir);
+        float spec = pow(max(dot(norm, halfwayDir), 0.0), max(obj.shininess, 1.0));
+        vec3 specular = obj.specular * specularStrength * spec;
+        lightColor += (i == 0 ? lit() : 1.0) * light * (diffuse + specular);
+    }
+    lightColor *= fragBaseLight;
+    vec3 color = textured != 0 ? fragColor * texture(meshTexture, fragTexCoord).rgb : fragColor;
+    switch (ubo.shadingDebug) {
+        case 1: outColor = vec4(color, 1.0); break;
+        case 2: outColor = vec4(norm * 0.5 + 0.5, 1.0); break;
+        case 3: outColor = vec4(lightColor, 1.0); break;
+        default: outColor = vec4(color * lightColor, obj.opacity);
+    }
+    if (OIT) {
+        weigh(outColor);
+    }
 }
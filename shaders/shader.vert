@@ -9,11 +9,44 @@ layout(binding = 0) uniform UniformBufferObject {
     vec3    lightPos;
     float   specularStrength;
     vec3    viewPos;
+    mat4    invView;
+    mat4    invProj;
+    mat4    lightViewProj;
+    float   shadowBias;
+    float   shadowNormalOffset;
+    uint    shadows;
 } ubo;
 
+struct Object {
+    mat4    transform;
+    vec3    diffuse;
+    uint    textured;   // 1 to draw with the mesh texture
+    vec3    ambient;
+    float   shininess;
+    vec3    specular;
+    int     normalMap;
+    float   opacity;
+};
+
+layout(set = 1, binding = 0) uniform ObjectConstants {
+    Object  drawn;
+};
+
+// the whole object buffer, for the indirect draws to find their constants by instance
+layout(std430, set = 1, binding = 1) readonly buffer ObjectBlocks {
+    Object  blocks[];
+};
+
+// true in the pipelines of the indirect draws
+layout(constant_id = 2) const bool INDIRECT = false;
+
+Object obj;
+
 layout(location = 0) in vec3    inPos;
 layout(location = 1) in vec3    inColor;
 layout(location = 2) in vec3    inNormal;
+layout(location = 3) in vec2    inTexCoord;
+layout(location = 4) in vec4    inTangent;
 
 layout(location = 0) out vec3   fragColor;
 layout(location = 1) out vec3   fragNormal;
@@ -23,17 +56,31 @@ layout(location = 4) out vec3   lightPos;
 layout(location = 5) out vec3   fragPos;
 layout(location = 6) out float  specularStrength;
 layout(location = 7) out vec3   viewPos;
+layout(location = 8) out vec2   fragTexCoord;
+layout(location = 9) flat out uint textured;
+layout(location = 10) out vec4  lightSpacePos;
+layout(location = 11) out vec4  fragTangent;
+layout(location = 12) flat out uint instance;
+
+// the depth pre-pass's pipelines run this too, and the shading ones test for the same depth it left
+invariant gl_Position;
 
 void main() {
+    obj = INDIRECT ? blocks[gl_InstanceIndex] : drawn;
+    instance = gl_InstanceIndex;
     // position transform
-    gl_Position = ubo.proj * ubo.view * ubo.model * vec4(inPos, 1.0);
-    fragPos = vec3(ubo.model * vec4(inPos, 1.0));
-    // gamma correction
-    fragColor = inColor;
-    float gamma = 2.2;
-    fragColor.rgb = pow(fragColor.rgb, vec3(1.0/gamma));
+    mat4 model = ubo.model * obj.transform;
+    gl_Position = ubo.proj * ubo.view * model * vec4(inPos, 1.0);
+    fragPos = vec3(model * vec4(inPos, 1.0));
+    // the vertex and material colors are sRGB, decoded into the linear light the lighting adds up
+    fragColor = pow(inColor * obj.diffuse, vec3(2.2));
     // normal transform
-    fragNormal = mat3(transpose(inverse(ubo.model))) * inNormal;
+    fragNormal = mat3(transpose(inverse(model))) * inNormal;
+    fragTangent = vec4(mat3(model) * inTangent.xyz, inTangent.w);
+    fragTexCoord = inTexCoord;
+    textured = obj.textured;
+    // where the shadow map is looked up, off the surface along its normal against acne
+    lightSpacePos = ubo.lightViewProj * vec4(fragPos + normalize(fragNormal) * ubo.shadowNormalOffset, 1.0);
     // output values
 	fragBaseLight = ubo.baseLight;
 	ambientStrength = ubo.ambientStrength;
@@ -0,0 +1,26 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+    vec3    baseLight;
+	float   ambientStrength;
+    vec3    lightPos;
+    float   specularStrength;
+    vec3    viewPos;
+    mat4    invView;
+    mat4    invProj;
+    mat4    lightViewProj;
+} ubo;
+
+layout(set = 1, binding = 0) uniform ObjectConstants {
+    mat4    transform;
+} obj;
+
+layout(location = 0) in vec3    inPos;
+
+void main() {
+    // the mesh as the light sees it, only its depth drawn
+    gl_Position = ubo.lightViewProj * ubo.model * obj.transform * vec4(inPos, 1.0);
+}
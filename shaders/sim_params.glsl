@@ -0,0 +1,43 @@
+// The solver parameters, laid out like `SimParams` on the CPU and bound as the uniform at set 1,
+// binding 0 for every pass that reads them; a shader binding them elsewhere defines SIM_PARAMS_SET first.
+
+const uint MAX_PHASES = 4;
+
+#ifndef SIM_PARAMS_SET
+#define SIM_PARAMS_SET 1
+#endif
+
+layout(std140, set = SIM_PARAMS_SET, binding = 0) uniform SimParams {
+    vec4    domainMin;
+    vec4    domainMax;
+    vec4    gravity;
+    float   h;
+    float   restDensity;
+    float   stiffness;
+    float   viscosity;
+    float   dt;
+    uint    particleCount;
+    uint    frame;
+    uint    emitCount;
+    uint    sinkCount;
+    float   domainMargin;
+    uint    sdfCount;
+    uint    boundaryCount;
+    uint    bodyCount;
+    uint    phaseCount;
+    float   vorticityEpsilon;
+    float   surfaceThreshold;
+    vec4    refine;     // split and merge color field thresholds, split and merge obstacle distances
+    vec4    surfaceDetection;   // color field gradient and neighbor count thresholds; zero is off
+    uvec4   boundaryKinds;      // per axis: 0 walls, 1 periodic
+    vec4    containerOffset;    // displacement of the walls from rest, w: their angle about containerSpin
+    vec4    containerSpin;      // axis the walls turn about through the domain center, w: angular velocity
+    vec4    containerVelocity;  // velocity of the walls, w: how far they may move
+    vec4    kernel;     // W, dW/dr and viscosity Laplacian normalization
+    vec4    phases[MAX_PHASES];         // rest density, viscosity, particle mass
+    vec4    phaseColors[MAX_PHASES];
+    vec4    gridOrigin; // w: unused
+    uvec4   gridDims;   // w: cell count
+    vec4    gridCell;   // cell size per axis, at least h
+    vec4    pointSize;  // smallest and largest point the device draws, in pixels; zw: unused
+} sim;
@@ -0,0 +1,11 @@
+#version 450
+
+layout(binding = 2) uniform samplerCube skybox;
+
+layout(location = 0) in vec3    direction;
+
+layout(location = 0) out vec4   outColor;
+
+void main() {
+    outColor = vec4(texture(skybox, direction).rgb, 1.0);
+}
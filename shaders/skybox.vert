@@ -0,0 +1,31 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+    vec3    baseLight;
+	float   ambientStrength;
+    vec3    lightPos;
+    float   specularStrength;
+    vec3    viewPos;
+    mat4    invView;
+    mat4    invProj;
+} ubo;
+
+// a unit cube's corners as one triangle strip covering all six faces
+const vec3 corners[14] = vec3[](
+    vec3(-1.0,  1.0,  1.0), vec3( 1.0,  1.0,  1.0), vec3(-1.0, -1.0,  1.0), vec3( 1.0, -1.0,  1.0),
+    vec3( 1.0, -1.0, -1.0), vec3( 1.0,  1.0,  1.0), vec3( 1.0,  1.0, -1.0), vec3(-1.0,  1.0,  1.0),
+    vec3(-1.0,  1.0, -1.0), vec3(-1.0, -1.0,  1.0), vec3(-1.0, -1.0, -1.0), vec3( 1.0, -1.0, -1.0),
+    vec3(-1.0,  1.0, -1.0), vec3( 1.0,  1.0, -1.0));
+
+layout(location = 0) out vec3   direction;
+
+void main() {
+    vec3 corner = corners[gl_VertexIndex];
+    direction = corner;
+    // turned with the camera but not moved, and put at the far plane
+    vec4 pos = ubo.proj * mat4(mat3(ubo.view)) * vec4(corner, 1.0);
+    gl_Position = pos.xyww;
+}
@@ -0,0 +1,40 @@
+#version 450
+
+// volume fraction at the reconstruction grid's nodes
+layout(binding = 1) uniform sampler3D field;
+
+layout(set = 1, binding = 0) uniform sampler1D colormap;
+
+layout(push_constant) uniform SliceConstants {
+    vec4    origin;
+    uvec4   dims;
+    float   offset;
+    float   opacity;
+    vec2    range;
+} slice;
+
+layout(location = 0) in vec3    fragPos;
+
+layout(location = 0) out vec4   outColor;
+
+// the field interpolated between the eight nodes around p, as the raymarcher does it
+float fieldAt(vec3 p) {
+    ivec3 dims = ivec3(slice.dims.xyz);
+    vec3 g = clamp((p - slice.origin.xyz) / slice.origin.w, vec3(0.0), vec3(dims - 1));
+    ivec3 lo = min(ivec3(floor(g)), max(dims - 2, ivec3(0)));
+    vec3 f = g - vec3(lo);
+    float c[8];
+    for (int k = 0; k < 8; k++) {
+        ivec3 node = min(lo + ivec3(k & 1, (k >> 1) & 1, (k >> 2) & 1), dims - 1);
+        c[k] = texelFetch(field, node, 0).r;
+    }
+    vec4 x = mix(vec4(c[0], c[2], c[4], c[6]), vec4(c[1], c[3], c[5], c[7]), f.x);
+    vec2 y = mix(x.xz, x.yw, f.y);
+    return mix(y.x, y.y, f.z);
+}
+
+void main() {
+    float span = max(slice.range.y - slice.range.x, 1e-6);
+    float t = clamp((fieldAt(fragPos) - slice.range.x) / span, 0.0, 1.0);
+    outColor = vec4(texture(colormap, t).rgb, slice.opacity);
+}
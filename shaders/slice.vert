@@ -0,0 +1,33 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+} ubo;
+
+layout(push_constant) uniform SliceConstants {
+    vec4    origin;     // w is the node spacing
+    uvec4   dims;       // w is the axis the plane is perpendicular to
+    float   offset;     // across the grid along that axis, 0 to 1
+    float   opacity;
+    vec2    range;      // field values at the ends of the colormap
+} slice;
+
+layout(location = 0) out vec3   fragPos;
+
+void main() {
+    // the plane's corners as a strip, spanning the grid along the other two axes
+    vec3 lo = slice.origin.xyz;
+    vec3 hi = lo + slice.origin.w * vec3(slice.dims.xyz - 1u);
+    int axis = int(slice.dims.w);
+    int u = (axis + 1) % 3;
+    int v = (axis + 2) % 3;
+    vec3 pos;
+    pos[axis] = mix(lo[axis], hi[axis], slice.offset);
+    pos[u] = (gl_VertexIndex & 1) == 0 ? lo[u] : hi[u];
+    pos[v] = (gl_VertexIndex & 2) == 0 ? lo[v] : hi[v];
+    fragPos = pos;
+    // the grid lies in the frame the fluid is simulated in, which the world frame is
+    gl_Position = ubo.proj * ubo.view * vec4(pos, 1.0);
+}
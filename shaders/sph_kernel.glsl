@@ -0,0 +1,39 @@
+// The SPH smoothing kernels the solver's passes share, selected by the KERNEL specialization constant
+// and normalized by the sim uniform's kernel and h, which the including shader declares first.
+
+const uint KERNEL_POLY6_SPIKY = 0;
+const uint KERNEL_CUBIC_SPLINE = 1;
+const uint KERNEL_WENDLAND_C2 = 2;
+
+// kernel value at distance r; sim.kernel.x is the normalization computed on the CPU
+float kernelW(float r) {
+    float q = r / sim.h;
+    if (q >= 1.0) {
+        return 0.0;
+    }
+    if (KERNEL == KERNEL_POLY6_SPIKY) {
+        float w = sim.h * sim.h - r * r;
+        return sim.kernel.x * w * w * w;
+    } else if (KERNEL == KERNEL_CUBIC_SPLINE) {
+        return sim.kernel.x * (q <= 0.5 ? 6.0 * (q * q * q - q * q) + 1.0 : 2.0 * pow(1.0 - q, 3.0));
+    } else {
+        float t = 1.0 - q;
+        return sim.kernel.x * t * t * t * t * (1.0 + 4.0 * q);
+    }
+}
+
+// radial derivative dW/dr at distance r, scaled by sim.kernel.y
+float kernelDW(float r) {
+    float q = r / sim.h;
+    if (q >= 1.0) {
+        return 0.0;
+    }
+    if (KERNEL == KERNEL_POLY6_SPIKY) {
+        float hr = sim.h - r;
+        return sim.kernel.y * hr * hr;
+    } else if (KERNEL == KERNEL_CUBIC_SPLINE) {
+        return sim.kernel.y * (q <= 0.5 ? 3.0 * q * q - 2.0 * q : -(1.0 - q) * (1.0 - q));
+    } else {
+        return sim.kernel.y * q * pow(1.0 - q, 3.0);
+    }
+}
@@ -0,0 +1,9 @@
+// Where a fragment lies on its particle, from (0, 0) to (1, 1) across: the point's own coordinate, or
+// with SPHERES defined the corner particle.vert passes across the instanced quad.
+
+#ifdef SPHERES
+layout(location = 7) in vec2    fragCorner;
+#define SPRITE_COORD fragCorner
+#else
+#define SPRITE_COORD gl_PointCoord
+#endif
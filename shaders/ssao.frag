@@ -0,0 +1,65 @@
+#version 450
+
+const uint MAX_SAMPLES = 64;    // SSAO_MAX_SAMPLES
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+} ubo;
+
+layout(binding = 1) uniform sampler2D normalDepth;
+layout(binding = 2) uniform sampler2D noise;
+
+layout(binding = 3) uniform Kernel {
+    vec4    samples[MAX_SAMPLES];
+} kernel;
+
+layout(push_constant) uniform Ssao {
+    vec4    viewport;       // x, y, width and height in pixels
+    float   radius;
+    float   intensity;
+    uint    samples;
+} ssao;
+
+layout(location = 0) out float  outOcclusion;
+
+// the pixel of the view a view-space point projects to
+ivec2 project(vec3 p) {
+    vec4 clip = ubo.proj * vec4(p, 1.0);
+    vec2 ndc = clip.xy / clip.w;
+    vec2 pixel = ssao.viewport.xy + (ndc * 0.5 + 0.5) * ssao.viewport.zw;
+    return ivec2(clamp(pixel, ssao.viewport.xy, ssao.viewport.xy + ssao.viewport.zw - 1.0));
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    vec4 center = texelFetch(normalDepth, pixel, 0);
+    if (center.w <= 0.0) {
+        outOcclusion = 1.0;
+        return;
+    }
+    // the point back in view space, from its depth along the ray through the pixel
+    vec2 ndc = (gl_FragCoord.xy - ssao.viewport.xy) / ssao.viewport.zw * 2.0 - 1.0;
+    float depth = center.w;
+    vec3 position = vec3(ndc.x * depth / ubo.proj[0][0], ndc.y * depth / ubo.proj[1][1], -depth);
+    vec3 normal = normalize(center.xyz);
+    // the kernel's hemisphere about the normal, turned by the noise
+    vec3 random = vec3(texture(noise, gl_FragCoord.xy / vec2(textureSize(noise, 0))).xy, 0.0);
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    mat3 tbn = mat3(tangent, cross(normal, tangent), normal);
+
+    uint count = min(ssao.samples, MAX_SAMPLES);
+    float occluded = 0.0;
+    for (uint k = 0; k < count; k++) {
+        vec3 p = position + tbn * kernel.samples[k].xyz * ssao.radius;
+        float drawn = texelFetch(normalDepth, project(p), 0).w;
+        if (drawn <= 0.0) {
+            continue;
+        }
+        // hidden if what is drawn there is in front of it, unless so far in front it lies outside the radius
+        float range = smoothstep(0.0, 1.0, ssao.radius / abs(depth - drawn));
+        occluded += (drawn < -p.z - 0.02 * ssao.radius ? 1.0 : 0.0) * range;
+    }
+    outOcclusion = pow(1.0 - occluded / float(max(count, 1)), ssao.intensity);
+}
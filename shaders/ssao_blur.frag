@@ -0,0 +1,20 @@
+#version 450
+
+const int NOISE_SIZE = 4;
+
+layout(binding = 1) uniform sampler2D occlusion;
+
+layout(location = 0) out float  outOcclusion;
+
+void main() {
+    // a box as wide as the noise's tile, which evens out its pattern
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    ivec2 size = textureSize(occlusion, 0);
+    float sum = 0.0;
+    for (int y = -NOISE_SIZE / 2; y < NOISE_SIZE / 2; y++) {
+        for (int x = -NOISE_SIZE / 2; x < NOISE_SIZE / 2; x++) {
+            sum += texelFetch(occlusion, clamp(pixel + ivec2(x, y), ivec2(0), size - 1), 0).r;
+        }
+    }
+    outOcclusion = sum / float(NOISE_SIZE * NOISE_SIZE);
+}
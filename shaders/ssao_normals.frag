@@ -0,0 +1,18 @@
+#version 450
+
+layout(location = 1) in vec3    fragNormal;
+layout(location = 5) in vec3    fragPos;
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+} ubo;
+
+layout(location = 0) out vec4   outNormalDepth;
+
+void main() {
+    // the view turns normals as it turns positions, having no scale
+    vec3 normal = normalize(mat3(ubo.view) * normalize(fragNormal));
+    float depth = -(ubo.view * vec4(fragPos, 1.0)).z;
+    outNormalDepth = vec4(normal, depth);
+}
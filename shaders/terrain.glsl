@@ -0,0 +1,35 @@
+// Height fields baked into the obstacle SDF buffer, sampled as terrain. The including shader declares
+// SdfInfo and the SdfValues buffer first.
+
+const uint SDF_HEIGHTFIELD = 1;
+
+float heightSample(SdfInfo field, ivec2 s) {
+    s = clamp(s, ivec2(0), ivec2(field.dims.xy) - 1);
+    return sdfValues[field.dims.w + s.y * field.dims.x + s.x];
+}
+
+// bilinear terrain height at xz, held at the edge value past the edges
+float terrainHeight(SdfInfo field, vec2 xz) {
+    vec2 cell = vec2(field.origin.w, field.origin.y);
+    vec2 g = clamp((xz - field.origin.xz) / cell, vec2(0.0), vec2(field.dims.xy) - 1.0);
+    ivec2 b = min(ivec2(floor(g)), ivec2(field.dims.xy) - 2);
+    vec2 t = g - vec2(b);
+    float row0 = mix(heightSample(field, b), heightSample(field, b + ivec2(1, 0)), t.x);
+    float row1 = mix(heightSample(field, b + ivec2(0, 1)), heightSample(field, b + ivec2(1, 1)), t.x);
+    return mix(row0, row1, t.y);
+}
+
+// upward terrain normal at xz, from central differences of the height a cell either side
+vec3 terrainNormal(SdfInfo field, vec2 xz) {
+    vec2 dx = vec2(field.origin.w, 0.0);
+    vec2 dz = vec2(0.0, field.origin.y);
+    float slopeX = (terrainHeight(field, xz + dx) - terrainHeight(field, xz - dx)) / (2.0 * dx.x);
+    float slopeZ = (terrainHeight(field, xz + dz) - terrainHeight(field, xz - dz)) / (2.0 * dz.y);
+    return normalize(vec3(-slopeX, 1.0, -slopeZ));
+}
+
+// signed distance to the terrain, negative below it, to first order: the height above the ground
+// measured along the normal there
+float terrainDistance(SdfInfo field, vec3 p) {
+    return (p.y - terrainHeight(field, p.xz)) * terrainNormal(field, p.xz).y;
+}
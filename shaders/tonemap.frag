@@ -0,0 +1,27 @@
+#version 450
+
+layout(location = 0) in vec2    fragUV;
+
+layout(binding = 0) uniform sampler2D hdrImage;
+layout(binding = 1) uniform sampler2D bloomImage;
+
+layout(push_constant) uniform Tonemap {
+    float   exposure;
+    uint    operator;       // 0 Reinhard, 1 ACES
+    float   bloom;          // how much of the bloom is added, 0 without it
+} tonemap;
+
+layout(location = 0) out vec4   outColor;
+
+// Narkowicz's fit of the ACES filmic curve
+vec3 aces(vec3 x) {
+    return clamp(x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main() {
+    vec3 hdr = texture(hdrImage, fragUV).rgb + tonemap.bloom * texture(bloomImage, fragUV).rgb;
+    vec3 color = hdr * tonemap.exposure;
+    color = tonemap.operator == 0 ? color / (1.0 + color) : aces(color);
+    // linear, for the sRGB swapchain to encode
+    outColor = vec4(color, 1.0);
+}
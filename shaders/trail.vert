@@ -0,0 +1,54 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+} ubo;
+
+struct TrailPoint {
+    vec3    pos;
+    uint    stamp;
+};
+
+// the solver's set, of which the trail pass fills binding 27
+layout(std430, set = 1, binding = 27) readonly buffer Trails {
+    uint    head;
+    uint    pad0;
+    uint    pad1;
+    uint    pad2;
+    TrailPoint points[];
+};
+
+layout(push_constant) uniform Trail {
+    uint    stamp;
+    uint    length;
+    uint    stride;
+    uint    slots;
+    vec4    color;      // opacity at the head in w
+} trail;
+
+layout(location = 0) out vec4   fragColor;
+
+void main() {
+    // one strip per ring, from its newest sample at vertex 0 back to its oldest
+    uint base = gl_InstanceIndex * (trail.length + 1);
+    uint k = gl_VertexIndex;
+    // samples the ring holds unbroken from the newest back to this vertex's
+    uint reach = 0;
+    while (reach <= k && reach < head && points[base + (head - reach) % trail.length].stamp == head - reach) {
+        reach++;
+    }
+    if (reach == 0) {
+        // nothing recent in the ring: the whole strip outside the clip volume
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        fragColor = vec4(0.0);
+        return;
+    }
+    // past the break, the vertices fold onto the last sample held, and draw nothing
+    uint j = reach - 1;
+    gl_Position = ubo.proj * ubo.view * vec4(points[base + (head - j) % trail.length].pos, 1.0);
+    float fade = reach > k ? 1.0 - float(k) / float(trail.length - 1) : 0.0;
+    // sRGB to linear, as for the meshes; the opacity is no color and stays as it is
+    fragColor = vec4(pow(trail.color.rgb, vec3(2.2)), trail.color.a * fade);
+}
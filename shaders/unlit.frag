@@ -0,0 +1,43 @@
+#version 450
+
+layout(location = 0) in vec3    fragColor;
+layout(location = 8) in vec2    fragTexCoord;
+layout(location = 9) flat in uint textured;
+layout(location = 12) flat in uint instance;
+
+layout(binding = 1) uniform sampler2D meshTexture;
+
+struct Object {
+    mat4    transform;
+    vec3    diffuse;
+    uint    textured;
+    vec3    ambient;
+    float   shininess;
+    vec3    specular;
+    int     normalMap;
+    float   opacity;
+};
+
+layout(set = 1, binding = 0) uniform ObjectConstants {
+    Object  drawn;
+};
+
+layout(std430, set = 1, binding = 1) readonly buffer ObjectBlocks {
+    Object  blocks[];
+};
+
+// true in the pipelines of the indirect draws, which find their constants by instance
+layout(constant_id = 2) const bool INDIRECT = false;
+
+Object obj;
+
+#include "oit.glsl"
+
+void main() {
+    obj = INDIRECT ? blocks[instance] : drawn;
+    vec3 color = textured != 0 ? fragColor * texture(meshTexture, fragTexCoord).rgb : fragColor;
+    outColor = vec4(color, obj.opacity);
+    if (OIT) {
+        weigh(outColor);
+    }
+}
@@ -0,0 +1,23 @@
+#version 450
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4    model;
+    mat4    view;
+    mat4    proj;
+} ubo;
+
+// from the frame the fluid is simulated in, which is the world's, to where the boxes are drawn
+layout(push_constant) uniform WireConstants {
+    mat4    transform;
+} wire;
+
+layout(location = 0) in vec3    inPos;
+layout(location = 1) in vec3    inColor;
+
+layout(location = 0) out vec3   fragColor;
+
+void main() {
+    gl_Position = ubo.proj * ubo.view * wire.transform * vec4(inPos, 1.0);
+    // sRGB to linear, as the mesh shader does it
+    fragColor = pow(inColor, vec3(2.2));
+}
@@ -1,6 +1,9 @@
 use std::collections::HashSet;
+use std::ops::Range;
 use std::time::Instant;
 use anyhow::{anyhow, Result};
+use log::*;
+use nalgebra_glm as glm;
 use winit::window::Window;
 use vulkanalia::window as vk_window;
 use vulkanalia::loader::{LibloadingLoader, LIBRARY};
@@ -10,13 +13,76 @@ use vulkanalia::vk::{ExtDebugUtilsExtension, KhrSurfaceExtension, InstanceCreate
 
 use crate::appdata::AppData;
 use crate::callback::debug_callback;
-use crate::config::{VALIDATION_ENABLED, VALIDATION_LAYER, MAX_FRAMES_IN_FLIGHT};
+use crate::config::{VALIDATION_ENABLED, VALIDATION_LAYER, sync_validation_enabled, scan_verification_enabled,
+    autotune_enabled, diagnostics_log_interval, compression_limit, WORKGROUP_SIZE, MAX_FRAMES_IN_FLIGHT,
+    CFL_SAFETY_FACTOR, MIN_TIMESTEP, MAX_TIMESTEP, MAX_BODIES, SURFACE_THRESHOLD,
+    REPLAY_KEYFRAME_INTERVAL, REFINE_MAX_SPLITS, MOUSE_FORCE_STRENGTH, MOUSE_FORCE_RADIUS, PICK_RADIUS,
+    SURFACE_RESOLUTION, SURFACE_ISO, RAYMARCH_STEP_RANGE, COLOR_RANGE_EASING, SMOOTHING_SPHERE_SEGMENTS, SMOOTHING_SPHERE_COLOR, NEAR_PLANE,
+    MSAA_SAMPLES, SHADOW_RESOLUTION, POINT_SHADOW_RESOLUTION, MAX_LIGHTS, SSAO_MAX_SAMPLES, mesh_texture_path, skybox_path,
+    UNLIT_FRAGMENT_SHADER, MESH_CULL_SHADER, SCAN_BLOCKS_SHADER, SCAN_BLOCK_SUMS_SHADER, ADD_BLOCK_OFFSETS_SHADER, TUNE_SHADER};
 use crate::utils::*;
-use crate::camera::{UniformBufferObject, Camera};
-use crate::model::Object;
+use crate::camera::{UniformBufferObject, Camera, Turntable, projection, scene_model};
+use crate::model::{Object, Obstacle};
+use crate::simulation::*;
+use crate::emitter::{Emitter, Inflow, Outflow, Sink};
+use crate::rigid::RigidBody;
+use crate::kernel::{Kernel, KernelKind};
+use crate::checkpoint::Checkpoint;
+use crate::export::{ObjExporter, PlyExporter, PlyFields};
+use crate::grid::check_sorted;
+use crate::tune::autotune_workgroup_size;
+use crate::timing::{FrameTimings, create_timestamp_queries, read_step_timings, read_render_time};
+use crate::scan::{create_scan_pipelines, unregister_scan_buffer, verify_scan};
+use crate::scene::{Scene, ObstacleSpec};
+use crate::diagnostics::{Diagnostics, read_diagnostics};
+use crate::conservation::{CompressionCheck, DensityHistogram, read_density_histogram};
+use crate::tunables::{Tunable, TUNABLES};
+use crate::replay::{Playback, Recorder, Replay, ReplayEnd};
+use crate::pick::{pick_particle, read_selection, SELECTED_SLOT, WATCHED_SLOT};
+use crate::neighbors::{NeighborHighlight, clear_highlights};
+use crate::stats::{FrameStats, StatsWriter};
+use crate::solver::PRESSURE_ITERATIONS;
+use crate::bench::{Benchmark, BenchReport};
+use crate::reconstruct::{Anisotropy, FieldGrid, FluidRendering, anisotropic_kernels, marching_cubes, splat_field,
+    splat_kernels};
+use crate::foam::{Foam, clear_foam, destroy_foam_render_pipeline};
+use crate::sprites::{destroy_particle_pipeline, point_size_at};
+use crate::glyphs::{VelocityGlyphs, destroy_glyph_pipeline};
+use crate::slice::{DensitySlice, destroy_slice_pipeline};
+use crate::occupancy::{GridOccupancy, create_occupancy_buffer, destroy_occupancy_render_pipeline};
+use crate::debug_view::{DebugView, create_debug_targets, create_debug_view, destroy_debug_targets, destroy_debug_view};
+use crate::texture::{create_flat_normal_map, MeshTexture, COLOR_TEXTURE_FORMAT};
+use crate::skybox::{SkyboxTexture, destroy_skybox_pipeline, write_skybox};
+use crate::lighting::{Light, ShadingDebug, three_point_rig};
+use crate::tonemap::{Tonemapping, create_tonemap, create_tonemap_targets, destroy_tonemap, destroy_tonemap_targets};
+use crate::bloom::{Bloom, create_bloom, create_bloom_targets, destroy_bloom, destroy_bloom_targets};
+use crate::ssao::{Ssao, create_ssao, create_ssao_targets, destroy_ssao, destroy_ssao_targets};
+use crate::oit::{create_oit, create_oit_targets, destroy_oit, destroy_oit_targets};
+use crate::indirect::{create_indirect, destroy_indirect, destroy_object_geometry, merge_object_geometry,
+    update_indirect_draws};
+use crate::shadow::{PointShadows, ShadowMapping, create_point_shadow_map, create_shadow_map, create_shadow_pass,
+    destroy_point_shadow_map, destroy_shadow_map, destroy_shadow_pass, shadow_view_proj, write_point_shadow_map,
+    write_shadow_map};
+use crate::split::{SplitScreen, full_viewport, viewport_extent, to_window};
+use crate::depth_sort::{Translucency, create_depth_sort_buffers, depth_range};
+use crate::hot_reload::ShaderWatcher;
+use crate::pipeline_cache::{create_pipeline_cache, destroy_pipeline_cache};
+use crate::secondary::{RecordingTimes, create_secondaries, destroy_secondaries, invalidate_secondaries,
+    record_secondaries};
+use crate::cull::{FrustumCulling, ParticleLod, create_cull_buffer, cull_objects};
+use crate::gizmo::{AxisGizmo, destroy_gizmo_pipeline};
+use crate::ground::{GroundPlane, destroy_ground_pipeline};
+use crate::wireframe::{MeshWireframe, NormalLines, WireBox, WireKind, destroy_normal_buffer, destroy_wire_buffer, destroy_wire_pipeline,
+    update_normal_lines, update_wire_boxes};
+use crate::trails::{Trails, clear_trails, create_trail_buffer, destroy_trail_render_pipeline};
+use crate::colormap::{Colormap, create_colormap_set_layout, create_colormaps, destroy_colormaps, destroy_legend_pipeline};
+use crate::screen_space::{ScreenSpaceFluid, create_fluid_targets, create_screen_space_fluid, destroy_fluid_targets,
+    destroy_screen_space_fluid};
+use crate::raymarch::{Raymarching, create_raymarch_descriptor_sets, destroy_field_volume, destroy_raymarch_pipeline,
+    upload_field_volume};
 
 /// The application.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct App{
     entry: Entry,
     instance: Instance,
@@ -24,15 +90,141 @@ pub struct App{
     device: Device,
     frame: usize,
     resized: bool,
+    /// Samples per pixel asked of the main pass; see `set_msaa`.
+    msaa: u32,
     ubo: UniformBufferObject,
     camera: Camera,
+    /// The camera of the split screen's right view, starting where `camera` was when the window was split.
+    split_camera: Camera,
+    /// Whether the cull keeps the frustum it had when frozen, the camera free to look at what it left.
+    cull_frozen: bool,
+    /// Objects left after the latest cull.
+    visible_objects: u32,
+    /// Orbits the cameras while set.
+    turntable: Option<Turntable>,
+    /// Whether a turntable ran out since `take_turntable_finished` last said so.
+    turntable_finished: bool,
+    /// Acceleration (m/s^2) and reach (m) of the mouse force tool.
+    mouse_force_strength: f32,
+    mouse_force_radius: f32,
     timer: Instant,
+    sim: SimParams,
+    time_step: TimeStep,
+    time_mode: TimeMode,
+    /// Wall-clock seconds a realtime run has yet to simulate.
+    lag: f64,
+    last_frame: Instant,
+    /// Particles each frame slot's last step may have added, emitted or split off, not yet reflected in a
+    /// read-back live count.
+    added_in_flight: [u32; MAX_FRAMES_IN_FLIGHT],
+    /// Frame slot whose `graphics_finished_semaphores` entry is signaled but not yet waited on.
+    graphics_pending: Option<usize>,
+    /// Spawn state that `reset` restores.
+    initial_particles: Vec<Particle>,
+    paused: bool,
+    /// Simulated seconds, advanced by every solver step.
+    sim_time: f64,
+    /// Solver steps requested while paused and not yet dispatched.
+    pending_steps: u32,
+    /// Turns gravity over time while set.
+    gravity_animation: Option<RotatingGravity>,
+    /// Prescribed motion of the domain walls, if any, and whether it is running.
+    container_motion: Option<ContainerMotion>,
+    container_running: bool,
+    /// Seconds on the container motion's clock, which stands still while the motion is stopped.
+    container_clock: f64,
+    exporter: PlyExporter,
+    /// Simulated time at the end of each frame slot's last step, if it copied its particles out for the
+    /// exporter and recorder.
+    export_pending: [Option<f64>; MAX_FRAMES_IN_FLIGHT],
+    /// Per frame slot, the id of the particle selected when its step was recorded and the time it stepped
+    /// to, for the selection to be read back once the slot's fence signals.
+    selection_pending: [Option<(u32, f64)>; MAX_FRAMES_IN_FLIGHT],
+    /// The same for the watched particle.
+    watch_pending: [Option<(u32, f64)>; MAX_FRAMES_IN_FLIGHT],
+    /// Whether each frame slot's last step ran the tiled density and force passes, until its timestamps are read.
+    timing_pending: [Option<bool>; MAX_FRAMES_IN_FLIGHT],
+    /// Swapchain image each frame slot last rendered and whether it had the depth pre-pass, until its
+    /// timestamps are read.
+    render_pending: [Option<(usize, bool)>; MAX_FRAMES_IN_FLIGHT],
+    /// Running average GPU milliseconds of the density and force passes, untiled and tiled.
+    neighbor_pass_times: [Option<f32>; 2],
+    /// Running average GPU milliseconds of the render pass, without and with the depth pre-pass.
+    depth_prepass_times: [Option<f32>; 2],
+    timings: FrameTimings,
+    average_timings: Option<FrameTimings>,
+    last_timing_log: Instant,
+    diagnostics: Diagnostics,
+    /// Seconds between diagnostics logs, if they are logged.
+    diagnostics_interval: Option<f32>,
+    last_diagnostics_log: Instant,
+    color_range: ColorRange,
+    /// Whether `data.color_range` has eased towards the current coloring's range yet, or must jump to it.
+    color_range_primed: bool,
+    /// Pauses the solver once too much of the fluid is compressed, if set.
+    compression_check: Option<CompressionCheck>,
+    /// Frames stepped since the last density histogram.
+    frames_since_histogram: u32,
+    /// Frames stepped since the last trail sample.
+    frames_since_trail: u32,
+    /// Frame slots whose last step binned the densities.
+    histogram_pending: [bool; MAX_FRAMES_IN_FLIGHT],
+    density_histogram: Option<DensityHistogram>,
+    /// Index into `TUNABLES` of the parameter `tune` adjusts.
+    tunable: usize,
+    /// Id of the particle whose state is logged after every solver step, if any.
+    watched: Option<u32>,
+    /// Id of the particle picked with the mouse, followed and logged every frame, if any.
+    selected: Option<u32>,
+    /// Streams the positions of every stepped frame to a file while set.
+    recorder: Option<Recorder>,
+    /// Plays a recording back in place of the solver while set.
+    playback: Option<Playback>,
+    /// Writes a row of statistics per stepped frame while set.
+    stats: Option<StatsWriter>,
+    /// What each frame slot's last step did, if it has a statistics row to write once its fence signals.
+    stats_pending: [Option<FrameStats>; MAX_FRAMES_IN_FLIGHT],
+    /// Frames stepped since the last reset.
+    frames_stepped: u64,
+    /// Times the stepped frames while set, until it has seen enough of them.
+    bench: Option<Benchmark>,
+    /// Nodes along the longest side of the surface reconstruction's grid, and the volume fraction the
+    /// surface passes through.
+    surface_resolution: u32,
+    surface_iso: f32,
+    /// Kernels the surface reconstruction stretches along each particle's neighborhood, if set; round
+    /// ones otherwise.
+    anisotropy: Option<Anisotropy>,
+    /// Whether the particles or the reconstruction settings changed since the fluid surface was built.
+    surface_stale: bool,
+    /// Whether the objects or the fluid surface changed since the normal lines were built.
+    normals_stale: bool,
+    /// Where the light was, with what settings and where the objects were, when the cube shadow map was
+    /// last drawn, if it has been since it was made.
+    point_shadows_drawn: Option<(glm::Vec3, PointShadows, Vec<glm::Mat4>)>,
+    surface_exporter: ObjExporter,
+    /// Watches the shaders for edits to rebuild what they go into, while on.
+    shader_watcher: Option<ShaderWatcher>,
+    /// What the app was created from, defaults filled in.
+    scene: Scene,
 }
 
 impl App {
-    /// Creates the app instance.
-    pub unsafe fn create(window: &Window, model_paths: Vec<String>,
+    /// Creates the app instance in the dam break scene with the models at `model_paths` as obstacles.
+    /// Particles start from `particles_path` if given, else as the scene's column of fluid.
+    pub unsafe fn create(window: &Window, model_paths: Vec<String>, particles_path: Option<String>,
             vshader_path: String, fshader_path: String) -> Result<Self> {
+        let mut scene = Scene { obstacles: model_paths.into_iter().map(ObstacleSpec::viewed).collect(), ..Scene::default() };
+        if particles_path.is_some() {
+            scene.fluid.clear();
+            scene.particle_file = particles_path;
+        }
+        Self::from_scene(window, scene, vshader_path, fshader_path)
+    }
+
+    /// Creates the app instance with everything `scene` describes.
+    pub unsafe fn from_scene(window: &Window, scene: Scene, vshader_path: String, fshader_path: String) -> Result<Self> {
+        scene.validate()?;
         // loader and entry 
         let loader = LibloadingLoader::new(LIBRARY)?;
         let entry = Entry::new(loader).map_err(|b| anyhow!("{}", b))?;
@@ -47,32 +239,134 @@ impl App {
         // device
         pick_physical_device(&instance, &mut data)?;
         let device = create_logical_device(&instance, &mut data)?;
+        create_pipeline_cache(&instance, &device, &mut data)?;
+        data.msaa_samples = msaa_sample_count(&instance, &data, MSAA_SAMPLES);
         // other setups
         create_swapchain(window, &instance, &device, &mut data)?;
         create_swapchain_image_views(&device, &mut data)?;
         create_render_pass(&instance, &device, &mut data)?;
         create_descriptor_set_layout(&device, &mut data)?;
+        create_colormap_set_layout(&device, &mut data)?;
         create_pipeline(&device, &mut data)?;
         create_command_pool(&instance, &device, &mut data)?;
-        create_depth_objects(&instance, &device, &mut data)?;
-        create_framebuffers(&device, &mut data)?;
+        create_colormaps(&instance, &device, &mut data)?;
+        data.mesh_texture = MeshTexture::load(&instance, &device, &data, mesh_texture_path().as_deref(),
+            COLOR_TEXTURE_FORMAT)?;
+        data.skybox_texture = SkyboxTexture::load(&instance, &device, &data, skybox_path().as_deref())?;
+        create_flat_normal_map(&instance, &device, &mut data)?;
+        data.lights = three_point_rig();
+        create_tonemap(&device, &mut data)?;
+        create_bloom(&device, &mut data)?;
+        create_oit(&device, &mut data)?;
+        create_shadow_pass(&device, &mut data)?;
+        create_shadow_map(&instance, &device, &mut data, SHADOW_RESOLUTION)?;
+        create_point_shadow_map(&instance, &device, &mut data, POINT_SHADOW_RESOLUTION)?;
         // load models for each object to render
-        for model_path in model_paths {
-            let obj = Object::new(model_path, &instance, &device, &mut data)?;
+        for spec in &scene.obstacles {
+            let mut obj = Object::new(spec.mesh.clone(), &instance, &device, &mut data)?;
+            obj.transform = spec.transform();
+            obj.obstacle = spec.collision;
+            obj.sdf_resolution = spec.sdf_resolution;
+            obj.refine = spec.refine;
+            obj.material_kind = spec.shading;
+            obj.opacity = spec.opacity;
+            data.objects.push(obj);
+        }
+        for spec in &scene.terrains {
+            let mut obj = Object::terrain(spec.heightfield()?, glm::make_vec3(&spec.color), &instance, &device, &mut data)?;
+            obj.obstacle = spec.collision;
+            obj.refine = spec.refine;
+            obj.material_kind = spec.shading;
             data.objects.push(obj);
         }
+        merge_object_geometry(&instance, &device, &mut data)?;
+        let half_extent = glm::vec3(1.0, 1.0, 1.0) * SimParams::PARTICLE_SPACING;
+        data.selection_marker = Object::wire_box(half_extent, glm::vec3(1.0, 0.8, 0.1), &instance, &device, &mut data)?;
+        data.smoothing_sphere_marker = Object::wire_sphere(1.0, SMOOTHING_SPHERE_SEGMENTS,
+            glm::make_vec3(&SMOOTHING_SPHERE_COLOR), &instance, &device, &mut data)?;
+        // particles and the SPH solver passes
+        let mut sim = scene.sim();
+        let particles = scene.particles(&sim)?;
+        data.kernel_kind = scene.solver.kernel;
+        data.boundary_mode = scene.solver.boundary;
+        data.strict_order = scene.solver.strict_order;
+        data.emitters = scene.make_emitters();
+        data.sinks = scene.make_sinks();
+        data.inflows = scene.make_inflows();
+        data.outflows = scene.make_outflows();
+        data.particle_radius = scene.particle_radius;
+        data.radius_scale = 1.0;
+        data.ground = Some(GroundPlane::default());
+        data.skybox = true;
+        data.gizmo = Some(AxisGizmo::default());
+        let mut camera = Camera::new(0.1, 0.2)?;
+        camera.set_view(scene.camera.distance, scene.camera.yaw, scene.camera.pitch)?;
+        sim.particle_count = particles.len() as u32;
+        data.workgroup_size = if autotune_enabled() {
+            autotune_workgroup_size(&instance, &device, &data)?
+        } else {
+            WORKGROUP_SIZE
+        };
+        create_scan_pipelines(&device, &mut data)?;
+        create_particle_buffers(&instance, &device, &mut data, &particles)?;
+        create_grid_buffers(&instance, &device, &mut data, &sim)?;
+        sim.sdf_count = create_sdf_buffers(&instance, &device, &mut data)?;
+        sim.boundary_count = create_boundary_buffer(&instance, &device, &mut data, &sim)?;
+        create_body_buffers(&instance, &device, &mut data)?;
+        create_compute_descriptor_set_layout(&device, &mut data)?;
+        create_sim_params_buffers(&instance, &device, &mut data)?;
+        query_tile_size(&instance, &mut data);
+        create_timestamp_queries(&instance, &device, &mut data)?;
+        create_compute_pipelines(&device, &mut data)?;
+        create_compute_descriptor_pool(&device, &mut data)?;
+        create_compute_descriptor_sets(&device, &mut data)?;
+        create_compute_command_buffers(&instance, &device, &mut data)?;
+        if scan_verification_enabled() {
+            verify_scan(&instance, &device, &mut data)?;
+        }
         // uniform and command buffers
         create_uniform_buffers(&instance, &device, &mut data)?;
         create_descriptor_pool(&device, &mut data)?;
+        create_ssao(&instance, &device, &mut data)?;
         create_descriptor_sets(&device, &mut data)?;
+        create_indirect(&instance, &device, &mut data)?;
+        create_screen_space_fluid(&instance, &device, &mut data)?;
+        create_debug_view(&device, &mut data)?;
+        create_raymarch_descriptor_sets(&device, &mut data)?;
+        create_swapchain_targets(&instance, &device, &mut data)?;
+        create_secondaries(&instance, &device, &mut data)?;
         create_command_buffers(&device, &mut data)?;
         create_sync_objects(&device, &mut data)?;
-        Ok(Self { entry, instance, data, device, frame: 0, resized: false, ubo: UniformBufferObject::new(),
-            timer: Instant::now(), camera: Camera::new(0.1, 0.2)? })
+        Ok(Self { entry, instance, data, device, frame: 0, resized: false, msaa: MSAA_SAMPLES, ubo: UniformBufferObject::new(),
+            timer: Instant::now(), camera, split_camera: camera, cull_frozen: false, visible_objects: 0,
+            turntable: None, turntable_finished: false,
+            mouse_force_strength: MOUSE_FORCE_STRENGTH, mouse_force_radius: MOUSE_FORCE_RADIUS, sim,
+            time_step: TimeStep::new(CFL_SAFETY_FACTOR, MIN_TIMESTEP, MAX_TIMESTEP),
+            time_mode: scene.solver.time, lag: 0.0, last_frame: Instant::now(),
+            added_in_flight: [0; MAX_FRAMES_IN_FLIGHT], graphics_pending: None,
+            initial_particles: particles, paused: false, sim_time: 0.0, pending_steps: 0,
+            gravity_animation: None, container_motion: scene.domain.motion, container_running: scene.domain.motion.is_some(),
+            container_clock: 0.0, exporter: PlyExporter::default(), export_pending: [None; MAX_FRAMES_IN_FLIGHT],
+            selection_pending: [None; MAX_FRAMES_IN_FLIGHT], watch_pending: [None; MAX_FRAMES_IN_FLIGHT],
+            timing_pending: [None; MAX_FRAMES_IN_FLIGHT], render_pending: [None; MAX_FRAMES_IN_FLIGHT],
+            neighbor_pass_times: [None; 2], depth_prepass_times: [None; 2], timings: FrameTimings::default(), average_timings: None,
+            last_timing_log: Instant::now(), diagnostics: Diagnostics::default(),
+            diagnostics_interval: diagnostics_log_interval(), last_diagnostics_log: Instant::now(),
+            color_range: ColorRange::Auto, color_range_primed: false,
+            compression_check: compression_limit().map(CompressionCheck::with_limit), frames_since_histogram: 0,
+            frames_since_trail: 0, histogram_pending: [false; MAX_FRAMES_IN_FLIGHT], density_histogram: None, tunable: 0, watched: None, selected: None, recorder: None, playback: None,
+            stats: None, stats_pending: [None; MAX_FRAMES_IN_FLIGHT], frames_stepped: 0, bench: None,
+            surface_resolution: SURFACE_RESOLUTION, surface_iso: SURFACE_ISO, anisotropy: None, surface_stale: true,
+            normals_stale: false, point_shadows_drawn: None, surface_exporter: ObjExporter::default(), shader_watcher: None, scene })
     }
 
     /// Renders a frame for the app.
     pub unsafe fn render(&mut self, window: &Window) -> Result<()> {
+        // a resize or a change of sample count rebuilds the swapchain before anything draws with it
+        if std::mem::take(&mut self.resized) {
+            return self.recreate_swapchain(window);
+        }
+        self.reload_shaders(window)?;
         let t1 = self.timer.elapsed().as_secs_f32();
         // wait and reset fences for GPU-CPU sync
         let in_flight_fence = self.data.in_flight_fences[self.frame];
@@ -84,32 +378,153 @@ impl App {
             Err(e) => return Err(anyhow!(e)),
         };
         self.device.wait_for_fences(&[in_flight_fence], true, u64::max_value())?;
+        if let Some(time) = self.export_pending[self.frame].take() {
+            let particles = read_exported_particles(&self.device, &self.data, self.frame)?;
+            if let Some(recorder) = &mut self.recorder {
+                recorder.write(&particles, time as f32)?;
+            }
+            if self.exporter.is_running() {
+                self.exporter.submit(particles);
+            }
+        }
+        if let Some((id, time)) = self.selection_pending[self.frame].take() {
+            self.follow_selection(id, time);
+        }
+        if let Some((id, time)) = self.watch_pending[self.frame].take() {
+            self.log_watched(id, time);
+        }
+        self.read_timings()?;
+        self.update_diagnostics()?;
+        self.write_stats()?;
+        self.check_compression()?;
+        let substeps = if self.playback.is_some() {
+            self.advance_replay()?;
+            0
+        } else {
+            self.plan_steps()?
+        };
+        self.advance_turntable()?;
+        let surface_stale = self.surface_stale;
+        self.update_fluid_surface()?;
+        let remeshed = surface_stale && !self.surface_stale;
+        // translucent particles are sorted for the view this frame is drawn with
+        let view = self.camera.get_view_matrix();
+        self.data.depth_sort_view = view;
+        self.data.depth_sort_range = depth_range(&view, &self.sim.domain_min.xyz(), &self.sim.domain_max.xyz());
+        if !self.cull_frozen {
+            self.data.cull_view = view;
+        }
+        self.visible_objects = cull_objects(&mut self.data);
+        self.choose_particle_sprites();
+        let compute_command_buffer = if substeps > 0 {
+            self.data.bin_densities = self.histogram_due();
+            // the divergence pass only runs for what shows or records it
+            self.data.measure_divergence = self.data.particle_coloring == ParticleColoring::Divergence
+                || self.stats.is_some();
+            self.data.sample_trails = self.trails_due();
+            self.histogram_pending[self.frame] = self.data.bin_densities;
+            self.timing_pending[self.frame] = Some(self.tiled());
+            let command_buffer = self.record_step(substeps)?;
+            self.surface_stale = true;
+            self.export_pending[self.frame] = self.data.export_enabled.then_some(self.sim_time);
+            self.selection_pending[self.frame] = self.data.selected_id.map(|id| (id, self.sim_time));
+            self.watch_pending[self.frame] = self.data.watched_id.map(|id| (id, self.sim_time));
+            self.frames_stepped += 1;
+            self.stats_pending[self.frame] = self.stats.is_some().then_some(FrameStats {
+                frame: self.frames_stepped, time: self.sim_time, dt: self.sim.dt, substeps,
+                solver_iterations: PRESSURE_ITERATIONS, visible_objects: self.visible_objects, objects: self.data.objects.len() as u32, ..Default::default() });
+            command_buffer
+        } else {
+            self.added_in_flight[self.frame] = 0;
+            // the particle draws read their point sizes out of the parameters with no step to run too
+            self.sim.update(self.frame, &self.data, &self.device)?;
+            record_paused_commands(&self.device, &self.data, self.frame)?
+        };
         // wait for image fence
         let image_in_flight = self.data.images_in_flight[image_index];
         if !image_in_flight.is_null() {
             self.device.wait_for_fences(&[image_in_flight], true, u64::max_value())?;
         }
         self.data.images_in_flight[image_index] = in_flight_fence;
-        self.ubo.update(image_index, self.camera.get_view_matrix(), &self.data, &self.device)?;
+        self.update_wireframe()?;
+        if self.normals_stale {
+            update_normal_lines(&self.instance, &self.device, &mut self.data)?;
+            self.normals_stale = false;
+        }
+        self.data.phase_colors = self.sim.phase_colors;
+        self.data.phase_masses = std::array::from_fn(|k| self.sim.phase_mass(k as u32));
+        self.data.particle_count = self.sim.particle_count;
+        self.data.domain_floor = self.sim.domain_min.y;
+        // the key light shines from along its position, its shadows cast within the domain's bounding sphere
+        if let Some(key) = self.data.lights.first() {
+            self.ubo.light_pos = key.origin();
+        }
+        let (min, max) = (self.sim.domain_min.xyz(), self.sim.domain_max.xyz());
+        let center = (min + max) / 2.0 + self.sim.container_offset.xyz();
+        self.data.shadow_view_proj = shadow_view_proj(&self.ubo.light_pos, &center, glm::distance(&min, &max) / 2.0);
+        // the cube shadow map is redrawn only once something in it moved: the light, the objects, the
+        // bodies with a step or the fluid surface with a remesh
+        let drawn = self.data.point_shadows.map(|shadows|
+            (self.ubo.light_pos, shadows, self.data.objects.iter().map(|obj| obj.transform).collect::<Vec<_>>()));
+        self.data.point_shadow_redraw = drawn.is_some()
+            && (substeps > 0 || remeshed || drawn != self.point_shadows_drawn);
+        self.data.point_shadow_light = self.ubo.light_pos;
+        self.point_shadows_drawn = drawn;
+        update_object_blocks(&self.device, &mut self.data, image_index)?;
+        update_indirect_draws(&self.device, &mut self.data, image_index)?;
+        prepare_mesh_pipelines(&self.device, &mut self.data)?;
+        record_secondaries(&self.device, &mut self.data, image_index)?;
+        self.data.sim_params_frame = self.frame;
+        record_command_buffer(&self.device, &self.data, image_index)?;
+        match &self.data.split_screen {
+            None => self.ubo.update(image_index, self.camera.get_view_matrix(), self.data.swapchain_extent,
+                &self.data, &self.device)?,
+            Some(split) => {
+                let [left, right] = split.viewports(self.data.swapchain_extent);
+                self.ubo.update(image_index, self.camera.get_view_matrix(), viewport_extent(&left),
+                    &self.data, &self.device)?;
+                self.ubo.update(self.data.swapchain_images.len() + image_index, self.split_camera.get_view_matrix(),
+                    viewport_extent(&right), &self.data, &self.device)?;
+            }
+        }
     
+        // submit the solver step to the compute queue; it updates the buffer the previous frame drew
+        // before compacting into the other one, so it must not start until that rendering is done
+        let compute_waits = self.graphics_pending.take()
+            .map(|f| self.data.graphics_finished_semaphores[f]).into_iter().collect::<Vec<_>>();
+        let compute_wait_stages = vec![vk::PipelineStageFlags::COMPUTE_SHADER; compute_waits.len()];
+        let compute_command_buffers = &[compute_command_buffer];
+        let compute_signals = &[self.data.compute_finished_semaphores[self.frame]];
+        let compute_info = vk::SubmitInfo::builder()
+            .wait_semaphores(&compute_waits)
+            .wait_dst_stage_mask(&compute_wait_stages)
+            .command_buffers(compute_command_buffers)
+            .signal_semaphores(compute_signals);
+        self.device.queue_submit(self.data.compute_queue, &[compute_info], vk::Fence::null())?;
+
         // get image from swapchain, and get ready to submit it to present queue
-        let wait_semaphores = &[self.data.image_available_semaphores[self.frame]];
-        let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-        let command_buffers = &[self.data.command_buffers[image_index as usize]];
-        let signal_semaphores = &[self.data.render_finished_semaphores[self.frame]];
+        let wait_semaphores = &[self.data.image_available_semaphores[self.frame], compute_signals[0]];
+        let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::DRAW_INDIRECT];
+        let command_buffers = &[self.data.command_buffers[image_index]];
+        let signal_semaphores = &[self.data.render_finished_semaphores[self.frame],
+            self.data.graphics_finished_semaphores[self.frame]];
         let submit_info = vk::SubmitInfo::builder()
             .wait_semaphores(wait_semaphores)
             .wait_dst_stage_mask(wait_stages)
             .command_buffers(command_buffers)
             .signal_semaphores(signal_semaphores);
-        // submit to the graphics queue
+        // submit to the graphics queue; its fence also covers the compute work it waited on
         self.device.reset_fences(&[self.data.in_flight_fences[self.frame]])?;
         self.device.queue_submit(self.data.graphics_queue, &[submit_info], in_flight_fence)?;
+        self.render_pending[self.frame] = Some((image_index, self.data.depth_prepass));
+        self.graphics_pending = Some(self.frame);
         // present to screen
         let swapchains = &[self.data.swapchain];
         let image_indices = &[image_index as u32];
+        let present_wait = &[signal_semaphores[0]];
         let present_info = vk::PresentInfoKHR::builder()
-            .wait_semaphores(signal_semaphores)
+            .wait_semaphores(present_wait)
             .swapchains(swapchains)
             .image_indices(image_indices);
         let result = self.device.queue_present_khr(self.data.present_queue, &present_info);
@@ -126,18 +541,42 @@ impl App {
     /// Destroys the app.
     #[rustfmt::skip]
     pub unsafe fn destroy(&mut self) {
+        self.stop_export();
+        self.stop_surface_export();
+        self.stop_recording();
+        self.stop_stats();
         self.destroy_swapchain();
+        destroy_shadow_map(&self.device, &self.data);
+        destroy_point_shadow_map(&self.device, &self.data);
+        destroy_shadow_pass(&self.device, &self.data);
         self.device.destroy_descriptor_set_layout(self.data.descriptor_set_layout, None);
-        self.data.objects.iter().for_each(|obj| {
+        self.device.destroy_descriptor_set_layout(self.data.object_set_layout, None);
+        destroy_colormaps(&self.device, &mut self.data);
+        self.data.mesh_texture.destroy(&self.device);
+        self.data.flat_normal_map.destroy(&self.device);
+        self.data.normal_maps.iter().for_each(|(_, texture)| texture.destroy(&self.device));
+        self.data.skybox_texture.destroy(&self.device);
+        destroy_simulation(&self.device, &self.data);
+        destroy_field_volume(&self.device, &mut self.data);
+        destroy_wire_buffer(&self.device, &mut self.data);
+        destroy_normal_buffer(&self.device, &mut self.data);
+        // the objects out of the shared buffers leave them to be destroyed once
+        let meshes = [&self.data.selection_marker, &self.data.smoothing_sphere_marker, &self.data.fluid_surface];
+        let shared = self.data.object_vertex_buffer;
+        self.data.objects.iter().filter(|obj| obj.vertex_buffer != shared).chain(meshes).for_each(|obj| {
             self.device.destroy_buffer(obj.index_buffer, None);
             self.device.free_memory(obj.index_buffer_memory, None);
             self.device.destroy_buffer(obj.vertex_buffer, None);
             self.device.free_memory(obj.vertex_buffer_memory, None);
         });
+        destroy_object_geometry(&self.device, &mut self.data);
         self.data.in_flight_fences.iter().for_each(|f| self.device.destroy_fence(*f, None));
         self.data.render_finished_semaphores.iter().for_each(|s| self.device.destroy_semaphore(*s, None));
         self.data.image_available_semaphores.iter().for_each(|s| self.device.destroy_semaphore(*s, None));
+        self.data.compute_finished_semaphores.iter().for_each(|s| self.device.destroy_semaphore(*s, None));
+        self.data.graphics_finished_semaphores.iter().for_each(|s| self.device.destroy_semaphore(*s, None));
         self.device.destroy_command_pool(self.data.command_pool, None);
+        destroy_pipeline_cache(&self.instance, &self.device, &self.data);
         self.device.destroy_device(None);
         self.instance.destroy_surface_khr(self.data.surface, None);
         if VALIDATION_ENABLED {
@@ -147,33 +586,96 @@ impl App {
     }
 
     unsafe fn destroy_swapchain(&mut self) {
+        self.destroy_swapchain_targets();
+        self.destroy_swapchain_pipelines();
+    }
+
+    /// Destroys the swapchain and everything sized to it.
+    unsafe fn destroy_swapchain_targets(&mut self) {
         self.device.destroy_image_view(self.data.depth_image_view, None);
         self.device.free_memory(self.data.depth_image_memory, None);
         self.device.destroy_image(self.data.depth_image, None);
+        destroy_msaa_objects(&self.device, &mut self.data);
+        destroy_debug_targets(&self.device, &self.data);
+        destroy_fluid_targets(&self.device, &self.data);
+        destroy_ssao_targets(&self.device, &self.data);
+        destroy_oit_targets(&self.device, &mut self.data);
+        destroy_bloom_targets(&self.device, &self.data);
+        destroy_tonemap_targets(&self.device, &self.data);
+        self.data.framebuffers.iter().for_each(|f| self.device.destroy_framebuffer(*f, None));
+        self.device.free_command_buffers(self.data.command_pool, &self.data.command_buffers);
+        self.data.swapchain_image_views.iter().for_each(|v| self.device.destroy_image_view(*v, None));
+        self.device.destroy_swapchain_khr(self.data.swapchain, None);
+    }
+
+    /// Destroys the passes and pipelines drawing into the swapchain, with the buffers and sets there is
+    /// one of per swapchain image.
+    unsafe fn destroy_swapchain_pipelines(&mut self) {
         self.device.destroy_descriptor_pool(self.data.descriptor_pool, None);
         self.data.uniform_buffers.iter().for_each(|b| self.device.destroy_buffer(*b, None));
         self.data.uniform_buffers_memory.iter().for_each(|m| self.device.free_memory(*m, None));
-        self.data.framebuffers.iter().for_each(|f| self.device.destroy_framebuffer(*f, None));
-        self.device.free_command_buffers(self.data.command_pool, &self.data.command_buffers);
-        self.device.destroy_pipeline(self.data.pipeline, None);
+        self.data.object_buffers.iter().for_each(|b| self.device.destroy_buffer(*b, None));
+        self.data.object_buffers_memory.iter().for_each(|m| self.device.free_memory(*m, None));
+        destroy_mesh_pipelines(&self.device, &mut self.data);
+        destroy_particle_pipeline(&self.device, &self.data);
+        destroy_foam_render_pipeline(&self.device, &self.data);
+        destroy_debug_view(&self.device, &self.data);
+        destroy_screen_space_fluid(&self.device, &self.data);
+        destroy_bloom(&self.device, &self.data);
+        destroy_oit(&self.device, &mut self.data);
+        destroy_indirect(&self.device, &mut self.data);
+        destroy_secondaries(&self.device, &mut self.data);
+        destroy_ssao(&self.device, &self.data);
+        destroy_tonemap(&self.device, &self.data);
+        destroy_raymarch_pipeline(&self.device, &self.data);
+        destroy_legend_pipeline(&self.device, &self.data);
+        destroy_glyph_pipeline(&self.device, &self.data);
+        destroy_trail_render_pipeline(&self.device, &self.data);
+        destroy_slice_pipeline(&self.device, &self.data);
+        destroy_wire_pipeline(&self.device, &self.data);
+        destroy_ground_pipeline(&self.device, &self.data);
+        destroy_skybox_pipeline(&self.device, &self.data);
+        destroy_gizmo_pipeline(&self.device, &self.data);
+        destroy_occupancy_render_pipeline(&self.device, &self.data);
         self.device.destroy_pipeline_layout(self.data.pipeline_layout, None);
         self.device.destroy_render_pass(self.data.render_pass, None);
-        self.data.swapchain_image_views.iter().for_each(|v| self.device.destroy_image_view(*v, None));
-        self.device.destroy_swapchain_khr(self.data.swapchain, None);
     }
 
+    /// Rebuilds the swapchain at the window's size. Only what is sized to it is remade, the viewport and
+    /// scissor being set as the frame is recorded, unless its format, image count or the sample count
+    /// changed, which the passes and pipelines are made for.
     pub unsafe fn recreate_swapchain(&mut self, window: &Window) -> Result<()> {
+        self.rebuild_swapchain(window, false)
+    }
+
+    /// Rebuilds the swapchain, with its passes and pipelines too if `pipelines` or they need it.
+    unsafe fn rebuild_swapchain(&mut self, window: &Window, pipelines: bool) -> Result<()> {
         self.device.device_wait_idle()?;
-        self.destroy_swapchain();
+        let before = (self.data.swapchain_format, self.data.swapchain_images.len(), self.data.msaa_samples);
+        self.destroy_swapchain_targets();
         create_swapchain(window, &self.instance, &self.device, &mut self.data)?;
         create_swapchain_image_views(&self.device, &mut self.data)?;
-        create_render_pass(&self.instance, &self.device, &mut self.data)?;
-        create_pipeline(&self.device, &mut self.data)?;
-        create_depth_objects(&self.instance, &self.device, &mut self.data)?;
-        create_framebuffers(&self.device, &mut self.data)?;
-        create_uniform_buffers(&self.instance, &self.device, &mut self.data)?;
-        create_descriptor_pool(&self.device, &mut self.data)?;
-        create_descriptor_sets(&self.device, &mut self.data)?;
+        self.data.msaa_samples = self.msaa_samples();
+        if pipelines || before != (self.data.swapchain_format, self.data.swapchain_images.len(), self.data.msaa_samples) {
+            self.destroy_swapchain_pipelines();
+            create_render_pass(&self.instance, &self.device, &mut self.data)?;
+            create_pipeline(&self.device, &mut self.data)?;
+            create_tonemap(&self.device, &mut self.data)?;
+            create_bloom(&self.device, &mut self.data)?;
+            create_oit(&self.device, &mut self.data)?;
+            create_uniform_buffers(&self.instance, &self.device, &mut self.data)?;
+            create_descriptor_pool(&self.device, &mut self.data)?;
+            create_ssao(&self.instance, &self.device, &mut self.data)?;
+            create_descriptor_sets(&self.device, &mut self.data)?;
+            create_indirect(&self.instance, &self.device, &mut self.data)?;
+            create_screen_space_fluid(&self.instance, &self.device, &mut self.data)?;
+            create_debug_view(&self.device, &mut self.data)?;
+            create_raymarch_descriptor_sets(&self.device, &mut self.data)?;
+            create_secondaries(&self.instance, &self.device, &mut self.data)?;
+        }
+        // the framebuffers and the sets sampling the targets are remade
+        invalidate_secondaries(&mut self.data);
+        create_swapchain_targets(&self.instance, &self.device, &mut self.data)?;
         create_command_buffers(&self.device, &mut self.data)?;
         self.data.images_in_flight.resize(self.data.swapchain_images.len(), vk::Fence::null());
         Ok(())
@@ -188,66 +690,2064 @@ impl App {
         &self.data
     }
 
-    pub fn resized(&mut self, newval: bool) {
-        self.resized = newval;
+    /// The scene the app was created from; later edits through the app are not reflected in it.
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    pub fn sim(&self) -> &SimParams {
+        &self.sim
+    }
+
+    /// The time step the CFL condition chose for the current frame.
+    pub fn timestep(&self) -> f32 {
+        self.time_step.dt
+    }
+
+    /// Upper bound on the live particle count; the solver dispatches over this many.
+    pub fn particle_count(&self) -> u32 {
+        self.sim.particle_count
+    }
+
+    /// Adds an emitter and returns its index.
+    pub fn add_emitter(&mut self, emitter: Emitter) -> usize {
+        self.data.emitters.push(emitter);
+        self.data.emitters.len() - 1
+    }
+
+    /// Removes an emitter; particles it already released stay in the simulation.
+    pub fn remove_emitter(&mut self, index: usize) -> Option<Emitter> {
+        (index < self.data.emitters.len()).then(|| self.data.emitters.remove(index))
+    }
+
+    pub fn set_emitter_active(&mut self, index: usize, active: bool) {
+        if let Some(emitter) = self.data.emitters.get_mut(index) {
+            if active { emitter.start() } else { emitter.stop() }
+        }
+    }
+
+    /// Moves an object; the fluid collides with it at its new place.
+    pub unsafe fn set_object_transform(&mut self, index: usize, transform: glm::Mat4) -> Result<()> {
+        if let Some(obj) = self.data.objects.get_mut(index) {
+            obj.transform = transform;
+        }
+        self.update_obstacles()
+    }
+
+    pub unsafe fn set_sdf_resolution(&mut self, index: usize, resolution: u32) -> Result<()> {
+        if let Some(obj) = self.data.objects.get_mut(index) {
+            obj.sdf_resolution = resolution;
+        }
+        self.update_obstacles()
+    }
+
+    /// Chooses how the fluid collides with an object.
+    pub unsafe fn set_obstacle(&mut self, index: usize, obstacle: Obstacle) -> Result<()> {
+        if let Some(obj) = self.data.objects.get_mut(index) {
+            obj.obstacle = obstacle;
+        }
+        self.update_obstacles()
+    }
+
+    /// Chooses whether particles near an object's SDF split, while the refinement is on.
+    pub unsafe fn set_obstacle_refine(&mut self, index: usize, refine: bool) -> Result<()> {
+        if let Some(obj) = self.data.objects.get_mut(index) {
+            obj.refine = refine;
+        }
+        self.update_obstacles()
+    }
+
+    /// Switches the smoothing kernel. Recompiles the solver passes, since the kernel is a specialization constant.
+    pub unsafe fn set_kernel(&mut self, kind: KernelKind) -> Result<()> {
+        self.device.device_wait_idle()?;
+        self.data.kernel_kind = kind;
+        self.sim.set_kernel(kind, self.sim.h);
+        destroy_compute_pipelines(&self.device, &self.data);
+        create_compute_pipelines(&self.device, &mut self.data)?;
+        // boundary volumes are kernel sums
+        self.update_obstacles()
     }
 
-    pub fn handle_mouse(&mut self, x_diff: f32, y_diff: f32) -> Result<()> {
-        self.camera.handle_mouse(x_diff, y_diff)?;
+    /// Switches how particles meet the domain walls. Recompiles the solver passes, since the mode is a
+    /// specialization constant.
+    pub unsafe fn set_boundary_mode(&mut self, mode: BoundaryMode) -> Result<()> {
+        self.device.device_wait_idle()?;
+        self.data.boundary_mode = mode;
+        destroy_compute_pipelines(&self.device, &self.data);
+        create_compute_pipelines(&self.device, &mut self.data)
+    }
+
+    pub fn boundary_mode(&self) -> BoundaryMode {
+        self.data.boundary_mode
+    }
+
+    /// Rebakes every obstacle field and re-records the draw commands after objects changed.
+    unsafe fn update_obstacles(&mut self) -> Result<()> {
+        self.device.device_wait_idle()?;
+        destroy_obstacle_buffers(&self.device, &self.data);
+        self.sim.sdf_count = create_sdf_buffers(&self.instance, &self.device, &mut self.data)?;
+        self.sim.boundary_count = create_boundary_buffer(&self.instance, &self.device, &mut self.data, &self.sim)?;
+        write_compute_descriptor_sets(&self.device, &self.data);
+        self.normals_stale |= self.data.normal_lines.is_some();
         Ok(())
     }
-}
 
-unsafe fn create_instance(window: &Window, entry: &Entry, data: &mut AppData) -> Result<Instance> {
-    // Application Info
+    /// Sets this frame's time step and returns how many solver steps to take, per the time mode. While
+    /// paused the solver, its time step and the bodies stay frozen; a queued single step still runs a
+    /// frame's worth of fixed steps, or one realtime step.
+    unsafe fn plan_steps(&mut self) -> Result<u32> {
+        let elapsed = std::mem::replace(&mut self.last_frame, Instant::now()).elapsed().as_secs_f64();
+        if self.paused {
+            self.lag = 0.0;
+            if self.pending_steps == 0 {
+                return Ok(0);
+            }
+            self.pending_steps -= 1;
+        }
+        match self.time_mode {
+            TimeMode::Fixed { dt, substeps } => {
+                self.sim.dt = dt;
+                // a single step while paused is one solver step, not a frame's worth
+                Ok(if self.paused { 1 } else { substeps.max(1) })
+            }
+            TimeMode::Realtime { max_substeps } => {
+                // the reduction this frame slot last ran has finished, so its max speed drives the next dt
+                let max_speed = self.diagnostics.max_speed;
+                self.sim.dt = self.time_step.update(max_speed, self.sim.h);
+                debug!("dt = {:.6}s (max speed {:.4})", self.sim.dt, max_speed);
+                if self.paused {
+                    return Ok(1);
+                }
+                // whole steps only; the remainder carries over, unless the frame cap left us behind for good
+                let max_substeps = max_substeps.max(1);
+                self.lag += elapsed;
+                let substeps = ((self.lag / self.sim.dt as f64) as u32).min(max_substeps);
+                self.lag = if substeps == max_substeps { 0.0 } else { self.lag - substeps as f64 * self.sim.dt as f64 };
+                Ok(substeps)
+            }
+        }
+    }
 
-    let application_info = vk::ApplicationInfo::builder()
-        .application_name(b"Vulkan Tutorial (Rust)\0")
-        .application_version(vk::make_version(1, 0, 0))
-        .engine_name(b"No Engine\0")
-        .engine_version(vk::make_version(1, 0, 0))
-        .api_version(vk::make_version(1, 0, 0));
+    /// Runs the CPU side of this frame slot's `substeps` solver steps and records their compute commands.
+    /// Emitters and bodies advance once over the whole frame.
+    unsafe fn record_step(&mut self, substeps: u32) -> Result<vk::CommandBuffer> {
+        let frame_dt = self.sim.dt * substeps as f32;
+        self.sim.frame = self.frame as u32;
+        // the GPU owns the live count; bound it by the last read-back plus what other frames may have added
+        let live_count = read_live_count(&self.device, &self.data, self.frame)?;
+        self.added_in_flight[self.frame] = 0;
+        let estimate = live_count + self.added_in_flight.iter().sum::<u32>();
+        let emitted = stage_emitted_particles(&mut self.data, self.frame, &self.sim, frame_dt, estimate);
+        self.sim.emit_count = emitted;
+        self.sim.sink_count = stage_sinks(&self.data, self.frame);
+        stage_openings(&self.data, self.frame);
+        stage_mouse_force(&self.data, self.frame);
+        self.step_bodies(frame_dt)?;
+        self.sim.body_count = stage_bodies(&self.data, self.frame);
+        // splits go past the emitted particles, up to the headroom granted here
+        let splits = if self.sim.refinement().is_some() { substeps * REFINE_MAX_SPLITS } else { 0 };
+        self.sim.particle_count = (estimate + emitted + splits).min(self.data.particle_capacity.max(estimate + emitted));
+        self.added_in_flight[self.frame] = self.sim.particle_count - estimate;
+        if let Some(animation) = self.gravity_animation {
+            self.sim.gravity = glm::vec3_to_vec4(&animation.at(self.sim_time));
+        }
+        // the walls stand where the middle of the frame has them
+        let running = self.container_running;
+        let clock = self.container_clock + if running { frame_dt as f64 / 2.0 } else { 0.0 };
+        self.sim.set_container(self.container_motion.as_ref(), clock, running);
+        if running {
+            self.container_clock += frame_dt as f64;
+        }
+        self.sim.update(self.frame, &self.data, &self.device)?;
+        self.sim_time += self.sim.dt as f64 * substeps as f64;
+        let command_buffer = record_simulation_commands(&self.device, &self.data, self.frame, &self.sim, substeps)?;
+        // a push lasts one frame, until the cursor moves again
+        self.data.mouse_force = None;
+        self.data.particle_parity ^= (substeps & 1) as usize;
+        Ok(command_buffer)
+    }
 
-    // Layers
-    let available_layers = entry.enumerate_instance_layer_properties()?.iter().map(|l| l.layer_name).collect::<HashSet<_>>();
-    if VALIDATION_ENABLED && !available_layers.contains(&VALIDATION_LAYER) {
-        return Err(anyhow!("Validation layer requested but not supported."));
+    /// Health of the fluid as of a recent step; see `Diagnostics`. It trails the newest step by the
+    /// frames in flight, so reading it never waits on the GPU.
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.diagnostics
     }
 
-    let layers: Vec<*const i8> = if VALIDATION_ENABLED {
-        vec![VALIDATION_LAYER.as_ptr()]
-    } else {
-        Vec::new()
-    };
+    /// Logs the diagnostics every `seconds`, or never.
+    pub fn set_diagnostics_interval(&mut self, seconds: Option<f32>) {
+        self.diagnostics_interval = seconds;
+    }
 
-    // Extensions
-    let mut extensions = vk_window::get_required_instance_extensions(window).iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+    /// Folds the timestamps of the frame slot whose fence just signaled into the timings, and logs the
+    /// rolling average once a second.
+    unsafe fn read_timings(&mut self) -> Result<()> {
+        let mut latest = self.timings;
+        let mut fresh = false;
+        let stepped = self.timing_pending[self.frame].take();
+        let mut step_read = false;
+        if let Some(tiled) = stepped {
+            if let Some(step) = read_step_timings(&self.device, &self.data, self.frame)? {
+                let ms = step.density + step.force;
+                let average = &mut self.neighbor_pass_times[tiled as usize];
+                *average = Some(average.map_or(ms, |a| 0.9 * a + 0.1 * ms));
+                latest = FrameTimings { render: latest.render, ..step };
+                fresh = true;
+                step_read = true;
+            }
+        }
+        if let Some((image, prepass)) = self.render_pending[self.frame].take() {
+            if let Some(ms) = read_render_time(&self.device, &self.data, image)? {
+                let average = &mut self.depth_prepass_times[prepass as usize];
+                *average = Some(average.map_or(ms, |a| 0.9 * a + 0.1 * ms));
+                latest.render = ms;
+                fresh = true;
+            }
+        }
+        if let (Some(bench), Some(_)) = (&mut self.bench, stepped) {
+            bench.complete(step_read.then_some(&latest));
+        }
+        if fresh {
+            self.timings = latest;
+            match &mut self.average_timings {
+                Some(average) => average.blend(&latest, 0.1),
+                None => self.average_timings = Some(latest),
+            }
+        }
+        if let Some(average) = self.average_timings.filter(|_| self.last_timing_log.elapsed().as_secs() >= 1) {
+            info!("GPU {}", average);
+            self.last_timing_log = Instant::now();
+        }
+        Ok(())
+    }
 
-    // if cfg!(target_os = "macos") {
-    let names = &[ExtensionName::from_bytes(b"VK_KHR_portability_enumeration"),
-        ExtensionName::from_bytes(b"VK_KHR_get_physical_device_properties2")];
-    names.iter().for_each(|name| extensions.push(name.as_ptr()));
-    // }
-   
-    if VALIDATION_ENABLED {
-        extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION.name.as_ptr());
+    /// Takes the diagnostics of the step this frame slot last ran, whose fence just signaled, and logs
+    /// them once every `diagnostics_interval` seconds.
+    unsafe fn update_diagnostics(&mut self) -> Result<()> {
+        self.diagnostics = read_diagnostics(&self.device, &self.data, self.frame)?;
+        // the particles keep whatever divergence was last measured, long gone once nothing measures it
+        if !self.data.measure_divergence {
+            (self.diagnostics.max_divergence, self.diagnostics.mean_divergence) = (0.0, 0.0);
+        }
+        self.data.max_speed = self.diagnostics.max_speed;
+        self.update_color_range();
+        let due = self.diagnostics_interval.is_some_and(|s| self.last_diagnostics_log.elapsed().as_secs_f32() >= s);
+        if due {
+            info!("Fluid: {}", self.diagnostics);
+            self.last_diagnostics_log = Instant::now();
+        }
+        Ok(())
     }
 
-    // Create
-    let mut info = vk::InstanceCreateInfo::builder()
-        .flags(InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR)
-        .application_info(&application_info)
-        .enabled_layer_names(&layers)
-        .enabled_extension_names(&extensions);
-        
+    /// Whether the step about to be recorded should bin the densities for the compression check.
+    fn histogram_due(&mut self) -> bool {
+        let Some(check) = self.compression_check else {
+            return false;
+        };
+        self.frames_since_histogram += 1;
+        if self.frames_since_histogram < check.interval {
+            return false;
+        }
+        self.frames_since_histogram = 0;
+        true
+    }
 
-    let mut debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
-        .message_severity(vk::DebugUtilsMessageSeverityFlagsEXT::all())
-        .message_type(vk::DebugUtilsMessageTypeFlagsEXT::all())
-        .user_callback(Some(debug_callback));
+    /// Whether the step about to be recorded should record a trail sample, numbering it if so.
+    fn trails_due(&mut self) -> bool {
+        let Some(trails) = self.data.trails else {
+            return false;
+        };
+        self.frames_since_trail += 1;
+        if self.frames_since_trail < trails.interval {
+            return false;
+        }
+        self.frames_since_trail = 0;
+        self.data.trail_stamp = self.data.trail_stamp.wrapping_add(1);
+        true
+    }
 
-    if VALIDATION_ENABLED {
-        info = info.push_next(&mut debug_info);
+    /// Takes the density histogram of the step this frame slot last ran, if it binned one, and pauses
+    /// the solver with an error logged when the compression check fails on it.
+    unsafe fn check_compression(&mut self) -> Result<()> {
+        if !std::mem::take(&mut self.histogram_pending[self.frame]) {
+            return Ok(());
+        }
+        let histogram = read_density_histogram(&self.device, &self.data, self.frame)?;
+        self.density_histogram = Some(histogram);
+        if let Some(Err(e)) = self.compression_check.map(|c| c.check(&histogram)) {
+            if !self.paused {
+                error!("Compression check failed at {:.3} s, pausing: {}", self.sim_time, e);
+                self.paused = true;
+                self.pending_steps = 0;
+            }
+        }
+        Ok(())
+    }
+
+    /// Advances every rigid body `dt` seconds under the fluid forces reduced by this frame slot's last
+    /// step, and moves the objects they drive.
+    unsafe fn step_bodies(&mut self, dt: f32) -> Result<()> {
+        let forces = read_body_forces(&self.device, &self.data, self.frame)?;
+        let gravity = self.sim.gravity.xyz();
+        let (min, max) = (self.sim.domain_min.xyz(), self.sim.domain_max.xyz());
+        let to_scene = glm::inverse(&scene_model());
+        for (body, force) in self.data.bodies.iter_mut().zip(&forces) {
+            body.step(force, &gravity, dt, &min, &max);
+            self.data.objects[body.object].transform = to_scene * body.matrix() * body.rest_transform;
+        }
+        Ok(())
+    }
+
+    /// Turns an object into a rigid body of the given density that floats in and pushes on the fluid.
+    pub unsafe fn add_rigid_body(&mut self, object: usize, density: f32) -> Result<Option<usize>> {
+        if object >= self.data.objects.len() || self.data.bodies.len() >= MAX_BODIES
+            || self.data.bodies.iter().any(|b| b.object == object) {
+            return Ok(None);
+        }
+        let (min, max) = self.data.objects[object].bounds(&scene_model());
+        self.data.bodies.push(RigidBody::from_bounds(object, min, max, density));
+        self.data.objects[object].obstacle = Obstacle::Particles;
+        self.update_obstacles()?;
+        Ok(Some(self.data.bodies.len() - 1))
+    }
+
+    /// Freezes a rigid body where it is; the object stays behind as a static obstacle.
+    pub unsafe fn remove_rigid_body(&mut self, index: usize) -> Result<Option<RigidBody>> {
+        if index >= self.data.bodies.len() {
+            return Ok(None);
+        }
+        let body = self.data.bodies.remove(index);
+        self.update_obstacles()?;
+        Ok(Some(body))
+    }
+
+    pub fn rigid_body(&self, index: usize) -> Option<&RigidBody> {
+        self.data.bodies.get(index)
+    }
+
+    /// Adds a box centered at the world-space `center` and returns its object index.
+    pub unsafe fn add_cube(&mut self, center: glm::Vec3, half_extent: glm::Vec3) -> Result<usize> {
+        let mut cube = Object::cube(half_extent, &self.instance, &self.device, &mut self.data)?;
+        cube.transform = glm::inverse(&scene_model()) * glm::translation(&center);
+        self.data.objects.push(cube);
+        self.update_obstacles()?;
+        merge_object_geometry(&self.instance, &self.device, &mut self.data)?;
+        Ok(self.data.objects.len() - 1)
+    }
+
+    /// Adds an axis-aligned sink box that deletes every particle entering it, and returns its index.
+    pub fn add_sink(&mut self, sink: Sink) -> usize {
+        self.data.sinks.push(sink);
+        self.data.sinks.len() - 1
+    }
+
+    pub fn remove_sink(&mut self, index: usize) -> Option<Sink> {
+        (index < self.data.sinks.len()).then(|| self.data.sinks.remove(index))
+    }
+
+    pub fn set_sink_active(&mut self, index: usize, active: bool) {
+        if let Some(sink) = self.data.sinks.get_mut(index) {
+            sink.active = active;
+        }
+    }
+
+    /// Adds an inflow, which feeds particles from the next step on, and returns its index. Inflows and
+    /// outflows past `MAX_OPENINGS` together stay idle.
+    pub fn add_inflow(&mut self, inflow: Inflow) -> usize {
+        self.data.inflows.push(inflow);
+        self.data.inflows.len() - 1
+    }
+
+    pub fn set_inflow_active(&mut self, index: usize, active: bool) {
+        if let Some(inflow) = self.data.inflows.get_mut(index) {
+            inflow.active = active;
+        }
+    }
+
+    /// Adds an outflow and returns its index.
+    pub fn add_outflow(&mut self, outflow: Outflow) -> usize {
+        self.data.outflows.push(outflow);
+        self.data.outflows.len() - 1
+    }
+
+    pub fn set_outflow_active(&mut self, index: usize, active: bool) {
+        if let Some(outflow) = self.data.outflows.get_mut(index) {
+            outflow.active = active;
+        }
+    }
+
+    /// Switches how frames advance the solver; see `TimeMode`.
+    pub fn set_time_mode(&mut self, mode: TimeMode) {
+        self.time_mode = mode;
+        self.lag = 0.0;
+    }
+
+    pub fn time_mode(&self) -> TimeMode {
+        self.time_mode
+    }
+
+    /// Visits neighbors in an order fixed by the particles alone, by sorting each grid cell after the
+    /// counting sort. Without it, atomics order the particles within a cell differently from run to run,
+    /// so the GPU float sums, and with them a `TimeMode::Fixed` run, can differ in the last bits; with
+    /// it, runs on the same device, driver and settings match bit for bit.
+    pub fn set_strict_order(&mut self, strict: bool) {
+        self.data.strict_order = strict;
+    }
+
+    pub fn strict_order(&self) -> bool {
+        self.data.strict_order
+    }
+
+    /// Bins the particle densities every `check.interval` frames and pauses the solver once the
+    /// fraction of compressed particles goes over `check.limit`; `None` turns the check off.
+    pub fn set_compression_check(&mut self, check: Option<CompressionCheck>) {
+        self.compression_check = check;
+        self.frames_since_histogram = 0;
+    }
+
+    pub fn compression_check(&self) -> Option<CompressionCheck> {
+        self.compression_check
+    }
+
+    /// The newest density histogram the compression check binned, trailing the newest step by the
+    /// frames in flight like the diagnostics.
+    pub fn density_histogram(&self) -> Option<DensityHistogram> {
+        self.density_histogram
+    }
+
+    /// Sets the strength of the vorticity confinement force, in m/s, which feeds back the small swirls
+    /// the SPH smoothing damps out; zero skips its two passes altogether.
+    pub fn set_vorticity_epsilon(&mut self, epsilon: f32) {
+        self.sim.vorticity_epsilon = epsilon.max(0.0);
+    }
+
+    pub fn vorticity_epsilon(&self) -> f32 {
+        self.sim.vorticity_epsilon
+    }
+
+    /// Switches the free-surface density correction, which renormalizes the density of particles whose
+    /// kernel the surface cuts short by how much of it is filled, so the top layers are not pulled down
+    /// into clumps. It costs one more neighbor pass per step.
+    pub fn set_surface_correction(&mut self, on: bool) {
+        self.sim.surface_threshold = if on { SURFACE_THRESHOLD } else { 0.0 };
+    }
+
+    pub fn surface_correction(&self) -> bool {
+        self.sim.surface_threshold > 0.0
+    }
+
+    /// Turns the adaptive resolution on with the given criteria, or off. Particles near the free surface
+    /// or a refining obstacle split in two, and pairs of halves deep in the fluid merge again, so the
+    /// detail goes where the fluid shows it; see `Refinement`. Split particles take up the emitters'
+    /// headroom, up to `REFINE_MAX_SPLITS` per substep, and turning it off leaves them split.
+    pub fn set_refinement(&mut self, refinement: Option<Refinement>) {
+        self.sim.set_refinement(refinement.as_ref());
+    }
+
+    pub fn refinement(&self) -> Option<Refinement> {
+        self.sim.refinement()
+    }
+
+    /// Turns the surface detection on with the given thresholds, or off. While on, the color field pass
+    /// flags the particles on the free surface with `Particle::SURFACE` and the diagnostics count them.
+    pub fn set_surface_detection(&mut self, detection: Option<SurfaceDetection>) {
+        self.sim.set_surface_detection(detection.as_ref());
+    }
+
+    pub fn surface_detection(&self) -> Option<SurfaceDetection> {
+        self.sim.surface_detection()
+    }
+
+    /// Chooses how particles are colored when drawn; tinting the surface turns its detection on with
+    /// the default thresholds if it is off, and coloring by divergence switches to the diverging colormap.
+    pub fn set_particle_coloring(&mut self, coloring: ParticleColoring) {
+        if coloring == ParticleColoring::Surface && self.surface_detection().is_none() {
+            self.set_surface_detection(Some(SurfaceDetection::default()));
+        }
+        if coloring == ParticleColoring::Divergence {
+            self.data.colormap = Colormap::Coolwarm;
+        }
+        self.data.particle_coloring = coloring;
+        self.color_range_primed = false;
+        self.update_color_range();
+    }
+
+    pub fn particle_coloring(&self) -> ParticleColoring {
+        self.data.particle_coloring
+    }
+
+    /// Sets the values of the coloring scalar the ends of the colormap stand for.
+    pub fn set_color_range(&mut self, range: ColorRange) {
+        self.color_range = range;
+        self.color_range_primed = false;
+        self.update_color_range();
+    }
+
+    pub fn color_range(&self) -> ColorRange {
+        self.color_range
+    }
+
+    /// The values at the ends of the colormap as drawn now, eased or fixed.
+    pub fn current_color_range(&self) -> [f32; 2] {
+        self.data.color_range
+    }
+
+    /// Chooses the colormap scalars are drawn through.
+    pub fn set_colormap(&mut self, colormap: Colormap) {
+        self.data.colormap = colormap;
+    }
+
+    pub fn colormap(&self) -> Colormap {
+        self.data.colormap
+    }
+
+    /// Draws the meshes with texture coordinates with the PNG or JPEG at `path`, or untextured with `None`.
+    pub unsafe fn load_mesh_texture(&mut self, path: Option<&str>) -> Result<()> {
+        let texture = MeshTexture::load(&self.instance, &self.device, &self.data, path, COLOR_TEXTURE_FORMAT)?;
+        self.device.device_wait_idle()?;
+        std::mem::replace(&mut self.data.mesh_texture, texture).destroy(&self.device);
+        write_mesh_texture(&self.device, &self.data);
+        invalidate_secondaries(&mut self.data);
+        Ok(())
+    }
+
+    /// Draws the sky behind the scene with the faces in the directory at `path` or the panorama there, or
+    /// the gradient with `None`.
+    pub unsafe fn load_skybox(&mut self, path: Option<&str>) -> Result<()> {
+        let texture = SkyboxTexture::load(&self.instance, &self.device, &self.data, path)?;
+        self.device.device_wait_idle()?;
+        std::mem::replace(&mut self.data.skybox_texture, texture).destroy(&self.device);
+        write_skybox(&self.device, &self.data);
+        invalidate_secondaries(&mut self.data);
+        Ok(())
+    }
+
+    /// Shows or hides the skybox; without it the scene is drawn against the clear color.
+    pub fn set_skybox(&mut self, shown: bool) {
+        self.data.skybox = shown;
+    }
+
+    pub fn skybox(&self) -> bool {
+        self.data.skybox
+    }
+
+    /// Draws the light's shadows over the meshes with `shadows`' settings, or none with `None`, remaking
+    /// the shadow map if its resolution changes.
+    pub unsafe fn set_shadows(&mut self, shadows: Option<ShadowMapping>) -> Result<()> {
+        if let Some(resolution) = shadows.map(|s| s.resolution.max(1)).filter(|r| *r != self.data.shadow_resolution) {
+            self.device.device_wait_idle()?;
+            destroy_shadow_map(&self.device, &self.data);
+            create_shadow_map(&self.instance, &self.device, &mut self.data, resolution)?;
+            write_shadow_map(&self.device, &self.data);
+            invalidate_secondaries(&mut self.data);
+        }
+        self.data.shadows = shadows;
+        Ok(())
+    }
+
+    pub fn shadows(&self) -> Option<ShadowMapping> {
+        self.data.shadows
+    }
+
+    /// Draws the light's shadows as a point's, in every direction from it, with `shadows`' settings in
+    /// place of the directional ones, or stops with `None`, remaking the cube shadow map if its
+    /// resolution changes.
+    pub unsafe fn set_point_shadows(&mut self, shadows: Option<PointShadows>) -> Result<()> {
+        if let Some(resolution) = shadows.map(|s| s.resolution.max(1))
+            .filter(|r| *r != self.data.point_shadow_resolution) {
+            self.device.device_wait_idle()?;
+            destroy_point_shadow_map(&self.device, &self.data);
+            create_point_shadow_map(&self.instance, &self.device, &mut self.data, resolution)?;
+            write_point_shadow_map(&self.device, &self.data);
+            invalidate_secondaries(&mut self.data);
+            self.point_shadows_drawn = None;
+        }
+        self.data.point_shadows = shadows;
+        Ok(())
+    }
+
+    pub fn point_shadows(&self) -> Option<PointShadows> {
+        self.data.point_shadows
+    }
+
+    /// Moves the key light to `position` in the world the fluid lives in, or turns it to shine from there
+    /// if directional.
+    pub fn set_light_position(&mut self, position: glm::Vec3) {
+        match self.data.lights.first_mut() {
+            Some(key) => key.move_to(position),
+            None => self.ubo.light_pos = position,
+        }
+    }
+
+    pub fn light_position(&self) -> glm::Vec3 {
+        self.data.lights.first().map_or(self.ubo.light_pos, Light::origin)
+    }
+
+    /// Tonemaps the HDR scene into the window with `tonemapping`'s curve and exposure.
+    pub fn set_tonemapping(&mut self, tonemapping: Tonemapping) {
+        self.data.tonemapping = Tonemapping { exposure: tonemapping.exposure.max(0.0), ..tonemapping };
+    }
+
+    pub fn tonemapping(&self) -> Tonemapping {
+        self.data.tonemapping
+    }
+
+    /// Blooms the light in the HDR scene above `bloom`'s threshold over its surroundings, or none with
+    /// `None`. Its levels are made for each swapchain, so only their count is clamped.
+    pub fn set_bloom(&mut self, bloom: Option<Bloom>) {
+        self.data.bloom = bloom.map(|b| Bloom { threshold: b.threshold.max(0.0), intensity: b.intensity.max(0.0),
+            mips: b.mips.max(1) });
+    }
+
+    pub fn bloom(&self) -> Option<Bloom> {
+        self.data.bloom
+    }
+
+    /// Darkens the ambient light on the meshes and the fluid surface where `ssao` finds them occluded, or
+    /// nowhere with `None`.
+    pub fn set_ssao(&mut self, ssao: Option<Ssao>) {
+        self.data.ssao = ssao.map(|s| Ssao { radius: s.radius.max(0.0), samples: s.samples.clamp(1, SSAO_MAX_SAMPLES as u32),
+            intensity: s.intensity.max(0.0) });
+    }
+
+    pub fn ssao(&self) -> Option<Ssao> {
+        self.data.ssao
+    }
+
+    /// Weighs the translucent meshes and particles into transparency targets of their own and composites
+    /// them over the opaque scene, in whatever order they are drawn, or blends them in it in the order
+    /// they are drawn, the particles sorted back to front. The screen-space fluid's scene blends them
+    /// either way.
+    pub fn set_oit(&mut self, oit: bool) {
+        self.data.oit = oit;
+    }
+
+    pub fn oit(&self) -> bool {
+        self.data.oit
+    }
+
+    /// Lays the opaque meshes' and particles' depth down before shading them, for each pixel to be shaded
+    /// once, or shades them as they are drawn; see `depth_prepass_times` for which is faster.
+    pub fn set_depth_prepass(&mut self, prepass: bool) {
+        self.data.depth_prepass = prepass;
+    }
+
+    pub fn depth_prepass(&self) -> bool {
+        self.data.depth_prepass
+    }
+
+    /// Average GPU milliseconds of the render pass, without and with the depth pre-pass, where measured.
+    pub fn depth_prepass_times(&self) -> [Option<f32>; 2] {
+        self.depth_prepass_times
+    }
+
+    /// Shows `debug`'s part of the meshes' shading in place of it from the next frame on.
+    pub fn set_shading_debug(&mut self, debug: ShadingDebug) {
+        self.data.shading_debug = debug;
+    }
+
+    pub fn shading_debug(&self) -> ShadingDebug {
+        self.data.shading_debug
+    }
+
+    /// Shades the meshes with `lights`, the first of them the key light, of which only the first
+    /// `MAX_LIGHTS` are drawn.
+    pub fn set_lights(&mut self, lights: Vec<Light>) {
+        if lights.len() > MAX_LIGHTS {
+            warn!("Only the first {} of {} lights are drawn.", MAX_LIGHTS, lights.len());
+        }
+        self.data.lights = lights;
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.data.lights
+    }
+
+    /// Shows or hides the bar through the colormap drawn in a corner while particles are colored by a
+    /// scalar or the density slice is shown.
+    pub fn set_legend(&mut self, legend: bool) {
+        self.data.legend = legend;
+    }
+
+    pub fn legend(&self) -> bool {
+        self.data.legend
+    }
+
+    /// Moves the colormap's range towards the coloring scalar's range over the particles as of the latest
+    /// diagnostics, or sets the fixed one.
+    fn update_color_range(&mut self) {
+        let d = &self.diagnostics;
+        let [min, max] = match (self.color_range, self.data.particle_coloring) {
+            (ColorRange::Fixed { min, max }, _) => [min, max],
+            (ColorRange::Auto, ParticleColoring::Density) => d.density_range,
+            (ColorRange::Auto, ParticleColoring::Speed) => [0.0, d.max_speed],
+            (ColorRange::Auto, ParticleColoring::Pressure) => d.pressure_range,
+            // either way from zero alike, so no divergence sits at the diverging colormap's middle
+            (ColorRange::Auto, ParticleColoring::Divergence) => [-d.max_divergence, d.max_divergence],
+            (ColorRange::Auto, ParticleColoring::Phase | ParticleColoring::Surface) => return,
+        };
+        // nothing to spread the colors over: no particles, no step yet, or all alike
+        let auto = self.color_range == ColorRange::Auto;
+        if auto && min >= max {
+            return;
+        }
+        let t = if auto && self.color_range_primed { COLOR_RANGE_EASING } else { 1.0 };
+        let [low, high] = self.data.color_range;
+        self.data.color_range = [low + t * (min - low), high + t * (max - high)];
+        self.color_range_primed = true;
+    }
+
+    /// Selects the next of `TUNABLES` for `tune` to adjust, wrapping around, and returns it.
+    pub fn select_next_tunable(&mut self) -> &'static Tunable {
+        self.tunable = (self.tunable + 1) % TUNABLES.len();
+        self.selected_tunable()
+    }
+
+    pub fn selected_tunable(&self) -> &'static Tunable {
+        &TUNABLES[self.tunable]
+    }
+
+    /// Multiplies the selected parameter by `factor`, clamped to its range, and returns the new value;
+    /// the next step runs with it.
+    pub fn tune(&mut self, factor: f32) -> f32 {
+        TUNABLES[self.tunable].scale(&mut self.sim, factor)
+    }
+
+    /// Pauses or resumes the solver; rendering carries on either way.
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.pending_steps = 0;
+        info!("Simulation {}.", if self.paused { "paused" } else { "resumed" });
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Queues exactly one solver step while paused; each press runs once, however fast frames come.
+    pub fn step_once(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    /// Puts the particles back to their spawn state. Emitters, sinks and bodies are left as they are.
+    pub unsafe fn reset(&mut self) -> Result<()> {
+        self.device.device_wait_idle()?;
+        reset_particles(&self.instance, &self.device, &mut self.data, &self.initial_particles)?;
+        self.sim.particle_count = self.initial_particles.len() as u32;
+        self.added_in_flight = [0; MAX_FRAMES_IN_FLIGHT];
+        self.time_step = TimeStep::new(CFL_SAFETY_FACTOR, MIN_TIMESTEP, MAX_TIMESTEP);
+        self.sim_time = 0.0;
+        self.frames_stepped = 0;
+        self.stats_pending = [None; MAX_FRAMES_IN_FLIGHT];
+        self.container_clock = 0.0;
+        self.lag = 0.0;
+        self.pending_steps = 0;
+        self.export_pending = [None; MAX_FRAMES_IN_FLIGHT];
+        self.selection_pending = [None; MAX_FRAMES_IN_FLIGHT];
+        self.watch_pending = [None; MAX_FRAMES_IN_FLIGHT];
+        self.histogram_pending = [false; MAX_FRAMES_IN_FLIGHT];
+        self.frames_since_histogram = 0;
+        self.surface_stale = true;
+        Ok(())
+    }
+
+    /// Adds a fluid phase and returns its index, or `None` once `MAX_PHASES` are in use.
+    pub fn add_phase(&mut self, phase: Phase) -> Option<u32> {
+        let index = self.sim.phase_count;
+        self.sim.set_phase(index as usize, &phase).then_some(index)
+    }
+
+    /// Changes an existing phase; particles already spawned keep their mass.
+    pub fn set_phase(&mut self, index: u32, phase: Phase) -> bool {
+        index < self.sim.phase_count && self.sim.set_phase(index as usize, &phase)
+    }
+
+    /// Replaces the particles and makes them the state `reset` returns to.
+    pub unsafe fn set_particles(&mut self, particles: Vec<Particle>) -> Result<()> {
+        self.device.device_wait_idle()?;
+        self.fit_particle_buffers(&particles)?;
+        self.initial_particles = particles;
+        self.deselect_particle();
+        self.reset()
+    }
+
+    /// Checks on the CPU that the latest step sorted every particle into its neighbor grid cell.
+    pub unsafe fn verify_grid(&self) -> Result<()> {
+        self.device.device_wait_idle()?;
+        let (particles, starts, counts) = read_grid(&self.instance, &self.device, &self.data)?;
+        if particles.is_empty() {
+            // nothing has been sorted since the last reset
+            return Ok(());
+        }
+        check_sorted(&self.sim, &particles, &starts, &counts)
+    }
+
+    /// Switches the density and force passes between the tiled and untiled variants; tiling stays off
+    /// when the device's shared memory is too small for it. Returns whether tiling is on.
+    pub fn set_tiled(&mut self, tiled: bool) -> bool {
+        if tiled && self.data.tile_size == 0 {
+            warn!("Not enough compute shared memory for tiling; keeping the untiled passes.");
+        }
+        self.data.tiled = tiled && self.data.tile_size > 0;
+        self.data.tiled
+    }
+
+    pub fn tiled(&self) -> bool {
+        self.data.tiled && self.data.tile_size > 0 && !self.sim.periodic()
+    }
+
+    /// Average GPU milliseconds of the density and force passes, untiled and tiled, where measured.
+    pub fn neighbor_pass_times(&self) -> [Option<f32>; 2] {
+        self.neighbor_pass_times
+    }
+
+    /// GPU time of the most recently measured frame's passes.
+    pub fn frame_timings(&self) -> FrameTimings {
+        self.timings
+    }
+
+    /// Rolling average of `frame_timings`, once anything has been measured.
+    pub fn average_frame_timings(&self) -> Option<FrameTimings> {
+        self.average_timings
+    }
+
+    /// Makes each axis of the domain a wall or periodic. Particles leaving through a periodic side come
+    /// back in through the opposite one and interact across it; such an axis must be at least 3 h long.
+    /// The density and force passes run untiled while any axis is periodic.
+    pub unsafe fn set_boundary_kinds(&mut self, kinds: [BoundaryKind; 3]) -> Result<()> {
+        if self.container_motion.is_some() && kinds.contains(&BoundaryKind::Periodic) {
+            return Err(anyhow!("Periodic boundaries cannot move with the container."));
+        }
+        self.sim.set_boundary_kinds(kinds)?;
+        self.resize_grid()
+    }
+
+    /// Makes the domain walls follow `motion` from rest, running, or holds them at rest with `None`.
+    /// The walls push the fluid with their own velocity, and the grid grows to cover where they go.
+    pub unsafe fn set_container_motion(&mut self, motion: Option<ContainerMotion>) -> Result<()> {
+        if motion.is_some() && self.sim.periodic() {
+            return Err(anyhow!("Periodic boundaries cannot move with the container."));
+        }
+        self.container_motion = motion;
+        self.container_running = motion.is_some();
+        self.container_clock = 0.0;
+        self.sim.set_container(motion.as_ref(), 0.0, self.container_running);
+        self.resize_grid()
+    }
+
+    pub fn container_motion(&self) -> Option<ContainerMotion> {
+        self.container_motion
+    }
+
+    /// Starts or stops the walls' motion; stopped, they stay where they are until started again.
+    pub fn set_container_running(&mut self, running: bool) {
+        self.container_running = running && self.container_motion.is_some();
+    }
+
+    pub fn container_running(&self) -> bool {
+        self.container_running
+    }
+
+    /// Recreates the grid buffers if the grid no longer has as many cells as they hold.
+    unsafe fn resize_grid(&mut self) -> Result<()> {
+        if self.sim.grid_dims[3] != self.data.grid_cells {
+            self.device.device_wait_idle()?;
+            let cell_starts = self.data.cell_starts_buffer;
+            unregister_scan_buffer(&self.device, &mut self.data, cell_starts)?;
+            destroy_grid_buffers(&self.device, &self.data);
+            create_grid_buffers(&self.instance, &self.device, &mut self.data, &self.sim)?;
+            write_compute_descriptor_sets(&self.device, &self.data);
+        }
+        Ok(())
+    }
+
+    /// Moves the wireframe domain box to where the walls are, and rebuilds the boxes if the domain or a
+    /// region changed since they were last built.
+    unsafe fn update_wireframe(&mut self) -> Result<()> {
+        let center = (self.sim.domain_min.xyz() + self.sim.domain_max.xyz()) / 2.0;
+        self.data.domain_transform = glm::translation(&(center + self.sim.container_offset.xyz()))
+            * glm::mat3_to_mat4(&self.sim.container_rotation()) * glm::translation(&-center);
+        let domain = WireBox { min: self.sim.domain_min.xyz(), max: self.sim.domain_max.xyz(), kind: WireKind::Domain };
+        let reach = |e: &Emitter| glm::vec3(e.radius, e.radius, e.radius);
+        let boxes = std::iter::once(domain)
+            .chain(self.data.emitters.iter().map(|e| WireBox { min: e.position - reach(e), max: e.position + reach(e),
+                kind: WireKind::Emitter }))
+            .chain(self.data.sinks.iter().map(|s| WireBox { min: s.min, max: s.max, kind: WireKind::Sink }))
+            .chain(self.data.inflows.iter().map(|i| WireBox { min: i.min, max: i.max, kind: WireKind::Inflow }))
+            .chain(self.data.outflows.iter().map(|o| WireBox { min: o.min, max: o.max, kind: WireKind::Outflow }))
+            .collect();
+        update_wire_boxes(&self.instance, &self.device, &mut self.data, boxes)
+    }
+
+    pub fn boundary_kinds(&self) -> [BoundaryKind; 3] {
+        [self.sim.boundary_kinds[0], self.sim.boundary_kinds[1], self.sim.boundary_kinds[2]]
+    }
+
+    pub fn gravity(&self) -> glm::Vec3 {
+        self.sim.gravity.xyz()
+    }
+
+    /// Sets gravity for the GPU solver and the rigid bodies; a running rotation continues from it.
+    pub fn set_gravity(&mut self, gravity: glm::Vec3) {
+        self.sim.gravity = glm::vec3_to_vec4(&gravity);
+        if let Some(animation) = &mut self.gravity_animation {
+            *animation = RotatingGravity::through(gravity, animation.axis, animation.period, self.sim_time);
+        }
+    }
+
+    /// Tilts gravity by `pitch` radians about the x axis and `roll` radians about the z axis.
+    pub fn tilt_gravity(&mut self, pitch: f32, roll: f32) {
+        let gravity = glm::rotate_vec3(&self.gravity(), pitch, &glm::vec3(1.0, 0.0, 0.0));
+        self.set_gravity(glm::rotate_vec3(&gravity, roll, &glm::vec3(0.0, 0.0, 1.0)));
+    }
+
+    pub fn gravity_animated(&self) -> bool {
+        self.gravity_animation.is_some()
+    }
+
+    /// Rotates gravity about `axis` once every `period` simulated seconds, or stops it with `None`
+    /// and leaves gravity where it is.
+    pub fn animate_gravity(&mut self, rotation: Option<(glm::Vec3, f32)>) {
+        self.gravity_animation = rotation.filter(|(axis, period)| glm::length(axis) > 0.0 && *period != 0.0)
+            .map(|(axis, period)| RotatingGravity::through(self.gravity(), axis, period, self.sim_time));
+    }
+
+    /// Starts writing every solver step's particles to numbered PLY files in `dir`. At most `queue` frames
+    /// wait for the disk before rendering stalls on it.
+    pub fn start_export(&mut self, dir: &str, fields: PlyFields, queue: usize) -> Result<()> {
+        self.exporter.start(dir, fields, queue.max(1))?;
+        self.data.export_enabled = true;
+        info!("Exporting particles to {}.", dir);
+        Ok(())
+    }
+
+    pub fn exporting(&self) -> bool {
+        self.exporter.is_running()
+    }
+
+    /// Stops exporting; frames already read back are still written.
+    pub fn stop_export(&mut self) {
+        self.exporter.stop();
+        self.data.export_enabled = self.recorder.is_some();
+    }
+
+    /// Starts writing the reconstructed fluid surface of every `interval`th stepped frame to numbered OBJ
+    /// files in `dir`, with a manifest of their simulated times; see `ObjExporter`. The surface is
+    /// rebuilt for it whether or not it is drawn. At most `queue` meshes wait for the disk.
+    pub fn start_surface_export(&mut self, dir: &str, interval: u32, queue: usize) -> Result<()> {
+        self.surface_exporter.start(dir, interval, queue.max(1))?;
+        self.surface_stale = true;
+        info!("Exporting the fluid surface to {}.", dir);
+        Ok(())
+    }
+
+    pub fn exporting_surface(&self) -> bool {
+        self.surface_exporter.is_running()
+    }
+
+    /// Stops exporting the surface; meshes already queued are still written.
+    pub fn stop_surface_export(&mut self) {
+        self.surface_exporter.stop();
+    }
+
+    /// Starts streaming the particle positions at the end of every stepped frame to `path`, quantized
+    /// to the domain and its margin; see `Recorder`.
+    pub fn start_recording(&mut self, path: &str) -> Result<()> {
+        self.stop_recording();
+        let margin = self.sim.domain_margin + self.sim.container_velocity.w;
+        self.recorder = Some(Recorder::create(path, self.sim.domain_min.xyz().add_scalar(-margin),
+            self.sim.domain_max.xyz().add_scalar(margin), REPLAY_KEYFRAME_INTERVAL)?);
+        self.data.export_enabled = true;
+        info!("Recording particles to {}.", path);
+        Ok(())
+    }
+
+    pub fn recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Stops recording and completes the file's header. Frames still in flight are left out.
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            let frames = recorder.frames();
+            match recorder.finish() {
+                Ok(()) => info!("Recorded {} frames.", frames),
+                Err(e) => error!("Failed to finish the recording: {}", e),
+            }
+        }
+        self.data.export_enabled = self.exporter.is_running();
+    }
+
+    /// Starts writing a CSV row of statistics to `path` for every stepped frame; see `StatsWriter`.
+    pub fn start_stats(&mut self, path: &str) -> Result<()> {
+        self.stop_stats();
+        self.stats = Some(StatsWriter::create(path)?);
+        info!("Writing statistics to {}.", path);
+        Ok(())
+    }
+
+    pub fn writing_stats(&self) -> bool {
+        self.stats.is_some()
+    }
+
+    /// Stops writing statistics and closes the file. Frames still in flight are left out.
+    pub fn stop_stats(&mut self) {
+        if let Some(stats) = self.stats.take() {
+            let rows = stats.rows();
+            match stats.finish() {
+                Ok(()) => info!("Wrote statistics for {} frames.", rows),
+                Err(e) => error!("Failed to finish the statistics: {}", e),
+            }
+        }
+    }
+
+    /// Times the next `steps` stepped frames after `warmup` more, which should each take a single solver
+    /// step for the timings to be per step; see `bench_report`.
+    pub fn start_bench(&mut self, warmup: u32, steps: u32) {
+        self.bench = Some(Benchmark::new(warmup, steps, self.sim.particle_count));
+    }
+
+    /// What the benchmark measured, once it has seen all its frames complete.
+    pub fn bench_report(&self) -> Option<BenchReport> {
+        self.bench.as_ref().and_then(Benchmark::report)
+    }
+
+    /// Presents frames as soon as they are drawn, tearing, rather than waiting for the vertical blank.
+    pub unsafe fn set_uncapped_present(&mut self, window: &Window, uncapped: bool) -> Result<()> {
+        self.data.uncapped_present = uncapped;
+        self.recreate_swapchain(window)
+    }
+
+    /// Writes the statistics row of the step this frame slot last ran, whose fence just signaled, with
+    /// the live count, diagnostics and timings read back since. A failed write stops the statistics.
+    unsafe fn write_stats(&mut self) -> Result<()> {
+        let Some(mut row) = self.stats_pending[self.frame].take() else { return Ok(()) };
+        let Some(stats) = &mut self.stats else { return Ok(()) };
+        row.particles = read_live_count(&self.device, &self.data, self.frame)?;
+        row.diagnostics = self.diagnostics;
+        row.timings = self.timings;
+        if let Err(e) = stats.write(&row) {
+            error!("Failed to write statistics, stopping: {}", e);
+            self.stop_stats();
+        }
+        Ok(())
+    }
+
+    /// Plays the recording at `path` back in place of the solver, from its first frame at one recorded
+    /// frame per rendered frame. Pausing holds the frame; `end` says what happens past the last one.
+    pub unsafe fn start_replay(&mut self, path: &str, end: ReplayEnd) -> Result<()> {
+        let replay = Replay::open(path)?;
+        info!("Replaying {} frames of up to {} particles, {:.5}s apart, from {}.",
+            replay.frames(), replay.particle_count, replay.dt, path);
+        self.playback = Some(Playback::new(replay, end));
+        Ok(())
+    }
+
+    /// Stops the replay and puts the particles back to their spawn state.
+    pub unsafe fn stop_replay(&mut self) -> Result<()> {
+        if self.playback.take().is_some() {
+            self.reset()?;
+        }
+        Ok(())
+    }
+
+    /// The frame the replay shows and how many there are, while replaying.
+    pub fn replay_frame(&self) -> Option<(u32, u32)> {
+        self.playback.as_ref().map(|p| (p.frame(), p.replay.frames()))
+    }
+
+    /// Jumps the replay to `frame`, clamped to the recording.
+    pub fn seek_replay(&mut self, frame: u32) {
+        if let Some(playback) = &mut self.playback {
+            playback.seek(frame);
+        }
+    }
+
+    /// Sets how many recorded frames the replay moves per rendered frame; negative plays backwards.
+    pub fn set_replay_rate(&mut self, rate: f64) {
+        if let Some(playback) = &mut self.playback {
+            playback.rate = rate;
+        }
+    }
+
+    pub fn replay_rate(&self) -> Option<f64> {
+        self.playback.as_ref().map(|p| p.rate)
+    }
+
+    /// Uploads the replay's next frame into the particle buffer, if it moved on.
+    unsafe fn advance_replay(&mut self) -> Result<()> {
+        let Some(playback) = &mut self.playback else { return Ok(()) };
+        let Some(frame) = playback.advance(self.paused) else { return Ok(()) };
+        let (positions, time) = playback.replay.frame(frame)?;
+        let mass = self.sim.phases[0].z;
+        let particles = positions.into_iter().map(|pos| Particle::new(pos, mass)).collect::<Vec<_>>();
+        self.device.device_wait_idle()?;
+        self.fit_particle_buffers(&particles)?;
+        reset_particles(&self.instance, &self.device, &mut self.data, &particles)?;
+        self.sim.particle_count = particles.len() as u32;
+        self.sim_time = time as f64;
+        self.surface_stale = true;
+        Ok(())
+    }
+
+    /// Grows the particle buffers to fit `particles` if they are too small, starting them over with those.
+    /// Nothing may be in flight.
+    unsafe fn fit_particle_buffers(&mut self, particles: &[Particle]) -> Result<()> {
+        if particles.len() > self.data.particle_capacity as usize {
+            destroy_particle_buffers(&self.device, &self.data);
+            create_particle_buffers(&self.instance, &self.device, &mut self.data, particles)?;
+            write_compute_descriptor_sets(&self.device, &self.data);
+            self.fit_depth_sort()?;
+        }
+        Ok(())
+    }
+
+    /// Simulated seconds since the start of the run, the last reset or the loaded checkpoint.
+    pub fn sim_time(&self) -> f64 {
+        self.sim_time
+    }
+
+    /// The live particles in `range`, clipped to the live count, as the latest submitted step leaves
+    /// them. Waits for the GPU to go idle first, so the values come from a finished step whether the
+    /// solver is running or paused. Every step sorts the particles by grid cell, so an index does not
+    /// name the same particle from one step to the next.
+    pub unsafe fn read_particles(&self, range: Range<u32>) -> Result<Vec<Particle>> {
+        self.device.device_wait_idle()?;
+        read_particle_range(&self.instance, &self.device, &self.data, range)
+    }
+
+    /// Particle `index` as `read_particles` reads it, or `None` past the live count.
+    pub unsafe fn read_particle(&self, index: u32) -> Result<Option<Particle>> {
+        Ok(self.read_particles(index..index.saturating_add(1))?.pop())
+    }
+
+    /// Logs the particle with id `id` after every solver step from now on, or stops with `None`. Each
+    /// step copies it out wherever the sort put it, and the log follows a frame in flight behind.
+    pub fn watch_particle(&mut self, id: Option<u32>) {
+        self.watched = id;
+        self.data.watched_id = id;
+    }
+
+    pub fn watched_particle(&self) -> Option<u32> {
+        self.watched
+    }
+
+    /// Selects the particle drawn nearest the camera within `PICK_RADIUS` pixels of window pixel `(x, y)`,
+    /// to follow, mark and log every frame, and returns its id; a click on no particle keeps the current
+    /// selection. Waits for the GPU to go idle first.
+    pub unsafe fn select_particle_at(&mut self, x: f32, y: f32) -> Result<Option<u32>> {
+        self.device.device_wait_idle()?;
+        let (viewport, camera) = self.view_at(x);
+        let view_proj = to_window(&viewport, self.data.swapchain_extent) * projection(viewport_extent(&viewport))
+            * camera.get_view_matrix();
+        let Some(index) = pick_particle(&self.instance, &self.device, &self.data, &view_proj, glm::vec2(x, y), PICK_RADIUS)? else {
+            info!("No particle under the cursor.");
+            return Ok(None);
+        };
+        let Some(particle) = self.read_particle(index)? else { return Ok(None) };
+        self.selected = Some(particle.id);
+        self.data.selected_id = Some(particle.id);
+        info!("Selected particle #{}.", particle.id);
+        self.show_selection(&particle, self.sim_time);
+        Ok(self.selected)
+    }
+
+    pub fn deselect_particle(&mut self) {
+        self.selected = None;
+        self.data.selected_id = None;
+    }
+
+    /// Highlights the selected particle's neighbors with `highlight`'s settings, or stops with `None`. They
+    /// are marked from the next frame on, whenever a particle is selected.
+    pub unsafe fn set_neighbor_highlight(&mut self, highlight: Option<NeighborHighlight>) -> Result<()> {
+        if highlight.is_none() && self.data.neighbor_highlight.is_some() {
+            // the marks of the last frames would stay with the pass no longer clearing them
+            self.device.device_wait_idle()?;
+            clear_highlights(&self.device, &self.data)?;
+        }
+        self.data.neighbor_highlight = highlight;
+        Ok(())
+    }
+
+    pub fn neighbor_highlight(&self) -> Option<NeighborHighlight> {
+        self.data.neighbor_highlight
+    }
+
+    /// Shows or hides the wireframe sphere at the smoothing radius around the selected particle.
+    pub fn set_smoothing_sphere(&mut self, shown: bool) {
+        self.data.smoothing_sphere = shown;
+    }
+
+    pub fn smoothing_sphere(&self) -> bool {
+        self.data.smoothing_sphere
+    }
+
+    /// Id of the particle picked with the mouse, if any.
+    pub fn selected_particle(&self) -> Option<u32> {
+        self.selected
+    }
+
+    /// Takes in the selected particle as the step of the frame slot just finished left it at `time`:
+    /// logs its state and moves the markers to it, or drops the selection once it is gone. A selection
+    /// changed since that step was recorded is left alone.
+    unsafe fn follow_selection(&mut self, id: u32, time: f64) {
+        if self.selected != Some(id) {
+            return;
+        }
+        match read_selection(&self.data, self.frame, SELECTED_SLOT) {
+            Some(particle) => self.show_selection(&particle, time),
+            None => {
+                info!("Particle #{} at t = {:.4}s: not live any more.", id, time);
+                self.deselect_particle();
+            }
+        }
+    }
+
+    /// Logs the watched particle as the step of the frame slot just finished left it at `time`, and stops
+    /// watching it once it is gone. A watch changed since that step was recorded is left alone.
+    unsafe fn log_watched(&mut self, id: u32, time: f64) {
+        if self.watched != Some(id) {
+            return;
+        }
+        match read_selection(&self.data, self.frame, WATCHED_SLOT) {
+            Some(particle) => info!("Watched particle #{} at t = {:.4}s: {}", id, time, particle),
+            None => {
+                info!("Watched particle #{} at t = {:.4}s: not live any more.", id, time);
+                self.watch_particle(None);
+            }
+        }
+    }
+
+    /// Logs the selected particle's state at `time` and moves the markers to it.
+    fn show_selection(&mut self, particle: &Particle, time: f64) {
+        info!("Particle #{} at t = {:.4}s: {}", particle.id, time, particle);
+        self.data.selection_marker.transform = glm::inverse(&scene_model()) * glm::translation(&particle.pos);
+        self.data.smoothing_sphere_marker.transform = self.data.selection_marker.transform
+            * glm::scaling(&glm::vec3(self.sim.h, self.sim.h, self.sim.h));
+    }
+
+    /// Writes the live particles, the solver parameters and the simulated time to `path`.
+    pub unsafe fn save_checkpoint(&mut self, path: &str) -> Result<()> {
+        self.device.device_wait_idle()?;
+        let particles = read_particles(&self.instance, &self.device, &self.data)?;
+        let checkpoint = Checkpoint { params: self.sim, kernel: self.data.kernel_kind, time: self.sim_time, particles };
+        checkpoint.save(path)?;
+        info!("Saved {} particles at t = {:.4}s to {}.", checkpoint.particles.len(), self.sim_time, path);
+        Ok(())
+    }
+
+    /// Restores a checkpoint written by `save_checkpoint`, growing the particle buffers if it holds more
+    /// particles than they fit. Obstacles, bodies, emitters and sinks stay as they are in the scene.
+    pub unsafe fn load_checkpoint(&mut self, path: &str) -> Result<()> {
+        let checkpoint = Checkpoint::load(path)?;
+        self.device.device_wait_idle()?;
+        if checkpoint.particles.len() > self.data.particle_capacity as usize {
+            destroy_particle_buffers(&self.device, &self.data);
+            create_particle_buffers(&self.instance, &self.device, &mut self.data, &checkpoint.particles)?;
+            write_compute_descriptor_sets(&self.device, &self.data);
+            self.fit_depth_sort()?;
+        } else {
+            reset_particles(&self.instance, &self.device, &mut self.data, &checkpoint.particles)?;
+        }
+        // the scene-dependent counts belong to what is loaded now, not to the saved run
+        let params = checkpoint.params;
+        self.sim = SimParams { sdf_count: self.sim.sdf_count, boundary_count: self.sim.boundary_count,
+            body_count: self.sim.body_count, particle_count: checkpoint.particles.len() as u32, ..params };
+        self.resize_grid()?;
+        if checkpoint.kernel != self.data.kernel_kind {
+            self.set_kernel(checkpoint.kernel)?;
+        }
+        self.added_in_flight = [0; MAX_FRAMES_IN_FLIGHT];
+        self.time_step = TimeStep::new(CFL_SAFETY_FACTOR, MIN_TIMESTEP, MAX_TIMESTEP);
+        self.sim_time = checkpoint.time;
+        self.pending_steps = 0;
+        self.export_pending = [None; MAX_FRAMES_IN_FLIGHT];
+        self.selection_pending = [None; MAX_FRAMES_IN_FLIGHT];
+        self.watch_pending = [None; MAX_FRAMES_IN_FLIGHT];
+        self.surface_stale = true;
+        // the loaded particles are numbered afresh
+        self.deselect_particle();
+        self.watch_particle(None);
+        info!("Loaded {} particles at t = {:.4}s from {}.", checkpoint.particles.len(), self.sim_time, path);
+        Ok(())
+    }
+
+    pub fn resized(&mut self, newval: bool) {
+        self.resized = newval;
+    }
+
+    /// Turns the camera of the view under the window pixel column `x` as the mouse moved.
+    pub fn handle_mouse(&mut self, x: f32, x_diff: f32, y_diff: f32) -> Result<()> {
+        if self.turntable.is_some() {
+            return Ok(());
+        }
+        let right = self.data.split_screen.is_some_and(|split| split.side_at(self.data.swapchain_extent, x) == 1);
+        let camera = if right { &mut self.split_camera } else { &mut self.camera };
+        camera.handle_mouse(x_diff, y_diff)?;
+        Ok(())
+    }
+
+    /// Orbits the cameras `degrees_per_frame` of yaw every rendered frame, stepped or replayed, for
+    /// `total_frames` frames, with dragging and the gizmo turning neither; see `Turntable`. Replaces a
+    /// turntable already running, keeping the time mode it is to restore.
+    pub fn start_turntable(&mut self, degrees_per_frame: f32, total_frames: u32) {
+        let restore = self.turntable.and_then(|turntable| turntable.restore);
+        self.turntable = Some(Turntable { degrees_per_frame, frames_left: total_frames, restore });
+        self.turntable_finished = false;
+        info!("Turntable of {} frames at {} degrees per frame.", total_frames, degrees_per_frame);
+    }
+
+    /// Steps the solver in `mode` for as long as the running turntable lasts, then goes back to the time
+    /// mode from before; does nothing without a turntable.
+    pub fn lock_turntable_steps(&mut self, mode: TimeMode) {
+        let previous = self.time_mode;
+        let Some(turntable) = &mut self.turntable else { return };
+        turntable.restore.get_or_insert(previous);
+        self.set_time_mode(mode);
+    }
+
+    /// Stops the running turntable where the cameras are, as if it had run out.
+    pub fn stop_turntable(&mut self) {
+        if let Some(turntable) = &mut self.turntable {
+            turntable.frames_left = 0;
+        }
+    }
+
+    pub fn turntable(&self) -> Option<Turntable> {
+        self.turntable
+    }
+
+    /// Whether a turntable ran out since the last call, for a capture to stop with it.
+    pub fn take_turntable_finished(&mut self) -> bool {
+        std::mem::take(&mut self.turntable_finished)
+    }
+
+    /// Turns the cameras for this frame, ending the turntable once it has no frames left.
+    fn advance_turntable(&mut self) -> Result<()> {
+        let Some(turntable) = &mut self.turntable else { return Ok(()) };
+        if turntable.frames_left == 0 {
+            let restore = turntable.restore;
+            self.turntable = None;
+            self.turntable_finished = true;
+            if let Some(mode) = restore {
+                self.set_time_mode(mode);
+            }
+            info!("Turntable done.");
+            return Ok(());
+        }
+        turntable.frames_left -= 1;
+        let degrees = turntable.degrees_per_frame;
+        self.camera.orbit(degrees)?;
+        self.split_camera.orbit(degrees)
+    }
+
+    /// Multisamples the main pass with `samples` per pixel, 1 for none, or as many as the device supports
+    /// below that; its render pass, pipelines and targets are rebuilt before the next frame. The
+    /// screen-space fluid and the debug view read the scene's depth back single-sampled, so the main pass
+    /// is not multisampled while either is drawn.
+    pub fn set_msaa(&mut self, samples: u32) {
+        self.msaa = samples.max(1);
+        self.sync_msaa();
+    }
+
+    /// Samples per pixel asked of the main pass.
+    pub fn msaa_request(&self) -> u32 {
+        self.msaa
+    }
+
+    /// Samples per pixel the main pass draws with, from the next frame on.
+    pub fn msaa(&self) -> u32 {
+        unsafe { self.msaa_samples() }.bits()
+    }
+
+    /// The sample count `msaa` comes to on this device with what is drawn now.
+    unsafe fn msaa_samples(&self) -> vk::SampleCountFlags {
+        let screen_space = self.data.fluid_rendering == FluidRendering::ScreenSpace && self.data.split_screen.is_none();
+        if screen_space || self.data.debug_view.is_some() {
+            return vk::SampleCountFlags::_1;
+        }
+        msaa_sample_count(&self.instance, &self.data, self.msaa)
+    }
+
+    /// Rebuilds the swapchain before the next frame if the main pass is to draw with another sample count.
+    fn sync_msaa(&mut self) {
+        if unsafe { self.msaa_samples() } != self.data.msaa_samples {
+            self.resized = true;
+        }
+    }
+
+    /// The viewport of the view under the window pixel column `x`, and the camera it is seen through: the
+    /// whole window's unless it is split.
+    fn view_at(&self, x: f32) -> (vk::Viewport, &Camera) {
+        let extent = self.data.swapchain_extent;
+        match &self.data.split_screen {
+            None => (full_viewport(extent), &self.camera),
+            Some(split) => {
+                let side = split.side_at(extent, x);
+                (split.viewports(extent)[side], if side == 1 { &self.split_camera } else { &self.camera })
+            }
+        }
+    }
+
+    /// Pushes the particles near the ray through window pixel `(x, y)` for the next frame's steps, in
+    /// the direction the cursor just moved `(x_diff, y_diff)` pixels in, as seen at the domain center.
+    pub fn drag_particles(&mut self, x: f32, y: f32, x_diff: f32, y_diff: f32) {
+        let (viewport, camera) = self.view_at(x);
+        let extent = viewport_extent(&viewport);
+        if extent.width == 0 || extent.height == 0 {
+            return;
+        }
+        let x = x - viewport.x;
+        let (origin, direction) = camera.cursor_ray(extent, x, y);
+        let (last_origin, last_direction) = camera.cursor_ray(extent, x - x_diff, y - y_diff);
+        let center = (self.sim.domain_min.xyz() + self.sim.domain_max.xyz()) / 2.0;
+        let depth = glm::dot(&(center - origin), &direction);
+        let moved = (origin + depth * direction) - (last_origin + depth * last_direction);
+        if glm::length(&moved) < 1e-6 {
+            return;
+        }
+        self.data.mouse_force = Some(MouseForce { origin, direction, radius: self.mouse_force_radius,
+            acceleration: glm::normalize(&moved) * self.mouse_force_strength });
+    }
+
+    /// Sets the acceleration, in m/s^2, the mouse force tool gives the particles on the cursor ray.
+    pub fn set_mouse_force_strength(&mut self, strength: f32) {
+        self.mouse_force_strength = strength.max(0.0);
+    }
+
+    pub fn mouse_force_strength(&self) -> f32 {
+        self.mouse_force_strength
+    }
+
+    /// Sets how far from the cursor ray, in meters, the mouse force tool reaches.
+    pub fn set_mouse_force_radius(&mut self, radius: f32) {
+        self.mouse_force_radius = radius.max(0.0);
+    }
+
+    pub fn mouse_force_radius(&self) -> f32 {
+        self.mouse_force_radius
+    }
+
+    /// Draws the fluid as particles, as the surface reconstructed from them, which is rebuilt on the CPU
+    /// every frame the particles change and so slows the app down, as a surface smoothed on screen
+    /// from the particles' depth, which stays on the GPU, or as the surface raymarched through the
+    /// reconstruction's volume, which skips marching cubes but still splats every frame.
+    pub fn set_fluid_rendering(&mut self, rendering: FluidRendering) {
+        self.data.fluid_rendering = rendering;
+        self.surface_stale = true;
+        self.sync_msaa();
+    }
+
+    pub fn fluid_rendering(&self) -> FluidRendering {
+        self.data.fluid_rendering
+    }
+
+    /// Whether either view draws the fluid as `rendering`.
+    fn drawn_as(&self, rendering: FluidRendering) -> bool {
+        self.data.fluid_rendering == rendering || self.data.split_screen.is_some_and(|split| split.right == rendering)
+    }
+
+    /// Splits the window into `split`'s two views, or draws one across it with `None`. The right view's
+    /// camera starts where the left one's is when the window is split.
+    pub fn set_split_screen(&mut self, split: Option<SplitScreen>) {
+        if self.data.split_screen.is_none() {
+            self.split_camera = self.camera;
+        }
+        self.data.split_screen = split;
+        self.surface_stale = true;
+        self.sync_msaa();
+    }
+
+    pub fn split_screen(&self) -> Option<SplitScreen> {
+        self.data.split_screen
+    }
+
+    /// Sets how the screen-space surface is smoothed, at least one world-space millimeter of filter.
+    pub fn set_screen_space(&mut self, params: ScreenSpaceFluid) {
+        self.data.screen_space = ScreenSpaceFluid { filter_radius: params.filter_radius.max(1e-3), ..params };
+    }
+
+    pub fn screen_space(&self) -> ScreenSpaceFluid {
+        self.data.screen_space
+    }
+
+    /// Sets how finely the raymarched surface is sampled, the steps within `RAYMARCH_STEP_RANGE`.
+    pub fn set_raymarching(&mut self, params: Raymarching) {
+        let [min, max] = RAYMARCH_STEP_RANGE;
+        self.data.raymarching = Raymarching { steps: params.steps.clamp(min, max), ..params };
+    }
+
+    pub fn raymarching(&self) -> Raymarching {
+        self.data.raymarching
+    }
+
+    /// Sets how many grid nodes the surface reconstruction samples along the longest side of the
+    /// neighbor grid, at least two.
+    pub fn set_surface_resolution(&mut self, resolution: u32) {
+        self.surface_resolution = resolution.max(2);
+        self.surface_stale = true;
+    }
+
+    pub fn surface_resolution(&self) -> u32 {
+        self.surface_resolution
+    }
+
+    /// Sets the volume fraction the reconstructed surface passes through: about 1 inside the fluid and
+    /// 0 outside, so lower values puff the surface out and higher ones pull it in.
+    pub fn set_surface_iso(&mut self, iso: f32) {
+        self.surface_iso = iso;
+        self.surface_stale = true;
+    }
+
+    pub fn surface_iso(&self) -> f32 {
+        self.surface_iso
+    }
+
+    /// Switches the surface reconstruction to anisotropic kernels, or back to round ones with `None`.
+    /// The anisotropic kernels are shaped and splatted on the CPU, which is slower.
+    pub fn set_anisotropy(&mut self, anisotropy: Option<Anisotropy>) {
+        self.anisotropy = anisotropy;
+        self.surface_stale = true;
+    }
+
+    pub fn anisotropy(&self) -> Option<Anisotropy> {
+        self.anisotropy
+    }
+
+    /// Turns the foam and spray on with `foam`'s settings, or off with `None`. Turning it on starts
+    /// from no foam.
+    pub unsafe fn set_foam(&mut self, foam: Option<Foam>) -> Result<()> {
+        if self.data.foam.is_none() && foam.is_some() {
+            self.device.device_wait_idle()?;
+            clear_foam(&self.device, &self.data)?;
+        }
+        self.data.foam = foam;
+        Ok(())
+    }
+
+    pub fn foam(&self) -> Option<Foam> {
+        self.data.foam
+    }
+
+    /// Draws a line along each particle's velocity with `glyphs`' settings, or none with `None`.
+    pub fn set_velocity_glyphs(&mut self, glyphs: Option<VelocityGlyphs>) {
+        self.data.velocity_glyphs = glyphs;
+    }
+
+    pub fn velocity_glyphs(&self) -> Option<VelocityGlyphs> {
+        self.data.velocity_glyphs
+    }
+
+    /// Scales every particle's drawn radius, and the spheres the fluid surface is made of, by `scale`.
+    pub fn set_radius_scale(&mut self, scale: f32) {
+        self.data.radius_scale = scale.max(0.0);
+    }
+
+    pub fn radius_scale(&self) -> f32 {
+        self.data.radius_scale
+    }
+
+    /// Draws the ground plane with `ground`'s settings, or none with `None`.
+    pub fn set_ground(&mut self, ground: Option<GroundPlane>) {
+        self.data.ground = ground;
+    }
+
+    pub fn ground(&self) -> Option<GroundPlane> {
+        self.data.ground
+    }
+
+    /// Shows `view`'s offscreen image in the window's corner, or none with `None`. Images the frame does
+    /// not draw, as the fluid is drawn, are not shown.
+    pub fn set_debug_view(&mut self, view: Option<DebugView>) {
+        self.data.debug_view = view;
+        self.sync_msaa();
+    }
+
+    pub fn debug_view(&self) -> Option<DebugView> {
+        self.data.debug_view
+    }
+
+    /// Draws lines along the normals of the objects and the fluid surface with `lines`' settings, or none
+    /// with `None`. They are rebuilt from the next frame on, and whenever the meshes change.
+    pub fn set_normal_lines(&mut self, lines: Option<NormalLines>) {
+        self.data.normal_lines = lines;
+        self.normals_stale = true;
+    }
+
+    pub fn normal_lines(&self) -> Option<NormalLines> {
+        self.data.normal_lines
+    }
+
+    /// Draws the objects' triangle edges with `wireframe`'s settings instead of filling them, or fills
+    /// them again with `None`. Fails, leaving them as they are, where the device draws no polygons as
+    /// lines.
+    pub fn set_mesh_wireframe(&mut self, wireframe: Option<MeshWireframe>) -> Result<()> {
+        if wireframe.is_some() && !self.data.fill_mode_non_solid {
+            return Err(anyhow!("The device has no fillModeNonSolid to draw the meshes in wireframe."));
+        }
+        self.data.mesh_wireframe = wireframe;
+        Ok(())
+    }
+
+    pub fn mesh_wireframe(&self) -> Option<MeshWireframe> {
+        self.data.mesh_wireframe
+    }
+
+    /// Draws the axis gizmo with `gizmo`'s settings, or none with `None`.
+    pub fn set_gizmo(&mut self, gizmo: Option<AxisGizmo>) {
+        self.data.gizmo = gizmo;
+    }
+
+    pub fn gizmo(&self) -> Option<AxisGizmo> {
+        self.data.gizmo
+    }
+
+    /// Turns the camera to look back along the gizmo's axis whose tip is at the window pixel `(x, y)`;
+    /// whether there was one.
+    pub fn snap_to_gizmo_axis(&mut self, x: f32, y: f32) -> Result<bool> {
+        let Some(gizmo) = &self.data.gizmo else { return Ok(false) };
+        if self.turntable.is_some() {
+            return Ok(false);
+        }
+        let Some(axis) = gizmo.axis_at(self.data.swapchain_extent, &self.camera.get_view_matrix(), x, y) else {
+            return Ok(false);
+        };
+        self.camera.view_along(axis)?;
+        Ok(true)
+    }
+
+    /// Traces particles with `trails`' settings, or stops with `None`. The trail buffer is made the first
+    /// time, and anew when the length or stride change; turning trails on starts them all afresh.
+    pub unsafe fn set_trails(&mut self, trails: Option<Trails>) -> Result<()> {
+        if let Some(trails) = &trails {
+            self.device.device_wait_idle()?;
+            create_trail_buffer(&self.instance, &self.device, &mut self.data, trails)?;
+            if self.data.trails.is_none() {
+                clear_trails(&self.device, &mut self.data)?;
+            }
+        }
+        self.data.trails = trails;
+        self.frames_since_trail = 0;
+        Ok(())
+    }
+
+    pub fn trails(&self) -> Option<Trails> {
+        self.data.trails
+    }
+
+    /// Shows the neighbor grid's occupied cells with `occupancy`'s settings, or stops with `None`. The
+    /// occupancy buffer is made the first time, and anew when the cap changes; the cells show from the
+    /// next step on.
+    pub unsafe fn set_grid_occupancy(&mut self, occupancy: Option<GridOccupancy>) -> Result<()> {
+        if let Some(occupancy) = &occupancy {
+            self.device.device_wait_idle()?;
+            create_occupancy_buffer(&self.instance, &self.device, &mut self.data, occupancy)?;
+        }
+        self.data.grid_occupancy = occupancy;
+        Ok(())
+    }
+
+    pub fn grid_occupancy(&self) -> Option<GridOccupancy> {
+        self.data.grid_occupancy
+    }
+
+    /// Draws the particles translucent with `translucency`'s settings, sorted back to front every frame,
+    /// or opaque and unsorted with `None`. The sort's buffers are made the first time; the particles are
+    /// drawn opaque until then.
+    pub unsafe fn set_translucency(&mut self, translucency: Option<Translucency>) -> Result<()> {
+        self.data.translucency = translucency;
+        self.fit_depth_sort()
+    }
+
+    pub fn translucency(&self) -> Option<Translucency> {
+        self.data.translucency
+    }
+
+    /// Makes the depth sort's and the cull's buffers fit the particle buffers, if the particles are
+    /// translucent, or culled or drawn in less detail far away.
+    unsafe fn fit_depth_sort(&mut self) -> Result<()> {
+        if self.data.translucency.is_some() {
+            self.device.device_wait_idle()?;
+            create_depth_sort_buffers(&self.instance, &self.device, &mut self.data)?;
+        }
+        if self.data.frustum_culling.is_some() || self.data.particle_lod.is_some() {
+            self.device.device_wait_idle()?;
+            create_cull_buffer(&self.instance, &self.device, &mut self.data)?;
+        }
+        Ok(())
+    }
+
+    /// Draws only the opaque particles the camera sees, with `culling`'s settings, culling them every
+    /// frame, or all of them with `None`. The cull buffer is made the first time; every particle is drawn
+    /// until then.
+    pub unsafe fn set_frustum_culling(&mut self, culling: Option<FrustumCulling>) -> Result<()> {
+        self.data.frustum_culling = culling;
+        self.fit_depth_sort()
+    }
+
+    pub fn frustum_culling(&self) -> Option<FrustumCulling> {
+        self.data.frustum_culling
+    }
+
+    /// Culls and draws the objects' filled meshes on the GPU, or records each draw of them on the CPU.
+    /// Where the device draws no batches of indirect draws, they stay recorded on the CPU.
+    pub fn set_indirect_draws(&mut self, indirect: bool) {
+        if indirect && !self.data.multi_draw_indirect {
+            warn!("The device draws no batches of indirect draws from any instance; the objects' draws stay recorded one by one.");
+        }
+        self.data.indirect_draws = indirect;
+    }
+
+    pub fn indirect_draws(&self) -> bool {
+        self.data.indirect_draws
+    }
+
+    /// Whether the indirect draws are counted on the GPU, rather than recorded culled or not.
+    pub fn draw_indirect_count(&self) -> bool {
+        self.data.draw_indirect_count
+    }
+
+    /// Records the main pass's draws into secondary command buffers on every thread, each object's
+    /// kept until what its commands bind changes, or inline every frame.
+    pub fn set_parallel_recording(&mut self, parallel: bool) {
+        self.data.parallel_recording = parallel;
+    }
+
+    pub fn parallel_recording(&self) -> bool {
+        self.data.parallel_recording
+    }
+
+    /// Watches the shaders, rebuilding what each goes into once it is saved and compiles.
+    pub fn set_shader_hot_reload(&mut self, on: bool) {
+        if on != self.shader_watcher.is_some() {
+            self.shader_watcher = on.then(|| ShaderWatcher::start(vec![self.data.vshader_path.clone(),
+                self.data.fshader_path.clone()]));
+        }
+    }
+
+    pub fn shader_hot_reload(&self) -> bool {
+        self.shader_watcher.is_some()
+    }
+
+    /// Rebuilds what the shaders the watcher saw change go into: the mesh pipelines alone for the mesh
+    /// shaders, the solver's passes for the compute shaders, and every pipeline of the swapchain for the
+    /// rest. The scan's and the tuning's are only built at startup.
+    unsafe fn reload_shaders(&mut self, window: &Window) -> Result<()> {
+        let Some(watcher) = &self.shader_watcher else {
+            return Ok(());
+        };
+        let changed = watcher.changed();
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let mesh = [self.data.vshader_path.as_str(), self.data.fshader_path.as_str(), UNLIT_FRAGMENT_SHADER];
+        let startup = [SCAN_BLOCKS_SHADER, SCAN_BLOCK_SUMS_SHADER, ADD_BLOCK_OFFSETS_SHADER, TUNE_SHADER];
+        let (mut meshes, mut compute, mut swapchain) = (false, false, false);
+        for path in &changed {
+            match path.as_str() {
+                path if mesh.contains(&path) => meshes = true,
+                path if startup.contains(&path) => warn!("{} is only built at startup; restart to use it.", path),
+                MESH_CULL_SHADER => swapchain = true,
+                path if path.ends_with(".comp") => compute = true,
+                _ => swapchain = true,
+            }
+        }
+        self.device.device_wait_idle()?;
+        if compute {
+            destroy_compute_pipelines(&self.device, &self.data);
+            create_compute_pipelines(&self.device, &mut self.data)?;
+        }
+        if swapchain {
+            self.rebuild_swapchain(window, true)?;
+        } else if meshes {
+            if let Err(e) = reload_mesh_pipelines(&self.device, &mut self.data) {
+                error!("{}", e);
+                return Ok(());
+            }
+            invalidate_secondaries(&mut self.data);
+        }
+        info!("Reloaded {}.", changed.join(", "));
+        Ok(())
+    }
+
+    /// Times recording the first swapchain image's commands with `objects` cubes more in the domain,
+    /// each drawn on its own, over `frames` recordings: inline, in secondary command buffers all recorded
+    /// anew, and in secondaries with the objects' kept. The cubes are taken away after.
+    pub unsafe fn time_recording(&mut self, objects: usize, frames: u32) -> Result<RecordingTimes> {
+        self.device.device_wait_idle()?;
+        let (kept, parallel, indirect) = (self.data.objects.len(), self.data.parallel_recording, self.data.indirect_draws);
+        let (min, max) = (self.sim.domain_min.xyz(), self.sim.domain_max.xyz());
+        let side = (objects as f32).cbrt().ceil() as usize;
+        let step = (max - min) / side as f32;
+        for k in 0..objects {
+            let cell = glm::vec3((k % side) as f32, (k / side % side) as f32, (k / side / side) as f32);
+            let mut cube = Object::cube(step * 0.25, &self.instance, &self.device, &mut self.data)?;
+            let center = min + step.component_mul(&cell.add_scalar(0.5));
+            cube.transform = glm::inverse(&scene_model()) * glm::translation(&center);
+            self.data.objects.push(cube);
+        }
+        merge_object_geometry(&self.instance, &self.device, &mut self.data)?;
+        self.data.indirect_draws = false;
+        cull_objects(&mut self.data);
+        update_object_blocks(&self.device, &mut self.data, 0)?;
+        update_indirect_draws(&self.device, &mut self.data, 0)?;
+        prepare_mesh_pipelines(&self.device, &mut self.data)?;
+        let mut times = [0.0; 3];
+        for (mode, time) in times.iter_mut().enumerate() {
+            self.data.parallel_recording = mode > 0;
+            let start = Instant::now();
+            for _ in 0..frames {
+                if mode == 1 {
+                    invalidate_secondaries(&mut self.data);
+                }
+                record_secondaries(&self.device, &mut self.data, 0)?;
+                record_command_buffer(&self.device, &self.data, 0)?;
+            }
+            *time = start.elapsed().as_secs_f64() * 1000.0 / frames as f64;
+        }
+        self.data.objects.truncate(kept);
+        merge_object_geometry(&self.instance, &self.device, &mut self.data)?;
+        (self.data.parallel_recording, self.data.indirect_draws) = (parallel, indirect);
+        let [inline, anew, kept] = times;
+        Ok(RecordingTimes { objects, threads: rayon::current_num_threads(), inline, anew, kept })
+    }
+
+    /// Milliseconds remaking every pipeline took, the swapchain's with what is sized to it and the
+    /// solver's, through an empty pipeline cache and then through the warm one. The shaders are compiled
+    /// anew both times.
+    pub unsafe fn time_pipeline_creation(&mut self, window: &Window) -> Result<[f64; 2]> {
+        let warm = self.data.pipeline_cache;
+        let mut times = [0.0; 2];
+        for (k, time) in times.iter_mut().enumerate() {
+            let cold = if k == 0 {
+                Some(self.device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)?)
+            } else {
+                None
+            };
+            self.data.pipeline_cache = cold.unwrap_or(warm);
+            let start = Instant::now();
+            self.rebuild_swapchain(window, true)?;
+            destroy_compute_pipelines(&self.device, &self.data);
+            create_compute_pipelines(&self.device, &mut self.data)?;
+            prepare_mesh_pipelines(&self.device, &mut self.data)?;
+            *time = start.elapsed().as_secs_f64() * 1000.0;
+            if let Some(cold) = cold {
+                self.device.destroy_pipeline_cache(cold, None);
+            }
+        }
+        self.data.pipeline_cache = warm;
+        Ok(times)
+    }
+
+    /// Holds the particles' and the objects' cull to the frustum the camera has now, or lets it follow
+    /// the camera again.
+    pub fn set_cull_frozen(&mut self, frozen: bool) {
+        self.cull_frozen = frozen;
+    }
+
+    pub fn cull_frozen(&self) -> bool {
+        self.cull_frozen
+    }
+
+    /// Objects the latest cull left to draw, and the objects there are.
+    pub fn visible_objects(&self) -> (u32, u32) {
+        (self.visible_objects, self.data.objects.len() as u32)
+    }
+
+    /// Smallest and largest point size the device draws the particles at, in pixels; both 1 without
+    /// `largePoints`.
+    pub fn point_size_range(&self) -> [f32; 2] {
+        self.data.point_size_range
+    }
+
+    /// Smallest and largest line width the device draws the glyphs and the gizmo at, in pixels.
+    pub fn line_width_range(&self) -> [f32; 2] {
+        self.data.line_width_range
+    }
+
+    /// Whether the particles are drawn as instanced quads with the sphere shaded over them, the nearest
+    /// being too wide for the device's largest point.
+    pub fn particle_spheres(&self) -> bool {
+        self.data.particle_spheres
+    }
+
+    /// Has the particles drawn as quads while the domain's nearest corner brings them too close for the
+    /// device's largest point, and as points again once it does not; the largest is the one particle.vert
+    /// clamps the points to, out of the same parameters.
+    fn choose_particle_sprites(&mut self) {
+        let [min, max] = self.data.point_size_range;
+        self.sim.point_size = glm::vec4(min, max, 0.0, 0.0);
+        if !self.drawn_as(FluidRendering::Particles) {
+            return;
+        }
+        // nothing is drawn nearer than the near plane
+        let nearest = self.data.depth_sort_range[0].max(NEAR_PLANE);
+        let spheres = point_size_at(&self.data, nearest) > self.sim.point_size.y;
+        if spheres != self.data.particle_spheres {
+            if spheres {
+                info!("Particles this close need points wider than the device's largest, {} px; drawing them as quads.", max);
+            } else {
+                info!("Particles drawn as points again.");
+            }
+        }
+        self.data.particle_spheres = spheres;
+    }
+
+    /// Draws the opaque particles past `lod`'s distance flat and thinned out, or all alike with `None`,
+    /// through the cull pass whether or not they are culled. The cull buffer is made the first time;
+    /// every particle is drawn in full until then.
+    pub unsafe fn set_particle_lod(&mut self, lod: Option<ParticleLod>) -> Result<()> {
+        self.data.particle_lod = lod.map(|lod| ParticleLod { distance: lod.distance.max(0.0),
+            keep: lod.keep.clamp(0.0, 1.0) });
+        self.fit_depth_sort()
+    }
+
+    pub fn particle_lod(&self) -> Option<ParticleLod> {
+        self.data.particle_lod
+    }
+
+    /// Places the slice plane through the field volume, within the field's grid; showing it samples the
+    /// field even when no surface is drawn.
+    pub fn set_density_slice(&mut self, slice: DensitySlice) {
+        if slice.visible && !self.data.density_slice.visible {
+            self.surface_stale = true;
+        }
+        self.data.density_slice = DensitySlice { offset: slice.offset.clamp(0.0, 1.0), ..slice };
+    }
+
+    pub fn density_slice(&self) -> DensitySlice {
+        self.data.density_slice
+    }
+
+    /// Rebuilds the fluid surface from the latest particles if it is drawn or exported and out of date,
+    /// and offers it to the exporter: the splat
+    /// pass samples their volume fraction on a grid, which is read back and run through marching cubes.
+    /// Anisotropic kernels are shaped and splatted on the CPU from the particles read back instead. When
+    /// raymarched or sliced, the volume fraction goes back up as a 3D image instead of through marching
+    /// cubes.
+    unsafe fn update_fluid_surface(&mut self) -> Result<()> {
+        let raymarched = self.drawn_as(FluidRendering::Raymarched);
+        let meshed = self.drawn_as(FluidRendering::Surface) || self.surface_exporter.is_running();
+        // the slice plane samples the same volume the raymarcher does
+        let volume = raymarched || self.data.density_slice.visible;
+        let wanted = volume || meshed;
+        if !wanted || !self.surface_stale {
+            return Ok(());
+        }
+        self.device.device_wait_idle()?;
+        let grid = FieldGrid::covering(&self.sim, self.surface_resolution);
+        let field = match &self.anisotropy {
+            Some(anisotropy) => {
+                let particles = read_particles(&self.instance, &self.device, &self.data)?;
+                let kernels = anisotropic_kernels(&particles, &self.sim, anisotropy);
+                splat_kernels(&kernels, &grid, &Kernel::new(self.data.kernel_kind, self.sim.h))
+            }
+            None => {
+                // the splat reads the neighbor grid through the parameters of the step that sorted the particles
+                self.sim.update(self.frame, &self.data, &self.device)?;
+                splat_field(&self.instance, &self.device, &mut self.data, &grid, self.frame)?
+            }
+        };
+        if volume {
+            upload_field_volume(&self.instance, &self.device, &mut self.data, &grid, &field, self.surface_iso)?;
+        }
+        if !meshed {
+            self.surface_stale = false;
+            return Ok(());
+        }
+        let (vertices, indices) = marching_cubes(&field, &grid, self.surface_iso, self.sim.phase_colors[0].xyz());
+        let old = std::mem::take(&mut self.data.fluid_surface);
+        self.device.destroy_buffer(old.index_buffer, None);
+        self.device.free_memory(old.index_buffer_memory, None);
+        self.device.destroy_buffer(old.vertex_buffer, None);
+        self.device.free_memory(old.vertex_buffer_memory, None);
+        if !indices.is_empty() {
+            let mut surface = Object::from_mesh(vertices, indices, &self.instance, &self.device, &mut self.data)?;
+            surface.transform = glm::inverse(&scene_model());
+            self.data.fluid_surface = surface;
+        }
+        let surface = &self.data.fluid_surface;
+        self.surface_exporter.offer(self.sim_time, || (surface.vertices.clone(), surface.indices.clone()));
+        self.surface_stale = false;
+        self.normals_stale |= self.data.normal_lines.is_some();
+        Ok(())
+    }
+}
+
+/// Creates the images sized to the swapchain the passes draw into and sample, their framebuffers and
+/// the sets reading them. Call after the passes and sets they go with are made.
+unsafe fn create_swapchain_targets(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    create_depth_objects(instance, device, data)?;
+    create_msaa_objects(instance, device, data)?;
+    create_tonemap_targets(instance, device, data)?;
+    create_bloom_targets(instance, device, data)?;
+    create_oit_targets(instance, device, data)?;
+    create_framebuffers(device, data)?;
+    create_ssao_targets(instance, device, data)?;
+    create_fluid_targets(instance, device, data)?;
+    create_debug_targets(device, data)
+}
+
+unsafe fn create_instance(window: &Window, entry: &Entry, data: &mut AppData) -> Result<Instance> {
+    // Application Info
+
+    let application_info = vk::ApplicationInfo::builder()
+        .application_name(b"Vulkan Tutorial (Rust)\0")
+        .application_version(vk::make_version(1, 0, 0))
+        .engine_name(b"No Engine\0")
+        .engine_version(vk::make_version(1, 0, 0))
+        .api_version(vk::make_version(1, 0, 0));
+
+    // Layers
+    let available_layers = entry.enumerate_instance_layer_properties()?.iter().map(|l| l.layer_name).collect::<HashSet<_>>();
+    if VALIDATION_ENABLED && !available_layers.contains(&VALIDATION_LAYER) {
+        return Err(anyhow!("Validation layer requested but not supported."));
+    }
+
+    let layers: Vec<*const i8> = if VALIDATION_ENABLED {
+        vec![VALIDATION_LAYER.as_ptr()]
+    } else {
+        Vec::new()
+    };
+
+    // Extensions
+    let mut extensions = vk_window::get_required_instance_extensions(window).iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+
+    // if cfg!(target_os = "macos") {
+    let names = &[ExtensionName::from_bytes(b"VK_KHR_portability_enumeration"),
+        ExtensionName::from_bytes(b"VK_KHR_get_physical_device_properties2")];
+    names.iter().for_each(|name| extensions.push(name.as_ptr()));
+    // }
+   
+    if VALIDATION_ENABLED {
+        extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION.name.as_ptr());
+    }
+    if sync_validation_enabled() {
+        extensions.push(vk::EXT_VALIDATION_FEATURES_EXTENSION.name.as_ptr());
+    }
+
+    // Create
+    let mut info = vk::InstanceCreateInfo::builder()
+        .flags(InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR)
+        .application_info(&application_info)
+        .enabled_layer_names(&layers)
+        .enabled_extension_names(&extensions);
+        
+
+    let mut debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
+        .message_severity(vk::DebugUtilsMessageSeverityFlagsEXT::all())
+        .message_type(vk::DebugUtilsMessageTypeFlagsEXT::all())
+        .user_callback(Some(debug_callback));
+
+    if VALIDATION_ENABLED {
+        info = info.push_next(&mut debug_info);
+    }
+    let enabled_features = &[vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION];
+    let mut validation_features = vk::ValidationFeaturesEXT::builder().enabled_validation_features(enabled_features);
+    if sync_validation_enabled() {
+        info!("Synchronization validation enabled.");
+        info = info.push_next(&mut validation_features);
     }
     let instance = entry.create_instance(&info, None)?;
 
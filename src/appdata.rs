@@ -1,5 +1,41 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use vulkanalia::prelude::v1_0::*;
+use nalgebra_glm as glm;
 use crate::model::Object;
+use crate::emitter::{Emitter, Inflow, Outflow, Sink};
+use crate::simulation::{BoundaryMode, MouseForce, Particle, ParticleColoring};
+use crate::rigid::{BodyState, RigidBody};
+use crate::kernel::KernelKind;
+use crate::scan::ScanTarget;
+use crate::reconstruct::{FieldGrid, FluidRendering};
+use crate::screen_space::ScreenSpaceFluid;
+use crate::raymarch::Raymarching;
+use crate::foam::Foam;
+use crate::colormap::{Colormap, ColormapTexture};
+use crate::texture::MeshTexture;
+use crate::skybox::SkyboxTexture;
+use crate::shadow::{PointShadows, ShadowMapping};
+use crate::lighting::{Light, ShadingDebug};
+use crate::utils::{MeshPipeline, ObjectBlocks};
+use crate::reflect::DescriptorBinding;
+use crate::glyphs::VelocityGlyphs;
+use crate::trails::Trails;
+use crate::slice::DensitySlice;
+use crate::neighbors::NeighborHighlight;
+use crate::wireframe::{MeshWireframe, NormalLines, WireBox};
+use crate::ground::GroundPlane;
+use crate::gizmo::AxisGizmo;
+use crate::occupancy::GridOccupancy;
+use crate::depth_sort::Translucency;
+use crate::cull::{FrustumCulling, ParticleLod};
+use crate::debug_view::DebugView;
+use crate::tonemap::Tonemapping;
+use crate::bloom::Bloom;
+use crate::ssao::Ssao;
+use crate::split::SplitScreen;
+use crate::secondary::Secondaries;
+use crate::config::MAX_PHASES;
 
 /// The Vulkan handles and associated properties used by our Vulkan app.
 #[derive(Clone, Debug, Default)]
@@ -9,30 +45,617 @@ pub struct AppData {
     pub graphics_queue: vk::Queue,
     pub surface: vk::SurfaceKHR,
     pub present_queue: vk::Queue,
+    pub compute_queue: vk::Queue,
+    /// Graphics and compute families when they differ, for CONCURRENT sharing; empty otherwise.
+    pub shared_queue_families: Vec<u32>,
     pub swapchain_format: vk::Format,
     pub swapchain_extent: vk::Extent2D,
     pub swapchain: vk::SwapchainKHR,
     pub swapchain_images: Vec<vk::Image>,
     pub swapchain_image_views: Vec<vk::ImageView>,
+    /// Whether the swapchain presents without waiting for the vertical blank; see `App::set_uncapped_present`.
+    pub uncapped_present: bool,
     pub render_pass: vk::RenderPass,
+    /// What every pipeline is made through, saved to disk between runs.
+    pub pipeline_cache: vk::PipelineCache,
+    /// Whether `pipeline_cache` started with what a previous run saved.
+    pub pipeline_cache_warm: bool,
     pub descriptor_set_layout: vk::DescriptorSetLayout,
+    /// The bindings of `descriptor_set_layout` and `object_set_layout`, as the shaders declare them.
+    pub scene_bindings: Vec<DescriptorBinding>,
     pub pipeline_layout: vk::PipelineLayout,
-    pub pipeline: vk::Pipeline,
+    /// The pipelines of `pipeline_layout` the meshes are drawn through, made as draws first need them
+    /// and dropped with the swapchain.
+    pub mesh_pipelines: HashMap<MeshPipeline, vk::Pipeline>,
+    /// How the objects are drawn in wireframe, or `None` with them filled.
+    pub mesh_wireframe: Option<MeshWireframe>,
+    /// Whether the device draws polygons as lines, for `wireframe_pipeline`.
+    pub fill_mode_non_solid: bool,
     pub framebuffers: Vec<vk::Framebuffer>,
     pub command_pool: vk::CommandPool,
     pub command_buffers: Vec<vk::CommandBuffer>,
     pub image_available_semaphores: Vec<vk::Semaphore>,
     pub render_finished_semaphores: Vec<vk::Semaphore>,
+    pub compute_finished_semaphores: Vec<vk::Semaphore>,
+    pub graphics_finished_semaphores: Vec<vk::Semaphore>,
     pub in_flight_fences: Vec<vk::Fence>,
     pub images_in_flight: Vec<vk::Fence>,
     pub vshader_path: String,
     pub fshader_path: String,
     pub objects: Vec<Object>,
+    /// Wireframe boxes around the domain and the emitter, sink, inflow and outflow regions, the domain's
+    /// first, and the line list `wire_buffer` holds of their edges.
+    pub wire_boxes: Vec<WireBox>,
+    pub wire_buffer: vk::Buffer,
+    pub wire_buffer_memory: vk::DeviceMemory,
+    /// Places the domain's box where the container motion has the walls.
+    pub domain_transform: glm::Mat4,
+    /// Lines along the normals of the objects' and fluid surface's vertices, if drawn, the line list
+    /// `normal_buffer` holds of them, and the object each range of it belongs to, `None` for the surface.
+    pub normal_lines: Option<NormalLines>,
+    pub normal_buffer: vk::Buffer,
+    pub normal_buffer_memory: vk::DeviceMemory,
+    pub normal_ranges: Vec<(Option<usize>, std::ops::Range<u32>)>,
+    pub wire_pipeline_layout: vk::PipelineLayout,
+    pub wire_pipeline: vk::Pipeline,
+    /// Ground plane under the scene, if one is drawn.
+    pub ground: Option<GroundPlane>,
+    /// Height of the domain's bottom, where the ground plane lies unless it says otherwise.
+    pub domain_floor: f32,
+    pub ground_pipeline_layout: vk::PipelineLayout,
+    /// Whether the skybox is drawn behind the scene, the cubemap bound to every set of `descriptor_sets`,
+    /// and its pipeline.
+    pub skybox: bool,
+    pub skybox_texture: SkyboxTexture,
+    pub skybox_pipeline_layout: vk::PipelineLayout,
+    pub skybox_pipeline: vk::Pipeline,
+    /// The lights the meshes are shaded by, the first `MAX_LIGHTS` of them; see `Light`.
+    pub lights: Vec<Light>,
+    /// What the meshes show in place of their shading, if anything.
+    pub shading_debug: ShadingDebug,
+    /// Whether the opaque meshes and particles lay down their depth before any of them is shaded, so
+    /// each pixel is shaded once.
+    pub depth_prepass: bool,
+    /// Shadows of the light, if drawn, and what they are drawn with: its view-projection, the map
+    /// `shadow_resolution` texels square bound to every set of `descriptor_sets` whether drawn or not,
+    /// and the depth-only pass into it.
+    pub shadows: Option<ShadowMapping>,
+    pub shadow_view_proj: glm::Mat4,
+    pub shadow_resolution: u32,
+    pub shadow_image: vk::Image,
+    pub shadow_image_memory: vk::DeviceMemory,
+    pub shadow_image_view: vk::ImageView,
+    pub shadow_sampler: vk::Sampler,
+    pub shadow_render_pass: vk::RenderPass,
+    pub shadow_framebuffer: vk::Framebuffer,
+    pub shadow_pipeline_layout: vk::PipelineLayout,
+    pub shadow_pipeline: vk::Pipeline,
+    /// Shadows of the light as a point, if drawn, whether the frame redraws their cube map and from where
+    /// the light is then, and the map `point_shadow_resolution` texels square a face, bound to every set
+    /// of `descriptor_sets` whether drawn or not, with a view and framebuffer of each face.
+    pub point_shadows: Option<PointShadows>,
+    pub point_shadow_redraw: bool,
+    pub point_shadow_light: glm::Vec3,
+    pub point_shadow_resolution: u32,
+    pub point_shadow_image: vk::Image,
+    pub point_shadow_image_memory: vk::DeviceMemory,
+    pub point_shadow_image_view: vk::ImageView,
+    pub point_shadow_face_views: Vec<vk::ImageView>,
+    pub point_shadow_framebuffers: Vec<vk::Framebuffer>,
+    pub point_shadow_pipeline_layout: vk::PipelineLayout,
+    pub point_shadow_pipeline: vk::Pipeline,
+    pub ground_pipeline: vk::Pipeline,
+    /// Axis gizmo in the window's corner, if one is drawn.
+    pub gizmo: Option<AxisGizmo>,
+    pub gizmo_pipeline_layout: vk::PipelineLayout,
+    pub gizmo_pipeline: vk::Pipeline,
+    /// Box drawn around the selected particle, where the host last read it back.
+    pub selection_marker: Object,
+    /// Id of the selected particle, which the particle sprites highlight wherever the solver moves it.
+    pub selected_id: Option<u32>,
+    /// Id of the particle whose state is logged after every step, wherever the solver moves it.
+    pub watched_id: Option<u32>,
+    /// Sphere of unit radius drawn around the selected particle at the smoothing radius while
+    /// `smoothing_sphere` is set.
+    pub smoothing_sphere_marker: Object,
+    pub smoothing_sphere: bool,
+    /// Highlighting of the selected particle's neighbors, if on, and per slot of the latest particle
+    /// buffer the RGBA8 color the neighbor pass highlights it in, zero for none.
+    pub neighbor_highlight: Option<NeighborHighlight>,
+    pub highlight_buffer: vk::Buffer,
+    pub highlight_buffer_memory: vk::DeviceMemory,
+    pub neighbor_pipeline_layout: vk::PipelineLayout,
+    pub neighbor_pipeline: vk::Pipeline,
+    /// Fluid surface from the last reconstruction, in the frame the fluid is simulated in.
+    pub fluid_surface: Object,
+    pub fluid_rendering: FluidRendering,
+    pub screen_space: ScreenSpaceFluid,
+    /// The opaque scene, drawn first into an image of the swapchain's format, with `depth_image`, for the
+    /// screen-space fluid to refract.
+    pub scene_color_image: vk::Image,
+    pub scene_color_image_memory: vk::DeviceMemory,
+    pub scene_color_image_view: vk::ImageView,
+    pub scene_render_pass: vk::RenderPass,
+    pub scene_framebuffer: vk::Framebuffer,
+    /// Offscreen targets of the screen-space fluid, sized to the swapchain: the eye-space depth of the
+    /// particle spheres drawn into the first color image and smoothed back and forth between the two,
+    /// and the thickness of fluid the particles add up to. Both are drawn over the scene's depth, so
+    /// that they leave out the fluid the scene hides.
+    pub fluid_depth_images: Vec<vk::Image>,
+    pub fluid_depth_images_memory: Vec<vk::DeviceMemory>,
+    pub fluid_depth_image_views: Vec<vk::ImageView>,
+    pub fluid_thickness_image: vk::Image,
+    pub fluid_thickness_image_memory: vk::DeviceMemory,
+    pub fluid_thickness_image_view: vk::ImageView,
+    pub fluid_thickness_render_pass: vk::RenderPass,
+    pub fluid_depth_render_pass: vk::RenderPass,
+    pub fluid_smooth_render_pass: vk::RenderPass,
+    /// Draws the scene, refracted and absorbed through the fluid, into the HDR image.
+    pub fluid_composite_render_pass: vk::RenderPass,
+    pub fluid_thickness_framebuffer: vk::Framebuffer,
+    pub fluid_depth_framebuffer: vk::Framebuffer,
+    /// Framebuffer `k` draws into `fluid_depth_images[k]`.
+    pub fluid_smooth_framebuffers: Vec<vk::Framebuffer>,
+    pub fluid_composite_framebuffer: vk::Framebuffer,
+    pub fluid_sampler: vk::Sampler,
+    /// The scene's uniform buffer, a depth image, the thickness and the scene's color to sample; set
+    /// `2 i + k` holds swapchain image `i`'s uniform buffer and `fluid_depth_images[k]`.
+    pub fluid_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub fluid_descriptor_pool: vk::DescriptorPool,
+    pub fluid_descriptor_sets: Vec<vk::DescriptorSet>,
+    pub fluid_pipeline_layout: vk::PipelineLayout,
+    pub fluid_thickness_pipeline: vk::Pipeline,
+    pub fluid_depth_pipeline: vk::Pipeline,
+    pub fluid_smooth_pipeline: vk::Pipeline,
+    pub fluid_composite_pipeline: vk::Pipeline,
+    pub raymarching: Raymarching,
+    /// The scene's uniform buffer and `field_volume`.
+    pub raymarch_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub raymarch_descriptor_pool: vk::DescriptorPool,
+    pub raymarch_descriptor_sets: Vec<vk::DescriptorSet>,
+    pub raymarch_sampler: vk::Sampler,
+    pub raymarch_pipeline_layout: vk::PipelineLayout,
+    pub raymarch_pipeline: vk::Pipeline,
+    /// The scene's uniform buffer for each swapchain image, then one each for the split screen's right
+    /// view, and a set on each.
     pub uniform_buffers: Vec<vk::Buffer>,
     pub uniform_buffers_memory: Vec<vk::DeviceMemory>,
     pub descriptor_pool: vk::DescriptorPool,
     pub descriptor_sets: Vec<vk::DescriptorSet>,
+    /// Each swapchain image's buffer of per-draw transforms and materials, the blocks of it handed out
+    /// each frame, and a set on each binding it at a dynamic offset.
+    pub object_set_layout: vk::DescriptorSetLayout,
+    pub object_buffers: Vec<vk::Buffer>,
+    pub object_buffers_memory: Vec<vk::DeviceMemory>,
+    pub object_blocks: ObjectBlocks,
+    pub object_sets: Vec<vk::DescriptorSet>,
+    /// The vertices and indices of every object, in one buffer each once merged; see `Object::base_vertex`.
+    pub object_vertex_buffer: vk::Buffer,
+    pub object_vertex_buffer_memory: vk::DeviceMemory,
+    pub object_index_buffer: vk::Buffer,
+    pub object_index_buffer_memory: vk::DeviceMemory,
     pub depth_image: vk::Image,
     pub depth_image_memory: vk::DeviceMemory,
     pub depth_image_view: vk::ImageView,
+    /// Samples per pixel of the main pass. Past 1, it draws into the multisampled targets and resolves
+    /// the color into the swapchain image, leaving `depth_image` alone.
+    pub msaa_samples: vk::SampleCountFlags,
+    pub msaa_color_image: vk::Image,
+    pub msaa_color_image_memory: vk::DeviceMemory,
+    pub msaa_color_image_view: vk::ImageView,
+    pub msaa_depth_image: vk::Image,
+    pub msaa_depth_image_memory: vk::DeviceMemory,
+    pub msaa_depth_image_view: vk::ImageView,
+    /// Ping-pong particle storage; `particle_buffers[particle_parity]` holds the latest solver state.
+    pub particle_buffers: Vec<vk::Buffer>,
+    pub particle_buffers_memory: Vec<vk::DeviceMemory>,
+    pub particle_parity: usize,
+    pub particle_capacity: u32,
+    /// Persistently mapped copy of each frame slot's step result, filled only while `export_enabled`.
+    pub export_buffer: vk::Buffer,
+    pub export_buffer_memory: vk::DeviceMemory,
+    pub export_staging: Option<*mut Particle>,
+    pub export_enabled: bool,
+    pub emit_staging_buffer: vk::Buffer,
+    pub emit_staging_buffer_memory: vk::DeviceMemory,
+    pub emit_staging: Option<*mut Particle>,
+    pub emitters: Vec<Emitter>,
+    /// Id the next particle uploaded or emitted from the CPU gets.
+    pub next_particle_id: u32,
+    pub sink_buffer: vk::Buffer,
+    pub sink_buffer_memory: vk::DeviceMemory,
+    pub sink_staging: Option<*mut glm::Vec4>,
+    pub sinks: Vec<Sink>,
+    /// Per frame in flight, `MAX_OPENINGS` entries of three vectors each; see `stage_openings`.
+    pub opening_buffer: vk::Buffer,
+    pub opening_buffer_memory: vk::DeviceMemory,
+    pub opening_staging: Option<*mut glm::Vec4>,
+    pub inflows: Vec<Inflow>,
+    pub outflows: Vec<Outflow>,
+    /// Per frame in flight, the mouse force tool's ray and push; see `stage_mouse_force`.
+    pub mouse_force_buffer: vk::Buffer,
+    pub mouse_force_buffer_memory: vk::DeviceMemory,
+    pub mouse_force_staging: Option<*mut glm::Vec4>,
+    /// Push of the mouse force tool for the next frame's steps, cleared once they are recorded.
+    pub mouse_force: Option<MouseForce>,
+    /// Per-particle force density the mouse force pass writes for the force pass to add.
+    pub impulse_buffer: vk::Buffer,
+    pub impulse_buffer_memory: vk::DeviceMemory,
+    pub counter_buffer: vk::Buffer,
+    pub counter_buffer_memory: vk::DeviceMemory,
+    pub live_count_buffer: vk::Buffer,
+    pub live_count_buffer_memory: vk::DeviceMemory,
+    pub cell_ids_buffer: vk::Buffer,
+    pub cell_ids_buffer_memory: vk::DeviceMemory,
+    pub cell_ranks_buffer: vk::Buffer,
+    pub cell_ranks_buffer_memory: vk::DeviceMemory,
+    /// Neighbor grid of the latest step: particles per cell and where each cell's particles start.
+    pub cell_counts_buffer: vk::Buffer,
+    pub cell_counts_buffer_memory: vk::DeviceMemory,
+    pub cell_starts_buffer: vk::Buffer,
+    pub cell_starts_buffer_memory: vk::DeviceMemory,
+    pub grid_cells: u32,
+    pub sdf_info_buffer: vk::Buffer,
+    pub sdf_info_buffer_memory: vk::DeviceMemory,
+    pub sdf_value_buffer: vk::Buffer,
+    pub sdf_value_buffer_memory: vk::DeviceMemory,
+    pub boundary_buffer: vk::Buffer,
+    pub boundary_buffer_memory: vk::DeviceMemory,
+    pub boundary_force_buffer: vk::Buffer,
+    pub boundary_force_buffer_memory: vk::DeviceMemory,
+    pub body_buffer: vk::Buffer,
+    pub body_buffer_memory: vk::DeviceMemory,
+    pub body_staging: Option<*mut BodyState>,
+    pub body_force_buffer: vk::Buffer,
+    pub body_force_buffer_memory: vk::DeviceMemory,
+    pub bodies: Vec<RigidBody>,
+    pub reduce_partials_buffer: vk::Buffer,
+    pub reduce_partials_buffer_memory: vk::DeviceMemory,
+    pub diagnostics_buffer: vk::Buffer,
+    pub diagnostics_buffer_memory: vk::DeviceMemory,
+    /// Per-particle vorticity, written and read by the vorticity confinement passes.
+    pub vorticity_buffer: vk::Buffer,
+    pub vorticity_buffer_memory: vk::DeviceMemory,
+    pub vorticity_pipeline: vk::Pipeline,
+    /// Whether the steps measure each particle's velocity divergence, for the colormap and the diagnostics.
+    pub measure_divergence: bool,
+    pub divergence_pipeline: vk::Pipeline,
+    pub vorticity_force_pipeline: vk::Pipeline,
+    /// Per-particle color field gradient and Shepard sum, for the free-surface density correction.
+    pub surface_buffer: vk::Buffer,
+    pub surface_buffer_memory: vk::DeviceMemory,
+    pub surface_pipeline: vk::Pipeline,
+    /// Per-particle merge partner of the adaptive resolution, picked by the pair pass for the refine pass.
+    pub partner_buffer: vk::Buffer,
+    pub partner_buffer_memory: vk::DeviceMemory,
+    pub refine_pair_pipeline: vk::Pipeline,
+    pub refine_pipeline: vk::Pipeline,
+    /// How the particle renderer colors particles.
+    pub particle_coloring: ParticleColoring,
+    /// Values of the coloring scalar at the ends of the colormap, as drawn this frame.
+    pub color_range: [f32; 2],
+    /// The colormap scalars are drawn through, and every colormap's texture with a set binding it, in
+    /// the order of `Colormap::ALL`.
+    pub colormap: Colormap,
+    pub colormap_textures: Vec<ColormapTexture>,
+    pub colormap_set_layout: vk::DescriptorSetLayout,
+    pub colormap_descriptor_pool: vk::DescriptorPool,
+    pub colormap_descriptor_sets: Vec<vk::DescriptorSet>,
+    /// The texture meshes with texture coordinates are drawn with, bound to every set of `descriptor_sets`.
+    pub mesh_texture: MeshTexture,
+    /// The normal maps the meshes' materials name, with the files they came from, in the slots of binding 5
+    /// of `descriptor_sets`; the slots past them hold `flat_normal_map`. See `normal_map_slot`.
+    pub normal_maps: Vec<(PathBuf, MeshTexture)>,
+    pub flat_normal_map: MeshTexture,
+    /// Whether shaders may pick a sampler out of an array by a per-draw index, which normal maps need.
+    pub sampler_array_indexing: bool,
+    /// Whether a bar through the colormap is drawn in a corner while particles are colored by a scalar.
+    pub legend: bool,
+    pub legend_pipeline_layout: vk::PipelineLayout,
+    pub legend_pipeline: vk::Pipeline,
+    /// `SimParams::phase_colors` as of the frame being drawn.
+    pub phase_colors: [glm::Vec4; MAX_PHASES],
+    /// Each frame slot's density histogram, filled only by steps recorded with `bin_densities`.
+    pub density_histogram_buffer: vk::Buffer,
+    pub density_histogram_buffer_memory: vk::DeviceMemory,
+    pub density_histogram_pipeline: vk::Pipeline,
+    pub bin_densities: bool,
+    pub compute_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub compute_descriptor_pool: vk::DescriptorPool,
+    /// Set `k` reads and updates `particle_buffers[k]` and compacts into the other one.
+    pub compute_descriptor_sets: Vec<vk::DescriptorSet>,
+    pub sim_params_set_layout: vk::DescriptorSetLayout,
+    pub sim_params_buffers: Vec<vk::Buffer>,
+    pub sim_params_buffers_memory: Vec<vk::DeviceMemory>,
+    pub sim_params_sets: Vec<vk::DescriptorSet>,
+    pub compute_pipeline_layout: vk::PipelineLayout,
+    pub density_pipeline: vk::Pipeline,
+    pub force_pipeline: vk::Pipeline,
+    /// Variants of the density and force passes that stage neighbors through shared memory.
+    pub density_tiled_pipeline: vk::Pipeline,
+    pub force_tiled_pipeline: vk::Pipeline,
+    /// Particles per shared-memory tile; 0 when no worthwhile tile fits the device, so tiling is unavailable.
+    pub tile_size: u32,
+    pub tiled: bool,
+    /// World-space radius particles of their phase's rest mass are drawn at; heavier and lighter ones
+    /// are drawn as large as their share of the fluid, and all of them scaled by `radius_scale`.
+    pub particle_radius: f32,
+    pub radius_scale: f32,
+    /// `SimParams::phase_mass` of each phase as of the frame being drawn.
+    pub phase_masses: [f32; MAX_PHASES],
+    /// Draws the particles out of the latest particle buffer. Each of its pipelines comes in two: the
+    /// first draws a point sprite per particle, the second an instanced quad per slot of the buffer,
+    /// for `particle_spheres`.
+    pub particle_pipeline_layout: vk::PipelineLayout,
+    pub particle_pipeline: [vk::Pipeline; 2],
+    /// Draws them blended back to front through the depth sort's indices, when they are translucent.
+    pub translucent_particle_pipeline: [vk::Pipeline; 2],
+    /// Draws the far particles of the level of detail as flat discs.
+    pub far_particle_pipeline: [vk::Pipeline; 2],
+    /// The opaque particles' depth alone, for the depth pre-pass, then their near and far shading over
+    /// the depth it left.
+    pub prepass_particle_pipeline: [vk::Pipeline; 2],
+    pub equal_particle_pipeline: [vk::Pipeline; 2],
+    pub equal_far_particle_pipeline: [vk::Pipeline; 2],
+    /// The translucent particles weighted into the transparency targets, unsorted.
+    pub oit_particle_pipeline: [vk::Pipeline; 2],
+    /// Smallest and largest point size the device draws, in pixels; both 1 without `largePoints`.
+    pub point_size_range: [f32; 2],
+    /// Whether the particles are drawn as quads the sphere is shaded over, not points, the nearest being
+    /// wider than `point_size_range` lets a point be. The quads are neither culled nor sorted.
+    pub particle_spheres: bool,
+    /// The frame in flight whose `sim_params_sets` the particle draws being recorded bind.
+    pub sim_params_frame: usize,
+    /// Velocity glyph settings, or `None` with none drawn.
+    pub velocity_glyphs: Option<VelocityGlyphs>,
+    pub glyph_pipeline_layout: vk::PipelineLayout,
+    pub glyph_pipeline: vk::Pipeline,
+    /// Trail settings, or `None` with no positions recorded or drawn.
+    pub trails: Option<Trails>,
+    /// Rings of the traced particles' last positions, null until trails are first on, and their length,
+    /// stride and count as the buffer was made for.
+    pub trail_buffer: vk::Buffer,
+    pub trail_buffer_memory: vk::DeviceMemory,
+    pub trail_shape: [u32; 3],
+    /// Whether the step being recorded records a trail sample, and that sample's number.
+    pub sample_trails: bool,
+    pub trail_stamp: u32,
+    pub trail_pipeline_layout: vk::PipelineLayout,
+    pub trail_pipeline: vk::Pipeline,
+    pub trail_render_pipeline_layout: vk::PipelineLayout,
+    pub trail_render_pipeline: vk::Pipeline,
+    /// Grid occupancy view, if it is on, and the buffer its pass compacts the occupied cells into, made
+    /// when the view is first turned on with room for `occupancy_capacity` cells.
+    pub grid_occupancy: Option<GridOccupancy>,
+    pub occupancy_buffer: vk::Buffer,
+    pub occupancy_buffer_memory: vk::DeviceMemory,
+    pub occupancy_capacity: u32,
+    pub occupancy_pipeline_layout: vk::PipelineLayout,
+    pub occupancy_pipeline: vk::Pipeline,
+    pub occupancy_render_pipeline_layout: vk::PipelineLayout,
+    pub occupancy_render_pipeline: vk::Pipeline,
+    /// Translucency of the particles, or `None` for opaque ones, which are never sorted. The sort's
+    /// buffers are made when it is first turned on with room for `depth_sort_capacity` particles, and
+    /// sort under the camera's view as of the frame being recorded, across the given view depths.
+    pub translucency: Option<Translucency>,
+    pub depth_sort_buffer: vk::Buffer,
+    pub depth_sort_buffer_memory: vk::DeviceMemory,
+    pub depth_buckets_buffer: vk::Buffer,
+    pub depth_buckets_buffer_memory: vk::DeviceMemory,
+    pub depth_sort_capacity: u32,
+    pub depth_sort_view: glm::Mat4,
+    pub depth_sort_range: [f32; 2],
+    pub depth_sort_pipeline_layout: vk::PipelineLayout,
+    pub depth_keys_pipeline: vk::Pipeline,
+    pub depth_scatter_pipeline: vk::Pipeline,
+    /// Frustum culling and level of detail of the particles, or `None` for neither. The cull buffer is
+    /// made when either is first turned on with room for `cull_capacity` particles, and culls under the
+    /// camera's view as of the frame being recorded.
+    pub frustum_culling: Option<FrustumCulling>,
+    pub particle_lod: Option<ParticleLod>,
+    pub cull_buffer: vk::Buffer,
+    pub cull_buffer_memory: vk::DeviceMemory,
+    pub cull_capacity: u32,
+    pub cull_view: glm::Mat4,
+    pub cull_pipeline_layout: vk::PipelineLayout,
+    pub cull_pipeline: vk::Pipeline,
+    /// The picture-in-picture view of an offscreen image if it is on, and what draws it over the
+    /// swapchain images: a set per image it can show.
+    pub debug_view: Option<DebugView>,
+    pub debug_render_pass: vk::RenderPass,
+    pub debug_framebuffers: Vec<vk::Framebuffer>,
+    pub debug_set_layout: vk::DescriptorSetLayout,
+    pub debug_descriptor_pool: vk::DescriptorPool,
+    pub debug_descriptor_sets: Vec<vk::DescriptorSet>,
+    pub debug_pipeline_layout: vk::PipelineLayout,
+    pub debug_pipeline: vk::Pipeline,
+    /// How the scene, drawn into the HDR image, is tonemapped into each swapchain image, through a pass
+    /// of its own sampling the image through `tonemap_descriptor_set`.
+    pub tonemapping: Tonemapping,
+    pub hdr_image: vk::Image,
+    pub hdr_image_memory: vk::DeviceMemory,
+    pub hdr_image_view: vk::ImageView,
+    pub hdr_sampler: vk::Sampler,
+    pub tonemap_render_pass: vk::RenderPass,
+    pub tonemap_framebuffers: Vec<vk::Framebuffer>,
+    pub tonemap_set_layout: vk::DescriptorSetLayout,
+    pub tonemap_descriptor_pool: vk::DescriptorPool,
+    pub tonemap_descriptor_set: vk::DescriptorSet,
+    pub tonemap_pipeline_layout: vk::PipelineLayout,
+    pub tonemap_pipeline: vk::Pipeline,
+    /// The bloom over the HDR scene if it is on, and the levels of `bloom_image` it is blurred through,
+    /// each drawn down into by one pass and added back up into by another.
+    pub bloom: Option<Bloom>,
+    pub bloom_image: vk::Image,
+    pub bloom_image_memory: vk::DeviceMemory,
+    pub bloom_mip_views: Vec<vk::ImageView>,
+    pub bloom_mip_extents: Vec<vk::Extent2D>,
+    pub bloom_sampler: vk::Sampler,
+    pub bloom_down_render_pass: vk::RenderPass,
+    pub bloom_up_render_pass: vk::RenderPass,
+    pub bloom_down_framebuffers: Vec<vk::Framebuffer>,
+    pub bloom_up_framebuffers: Vec<vk::Framebuffer>,
+    pub bloom_set_layout: vk::DescriptorSetLayout,
+    pub bloom_descriptor_pool: vk::DescriptorPool,
+    /// The HDR image's, then each level's.
+    pub bloom_descriptor_sets: Vec<vk::DescriptorSet>,
+    pub bloom_pipeline_layout: vk::PipelineLayout,
+    pub bloom_down_pipeline: vk::Pipeline,
+    pub bloom_up_pipeline: vk::Pipeline,
+    /// The ambient occlusion if it is on, and what draws it: the meshes' view-space normals and depth
+    /// over `ssao_depth_image`, the occlusion then its blur in `ssao_images`, which the meshes' sets bind.
+    pub ssao: Option<Ssao>,
+    pub ssao_normal_image: vk::Image,
+    pub ssao_normal_image_memory: vk::DeviceMemory,
+    pub ssao_normal_image_view: vk::ImageView,
+    pub ssao_depth_image: vk::Image,
+    pub ssao_depth_image_memory: vk::DeviceMemory,
+    pub ssao_depth_image_view: vk::ImageView,
+    pub ssao_images: Vec<vk::Image>,
+    pub ssao_images_memory: Vec<vk::DeviceMemory>,
+    pub ssao_image_views: Vec<vk::ImageView>,
+    pub ssao_noise_image: vk::Image,
+    pub ssao_noise_image_memory: vk::DeviceMemory,
+    pub ssao_noise_image_view: vk::ImageView,
+    pub ssao_kernel_buffer: vk::Buffer,
+    pub ssao_kernel_buffer_memory: vk::DeviceMemory,
+    pub ssao_sampler: vk::Sampler,
+    pub ssao_noise_sampler: vk::Sampler,
+    pub ssao_normal_render_pass: vk::RenderPass,
+    pub ssao_render_pass: vk::RenderPass,
+    pub ssao_normal_framebuffer: vk::Framebuffer,
+    pub ssao_framebuffers: Vec<vk::Framebuffer>,
+    pub ssao_set_layout: vk::DescriptorSetLayout,
+    pub ssao_descriptor_pool: vk::DescriptorPool,
+    /// One per uniform buffer for the occlusion, then the blur's.
+    pub ssao_descriptor_sets: Vec<vk::DescriptorSet>,
+    pub ssao_pipeline_layout: vk::PipelineLayout,
+    pub ssao_normal_pipeline: vk::Pipeline,
+    pub ssao_pipeline: vk::Pipeline,
+    pub ssao_blur_pipeline: vk::Pipeline,
+    /// Whether translucent meshes and particles are weighted into the accumulated color and revealage of
+    /// `oit_images`, in a pass of their own over the opaque scene's depth, then composited over it,
+    /// rather than blended in the order they are drawn. With multisampling, the pass draws into the two
+    /// multisampled images after them and resolves into them.
+    pub oit: bool,
+    pub oit_images: Vec<vk::Image>,
+    pub oit_images_memory: Vec<vk::DeviceMemory>,
+    pub oit_image_views: Vec<vk::ImageView>,
+    pub oit_sampler: vk::Sampler,
+    pub oit_render_pass: vk::RenderPass,
+    pub oit_framebuffer: vk::Framebuffer,
+    pub oit_composite_render_pass: vk::RenderPass,
+    pub oit_composite_framebuffer: vk::Framebuffer,
+    pub oit_set_layout: vk::DescriptorSetLayout,
+    pub oit_descriptor_pool: vk::DescriptorPool,
+    pub oit_descriptor_set: vk::DescriptorSet,
+    pub oit_pipeline_layout: vk::PipelineLayout,
+    pub oit_composite_pipeline: vk::Pipeline,
+    /// Whether the objects' filled meshes are culled and drawn on the GPU, see `indirect`; only where
+    /// the device has `multi_draw_indirect`, which takes `multiDrawIndirect` and `drawIndirectFirstInstance`.
+    /// With `draw_indirect_count` (`VK_KHR_draw_indirect_count`), the draws left are counted on the GPU too.
+    pub indirect_draws: bool,
+    pub multi_draw_indirect: bool,
+    pub draw_indirect_count: bool,
+    /// Each swapchain image's draws to cull, written every frame, and the commands and counts the cull
+    /// leaves, with a set binding them and the image's object buffer.
+    pub indirect_input_buffers: Vec<vk::Buffer>,
+    pub indirect_input_buffers_memory: Vec<vk::DeviceMemory>,
+    pub indirect_buffers: Vec<vk::Buffer>,
+    pub indirect_buffers_memory: Vec<vk::DeviceMemory>,
+    pub mesh_cull_set_layout: vk::DescriptorSetLayout,
+    pub mesh_cull_descriptor_pool: vk::DescriptorPool,
+    pub mesh_cull_sets: Vec<vk::DescriptorSet>,
+    pub mesh_cull_pipeline_layout: vk::PipelineLayout,
+    pub mesh_cull_pipeline: vk::Pipeline,
+    /// The pipeline of each run of this frame's indirect draws, with the run's first slot and length.
+    pub indirect_groups: Vec<(MeshPipeline, u32, u32)>,
+    /// Whether the main pass runs secondary command buffers, recorded in parallel and kept for each
+    /// object until it changes, rather than recording its draws inline every frame.
+    pub parallel_recording: bool,
+    /// Each swapchain image's command pools, one per recording thread, and the secondary command
+    /// buffers recorded from them.
+    pub secondary_pools: Vec<Vec<vk::CommandPool>>,
+    pub secondaries: Vec<Secondaries>,
+    /// The two views the window is split into, if it is.
+    pub split_screen: Option<SplitScreen>,
+    /// The cutting plane through the field volume, and the pipeline drawing it.
+    pub density_slice: DensitySlice,
+    pub slice_pipeline_layout: vk::PipelineLayout,
+    pub slice_pipeline: vk::Pipeline,
+    /// Narrowest and widest line the device draws, in pixels; both 1 without `wideLines`.
+    pub line_width_range: [f32; 2],
+    /// The solver's estimate of the live particles, and the fastest one's speed as of the latest
+    /// diagnostics, as of the frame being drawn.
+    pub particle_count: u32,
+    pub max_speed: f32,
+    /// Timestamps of each frame in flight's solver step and of each swapchain image's render pass;
+    /// null when the queue cannot write timestamps.
+    pub step_query_pool: vk::QueryPool,
+    pub render_query_pool: vk::QueryPool,
+    /// Nanoseconds per timestamp tick.
+    pub timestamp_period: f32,
+    pub speed_reduce_pipeline: vk::Pipeline,
+    pub max_reduce_pipeline: vk::Pipeline,
+    pub integrate_pipeline: vk::Pipeline,
+    pub kernel_kind: KernelKind,
+    pub boundary_mode: BoundaryMode,
+    /// Local size the compute pipelines are specialized with.
+    pub workgroup_size: u32,
+    pub append_pipeline: vk::Pipeline,
+    pub mark_pipeline: vk::Pipeline,
+    pub scatter_pipeline: vk::Pipeline,
+    /// Sorts each cell's particles by their state after the scatter, when `strict_order` is set.
+    pub sort_cells_pipeline: vk::Pipeline,
+    /// Whether neighbors are visited in an order fixed by the particles alone, so the GPU float sums
+    /// come out the same on every run, at the cost of the extra sort.
+    pub strict_order: bool,
+    /// GPU prefix sum, with its own layout so it can scan any registered buffer.
+    pub scan_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub scan_pipeline_layout: vk::PipelineLayout,
+    pub scan_descriptor_pool: vk::DescriptorPool,
+    pub scan_blocks_pipeline: vk::Pipeline,
+    pub scan_block_sums_pipeline: vk::Pipeline,
+    pub add_block_offsets_pipeline: vk::Pipeline,
+    pub scan_targets: Vec<ScanTarget>,
+    pub boundary_update_pipeline: vk::Pipeline,
+    pub boundary_force_pipeline: vk::Pipeline,
+    pub body_reduce_pipeline: vk::Pipeline,
+    pub mouse_force_pipeline: vk::Pipeline,
+    pub pick_pipeline_layout: vk::PipelineLayout,
+    pub pick_pipeline: vk::Pipeline,
+    /// Per pick pass workgroup, its best candidate's key and slot.
+    pub pick_buffer: vk::Buffer,
+    pub pick_buffer_memory: vk::DeviceMemory,
+    /// Persistently mapped, the selected and the watched particle as each frame slot's step left them, or
+    /// all ones where the step found no live particle with `selected_id` or `watched_id`.
+    pub selection_buffer: vk::Buffer,
+    pub selection_buffer_memory: vk::DeviceMemory,
+    pub selection_staging: Option<*mut Particle>,
+    pub splat_pipeline_layout: vk::PipelineLayout,
+    pub splat_pipeline: vk::Pipeline,
+    /// Volume fraction at each node of the surface reconstruction's grid, room for `field_capacity` nodes.
+    pub field_buffer: vk::Buffer,
+    pub field_buffer_memory: vk::DeviceMemory,
+    pub field_capacity: u32,
+    /// The last reconstruction's volume fraction as a 3D image for the raymarcher, the grid it spans,
+    /// `None` before the first, and the iso level it was reconstructed at.
+    pub field_volume: vk::Image,
+    pub field_volume_memory: vk::DeviceMemory,
+    pub field_volume_view: vk::ImageView,
+    pub field_volume_grid: Option<FieldGrid>,
+    pub field_volume_iso: f32,
+    /// Foam and spray settings, or `None` with no foam seeded, moved or drawn.
+    pub foam: Option<Foam>,
+    pub foam_pipeline_layout: vk::PipelineLayout,
+    pub foam_normals_pipeline: vk::Pipeline,
+    pub foam_seed_pipeline: vk::Pipeline,
+    pub foam_update_pipeline: vk::Pipeline,
+    /// Ring of `FOAM_CAPACITY` foam particles, the head of which is in the counter buffer.
+    pub foam_buffer: vk::Buffer,
+    pub foam_buffer_memory: vk::DeviceMemory,
+    pub foam_render_pipeline_layout: vk::PipelineLayout,
+    pub foam_render_pipeline: vk::Pipeline,
+    pub compute_command_pool: vk::CommandPool,
+    pub compute_command_buffers: Vec<vk::CommandBuffer>,
 }
\ No newline at end of file
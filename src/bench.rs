@@ -0,0 +1,90 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::timing::FrameTimings;
+
+/// Times a run of solver steps, one per frame, after letting a few warm up. Frames count as they
+/// complete on the GPU, so the wall time runs from the completion of the last warm-up frame to that of
+/// the last timed one and covers the timed frames alone.
+#[derive(Clone, Debug)]
+pub struct Benchmark {
+    warmup: u32,
+    steps: u32,
+    particles: u32,
+    completed: u32,
+    started: Option<Instant>,
+    wall: Duration,
+    /// Mean GPU timings of the timed frames that had them, and how many did.
+    timings: FrameTimings,
+    timed: u32,
+}
+
+/// What a finished `Benchmark` measured.
+#[derive(Copy, Clone, Debug)]
+pub struct BenchReport {
+    pub particles: u32,
+    pub steps: u32,
+    pub wall: Duration,
+    /// Mean GPU milliseconds per step, `None` when the device cannot time its passes.
+    pub timings: Option<FrameTimings>,
+}
+
+impl Benchmark {
+    pub fn new(warmup: u32, steps: u32, particles: u32) -> Self {
+        Self { warmup, steps: steps.max(1), particles, completed: 0, started: None, wall: Duration::ZERO,
+            timings: FrameTimings::default(), timed: 0 }
+    }
+
+    /// Counts a stepped frame whose fence just signaled, with its GPU timings if they could be read.
+    pub fn complete(&mut self, timings: Option<&FrameTimings>) {
+        if self.finished() {
+            return;
+        }
+        self.completed += 1;
+        if self.completed <= self.warmup {
+            if self.completed == self.warmup {
+                self.started = Some(Instant::now());
+            }
+            return;
+        }
+        let started = *self.started.get_or_insert_with(Instant::now);
+        self.wall = started.elapsed();
+        if let Some(timings) = timings {
+            self.timed += 1;
+            self.timings.blend(timings, 1.0 / self.timed as f32);
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.completed >= self.warmup + self.steps
+    }
+
+    pub fn report(&self) -> Option<BenchReport> {
+        self.finished().then_some(BenchReport { particles: self.particles, steps: self.steps, wall: self.wall,
+            timings: (self.timed > 0).then_some(self.timings) })
+    }
+}
+
+impl BenchReport {
+    pub fn ms_per_step(&self) -> f64 {
+        self.wall.as_secs_f64() * 1e3 / self.steps as f64
+    }
+
+    /// Particle updates per second of wall time.
+    pub fn particles_per_second(&self) -> f64 {
+        self.particles as f64 * self.steps as f64 / self.wall.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} particles, {} steps in {:.3} s: {:.3} ms per step, {:.2} M particles/s",
+            self.particles, self.steps, self.wall.as_secs_f64(), self.ms_per_step(), self.particles_per_second() * 1e-6)?;
+        match &self.timings {
+            Some(t) => write!(f, "GPU per step: {:.3} ms, of which grid {:.3}, density {:.3}, force {:.3}, integrate {:.3}, \
+                other {:.3}; render {:.3} ms", t.step, t.grid_build, t.density, t.force, t.integrate,
+                t.step - t.grid_build - t.density - t.force - t.integrate, t.render),
+            None => write!(f, "GPU per step: unavailable, the device cannot write timestamps"),
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use std::mem::size_of;
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::{BLOOM_INTENSITY, BLOOM_MAX_MIPS, BLOOM_MIPS, BLOOM_SHADER, BLOOM_THRESHOLD, FULLSCREEN_VERTEX_SHADER};
+use crate::tonemap::HDR_FORMAT;
+use crate::utils::{allocate_image, begin_single_time_commands, compile_shader, create_shader_module,
+    end_single_time_commands};
+
+/// Light in the HDR scene brighter than `threshold` bled over its surroundings: picked out into half
+/// the window's size, blurred down a chain of `mips` levels each half the last, then added back up them
+/// with each level's blur widening the one below, and added over the scene as it is tonemapped.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bloom {
+    pub threshold: f32,
+    /// How much of the blur is added over the scene.
+    pub intensity: f32,
+    /// Levels the blur goes down through, up to those made for the swapchain.
+    pub mips: u32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self { threshold: BLOOM_THRESHOLD, intensity: BLOOM_INTENSITY, mips: BLOOM_MIPS }
+    }
+}
+
+/// Push constants of the bloom passes, laid out like `Bloom` in `bloom.frag`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct BloomConstants {
+    /// Size of a texel of the level read.
+    texel: [f32; 2],
+    threshold: f32,
+    /// 0 picks out the bright light and downsamples, 1 downsamples, 2 upsamples.
+    mode: u32,
+}
+
+/// Creates the passes and pipelines drawing down into each level of the bloom and back up into it, and
+/// the layout of the sets they read through.
+pub unsafe fn create_bloom(device: &Device, data: &mut AppData) -> Result<()> {
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::LINEAR).min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+    data.bloom_sampler = device.create_sampler(&sampler_info, None)?;
+    data.bloom_down_render_pass = create_bloom_render_pass(device, false)?;
+    data.bloom_up_render_pass = create_bloom_render_pass(device, true)?;
+
+    // set 0 reads the HDR image, set 1 + k level k
+    let binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+    let bindings = &[binding];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+    data.bloom_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    let set_layouts = &[data.bloom_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(size_of::<BloomConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.bloom_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    data.bloom_down_pipeline = create_bloom_pipeline(device, data, data.bloom_down_render_pass, false)?;
+    data.bloom_up_pipeline = create_bloom_pipeline(device, data, data.bloom_up_render_pass, true)?;
+    Ok(())
+}
+
+/// Creates the levels the bloom is blurred through, as many as the current swapchain's extent has
+/// room for, with their framebuffers and sets, and binds the largest to the tonemapping pass. The
+/// levels start black, so the tonemapping can sample them with bloom off. Call after
+/// `create_tonemap_targets`, whose HDR image it reads.
+pub unsafe fn create_bloom_targets(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let extent = data.swapchain_extent;
+    let base = vk::Extent2D { width: (extent.width / 2).max(1), height: (extent.height / 2).max(1) };
+    let levels = (32 - base.width.min(base.height).leading_zeros()).min(BLOOM_MAX_MIPS);
+    let info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::_2D)
+        .extent(vk::Extent3D { width: base.width, height: base.height, depth: 1 })
+        .mip_levels(levels).array_layers(1).format(HDR_FORMAT)
+        .tiling(vk::ImageTiling::OPTIMAL).initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
+        .samples(vk::SampleCountFlags::_1).sharing_mode(vk::SharingMode::EXCLUSIVE);
+    (data.bloom_image, data.bloom_image_memory) = allocate_image(instance, device, data, &info,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+    data.bloom_mip_extents = (0..levels)
+        .map(|k| vk::Extent2D { width: (base.width >> k).max(1), height: (base.height >> k).max(1) }).collect();
+    data.bloom_mip_views = (0..levels).map(|k| {
+        let subresource = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_mip_level(k).level_count(1).base_array_layer(0).layer_count(1);
+        let info = vk::ImageViewCreateInfo::builder()
+            .image(data.bloom_image).view_type(vk::ImageViewType::_2D).format(HDR_FORMAT)
+            .subresource_range(subresource);
+        device.create_image_view(&info, None)
+    }).collect::<Result<Vec<_>, _>>()?;
+    let framebuffers = |render_pass: vk::RenderPass| data.bloom_mip_views.iter().zip(&data.bloom_mip_extents)
+        .map(|(view, extent)| {
+            let attachments = &[*view];
+            let info = vk::FramebufferCreateInfo::builder().render_pass(render_pass)
+                .attachments(attachments).width(extent.width).height(extent.height).layers(1);
+            device.create_framebuffer(&info, None)
+        }).collect::<Result<Vec<_>, _>>();
+    data.bloom_down_framebuffers = framebuffers(data.bloom_down_render_pass)?;
+    data.bloom_up_framebuffers = framebuffers(data.bloom_up_render_pass)?;
+
+    // a set per level, so the pool goes with them
+    let count = levels + 1;
+    let pool_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).descriptor_count(count);
+    let pool_sizes = &[pool_size];
+    let info = vk::DescriptorPoolCreateInfo::builder().pool_sizes(pool_sizes).max_sets(count);
+    data.bloom_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    let layouts = vec![data.bloom_set_layout; count as usize];
+    let info = vk::DescriptorSetAllocateInfo::builder().descriptor_pool(data.bloom_descriptor_pool).set_layouts(&layouts);
+    data.bloom_descriptor_sets = device.allocate_descriptor_sets(&info)?;
+    let views = [data.hdr_image_view].into_iter().chain(data.bloom_mip_views.iter().copied());
+    let targets = data.bloom_descriptor_sets.iter().map(|set| (*set, 0)).chain([(data.tonemap_descriptor_set, 1)]);
+    for ((set, binding), view) in targets.zip(views.chain([data.bloom_mip_views[0]])) {
+        let image_info = &[vk::DescriptorImageInfo::builder()
+            .sampler(data.bloom_sampler).image_view(view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(set).dst_binding(binding).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info);
+        device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+    }
+
+    // clear every level, leaving it where the tonemapping samples it
+    let command_buffer = begin_single_time_commands(device, data)?;
+    for (framebuffer, extent) in data.bloom_down_framebuffers.iter().zip(&data.bloom_mip_extents) {
+        begin_bloom_pass(device, command_buffer, data.bloom_down_render_pass, *framebuffer, *extent);
+        device.cmd_end_render_pass(command_buffer);
+    }
+    end_single_time_commands(device, data, command_buffer)
+}
+
+pub unsafe fn destroy_bloom_targets(device: &Device, data: &AppData) {
+    device.destroy_descriptor_pool(data.bloom_descriptor_pool, None);
+    data.bloom_down_framebuffers.iter().chain(&data.bloom_up_framebuffers)
+        .for_each(|f| device.destroy_framebuffer(*f, None));
+    data.bloom_mip_views.iter().for_each(|v| device.destroy_image_view(*v, None));
+    device.destroy_image(data.bloom_image, None);
+    device.free_memory(data.bloom_image_memory, None);
+}
+
+/// A pass drawing into one level, which is cleared first going down and added to going up, and left
+/// for the next pass or the tonemapping to sample.
+unsafe fn create_bloom_render_pass(device: &Device, upsample: bool) -> Result<vk::RenderPass> {
+    let (load_op, initial_layout) = if upsample {
+        (vk::AttachmentLoadOp::LOAD, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    } else {
+        (vk::AttachmentLoadOp::CLEAR, vk::ImageLayout::UNDEFINED)
+    };
+    let color_attachment = vk::AttachmentDescription::builder()
+        .format(HDR_FORMAT).samples(vk::SampleCountFlags::_1)
+        .load_op(load_op).store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE).stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(initial_layout).final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    let color_attachment_ref = vk::AttachmentReference::builder().attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let color_attachments = &[color_attachment_ref];
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(color_attachments);
+    // each level is drawn once the pass before is done with it, and read once drawn
+    let before = vk::SubpassDependency::builder()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::COLOR_ATTACHMENT_READ
+            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+    let after = vk::SubpassDependency::builder()
+        .src_subpass(0)
+        .dst_subpass(vk::SUBPASS_EXTERNAL)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ);
+    let attachments = &[color_attachment];
+    let subpasses = &[subpass];
+    let dependencies = &[before, after];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+    Ok(device.create_render_pass(&info, None)?)
+}
+
+/// A full-screen triangle through `bloom.frag`, adding onto the level when `additive`.
+unsafe fn create_bloom_pipeline(device: &Device, data: &AppData, render_pass: vk::RenderPass, additive: bool)
+-> Result<vk::Pipeline> {
+    let vshader = compile_shader(&FULLSCREEN_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&BLOOM_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+    // the viewport and scissor are set to each level as it is drawn
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlags::_1);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(additive)
+        .src_color_blend_factor(vk::BlendFactor::ONE)
+        .dst_color_blend_factor(vk::BlendFactor::ONE)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .alpha_blend_op(vk::BlendOp::ADD);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .dynamic_state(&dynamic_state)
+        .multisample_state(&multisample_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.bloom_pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0);
+    let pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(pipeline)
+}
+
+pub unsafe fn destroy_bloom(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.bloom_down_pipeline, None);
+    device.destroy_pipeline(data.bloom_up_pipeline, None);
+    device.destroy_pipeline_layout(data.bloom_pipeline_layout, None);
+    device.destroy_descriptor_set_layout(data.bloom_set_layout, None);
+    device.destroy_render_pass(data.bloom_down_render_pass, None);
+    device.destroy_render_pass(data.bloom_up_render_pass, None);
+    device.destroy_sampler(data.bloom_sampler, None);
+}
+
+unsafe fn begin_bloom_pass(device: &Device, command_buffer: vk::CommandBuffer,
+    render_pass: vk::RenderPass, framebuffer: vk::Framebuffer, extent: vk::Extent2D) {
+    let clear_values = &[vk::ClearValue { color: vk::ClearColorValue { float32: [0.0, 0.0, 0.0, 1.0] } }];
+    let area = vk::Rect2D::builder().offset(vk::Offset2D::default()).extent(extent).build();
+    let info = vk::RenderPassBeginInfo::builder()
+        .render_pass(render_pass)
+        .framebuffer(framebuffer)
+        .render_area(area)
+        .clear_values(clear_values);
+    device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+    let viewport = vk::Viewport { x: 0.0, y: 0.0, width: extent.width as f32, height: extent.height as f32,
+        min_depth: 0.0, max_depth: 1.0 };
+    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+    device.cmd_set_scissor(command_buffer, 0, &[area]);
+}
+
+/// Records the bloom of the finished HDR scene, if drawn: down the levels from the HDR image, then back
+/// up them into the largest, which the tonemapping adds over the scene.
+pub unsafe fn record_bloom(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer) {
+    let Some(bloom) = &data.bloom else { return };
+    let levels = (bloom.mips as usize).clamp(1, data.bloom_mip_views.len());
+    let texel = |extent: vk::Extent2D| [1.0 / extent.width as f32, 1.0 / extent.height as f32];
+    let draw = |pipeline: vk::Pipeline, set: vk::DescriptorSet, constants: BloomConstants| {
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+            data.bloom_pipeline_layout, 0, &[set], &[]);
+        let bytes = std::slice::from_raw_parts((&constants as *const BloomConstants).cast::<u8>(),
+            size_of::<BloomConstants>());
+        device.cmd_push_constants(command_buffer, data.bloom_pipeline_layout, vk::ShaderStageFlags::FRAGMENT, 0, bytes);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        device.cmd_end_render_pass(command_buffer);
+    };
+    for k in 0..levels {
+        let extent = data.bloom_mip_extents[k];
+        let source = if k == 0 { data.swapchain_extent } else { data.bloom_mip_extents[k - 1] };
+        begin_bloom_pass(device, command_buffer, data.bloom_down_render_pass, data.bloom_down_framebuffers[k],
+            extent);
+        draw(data.bloom_down_pipeline, data.bloom_descriptor_sets[k],
+            BloomConstants { texel: texel(source), threshold: bloom.threshold, mode: (k > 0) as u32 });
+    }
+    for k in (0..levels - 1).rev() {
+        begin_bloom_pass(device, command_buffer, data.bloom_up_render_pass, data.bloom_up_framebuffers[k],
+            data.bloom_mip_extents[k]);
+        draw(data.bloom_up_pipeline, data.bloom_descriptor_sets[k + 2],
+            BloomConstants { texel: texel(data.bloom_mip_extents[k + 1]), threshold: bloom.threshold, mode: 2 });
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use nalgebra_glm as glm;
+
+use crate::kernel::Kernel;
+
+/// A boundary sample (Akinci et al. 2012), laid out to match the std430 `BoundaryParticle` in the shaders.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BoundaryParticle {
+    pub pos: glm::Vec3,
+    /// Boundary volume times rest density; stands in for the mass of a fluid neighbor.
+    pub psi: f32,
+    /// Position in the owning body's frame; equal to `pos` for static boundaries.
+    pub rest: glm::Vec3,
+    /// Index of the owning rigid body, or `STATIC_BOUNDARY`.
+    pub body: u32,
+}
+
+/// Body index of boundary particles that never move.
+pub const STATIC_BOUNDARY: u32 = u32::MAX;
+
+/// Candidates thrown per `spacing^2` of surface area before Poisson-disk elimination.
+const OVERSAMPLING: f32 = 8.0;
+
+/// Samples the surface of a transformed triangle mesh with a Poisson-disk distribution of radius `spacing`.
+/// Candidates are jittered uniformly over each triangle in proportion to its area, so the result does not
+/// depend on how the mesh vertices are distributed.
+pub fn sample_surface(positions: &[glm::Vec3], indices: &[u32], transform: &glm::Mat4, spacing: f32,
+    seed: u32) -> Vec<glm::Vec3> {
+    let world = positions.iter()
+        .map(|p| (transform * glm::vec4(p.x, p.y, p.z, 1.0)).xyz())
+        .collect::<Vec<_>>();
+    let mut rng = XorShift(seed.max(1));
+    let mut candidates = Vec::new();
+    for tri in indices.chunks_exact(3) {
+        let (a, b, c) = (world[tri[0] as usize], world[tri[1] as usize], world[tri[2] as usize]);
+        let area = 0.5 * glm::length(&glm::cross(&(b - a), &(c - a)));
+        // stochastic rounding keeps small triangles represented on average
+        let expected = OVERSAMPLING * area / (spacing * spacing);
+        let count = expected as u32 + (rng.next() < expected.fract()) as u32;
+        for _ in 0..count {
+            let (mut u, mut v) = (rng.next(), rng.next());
+            if u + v > 1.0 {
+                (u, v) = (1.0 - u, 1.0 - v);
+            }
+            candidates.push(a + (b - a) * u + (c - a) * v);
+        }
+    }
+
+    // dart throwing over the shuffled candidates: keep one only if nothing kept lies within `spacing`
+    for i in (1..candidates.len()).rev() {
+        let j = (rng.next() * (i + 1) as f32) as usize;
+        candidates.swap(i, j.min(i));
+    }
+    let mut grid = HashGrid::new(spacing);
+    let mut samples = Vec::new();
+    for p in candidates {
+        if grid.neighbors(&p).all(|k| glm::distance2(&samples[k], &p) >= spacing * spacing) {
+            grid.insert(&p, samples.len());
+            samples.push(p);
+        }
+    }
+    samples
+}
+
+/// Computes each sample's boundary volume `1 / sum_k W(x_b - x_k)` over its boundary neighbors,
+/// and scales it by the rest density.
+pub fn boundary_particles(samples: &[glm::Vec3], kernel: &Kernel, rest_density: f32) -> Vec<BoundaryParticle> {
+    let mut grid = HashGrid::new(kernel.h);
+    samples.iter().enumerate().for_each(|(i, p)| grid.insert(p, i));
+    samples.iter().map(|p| {
+        let weight = grid.neighbors(p).map(|k| kernel.w(glm::distance(p, &samples[k]))).sum::<f32>();
+        BoundaryParticle { pos: *p, psi: rest_density / weight.max(f32::EPSILON), rest: *p, body: STATIC_BOUNDARY }
+    }).collect()
+}
+
+/// Uniform hash grid whose cells are `cell` wide, so a radius-`cell` query only visits the 27 surrounding cells.
+struct HashGrid {
+    cell: f32,
+    cells: HashMap<(i32, i32, i32), Vec<usize>>,
+}
+
+impl HashGrid {
+    fn new(cell: f32) -> Self {
+        Self { cell, cells: HashMap::new() }
+    }
+
+    fn key(&self, p: &glm::Vec3) -> (i32, i32, i32) {
+        let k = (p / self.cell).map(|x| x.floor() as i32);
+        (k.x, k.y, k.z)
+    }
+
+    fn insert(&mut self, p: &glm::Vec3, index: usize) {
+        self.cells.entry(self.key(p)).or_default().push(index);
+    }
+
+    fn neighbors(&self, p: &glm::Vec3) -> impl Iterator<Item = usize> + '_ {
+        let (x, y, z) = self.key(p);
+        (0..27).flat_map(move |n| {
+            let key = (x + n % 3 - 1, y + n / 3 % 3 - 1, z + n / 9 - 1);
+            self.cells.get(&key).into_iter().flatten().copied()
+        })
+    }
+}
+
+/// Small deterministic generator so the same mesh always samples the same way.
+pub(crate) struct XorShift(pub u32);
+
+impl XorShift {
+    /// Uniform in `[0, 1)`.
+    pub fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
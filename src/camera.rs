@@ -6,6 +6,9 @@ use vulkanalia::prelude::v1_0::*;
 
 use anyhow::{Result, Ok};
 use crate::appdata::AppData;
+use crate::config::{FAR_PLANE, MAX_LIGHTS, NEAR_PLANE};
+use crate::lighting::GpuLight;
+use crate::simulation::TimeMode;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
@@ -18,6 +21,26 @@ pub struct UniformBufferObject {
     pub light_pos: glm::Vec3,
     pub specular_strength: f32,
     pub view_pos: glm::Vec3, 
+    _pad: f32,
+    /// Inverses of `view` and `proj`, to turn pixels back into rays.
+    pub inv_view: glm::Mat4,
+    pub inv_proj: glm::Mat4,
+    /// The light's view-projection into the shadow map, the depth bias and normal offset of its
+    /// lookups, and 1 if shadows are drawn.
+    pub light_view_proj: glm::Mat4,
+    pub shadow_bias: f32,
+    pub shadow_normal_offset: f32,
+    pub shadows: u32,
+    /// 1 if the shadows are the light's as a point, and how far they reach and the bias of their lookups.
+    pub point_shadows: u32,
+    pub point_shadow_far: f32,
+    pub point_shadow_bias: f32,
+    /// The first `light_count` of `lights` shade the meshes, the first of them also being `light_pos`.
+    pub light_count: u32,
+    _pad_lights: u32,
+    pub lights: [GpuLight; MAX_LIGHTS],
+    /// What the meshes show, 0 their shading; see `ShadingDebug`.
+    pub shading_debug: u32,
 }
 
 
@@ -31,43 +54,87 @@ pub struct Camera {
     facing: glm::Vec3,
 }
 
+/// A turntable for capturing footage: every rendered frame turns the cameras `degrees_per_frame` of yaw
+/// around the scene, with the mouse leaving them be, until `frames_left` runs out. Only the yaw turns,
+/// so the orbit's radius and framing stay put through a window resize.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Turntable {
+    pub degrees_per_frame: f32,
+    pub frames_left: u32,
+    /// The time mode to go back to when it ends, if it locked the solver to fixed steps for footage that
+    /// comes out the same every run.
+    pub restore: Option<TimeMode>,
+}
+
+/// Places the loaded models in the world the fluid lives in.
+pub fn scene_model() -> glm::Mat4 {
+    glm::rotate(
+        &glm::identity(),
+        glm::radians(&glm::vec1(90.0))[0],
+        &glm::vec3(0.0, 0.0, 1.0),
+    )
+}
+
+/// Perspective projection of a swapchain of `extent`, flipped for Vulkan's downward y.
+pub fn projection(extent: vk::Extent2D) -> glm::Mat4 {
+    let mut proj = glm::perspective_rh_zo(
+        extent.width as f32 / extent.height as f32,
+        glm::radians(&glm::vec1(45.0))[0],
+        NEAR_PLANE,
+        FAR_PLANE,
+    );
+    proj[(1, 1)] *= -1.0;
+    proj
+}
 
 impl UniformBufferObject {
     pub fn new() -> Self {
         Self { model: glm::identity(), view: glm::identity(), proj: glm::identity(), 
             base_light: glm::Vec3::default(), ambient_strength: 0.1, 
             light_pos: glm::vec3(1.0, 1.0, 1.0), view_pos: glm::vec3(1.0, 1.0, 1.0),
-            specular_strength: 0.8,
+            specular_strength: 0.8, _pad: 0.0, inv_view: glm::identity(), inv_proj: glm::identity(),
+            light_view_proj: glm::identity(), shadow_bias: 0.0, shadow_normal_offset: 0.0, shadows: 0,
+            point_shadows: 0, point_shadow_far: 1.0, point_shadow_bias: 0.0, light_count: 0, _pad_lights: 0,
+            lights: [GpuLight::default(); MAX_LIGHTS], shading_debug: 0,
         }
     }
 
-    pub unsafe fn update(&mut self, image_index: usize, view_mat: glm::Mat4,
+    /// Writes the scene as seen through `view_mat` into viewports of `extent` to uniform buffer `slot`.
+    pub unsafe fn update(&mut self, slot: usize, view_mat: glm::Mat4, extent: vk::Extent2D,
         data: &AppData, device: &Device) 
     -> Result<()> {
         self.view_pos = glm::vec3(1.0, 1.0, 1.0);
         self.model = view_mat;
         
-        self.model = glm::rotate(
-            &glm::identity(),
-            glm::radians(&glm::vec1(90.0))[0],
-            &glm::vec3(0.0, 0.0, 1.0),
-        );
+        self.model = scene_model();
         self.view = view_mat;
-        self.proj = glm::perspective_rh_zo(
-            data.swapchain_extent.width as f32 / data.swapchain_extent.height as f32,
-            glm::radians(&glm::vec1(45.0))[0],
-            0.1,
-            10.0,
-        );
-        self.proj[(1, 1)] *= -1.0;
+        self.proj = projection(extent);
+        self.inv_view = glm::inverse(&self.view);
+        self.inv_proj = glm::inverse(&self.proj);
         self.base_light = glm::vec3(1.0, 1.0, 1.0);
+        self.light_count = data.lights.len().min(MAX_LIGHTS) as u32;
+        for (slot, light) in self.lights.iter_mut().zip(&data.lights) {
+            *slot = light.into();
+        }
+        self.shading_debug = data.shading_debug as u32;
+        self.light_view_proj = data.shadow_view_proj;
+        self.shadows = data.shadows.is_some() as u32;
+        if let Some(shadows) = &data.shadows {
+            self.shadow_bias = shadows.bias;
+            self.shadow_normal_offset = shadows.normal_offset;
+        }
+        self.point_shadows = data.point_shadows.is_some() as u32;
+        if let Some(shadows) = &data.point_shadows {
+            self.point_shadow_far = shadows.far;
+            self.point_shadow_bias = shadows.bias;
+        }
 
         let memory = device.map_memory(
-            data.uniform_buffers_memory[image_index], 0,
+            data.uniform_buffers_memory[slot], 0,
             size_of::<UniformBufferObject>() as u64, vk::MemoryMapFlags::empty())?;
         
         memcpy(self, memory.cast(), 1);
-        device.unmap_memory(data.uniform_buffers_memory[image_index]);
+        device.unmap_memory(data.uniform_buffers_memory[slot]);
         Ok(())
     } 
 }
@@ -80,6 +147,25 @@ impl Camera {
         Ok(retval)
     }
 
+    /// Puts the camera `distance` from the origin at `yaw` and `pitch` degrees, looking at the origin.
+    pub fn set_view(&mut self, distance: f32, yaw: f32, pitch: f32) -> Result<()> {
+        self.dist_from_origin = distance.max(0.5);
+        self.yaw = yaw;
+        self.pitch = pitch;
+        self.rotate(0.0, 0.0)
+    }
+
+    /// Puts the camera on the positive side of world `axis` at its distance, looking back along it; from
+    /// straight above for Y, as far as the pitch goes.
+    pub fn view_along(&mut self, axis: usize) -> Result<()> {
+        let (yaw, pitch) = match axis {
+            0 => (0.0, 0.0),
+            1 => (self.yaw, 89.0),
+            _ => (90.0, 0.0),
+        };
+        self.set_view(self.dist_from_origin, yaw, pitch)
+    }
+
     pub fn handle_scroll(&mut self, diff: f32){
         self.dist_from_origin -= diff * self.zoom_speed;
         if self.dist_from_origin < 0.5 {
@@ -92,6 +178,24 @@ impl Camera {
         glm::look_at(&position, &glm::vec3(0.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0))
     }
 
+    /// Ray through the window pixel `(x, y)` of a swapchain of `extent`, as its origin on the near plane
+    /// and its direction, in the world the fluid lives in.
+    pub fn cursor_ray(&self, extent: vk::Extent2D, x: f32, y: f32) -> (glm::Vec3, glm::Vec3) {
+        let inverse = glm::inverse(&(projection(extent) * self.get_view_matrix()));
+        let ndc = glm::vec2(2.0 * x / extent.width as f32 - 1.0, 2.0 * y / extent.height as f32 - 1.0);
+        let unproject = |z: f32| {
+            let p = inverse * glm::vec4(ndc.x, ndc.y, z, 1.0);
+            p.xyz() / p.w
+        };
+        let (near, far) = (unproject(0.0), unproject(1.0));
+        (near, glm::normalize(&(far - near)))
+    }
+
+    /// Turns the camera `degrees` of yaw around the origin, at its distance and pitch.
+    pub fn orbit(&mut self, degrees: f32) -> Result<()> {
+        self.rotate(degrees, 0.0)
+    }
+
     pub fn handle_mouse(&mut self, x_diff: f32, y_diff: f32) -> Result<()> {
         self.rotate(self.sensitivity * x_diff, self.sensitivity * y_diff)?;
         Ok(())
@@ -0,0 +1,188 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::mem::size_of;
+use std::path::Path;
+use anyhow::{anyhow, Result};
+
+use crate::kernel::KernelKind;
+use crate::simulation::{BoundaryKind, Particle, SimParams};
+
+const MAGIC: &[u8; 8] = b"SPHCKPT\0";
+/// Bump whenever the layout below or of `Particle`/`SimParams` changes.
+const VERSION: u32 = 5;
+
+/// A snapshot of the solver: every live particle, the parameters and the simulated time.
+///
+/// The file is the magic, then `VERSION`, the sizes of `Particle` and `SimParams`, the kernel kind,
+/// the particle count and the time as little-endian words, then the raw parameters and particles.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub params: SimParams,
+    pub kernel: KernelKind,
+    /// Simulated seconds since the start of the run.
+    pub time: f64,
+    pub particles: Vec<Particle>,
+}
+
+impl Checkpoint {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        for word in [VERSION, size_of::<Particle>() as u32, size_of::<SimParams>() as u32,
+            self.kernel as u32, self.particles.len() as u32] {
+            writer.write_all(&word.to_le_bytes())?;
+        }
+        writer.write_all(&self.time.to_le_bytes())?;
+        writer.write_all(as_bytes(std::slice::from_ref(&self.params)))?;
+        writer.write_all(as_bytes(&self.particles))?;
+        Ok(())
+    }
+
+    pub fn read(reader: &mut impl Read) -> Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(anyhow!("Not a checkpoint file."));
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(anyhow!("Checkpoint version {} is not supported (expected {}).", version, VERSION));
+        }
+        let (particle_size, params_size) = (read_u32(reader)?, read_u32(reader)?);
+        if particle_size as usize != size_of::<Particle>() || params_size as usize != size_of::<SimParams>() {
+            return Err(anyhow!("Checkpoint particle/parameter sizes {}/{} do not match this build's {}/{}.",
+                particle_size, params_size, size_of::<Particle>(), size_of::<SimParams>()));
+        }
+        let kernel = match read_u32(reader)? {
+            0 => KernelKind::Poly6Spiky,
+            1 => KernelKind::CubicSpline,
+            2 => KernelKind::WendlandC2,
+            k => return Err(anyhow!("Unknown kernel kind {} in checkpoint.", k)),
+        };
+        let count = read_u32(reader)? as usize;
+        let mut time = [0u8; 8];
+        reader.read_exact(&mut time)?;
+        let mut params = [0u8; size_of::<SimParams>()];
+        reader.read_exact(&mut params)?;
+        // the boundary kinds are an enum, which any other word would make an invalid value of
+        let kinds = std::mem::offset_of!(SimParams, boundary_kinds);
+        for word in params[kinds..kinds + size_of::<[BoundaryKind; 4]>()].chunks_exact(4) {
+            let kind = u32::from_le_bytes(word.try_into().unwrap());
+            if kind != BoundaryKind::Wall as u32 && kind != BoundaryKind::Periodic as u32 {
+                return Err(anyhow!("Unknown boundary kind {} in checkpoint.", kind));
+            }
+        }
+        let params = unsafe { std::ptr::read_unaligned(params.as_ptr().cast::<SimParams>()) };
+        // read only what the file holds before trusting `count` with an allocation
+        let size = count.checked_mul(size_of::<Particle>()).ok_or_else(|| anyhow!("Checkpoint particle count {} is too large.", count))?;
+        let mut bytes = Vec::new();
+        reader.take(size as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != size {
+            return Err(anyhow!("Checkpoint is truncated: it holds {} of {} particles' bytes.", bytes.len(), size));
+        }
+        let mut particles = vec![Particle::default(); count];
+        as_bytes_mut(&mut particles).copy_from_slice(&bytes);
+        Ok(Self { params, kernel, time: f64::from_le_bytes(time), particles })
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Views plain `repr(C)` values as raw bytes. Only `Particle`, which holds nothing but `f32`s and `u32`s,
+/// is written through the mutable view; the parameters' boundary kinds are checked before they are read.
+fn as_bytes<T: Copy>(values: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(values.as_ptr().cast(), std::mem::size_of_val(values)) }
+}
+
+fn as_bytes_mut<T: Copy>(values: &mut [T]) -> &mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(values.as_mut_ptr().cast(), std::mem::size_of_val(values)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra_glm as glm;
+    use super::*;
+
+    fn checkpoint() -> Checkpoint {
+        let mut params = SimParams::new();
+        params.boundary_kinds[0] = BoundaryKind::Periodic;
+        let particles = (0..5).map(|i| {
+            let mut p = Particle::new(glm::vec3(i as f32, 0.5, -1.0), 0.02);
+            p.vel = glm::vec3(0.0, -(i as f32), 0.25);
+            p.id = 10 + i;
+            p
+        }).collect();
+        Checkpoint { params, kernel: KernelKind::WendlandC2, time: 1.25, particles }
+    }
+
+    fn written(checkpoint: &Checkpoint) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        checkpoint.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let saved = checkpoint();
+        let loaded = Checkpoint::read(&mut written(&saved).as_slice()).unwrap();
+        assert_eq!(loaded.kernel, saved.kernel);
+        assert_eq!(loaded.time, saved.time);
+        assert_eq!(loaded.params.boundary_kinds, saved.params.boundary_kinds);
+        assert_eq!(as_bytes(std::slice::from_ref(&loaded.params)), as_bytes(std::slice::from_ref(&saved.params)));
+        assert_eq!(as_bytes(&loaded.particles), as_bytes(&saved.particles));
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut bytes = written(&checkpoint());
+        bytes[0] = b'X';
+        assert!(Checkpoint::read(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn rejects_other_version() {
+        let mut bytes = written(&checkpoint());
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let error = Checkpoint::read(&mut bytes.as_slice()).unwrap_err();
+        assert!(error.to_string().contains("version"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_other_sizes() {
+        let mut bytes = written(&checkpoint());
+        let at = MAGIC.len() + 4;
+        bytes[at..at + 4].copy_from_slice(&(size_of::<Particle>() as u32 + 4).to_le_bytes());
+        assert!(Checkpoint::read(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated() {
+        let bytes = written(&checkpoint());
+        let error = Checkpoint::read(&mut &bytes[..bytes.len() - 1]).unwrap_err();
+        assert!(error.to_string().contains("truncated"), "{}", error);
+        assert!(Checkpoint::read(&mut &bytes[..20]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_boundary_kind() {
+        let mut bytes = written(&checkpoint());
+        let at = MAGIC.len() + 5 * 4 + 8 + std::mem::offset_of!(SimParams, boundary_kinds);
+        bytes[at..at + 4].copy_from_slice(&7u32.to_le_bytes());
+        let error = Checkpoint::read(&mut bytes.as_slice()).unwrap_err();
+        assert!(error.to_string().contains("boundary kind"), "{}", error);
+    }
+}
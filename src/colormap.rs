@@ -0,0 +1,280 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::{COLORMAP_TEXELS, LEGEND_FRAGMENT_SHADER, LEGEND_RECT, LEGEND_VERTEX_SHADER};
+use crate::reconstruct::FluidRendering;
+use crate::split::{full_viewport, viewport_scissor};
+use crate::utils::{compile_shader, create_shader_module, create_texture, upload_texture};
+
+/// The colormaps a scalar can be drawn through, each a 1D texture running from the scalar's low end to
+/// its high end.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Colormap {
+    #[default]
+    Viridis,
+    Plasma,
+    /// Diverging, blue through grey to red, for scalars with a meaningful middle.
+    Coolwarm,
+    /// The rainbow of old; not perceptually uniform, for comparison with tools that default to it.
+    Jet,
+}
+
+/// A colormap's texture, filtered linearly between its texels and clamped at its ends.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ColormapTexture {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+}
+
+// evenly spaced stops sampled from each map's published table, interpolated linearly into the texels
+const VIRIDIS: [[f32; 3]; 9] = [
+    [0.267, 0.005, 0.329], [0.278, 0.175, 0.483], [0.229, 0.322, 0.546], [0.173, 0.448, 0.557], [0.128, 0.567, 0.551],
+    [0.158, 0.684, 0.502], [0.369, 0.789, 0.383], [0.678, 0.864, 0.190], [0.993, 0.906, 0.144],
+];
+const PLASMA: [[f32; 3]; 9] = [
+    [0.050, 0.030, 0.528], [0.299, 0.009, 0.631], [0.494, 0.012, 0.658], [0.665, 0.139, 0.585], [0.798, 0.280, 0.470],
+    [0.898, 0.418, 0.363], [0.973, 0.580, 0.254], [0.993, 0.765, 0.157], [0.940, 0.975, 0.131],
+];
+const COOLWARM: [[f32; 3]; 9] = [
+    [0.230, 0.299, 0.754], [0.384, 0.510, 0.918], [0.553, 0.690, 0.996], [0.722, 0.816, 0.976], [0.867, 0.867, 0.867],
+    [0.961, 0.769, 0.678], [0.957, 0.604, 0.482], [0.871, 0.376, 0.302], [0.706, 0.016, 0.150],
+];
+const JET: [[f32; 3]; 9] = [
+    [0.0, 0.0, 0.5], [0.0, 0.0, 1.0], [0.0, 0.5, 1.0], [0.0, 1.0, 1.0], [0.5, 1.0, 0.5],
+    [1.0, 1.0, 0.0], [1.0, 0.5, 0.0], [1.0, 0.0, 0.0], [0.5, 0.0, 0.0],
+];
+
+impl Colormap {
+    pub const ALL: [Colormap; 4] = [Colormap::Viridis, Colormap::Plasma, Colormap::Coolwarm, Colormap::Jet];
+
+    /// The colormap after this one in `ALL`, wrapping around.
+    pub fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+
+    /// Where this colormap is in `ALL`, and so which of `AppData::colormap_descriptor_sets` binds it.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    fn stops(self) -> &'static [[f32; 3]] {
+        match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Plasma => &PLASMA,
+            Colormap::Coolwarm => &COOLWARM,
+            Colormap::Jet => &JET,
+        }
+    }
+
+    /// `count` RGBA texels running through the colormap, evenly spaced from its low end to its high end.
+    pub fn texels(self, count: u32) -> Vec<[u8; 4]> {
+        let stops = self.stops();
+        let last = (stops.len() - 1) as f32;
+        (0..count).map(|k| {
+            let x = k as f32 / (count - 1).max(1) as f32 * last;
+            let i = (x.floor() as usize).min(stops.len() - 2);
+            let (a, b) = (glm::make_vec3(&stops[i]), glm::make_vec3(&stops[i + 1]));
+            let c = glm::lerp(&a, &b, x - i as f32);
+            let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+            [byte(c.x), byte(c.y), byte(c.z), 255]
+        }).collect()
+    }
+
+    /// Uploads the colormap as a 1D texture of `COLORMAP_TEXELS`, for a shader to sample with a
+    /// coordinate from 0 at the low end to 1 at the high end. Its stops are sRGB, which the texture
+    /// decodes to linear as it is sampled.
+    pub unsafe fn texture(self, instance: &Instance, device: &Device, data: &AppData) -> Result<ColormapTexture> {
+        let format = vk::Format::R8G8B8A8_SRGB;
+        let dims = [COLORMAP_TEXELS, 1, 1];
+        let (image, memory, view) = create_texture(instance, device, data, vk::ImageViewType::_1D, dims, format, 1,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)?;
+        upload_texture(instance, device, data, image, format, dims, 1, 1, &self.texels(COLORMAP_TEXELS))?;
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR).min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+        let sampler = device.create_sampler(&sampler_info, None)?;
+        Ok(ColormapTexture { image, memory, view, sampler })
+    }
+}
+
+impl ColormapTexture {
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_sampler(self.sampler, None);
+        device.destroy_image_view(self.view, None);
+        device.destroy_image(self.image, None);
+        device.free_memory(self.memory, None);
+    }
+}
+
+/// Creates the layout of a colormap's set, a single sampler at binding 0 for the fragment shader,
+/// which the pipelines drawing through a colormap are made with.
+pub unsafe fn create_colormap_set_layout(device: &Device, data: &mut AppData) -> Result<()> {
+    let binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+    let bindings = &[binding];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+    data.colormap_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    Ok(())
+}
+
+/// Uploads every colormap of `Colormap::ALL` and a set binding each, so switching between them only
+/// binds another set; call after `create_command_pool`.
+pub unsafe fn create_colormaps(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let count = Colormap::ALL.len() as u32;
+    let size = vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).descriptor_count(count);
+    let pool_sizes = &[size];
+    let info = vk::DescriptorPoolCreateInfo::builder().pool_sizes(pool_sizes).max_sets(count);
+    data.colormap_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    let layouts = vec![data.colormap_set_layout; count as usize];
+    let info = vk::DescriptorSetAllocateInfo::builder().descriptor_pool(data.colormap_descriptor_pool).set_layouts(&layouts);
+    data.colormap_descriptor_sets = device.allocate_descriptor_sets(&info)?;
+    for colormap in Colormap::ALL {
+        let texture = colormap.texture(instance, device, data)?;
+        let image_info = &[vk::DescriptorImageInfo::builder()
+            .sampler(texture.sampler).image_view(texture.view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(data.colormap_descriptor_sets[colormap.index()]).dst_binding(0).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info);
+        device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+        data.colormap_textures.push(texture);
+    }
+    Ok(())
+}
+
+/// Destroys the colormaps' textures and sets, and the layout of the sets.
+pub unsafe fn destroy_colormaps(device: &Device, data: &mut AppData) {
+    device.destroy_descriptor_pool(data.colormap_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(data.colormap_set_layout, None);
+    data.colormap_textures.drain(..).for_each(|t| t.destroy(device));
+}
+
+/// The set binding the colormap scalars are drawn through now.
+pub fn colormap_descriptor_set(data: &AppData) -> vk::DescriptorSet {
+    data.colormap_descriptor_sets[data.colormap.index()]
+}
+
+/// Push constants of the legend, laid out like `LegendConstants` in `legend.vert`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct LegendConstants {
+    /// Corners of the bar in normalized device coordinates, low end of the colormap at the first.
+    rect: [f32; 4],
+}
+
+/// Creates the pipeline that draws the legend: a bar in a corner of the screen running through the
+/// colormap, over everything else in the main render pass.
+pub unsafe fn create_legend_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&LEGEND_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&LEGEND_FRAGMENT_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    // two triangles over the bar, made up from the vertex indices alone
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_STRIP)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(false);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    // drawn last and over whatever the scene left in the corner
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(false).depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::ALWAYS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+
+    let set_layouts = &[data.colormap_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(size_of::<LegendConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.legend_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .dynamic_state(&dynamic_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.legend_pipeline_layout)
+        .render_pass(data.render_pass)
+        .subpass(0);
+    data.legend_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_legend_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.legend_pipeline, None);
+    device.destroy_pipeline_layout(data.legend_pipeline_layout, None);
+}
+
+/// Whether a legend would say anything: it is on, and the particles or the density slice are drawn
+/// through the colormap.
+pub fn legend_visible(data: &AppData) -> bool {
+    let particles = data.fluid_rendering == FluidRendering::Particles && data.particle_coloring.scalar() != 0;
+    let slice = data.density_slice.visible && data.field_volume_grid.is_some();
+    data.legend && (particles || slice)
+}
+
+/// Records the draw of the legend into the main render pass, if `legend_visible`.
+pub unsafe fn record_legend_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer) {
+    if !legend_visible(data) {
+        return;
+    }
+    let constants = LegendConstants { rect: LEGEND_RECT };
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.legend_pipeline);
+    // over the whole window, whichever view was drawn last
+    let viewport = full_viewport(data.swapchain_extent);
+    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+    device.cmd_set_scissor(command_buffer, 0, &[viewport_scissor(&viewport)]);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.legend_pipeline_layout, 0, &[colormap_descriptor_set(data)], &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const LegendConstants).cast::<u8>(),
+        size_of::<LegendConstants>());
+    device.cmd_push_constants(command_buffer, data.legend_pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, bytes);
+    device.cmd_draw(command_buffer, 4, 1, 0, 0);
+}
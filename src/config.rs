@@ -1,8 +1,107 @@
 use vulkanalia::{prelude::v1_0::*};
 
+use crate::reconstruct::FluidRendering;
+
 /// Whether the validation layers should be enabled.
 pub const VALIDATION_ENABLED: bool = cfg!(debug_assertions);
 
+/// Whether the validation layers should also check for synchronization hazards, which is slow, so it is
+/// opt-in through `SPH_SYNC_VALIDATION` on top of a validated build.
+pub fn sync_validation_enabled() -> bool {
+    VALIDATION_ENABLED && std::env::var_os("SPH_SYNC_VALIDATION").is_some()
+}
+
+/// Whether to replace the scene with a small random particle set and check the GPU neighbor grid
+/// against the CPU after every frame, opted into through `SPH_VERIFY_GRID`.
+pub fn grid_verification_enabled() -> bool {
+    std::env::var_os("SPH_VERIFY_GRID").is_some()
+}
+
+/// Whether to check at startup that two fixed-seed runs of the CPU solver agree bit for bit, opted into
+/// through `SPH_VERIFY_DETERMINISM`.
+pub fn determinism_verification_enabled() -> bool {
+    std::env::var_os("SPH_VERIFY_DETERMINISM").is_some()
+}
+
+/// Where to write the scene the app starts in, every default filled in, if `SPH_WRITE_SCENE` is set.
+pub fn scene_dump_path() -> Option<String> {
+    std::env::var("SPH_WRITE_SCENE").ok()
+}
+
+/// The image the meshes with texture coordinates are drawn with, if `SPH_MESH_TEXTURE` is set.
+pub fn mesh_texture_path() -> Option<String> {
+    std::env::var("SPH_MESH_TEXTURE").ok()
+}
+
+/// The sky drawn behind the scene, if `SPH_SKYBOX` is set: a directory of six faces named px, nx, py,
+/// ny, pz and nz, or an equirectangular panorama.
+pub fn skybox_path() -> Option<String> {
+    std::env::var("SPH_SKYBOX").ok()
+}
+
+/// Where to write a CSV row of statistics per stepped frame, if `SPH_STATS` is set.
+pub fn stats_path() -> Option<String> {
+    std::env::var("SPH_STATS").ok()
+}
+
+/// Stepped frames per fluid surface mesh the OBJ export writes, set through `SPH_SURFACE_EXPORT_EVERY`;
+/// every frame if it is not set or not a number.
+pub fn surface_export_interval() -> u32 {
+    std::env::var("SPH_SURFACE_EXPORT_EVERY").ok().and_then(|v| v.parse().ok()).unwrap_or(1)
+}
+
+/// Whether to time the CPU solver on a `CPU_BENCH_SIZE` dam break at startup, opted into through
+/// `SPH_CPU_BENCH`; compare with the GPU step time the app logs.
+pub fn cpu_benchmark_enabled() -> bool {
+    std::env::var_os("SPH_CPU_BENCH").is_some()
+}
+
+/// Whether to time recording a frame's commands with `RECORD_BENCH_OBJECTS` cubes added at startup,
+/// inline and in secondary command buffers, opted into through `SPH_RECORD_BENCH`.
+pub fn recording_benchmark_enabled() -> bool {
+    std::env::var_os("SPH_RECORD_BENCH").is_some()
+}
+
+/// Whether to time remaking every pipeline at startup, through an empty pipeline cache and then the
+/// warm one, opted into through `SPH_PIPELINE_BENCH`.
+pub fn pipeline_benchmark_enabled() -> bool {
+    std::env::var_os("SPH_PIPELINE_BENCH").is_some()
+}
+
+/// Whether shaders are looked up in and added to `SHADER_CACHE_DIR`, unless `NO_SHADER_CACHE_FLAG` is
+/// among the arguments.
+pub fn shader_cache_enabled() -> bool {
+    !std::env::args().skip(1).any(|a| a == NO_SHADER_CACHE_FLAG)
+}
+
+/// Seconds between logs of the fluid diagnostics, set through `SPH_DIAGNOSTICS` (one second if it is
+/// not a number); `None` keeps them quiet.
+pub fn diagnostics_log_interval() -> Option<f32> {
+    std::env::var("SPH_DIAGNOSTICS").ok().map(|v| v.parse().unwrap_or(1.0))
+}
+
+/// The most particles allowed over the compression threshold, as a fraction of them, if
+/// `SPH_VERIFY_COMPRESSION` turns on the compression check (`COMPRESSION_LIMIT` if it is not a number).
+pub fn compression_limit() -> Option<f32> {
+    std::env::var("SPH_VERIFY_COMPRESSION").ok().map(|v| v.parse().unwrap_or(COMPRESSION_LIMIT))
+}
+
+/// Whether to check at startup that the free-surface correction brings a still column's pressures closer
+/// to hydrostatic, opted into through `SPH_VERIFY_HYDROSTATIC`.
+pub fn hydrostatic_verification_enabled() -> bool {
+    std::env::var_os("SPH_VERIFY_HYDROSTATIC").is_some()
+}
+
+/// Whether to check the GPU prefix sum against the CPU at startup, opted into through `SPH_VERIFY_SCAN`.
+pub fn scan_verification_enabled() -> bool {
+    std::env::var_os("SPH_VERIFY_SCAN").is_some()
+}
+
+/// Whether to time the candidate workgroup sizes at startup and keep the fastest, opted into through `SPH_AUTOTUNE`.
+pub fn autotune_enabled() -> bool {
+    std::env::var_os("SPH_AUTOTUNE").is_some()
+}
+
 /// The name of the validation layers & extensions.
 pub const VALIDATION_LAYER: vk::ExtensionName = vk::ExtensionName::from_bytes(b"VK_LAYER_KHRONOS_validation");
 
@@ -17,3 +116,404 @@ pub const DEVICE_EXTENSIONS: &[vk::ExtensionName] = &[vk::KHR_SWAPCHAIN_EXTENSIO
 
 /// Max frames in flight to be presented.
 pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+
+/// Default local size of the SPH compute shaders, which is a specialization constant; `SPH_AUTOTUNE`
+/// picks one for the device instead. Must be a power of two for the reductions.
+pub const WORKGROUP_SIZE: u32 = 256;
+/// Workgroup sizes the autotune tries.
+pub const WORKGROUP_SIZE_CANDIDATES: [u32; 4] = [64, 128, 256, 512];
+
+/// Particles along x, y and z of the default dam break column, which fills a unit tank.
+pub const DAM_BREAK_SIZE: [u32; 3] = [9, 18, 36];
+
+/// Particles along x, y and z of the dam break the CPU solver is timed on, about 50k in all.
+pub const CPU_BENCH_SIZE: [u32; 3] = [24, 48, 44];
+/// Solver steps the CPU timing averages over.
+pub const CPU_BENCH_STEPS: u32 = 10;
+
+/// Cubes the recording timing adds, and the frames it averages over.
+pub const RECORD_BENCH_OBJECTS: usize = 500;
+pub const RECORD_BENCH_FRAMES: u32 = 50;
+
+/// Where the pipeline cache is kept between runs, in the working directory.
+pub const PIPELINE_CACHE_PATH: &str = "pipeline_cache.bin";
+/// Where shaders are kept compiled between runs, by a hash of their sources and options.
+pub const SHADER_CACHE_DIR: &str = "shader_cache";
+/// Skips the shader cache both ways, compiling every shader from source.
+pub const NO_SHADER_CACHE_FLAG: &str = "--no-shader-cache";
+
+/// The directory the shader hot reload watches, and the milliseconds between its looks at it.
+pub const SHADER_DIR: &str = "shaders";
+pub const SHADER_POLL_MS: u64 = 250;
+
+/// Particles along x, y and z of the dam break `--bench` times, about 55k in all, and the solver steps
+/// it lets warm up before timing the rest. One step per frame at `MAX_TIMESTEP`.
+pub const BENCH_SIZE: [u32; 3] = [24, 48, 48];
+pub const BENCH_WARMUP_STEPS: u32 = 50;
+/// Solver steps `--bench` times unless the command line gives a count.
+pub const BENCH_STEPS: u32 = 1000;
+
+/// Particle buffer capacity; emitters append into the headroom above the initial particles.
+pub const MAX_PARTICLES: u32 = 1 << 16;
+
+/// Upper bound on particles emitted in a single frame (sizes the emission staging buffer).
+pub const MAX_EMITTED_PER_FRAME: usize = 4096;
+
+/// Default voxel resolution along the longest side of an obstacle's signed distance field.
+pub const SDF_RESOLUTION: u32 = 64;
+
+/// Upper bound on rigid bodies coupled to the fluid (sizes the per-frame body buffers).
+pub const MAX_BODIES: usize = 16;
+
+/// Number of fluid phases the simulation parameters have room for.
+pub const MAX_PHASES: usize = 4;
+
+/// Upper bound on active sink boxes per frame (sizes the sink staging buffer).
+pub const MAX_SINKS: usize = 16;
+
+/// Upper bound on active inflows and outflows together (sizes the per-frame opening buffer).
+pub const MAX_OPENINGS: usize = 8;
+
+/// SPH solver compute shaders.
+pub const DENSITY_SHADER: &str = "shaders/density.comp";
+pub const FORCE_SHADER: &str = "shaders/force.comp";
+pub const SPEED_REDUCE_SHADER: &str = "shaders/reduce_speed.comp";
+pub const MAX_REDUCE_SHADER: &str = "shaders/reduce_max.comp";
+pub const INTEGRATE_SHADER: &str = "shaders/integrate.comp";
+pub const APPEND_SHADER: &str = "shaders/append.comp";
+pub const MARK_SHADER: &str = "shaders/mark.comp";
+pub const SCAN_BLOCKS_SHADER: &str = "shaders/scan_blocks.comp";
+pub const SCAN_BLOCK_SUMS_SHADER: &str = "shaders/scan_block_sums.comp";
+pub const SCATTER_SHADER: &str = "shaders/scatter.comp";
+pub const VORTICITY_SHADER: &str = "shaders/vorticity.comp";
+pub const VORTICITY_FORCE_SHADER: &str = "shaders/vorticity_force.comp";
+pub const DIVERGENCE_SHADER: &str = "shaders/divergence.comp";
+pub const SURFACE_SHADER: &str = "shaders/surface.comp";
+pub const REFINE_PAIR_SHADER: &str = "shaders/refine_pair.comp";
+pub const REFINE_SHADER: &str = "shaders/refine.comp";
+pub const DENSITY_HISTOGRAM_SHADER: &str = "shaders/density_histogram.comp";
+pub const SORT_CELLS_SHADER: &str = "shaders/sort_cells.comp";
+pub const ADD_BLOCK_OFFSETS_SHADER: &str = "shaders/add_block_offsets.comp";
+pub const TUNE_SHADER: &str = "shaders/tune.comp";
+pub const BOUNDARY_UPDATE_SHADER: &str = "shaders/boundary_update.comp";
+pub const BOUNDARY_FORCE_SHADER: &str = "shaders/boundary_force.comp";
+pub const BODY_REDUCE_SHADER: &str = "shaders/body_reduce.comp";
+pub const MOUSE_FORCE_SHADER: &str = "shaders/mouse_force.comp";
+pub const PICK_SHADER: &str = "shaders/pick.comp";
+pub const NEIGHBOR_SHADER: &str = "shaders/neighbors.comp";
+pub const SPLAT_SHADER: &str = "shaders/splat.comp";
+pub const FOAM_NORMALS_SHADER: &str = "shaders/foam_normals.comp";
+pub const FOAM_SEED_SHADER: &str = "shaders/foam_seed.comp";
+pub const FOAM_UPDATE_SHADER: &str = "shaders/foam_update.comp";
+pub const FOAM_VERTEX_SHADER: &str = "shaders/foam.vert";
+pub const FOAM_FRAGMENT_SHADER: &str = "shaders/foam.frag";
+
+/// Screen-space fluid shaders: the particle depth, a full-screen triangle, the depth smoothing and the
+/// composite over the scene.
+pub const FLUID_DEPTH_VERTEX_SHADER: &str = "shaders/fluid_depth.vert";
+pub const FLUID_DEPTH_FRAGMENT_SHADER: &str = "shaders/fluid_depth.frag";
+pub const FULLSCREEN_VERTEX_SHADER: &str = "shaders/fullscreen.vert";
+pub const FLUID_SMOOTH_SHADER: &str = "shaders/fluid_smooth.frag";
+pub const FLUID_THICKNESS_SHADER: &str = "shaders/fluid_thickness.frag";
+pub const RAYMARCH_SHADER: &str = "shaders/raymarch.frag";
+pub const FLUID_COMPOSITE_SHADER: &str = "shaders/fluid_composite.frag";
+
+/// Near and far planes of the camera's projection, in meters.
+pub const NEAR_PLANE: f32 = 0.1;
+pub const FAR_PLANE: f32 = 10.0;
+/// Samples per pixel the main pass asks for at startup, 1 for no multisampling; the device may draw fewer.
+pub const MSAA_SAMPLES: u32 = 4;
+/// Frames of the turntable F11 starts and the yaw it turns the camera each, a full turn in all.
+pub const TURNTABLE_FRAMES: u32 = 720;
+pub const TURNTABLE_DEGREES_PER_FRAME: f32 = 360.0 / TURNTABLE_FRAMES as f32;
+
+/// Point-sprite particle shaders: the far one for the far particles of the level of detail, the depth
+/// one for the depth pre-pass.
+pub const PARTICLE_VERTEX_SHADER: &str = "shaders/particle.vert";
+pub const PARTICLE_FRAGMENT_SHADER: &str = "shaders/particle.frag";
+pub const PARTICLE_FAR_FRAGMENT_SHADER: &str = "shaders/particle_far.frag";
+pub const PARTICLE_DEPTH_FRAGMENT_SHADER: &str = "shaders/particle_depth.frag";
+/// Color of the particles the surface detection flags, while particles are colored by `ParticleColoring::Surface`.
+pub const SURFACE_TINT: [f32; 3] = [0.95, 0.95, 1.0];
+/// Color of the particle picked with the mouse.
+pub const SELECTED_COLOR: [f32; 3] = [1.0, 0.8, 0.1];
+/// Color of the selected particle's neighbors while they are highlighted, and the color of the
+/// smoothing radius sphere around it and the segments each of its three circles is drawn with.
+pub const NEIGHBOR_COLOR: [f32; 3] = [0.1, 0.9, 0.9];
+pub const SMOOTHING_SPHERE_COLOR: [f32; 3] = [0.1, 0.9, 0.9];
+pub const SMOOTHING_SPHERE_SEGMENTS: u32 = 48;
+/// Ground plane: how far below the domain's bottom it lies by default, so the domain box's edges stay
+/// in front of it, the spacing of its grid's minor lines and how many make a major one, the distance
+/// the grid fades out over, its colors, and shaders.
+pub const GROUND_CLEARANCE: f32 = 0.002;
+pub const GROUND_SPACING: f32 = 0.1;
+pub const GROUND_MAJOR_EVERY: u32 = 10;
+pub const GROUND_FADE_DISTANCE: f32 = 12.0;
+pub const GROUND_COLOR: [f32; 3] = [0.12, 0.12, 0.13];
+pub const GROUND_LINE_COLOR: [f32; 3] = [0.22, 0.22, 0.24];
+pub const GROUND_MAJOR_COLOR: [f32; 3] = [0.38, 0.38, 0.42];
+pub const GROUND_VERTEX_SHADER: &str = "shaders/ground.vert";
+pub const GROUND_FRAGMENT_SHADER: &str = "shaders/ground.frag";
+/// Skybox: the gradient's colors at the zenith, along the horizon and below it, drawn without
+/// `SPH_SKYBOX`, the texels across each face of its cube, and shaders.
+pub const SKY_ZENITH_COLOR: [f32; 3] = [0.27, 0.47, 0.75];
+pub const SKY_HORIZON_COLOR: [f32; 3] = [0.78, 0.84, 0.9];
+pub const SKY_GROUND_COLOR: [f32; 3] = [0.2, 0.2, 0.22];
+pub const SKY_GRADIENT_SIZE: u32 = 64;
+pub const SKYBOX_VERTEX_SHADER: &str = "shaders/skybox.vert";
+pub const SKYBOX_FRAGMENT_SHADER: &str = "shaders/skybox.frag";
+/// Shadow mapping: texels across the shadow map, the rasterizer's constant and slope depth bias drawing
+/// it, the light-space depth bias and the normal offset in meters of the lookups, and the shader
+/// drawing it.
+pub const SHADOW_RESOLUTION: u32 = 2048;
+pub const SHADOW_DEPTH_BIAS_CONSTANT: f32 = 1.25;
+pub const SHADOW_DEPTH_BIAS_SLOPE: f32 = 1.75;
+pub const SHADOW_BIAS: f32 = 0.002;
+pub const SHADOW_NORMAL_OFFSET: f32 = 0.01;
+pub const SHADOW_VERTEX_SHADER: &str = "shaders/shadow.vert";
+/// Point-light shadows: texels across each face of the cube shadow map, meters from the light its
+/// casters are drawn from and to, the bias of its lookups in meters, and shaders drawing it.
+pub const POINT_SHADOW_RESOLUTION: u32 = 1024;
+pub const POINT_SHADOW_NEAR: f32 = 0.05;
+pub const POINT_SHADOW_FAR: f32 = 10.0;
+pub const POINT_SHADOW_BIAS: f32 = 0.02;
+pub const POINT_SHADOW_VERTEX_SHADER: &str = "shaders/point_shadow.vert";
+pub const POINT_SHADOW_FRAGMENT_SHADER: &str = "shaders/point_shadow.frag";
+/// Normal maps the meshes' materials can name between them, the length of `normalMaps` in `shader.frag`.
+pub const NORMAL_MAP_SLOTS: usize = 16;
+/// Lights: how many the scene's uniform buffer has room for, the length of `lights` in `shader.frag`,
+/// and how far back along its direction a directional light is placed for its shadows and the shaders
+/// lit by a single light.
+pub const MAX_LIGHTS: usize = 8;
+pub const DIRECTIONAL_LIGHT_DISTANCE: f32 = 100.0;
+/// Axis gizmo: pixels its viewport is wide and away from the window's bottom-left corner, its line
+/// width, its arrows' length as a fraction of the viewport's half width, how near a click must come to
+/// an arrow's tip to snap the camera to it, the X, Y and Z arrows' colors, and shaders.
+pub const GIZMO_SIZE: f32 = 96.0;
+pub const GIZMO_MARGIN: f32 = 12.0;
+pub const GIZMO_WIDTH: f32 = 2.0;
+pub const GIZMO_LENGTH: f32 = 0.8;
+pub const GIZMO_PICK_RADIUS: f32 = 10.0;
+pub const GIZMO_COLORS: [[f32; 3]; 3] = [[0.9, 0.2, 0.2], [0.3, 0.85, 0.3], [0.25, 0.45, 1.0]];
+pub const GIZMO_VERTEX_SHADER: &str = "shaders/gizmo.vert";
+pub const GIZMO_FRAGMENT_SHADER: &str = "shaders/gizmo.frag";
+/// Debug view: the fraction of the window it takes, pixels away from the bottom-right corner, the eye
+/// distances shown black and white, the fluid thickness shown white, and its fragment shader.
+pub const DEBUG_VIEW_SCALE: f32 = 0.3;
+pub const DEBUG_VIEW_MARGIN: f32 = 12.0;
+pub const DEBUG_VIEW_DEPTH_RANGE: [f32; 2] = [0.1, 4.0];
+pub const DEBUG_VIEW_THICKNESS: f32 = 0.5;
+pub const DEBUG_VIEW_SHADER: &str = "shaders/debug_view.frag";
+/// Tonemapping: what the HDR scene's colors are multiplied by before the curve, and its fragment shader.
+pub const TONEMAP_EXPOSURE: f32 = 1.0;
+pub const TONEMAP_SHADER: &str = "shaders/tonemap.frag";
+/// Bloom: the HDR brightness it picks out light above, how strongly its blur is added to the scene, the
+/// half-sized levels the blur goes down through and the most of them made, and its fragment shader.
+pub const BLOOM_THRESHOLD: f32 = 1.0;
+pub const BLOOM_INTENSITY: f32 = 0.08;
+pub const BLOOM_MIPS: u32 = 5;
+pub const BLOOM_MAX_MIPS: u32 = 8;
+pub const BLOOM_SHADER: &str = "shaders/bloom.frag";
+/// Ambient occlusion: how far around each point it looks for occluders, how many of its kernel's samples
+/// it takes and the most the kernel holds, and how strongly the occlusion darkens the ambient light.
+pub const SSAO_RADIUS: f32 = 0.1;
+pub const SSAO_SAMPLES: u32 = 16;
+pub const SSAO_MAX_SAMPLES: usize = 64;
+pub const SSAO_INTENSITY: f32 = 1.5;
+/// The pass drawing the view-space normals and depth, the occlusion pass and its blur.
+pub const SSAO_NORMAL_SHADER: &str = "shaders/ssao_normals.frag";
+pub const SSAO_SHADER: &str = "shaders/ssao.frag";
+pub const SSAO_BLUR_SHADER: &str = "shaders/ssao_blur.frag";
+/// Order-independent transparency: the distance, in meters, over which the weights of the transparent
+/// fragments fall off, and the shader compositing them over the opaque scene.
+pub const OIT_DEPTH_SCALE: f32 = 1.0;
+pub const OIT_COMPOSITE_SHADER: &str = "shaders/oit_composite.frag";
+/// How the right half of the split screen draws the fluid unless told otherwise.
+pub const SPLIT_RENDERING: FluidRendering = FluidRendering::Surface;
+/// Colors of the wireframe boxes around the domain and the emitter, sink, inflow and outflow regions,
+/// and their shaders.
+pub const DOMAIN_WIRE_COLOR: [f32; 3] = [0.8, 0.8, 0.8];
+pub const EMITTER_WIRE_COLOR: [f32; 3] = [0.2, 0.8, 0.3];
+pub const SINK_WIRE_COLOR: [f32; 3] = [0.9, 0.25, 0.2];
+pub const INFLOW_WIRE_COLOR: [f32; 3] = [0.2, 0.6, 1.0];
+pub const OUTFLOW_WIRE_COLOR: [f32; 3] = [0.9, 0.5, 0.9];
+pub const WIRE_VERTEX_SHADER: &str = "shaders/wire.vert";
+pub const WIRE_FRAGMENT_SHADER: &str = "shaders/wire.frag";
+/// Fragment shader of the objects drawn unlit.
+pub const UNLIT_FRAGMENT_SHADER: &str = "shaders/unlit.frag";
+/// Mesh draws a frame holds the transform and material of, each in a block of the frame's object buffer.
+pub const OBJECT_BLOCKS: u32 = 1024;
+/// Width in pixels the objects' edges are drawn at in wireframe, where the device draws wide lines.
+pub const MESH_WIREFRAME_WIDTH: f32 = 1.0;
+/// Normal lines: meters each is long, and every how many vertices of a mesh one is drawn from.
+pub const NORMAL_LINE_LENGTH: f32 = 0.03;
+pub const NORMAL_LINE_STRIDE: u32 = 1;
+/// Velocity glyphs: seconds of travel each segment spans, glyphs drawn at most before every Nth particle
+/// is skipped to stay under it, line width in pixels where the device draws wide lines, and shaders.
+pub const GLYPH_SCALE: f32 = 0.05;
+pub const GLYPH_MAX_COUNT: u32 = 20_000;
+pub const GLYPH_WIDTH: f32 = 1.5;
+pub const GLYPH_VERTEX_SHADER: &str = "shaders/glyph.vert";
+pub const GLYPH_FRAGMENT_SHADER: &str = "shaders/glyph.frag";
+/// Particle trails: positions each keeps, every how many particles by id get one, and frames stepped
+/// between positions; their color with the opacity at the head, and shaders.
+pub const TRAIL_LENGTH: u32 = 16;
+pub const TRAIL_STRIDE: u32 = 8;
+pub const TRAIL_INTERVAL: u32 = 2;
+pub const TRAIL_COLOR: [f32; 4] = [0.85, 0.9, 1.0, 0.8];
+pub const TRAIL_SHADER: &str = "shaders/trail.comp";
+pub const TRAIL_VERTEX_SHADER: &str = "shaders/trail.vert";
+pub const TRAIL_FRAGMENT_SHADER: &str = "shaders/trail.frag";
+/// Density slice: where the plane starts across the field's grid, the step the keys move it by, the
+/// field values at the ends of the colormap, its opacity, and shaders.
+pub const SLICE_OFFSET: f32 = 0.5;
+pub const SLICE_STEP: f32 = 1.0 / 32.0;
+pub const SLICE_RANGE: [f32; 2] = [0.0, 1.0];
+pub const SLICE_OPACITY: f32 = 0.85;
+pub const SLICE_VERTEX_SHADER: &str = "shaders/slice.vert";
+pub const SLICE_FRAGMENT_SHADER: &str = "shaders/slice.frag";
+/// Grid occupancy view: occupied cells drawn at most, particles in a cell at the top of the colormap,
+/// the cubes' opacity, and shaders.
+pub const OCCUPANCY_MAX_CELLS: u32 = 32_768;
+pub const OCCUPANCY_FULL: u32 = 16;
+pub const OCCUPANCY_OPACITY: f32 = 0.15;
+pub const OCCUPANCY_SHADER: &str = "shaders/occupancy.comp";
+pub const OCCUPANCY_VERTEX_SHADER: &str = "shaders/occupancy.vert";
+pub const OCCUPANCY_FRAGMENT_SHADER: &str = "shaders/occupancy.frag";
+/// Translucent particles: the opacity of the densest, the density they reach it at, the depth buckets
+/// they are sorted into, and the sort's shaders.
+pub const TRANSLUCENT_OPACITY: f32 = 0.35;
+pub const TRANSLUCENT_FULL_DENSITY: f32 = 1000.0;
+pub const DEPTH_SORT_BUCKETS: u32 = 16_384;
+pub const DEPTH_KEYS_SHADER: &str = "shaders/depth_keys.comp";
+pub const DEPTH_SCATTER_SHADER: &str = "shaders/depth_scatter.comp";
+/// Default meters past the camera's frustum a culled particle's sphere may lie and still be drawn, and
+/// the cull pass.
+pub const CULL_MARGIN: f32 = 0.01;
+pub const CULL_SHADER: &str = "shaders/cull.comp";
+/// Pass culling the objects' indirect draws and compacting those left.
+pub const MESH_CULL_SHADER: &str = "shaders/mesh_cull.comp";
+/// Default particle level of detail: the view depth (m) past which particles are far, and the fraction
+/// of the far ones drawn.
+pub const LOD_DISTANCE: f32 = 3.0;
+pub const LOD_KEEP: f32 = 0.25;
+/// Texels each colormap's stops are interpolated across.
+pub const COLORMAP_TEXELS: u32 = 256;
+/// Corners of the colormap's legend bar in normalized device coordinates (y down), its low end at the
+/// first, and its shaders.
+pub const LEGEND_RECT: [f32; 4] = [0.55, 0.86, 0.95, 0.92];
+pub const LEGEND_VERTEX_SHADER: &str = "shaders/legend.vert";
+pub const LEGEND_FRAGMENT_SHADER: &str = "shaders/legend.frag";
+/// Fraction of the way the auto-ranged coloring range moves towards the particles' range each frame.
+pub const COLOR_RANGE_EASING: f32 = 0.05;
+
+/// CFL safety factor and clamps for the adaptive time step (seconds).
+pub const CFL_SAFETY_FACTOR: f32 = 0.4;
+pub const MIN_TIMESTEP: f32 = 1e-4;
+pub const MAX_TIMESTEP: f32 = 4e-3;
+
+/// Most solver steps a realtime frame takes to keep up with the wall clock.
+pub const MAX_SUBSTEPS: u32 = 4;
+
+/// Vorticity confinement strength (m/s) the runtime toggle switches to when the scene sets none.
+pub const VORTICITY_EPSILON: f32 = 0.05;
+
+/// Amplitude (m) and frequency (Hz) of the shake along x the runtime toggle starts when the scene sets
+/// no container motion.
+pub const SHAKE_AMPLITUDE: f32 = 0.03;
+pub const SHAKE_FREQUENCY: f32 = 1.0;
+
+/// Acceleration (m/s^2) the mouse force tool gives the particles on the cursor ray, and how far from the
+/// ray (m) it reaches.
+pub const MOUSE_FORCE_STRENGTH: f32 = 60.0;
+pub const MOUSE_FORCE_RADIUS: f32 = 0.08;
+
+/// How far from the cursor, in pixels, a click picks a particle.
+pub const PICK_RADIUS: f32 = 12.0;
+
+/// Grid nodes along the longest side of the surface reconstruction's grid, and the volume fraction its
+/// surface passes through.
+pub const SURFACE_RESOLUTION: u32 = 64;
+pub const SURFACE_ISO: f32 = 0.5;
+/// Default anisotropic kernel settings of the surface reconstruction: how far kernel centers move
+/// towards their neighbors, the longest axis a kernel may have per its shortest, and fewer neighbors
+/// than a particle in a one-layer sheet at the default spacing and `h` has, so only spray stays round.
+pub const ANISOTROPY_SMOOTHING: f32 = 0.9;
+pub const ANISOTROPY_MAX_STRETCH: f32 = 4.0;
+pub const ANISOTROPY_MIN_NEIGHBORS: u32 = 6;
+
+/// Default screen-space fluid rendering: smoothing passes over the particle depth, the world-space
+/// radius of the bilateral filter and the depth difference over which it stops mixing across edges,
+/// and the most pixels the filter may reach however near the fluid comes.
+pub const SCREEN_SPACE_ITERATIONS: u32 = 4;
+pub const SCREEN_SPACE_FILTER_RADIUS: f32 = 0.03;
+pub const SCREEN_SPACE_DEPTH_FALLOFF: f32 = 0.03;
+pub const SCREEN_SPACE_MAX_FILTER_PIXELS: f32 = 12.0;
+/// Light the screen-space fluid absorbs per meter of thickness, red, green and blue, and its index of
+/// refraction.
+pub const SCREEN_SPACE_ABSORPTION: [f32; 3] = [2.0, 0.6, 0.3];
+pub const SCREEN_SPACE_IOR: f32 = 1.33;
+
+/// Default raymarched surface: samples along the longest ray through the reconstruction's grid, and
+/// bisections that settle where the ray crosses the iso level.
+pub const RAYMARCH_STEPS: u32 = 96;
+pub const RAYMARCH_REFINEMENTS: u32 = 5;
+/// Fewest and most samples the step count may be set to.
+pub const RAYMARCH_STEP_RANGE: [u32; 2] = [8, 1024];
+
+/// Foam particles alive at once; seeding past it recycles the oldest. A power of two, so the ring's
+/// head wrapping around keeps the slots in order.
+pub const FOAM_CAPACITY: u32 = 1 << 16;
+/// Default foam seeding: the trapped air potential (m/s), wave crest potential and kinetic energy per
+/// unit mass (J/kg) that start to seed foam and that seed the most, and the foam particles a second a
+/// fluid particle seeds at full potential. A splash closes in at a few m/s over a dozen neighbors.
+pub const FOAM_TRAPPED_AIR: [f32; 2] = [2.0, 10.0];
+pub const FOAM_WAVE_CREST: [f32; 2] = [1.0, 4.0];
+pub const FOAM_ENERGY: [f32; 2] = [0.1, 2.0];
+pub const FOAM_TRAPPED_AIR_RATE: f32 = 200.0;
+pub const FOAM_WAVE_CREST_RATE: f32 = 400.0;
+/// Default longest foam lifetime (s), fluid neighbors below which foam flies as spray, and drawn radius (m),
+/// about a quarter of the particle spacing.
+pub const FOAM_LIFETIME: f32 = 3.0;
+pub const FOAM_SPRAY_NEIGHBORS: u32 = 6;
+pub const FOAM_RADIUS: f32 = 0.006;
+
+/// Recorded frames between the keyframes a replay can seek to.
+pub const REPLAY_KEYFRAME_INTERVAL: u32 = 60;
+
+/// Color field gradient, in units of `1 / h`, past which the free-surface correction treats a particle
+/// as near the surface; a full lattice reads zero inside and climbs over the last layer or two.
+pub const SURFACE_THRESHOLD: f32 = 0.5;
+/// Layers in the still column the hydrostatic check measures.
+pub const HYDROSTATIC_LAYERS: u32 = 16;
+/// Velocity (m/s), steps and tolerance, relative to the rest density and the velocity, of the periodic
+/// advection check; the steps carry the fluid most of the way across the box.
+pub const PERIODIC_CHECK_VELOCITY: [f32; 3] = [0.8, -0.3, 0.5];
+pub const PERIODIC_CHECK_STEPS: u32 = 50;
+pub const PERIODIC_CHECK_TOLERANCE: f32 = 1e-3;
+
+/// Default split and merge criteria of the adaptive resolution: color field gradients, like
+/// `SURFACE_THRESHOLD`, and distances in meters to refining obstacles, about two and four particle spacings.
+pub const REFINE_SPLIT_SURFACE: f32 = 0.5;
+pub const REFINE_MERGE_SURFACE: f32 = 0.2;
+pub const REFINE_SPLIT_DISTANCE: f32 = 0.055;
+pub const REFINE_MERGE_DISTANCE: f32 = 0.11;
+/// Default surface detection thresholds: the color field gradient of `SURFACE_THRESHOLD`, and fewer
+/// neighbors than the 20 or so of a particle inside a lattice at the default spacing and `h`.
+pub const SURFACE_DETECTION_GRADIENT: f32 = 0.5;
+pub const SURFACE_DETECTION_NEIGHBORS: u32 = 15;
+/// Particles the adaptive resolution may split per substep, as headroom past the live count.
+pub const REFINE_MAX_SPLITS: u32 = 4096;
+
+/// Bins of the density histogram over `DENSITY_RATIO_RANGE` times each particle's rest density, the
+/// outer bins taking everything beyond; `density_histogram.comp` hardcodes both.
+pub const DENSITY_BINS: usize = 64;
+pub const DENSITY_RATIO_RANGE: [f32; 2] = [0.5, 1.5];
+
+/// Defaults of the compression check: frames between histograms, how far above rest density a
+/// particle counts as compressed, and the fraction of compressed particles that fails the check.
+pub const COMPRESSION_CHECK_FRAMES: u32 = 30;
+pub const COMPRESSION_THRESHOLD: f32 = 0.05;
+pub const COMPRESSION_LIMIT: f32 = 0.02;
+/// CPU solver steps of the headless dam break the compression check runs at startup.
+pub const COMPRESSION_CPU_STEPS: u32 = 150;
@@ -0,0 +1,159 @@
+use std::mem::size_of;
+use anyhow::{anyhow, Result};
+use rayon::prelude::*;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::kernel::Kernel;
+use crate::scene::Scene;
+use crate::simulation::{Particle, SimParams};
+use crate::solver;
+
+/// How many particles fall in each bin of density over rest density. Laid out like one frame's slot
+/// of the histogram `density_histogram.comp` writes.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DensityHistogram {
+    pub counts: [u32; DENSITY_BINS],
+}
+
+impl Default for DensityHistogram {
+    fn default() -> Self {
+        Self { counts: [0; DENSITY_BINS] }
+    }
+}
+
+impl DensityHistogram {
+    /// The bin of a density `ratio` times the rest density, as the shader picks it.
+    pub fn bin(ratio: f32) -> usize {
+        let [min, max] = DENSITY_RATIO_RANGE;
+        ((ratio - min) / (max - min) * DENSITY_BINS as f32).clamp(0.0, (DENSITY_BINS - 1) as f32) as usize
+    }
+
+    /// The ratio at the lower edge of `bin`.
+    pub fn bin_start(bin: usize) -> f32 {
+        let [min, max] = DENSITY_RATIO_RANGE;
+        min + (max - min) * bin as f32 / DENSITY_BINS as f32
+    }
+
+    /// Bins particles on the CPU, such as the CPU solver's.
+    pub fn from_particles(particles: &[Particle], sim: &SimParams) -> Self {
+        let counts = particles.par_iter().fold(|| [0u32; DENSITY_BINS], |mut counts, p| {
+            counts[Self::bin(p.density / sim.phases[(p.phase as usize).min(MAX_PHASES - 1)].x)] += 1;
+            counts
+        }).reduce(|| [0; DENSITY_BINS], |mut a, b| {
+            a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+            a
+        });
+        Self { counts }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.counts.iter().sum()
+    }
+
+    /// Fraction of the particles whose bin starts at `ratio` times the rest density or above, so the
+    /// ratio is in effect rounded up to a bin edge.
+    pub fn fraction_above(&self, ratio: f32) -> f32 {
+        let first = (0..DENSITY_BINS).find(|&b| Self::bin_start(b) >= ratio - 1e-6).unwrap_or(DENSITY_BINS);
+        self.counts[first..].iter().sum::<u32>() as f32 / self.total().max(1) as f32
+    }
+}
+
+/// Fails the run once too much of the fluid is compressed, which an incompressible solver should not
+/// allow; see `App::set_compression_check`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CompressionCheck {
+    /// Solver frames between histograms.
+    pub interval: u32,
+    /// How far above its rest density, as a fraction of it, a particle counts as compressed.
+    pub threshold: f32,
+    /// The largest fraction of compressed particles that passes.
+    pub limit: f32,
+}
+
+impl CompressionCheck {
+    pub fn with_limit(limit: f32) -> Self {
+        Self { interval: COMPRESSION_CHECK_FRAMES, threshold: COMPRESSION_THRESHOLD, limit }
+    }
+
+    /// The compressed fraction of `histogram`, or an error saying how far it is over the limit.
+    pub fn check(&self, histogram: &DensityHistogram) -> Result<f32> {
+        let fraction = histogram.fraction_above(1.0 + self.threshold);
+        if fraction > self.limit {
+            return Err(anyhow!("{:.2}% of {} particles are over {:.0}% denser than at rest, past the {:.2}% limit.",
+                100.0 * fraction, histogram.total(), 100.0 * self.threshold, 100.0 * self.limit));
+        }
+        Ok(fraction)
+    }
+}
+
+impl Default for CompressionCheck {
+    fn default() -> Self {
+        Self::with_limit(COMPRESSION_LIMIT)
+    }
+}
+
+/// Conservation helpers
+/// Reads back the histogram the binning pass wrote for `frame`'s last step; the caller must have waited
+/// on that frame's fence, and the step must have been recorded with `bin_densities`.
+pub unsafe fn read_density_histogram(device: &Device, data: &AppData, frame: usize) -> Result<DensityHistogram> {
+    let offset = (size_of::<DensityHistogram>() * frame) as u64;
+    let memory = device.map_memory(data.density_histogram_buffer_memory, offset,
+        size_of::<DensityHistogram>() as u64, vk::MemoryMapFlags::empty())?;
+    let histogram = *(memory as *const DensityHistogram);
+    device.unmap_memory(data.density_histogram_buffer_memory);
+    Ok(histogram)
+}
+
+/// Runs `steps` CPU solver steps of an `nx` by `ny` by `nz` dam break at the largest time step, with
+/// no window or GPU, checking the histogram every `check.interval` steps. Returns the largest
+/// compressed fraction seen, or the first failure.
+pub fn check_dam_break([nx, ny, nz]: [u32; 3], steps: u32, check: &CompressionCheck) -> Result<f32> {
+    check_scene(&Scene::dam_break(nx, ny, nz), MAX_TIMESTEP, steps, check)
+}
+
+/// `check_dam_break` for any scene, stepped by `dt`.
+pub fn check_scene(scene: &Scene, dt: f32, steps: u32, check: &CompressionCheck) -> Result<f32> {
+    let mut sim = scene.sim();
+    sim.dt = dt;
+    let kernel = Kernel::new(scene.solver.kernel, sim.h);
+    let mut particles = scene.particles(&sim)?;
+    let mut worst = 0.0f32;
+    for step in 1..=steps {
+        solver::step(&mut particles, &sim, &kernel);
+        if step % check.interval.max(1) == 0 || step == steps {
+            let fraction = check.check(&DensityHistogram::from_particles(&particles, &sim))
+                .map_err(|e| anyhow!("Step {}: {}", step, e))?;
+            worst = worst.max(fraction);
+        }
+    }
+    Ok(worst)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stiff enough that the sound speed is ten times the fastest the collapsing column falls, and
+    /// stepped within the sound speed's CFL limit, as a near-incompressible solver would be.
+    #[test]
+    fn stiff_dam_break_stays_within_compression_limit() {
+        let mut scene = Scene::dam_break(5, 10, 5);
+        scene.solver.stiffness = 1000.0;
+        let check = CompressionCheck { interval: 10, ..CompressionCheck::default() };
+        let worst = check_scene(&scene, 6e-4, 200, &check).unwrap();
+        assert!(worst <= check.limit);
+    }
+
+    /// The demo's own equation of state is soft enough to squash the bottom of the column well past
+    /// the threshold, which the check must catch.
+    #[test]
+    fn default_dam_break_fails_compression_check() {
+        let check = CompressionCheck { interval: 10, ..CompressionCheck::default() };
+        let error = check_dam_break([6, 12, 6], COMPRESSION_CPU_STEPS, &check).unwrap_err();
+        assert!(error.to_string().contains("past the"), "{}", error);
+    }
+}
@@ -0,0 +1,206 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::camera::{projection, scene_model};
+use crate::config::*;
+use crate::simulation::{compute_barrier, create_storage_buffer, solver_constants, transfer_barrier,
+    write_compute_descriptor_sets};
+use crate::utils::{create_compute_pipeline, begin_single_time_commands, end_single_time_commands};
+
+/// Frustum culling of the opaque particles: a pass each frame, stepped or not, appends the index of
+/// every live particle whose drawn sphere reaches into the camera's frustum to an index buffer, and the
+/// particles are drawn through it, as many as it holds. Its buffer takes 8 bytes per particle and is
+/// only made once culling or the level of detail is first turned on. Translucent particles are drawn as
+/// sorted, unculled, and nothing is culled while the window is split, its two cameras seeing different
+/// particles. The objects are culled on the CPU alike, each by its transformed box, before the frame is
+/// recorded.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrustumCulling {
+    /// Meters past the frustum's planes a particle's sphere may lie and still be drawn, for points the
+    /// device draws wider than the sphere at its smallest point size.
+    pub margin: f32,
+}
+
+impl Default for FrustumCulling {
+    fn default() -> Self {
+        Self { margin: CULL_MARGIN }
+    }
+}
+
+/// Level of detail of the opaque particles, through the cull pass: those farther in front of the camera
+/// than `distance` go to a second index list, thinned out to `keep` of them and drawn as flat discs.
+/// Which far particles are kept goes by a hash of their ids, so the same ones stay from frame to frame
+/// wherever compaction moves them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ParticleLod {
+    /// View depth, in meters, past which particles are far.
+    pub distance: f32,
+    /// Fraction of the far particles drawn, 0 to 1.
+    pub keep: f32,
+}
+
+impl Default for ParticleLod {
+    fn default() -> Self {
+        Self { distance: LOD_DISTANCE, keep: LOD_KEEP }
+    }
+}
+
+/// Bytes before the visible indices: the near and the far list's VkDrawIndexedIndirectCommand, each
+/// padded to 32 bytes.
+pub const CULL_HEADER: u64 = 64;
+
+/// Push constants of the cull pass, laid out to match its `Cull` block.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct CullConstants {
+    /// The frustum's planes pushed out by the margin, their normals pointing inward and of unit length;
+    /// those left out of the test hold every point.
+    planes: [glm::Vec4; 6],
+    phase_masses: [f32; MAX_PHASES],
+    /// Radius of a particle of its phase's mass.
+    radius: f32,
+    capacity: u32,
+    /// Distance from the near plane past which particles are far, and the fraction of those kept.
+    lod_depth: f32,
+    lod_keep: f32,
+}
+
+/// The planes of the frustum `view_proj` projects into clip space with a depth of 0 to 1: left,
+/// right, top, bottom, near and far, each as `(n, d)` with `n . p + d >= 0` inside it.
+pub fn frustum_planes(view_proj: &glm::Mat4) -> [glm::Vec4; 6] {
+    let row = |k: usize| view_proj.row(k).transpose();
+    let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+    [w + x, w - x, w + y, w - y, z, w - z].map(|plane| plane / glm::length(&plane.xyz()).max(1e-12))
+}
+
+/// Marks the objects whose boxes lie wholly outside the frustum `data.cull_view` sees, pushed out by the
+/// culling's margin, culled, or none of them without culling or while the window is split. Returns how
+/// many are left to draw.
+pub fn cull_objects(data: &mut AppData) -> u32 {
+    let planes = match data.frustum_culling.filter(|_| data.split_screen.is_none()) {
+        Some(culling) => frustum_planes(&(projection(data.swapchain_extent) * data.cull_view))
+            .map(|plane| plane + glm::vec4(0.0, 0.0, 0.0, culling.margin)),
+        None => [glm::vec4(0.0, 0.0, 0.0, 1.0); 6],
+    };
+    let model = scene_model();
+    for obj in &mut data.objects {
+        obj.culled = !obj.in_frustum(&model, &planes);
+    }
+    data.objects.iter().filter(|obj| !obj.culled).count() as u32
+}
+
+/// Whether the particles are drawn through the cull buffer this frame.
+pub fn culled(data: &AppData) -> bool {
+    (data.frustum_culling.is_some() || data.particle_lod.is_some()) && data.split_screen.is_none()
+        && data.translucency.is_none() && data.cull_buffer != vk::Buffer::null()
+}
+
+pub unsafe fn create_cull_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<CullConstants>() as u32);
+    let set_layouts = &[data.compute_descriptor_set_layout];
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.cull_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let constants = solver_constants(data, false);
+    data.cull_pipeline = create_compute_pipeline(device, data.pipeline_cache, data.cull_pipeline_layout, &CULL_SHADER.to_string(), &constants)?;
+    Ok(())
+}
+
+pub unsafe fn destroy_cull_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.cull_pipeline, None);
+    device.destroy_pipeline_layout(data.cull_pipeline_layout, None);
+}
+
+/// Makes the cull buffer with room for every particle the particle buffers hold, unless the one there
+/// already has it, and binds it to the compute sets; it draws nothing until the first cull. Nothing may
+/// be in flight.
+pub unsafe fn create_cull_buffer(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let capacity = data.particle_capacity.max(1);
+    if data.cull_buffer != vk::Buffer::null() && data.cull_capacity == capacity {
+        return Ok(());
+    }
+    destroy_cull_buffer(device, data);
+    // the two indexed indirect draws, then the near and the far particles' indices
+    let size = CULL_HEADER + 2 * size_of::<u32>() as u64 * capacity as u64;
+    let (buffer, memory) = create_storage_buffer(instance, device, data, size,
+        vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::INDEX_BUFFER, false)?;
+    data.cull_buffer = buffer;
+    data.cull_buffer_memory = memory;
+    data.cull_capacity = capacity;
+    write_compute_descriptor_sets(device, data);
+    let command_buffer = begin_single_time_commands(device, data)?;
+    device.cmd_fill_buffer(command_buffer, data.cull_buffer, 0, vk::WHOLE_SIZE as u64, 0);
+    end_single_time_commands(device, data, command_buffer)
+}
+
+pub unsafe fn destroy_cull_buffer(device: &Device, data: &mut AppData) {
+    device.destroy_buffer(data.cull_buffer, None);
+    device.free_memory(data.cull_buffer_memory, None);
+    data.cull_buffer = vk::Buffer::null();
+    data.cull_buffer_memory = vk::DeviceMemory::null();
+    data.cull_capacity = 0;
+}
+
+/// Records the cull of the latest particles `particle_buffers[parity]` under `data.cull_view`, leaving
+/// the visible ones' indices and the indexed draws of them in the cull buffer, the far ones apart if
+/// there is a level of detail.
+pub unsafe fn record_cull(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, parity: usize) {
+    if !culled(data) {
+        return;
+    }
+    let mut planes = frustum_planes(&(projection(data.swapchain_extent) * data.cull_view));
+    match &data.frustum_culling {
+        Some(culling) => planes.iter_mut().for_each(|plane| plane.w += culling.margin),
+        // only what is behind the camera, which is never drawn anyway, for the near plane to stay
+        None => planes.iter_mut().enumerate().filter(|(k, _)| *k != 4)
+            .for_each(|(_, plane)| *plane = glm::vec4(0.0, 0.0, 0.0, 1.0)),
+    }
+    // the pass measures view depths along the near plane's normal, from where the plane puts the camera
+    let (near, eye) = (planes[4], glm::inverse(&data.cull_view).column(3).xyz());
+    let (lod_depth, lod_keep) = data.particle_lod.map_or((f32::MAX, 1.0), |lod|
+        (lod.distance + glm::dot(&near.xyz(), &eye) + near.w, lod.keep.clamp(0.0, 1.0)));
+    let constants = CullConstants { planes, phase_masses: data.phase_masses,
+        radius: data.particle_radius * data.radius_scale, capacity: data.cull_capacity, lod_depth, lod_keep };
+    // the last frame's draws through the buffer are done, as the compute queue waited on them; the
+    // counts start from none, and the pass fills in the rest of the draws
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+    device.cmd_fill_buffer(command_buffer, data.cull_buffer, 0, CULL_HEADER, 0);
+    transfer_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, data.cull_pipeline_layout, 0,
+        &[data.compute_descriptor_sets[parity]], &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const CullConstants).cast::<u8>(),
+        size_of::<CullConstants>());
+    device.cmd_push_constants(command_buffer, data.cull_pipeline_layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, data.cull_pipeline);
+    device.cmd_dispatch(command_buffer, data.cull_capacity.div_ceil(data.workgroup_size), 1, 1);
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+}
+
+/// Records the draw of the live particles with the vertex buffers bound and a pipeline that draws them:
+/// those the cull left if they are culled, the far ones through `far_pipeline` if given, or else as
+/// many as the counter buffer says the last step left.
+pub unsafe fn record_live_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    far_pipeline: Option<vk::Pipeline>) {
+    if !culled(data) {
+        // the counter buffer starts with the live count as a VkDrawIndirectCommand
+        device.cmd_draw_indirect(command_buffer, data.counter_buffer, 0, 1, size_of::<vk::DrawIndirectCommand>() as u32);
+        return;
+    }
+    let stride = size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+    device.cmd_bind_index_buffer(command_buffer, data.cull_buffer, CULL_HEADER, vk::IndexType::UINT32);
+    device.cmd_draw_indexed_indirect(command_buffer, data.cull_buffer, 0, 1, stride);
+    if data.particle_lod.is_some() {
+        if let Some(pipeline) = far_pipeline {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+        }
+        device.cmd_draw_indexed_indirect(command_buffer, data.cull_buffer, CULL_HEADER / 2, 1, stride);
+    }
+}
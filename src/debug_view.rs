@@ -0,0 +1,303 @@
+use std::mem::size_of;
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::camera::projection;
+use crate::config::*;
+use crate::reconstruct::FluidRendering;
+use crate::utils::{compile_shader, create_shader_module};
+
+/// Offscreen images the debug view can show.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugTarget {
+    /// The scene's depth buffer, as distances from the eye.
+    SceneDepth,
+    /// What the screen-space fluid draws the scene into, and the fluid's thickness and smoothed depth.
+    SceneColor,
+    FluidThickness,
+    FluidDepth,
+}
+
+impl DebugTarget {
+    const ALL: [DebugTarget; 4] = [DebugTarget::SceneDepth, DebugTarget::SceneColor, DebugTarget::FluidThickness,
+        DebugTarget::FluidDepth];
+
+    /// Whether the frame draws the image at all when the fluid is drawn with `rendering`.
+    pub fn available(self, rendering: FluidRendering) -> bool {
+        self == DebugTarget::SceneDepth || rendering == FluidRendering::ScreenSpace
+    }
+
+    /// The next target after this one that is drawn when the fluid is drawn with `rendering`, if any.
+    pub fn after(self, rendering: FluidRendering) -> Option<Self> {
+        Self::ALL.iter().skip_while(|t| **t != self).skip(1).copied().find(|t| t.available(rendering))
+    }
+}
+
+/// A picture-in-picture view of one offscreen image in the window's bottom-right corner, drawn over the
+/// finished frame. Depth and thickness show as grayscale across the given ranges.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DebugView {
+    pub target: DebugTarget,
+    /// Fraction of the window's width and height the view takes, and pixels it keeps from the corner.
+    pub scale: f32,
+    pub margin: f32,
+    /// Distances from the eye shown black and white.
+    pub depth_range: [f32; 2],
+    /// Thickness of fluid shown white.
+    pub thickness: f32,
+}
+
+impl Default for DebugView {
+    fn default() -> Self {
+        Self { target: DebugTarget::SceneDepth, scale: DEBUG_VIEW_SCALE, margin: DEBUG_VIEW_MARGIN,
+            depth_range: DEBUG_VIEW_DEPTH_RANGE, thickness: DEBUG_VIEW_THICKNESS }
+    }
+}
+
+impl DebugView {
+    /// The view's viewport in a swapchain of `extent`, keeping the window's aspect ratio.
+    pub fn viewport(&self, extent: vk::Extent2D) -> vk::Viewport {
+        let scale = self.scale.clamp(0.0, 1.0);
+        let (width, height) = (extent.width as f32 * scale, extent.height as f32 * scale);
+        let x = (extent.width as f32 - self.margin - width).max(0.0);
+        let y = (extent.height as f32 - self.margin - height).max(0.0);
+        vk::Viewport { x, y, width, height, min_depth: 0.0, max_depth: 1.0 }
+    }
+}
+
+/// Push constants of the debug view, laid out like `DebugConstants` in `debug_view.frag`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct DebugConstants {
+    /// Values shown black and white.
+    range: [f32; 2],
+    /// The projection's `(2, 2)` and `(2, 3)` entries, which turn the depth buffer back into distances.
+    projection: [f32; 2],
+    /// 0 shows the colors as they are, 1 the first channel across `range`, 2 the depth buffer's
+    /// distances across it.
+    mode: u32,
+}
+
+/// Creates what the debug view draws with: a pass loading the finished swapchain image, a set for each
+/// target, and a pipeline drawing a full-screen triangle into the view's viewport.
+pub unsafe fn create_debug_view(device: &Device, data: &mut AppData) -> Result<()> {
+    // drawn over the presentable image, which is left presentable
+    let color_attachment = vk::AttachmentDescription::builder()
+        .format(data.swapchain_format).samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::LOAD).store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE).stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::PRESENT_SRC_KHR).final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+    let color_attachment_ref = vk::AttachmentReference::builder().attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let color_attachments = &[color_attachment_ref];
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(color_attachments);
+    let dependency = vk::SubpassDependency::builder()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+    let attachments = &[color_attachment];
+    let subpasses = &[subpass];
+    let dependencies = &[dependency];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+    data.debug_render_pass = device.create_render_pass(&info, None)?;
+
+    // one set per target, in the order of `DebugTarget::ALL`, the smoothed depth's for both depth images
+    // as it ends up in whichever the last smoothing pass drew
+    let binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+    let bindings = &[binding];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+    data.debug_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    let count = DebugTarget::ALL.len() as u32 + 1;
+    let pool_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).descriptor_count(count);
+    let pool_sizes = &[pool_size];
+    let info = vk::DescriptorPoolCreateInfo::builder().pool_sizes(pool_sizes).max_sets(count);
+    data.debug_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    let layouts = vec![data.debug_set_layout; count as usize];
+    let info = vk::DescriptorSetAllocateInfo::builder().descriptor_pool(data.debug_descriptor_pool).set_layouts(&layouts);
+    data.debug_descriptor_sets = device.allocate_descriptor_sets(&info)?;
+    create_debug_pipeline(device, data)
+}
+
+/// Creates the framebuffers of the debug view over each swapchain image and binds each target to its
+/// set. Call after `create_fluid_targets`, whose images it reads through the fluid's sampler.
+pub unsafe fn create_debug_targets(device: &Device, data: &mut AppData) -> Result<()> {
+    let extent = data.swapchain_extent;
+    data.debug_framebuffers = data.swapchain_image_views.iter().map(|v| {
+        let attachments = &[*v];
+        let info = vk::FramebufferCreateInfo::builder().render_pass(data.debug_render_pass)
+            .attachments(attachments).width(extent.width).height(extent.height).layers(1);
+        device.create_framebuffer(&info, None)
+    }).collect::<Result<Vec<_>, _>>()?;
+    let views = [data.depth_image_view, data.scene_color_image_view, data.fluid_thickness_image_view,
+        data.fluid_depth_image_views[0], data.fluid_depth_image_views[1]];
+    for (set, view) in data.debug_descriptor_sets.iter().zip(views) {
+        let image_info = &[vk::DescriptorImageInfo::builder()
+            .sampler(data.fluid_sampler).image_view(view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(*set).dst_binding(0).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info);
+        device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+    }
+    Ok(())
+}
+
+pub unsafe fn destroy_debug_targets(device: &Device, data: &AppData) {
+    data.debug_framebuffers.iter().for_each(|f| device.destroy_framebuffer(*f, None));
+}
+
+unsafe fn create_debug_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&FULLSCREEN_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&DEBUG_VIEW_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+    // the viewport and scissor are set as the view is drawn
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlags::_1);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(false);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+
+    let set_layouts = &[data.debug_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(size_of::<DebugConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.debug_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .dynamic_state(&dynamic_state)
+        .multisample_state(&multisample_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.debug_pipeline_layout)
+        .render_pass(data.debug_render_pass)
+        .subpass(0);
+    data.debug_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_debug_view(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.debug_pipeline, None);
+    device.destroy_pipeline_layout(data.debug_pipeline_layout, None);
+    device.destroy_descriptor_pool(data.debug_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(data.debug_set_layout, None);
+    device.destroy_render_pass(data.debug_render_pass, None);
+}
+
+/// Moves the scene's depth buffer between the layouts the scene's passes draw into and the debug view
+/// samples; `to_read` goes from the former to the latter.
+unsafe fn transition_scene_depth(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, to_read: bool) {
+    let fragment_tests = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+    let attachment = (vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL, fragment_tests,
+        vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE);
+    let read = (vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, vk::PipelineStageFlags::FRAGMENT_SHADER,
+        vk::AccessFlags::SHADER_READ);
+    let (from, to) = if to_read { (attachment, read) } else { (read, attachment) };
+    let subresource = vk::ImageSubresourceRange::builder()
+        .aspect_mask(vk::ImageAspectFlags::DEPTH)
+        .base_mip_level(0).level_count(1).base_array_layer(0).layer_count(1);
+    let barrier = vk::ImageMemoryBarrier::builder()
+        .old_layout(from.0).new_layout(to.0)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED).dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(data.depth_image)
+        .subresource_range(subresource)
+        .src_access_mask(from.2).dst_access_mask(to.2);
+    device.cmd_pipeline_barrier(command_buffer, from.1, to.1, vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier], &[] as &[vk::BufferMemoryBarrier], &[barrier]);
+}
+
+/// Records the debug view over swapchain image `i`, once everything else is drawn into it, if it is on
+/// and the frame draws its target. The depth buffer is handed back to the scene's passes afterwards.
+pub unsafe fn record_debug_view(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    let Some(view) = &data.debug_view else { return };
+    // nothing is smoothed on screen while the window is split
+    let rendering = if data.split_screen.is_some() { FluidRendering::Particles } else { data.fluid_rendering };
+    if !view.target.available(rendering) {
+        return;
+    }
+    let extent = data.swapchain_extent;
+    let proj = projection(extent);
+    let smoothed = data.screen_space.iterations as usize & 1;
+    let (mode, range, set) = match view.target {
+        DebugTarget::SceneDepth => (2, view.depth_range, 0),
+        DebugTarget::SceneColor => (0, [0.0, 1.0], 1),
+        DebugTarget::FluidThickness => (1, [0.0, view.thickness], 2),
+        DebugTarget::FluidDepth => (1, view.depth_range, 3 + smoothed),
+    };
+    let constants = DebugConstants { range, projection: [proj[(2, 2)], proj[(2, 3)]], mode };
+    let scene_depth = view.target == DebugTarget::SceneDepth;
+    if scene_depth {
+        transition_scene_depth(device, data, command_buffer, true);
+    }
+    let info = vk::RenderPassBeginInfo::builder()
+        .render_pass(data.debug_render_pass)
+        .framebuffer(data.debug_framebuffers[i])
+        .render_area(vk::Rect2D::builder().offset(vk::Offset2D::default()).extent(extent));
+    device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+    let viewport = view.viewport(extent);
+    let scissor = vk::Rect2D { offset: vk::Offset2D { x: viewport.x as i32, y: viewport.y as i32 },
+        extent: vk::Extent2D { width: viewport.width as u32, height: viewport.height as u32 } };
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.debug_pipeline);
+    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+    device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.debug_pipeline_layout, 0, &[data.debug_descriptor_sets[set]], &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const DebugConstants).cast::<u8>(),
+        size_of::<DebugConstants>());
+    device.cmd_push_constants(command_buffer, data.debug_pipeline_layout, vk::ShaderStageFlags::FRAGMENT, 0, bytes);
+    device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    device.cmd_end_render_pass(command_buffer);
+    if scene_depth {
+        transition_scene_depth(device, data, command_buffer, false);
+    }
+}
@@ -0,0 +1,161 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::oit::oit_active;
+use crate::scan::{gpu_exclusive_scan, register_scan_buffer, unregister_scan_buffer};
+use crate::simulation::{compute_barrier, create_storage_buffer, solver_constants, transfer_barrier,
+    write_compute_descriptor_sets};
+use crate::utils::{create_compute_pipeline, begin_single_time_commands, end_single_time_commands};
+
+/// Translucent particles: drawn alpha blended and back to front, through an index buffer a pass sorts
+/// by view depth each frame, stepped or not. The sort is a counting sort into depth buckets over the
+/// range the domain spans in front of the camera, reusing the scan the neighbor grid's sort runs on;
+/// its buffers take 8 bytes per particle and are only made once translucency is first turned on.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Translucency {
+    /// Opacity of a particle at `full_density` and above.
+    pub opacity: f32,
+    /// Density at which a particle is drawn at `opacity`, thinning towards none below it; 0 draws
+    /// every particle at `opacity`.
+    pub full_density: f32,
+}
+
+impl Default for Translucency {
+    fn default() -> Self {
+        Self { opacity: TRANSLUCENT_OPACITY, full_density: TRANSLUCENT_FULL_DENSITY }
+    }
+}
+
+/// Bytes before the sorted indices: a VkDrawIndexedIndirectCommand, padded to 16 bytes.
+pub const DEPTH_SORT_HEADER: u64 = 32;
+
+/// Push constants of the depth sort passes, laid out to match their `DepthSort` blocks.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct DepthSortConstants {
+    view: glm::Mat4,
+    /// Nearest and farthest view depth the buckets span; particles past them share the end buckets.
+    depth_range: [f32; 2],
+    capacity: u32,
+    buckets: u32,
+}
+
+/// Creates the passes that bucket the particles by depth and scatter their indices back to front.
+pub unsafe fn create_depth_sort_pipelines(device: &Device, data: &mut AppData) -> Result<()> {
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<DepthSortConstants>() as u32);
+    let set_layouts = &[data.compute_descriptor_set_layout];
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.depth_sort_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let layout = data.depth_sort_pipeline_layout;
+    let constants = solver_constants(data, false);
+    data.depth_keys_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &DEPTH_KEYS_SHADER.to_string(), &constants)?;
+    data.depth_scatter_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &DEPTH_SCATTER_SHADER.to_string(), &constants)?;
+    Ok(())
+}
+
+pub unsafe fn destroy_depth_sort_pipelines(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.depth_keys_pipeline, None);
+    device.destroy_pipeline(data.depth_scatter_pipeline, None);
+    device.destroy_pipeline_layout(data.depth_sort_pipeline_layout, None);
+}
+
+/// Makes the depth sort's buffers with room for every particle the particle buffers hold, unless the
+/// ones there already have it, and binds them to the compute sets; nothing is drawn through them until
+/// the first sort. Nothing may be in flight.
+pub unsafe fn create_depth_sort_buffers(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let capacity = data.particle_capacity.max(1);
+    if data.depth_sort_buffer != vk::Buffer::null() && data.depth_sort_capacity == capacity {
+        return Ok(());
+    }
+    destroy_depth_sort_buffers(device, data)?;
+    // the indirect draw, then the sorted indices and each particle's rank within its bucket
+    let uint_size = size_of::<u32>() as u64;
+    let size = DEPTH_SORT_HEADER + 2 * uint_size * capacity as u64;
+    let (buffer, memory) = create_storage_buffer(instance, device, data, size,
+        vk::BufferUsageFlags::INDIRECT_BUFFER | vk::BufferUsageFlags::INDEX_BUFFER, false)?;
+    data.depth_sort_buffer = buffer;
+    data.depth_sort_buffer_memory = memory;
+    let (buffer, memory) = create_storage_buffer(instance, device, data, uint_size * DEPTH_SORT_BUCKETS as u64,
+        vk::BufferUsageFlags::empty(), false)?;
+    data.depth_buckets_buffer = buffer;
+    data.depth_buckets_buffer_memory = memory;
+    data.depth_sort_capacity = capacity;
+    register_scan_buffer(instance, device, data, data.depth_buckets_buffer, DEPTH_SORT_BUCKETS)?;
+    write_compute_descriptor_sets(device, data);
+    let command_buffer = begin_single_time_commands(device, data)?;
+    device.cmd_fill_buffer(command_buffer, data.depth_sort_buffer, 0, vk::WHOLE_SIZE as u64, 0);
+    end_single_time_commands(device, data, command_buffer)
+}
+
+pub unsafe fn destroy_depth_sort_buffers(device: &Device, data: &mut AppData) -> Result<()> {
+    unregister_scan_buffer(device, data, data.depth_buckets_buffer)?;
+    device.destroy_buffer(data.depth_sort_buffer, None);
+    device.free_memory(data.depth_sort_buffer_memory, None);
+    device.destroy_buffer(data.depth_buckets_buffer, None);
+    device.free_memory(data.depth_buckets_buffer_memory, None);
+    data.depth_sort_buffer = vk::Buffer::null();
+    data.depth_sort_buffer_memory = vk::DeviceMemory::null();
+    data.depth_buckets_buffer = vk::Buffer::null();
+    data.depth_buckets_buffer_memory = vk::DeviceMemory::null();
+    data.depth_sort_capacity = 0;
+    Ok(())
+}
+
+/// The view depths the domain's corners span under `view`, which the sort's buckets divide.
+pub fn depth_range(view: &glm::Mat4, domain_min: &glm::Vec3, domain_max: &glm::Vec3) -> [f32; 2] {
+    (0..8).map(|k| {
+        let corner = glm::vec3(
+            if k & 1 == 0 { domain_min.x } else { domain_max.x },
+            if k & 2 == 0 { domain_min.y } else { domain_max.y },
+            if k & 4 == 0 { domain_min.z } else { domain_max.z });
+        -(view * glm::vec4(corner.x, corner.y, corner.z, 1.0)).z
+    })
+    .fold([f32::MAX, f32::MIN], |[near, far], depth| [near.min(depth), far.max(depth)])
+}
+
+/// Records the sort of the latest particles `particle_buffers[parity]` back to front under
+/// `data.depth_sort_view`, leaving their indices and the indexed draw of them in the sort buffer.
+pub unsafe fn record_depth_sort(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    parity: usize) -> Result<()> {
+    // weighted into the transparency targets, the particles need no order
+    if data.depth_sort_buffer == vk::Buffer::null() || oit_active(data) {
+        return Ok(());
+    }
+    let constants = DepthSortConstants { view: data.depth_sort_view, depth_range: data.depth_sort_range,
+        capacity: data.depth_sort_capacity, buckets: DEPTH_SORT_BUCKETS };
+    let bytes = std::slice::from_raw_parts((&constants as *const DepthSortConstants).cast::<u8>(),
+        size_of::<DepthSortConstants>());
+    let groups = data.depth_sort_capacity.div_ceil(data.workgroup_size);
+    // the last frame's draw of the sorted indices is done, as the compute queue waited on it
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+    device.cmd_fill_buffer(command_buffer, data.depth_buckets_buffer, 0, vk::WHOLE_SIZE as u64, 0);
+    transfer_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    // count each bucket's particles, then turn the counts into where each bucket starts
+    let sets = [data.compute_descriptor_sets[parity]];
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, data.depth_sort_pipeline_layout, 0,
+        &sets, &[]);
+    device.cmd_push_constants(command_buffer, data.depth_sort_pipeline_layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, data.depth_keys_pipeline);
+    device.cmd_dispatch(command_buffer, groups, 1, 1);
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    gpu_exclusive_scan(device, data, command_buffer, data.depth_buckets_buffer, DEPTH_SORT_BUCKETS)?;
+    // the scan bound its own layout
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, data.depth_sort_pipeline_layout, 0,
+        &sets, &[]);
+    device.cmd_push_constants(command_buffer, data.depth_sort_pipeline_layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, data.depth_scatter_pipeline);
+    device.cmd_dispatch(command_buffer, groups, 1, 1);
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    Ok(())
+}
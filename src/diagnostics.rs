@@ -0,0 +1,76 @@
+use std::fmt;
+use std::mem::size_of;
+use anyhow::Result;
+use rayon::prelude::*;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::MAX_PHASES;
+use crate::simulation::{Particle, SimParams};
+
+/// Health of the fluid after a solver step, reduced over the live particles. Laid out like the
+/// `FrameDiagnostics` the reduction and surface passes write per frame in flight.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Diagnostics {
+    pub max_speed: f32,
+    pub kinetic_energy: f32,
+    /// `|density - rest density| / rest density`, over the particles and at worst.
+    pub mean_density_error: f32,
+    pub max_density_error: f32,
+    /// Particles the surface detection flags, or zero while it is off.
+    pub surface_particles: u32,
+    pub _pad: [u32; 3],
+    /// Lowest and highest density and pressure, lowest above highest with no particles.
+    pub density_range: [f32; 2],
+    pub pressure_range: [f32; 2],
+    /// Largest and mean magnitude of the particles' velocity divergence, zero unless the steps measure it.
+    pub max_divergence: f32,
+    pub mean_divergence: f32,
+    pub _pad2: [u32; 2],
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "kinetic energy {:.4} J, max speed {:.3} m/s, density error {:.2}% mean, {:.2}% max",
+            self.kinetic_energy, self.max_speed, 100.0 * self.mean_density_error, 100.0 * self.max_density_error)?;
+        if self.max_divergence > 0.0 {
+            write!(f, ", divergence {:.3}/s mean, {:.3}/s max", self.mean_divergence, self.max_divergence)?;
+        }
+        if self.surface_particles > 0 {
+            write!(f, ", {} on the surface", self.surface_particles)?;
+        }
+        Ok(())
+    }
+}
+
+/// Diagnostics helpers
+/// Reads back the diagnostics the reduction passes wrote for `frame`'s last step; the caller must have
+/// waited on that frame's fence, so the values trail the newest step by the frames in flight.
+pub unsafe fn read_diagnostics(device: &Device, data: &AppData, frame: usize) -> Result<Diagnostics> {
+    let offset = (size_of::<Diagnostics>() * frame) as u64;
+    let memory = device.map_memory(data.diagnostics_buffer_memory, offset,
+        size_of::<Diagnostics>() as u64, vk::MemoryMapFlags::empty())?;
+    let diagnostics = *(memory as *const Diagnostics);
+    device.unmap_memory(data.diagnostics_buffer_memory);
+    Ok(diagnostics)
+}
+
+/// The same numbers for particles on the CPU, such as the CPU solver's.
+pub fn cpu_diagnostics(particles: &[Particle], sim: &SimParams) -> Diagnostics {
+    let stats = particles.par_iter().map(|p| {
+        let speed = p.vel.norm();
+        let rest = sim.phases[(p.phase as usize).min(MAX_PHASES - 1)].x;
+        let error = (p.density - rest).abs() / rest;
+        let divergence = p.divergence.abs();
+        [speed, 0.5 * p.mass * speed * speed, error, error, p.density, p.density, p.pressure, p.pressure,
+            divergence, divergence]
+    }).reduce(|| [0.0, 0.0, 0.0, 0.0, f32::MAX, -f32::MAX, f32::MAX, -f32::MAX, 0.0, 0.0], |a, b| [a[0].max(b[0]),
+        a[1] + b[1], a[2] + b[2], a[3].max(b[3]), a[4].min(b[4]), a[5].max(b[5]), a[6].min(b[6]), a[7].max(b[7]),
+        a[8].max(b[8]), a[9] + b[9]]);
+    let count = particles.len().max(1) as f32;
+    Diagnostics { max_speed: stats[0], kinetic_energy: stats[1],
+        mean_density_error: stats[2] / count, max_density_error: stats[3],
+        density_range: [stats[4], stats[5]], pressure_range: [stats[6], stats[7]],
+        max_divergence: stats[8], mean_divergence: stats[9] / count, ..Default::default() }
+}
@@ -0,0 +1,176 @@
+use nalgebra_glm as glm;
+
+use crate::simulation::Particle;
+
+/// A circular nozzle that pours particles into the scene along `direction`.
+#[derive(Copy, Clone, Debug)]
+pub struct Emitter {
+    pub position: glm::Vec3,
+    pub direction: glm::Vec3,
+    pub radius: f32,
+    pub speed: f32,
+    /// Particles per second of simulated time.
+    pub rate: f32,
+    pub active: bool,
+    /// Fluid phase of the released particles.
+    pub phase: u32,
+    pending: f32,
+    cursor: usize,
+}
+
+impl Emitter {
+    pub fn new(position: glm::Vec3, direction: glm::Vec3, radius: f32, speed: f32, rate: f32) -> Self {
+        Self { position, direction: glm::normalize(&direction), radius, speed, rate,
+            active: true, phase: 0, pending: 0.0, cursor: 0 }
+    }
+
+    pub fn start(&mut self) {
+        self.active = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.pending = 0.0;
+    }
+
+    /// Lattice points covering the nozzle disk, relative to `position`.
+    fn nozzle(&self, spacing: f32) -> Vec<glm::Vec3> {
+        let helper = if self.direction.x.abs() < 0.9 { glm::vec3(1.0, 0.0, 0.0) } else { glm::vec3(0.0, 1.0, 0.0) };
+        let u = glm::normalize(&glm::cross(&self.direction, &helper));
+        let v = glm::cross(&self.direction, &u);
+        let n = (self.radius / spacing) as i32;
+        let mut points = Vec::new();
+        for i in -n..=n {
+            for j in -n..=n {
+                let (a, b) = (i as f32 * spacing, j as f32 * spacing);
+                if a * a + b * b <= self.radius * self.radius {
+                    points.push(u * a + v * b);
+                }
+            }
+        }
+        points
+    }
+
+    /// Appends the particles released over `dt` seconds to `out`, at most `limit` of them.
+    /// Particles are staggered along the jet as if released uniformly during the step.
+    pub fn emit(&mut self, dt: f32, spacing: f32, mass: f32, limit: usize, out: &mut Vec<Particle>) {
+        if !self.active {
+            return;
+        }
+        self.pending += self.rate * dt;
+        let count = (self.pending as usize).min(limit);
+        self.pending -= count as f32;
+        let nozzle = self.nozzle(spacing);
+        for k in 0..count {
+            let offset = nozzle[self.cursor % nozzle.len()];
+            let travel = self.speed * dt * (count - k) as f32 / count as f32;
+            let mut particle = Particle::new(self.position + offset + self.direction * travel, mass);
+            particle.vel = self.direction * self.speed;
+            particle.phase = self.phase;
+            out.push(particle);
+            self.cursor += 1;
+        }
+        // whatever did not fit is dropped rather than piling up while the buffer is full
+        if count == limit {
+            self.pending = self.pending.min(1.0);
+        }
+    }
+}
+
+/// An axis-aligned box that deletes every particle entering it.
+#[derive(Copy, Clone, Debug)]
+pub struct Sink {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+    pub active: bool,
+}
+
+impl Sink {
+    pub fn new(min: glm::Vec3, max: glm::Vec3) -> Self {
+        Self { min: glm::min2(&min, &max), max: glm::max2(&min, &max), active: true }
+    }
+}
+
+/// An open boundary fluid enters through: an axis-aligned box of ghost particles that stream along one
+/// axis at `velocity` whatever the fluid around them does, fed in lattice layers at the upstream face.
+/// Past the downstream face they are fluid like any other, and the ghosts behind give them a full
+/// kernel's worth of neighbors on the way in.
+#[derive(Copy, Clone, Debug)]
+pub struct Inflow {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+    pub velocity: glm::Vec3,
+    pub active: bool,
+    /// Fluid phase of the fed particles.
+    pub phase: u32,
+    /// How far the last fed layer has streamed from the upstream face.
+    travelled: f32,
+}
+
+impl Inflow {
+    pub fn new(min: glm::Vec3, max: glm::Vec3, velocity: glm::Vec3) -> Self {
+        Self { min: glm::min2(&min, &max), max: glm::max2(&min, &max), velocity, active: true, phase: 0, travelled: 0.0 }
+    }
+
+    /// The axis the flow runs along, the one its velocity is largest in.
+    pub fn axis(&self) -> usize {
+        self.velocity.abs().imax()
+    }
+
+    /// Particles of a lattice layer `offset` downstream of the upstream face, `spacing` apart across it.
+    fn layer(&self, offset: f32, spacing: f32, mass: f32) -> impl Iterator<Item = Particle> + '_ {
+        let a = self.axis();
+        let (b, c) = ((a + 1) % 3, (a + 2) % 3);
+        let count = move |k: usize| ((self.max[k] - self.min[k]) / spacing).max(1.0) as u32;
+        let face = if self.velocity[a] > 0.0 { self.min[a] + offset } else { self.max[a] - offset };
+        (0..count(b) * count(c)).map(move |n| {
+            let mut pos = glm::Vec3::zeros();
+            pos[a] = face;
+            pos[b] = self.min[b] + ((n % count(b)) as f32 + 0.5) * spacing;
+            pos[c] = self.min[c] + ((n / count(b)) as f32 + 0.5) * spacing;
+            Particle { vel: self.velocity, phase: self.phase, ..Particle::new(pos, mass) }
+        })
+    }
+
+    /// The ghosts filling the box, which a run starts from; the first layer lies on the upstream face,
+    /// where `feed` goes on from.
+    pub fn fill(&self, spacing: f32, mass: f32) -> Vec<Particle> {
+        let a = self.axis();
+        let layers = ((self.max[a] - self.min[a]) / spacing) as u32;
+        (0..layers).flat_map(|k| self.layer(k as f32 * spacing, spacing, mass)).collect()
+    }
+
+    /// Appends the layers fed over `dt` seconds to `out`, at most `limit` particles; a layer that does
+    /// not fit is skipped rather than fed late.
+    pub fn feed(&mut self, dt: f32, spacing: f32, mass: f32, limit: usize, out: &mut Vec<Particle>) {
+        if !self.active {
+            return;
+        }
+        self.travelled += glm::length(&self.velocity) * dt;
+        let start = out.len();
+        while self.travelled >= spacing {
+            self.travelled -= spacing;
+            let layer = self.layer(self.travelled, spacing, mass).collect::<Vec<_>>();
+            if out.len() - start + layer.len() <= limit {
+                out.extend(layer);
+            }
+        }
+    }
+}
+
+/// An open boundary fluid leaves through: particles crossing the face of an axis-aligned box that
+/// `direction` points out of are deleted, and the pressure of those inside eases off towards the face,
+/// so waves run out instead of reflecting off the cut.
+#[derive(Copy, Clone, Debug)]
+pub struct Outflow {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+    pub direction: glm::Vec3,
+    pub active: bool,
+}
+
+impl Outflow {
+    pub fn new(min: glm::Vec3, max: glm::Vec3, direction: glm::Vec3) -> Self {
+        Self { min: glm::min2(&min, &max), max: glm::max2(&min, &max), direction, active: true }
+    }
+}
@@ -0,0 +1,216 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::thread::{self, JoinHandle};
+use anyhow::Result;
+use log::*;
+
+use crate::model::Vertex;
+use crate::simulation::Particle;
+
+/// Per-vertex properties written next to the positions.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PlyFields {
+    pub velocity: bool,
+    pub density: bool,
+}
+
+/// Writes particle frames as numbered binary PLY files on a worker thread. At most `queue` frames wait
+/// for the disk; beyond that `submit` blocks, so a slow disk slows the simulation instead of filling memory.
+#[derive(Debug, Default)]
+pub struct PlyExporter {
+    sender: Option<SyncSender<(u64, Vec<Particle>)>>,
+    worker: Option<JoinHandle<()>>,
+    next: u64,
+}
+
+impl PlyExporter {
+    /// Starts writing `dir/frame_000000.ply`, `dir/frame_000001.ply`, ... for every submitted frame.
+    pub fn start(&mut self, dir: impl AsRef<Path>, fields: PlyFields, queue: usize) -> Result<()> {
+        self.stop();
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let (sender, receiver) = sync_channel::<(u64, Vec<Particle>)>(queue);
+        self.worker = Some(thread::spawn(move || {
+            for (index, particles) in receiver {
+                let path = dir.join(format!("frame_{:06}.ply", index));
+                if let Err(e) = write_ply(&path, &particles, fields) {
+                    error!("Failed to write {}: {}", path.display(), e);
+                }
+            }
+        }));
+        self.sender = Some(sender);
+        self.next = 0;
+        Ok(())
+    }
+
+    /// Stops exporting once every queued frame is on disk.
+    pub fn stop(&mut self) {
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                error!("The PLY export thread panicked.");
+            }
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Queues one frame, blocking while the queue is full.
+    pub fn submit(&mut self, particles: Vec<Particle>) {
+        if let Some(sender) = &self.sender {
+            if sender.send((self.next, particles)).is_err() {
+                error!("The PLY export thread stopped; no more frames are written.");
+                self.sender = None;
+            }
+            self.next += 1;
+        }
+    }
+}
+
+impl Drop for PlyExporter {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// A fluid surface mesh queued for `ObjExporter`'s worker: the simulated time it shows, its vertices
+/// and its triangles.
+type SurfaceFrame = (f64, Vec<Vertex>, Vec<u32>);
+
+/// Writes fluid surface meshes as numbered OBJ files on a worker thread, like `PlyExporter`, along with
+/// `manifest.csv` listing each file's simulated time. Every `interval`th new frame offered is written.
+#[derive(Debug, Default)]
+pub struct ObjExporter {
+    sender: Option<SyncSender<(u64, SurfaceFrame)>>,
+    worker: Option<JoinHandle<()>>,
+    next: u64,
+    interval: u64,
+    /// New frames to pass over before the next is written.
+    skip: u64,
+    /// Simulated time of the last frame offered, so a mesh rebuilt without the fluid moving is not
+    /// written twice.
+    last_time: Option<f64>,
+}
+
+impl ObjExporter {
+    /// Starts writing `dir/surface_000000.obj`, `dir/surface_000001.obj`, ... for every `interval`th frame.
+    pub fn start(&mut self, dir: impl AsRef<Path>, interval: u32, queue: usize) -> Result<()> {
+        self.stop();
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut manifest = BufWriter::new(File::create(dir.join("manifest.csv"))?);
+        writeln!(manifest, "frame,time,file")?;
+        let (sender, receiver) = sync_channel::<(u64, SurfaceFrame)>(queue);
+        self.worker = Some(thread::spawn(move || {
+            for (index, (time, vertices, indices)) in receiver {
+                let name = format!("surface_{:06}.obj", index);
+                let path = dir.join(&name);
+                // a frame only goes in the manifest once its file is complete
+                let written = write_obj(&path, &vertices, &indices).and_then(|()| {
+                    writeln!(manifest, "{},{:.6},{}", index, time, name)?;
+                    Ok(manifest.flush()?)
+                });
+                if let Err(e) = written {
+                    error!("Failed to write {}: {}", path.display(), e);
+                }
+            }
+        }));
+        self.sender = Some(sender);
+        self.next = 0;
+        self.interval = interval.max(1) as u64;
+        self.skip = 0;
+        self.last_time = None;
+        Ok(())
+    }
+
+    /// Stops exporting once every queued frame is on disk.
+    pub fn stop(&mut self) {
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                error!("The OBJ export thread panicked.");
+            }
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Offers the surface at simulated `time`, calling `mesh` for its vertices and triangles only if the
+    /// frame is written, and blocking while the queue is full. A frame at the time of the last one is
+    /// ignored.
+    pub fn offer(&mut self, time: f64, mesh: impl FnOnce() -> (Vec<Vertex>, Vec<u32>)) {
+        let Some(sender) = &self.sender else { return };
+        if self.last_time == Some(time) {
+            return;
+        }
+        self.last_time = Some(time);
+        if self.skip > 0 {
+            self.skip -= 1;
+            return;
+        }
+        self.skip = self.interval - 1;
+        let (vertices, indices) = mesh();
+        if sender.send((self.next, (time, vertices, indices))).is_err() {
+            error!("The OBJ export thread stopped; no more frames are written.");
+            self.sender = None;
+        }
+        self.next += 1;
+    }
+}
+
+impl Drop for ObjExporter {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Writes a triangle mesh as a text OBJ file with per-vertex normals. Without triangles the file holds
+/// just its header comment, which readers take as an empty mesh.
+pub fn write_obj(path: &Path, vertices: &[Vertex], indices: &[u32]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "# fluid surface: {} vertices, {} triangles", vertices.len(), indices.len() / 3)?;
+    for v in vertices {
+        writeln!(writer, "v {} {} {}", v.pos.x, v.pos.y, v.pos.z)?;
+    }
+    for v in vertices {
+        writeln!(writer, "vn {} {} {}", v.normal.x, v.normal.y, v.normal.z)?;
+    }
+    // OBJ counts vertices from 1
+    for t in indices.chunks_exact(3) {
+        writeln!(writer, "f {0}//{0} {1}//{1} {2}//{2}", t[0] + 1, t[1] + 1, t[2] + 1)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes `particles` as a binary little-endian PLY point cloud.
+pub fn write_ply(path: &Path, particles: &[Particle], fields: PlyFields) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "ply\nformat binary_little_endian 1.0\nelement vertex {}", particles.len())?;
+    writeln!(writer, "property float x\nproperty float y\nproperty float z")?;
+    if fields.velocity {
+        writeln!(writer, "property float vx\nproperty float vy\nproperty float vz")?;
+    }
+    if fields.density {
+        writeln!(writer, "property float density")?;
+    }
+    writeln!(writer, "end_header")?;
+    for p in particles {
+        let mut values = vec![p.pos.x, p.pos.y, p.pos.z];
+        if fields.velocity {
+            values.extend_from_slice(&[p.vel.x, p.vel.y, p.vel.z]);
+        }
+        if fields.density {
+            values.push(p.density);
+        }
+        values.iter().try_for_each(|v| writer.write_all(&v.to_le_bytes()))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
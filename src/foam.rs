@@ -0,0 +1,290 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::simulation::{SimParams, compute_barrier, create_storage_buffer, solver_constants};
+use crate::utils::{compile_shader, create_compute_pipeline, create_shader_module, begin_single_time_commands,
+    end_single_time_commands};
+
+/// Secondary foam and spray (Ihmsen et al. 2012): fluid particles seed weightless foam particles where
+/// air is likely trapped, where neighbors close in on each other and on crests moving out of the fluid,
+/// in proportion to their kinetic energy. Foam with few fluid neighbors flies as spray under gravity
+/// alone, and otherwise goes with the fluid; either fades out over its lifetime.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Foam {
+    /// Trapped air potential, the kernel-weighted sum over the neighbors of the speeds they close in
+    /// at (m/s): the least that seeds foam and the most that counts.
+    pub trapped_air: [f32; 2],
+    /// Wave crest potential, the kernel-weighted sum of how far the surface normals of the neighbors
+    /// behind a surface particle part from its own: the least that seeds foam and the most that counts.
+    pub wave_crest: [f32; 2],
+    /// Kinetic energy per unit mass (J/kg): the least that seeds foam and the most that counts.
+    pub energy: [f32; 2],
+    /// Foam particles a second one fluid particle seeds at full trapped air or wave crest potential and
+    /// full energy.
+    pub trapped_air_rate: f32,
+    pub wave_crest_rate: f32,
+    /// Seconds a foam particle lives at most; each gets between half of it and all of it.
+    pub lifetime: f32,
+    /// Fluid neighbors within `h` below which a foam particle flies as spray.
+    pub spray_neighbors: u32,
+    /// Drawn radius of a foam particle (m); spray is drawn smaller.
+    pub radius: f32,
+}
+
+impl Default for Foam {
+    fn default() -> Self {
+        Self {
+            trapped_air: FOAM_TRAPPED_AIR,
+            wave_crest: FOAM_WAVE_CREST,
+            energy: FOAM_ENERGY,
+            trapped_air_rate: FOAM_TRAPPED_AIR_RATE,
+            wave_crest_rate: FOAM_WAVE_CREST_RATE,
+            lifetime: FOAM_LIFETIME,
+            spray_neighbors: FOAM_SPRAY_NEIGHBORS,
+            radius: FOAM_RADIUS,
+        }
+    }
+}
+
+/// One slot of the foam buffer, laid out to match `FoamParticle` in the foam passes.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FoamParticle {
+    pub pos: glm::Vec3,
+    /// Seconds left; dead at zero.
+    pub life: f32,
+    pub vel: glm::Vec3,
+    /// 0 for spray, 1 for foam, as of the last update.
+    pub kind: u32,
+}
+
+impl FoamParticle {
+    /// The foam buffer as the renderer's per-instance vertex input: position, life and kind.
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<FoamParticle>() as u32)
+            .input_rate(vk::VertexInputRate::INSTANCE)
+            .build()
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+        let pos = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(0).format(vk::Format::R32G32B32_SFLOAT).offset(0).build();
+        let life = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(1).format(vk::Format::R32_SFLOAT).offset(size_of::<glm::Vec3>() as u32).build();
+        let kind = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(2).format(vk::Format::R32_UINT).offset(7 * size_of::<f32>() as u32).build();
+        [pos, life, kind]
+    }
+}
+
+/// Push constants of the foam passes, laid out to match their `Foam` block.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct FoamConstants {
+    potentials: glm::Vec4,
+    energy: glm::Vec4,
+    dt: f32,
+    lifetime: f32,
+    surface: f32,
+    capacity: u32,
+    spray_neighbors: u32,
+}
+
+/// Push constants of the foam renderer, laid out to match its `FoamDraw` block.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct FoamDrawConstants {
+    radius: f32,
+    lifetime: f32,
+}
+
+/// Foam helpers
+pub unsafe fn create_foam_pipelines(device: &Device, data: &mut AppData) -> Result<()> {
+    // the solver's buffers and parameters, and the foam settings as push constants
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<FoamConstants>() as u32);
+    let set_layouts = &[data.compute_descriptor_set_layout, data.sim_params_set_layout];
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.foam_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let layout = data.foam_pipeline_layout;
+    let constants = solver_constants(data, false);
+    data.foam_normals_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &FOAM_NORMALS_SHADER.to_string(), &constants)?;
+    data.foam_seed_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &FOAM_SEED_SHADER.to_string(), &constants)?;
+    data.foam_update_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &FOAM_UPDATE_SHADER.to_string(), &constants)?;
+    Ok(())
+}
+
+pub unsafe fn destroy_foam_pipelines(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.foam_normals_pipeline, None);
+    device.destroy_pipeline(data.foam_seed_pipeline, None);
+    device.destroy_pipeline(data.foam_update_pipeline, None);
+    device.destroy_pipeline_layout(data.foam_pipeline_layout, None);
+}
+
+/// Creates the ring of `FOAM_CAPACITY` foam particles, all dead.
+pub unsafe fn create_foam_buffer(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let (buffer, memory) = create_storage_buffer(instance, device, data,
+        (size_of::<FoamParticle>() as u32 * FOAM_CAPACITY) as u64, vk::BufferUsageFlags::VERTEX_BUFFER, false)?;
+    data.foam_buffer = buffer;
+    data.foam_buffer_memory = memory;
+    clear_foam(device, data)
+}
+
+pub unsafe fn destroy_foam_buffer(device: &Device, data: &AppData) {
+    device.destroy_buffer(data.foam_buffer, None);
+    device.free_memory(data.foam_buffer_memory, None);
+}
+
+/// Kills every foam particle. Nothing may be in flight.
+pub unsafe fn clear_foam(device: &Device, data: &AppData) -> Result<()> {
+    let command_buffer = begin_single_time_commands(device, data)?;
+    device.cmd_fill_buffer(command_buffer, data.foam_buffer, 0, vk::WHOLE_SIZE as u64, 0);
+    end_single_time_commands(device, data, command_buffer)
+}
+
+/// Records the foam update for `frame` after its solver steps, over the latest particle buffer
+/// `particle_buffers[parity]` and its neighbor grid: the surface normals, the seeding, which hands out
+/// slots past the ring's head so the oldest foam is recycled first, and the advection of every slot.
+/// `dt` is the time the frame stepped.
+pub unsafe fn record_foam(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, frame: usize,
+    sim: &SimParams, parity: usize, foam: &Foam, dt: f32) {
+    let constants = FoamConstants {
+        potentials: glm::vec4(foam.trapped_air[0], foam.trapped_air[1], foam.wave_crest[0], foam.wave_crest[1]),
+        energy: glm::vec4(foam.energy[0], foam.energy[1], foam.trapped_air_rate, foam.wave_crest_rate),
+        dt,
+        lifetime: foam.lifetime,
+        surface: SURFACE_THRESHOLD,
+        capacity: FOAM_CAPACITY,
+        spray_neighbors: foam.spray_neighbors,
+    };
+    let sets = [data.compute_descriptor_sets[parity], data.sim_params_sets[frame]];
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, data.foam_pipeline_layout, 0,
+        &sets, &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const FoamConstants).cast::<u8>(), size_of::<FoamConstants>());
+    device.cmd_push_constants(command_buffer, data.foam_pipeline_layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+    let groups = sim.workgroups(data.workgroup_size);
+    for (pipeline, group_count) in [
+        (data.foam_normals_pipeline, groups),
+        (data.foam_seed_pipeline, groups),
+        (data.foam_update_pipeline, FOAM_CAPACITY.div_ceil(data.workgroup_size)),
+    ] {
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+        device.cmd_dispatch(command_buffer, group_count, 1, 1);
+        compute_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    }
+}
+
+/// Creates the pipeline that draws the foam over the scene: a camera-facing disc per foam slot, fading
+/// with its life, blended additively and depth tested without writing depth.
+pub unsafe fn create_foam_render_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&FOAM_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&FOAM_FRAGMENT_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let binding_descs = &[FoamParticle::binding_description()];
+    let attribute_descs = &FoamParticle::attribute_descriptions();
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(binding_descs)
+        .vertex_attribute_descriptions(attribute_descs);
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    // light adds up, leaving the target's alpha alone
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::ONE)
+        .dst_color_blend_factor(vk::BlendFactor::ONE)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    // hidden behind what is drawn before, without hiding each other
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true).depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+
+    // the scene's uniform buffer, and the drawn radius and lifetime as push constants
+    let set_layouts = &[data.descriptor_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(size_of::<FoamDrawConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.foam_render_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .dynamic_state(&dynamic_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.foam_render_pipeline_layout)
+        .render_pass(data.render_pass)
+        .subpass(0);
+    data.foam_render_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_foam_render_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.foam_render_pipeline, None);
+    device.destroy_pipeline_layout(data.foam_render_pipeline_layout, None);
+}
+
+/// Records the draw of every foam slot into swapchain image `i`'s render pass, after the rest of the
+/// scene; dead slots draw nothing.
+pub unsafe fn record_foam_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize,
+    foam: &Foam) {
+    let constants = FoamDrawConstants { radius: foam.radius, lifetime: foam.lifetime };
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.foam_render_pipeline);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.foam_render_pipeline_layout, 0, &[data.descriptor_sets[i]], &[]);
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[data.foam_buffer], &[0]);
+    let bytes = std::slice::from_raw_parts((&constants as *const FoamDrawConstants).cast::<u8>(),
+        size_of::<FoamDrawConstants>());
+    device.cmd_push_constants(command_buffer, data.foam_render_pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, bytes);
+    device.cmd_draw(command_buffer, 6, FOAM_CAPACITY, 0, 0);
+}
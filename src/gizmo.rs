@@ -0,0 +1,162 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::utils::{compile_shader, create_shader_module};
+
+/// Vertices `gizmo.vert` makes up for each axis: the shaft and four lines back from the tip for a head.
+const AXIS_VERTICES: u32 = 10;
+
+/// An XYZ axis gizmo in the bottom-left corner of the window, turning with the camera.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AxisGizmo {
+    /// Pixels the gizmo's square viewport is wide, and away from the window's corner.
+    pub size: f32,
+    pub margin: f32,
+    /// Line width in pixels, within what the device draws; 1 where it has no wide lines.
+    pub width: f32,
+}
+
+impl Default for AxisGizmo {
+    fn default() -> Self {
+        Self { size: GIZMO_SIZE, margin: GIZMO_MARGIN, width: GIZMO_WIDTH }
+    }
+}
+
+impl AxisGizmo {
+    /// The gizmo's viewport in a swapchain of `extent`, kept in the corner however the window is sized.
+    pub fn viewport(&self, extent: vk::Extent2D) -> vk::Viewport {
+        let size = self.size.min(extent.width as f32).min(extent.height as f32);
+        let x = self.margin.min(extent.width as f32 - size);
+        let y = (extent.height as f32 - self.margin - size).max(0.0);
+        vk::Viewport { x, y, width: size, height: size, min_depth: 0.0, max_depth: 1.0 }
+    }
+
+    /// The axis whose tip is at the window pixel `(x, y)`, if any, with the camera turned by `view`.
+    pub fn axis_at(&self, extent: vk::Extent2D, view: &glm::Mat4, x: f32, y: f32) -> Option<usize> {
+        let viewport = self.viewport(extent);
+        let center = glm::vec2(viewport.x + viewport.width / 2.0, viewport.y + viewport.height / 2.0);
+        let rotation = glm::mat4_to_mat3(view);
+        // the tips placed as `gizmo.vert` places them, the nearest to the cursor within reach winning
+        (0..3).map(|axis| {
+            let tip = rotation * glm::Vec3::ith(axis, GIZMO_LENGTH);
+            let pixel = center + glm::vec2(tip.x, -tip.y) * viewport.width / 2.0;
+            (axis, glm::distance(&pixel, &glm::vec2(x, y)))
+        })
+        .filter(|(_, distance)| *distance < GIZMO_PICK_RADIUS)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(axis, _)| axis)
+    }
+}
+
+/// Push constants of the gizmo pipeline, laid out like `GizmoConstants` in `gizmo.vert`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct GizmoConstants {
+    /// Colors of the X, Y and Z arrows, with the arrows' length in the first's w.
+    colors: [glm::Vec4; 3],
+}
+
+/// Creates the pipeline that draws the axis gizmo: three arrows made up in the vertex shader, turned by
+/// the camera's rotation alone, drawn into their own viewport without touching the scene's depth.
+pub unsafe fn create_gizmo_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&GIZMO_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&GIZMO_FRAGMENT_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::LINE_LIST)
+        .primitive_restart_enable(false);
+    // the viewport, scissor and width are set as the gizmo is drawn
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR, vk::DynamicState::LINE_WIDTH];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(false);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(false).depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::ALWAYS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+
+    // the scene's uniform buffer for the camera's rotation, and the arrows' colors as push constants
+    let set_layouts = &[data.descriptor_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(size_of::<GizmoConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.gizmo_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .dynamic_state(&dynamic_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.gizmo_pipeline_layout)
+        .render_pass(data.render_pass)
+        .subpass(0);
+    data.gizmo_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_gizmo_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.gizmo_pipeline, None);
+    device.destroy_pipeline_layout(data.gizmo_pipeline_layout, None);
+}
+
+/// Records the axis gizmo into swapchain image `i`'s render pass, if it is shown.
+pub unsafe fn record_gizmo_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    let Some(gizmo) = &data.gizmo else { return };
+    let viewport = gizmo.viewport(data.swapchain_extent);
+    let scissor = vk::Rect2D { offset: vk::Offset2D { x: viewport.x as i32, y: viewport.y as i32 },
+        extent: vk::Extent2D { width: viewport.width as u32, height: viewport.height as u32 } };
+    let mut colors = GIZMO_COLORS.map(|[r, g, b]| glm::vec4(r, g, b, 0.0));
+    colors[0].w = GIZMO_LENGTH;
+    let constants = GizmoConstants { colors };
+    let [min_width, max_width] = data.line_width_range;
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.gizmo_pipeline);
+    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+    device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+    device.cmd_set_line_width(command_buffer, gizmo.width.clamp(min_width, max_width));
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.gizmo_pipeline_layout, 0, &[data.descriptor_sets[i]], &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const GizmoConstants).cast::<u8>(),
+        size_of::<GizmoConstants>());
+    device.cmd_push_constants(command_buffer, data.gizmo_pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, bytes);
+    device.cmd_draw(command_buffer, 3 * AXIS_VERTICES, 1, 0, 0);
+}
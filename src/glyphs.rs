@@ -0,0 +1,147 @@
+use std::mem::size_of;
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::colormap::colormap_descriptor_set;
+use crate::config::{GLYPH_FRAGMENT_SHADER, GLYPH_MAX_COUNT, GLYPH_SCALE, GLYPH_VERTEX_SHADER, GLYPH_WIDTH};
+use crate::simulation::ParticleColoring;
+use crate::utils::{compile_shader, create_shader_module};
+
+/// Velocity glyphs: a line from each particle along its velocity, colored by its speed through the
+/// colormap, read straight out of the latest particle buffer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VelocityGlyphs {
+    /// Seconds of travel a glyph spans, so a particle moving 1 m/s gets a segment this many meters long.
+    pub scale: f32,
+    /// Glyphs drawn at most; past it only every Nth particle gets one, N as small as keeps under it.
+    pub max_count: u32,
+    /// Line width in pixels, within what the device draws; 1 where it has no wide lines.
+    pub width: f32,
+}
+
+impl Default for VelocityGlyphs {
+    fn default() -> Self {
+        Self { scale: GLYPH_SCALE, max_count: GLYPH_MAX_COUNT, width: GLYPH_WIDTH }
+    }
+}
+
+impl VelocityGlyphs {
+    /// Every how many particles one gets a glyph, out of `live` of them.
+    pub fn stride(&self, live: u32) -> u32 {
+        live.div_ceil(self.max_count.max(1)).max(1)
+    }
+}
+
+/// Push constants of the glyph pipeline, laid out like `GlyphConstants` in `glyph.vert`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct GlyphConstants {
+    scale: f32,
+    stride: u32,
+    /// Speeds at the ends of the colormap.
+    speed_range: [f32; 2],
+}
+
+/// Creates the pipeline that draws the velocity glyphs: two vertices per instance, both made up in the
+/// vertex shader from the particle the instance stands for.
+pub unsafe fn create_glyph_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&GLYPH_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&GLYPH_FRAGMENT_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::LINE_LIST)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    // the width is set as the glyphs are drawn, so changing it needs no new pipeline
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR, vk::DynamicState::LINE_WIDTH];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(false);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true).depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+
+    // the scene's uniform buffer, the solver's buffers for the particles and their count, the colormap,
+    // and the scale, stride and speed range as push constants
+    let set_layouts = &[data.descriptor_set_layout, data.compute_descriptor_set_layout, data.colormap_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(size_of::<GlyphConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.glyph_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .dynamic_state(&dynamic_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.glyph_pipeline_layout)
+        .render_pass(data.render_pass)
+        .subpass(0);
+    data.glyph_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_glyph_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.glyph_pipeline, None);
+    device.destroy_pipeline_layout(data.glyph_pipeline_layout, None);
+}
+
+/// Records the draw of the velocity glyphs into swapchain image `i`'s render pass: an instance per
+/// `stride`th slot of the particle buffer, those past the live count dropped by the vertex shader.
+pub unsafe fn record_glyph_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize,
+    glyphs: &VelocityGlyphs) {
+    let stride = glyphs.stride(data.particle_count);
+    // the particles' speeds spread over the colormap as they are when they color the particles
+    let speed_range = match data.particle_coloring {
+        ParticleColoring::Speed => data.color_range,
+        _ => [0.0, data.max_speed],
+    };
+    let constants = GlyphConstants { scale: glyphs.scale, stride, speed_range };
+    let [min_width, max_width] = data.line_width_range;
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.glyph_pipeline);
+    device.cmd_set_line_width(command_buffer, glyphs.width.clamp(min_width, max_width));
+    let sets = [data.descriptor_sets[i], data.compute_descriptor_sets[data.particle_parity], colormap_descriptor_set(data)];
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.glyph_pipeline_layout, 0, &sets, &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const GlyphConstants).cast::<u8>(),
+        size_of::<GlyphConstants>());
+    device.cmd_push_constants(command_buffer, data.glyph_pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, bytes);
+    device.cmd_draw(command_buffer, 2, data.particle_capacity.div_ceil(stride), 0, 0);
+}
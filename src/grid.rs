@@ -0,0 +1,39 @@
+use anyhow::{anyhow, Result};
+use nalgebra_glm as glm;
+
+use crate::boundary::XorShift;
+use crate::simulation::{Particle, SimParams};
+
+/// `count` particles of phase 0 at random positions in the domain, at rest.
+pub fn random_particles(sim: &SimParams, count: usize, seed: u32) -> Vec<Particle> {
+    let mut rng = XorShift(seed.max(1));
+    let (min, max) = (sim.domain_min.xyz(), sim.domain_max.xyz());
+    (0..count).map(|_| {
+        let t = glm::vec3(rng.next(), rng.next(), rng.next());
+        Particle { pos: min + (max - min).component_mul(&t), mass: sim.phase_mass(0), ..Default::default() }
+    }).collect()
+}
+
+/// Checks a sorted grid read back from the GPU: the cell ranges tile the particles in cell order, and
+/// every particle lies in the range of the cell its position maps to.
+pub fn check_sorted(sim: &SimParams, particles: &[Particle], starts: &[u32], counts: &[u32]) -> Result<()> {
+    let mut next = 0;
+    for (cell, (&start, &count)) in starts.iter().zip(counts).enumerate() {
+        if start != next {
+            return Err(anyhow!("Cell {} starts at {}, expected {}.", cell, start, next));
+        }
+        next += count;
+    }
+    if next as usize != particles.len() {
+        return Err(anyhow!("The grid holds {} particles, but {} are sorted.", next, particles.len()));
+    }
+    for (i, particle) in particles.iter().enumerate() {
+        let cell = sim.cell_of(&particle.pos) as usize;
+        let (start, count) = (starts[cell] as usize, counts[cell] as usize);
+        if i < start || i >= start + count {
+            return Err(anyhow!("Particle {} at {:?} is outside the range {}..{} of its cell {}.",
+                i, particle.pos, start, start + count, cell));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::utils::{compile_shader, create_shader_module};
+
+/// A ground plane under the scene with a grid drawn across it, fading out with distance.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GroundPlane {
+    /// Height of the plane, or `None` for just under the bottom of the domain.
+    pub height: Option<f32>,
+    /// Meters between the grid's minor lines, and minor lines per major one.
+    pub spacing: f32,
+    pub major_every: u32,
+    /// Distance from the camera at which the grid has faded out.
+    pub fade_distance: f32,
+    pub color: [f32; 3],
+    pub line_color: [f32; 3],
+    pub major_color: [f32; 3],
+}
+
+impl Default for GroundPlane {
+    fn default() -> Self {
+        Self { height: None, spacing: GROUND_SPACING, major_every: GROUND_MAJOR_EVERY,
+            fade_distance: GROUND_FADE_DISTANCE, color: GROUND_COLOR, line_color: GROUND_LINE_COLOR,
+            major_color: GROUND_MAJOR_COLOR }
+    }
+}
+
+/// Push constants of the ground pipeline, laid out like `GroundConstants` in `ground.vert`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct GroundConstants {
+    /// Colors of the plane and its lines, with the height, line spacing and fade distance in w.
+    color: glm::Vec4,
+    line_color: glm::Vec4,
+    major_color: glm::Vec4,
+    major_every: u32,
+}
+
+/// Creates the pipeline that draws the ground plane: a quad under the camera, its grid made up in the
+/// fragment shader and blended into the background as it fades, writing depth like the meshes.
+pub unsafe fn create_ground_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&GROUND_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&GROUND_FRAGMENT_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    // the quad's four corners as a strip, made up from the vertex indices alone
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_STRIP)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    // drawn first, so whatever lies below the plane is hidden by it
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true).depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+
+    // the scene's uniform buffer, and the plane's colors and sizes as push constants
+    let set_layouts = &[data.descriptor_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(size_of::<GroundConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.ground_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .dynamic_state(&dynamic_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.ground_pipeline_layout)
+        .render_pass(data.render_pass)
+        .subpass(0);
+    data.ground_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_ground_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.ground_pipeline, None);
+    device.destroy_pipeline_layout(data.ground_pipeline_layout, None);
+}
+
+/// Records the ground plane into swapchain image `i`'s render pass, if there is one.
+pub unsafe fn record_ground_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    let Some(ground) = &data.ground else { return };
+    let height = ground.height.unwrap_or(data.domain_floor - GROUND_CLEARANCE);
+    let with = |color: &[f32; 3], w: f32| glm::vec4(color[0], color[1], color[2], w);
+    let constants = GroundConstants {
+        color: with(&ground.color, height),
+        line_color: with(&ground.line_color, ground.spacing),
+        major_color: with(&ground.major_color, ground.fade_distance),
+        major_every: ground.major_every,
+    };
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.ground_pipeline);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.ground_pipeline_layout, 0, &[data.descriptor_sets[i]], &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const GroundConstants).cast::<u8>(),
+        size_of::<GroundConstants>());
+    device.cmd_push_constants(command_buffer, data.ground_pipeline_layout,
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0, bytes);
+    device.cmd_draw(command_buffer, 4, 1, 0, 0);
+}
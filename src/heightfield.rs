@@ -0,0 +1,127 @@
+use std::fs::File;
+use anyhow::{anyhow, Context, Result};
+use nalgebra_glm as glm;
+
+use crate::model::Vertex;
+use crate::sdf::SdfInfo;
+
+/// Terrain as a height over the x-z plane, sampled on a regular grid in world space. Between samples
+/// the height is bilinear; everything below it is solid.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Heightfield {
+    /// World-space x and z of sample (0, 0).
+    pub origin: glm::Vec2,
+    /// Sample spacing along x and z.
+    pub cell: glm::Vec2,
+    /// Samples along x and z, at least two each.
+    pub dims: [u32; 2],
+    /// World-space heights, x fastest.
+    pub heights: Vec<f32>,
+}
+
+impl Heightfield {
+    /// Samples `height`, a function of the fractions `(u, v)` of the way across the terrain along x and
+    /// z giving the fraction of the way from `min.y` to `max.y`, on a `dims` grid spanning `min` to `max`
+    /// in x and z.
+    pub fn from_fn(min: &glm::Vec3, max: &glm::Vec3, dims: [u32; 2], height: impl Fn(f32, f32) -> f32) -> Self {
+        let dims = dims.map(|d| d.max(2));
+        let heights = (0..dims[1]).flat_map(|z| (0..dims[0]).map(move |x| (x, z)))
+            .map(|(x, z)| {
+                let (u, v) = (x as f32 / (dims[0] - 1) as f32, z as f32 / (dims[1] - 1) as f32);
+                min.y + height(u, v) * (max.y - min.y)
+            })
+            .collect();
+        let extent = max - min;
+        Self { origin: glm::vec2(min.x, min.z), cell: glm::vec2(extent.x / (dims[0] - 1) as f32,
+            extent.z / (dims[1] - 1) as f32), dims, heights }
+    }
+
+    /// Loads a grayscale PNG as terrain spanning `min` to `max`: columns run along x and rows along z,
+    /// and black is `min.y` and white `max.y`. Color images are read by their mean channel.
+    pub fn load_png(path: &str, min: &glm::Vec3, max: &glm::Vec3) -> Result<Self> {
+        let mut decoder = png::Decoder::new(File::open(path).with_context(|| format!("Cannot open heightfield {}.", path))?);
+        // palettes to colors and low bit depths to bytes, so every sample is one or two bytes
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().with_context(|| format!("Cannot read heightfield {}.", path))?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        let (width, height) = (info.width as usize, info.height as usize);
+        if width < 2 || height < 2 {
+            return Err(anyhow!("Heightfield {} is {}x{}; it needs at least 2x2 pixels.", path, width, height));
+        }
+        let wide = info.bit_depth == png::BitDepth::Sixteen;
+        let (samples, channels) = (if wide { 2 } else { 1 }, info.color_type.samples());
+        // alpha does not count towards the height
+        let alpha = matches!(info.color_type, png::ColorType::GrayscaleAlpha | png::ColorType::Rgba);
+        let colors = channels - alpha as usize;
+        let levels = (0..width * height).map(|pixel| {
+            let start = pixel * channels * samples;
+            (0..colors).map(|c| {
+                let i = start + c * samples;
+                if wide { u16::from_be_bytes([buffer[i], buffer[i + 1]]) as f32 / 65535.0 } else { buffer[i] as f32 / 255.0 }
+            }).sum::<f32>() / colors as f32
+        }).collect::<Vec<_>>();
+        Ok(Self::from_fn(min, max, [width as u32, height as u32], |u, v| {
+            levels[(v * (height - 1) as f32).round() as usize * width + (u * (width - 1) as f32).round() as usize]
+        }))
+    }
+
+    fn at(&self, x: u32, z: u32) -> f32 {
+        self.heights[(z * self.dims[0] + x) as usize]
+    }
+
+    /// Bilinear height at `(x, z)`, held at the edge value past the edges.
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        let g = (glm::vec2(x, z) - self.origin).component_div(&self.cell);
+        let g = glm::vec2(g.x.clamp(0.0, (self.dims[0] - 1) as f32), g.y.clamp(0.0, (self.dims[1] - 1) as f32));
+        let base = [(g.x as u32).min(self.dims[0] - 2), (g.y as u32).min(self.dims[1] - 2)];
+        let t = glm::vec2(g.x - base[0] as f32, g.y - base[1] as f32);
+        let near = self.at(base[0], base[1]) * (1.0 - t.x) + self.at(base[0] + 1, base[1]) * t.x;
+        let far = self.at(base[0], base[1] + 1) * (1.0 - t.x) + self.at(base[0] + 1, base[1] + 1) * t.x;
+        near * (1.0 - t.y) + far * t.y
+    }
+
+    /// Upward unit normal at `(x, z)`, from central differences of the height a cell either side.
+    pub fn normal(&self, x: f32, z: f32) -> glm::Vec3 {
+        let (dx, dz) = (self.cell.x, self.cell.y);
+        let slope_x = (self.height(x + dx, z) - self.height(x - dx, z)) / (2.0 * dx);
+        let slope_z = (self.height(x, z + dz) - self.height(x, z - dz)) / (2.0 * dz);
+        glm::normalize(&glm::vec3(-slope_x, 1.0, -slope_z))
+    }
+
+    /// Signed distance from `p` to the terrain, negative below it, to first order: the height above
+    /// the ground measured along the normal there.
+    pub fn distance(&self, p: &glm::Vec3) -> f32 {
+        (p.y - self.height(p.x, p.z)) * self.normal(p.x, p.z).y
+    }
+
+    /// The terrain surface in world space as a triangle mesh of `color`, counter-clockwise seen from above.
+    pub fn mesh(&self, color: glm::Vec3) -> (Vec<Vertex>, Vec<u32>) {
+        let [nx, nz] = self.dims;
+        let vertices = (0..nz).flat_map(|z| (0..nx).map(move |x| (x, z))).map(|(x, z)| {
+            let (px, pz) = (self.origin.x + x as f32 * self.cell.x, self.origin.y + z as f32 * self.cell.y);
+            Vertex::new(glm::vec3(px, self.at(x, z), pz), color, self.normal(px, pz))
+        }).collect();
+        let indices = (0..nz - 1).flat_map(|z| (0..nx - 1).map(move |x| (x, z))).flat_map(|(x, z)| {
+            let a = z * nx + x;
+            let (b, c) = (a + nx, a + 1);
+            [a, b, c, c, b, b + 1]
+        }).collect();
+        (vertices, indices)
+    }
+
+    /// The terrain moved by `offset`.
+    pub fn translated(&self, offset: &glm::Vec3) -> Self {
+        Self { origin: self.origin + glm::vec2(offset.x, offset.z), heights: self.heights.iter().map(|h| h + offset.y).collect(),
+            ..self.clone() }
+    }
+
+    /// GPU header for this terrain, whose heights start at `offset` in the shared SDF value buffer.
+    pub fn info(&self, offset: u32, refine: bool) -> SdfInfo {
+        SdfInfo {
+            origin: glm::vec4(self.origin.x, self.cell.y, self.origin.y, self.cell.x),
+            dims: [self.dims[0], self.dims[1], 1, offset],
+            flags: [refine as u32, SdfInfo::HEIGHTFIELD, 0, 0],
+        }
+    }
+}
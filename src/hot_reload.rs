@@ -0,0 +1,93 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+use log::*;
+
+use crate::config::{SHADER_DIR, SHADER_POLL_MS};
+use crate::utils::{compile_shader, shader_includes};
+
+/// Watches the shader sources and what they include for changes from a thread of its own, which compiles each changed one
+/// to check it. Those that compile are handed on for the app to remake what is built from them; those
+/// that do not have shaderc's diagnostics logged, and what is built from them keeps running.
+#[derive(Debug)]
+pub struct ShaderWatcher {
+    changed: Receiver<String>,
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+/// The stage a shader source is compiled as, by its extension.
+fn shader_kind(path: &str) -> Option<shaderc::ShaderKind> {
+    match path.rsplit('.').next()? {
+        "vert" => Some(shaderc::ShaderKind::Vertex),
+        "frag" => Some(shaderc::ShaderKind::Fragment),
+        "comp" => Some(shaderc::ShaderKind::Compute),
+        _ => None,
+    }
+}
+
+/// When each shader in `SHADER_DIR` and of `extra`, and each file they include, was last modified.
+fn modified_times(extra: &[String]) -> HashMap<String, SystemTime> {
+    let listed = fs::read_dir(SHADER_DIR).into_iter().flatten().flatten()
+        .map(|entry| format!("{}/{}", SHADER_DIR, entry.file_name().to_string_lossy()));
+    let shaders = listed.chain(extra.iter().cloned()).filter(|path| shader_kind(path).is_some()).collect::<HashSet<_>>();
+    let includes = shaders.iter().flat_map(|path| shader_includes(path)).collect::<Vec<_>>();
+    shaders.into_iter().chain(includes)
+        .filter_map(|path| Some((path.clone(), fs::metadata(&path).and_then(|m| m.modified()).ok()?)))
+        .collect()
+}
+
+impl ShaderWatcher {
+    /// Starts watching `SHADER_DIR` and the shaders at `extra`, which may lie outside it.
+    pub fn start(extra: Vec<String>) -> Self {
+        let (sender, changed) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let worker = thread::spawn(move || {
+            let mut seen = modified_times(&extra);
+            while !stopped.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(SHADER_POLL_MS));
+                let now = modified_times(&extra);
+                let edited = now.iter().filter(|&(path, time)| seen.get(path) != Some(time))
+                    .map(|(path, _)| path.clone()).collect::<HashSet<_>>();
+                // an edited include stands for every shader including it
+                let shaders = now.keys().filter(|path| !edited.is_empty() && shader_kind(path).is_some());
+                for path in shaders.filter(|&path| edited.contains(path) || !shader_includes(path).is_disjoint(&edited)) {
+                    match compile_shader(path, shader_kind(path).unwrap()) {
+                        Ok(_) => {
+                            if sender.send(path.clone()).is_err() {
+                                return;
+                            }
+                        }
+                        Err(e) => error!("{}\nKeeping what was built from it before.", e),
+                    }
+                }
+                seen = now;
+            }
+        });
+        Self { changed, stop, worker: Some(worker) }
+    }
+
+    /// The shaders changed and compiled since last asked, each once.
+    pub fn changed(&self) -> Vec<String> {
+        let mut changed = self.changed.try_iter().collect::<Vec<_>>();
+        changed.sort();
+        changed.dedup();
+        changed
+    }
+}
+
+impl Drop for ShaderWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                error!("The shader watching thread panicked.");
+            }
+        }
+    }
+}
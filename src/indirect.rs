@@ -0,0 +1,275 @@
+use std::mem::size_of;
+use anyhow::{anyhow, Result};
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+use vulkanalia::vk::KhrDrawIndirectCountExtension;
+
+use crate::appdata::AppData;
+use crate::camera::{projection, scene_model};
+use crate::config::{MESH_CULL_SHADER, OBJECT_BLOCKS};
+use crate::cull::frustum_planes;
+use crate::model::Object;
+use crate::oit::oit_active;
+use crate::secondary::invalidate_secondaries;
+use crate::simulation::{compute_barrier, create_storage_buffer, transfer_barrier, write_mapped};
+use crate::utils::{MeshDepth, MeshPipeline, MeshPrimitive, OBJECT_ELEMENT, ShaderConfig, create_compute_pipeline,
+    create_index_buffer, create_vertex_buffer, record_object_block};
+
+/// Pipelines the indirect draws may be split between, each counted apart.
+const INDIRECT_GROUPS: usize = 8;
+
+/// Bytes before the commands in an indirect buffer: a draw count per group.
+const INDIRECT_HEADER: u64 = (INDIRECT_GROUPS * size_of::<u32>()) as u64;
+
+/// A submesh for the cull pass to test and draw, laid out to match its `Draw`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct IndirectDraw {
+    bounds_min: glm::Vec3,
+    index_count: u32,
+    bounds_max: glm::Vec3,
+    first_index: u32,
+    vertex_offset: i32,
+    /// Element of the object buffer with the submesh's constants, which the draw passes as its instance.
+    object: u32,
+    group: u32,
+    /// The group's first slot among the commands.
+    first: u32,
+}
+
+/// Push constants of the cull pass, laid out to match its `MeshCull` block.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct MeshCullConstants {
+    planes: [glm::Vec4; 6],
+    draw_count: u32,
+}
+
+/// Whether this frame draws the objects' filled meshes indirectly: with it on, where the device can, and
+/// with the objects merged and filled rather than in wireframe.
+pub(crate) fn indirect_active(data: &AppData) -> bool {
+    data.indirect_draws && data.multi_draw_indirect && data.object_vertex_buffer != vk::Buffer::null()
+        && !(data.mesh_wireframe.is_some() && data.fill_mode_non_solid)
+}
+
+/// Whether the draw of `obj` through `key` is among the indirect ones rather than recorded on its own.
+pub(crate) fn drawn_indirectly(data: &AppData, key: &MeshPipeline, obj: &Object) -> bool {
+    indirect_active(data) && key.primitive == MeshPrimitive::Triangles && obj.vertex_buffer == data.object_vertex_buffer
+}
+
+/// Moves the meshes of every object into one vertex and one index buffer, the objects drawing out of
+/// them from their `base_vertex` and `base_index`, and frees the ones the objects had. Nothing may be in
+/// flight.
+pub unsafe fn merge_object_geometry(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let mut merged = Object::default();
+    for obj in &mut data.objects {
+        obj.base_vertex = merged.vertices.len() as i32;
+        obj.base_index = merged.indices.len() as u32;
+        merged.vertices.extend_from_slice(&obj.vertices);
+        merged.indices.extend_from_slice(&obj.indices);
+    }
+    if merged.indices.is_empty() {
+        return Ok(());
+    }
+    create_vertex_buffer(instance, device, data, &mut merged)?;
+    create_index_buffer(instance, device, data, &mut merged)?;
+    for obj in &mut data.objects {
+        if obj.vertex_buffer != data.object_vertex_buffer {
+            device.destroy_buffer(obj.index_buffer, None);
+            device.free_memory(obj.index_buffer_memory, None);
+            device.destroy_buffer(obj.vertex_buffer, None);
+            device.free_memory(obj.vertex_buffer_memory, None);
+        }
+        obj.vertex_buffer = merged.vertex_buffer;
+        obj.vertex_buffer_memory = vk::DeviceMemory::null();
+        obj.index_buffer = merged.index_buffer;
+        obj.index_buffer_memory = vk::DeviceMemory::null();
+    }
+    destroy_object_geometry(device, data);
+    invalidate_secondaries(data);
+    data.object_vertex_buffer = merged.vertex_buffer;
+    data.object_vertex_buffer_memory = merged.vertex_buffer_memory;
+    data.object_index_buffer = merged.index_buffer;
+    data.object_index_buffer_memory = merged.index_buffer_memory;
+    Ok(())
+}
+
+pub unsafe fn destroy_object_geometry(device: &Device, data: &mut AppData) {
+    device.destroy_buffer(data.object_index_buffer, None);
+    device.free_memory(data.object_index_buffer_memory, None);
+    device.destroy_buffer(data.object_vertex_buffer, None);
+    device.free_memory(data.object_vertex_buffer_memory, None);
+    data.object_vertex_buffer = vk::Buffer::null();
+    data.object_index_buffer = vk::Buffer::null();
+}
+
+/// Makes each swapchain image's buffers of draws and of commands, room for a draw per object block, the
+/// sets binding them with the image's object buffer, and the cull pass.
+pub unsafe fn create_indirect(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    // binding 0: the object buffer; 1: the draws to cull; 2: the counts and the commands left
+    let bindings = (0..3).map(|binding| vk::DescriptorSetLayoutBinding::builder()
+        .binding(binding)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .build()).collect::<Vec<_>>();
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+    data.mesh_cull_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    let images = data.swapchain_images.len() as u32;
+    let pool_size = vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::STORAGE_BUFFER).descriptor_count(3 * images);
+    let pool_sizes = &[pool_size];
+    let info = vk::DescriptorPoolCreateInfo::builder().pool_sizes(pool_sizes).max_sets(images);
+    data.mesh_cull_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    let layouts = vec![data.mesh_cull_set_layout; images as usize];
+    let info = vk::DescriptorSetAllocateInfo::builder().descriptor_pool(data.mesh_cull_descriptor_pool).set_layouts(&layouts);
+    data.mesh_cull_sets = device.allocate_descriptor_sets(&info)?;
+    let input_size = size_of::<IndirectDraw>() as u64 * OBJECT_BLOCKS as u64;
+    let size = INDIRECT_HEADER + size_of::<vk::DrawIndexedIndirectCommand>() as u64 * OBJECT_BLOCKS as u64;
+    for k in 0..images as usize {
+        let (input, input_memory) = create_storage_buffer(instance, device, data, input_size,
+            vk::BufferUsageFlags::empty(), true)?;
+        let (buffer, memory) = create_storage_buffer(instance, device, data, size,
+            vk::BufferUsageFlags::INDIRECT_BUFFER, false)?;
+        data.indirect_input_buffers.push(input);
+        data.indirect_input_buffers_memory.push(input_memory);
+        data.indirect_buffers.push(buffer);
+        data.indirect_buffers_memory.push(memory);
+        let infos = [data.object_buffers[k], input, buffer].map(|buffer| [vk::DescriptorBufferInfo::builder()
+            .buffer(buffer).offset(0).range(vk::WHOLE_SIZE as u64).build()]);
+        let writes = infos.iter().enumerate().map(|(binding, info)| vk::WriteDescriptorSet::builder()
+            .dst_set(data.mesh_cull_sets[k]).dst_binding(binding as u32).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER).buffer_info(info).build())
+            .collect::<Vec<_>>();
+        device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+    }
+
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<MeshCullConstants>() as u32);
+    let set_layouts = &[data.mesh_cull_set_layout];
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.mesh_cull_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let constants = [data.workgroup_size, data.draw_indirect_count as u32];
+    data.mesh_cull_pipeline = create_compute_pipeline(device, data.pipeline_cache, data.mesh_cull_pipeline_layout,
+        &MESH_CULL_SHADER.to_string(), &constants)?;
+    Ok(())
+}
+
+pub unsafe fn destroy_indirect(device: &Device, data: &mut AppData) {
+    device.destroy_pipeline(data.mesh_cull_pipeline, None);
+    device.destroy_pipeline_layout(data.mesh_cull_pipeline_layout, None);
+    device.destroy_descriptor_pool(data.mesh_cull_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(data.mesh_cull_set_layout, None);
+    data.indirect_input_buffers.drain(..).for_each(|b| device.destroy_buffer(b, None));
+    data.indirect_input_buffers_memory.drain(..).for_each(|m| device.free_memory(m, None));
+    data.indirect_buffers.drain(..).for_each(|b| device.destroy_buffer(b, None));
+    data.indirect_buffers_memory.drain(..).for_each(|m| device.free_memory(m, None));
+    data.indirect_groups.clear();
+}
+
+/// Writes the objects' filled submeshes to swapchain image `image`'s draws to cull, grouped by the
+/// pipeline they are drawn through, after `update_object_blocks` has placed their constants. With the
+/// transparency targets drawn into, the translucent submeshes are left to them.
+pub unsafe fn update_indirect_draws(device: &Device, data: &mut AppData, image: usize) -> Result<()> {
+    data.indirect_groups.clear();
+    if !indirect_active(data) {
+        return Ok(());
+    }
+    let weighted = oit_active(data);
+    let depth = if data.depth_prepass { MeshDepth::Equal } else { MeshDepth::Test };
+    let mut draws = Vec::new();
+    for obj in data.objects.iter().filter(|obj| !obj.line_list && !obj.indices.is_empty()) {
+        let key = MeshPipeline { kind: obj.material_kind, primitive: MeshPrimitive::Triangles, depth, indirect: true,
+            config: ShaderConfig::of(data, obj.material_kind) };
+        let [bounds_min, bounds_max] = obj.local_bounds;
+        for (submesh, offset) in obj.draws().iter().zip(&obj.block_offsets) {
+            if weighted && obj.translucent(submesh) {
+                continue;
+            }
+            draws.push((key, IndirectDraw { bounds_min, index_count: submesh.index_count, bounds_max,
+                first_index: obj.base_index + submesh.first_index, vertex_offset: obj.base_vertex,
+                object: offset / OBJECT_ELEMENT as u32, group: 0, first: 0 }));
+        }
+    }
+    draws.sort_by_key(|(key, _)| *key);
+    for (slot, (key, draw)) in draws.iter_mut().enumerate() {
+        match data.indirect_groups.last_mut() {
+            Some((last, _, count)) if last == key => *count += 1,
+            _ => data.indirect_groups.push((*key, slot as u32, 1)),
+        }
+        draw.group = data.indirect_groups.len() as u32 - 1;
+        draw.first = data.indirect_groups.last().map_or(0, |group| group.1);
+    }
+    if data.indirect_groups.len() > INDIRECT_GROUPS {
+        data.indirect_groups.clear();
+        return Err(anyhow!("More than {} pipelines among the indirect draws.", INDIRECT_GROUPS));
+    }
+    let draws = draws.into_iter().map(|(_, draw)| draw).collect::<Vec<_>>();
+    write_mapped(device, data.indirect_input_buffers_memory[image], &draws)
+}
+
+/// Records the cull of swapchain image `image`'s indirect draws against the frustum `data.cull_view`
+/// sees, as `cull_objects` tests the objects on the CPU, leaving the commands of those left for the
+/// frame's passes to draw.
+pub unsafe fn record_mesh_cull(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, image: usize) {
+    let Some(&(_, first, count)) = data.indirect_groups.last() else {
+        return;
+    };
+    let planes = match data.frustum_culling.filter(|_| data.split_screen.is_none()) {
+        Some(culling) => frustum_planes(&(projection(data.swapchain_extent) * data.cull_view))
+            .map(|plane| plane + glm::vec4(0.0, 0.0, 0.0, culling.margin)),
+        None => [glm::vec4(0.0, 0.0, 0.0, 1.0); 6],
+    };
+    // the pass tests the boxes as placed by the objects' transforms alone, before the scene model
+    let model = glm::transpose(&scene_model());
+    let constants = MeshCullConstants { planes: planes.map(|plane| model * plane), draw_count: first + count };
+    let buffer = data.indirect_buffers[image];
+    device.cmd_fill_buffer(command_buffer, buffer, 0, INDIRECT_HEADER, 0);
+    transfer_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, data.mesh_cull_pipeline);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, data.mesh_cull_pipeline_layout, 0,
+        &[data.mesh_cull_sets[image]], &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const MeshCullConstants).cast::<u8>(),
+        size_of::<MeshCullConstants>());
+    device.cmd_push_constants(command_buffer, data.mesh_cull_pipeline_layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+    device.cmd_dispatch(command_buffer, constants.draw_count.div_ceil(data.workgroup_size), 1, 1);
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::DRAW_INDIRECT,
+        vk::AccessFlags::INDIRECT_COMMAND_READ);
+}
+
+/// Records swapchain image `image`'s indirect draws left by the cull, with set 0 bound: through their
+/// pipelines, or their depth pre-pass variants with `prepass`. Without a count from the GPU, every draw
+/// is recorded and the culled ones draw no instances.
+pub(crate) unsafe fn record_indirect_draws(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    image: usize, prepass: bool) {
+    if data.indirect_groups.is_empty() {
+        return;
+    }
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[data.object_vertex_buffer], &[0]);
+    device.cmd_bind_index_buffer(command_buffer, data.object_index_buffer, 0, vk::IndexType::UINT32);
+    // the draws find their constants in the object buffer by instance, whatever the dynamic offset
+    record_object_block(device, data, command_buffer, image, 0);
+    let buffer = data.indirect_buffers[image];
+    let stride = size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+    for (group, &(key, first, count)) in data.indirect_groups.iter().enumerate() {
+        let key = if prepass { MeshPipeline { depth: MeshDepth::Prepass, ..key } } else { key };
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.mesh_pipelines[&key]);
+        let offset = INDIRECT_HEADER + first as u64 * stride as u64;
+        if data.draw_indirect_count {
+            device.cmd_draw_indexed_indirect_count_khr(command_buffer, buffer, offset, buffer,
+                (group * size_of::<u32>()) as u64, count, stride);
+        } else {
+            device.cmd_draw_indexed_indirect(command_buffer, buffer, offset, count, stride);
+        }
+    }
+}
+
+/// The pipelines this frame's indirect draws are recorded through, for `prepare_mesh_pipelines` to make.
+pub(crate) fn indirect_pipelines(data: &AppData) -> Vec<MeshPipeline> {
+    let prepass = data.depth_prepass.then_some(MeshDepth::Prepass);
+    data.indirect_groups.iter().flat_map(|(key, _, _)| [Some(*key), prepass.map(|depth| MeshPipeline { depth, ..*key })])
+        .flatten().collect()
+}
@@ -0,0 +1,156 @@
+use std::f32::consts::PI;
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+/// SPH smoothing kernel family. The GPU passes select theirs with specialization constant 0 set to
+/// the discriminant, so keep the values in sync with `KERNEL_*` in the compute shaders.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KernelKind {
+    /// Poly6 for density with the spiky gradient for pressure (Müller et al. 2003).
+    #[default]
+    Poly6Spiky = 0,
+    /// M4 cubic B-spline (Monaghan 1992), rescaled to a support radius of `h`.
+    CubicSpline = 1,
+    /// Wendland C2 (Wendland 1995; Dehnen & Aly 2012).
+    WendlandC2 = 2,
+}
+
+/// A kernel with its normalization constants evaluated for one smoothing radius `h` (the support radius).
+#[derive(Copy, Clone, Debug)]
+pub struct Kernel {
+    pub kind: KernelKind,
+    pub h: f32,
+    /// Scale of `W`.
+    pub w_norm: f32,
+    /// Scale of `dW/dr`.
+    pub grad_norm: f32,
+    /// Scale of the viscosity Laplacian, which is Müller's viscosity kernel for every kind.
+    pub visc_norm: f32,
+}
+
+impl Kernel {
+    pub fn new(kind: KernelKind, h: f32) -> Self {
+        let (w_norm, grad_norm) = match kind {
+            KernelKind::Poly6Spiky => (315.0 / (64.0 * PI * h.powi(9)), -45.0 / (PI * h.powi(6))),
+            KernelKind::CubicSpline => (8.0 / (PI * h.powi(3)), 48.0 / (PI * h.powi(4))),
+            KernelKind::WendlandC2 => (21.0 / (2.0 * PI * h.powi(3)), -210.0 / (PI * h.powi(4))),
+        };
+        Self { kind, h, w_norm, grad_norm, visc_norm: 45.0 / (PI * h.powi(6)) }
+    }
+
+    /// Kernel value at distance `r`.
+    pub fn w(&self, r: f32) -> f32 {
+        let q = r / self.h;
+        if q >= 1.0 {
+            return 0.0;
+        }
+        match self.kind {
+            KernelKind::Poly6Spiky => {
+                let w = self.h * self.h - r * r;
+                self.w_norm * w * w * w
+            }
+            KernelKind::CubicSpline if q <= 0.5 => self.w_norm * (6.0 * (q * q * q - q * q) + 1.0),
+            KernelKind::CubicSpline => self.w_norm * 2.0 * (1.0 - q).powi(3),
+            KernelKind::WendlandC2 => self.w_norm * (1.0 - q).powi(4) * (1.0 + 4.0 * q),
+        }
+    }
+
+    /// Radial derivative `dW/dr` at distance `r`.
+    pub fn dw(&self, r: f32) -> f32 {
+        let q = r / self.h;
+        if q >= 1.0 {
+            return 0.0;
+        }
+        match self.kind {
+            KernelKind::Poly6Spiky => self.grad_norm * (self.h - r) * (self.h - r),
+            KernelKind::CubicSpline if q <= 0.5 => self.grad_norm * (3.0 * q * q - 2.0 * q),
+            KernelKind::CubicSpline => -self.grad_norm * (1.0 - q) * (1.0 - q),
+            KernelKind::WendlandC2 => self.grad_norm * q * (1.0 - q).powi(3),
+        }
+    }
+
+    /// Gradient of `W` with respect to the first particle, where `r` points from the neighbor to it.
+    pub fn grad(&self, r: &glm::Vec3) -> glm::Vec3 {
+        let dist = glm::length(r);
+        if dist <= 1e-6 {
+            return glm::Vec3::zeros();
+        }
+        r * (self.dw(dist) / dist)
+    }
+
+    /// Laplacian of the viscosity kernel at distance `r`.
+    pub fn viscosity_laplacian(&self, r: f32) -> f32 {
+        if r >= self.h { 0.0 } else { self.visc_norm * (self.h - r) }
+    }
+
+    /// `(w_norm, grad_norm, visc_norm, 0)` as the compute shaders expect them.
+    pub fn coefficients(&self) -> glm::Vec4 {
+        glm::vec4(self.w_norm, self.grad_norm, self.visc_norm, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [KernelKind; 3] = [KernelKind::Poly6Spiky, KernelKind::CubicSpline, KernelKind::WendlandC2];
+
+    /// Simpson's rule of `f` over `[0, h]`.
+    fn integrate(h: f32, f: impl Fn(f32) -> f64) -> f64 {
+        let n = 2000;
+        let step = h as f64 / n as f64;
+        let sum = (0..=n).map(|i| {
+            let weight = if i == 0 || i == n { 1.0 } else if i % 2 == 1 { 4.0 } else { 2.0 };
+            weight * f((i as f64 * step) as f32)
+        }).sum::<f64>();
+        sum * step / 3.0
+    }
+
+    #[test]
+    fn kernels_are_normalized() {
+        for kind in KINDS {
+            for h in [0.05, 1.0] {
+                let kernel = Kernel::new(kind, h);
+                let mass = integrate(h, |r| 4.0 * std::f64::consts::PI * (r as f64).powi(2) * kernel.w(r) as f64);
+                assert!((mass - 1.0).abs() < 1e-3, "{:?} with h = {} integrates to {}", kind, h, mass);
+            }
+        }
+    }
+
+    /// What `dw` is the derivative of: `W`, but for Poly6Spiky, whose gradient is the spiky kernel's.
+    fn differentiated(kernel: &Kernel, r: f32) -> f32 {
+        match kernel.kind {
+            KernelKind::Poly6Spiky => 15.0 / (PI * kernel.h.powi(6)) * (kernel.h - r).powi(3),
+            _ => kernel.w(r),
+        }
+    }
+
+    #[test]
+    fn spiky_kernel_is_normalized() {
+        let kernel = Kernel::new(KernelKind::Poly6Spiky, 1.0);
+        let mass = integrate(1.0, |r| 4.0 * std::f64::consts::PI * (r as f64).powi(2) * differentiated(&kernel, r) as f64);
+        assert!((mass - 1.0).abs() < 1e-3, "the spiky kernel integrates to {}", mass);
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        let eps = 1e-3;
+        for kind in KINDS {
+            let kernel = Kernel::new(kind, 1.0);
+            let scale = (1..100).map(|i| kernel.dw(i as f32 / 100.0).abs()).fold(0.0, f32::max);
+            for i in 1..100 {
+                let r = i as f32 / 100.0;
+                let difference = (differentiated(&kernel, r + eps) - differentiated(&kernel, r - eps)) / (2.0 * eps);
+                assert!((kernel.dw(r) - difference).abs() < 1e-2 * scale,
+                    "{:?} at r = {}: dw {} against {}", kind, r, kernel.dw(r), difference);
+            }
+            // the vector gradient lies along r, back toward the neighbor as W falls off with distance
+            let r = glm::vec3(0.3, -0.2, 0.1);
+            let grad = kernel.grad(&r);
+            assert!((grad - r * (kernel.dw(glm::length(&r)) / glm::length(&r))).norm() < 1e-6);
+            assert!(grad.dot(&r) < 0.0, "{:?}'s gradient points away from the neighbor", kind);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use nalgebra_glm as glm;
+
+use crate::config::DIRECTIONAL_LIGHT_DISTANCE;
+
+/// How a light's brightness falls off over the scene.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LightKind {
+    /// From infinitely far along its direction, the same everywhere.
+    Directional,
+    /// From its position in every direction, attenuated with distance.
+    Point,
+    /// From its position within a cone about its direction, attenuated with distance and fading out
+    /// between the cone's inner and outer angles.
+    Spot,
+}
+
+/// A light the meshes are shaded by. The first of `AppData::lights` is the key light, the one that
+/// casts the shadows and lights the particles and the fluid surface.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub position: glm::Vec3,
+    /// Where the light shines, for directional and spot lights.
+    pub direction: glm::Vec3,
+    pub color: glm::Vec3,
+    /// Constant, linear and quadratic terms of the distance falloff of point and spot lights.
+    pub attenuation: glm::Vec3,
+    /// Degrees off the direction a spot light is at full strength and fades out to nothing by.
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+impl Light {
+    pub fn directional(direction: glm::Vec3, color: glm::Vec3) -> Self {
+        Self { kind: LightKind::Directional, position: glm::Vec3::zeros(), direction: glm::normalize(&direction), color,
+            attenuation: glm::vec3(1.0, 0.0, 0.0), inner_angle: 0.0, outer_angle: 0.0 }
+    }
+
+    pub fn point(position: glm::Vec3, color: glm::Vec3, attenuation: glm::Vec3) -> Self {
+        Self { kind: LightKind::Point, position, direction: glm::vec3(0.0, -1.0, 0.0), color, attenuation,
+            inner_angle: 0.0, outer_angle: 0.0 }
+    }
+
+    /// A spot light at `position` aimed at `target`.
+    pub fn spot(position: glm::Vec3, target: glm::Vec3, color: glm::Vec3, attenuation: glm::Vec3, inner_angle: f32,
+        outer_angle: f32) -> Self {
+        Self { kind: LightKind::Spot, position, direction: glm::normalize(&(target - position)), color, attenuation,
+            inner_angle: inner_angle.min(outer_angle), outer_angle }
+    }
+
+    /// Where the light is for its shadows and the shaders lit by one light: its position, or far back
+    /// along its direction for a directional light.
+    pub fn origin(&self) -> glm::Vec3 {
+        match self.kind {
+            LightKind::Directional => -self.direction * DIRECTIONAL_LIGHT_DISTANCE,
+            _ => self.position,
+        }
+    }
+
+    /// Moves the light to `origin`, keeping a spot light aimed where it was and turning a directional
+    /// light to shine from there towards the world origin.
+    pub fn move_to(&mut self, origin: glm::Vec3) {
+        match self.kind {
+            LightKind::Directional => self.direction = glm::normalize(&-origin),
+            _ => self.position = origin,
+        }
+    }
+}
+
+impl Default for Light {
+    /// A white point light at (1, 1, 1) that does not fall off.
+    fn default() -> Self {
+        Self::point(glm::vec3(1.0, 1.0, 1.0), glm::vec3(1.0, 1.0, 1.0), glm::vec3(1.0, 0.0, 0.0))
+    }
+}
+
+/// A key light from above to one side that casts the shadows, a dimmer directional fill from the
+/// other side and a rim light from behind, all aimed at the middle of the scene.
+pub fn three_point_rig() -> Vec<Light> {
+    let origin = glm::Vec3::zeros();
+    let falloff = glm::vec3(1.0, 0.09, 0.032);
+    vec![
+        Light::spot(glm::vec3(1.5, 2.0, 1.5), origin, glm::vec3(1.0, 0.96, 0.9), falloff, 30.0, 40.0),
+        Light::directional(glm::vec3(1.0, -0.5, -1.0), glm::vec3(0.3, 0.33, 0.4)),
+        Light::point(glm::vec3(0.0, 1.5, -2.0), glm::vec3(0.7, 0.7, 0.75), falloff),
+    ]
+}
+
+/// What the meshes show in place of their shading, to check the lighting by its parts.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ShadingDebug {
+    /// The shaded color.
+    #[default]
+    Off,
+    /// The linear color the light is reflected in, from the vertices, the material and the texture.
+    Albedo,
+    /// The shading normal, after the normal map, each axis from -1 to 1 shown from 0 to 1.
+    Normals,
+    /// The light reaching the surface, as it would color a white one.
+    Lighting,
+}
+
+impl ShadingDebug {
+    pub fn next(self) -> Self {
+        match self {
+            ShadingDebug::Off => ShadingDebug::Albedo,
+            ShadingDebug::Albedo => ShadingDebug::Normals,
+            ShadingDebug::Normals => ShadingDebug::Lighting,
+            ShadingDebug::Lighting => ShadingDebug::Off,
+        }
+    }
+}
+
+/// A light laid out like `Light` in `shader.frag`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GpuLight {
+    position: glm::Vec3,
+    /// 0 directional, 1 point, 2 spot.
+    kind: u32,
+    direction: glm::Vec3,
+    /// Cosines of the spot light's inner and outer angles.
+    cos_inner: f32,
+    color: glm::Vec3,
+    cos_outer: f32,
+    attenuation: glm::Vec3,
+    _pad: f32,
+}
+
+impl From<&Light> for GpuLight {
+    fn from(light: &Light) -> Self {
+        let kind = match light.kind {
+            LightKind::Directional => 0,
+            LightKind::Point => 1,
+            LightKind::Spot => 2,
+        };
+        Self { position: light.position, kind, direction: light.direction, cos_inner: light.inner_angle.to_radians().cos(),
+            color: light.color, cos_outer: light.outer_angle.to_radians().cos(), attenuation: light.attenuation, _pad: 0.0 }
+    }
+}
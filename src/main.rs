@@ -6,16 +6,117 @@ pub mod config;
 pub mod utils;
 pub mod camera;
 pub mod model;
+pub mod simulation;
+pub mod emitter;
+pub mod sdf;
+pub mod boundary;
+pub mod rigid;
+pub mod kernel;
+pub mod solver;
+pub mod checkpoint;
+pub mod export;
+pub mod grid;
+pub mod scan;
+pub mod tune;
+pub mod timing;
+pub mod scene;
+pub mod diagnostics;
+pub mod conservation;
+pub mod tunables;
+pub mod replay;
+pub mod pick;
+pub mod stats;
+pub mod reconstruct;
+pub mod foam;
+pub mod heightfield;
+pub mod bench;
+pub mod sprites;
+pub mod screen_space;
+pub mod raymarch;
+pub mod colormap;
+pub mod glyphs;
+pub mod trails;
+pub mod slice;
+pub mod wireframe;
+pub mod ground;
+pub mod gizmo;
+pub mod occupancy;
+pub mod depth_sort;
+pub mod cull;
+pub mod debug_view;
+pub mod neighbors;
+pub mod split;
+pub mod texture;
+pub mod skybox;
+pub mod shadow;
+pub mod lighting;
+pub mod tonemap;
+pub mod bloom;
+pub mod ssao;
+pub mod oit;
+pub mod indirect;
+pub mod secondary;
+pub mod pipeline_cache;
+pub mod hot_reload;
+pub mod shader_cache;
+pub mod reflect;
 
 use anyhow::Result;
+use log::{error, info, warn};
+use nalgebra_glm as glm;
 use winit::dpi::LogicalSize;
-use winit::event::{Event, WindowEvent};
+use winit::event::{Event, WindowEvent, KeyboardInput, ElementState, ModifiersState, MouseButton, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 use winit::dpi::PhysicalPosition;
 use vulkanalia::prelude::v1_0::*;
 
 use crate::app::App;
+use crate::config::{grid_verification_enabled, scene_dump_path, stats_path, surface_export_interval, determinism_verification_enabled, cpu_benchmark_enabled,
+    recording_benchmark_enabled, RECORD_BENCH_OBJECTS, RECORD_BENCH_FRAMES, pipeline_benchmark_enabled,
+    compression_limit, hydrostatic_verification_enabled, MAX_SUBSTEPS, MAX_TIMESTEP, CPU_BENCH_SIZE, CPU_BENCH_STEPS, DAM_BREAK_SIZE, COMPRESSION_CPU_STEPS,
+    VORTICITY_EPSILON, SLICE_STEP, SURFACE_THRESHOLD, HYDROSTATIC_LAYERS,
+    SHAKE_AMPLITUDE, SHAKE_FREQUENCY, BENCH_SIZE, BENCH_STEPS, BENCH_WARMUP_STEPS,
+    TURNTABLE_FRAMES, TURNTABLE_DEGREES_PER_FRAME, NO_SHADER_CACHE_FLAG, SHADER_CACHE_DIR};
+use crate::export::PlyFields;
+use crate::kernel::Kernel;
+use crate::replay::ReplayEnd;
+use crate::scene::Scene;
+use crate::reconstruct::{Anisotropy, FluidRendering};
+use crate::foam::Foam;
+use crate::glyphs::VelocityGlyphs;
+use crate::trails::Trails;
+use crate::slice::DensitySlice;
+use crate::ground::GroundPlane;
+use crate::gizmo::AxisGizmo;
+use crate::occupancy::GridOccupancy;
+use crate::depth_sort::Translucency;
+use crate::cull::{FrustumCulling, ParticleLod};
+use crate::debug_view::DebugView;
+use crate::wireframe::{MeshWireframe, NormalLines};
+use crate::shadow::{PointShadows, ShadowMapping};
+use crate::lighting::{Light, three_point_rig};
+use crate::tonemap::Tonemapping;
+use crate::bloom::Bloom;
+use crate::ssao::Ssao;
+use crate::neighbors::NeighborHighlight;
+use crate::split::SplitScreen;
+use crate::screen_space::ScreenSpaceFluid;
+use crate::raymarch::Raymarching;
+use crate::simulation::{BoundaryMode, ColorRange, ContainerMotion, ParticleColoring, Phase, TimeMode, layered_tank};
+
+/// Radians the arrow keys tilt gravity per press.
+const GRAVITY_TILT: f32 = 0.05;
+/// Factor +/- scale the tuned solver parameter by per press.
+const TUNE_FACTOR: f32 = 1.1;
+/// Where C records to and P replays from, unless a recording is named on the command line.
+const RECORDING_PATH: &str = "recording.sphrec";
+/// Where O writes the fluid surface meshes.
+const SURFACE_EXPORT_DIR: &str = "surface";
+/// Recorded frames Page Up and Page Down skip in a replay.
+const REPLAY_SKIP: u32 = 60;
+/// Pixels the cursor may move between press and release for a click rather than a drag.
+const CLICK_SLOP: f64 = 4.0;
 
 #[rustfmt::skip]
 fn main() -> Result<()> {
@@ -28,20 +129,143 @@ fn main() -> Result<()> {
         .with_inner_size(LogicalSize::new(1024, 768))
         .build(&event_loop)?;
 
-    // App
-    let mut app = unsafe { App::create(&window, 
-        vec!["FinalBaseMesh.obj".to_string(),"Tree.obj".to_string()],
-        "shaders/shader.vert".to_string(), "shaders/shader.frag".to_string())? };
+    // App, from a scene file if the argument names one, or the preset `--shaking-tank`, `--channel`,
+    // `--crown-splash` or `--river` names; `--bench` steps a fixed dam break one step per frame.
+    // `--no-shader-cache` may come anywhere and is not counted
+    let args = std::env::args().filter(|a| a != NO_SHADER_CACHE_FLAG).collect::<Vec<_>>();
+    let arg = args.get(1).cloned();
+    let bench = arg.as_deref() == Some("--bench");
+    let scene = match arg.as_deref() {
+        Some(path) if path.ends_with(".toml") => Some(Scene::from_path(path)?),
+        Some("--shaking-tank") => Some(Scene::shaking_tank()),
+        Some("--channel") => Some(Scene::channel_flow()),
+        Some("--crown-splash") => Some(Scene::crown_splash()),
+        Some("--river") => Some(Scene::river()),
+        Some("--bench") => {
+            let [nx, ny, nz] = BENCH_SIZE;
+            let mut scene = Scene::dam_break(nx, ny, nz);
+            scene.solver.time = TimeMode::Fixed { dt: MAX_TIMESTEP, substeps: 1 };
+            Some(scene)
+        }
+        _ => None,
+    };
+    let mut app = match &scene {
+        Some(scene) => unsafe { App::from_scene(&window, scene.clone(),
+            "shaders/shader.vert".to_string(), "shaders/shader.frag".to_string())? },
+        None => unsafe { App::create(&window,
+            vec!["FinalBaseMesh.obj".to_string(),"Tree.obj".to_string()],
+            arg.clone().filter(|a| a != "--layered" && !a.ends_with(".sphrec")),
+            "shaders/shader.vert".to_string(), "shaders/shader.frag".to_string())? },
+    };
+    if let Some(path) = scene_dump_path() {
+        app.scene().write(&path)?;
+        info!("Wrote the effective scene to {}.", path);
+    }
+    if let Some(path) = stats_path() {
+        app.start_stats(&path)?;
+    }
+    // `--bench [steps]` times that many steps without waiting on the display, prints what they took and
+    // exits rather than running the event loop
+    if bench {
+        let steps = args.get(2).and_then(|s| s.parse().ok()).unwrap_or(BENCH_STEPS);
+        unsafe { app.set_uncapped_present(&window, true)? };
+        app.start_bench(BENCH_WARMUP_STEPS, steps);
+        let report = loop {
+            unsafe { app.render(&window)? };
+            if let Some(report) = app.bench_report() {
+                break report;
+            }
+        };
+        println!("{}", report);
+        unsafe {
+            app.device().device_wait_idle()?;
+            app.destroy();
+        }
+        return Ok(());
+    }
+    // `--layered` swaps the spawn block for a heavy fluid resting on water, which should sink through it
+    if arg.as_deref() == Some("--layered") {
+        app.add_phase(Phase { rest_density: 1400.0, viscosity: 5.0, color: glm::vec3(0.85, 0.35, 0.1) });
+        let particles = layered_tank(app.sim());
+        unsafe { app.set_particles(particles)? };
+    }
+    // a recording on the command line plays back instead of simulating
+    let replay_path = args.get(1).filter(|a| a.ends_with(".sphrec")).cloned();
+    if let Some(path) = &replay_path {
+        unsafe { app.start_replay(path, ReplayEnd::Loop)? };
+    }
+    let replay_path = replay_path.unwrap_or_else(|| RECORDING_PATH.to_string());
+    if determinism_verification_enabled() {
+        let mut sim = *app.sim();
+        sim.dt = MAX_TIMESTEP;
+        solver::check_deterministic(&sim, &Kernel::new(app.data().kernel_kind, sim.h), 300, 0x5eed, 20)?;
+        info!("CPU solver runs agree bit for bit.");
+    }
+    if cpu_benchmark_enabled() {
+        let [nx, ny, nz] = CPU_BENCH_SIZE;
+        let scene = Scene::dam_break(nx, ny, nz);
+        let mut sim = scene.sim();
+        sim.dt = MAX_TIMESTEP;
+        let mut particles = scene.particles(&sim)?;
+        let ms = solver::time_steps(&mut particles, &sim, &Kernel::new(scene.solver.kernel, sim.h), CPU_BENCH_STEPS);
+        info!("CPU solver: {:.2} ms per step for {} particles on {} threads, ending with {}.",
+            ms, particles.len(), rayon::current_num_threads(), diagnostics::cpu_diagnostics(&particles, &sim));
+    }
+    if recording_benchmark_enabled() {
+        let times = unsafe { app.time_recording(RECORD_BENCH_OBJECTS, RECORD_BENCH_FRAMES) }?;
+        info!("Recording {} objects: {:.3} ms inline, {:.3} ms in secondary command buffers on {} threads, {:.3} ms with the objects' kept.",
+            times.objects, times.inline, times.anew, times.threads, times.kept);
+    }
+    if pipeline_benchmark_enabled() {
+        let [cold, warm] = unsafe { app.time_pipeline_creation(&window) }?;
+        info!("Remaking every pipeline took {:.1} ms through an empty pipeline cache, {:.1} ms through the warm one.",
+            cold, warm);
+    }
+    if hydrostatic_verification_enabled() {
+        let sim = *app.sim();
+        let [plain, corrected] = solver::check_free_surface_correction(&sim,
+            &Kernel::new(app.data().kernel_kind, sim.h), HYDROSTATIC_LAYERS, SURFACE_THRESHOLD)?;
+        info!("Hydrostatic pressure error of a {}-layer column: {:.2}% plain, {:.2}% with the free-surface correction.",
+            HYDROSTATIC_LAYERS, 100.0 * plain, 100.0 * corrected);
+    }
+    if let Some(limit) = compression_limit() {
+        let check = conservation::CompressionCheck::with_limit(limit);
+        match conservation::check_dam_break(DAM_BREAK_SIZE, COMPRESSION_CPU_STEPS, &check) {
+            Ok(worst) => info!("CPU dam break stayed within the compression limit, {:.2}% at worst.", 100.0 * worst),
+            Err(e) => error!("CPU compression check failed: {}", e),
+        }
+    }
+    let verify_grid = grid_verification_enabled();
+    if verify_grid {
+        let particles = grid::random_particles(app.sim(), 500, 0x5eed);
+        unsafe { app.set_particles(particles)? };
+    }
+    // a light cube dropped into the pool, unless a scene says what is in it
+    if scene.is_none() {
+        unsafe {
+            let cube = app.add_cube(glm::vec3(-0.3, 0.3, -0.25), glm::vec3(0.06, 0.06, 0.06))?;
+            app.add_rigid_body(cube, 500.0)?;
+        }
+    }
     let mut destroying = false;
     let mut minimized = false;
     let mut last_mouse_pos = PhysicalPosition::<f64>::new(0.0f64, 0.0f64);
     let mut drag = false;
+    let mut press_pos = last_mouse_pos;
+    let mut modifiers = ModifiersState::empty();
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
         match event {
             // Render a frame if the Vulkan app is not being destroyed.
             Event::MainEventsCleared if !destroying && !minimized => {
-                unsafe { app.render(&window) }.unwrap()
+                unsafe { app.render(&window) }.unwrap();
+                // a recording made alongside a turntable ends with it
+                if app.take_turntable_finished() && app.recording() {
+                    app.stop_recording();
+                }
+                if verify_grid {
+                    unsafe { app.verify_grid() }.unwrap();
+                }
             }
             // Destroy the Vulkan app.
             Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
@@ -59,19 +283,488 @@ fn main() -> Result<()> {
                     app.resized(true);
                 }
             }
-            // Mouse event
+            // Mouse event: dragging turns the camera of the view it started in, or pushes the fluid under the
+            // cursor with Shift held
+            Event::WindowEvent { event: WindowEvent::ModifiersChanged(state), .. } => modifiers = state,
             Event::WindowEvent { event: WindowEvent::CursorMoved { position, .. } , .. } => {
                 if drag {
                     let x_diff = position.x - last_mouse_pos.x;
                     let y_diff = position.y - last_mouse_pos.y;
-                    app.handle_mouse(x_diff as f32, y_diff as f32).unwrap();
+                    if modifiers.shift() {
+                        app.drag_particles(position.x as f32, position.y as f32, x_diff as f32, y_diff as f32);
+                    } else {
+                        app.handle_mouse(press_pos.x as f32, x_diff as f32, y_diff as f32).unwrap();
+                    }
                 }
                 last_mouse_pos = position;
             }
+            // a left click that hardly moves snaps the camera to the gizmo's axis under the cursor, or
+            // else picks the particle under it
             Event::WindowEvent { event: WindowEvent::MouseInput { state, button, .. } , .. } => {
                 match state {
-                    winit::event::ElementState::Pressed => drag = true,
-                    winit::event::ElementState::Released => drag = false,
+                    winit::event::ElementState::Pressed => {
+                        drag = true;
+                        press_pos = last_mouse_pos;
+                    }
+                    winit::event::ElementState::Released => {
+                        drag = false;
+                        let moved = (last_mouse_pos.x - press_pos.x).hypot(last_mouse_pos.y - press_pos.y);
+                        if button == MouseButton::Left && !modifiers.shift() && moved < CLICK_SLOP {
+                            let (x, y) = (last_mouse_pos.x as f32, last_mouse_pos.y as f32);
+                            if !app.snap_to_gizmo_axis(x, y).unwrap() {
+                                unsafe { app.select_particle_at(x, y) }.unwrap();
+                            }
+                        }
+                    }
+                }
+            }
+            // Simulation controls: space pauses, period steps once while paused, R resets, E toggles PLY export,
+            // arrows tilt gravity, G sets it turning and Escape drops the picked particle
+            Event::WindowEvent { event: WindowEvent::KeyboardInput { input: KeyboardInput {
+                state: ElementState::Pressed, virtual_keycode: Some(key), .. }, .. }, .. } => {
+                match key {
+                    VirtualKeyCode::Space => app.toggle_pause(),
+                    VirtualKeyCode::Escape => app.deselect_particle(),
+                    VirtualKeyCode::Period => app.step_once(),
+                    VirtualKeyCode::R => unsafe { app.reset() }.unwrap(),
+                    VirtualKeyCode::Up => app.tilt_gravity(-GRAVITY_TILT, 0.0),
+                    VirtualKeyCode::Down => app.tilt_gravity(GRAVITY_TILT, 0.0),
+                    VirtualKeyCode::Left => app.tilt_gravity(0.0, GRAVITY_TILT),
+                    VirtualKeyCode::Right => app.tilt_gravity(0.0, -GRAVITY_TILT),
+                    VirtualKeyCode::G if app.gravity_animated() => app.animate_gravity(None),
+                    VirtualKeyCode::G => app.animate_gravity(Some((glm::vec3(0.0, 0.0, 1.0), 8.0))),
+                    VirtualKeyCode::E if app.exporting() => app.stop_export(),
+                    VirtualKeyCode::E => app.start_export("out", PlyFields { velocity: true, density: true }, 8).unwrap(),
+                    // O starts or stops writing the fluid surface to OBJ files
+                    VirtualKeyCode::O if app.exporting_surface() => app.stop_surface_export(),
+                    VirtualKeyCode::O => app.start_surface_export(SURFACE_EXPORT_DIR, surface_export_interval(), 8).unwrap(),
+                    VirtualKeyCode::B if app.boundary_mode() == BoundaryMode::Reflect =>
+                        unsafe { app.set_boundary_mode(BoundaryMode::Slip) }.unwrap(),
+                    VirtualKeyCode::B => unsafe { app.set_boundary_mode(BoundaryMode::Reflect) }.unwrap(),
+                    // F switches between realtime and fixed steps, D toggles the strict neighbor order
+                    VirtualKeyCode::F if app.time_mode() == TimeMode::default() =>
+                        app.set_time_mode(TimeMode::Fixed { dt: MAX_TIMESTEP / 2.0, substeps: MAX_SUBSTEPS }),
+                    VirtualKeyCode::F => app.set_time_mode(TimeMode::default()),
+                    VirtualKeyCode::D => {
+                        app.set_strict_order(!app.strict_order());
+                        info!("Strict neighbor order {}.", if app.strict_order() { "on" } else { "off" });
+                    }
+                    // V switches vorticity confinement on and off, at the scene's strength if it sets one
+                    VirtualKeyCode::V => {
+                        let on = match app.scene().solver.vorticity_epsilon {
+                            e if e > 0.0 => e,
+                            _ => VORTICITY_EPSILON,
+                        };
+                        app.set_vorticity_epsilon(if app.vorticity_epsilon() > 0.0 { 0.0 } else { on });
+                        info!("Vorticity confinement {}.", match app.vorticity_epsilon() {
+                            e if e > 0.0 => format!("on, epsilon {} m/s", e),
+                            _ => "off".to_string(),
+                        });
+                    }
+                    // S switches the free-surface density correction on and off
+                    VirtualKeyCode::S => {
+                        app.set_surface_correction(!app.surface_correction());
+                        info!("Free-surface correction {}.", if app.surface_correction() { "on" } else { "off" });
+                    }
+                    // M switches the adaptive resolution on, with the scene's criteria or the defaults, and off
+                    VirtualKeyCode::M => {
+                        app.set_refinement(match app.refinement() {
+                            Some(_) => None,
+                            None => Some(app.scene().solver.refinement.unwrap_or_default()),
+                        });
+                        info!("Adaptive resolution {}.", if app.refinement().is_some() { "on" } else { "off" });
+                    }
+                    // Y cycles through the colormaps the density, speed or pressure are drawn through
+                    VirtualKeyCode::Y => {
+                        app.set_colormap(app.colormap().next());
+                        info!("Colormap {:?}.", app.colormap());
+                    }
+                    // Z shows or hides the colormap's legend, and logs the values at its ends
+                    VirtualKeyCode::Z => {
+                        app.set_legend(!app.legend());
+                        let [min, max] = app.current_color_range();
+                        info!("Legend {}, {:?} from {} to {}.", if app.legend() { "on" } else { "off" },
+                            app.particle_coloring(), min, max);
+                    }
+                    // shift and U holds the colormap's range where it is, or lets it follow the particles again
+                    VirtualKeyCode::U if modifiers.shift() => {
+                        let [min, max] = app.current_color_range();
+                        app.set_color_range(match app.color_range() {
+                            ColorRange::Auto => ColorRange::Fixed { min, max },
+                            ColorRange::Fixed { .. } => ColorRange::Auto,
+                        });
+                        info!("Colormap range {:?}.", app.color_range());
+                    }
+                    // U cycles through coloring the particles by phase, tinting those the surface detection
+                    // flags, and mapping their density, speed, pressure or velocity divergence through the
+                    // colormap
+                    VirtualKeyCode::U => {
+                        app.set_particle_coloring(match app.particle_coloring() {
+                            ParticleColoring::Phase => ParticleColoring::Surface,
+                            ParticleColoring::Surface => ParticleColoring::Density,
+                            ParticleColoring::Density => ParticleColoring::Speed,
+                            ParticleColoring::Speed => ParticleColoring::Pressure,
+                            ParticleColoring::Pressure => ParticleColoring::Divergence,
+                            ParticleColoring::Divergence => ParticleColoring::Phase,
+                        });
+                        info!("Coloring particles by {:?}.", app.particle_coloring());
+                    }
+                    // X cycles through drawing the particles, the surface reconstructed from them, the one
+                    // smoothed on screen and the one raymarched
+                    VirtualKeyCode::X => {
+                        app.set_fluid_rendering(match app.fluid_rendering() {
+                            FluidRendering::Particles => FluidRendering::Surface,
+                            FluidRendering::Surface => FluidRendering::ScreenSpace,
+                            FluidRendering::ScreenSpace => FluidRendering::Raymarched,
+                            FluidRendering::Raymarched => FluidRendering::Particles,
+                        });
+                        info!("Drawing the fluid as {:?}.", app.fluid_rendering());
+                    }
+                    // L adds a smoothing pass to the screen-space surface, with shift removes one
+                    VirtualKeyCode::L => {
+                        let params = app.screen_space();
+                        let iterations =
+                            if modifiers.shift() { params.iterations.saturating_sub(1) } else { params.iterations + 1 };
+                        app.set_screen_space(ScreenSpaceFluid { iterations, ..params });
+                        info!("Screen-space smoothing passes: {}.", app.screen_space().iterations);
+                    }
+                    // J doubles the samples along each raymarched ray, with shift halves them
+                    VirtualKeyCode::J => {
+                        let params = app.raymarching();
+                        let steps = if modifiers.shift() { params.steps / 2 } else { params.steps * 2 };
+                        app.set_raymarching(Raymarching { steps, ..params });
+                        info!("Raymarching steps: {}.", app.raymarching().steps);
+                    }
+                    // N widens the screen-space smoothing filter, with shift narrows it
+                    VirtualKeyCode::N => {
+                        let params = app.screen_space();
+                        let scale = if modifiers.shift() { 0.8 } else { 1.25 };
+                        app.set_screen_space(ScreenSpaceFluid { filter_radius: params.filter_radius * scale, ..params });
+                        info!("Screen-space filter radius: {:.4} m.", app.screen_space().filter_radius);
+                    }
+                    // H turns the foam and spray on and off
+                    VirtualKeyCode::H => {
+                        let foam = if app.foam().is_some() { None } else { Some(Foam::default()) };
+                        unsafe { app.set_foam(foam) }.unwrap();
+                        info!("Foam {}.", if app.foam().is_some() { "on" } else { "off" });
+                    }
+                    // shift and A turns the density slice to the next axis, A shows or hides it
+                    VirtualKeyCode::A => {
+                        let slice = app.density_slice();
+                        app.set_density_slice(if modifiers.shift() {
+                            DensitySlice { axis: slice.axis.next(), ..slice }
+                        } else {
+                            DensitySlice { visible: !slice.visible, ..slice }
+                        });
+                        let slice = app.density_slice();
+                        info!("Density slice {}, across {:?} at {:.3}.", if slice.visible { "shown" } else { "hidden" },
+                            slice.axis, slice.offset);
+                    }
+                    // semicolon and apostrophe move the density slice down and up its axis, or with shift
+                    // narrow and widen the field values its colormap spans
+                    VirtualKeyCode::Semicolon | VirtualKeyCode::Apostrophe => {
+                        let slice = app.density_slice();
+                        let up = key == VirtualKeyCode::Apostrophe;
+                        app.set_density_slice(if modifiers.shift() {
+                            let [min, max] = slice.range;
+                            let max = min + (max - min) * if up { 1.25 } else { 0.8 };
+                            DensitySlice { range: [min, max], ..slice }
+                        } else {
+                            DensitySlice { offset: slice.offset + if up { SLICE_STEP } else { -SLICE_STEP }, ..slice }
+                        });
+                        let slice = app.density_slice();
+                        info!("Density slice at {:.3}, colormap from {} to {}.", slice.offset, slice.range[0], slice.range[1]);
+                    }
+                    // shift and Q traces every few particles' recent path, or stops tracing them
+                    VirtualKeyCode::Q if modifiers.shift() => {
+                        let trails = if app.trails().is_some() { None } else { Some(Trails::default()) };
+                        unsafe { app.set_trails(trails) }.unwrap();
+                        info!("Trails {}.", if app.trails().is_some() { "on" } else { "off" });
+                    }
+                    // Q draws a line along each particle's velocity, or stops drawing them
+                    VirtualKeyCode::Q => {
+                        let glyphs = if app.velocity_glyphs().is_some() { None } else { Some(VelocityGlyphs::default()) };
+                        app.set_velocity_glyphs(glyphs);
+                        info!("Velocity glyphs {}.", if app.velocity_glyphs().is_some() { "on" } else { "off" });
+                    }
+                    // slash shows the neighbor grid's occupied cells, or stops showing them
+                    VirtualKeyCode::Slash => {
+                        let occupancy = if app.grid_occupancy().is_some() { None } else { Some(GridOccupancy::default()) };
+                        unsafe { app.set_grid_occupancy(occupancy) }.unwrap();
+                        info!("Grid occupancy view {}.", if app.grid_occupancy().is_some() { "on" } else { "off" });
+                    }
+                    // backslash shows or hides the axis gizmo
+                    VirtualKeyCode::Backslash => {
+                        let gizmo = if app.gizmo().is_some() { None } else { Some(AxisGizmo::default()) };
+                        app.set_gizmo(gizmo);
+                        info!("Axis gizmo {}.", if app.gizmo().is_some() { "shown" } else { "hidden" });
+                    }
+                    // the grave key shows or hides the ground plane, or with shift the skybox
+                    VirtualKeyCode::Grave if modifiers.shift() => {
+                        app.set_skybox(!app.skybox());
+                        info!("Skybox {}.", if app.skybox() { "shown" } else { "hidden" });
+                    }
+                    VirtualKeyCode::Grave => {
+                        let ground = if app.ground().is_some() { None } else { Some(GroundPlane::default()) };
+                        app.set_ground(ground);
+                        info!("Ground plane {}.", if app.ground().is_some() { "shown" } else { "hidden" });
+                    }
+                    // comma shrinks the particles as drawn, or with shift grows them
+                    VirtualKeyCode::Comma => {
+                        app.set_radius_scale(app.radius_scale() * if modifiers.shift() { 1.25 } else { 0.8 });
+                        info!("Particle radius scaled by {:.3}.", app.radius_scale());
+                    }
+                    // shift and F1 draws the light's shadows, or none
+                    VirtualKeyCode::F1 if modifiers.shift() => {
+                        let shadows = if app.shadows().is_some() { None } else { Some(ShadowMapping::default()) };
+                        unsafe { app.set_shadows(shadows) }.unwrap();
+                        info!("Shadows {}.", if app.shadows().is_some() { "on" } else { "off" });
+                    }
+                    // F1 draws the objects in wireframe, or filled again
+                    VirtualKeyCode::F1 => {
+                        let wireframe = if app.mesh_wireframe().is_some() { None } else { Some(MeshWireframe::default()) };
+                        match app.set_mesh_wireframe(wireframe) {
+                            Ok(()) => info!("Mesh wireframe {}.", if app.mesh_wireframe().is_some() { "on" } else { "off" }),
+                            Err(e) => warn!("{}", e),
+                        }
+                    }
+                    // shift and F2 draws the light's shadows as a point's, or the directional ones again
+                    VirtualKeyCode::F2 if modifiers.shift() => {
+                        let shadows = if app.point_shadows().is_some() { None } else { Some(PointShadows::default()) };
+                        unsafe { app.set_point_shadows(shadows) }.unwrap();
+                        info!("Point-light shadows {}.", if app.point_shadows().is_some() { "on" } else { "off" });
+                    }
+                    // shift and F3 lights the scene with the three-point rig, or a single point light
+                    VirtualKeyCode::F3 if modifiers.shift() => {
+                        let rig = app.lights().len() == 1;
+                        app.set_lights(if rig { three_point_rig() } else { vec![Light::default()] });
+                        info!("Lit by {}.", if rig { "the three-point rig" } else { "a single point light" });
+                    }
+                    // shift and F4 switches the tonemapping curve, shift and F5 or F6 raise or lower the exposure
+                    VirtualKeyCode::F4 if modifiers.shift() => {
+                        let tonemapping = app.tonemapping();
+                        app.set_tonemapping(Tonemapping { operator: tonemapping.operator.next(), ..tonemapping });
+                        info!("Tonemapping with {:?}.", app.tonemapping().operator);
+                    }
+                    VirtualKeyCode::F5 | VirtualKeyCode::F6 if modifiers.shift() => {
+                        let tonemapping = app.tonemapping();
+                        let scale = if key == VirtualKeyCode::F5 { 1.25 } else { 0.8 };
+                        app.set_tonemapping(Tonemapping { exposure: tonemapping.exposure * scale, ..tonemapping });
+                        info!("Exposure {:.3}.", app.tonemapping().exposure);
+                    }
+                    // shift and F7 blooms the scene's brightest light, or stops
+                    VirtualKeyCode::F7 if modifiers.shift() => {
+                        app.set_bloom(if app.bloom().is_some() { None } else { Some(Bloom::default()) });
+                        info!("Bloom {}.", if app.bloom().is_some() { "on" } else { "off" });
+                    }
+                    // shift and F8 darkens the ambient light where the meshes occlude it, or stops
+                    VirtualKeyCode::F8 if modifiers.shift() => {
+                        app.set_ssao(if app.ssao().is_some() { None } else { Some(Ssao::default()) });
+                        info!("Ambient occlusion {}.", if app.ssao().is_some() { "on" } else { "off" });
+                    }
+                    // shift and F9 steps the meshes through their albedo, normals and lighting alone, then
+                    // back to their shading
+                    VirtualKeyCode::F9 if modifiers.shift() => {
+                        app.set_shading_debug(app.shading_debug().next());
+                        info!("Meshes showing {:?}.", app.shading_debug());
+                    }
+                    // shift and F12 lays down the opaque scene's depth before shading it, or stops, reporting
+                    // what the render pass took either way
+                    VirtualKeyCode::F12 if modifiers.shift() => {
+                        let [shaded, prepass] = app.depth_prepass_times();
+                        info!("Render GPU time: without depth pre-pass {:?} ms, with {:?} ms.", shaded, prepass);
+                        app.set_depth_prepass(!app.depth_prepass());
+                        info!("Depth pre-pass {}.", if app.depth_prepass() { "on" } else { "off" });
+                    }
+                    // Insert weighs the translucent meshes and particles into transparency targets of their
+                    // own, in any order, or blends them in the order they are drawn again
+                    VirtualKeyCode::Insert => {
+                        app.set_oit(!app.oit());
+                        info!("Order-independent transparency {}.", if app.oit() { "on" } else { "off" });
+                    }
+                    // F2 draws the particles translucent, sorted back to front, or opaque again
+                    VirtualKeyCode::F2 => {
+                        let translucency = if app.translucency().is_some() { None } else { Some(Translucency::default()) };
+                        unsafe { app.set_translucency(translucency) }.unwrap();
+                        info!("Particles {}.", if app.translucency().is_some() { "translucent" } else { "opaque" });
+                    }
+                    // F3 shows the debug view, steps it through the offscreen images the frame draws, then
+                    // hides it again
+                    VirtualKeyCode::F3 => {
+                        let view = match app.debug_view() {
+                            None => Some(DebugView::default()),
+                            Some(view) => view.target.after(app.fluid_rendering()).map(|target| DebugView { target, ..view }),
+                        };
+                        app.set_debug_view(view);
+                        match app.debug_view() {
+                            Some(view) => info!("Debug view of {:?}.", view.target),
+                            None => info!("Debug view hidden."),
+                        }
+                    }
+                    // F4 draws lines along the normals of the objects and the fluid surface, or hides them
+                    VirtualKeyCode::F4 => {
+                        let lines = if app.normal_lines().is_some() { None } else { Some(NormalLines::default()) };
+                        app.set_normal_lines(lines);
+                        info!("Normal lines {}.", if app.normal_lines().is_some() { "shown" } else { "hidden" });
+                    }
+                    // F5 highlights the selected particle's neighbors, as the solver's grid finds them, or stops
+                    VirtualKeyCode::F5 => {
+                        let highlight = if app.neighbor_highlight().is_some() { None } else { Some(NeighborHighlight::default()) };
+                        unsafe { app.set_neighbor_highlight(highlight) }.unwrap();
+                        info!("Neighbor highlighting {}.", if app.neighbor_highlight().is_some() { "on" } else { "off" });
+                    }
+                    // F6 shows or hides the smoothing radius around the selected particle
+                    VirtualKeyCode::F6 => {
+                        app.set_smoothing_sphere(!app.smoothing_sphere());
+                        info!("Smoothing radius sphere {}.", if app.smoothing_sphere() { "shown" } else { "hidden" });
+                    }
+                    // F7 splits the window into the fluid as drawn now beside the reconstructed surface, or
+                    // joins it back
+                    VirtualKeyCode::F7 => {
+                        app.set_split_screen(match app.split_screen() {
+                            Some(_) => None,
+                            None => Some(SplitScreen::default()),
+                        });
+                        info!("Split screen {}.", if app.split_screen().is_some() { "on" } else { "off" });
+                    }
+                    // F8 culls the particles outside the camera's frustum, or draws them all again
+                    VirtualKeyCode::F8 => {
+                        let culling = if app.frustum_culling().is_some() { None } else { Some(FrustumCulling::default()) };
+                        unsafe { app.set_frustum_culling(culling) }.unwrap();
+                        info!("Frustum culling {}.", if app.frustum_culling().is_some() { "on" } else { "off" });
+                    }
+                    // Delete culls and draws the objects' filled meshes on the GPU, or records their draws again
+                    VirtualKeyCode::Delete => {
+                        app.set_indirect_draws(!app.indirect_draws());
+                        info!("GPU-driven object drawing {}{}.", if app.indirect_draws() { "on" } else { "off" },
+                            if app.indirect_draws() && !app.draw_indirect_count() { ", every draw kept" } else { "" });
+                    }
+                    // 0 records the main pass in secondary command buffers across the threads, or inline
+                    VirtualKeyCode::Key0 => {
+                        app.set_parallel_recording(!app.parallel_recording());
+                        info!("Parallel command recording {}.", if app.parallel_recording() { "on" } else { "off" });
+                    }
+                    // 1 rebuilds what each shader goes into as it is saved
+                    VirtualKeyCode::Key1 => {
+                        app.set_shader_hot_reload(!app.shader_hot_reload());
+                        info!("Shader hot reload {}.", if app.shader_hot_reload() { "on" } else { "off" });
+                    }
+                    // 2 empties the shader cache, stale entries and all
+                    VirtualKeyCode::Key2 => match shader_cache::clean_shader_cache() {
+                        Ok(removed) => info!("Removed {} cached shaders from {}.", removed, SHADER_CACHE_DIR),
+                        Err(e) => error!("Could not clean the shader cache: {}", e),
+                    },
+                    // End holds the cull to the frustum the camera has now, to look around at what it left, or
+                    // lets it follow the camera again
+                    VirtualKeyCode::End => {
+                        app.set_cull_frozen(!app.cull_frozen());
+                        let (visible, objects) = app.visible_objects();
+                        info!("Culling frustum {}, {} of {} objects drawn.",
+                            if app.cull_frozen() { "frozen" } else { "following the camera" }, visible, objects);
+                    }
+                    // F12 steps the multisampling through 1, 2, 4 and 8 samples per pixel
+                    VirtualKeyCode::F12 => {
+                        app.set_msaa(if app.msaa_request() >= 8 { 1 } else { app.msaa_request() * 2 });
+                        info!("MSAA {}x, {}x drawn.", app.msaa_request(), app.msaa());
+                    }
+                    // F11 starts a turntable, with Shift one on fixed steps for the same footage every run, or
+                    // stops the one running
+                    VirtualKeyCode::F11 if app.turntable().is_some() => app.stop_turntable(),
+                    VirtualKeyCode::F11 => {
+                        app.start_turntable(TURNTABLE_DEGREES_PER_FRAME, TURNTABLE_FRAMES);
+                        if modifiers.shift() {
+                            app.lock_turntable_steps(TimeMode::Fixed { dt: MAX_TIMESTEP, substeps: 1 });
+                        }
+                    }
+                    // F9 draws the far particles flat and thinned out, or all alike again
+                    VirtualKeyCode::F9 => {
+                        let lod = if app.particle_lod().is_some() { None } else { Some(ParticleLod::default()) };
+                        unsafe { app.set_particle_lod(lod) }.unwrap();
+                        info!("Particle level of detail {}.", if app.particle_lod().is_some() { "on" } else { "off" });
+                    }
+                    // F10 pushes the level of detail's transition out, or with Shift pulls it in
+                    VirtualKeyCode::F10 => {
+                        if let Some(lod) = app.particle_lod() {
+                            let scale = if modifiers.shift() { 0.8 } else { 1.25 };
+                            unsafe { app.set_particle_lod(Some(ParticleLod { distance: lod.distance * scale, ..lod })) }.unwrap();
+                            info!("Particles far past {:.2} m.", lod.distance * scale);
+                        }
+                    }
+                    // I switches the surface reconstruction between round and anisotropic kernels
+                    VirtualKeyCode::I => {
+                        app.set_anisotropy(match app.anisotropy() {
+                            Some(_) => None,
+                            None => Some(Anisotropy::default()),
+                        });
+                        info!("Surface kernels {}.", if app.anisotropy().is_some() { "anisotropic" } else { "isotropic" });
+                    }
+                    // K sets the walls shaking if they are still, and stops and restarts their motion
+                    VirtualKeyCode::K => {
+                        if app.container_motion().is_none() {
+                            let motion = ContainerMotion::Shake { axis: [1.0, 0.0, 0.0], amplitude: SHAKE_AMPLITUDE,
+                                frequency: SHAKE_FREQUENCY };
+                            if let Err(e) = unsafe { app.set_container_motion(Some(motion)) } {
+                                error!("Cannot shake the container: {}", e);
+                            }
+                        } else {
+                            app.set_container_running(!app.container_running());
+                        }
+                        info!("Container motion {}.", if app.container_running() { "running" } else { "stopped" });
+                    }
+                    // W starts or stops logging the selected particle, or particle #0 with none selected, every step
+                    VirtualKeyCode::W => app.watch_particle(match app.watched_particle() {
+                        Some(_) => None,
+                        None => Some(app.selected_particle().unwrap_or(0)),
+                    }),
+                    // C starts or stops recording, P a replay of the recording; while replaying, [ and ] halve
+                    // and double the rate, Backspace plays backwards, Home rewinds and Page Up/Down skip
+                    VirtualKeyCode::C if app.recording() => app.stop_recording(),
+                    VirtualKeyCode::C => app.start_recording(RECORDING_PATH).unwrap(),
+                    VirtualKeyCode::P if app.replay_frame().is_some() => unsafe { app.stop_replay() }.unwrap(),
+                    VirtualKeyCode::P => if let Err(e) = unsafe { app.start_replay(&replay_path, ReplayEnd::Loop) } {
+                        error!("Cannot replay {}: {}", replay_path, e);
+                    },
+                    VirtualKeyCode::LBracket | VirtualKeyCode::RBracket | VirtualKeyCode::Back => {
+                        if let Some(rate) = app.replay_rate() {
+                            app.set_replay_rate(match key {
+                                VirtualKeyCode::LBracket => rate / 2.0,
+                                VirtualKeyCode::RBracket => rate * 2.0,
+                                _ => -rate,
+                            });
+                            info!("Replay rate {} frames per frame.", app.replay_rate().unwrap_or(0.0));
+                        }
+                    }
+                    VirtualKeyCode::Home | VirtualKeyCode::PageUp | VirtualKeyCode::PageDown => {
+                        if let Some((frame, frames)) = app.replay_frame() {
+                            let target = match key {
+                                VirtualKeyCode::Home => 0,
+                                VirtualKeyCode::PageUp => frame.saturating_sub(REPLAY_SKIP),
+                                _ => frame + REPLAY_SKIP,
+                            };
+                            app.seek_replay(target);
+                            info!("Replay frame {} of {}.", target.min(frames - 1), frames);
+                        }
+                    }
+                    // Tab picks the solver parameter to tune, +/- scale it
+                    VirtualKeyCode::Tab => {
+                        let tunable = app.select_next_tunable();
+                        info!("Tuning {} = {} {}.", tunable.name, tunable.get(app.sim()), tunable.unit);
+                    }
+                    VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd
+                    | VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => {
+                        let up = matches!(key, VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd);
+                        let value = app.tune(if up { TUNE_FACTOR } else { 1.0 / TUNE_FACTOR });
+                        let tunable = app.selected_tunable();
+                        info!("{} = {} {}.", tunable.name, value, tunable.unit);
+                    }
+                    // T flips the density and force passes between tiled and untiled, reporting what both took
+                    VirtualKeyCode::T => {
+                        let [untiled, tiled] = app.neighbor_pass_times();
+                        info!("Density + force GPU time: untiled {:?} ms, tiled {:?} ms.", untiled, tiled);
+                        let tiled = app.set_tiled(!app.tiled());
+                        info!("Tiled density and force passes {}.", if tiled { "on" } else { "off" });
+                    }
+                    _ => {}
                 }
             }
             _ => {}
@@ -1,14 +1,24 @@
 use std::hash::{Hash, Hasher};
 use std::collections::HashMap;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, Read};
 use std::fs::File;
+use std::path::{Path, PathBuf};
 use std::mem::size_of;
 use vulkanalia::prelude::v1_0::*;
 use nalgebra_glm as glm;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use log::*;
+use serde::{Deserialize, Serialize};
 
 use crate::appdata::AppData;
+use crate::config::{SDF_RESOLUTION, UNLIT_FRAGMENT_SHADER};
+use crate::sdf::Sdf;
+use crate::boundary::sample_surface;
 use crate::utils::{create_vertex_buffer, create_index_buffer};
+use crate::simulation::Particle;
+use crate::heightfield::Heightfield;
+use crate::camera::scene_model;
+use crate::texture::normal_map_slot;
 
 #[repr(C)]
 #[derive(Clone, Debug, Copy, Default)]
@@ -16,6 +26,81 @@ pub struct Vertex {
     pub pos: glm::Vec3,
     pub color: glm::Vec3,
     pub normal: glm::Vec3,
+    /// Where the mesh texture is sampled, with v down the image.
+    pub tex_coord: glm::Vec2,
+    /// Direction along the surface the normal map's x runs, with `w` the sign its y runs with across
+    /// the normal; zero on meshes without texture coordinates, which keep their vertex normals.
+    pub tangent: glm::Vec4,
+}
+
+/// Surface colors of a mesh from its OBJ file's MTL library, which scale its vertex colors and the
+/// lighting terms `shader.frag` sums; the default leaves them as they are without one.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Material {
+    pub diffuse: glm::Vec3,
+    pub ambient: glm::Vec3,
+    pub specular: glm::Vec3,
+    /// Exponent of the specular highlight.
+    pub shininess: f32,
+    /// Slot of the normal map in `AppData::normal_maps`, once `Object::new` has loaded it.
+    pub normal_map: Option<u32>,
+    /// 1 for an opaque material, less for one blended over what is behind it.
+    pub opacity: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        let white = glm::vec3(1.0, 1.0, 1.0);
+        Self { diffuse: white, ambient: white, specular: white, shininess: 32.0, normal_map: None, opacity: 1.0 }
+    }
+}
+
+impl From<&tobj::Material> for Material {
+    fn from(material: &tobj::Material) -> Self {
+        Self { diffuse: material.diffuse.into(), ambient: material.ambient.into(), specular: material.specular.into(),
+            shininess: material.shininess, normal_map: None, opacity: material.dissolve }
+    }
+}
+
+/// The shading model an object is drawn with, each through pipelines of its own shaders.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaterialKind {
+    /// Lit by the scene's light, through the shaders the app was made with.
+    #[default]
+    Lit,
+    /// Its colors as they are, for meshes with their lighting baked in.
+    Unlit,
+}
+
+impl MaterialKind {
+    /// The vertex and fragment shaders the kind's pipelines are built from.
+    pub fn shaders(self, data: &AppData) -> (String, String) {
+        match self {
+            MaterialKind::Lit => (data.vshader_path.clone(), data.fshader_path.clone()),
+            MaterialKind::Unlit => (data.vshader_path.clone(), UNLIT_FRAGMENT_SHADER.to_string()),
+        }
+    }
+}
+
+/// A run of an object's indices drawn with one material.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Submesh {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub material: Material,
+}
+
+/// How the fluid collides with an object.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Obstacle {
+    None,
+    /// Projected out of a baked signed distance field in the integrate pass.
+    #[default]
+    Sdf,
+    /// Pushed away by boundary particles sampled from the surface.
+    Particles,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -26,27 +111,230 @@ pub struct Object {
     pub index_buffer_memory: vk::DeviceMemory,
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
+    /// Object-to-scene transform, applied before the scene model matrix.
+    pub transform: glm::Mat4,
+    /// Voxels along the longest side of the obstacle's signed distance field.
+    pub sdf_resolution: u32,
+    pub obstacle: Obstacle,
+    /// Whether particles near the obstacle's SDF split, see `Refinement`.
+    pub refine: bool,
+    /// Whether the mesh came with texture coordinates, and so is drawn with the mesh texture.
+    pub textured: bool,
+    /// The runs of `indices` drawn with a material each; with none, they are all drawn with the default.
+    pub submeshes: Vec<Submesh>,
+    /// The normal map file named by the material of each of `submeshes`, if any.
+    pub normal_map_paths: Vec<Option<PathBuf>>,
+    pub material_kind: MaterialKind,
+    /// Opacity every material of the object is drawn with in place of its own, if any.
+    pub opacity: Option<f32>,
+    /// Whether `indices` are a line list rather than triangles.
+    pub line_list: bool,
+    /// Where in the frame's object buffer the constants of each of `draws()` are, as of the latest
+    /// `update_object_blocks`.
+    pub block_offsets: Vec<u32>,
+    /// Terrain the mesh was built from, which stands in for the baked SDF; see `Object::terrain`.
+    pub heightfield: Option<Heightfield>,
+    /// Where the object's vertices and indices start in `vertex_buffer` and `index_buffer`, which are the
+    /// scene's shared ones once `merge_object_geometry` has moved the object there.
+    pub base_vertex: i32,
+    pub base_index: u32,
+    /// Model-space corners of the box around `vertices`.
+    pub local_bounds: [glm::Vec3; 2],
+    /// Whether the object's box lay outside the camera's frustum at the latest cull, so the camera's
+    /// views skip it; it still casts shadows.
+    pub culled: bool,
 }
 
+/// Segments around the built-in cylinder.
+const CYLINDER_SEGMENTS: u32 = 32;
+
 impl Object {
+    /// Loads an OBJ file, or builds one of the unit meshes named `builtin:cube` (corners at +-1) and
+    /// `builtin:cylinder` (radius 1 about y, from -1 to 1). The normal maps a textured OBJ file's materials
+    /// name are loaded into `AppData::normal_maps`. The mesh gets buffers of its own, which it leaves for
+    /// the scene's shared ones once added to the scene and merged.
     pub unsafe fn new(model_path: String, instance: &Instance, device: &Device, data: &mut AppData) -> Result<Self> {
-        let mut obj = Object::default();
+        match model_path.as_str() {
+            "builtin:cube" => return Self::cube(glm::vec3(1.0, 1.0, 1.0), instance, device, data),
+            "builtin:cylinder" => return Self::cylinder(instance, device, data),
+            _ => {}
+        }
+        let mut obj = Object { transform: glm::identity(), sdf_resolution: SDF_RESOLUTION, ..Default::default() };
         load_model(model_path, &mut obj)?;
+        obj.local_bounds = local_bounds(&obj.vertices);
+        if obj.textured {
+            for (submesh, path) in obj.submeshes.iter_mut().zip(&obj.normal_map_paths) {
+                submesh.material.normal_map = path.as_ref().and_then(|path| normal_map_slot(instance, device, data, path));
+            }
+        }
         create_vertex_buffer(&instance, &device, data, &mut obj)?;
         create_index_buffer(&instance, &device, data, &mut obj)?;
         Ok(obj)
     }
 
-    pub fn move_to(position: glm::Vec3) -> Result<()>{
-        
+    /// Builds an object from an in-memory mesh instead of an OBJ file.
+    pub unsafe fn from_mesh(vertices: Vec<Vertex>, indices: Vec<u32>, instance: &Instance, device: &Device,
+        data: &mut AppData) -> Result<Self> {
+        let local_bounds = local_bounds(&vertices);
+        let mut obj = Object { vertices, indices, transform: glm::identity(), sdf_resolution: SDF_RESOLUTION,
+            local_bounds, ..Default::default() };
+        create_vertex_buffer(instance, device, data, &mut obj)?;
+        create_index_buffer(instance, device, data, &mut obj)?;
+        Ok(obj)
+    }
+
+    /// The surface of `heightfield` in `color`, placed where the heightfield says. The fluid collides with
+    /// the heightfield itself rather than a baked SDF, which moving the object only translates.
+    pub unsafe fn terrain(heightfield: Heightfield, color: glm::Vec3, instance: &Instance, device: &Device,
+        data: &mut AppData) -> Result<Self> {
+        let (vertices, indices) = heightfield.mesh(color);
+        let mut obj = Self::from_mesh(vertices, indices, instance, device, data)?;
+        obj.transform = glm::inverse(&scene_model());
+        obj.heightfield = Some(heightfield);
+        Ok(obj)
+    }
+
+    /// An axis-aligned box centered on the origin with flat-shaded faces.
+    pub unsafe fn cube(half_extent: glm::Vec3, instance: &Instance, device: &Device, data: &mut AppData)
+    -> Result<Self> {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for axis in 0..3 {
+            for side in [-1.0f32, 1.0] {
+                let mut normal = glm::Vec3::zeros();
+                normal[axis] = side;
+                let (u, v) = (glm::Vec3::ith((axis + 1) % 3, 1.0), glm::Vec3::ith((axis + 2) % 3, 1.0));
+                let base = vertices.len() as u32;
+                for (a, b) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                    let corner = normal + u * a + v * b;
+                    vertices.push(Vertex::new(corner.component_mul(&half_extent), glm::vec3(1.0, 1.0, 1.0), normal));
+                }
+                // counter-clockwise seen from outside
+                let quad = if side > 0.0 { [0, 1, 2, 0, 2, 3] } else { [0, 2, 1, 0, 3, 2] };
+                indices.extend(quad.iter().map(|i| base + i));
+            }
+        }
+        Self::from_mesh(vertices, indices, instance, device, data)
+    }
+
+    /// A closed cylinder of radius 1 about the y axis from -1 to 1, smooth around and flat at the caps.
+    pub unsafe fn cylinder(instance: &Instance, device: &Device, data: &mut AppData) -> Result<Self> {
+        let white = glm::vec3(1.0, 1.0, 1.0);
+        let around = |k: u32| {
+            let angle = k as f32 / CYLINDER_SEGMENTS as f32 * std::f32::consts::TAU;
+            glm::vec3(angle.cos(), 0.0, -angle.sin())
+        };
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for k in 0..CYLINDER_SEGMENTS {
+            let (a, b) = (around(k), around(k + 1));
+            let base = vertices.len() as u32;
+            for (p, n) in [(a, a), (b, b)] {
+                vertices.push(Vertex::new(p - glm::vec3(0.0, 1.0, 0.0), white, n));
+                vertices.push(Vertex::new(p + glm::vec3(0.0, 1.0, 0.0), white, n));
+            }
+            // counter-clockwise seen from outside: the side quad, then the top and bottom cap wedges
+            indices.extend([base, base + 2, base + 3, base, base + 3, base + 1]);
+            for (y, order) in [(1.0f32, [0, 1, 2]), (-1.0, [0, 2, 1])] {
+                let cap = vertices.len() as u32;
+                let normal = glm::vec3(0.0, y, 0.0);
+                vertices.extend([Vertex::new(normal, white, normal), Vertex::new(a + normal, white, normal),
+                    Vertex::new(b + normal, white, normal)]);
+                indices.extend(order.map(|i| cap + i));
+            }
+        }
+        Self::from_mesh(vertices, indices, instance, device, data)
+    }
+
+    /// The twelve edges of an axis-aligned box centered on the origin, as a line list.
+    pub unsafe fn wire_box(half_extent: glm::Vec3, color: glm::Vec3, instance: &Instance, device: &Device,
+        data: &mut AppData) -> Result<Self> {
+        let corner = |k: u32| glm::vec3(1.0 - 2.0 * (k & 1) as f32, 1.0 - (k & 2) as f32, 1.0 - 0.5 * (k & 4) as f32);
+        let vertices = (0..8).map(|k| Vertex::new(corner(k).component_mul(&half_extent), color,
+            glm::normalize(&corner(k)))).collect();
+        // corners one bit apart share an edge
+        let indices = (0..8).flat_map(|k| [1, 2, 4].into_iter().filter(move |bit| k & bit == 0)
+            .flat_map(move |bit| [k, k | bit])).collect();
+        Ok(Object { line_list: true, ..Self::from_mesh(vertices, indices, instance, device, data)? })
+    }
+
+    /// Three great circles of a sphere of `radius` centered on the origin, one around each axis, of
+    /// `segments` lines each, as a line list.
+    pub unsafe fn wire_sphere(radius: f32, segments: u32, color: glm::Vec3, instance: &Instance, device: &Device,
+        data: &mut AppData) -> Result<Self> {
+        let vertices = (0..3).flat_map(|axis| (0..segments).map(move |k| {
+            let angle = std::f32::consts::TAU * k as f32 / segments as f32;
+            let mut normal = glm::Vec3::zeros();
+            normal[(axis + 1) % 3] = angle.cos();
+            normal[(axis + 2) % 3] = angle.sin();
+            Vertex::new(normal * radius, color, normal)
+        })).collect();
+        let indices = (0..3).flat_map(|axis| (0..segments).flat_map(move |k| {
+            [axis * segments + k, axis * segments + (k + 1) % segments]
+        })).collect();
+        Ok(Object { line_list: true, ..Self::from_mesh(vertices, indices, instance, device, data)? })
+    }
+
+    /// World-space bounds of the object once placed by `scene_model`.
+    pub fn bounds(&self, scene_model: &glm::Mat4) -> (glm::Vec3, glm::Vec3) {
+        let model = scene_model * self.transform;
+        let mut min = glm::vec3(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = -min;
+        for v in &self.vertices {
+            let p = (model * glm::vec4(v.pos.x, v.pos.y, v.pos.z, 1.0)).xyz();
+            min = glm::min2(&min, &p);
+            max = glm::max2(&max, &p);
+        }
+        (min, max)
+    }
+
+    /// Whether any of the object's box, placed by `scene_model`, lies on the inner side of every one of
+    /// `planes`, given as by `frustum_planes`.
+    pub fn in_frustum(&self, scene_model: &glm::Mat4, planes: &[glm::Vec4; 6]) -> bool {
+        let model = scene_model * self.transform;
+        let [min, max] = self.local_bounds;
+        let center = (model * glm::vec4((min.x + max.x) / 2.0, (min.y + max.y) / 2.0, (min.z + max.z) / 2.0, 1.0)).xyz();
+        // the half extents of the box around the transformed box
+        let half = glm::abs(&glm::mat4_to_mat3(&model)) * (max - min) / 2.0;
+        planes.iter().all(|plane| glm::dot(&plane.xyz(), &center) + plane.w >= -glm::dot(&glm::abs(&plane.xyz()), &half))
+    }
+
+    /// The runs of `indices` to draw, with the material of each.
+    pub fn draws(&self) -> Vec<Submesh> {
+        if self.submeshes.is_empty() {
+            vec![Submesh { index_count: self.indices.len() as u32, ..Default::default() }]
+        } else {
+            self.submeshes.clone()
+        }
+    }
+
+    /// Whether `submesh` is drawn blended over what is behind it.
+    pub fn translucent(&self, submesh: &Submesh) -> bool {
+        self.opacity.unwrap_or(submesh.material.opacity) < 1.0
+    }
+
+    pub fn move_to(&mut self, position: glm::Vec3) -> Result<()>{
+        self.transform.set_column(3, &glm::vec4(position.x, position.y, position.z, 1.0));
         Ok(())
     }
+
+    /// Bakes the object's signed distance field in world space, where `scene_model` places the scene.
+    pub fn bake_sdf(&self, scene_model: &glm::Mat4) -> Sdf {
+        let positions = self.vertices.iter().map(|v| v.pos).collect::<Vec<_>>();
+        Sdf::bake(&positions, &self.indices, &(scene_model * self.transform), self.sdf_resolution)
+    }
+
+    /// Poisson-disk samples of the object's surface in world space, `spacing` apart.
+    pub fn sample_boundary(&self, scene_model: &glm::Mat4, spacing: f32) -> Vec<glm::Vec3> {
+        let positions = self.vertices.iter().map(|v| v.pos).collect::<Vec<_>>();
+        sample_surface(&positions, &self.indices, &(scene_model * self.transform), spacing, 1)
+    }
 }
 
 
 impl PartialEq for Vertex {
     fn eq(&self, other: &Self) -> bool {
-        self.pos == other.pos && self.color == other.color
+        self.pos == other.pos && self.color == other.color && self.tex_coord == other.tex_coord
     }
 }
 
@@ -63,12 +351,14 @@ impl Hash for Vertex {
         self.normal[0].to_bits().hash(state);
         self.normal[1].to_bits().hash(state);
         self.normal[2].to_bits().hash(state);
+        self.tex_coord[0].to_bits().hash(state);
+        self.tex_coord[1].to_bits().hash(state);
     }
 }
 
 impl Vertex {
     pub fn new(pos: glm::Vec3, color: glm::Vec3, normal: glm::Vec3) -> Self { 
-        Self { pos, color, normal } 
+        Self { pos, color, normal, tex_coord: glm::Vec2::zeros(), tangent: glm::Vec4::zeros() }
     }
 
     pub fn binding_description() -> vk::VertexInputBindingDescription {
@@ -79,35 +369,54 @@ impl Vertex {
             .build()
     }
 
-    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 5] {
         let pos = vk::VertexInputAttributeDescription::builder()
             .binding(0).location(0).format(vk::Format::R32G32B32_SFLOAT).offset(0).build();
         let color = vk::VertexInputAttributeDescription::builder()
             .binding(0).location(1).format(vk::Format::R32G32B32_SFLOAT).offset(size_of::<glm::Vec3>() as u32).build();
         let normal = vk::VertexInputAttributeDescription::builder()
             .binding(0).location(2).format(vk::Format::R32G32B32_SFLOAT).offset(2 * size_of::<glm::Vec3>() as u32).build();
-        [pos, color, normal]
+        let tex_coord = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(3).format(vk::Format::R32G32_SFLOAT).offset(3 * size_of::<glm::Vec3>() as u32).build();
+        let tangent = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(4).format(vk::Format::R32G32B32A32_SFLOAT)
+            .offset((3 * size_of::<glm::Vec3>() + size_of::<glm::Vec2>()) as u32).build();
+        [pos, color, normal, tex_coord, tangent]
     }
 }
 
+/// Loads an OBJ file into `obj`, with the materials of the MTL libraries it names, which are looked for
+/// next to it; meshes without one, or whose library fails to load, get the default material.
 pub fn load_model(model_path: String, obj: &mut Object) -> Result<()> {
-    let mut reader = BufReader::new(File::open(model_path)?);
+    let mut reader = BufReader::new(File::open(&model_path)?);
     let mut unique_vertices = HashMap::new();
-    let (models, _) = tobj::load_obj_buf(
+    let directory = Path::new(&model_path).parent().unwrap_or(Path::new(""));
+    let (models, materials) = tobj::load_obj_buf(
         &mut reader,
         &tobj::LoadOptions { triangulate: true, ..Default::default() },
-        |_| Ok(Default::default()),
+        |mtl_path| tobj::load_mtl(directory.join(mtl_path)),
     )?;
+    let materials = materials.unwrap_or_else(|e| {
+        warn!("{}: could not load its materials: {}.", model_path, e);
+        Vec::new()
+    });
     
-    // load positions and normals
+    // load positions, normals and texture coordinates, which have indices of their own
+    obj.textured = models.iter().any(|model| !model.mesh.texcoords.is_empty());
     for model in &models {
-        for index in &model.mesh.indices {
+        let first_index = obj.indices.len() as u32;
+        for (k, index) in model.mesh.indices.iter().enumerate() {
             let pos_offset = (3 * index) as usize;
             let normal_offset = (3 * index) as usize;
-            let tex_coord_offset = (2 * index) as usize;
+            let tex_coord_offset = (2 * model.mesh.texcoord_indices.get(k).unwrap_or(index)) as usize;
             if normal_offset >= model.mesh.normals.len() {
                 continue;
             }
+            // OBJ puts v = 0 at the bottom of the image
+            let tex_coord = match model.mesh.texcoords.get(tex_coord_offset..tex_coord_offset + 2) {
+                Some(&[u, v]) => glm::vec2(u, 1.0 - v),
+                _ => glm::Vec2::zeros(),
+            };
             let vertex = Vertex {
                 pos: glm::vec3(model.mesh.positions[pos_offset],
                     model.mesh.positions[pos_offset + 1],
@@ -115,7 +424,9 @@ pub fn load_model(model_path: String, obj: &mut Object) -> Result<()> {
                 color: glm::vec3(1.0, 1.0, 1.0),
                 normal: glm::vec3(model.mesh.normals[normal_offset],
                     model.mesh.normals[normal_offset + 1],
-                    model.mesh.normals[normal_offset + 2],)
+                    model.mesh.normals[normal_offset + 2],),
+                tex_coord,
+                tangent: glm::Vec4::zeros(),
             };
             if let Some(index) = unique_vertices.get(&vertex) {
                 obj.indices.push(*index as u32);
@@ -126,6 +437,22 @@ pub fn load_model(model_path: String, obj: &mut Object) -> Result<()> {
                 obj.indices.push(index as u32);
             }
         }
+        // meshes drawn one after another with the same material share a draw
+        let mtl = model.mesh.material_id.and_then(|id| materials.get(id));
+        let material = mtl.map(Material::from).unwrap_or_default();
+        // the file is the last of the map's arguments, after options like `-bm`
+        let normal_map = mtl.and_then(|mtl| mtl.normal_texture.split_whitespace().last())
+            .map(|file| directory.join(file));
+        let index_count = obj.indices.len() as u32 - first_index;
+        match obj.submeshes.last_mut() {
+            Some(last) if last.material == material && obj.normal_map_paths.last() == Some(&normal_map) => {
+                last.index_count += index_count
+            }
+            _ => {
+                obj.submeshes.push(Submesh { first_index, index_count, material });
+                obj.normal_map_paths.push(normal_map);
+            }
+        }
     }
 
     // normalize to [-1, 1]
@@ -141,7 +468,101 @@ pub fn load_model(model_path: String, obj: &mut Object) -> Result<()> {
         pos.y /= max_val - min_val;
         pos.z /= max_val - min_val;
     };
+    if obj.textured {
+        generate_tangents(&mut obj.vertices, &obj.indices);
+    }
 
     Ok(())
 }
 
+/// Sets the tangents of `vertices` from how the texture coordinates run across the triangles of
+/// `indices` they are in, made perpendicular to their normals. Triangles whose coordinates do not span
+/// an area add nothing, so vertices in only such triangles keep a zero tangent.
+/// Corners of the box around `vertices`, both at the origin with none.
+fn local_bounds(vertices: &[Vertex]) -> [glm::Vec3; 2] {
+    vertices.iter().map(|v| [v.pos, v.pos]).reduce(|[min, max], [p, _]| [glm::min2(&min, &p), glm::max2(&max, &p)])
+        .unwrap_or_default()
+}
+
+fn generate_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![glm::Vec3::zeros(); vertices.len()];
+    let mut bitangents = vec![glm::Vec3::zeros(); vertices.len()];
+    // v up the image again, the way normal maps' y points
+    let uv = |v: &Vertex| glm::vec2(v.tex_coord.x, 1.0 - v.tex_coord.y);
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|k| &vertices[triangle[k] as usize]);
+        let (e1, e2) = (b.pos - a.pos, c.pos - a.pos);
+        let (d1, d2) = (uv(b) - uv(a), uv(c) - uv(a));
+        let det = d1.x * d2.y - d2.x * d1.y;
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+        let tangent = (e1 * d2.y - e2 * d1.y) / det;
+        let bitangent = (e2 * d1.x - e1 * d2.x) / det;
+        for &k in triangle {
+            tangents[k as usize] += tangent;
+            bitangents[k as usize] += bitangent;
+        }
+    }
+    for (v, (t, b)) in vertices.iter_mut().zip(tangents.iter().zip(&bitangents)) {
+        let n = glm::normalize(&v.normal);
+        let t = t - n * glm::dot(&n, t);
+        if t.norm() < f32::EPSILON {
+            continue;
+        }
+        let handedness = if glm::dot(&glm::cross(&n, &t), b) < 0.0 { -1.0 } else { 1.0 };
+        let t = glm::normalize(&t);
+        v.tangent = glm::vec4(t.x, t.y, t.z, handedness);
+    }
+}
+
+/// Loads an initial particle layout. Files ending in `.bin` hold packed little-endian `f32` records of
+/// `x y z vx vy vz`; anything else is text with one `x y z [vx vy vz]` particle per line, separated by
+/// spaces or commas, where `#` starts a comment and a non-numeric first line is taken as a header.
+/// Positions outside `[domain_min, domain_max]` are clamped into it with a warning.
+pub fn load_particles(path: &str, mass: f32, domain_min: &glm::Vec3, domain_max: &glm::Vec3) -> Result<Vec<Particle>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let records = if Path::new(path).extension().is_some_and(|e| e.eq_ignore_ascii_case("bin")) {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        if bytes.len() % (6 * size_of::<f32>()) != 0 {
+            return Err(anyhow!("{}: size {} is not a whole number of 6-float particles.", path, bytes.len()));
+        }
+        bytes.chunks_exact(6 * size_of::<f32>()).map(|record| {
+            let v = record.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect::<Vec<_>>();
+            (glm::vec3(v[0], v[1], v[2]), glm::vec3(v[3], v[4], v[5]))
+        }).collect::<Vec<_>>()
+    } else {
+        let mut records = Vec::new();
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values = line.split(|c: char| c == ',' || c.is_whitespace()).filter(|v| !v.is_empty())
+                .map(str::parse::<f32>).collect::<Result<Vec<_>, _>>();
+            match values {
+                Ok(v) if v.len() == 3 || v.len() == 6 => {
+                    let vel = if v.len() == 6 { glm::vec3(v[3], v[4], v[5]) } else { glm::Vec3::zeros() };
+                    records.push((glm::vec3(v[0], v[1], v[2]), vel));
+                }
+                Err(_) if records.is_empty() && number == 0 => continue,
+                _ => return Err(anyhow!("{}:{}: expected `x y z [vx vy vz]`.", path, number + 1)),
+            }
+        }
+        records
+    };
+
+    let mut clamped = 0;
+    let particles = records.into_iter().map(|(pos, vel)| {
+        let inside = glm::clamp_vec(&pos, domain_min, domain_max);
+        clamped += (inside != pos) as usize;
+        Particle { vel, ..Particle::new(inside, mass) }
+    }).collect::<Vec<_>>();
+    if clamped > 0 {
+        warn!("{}: clamped {} particles that were outside the domain.", path, clamped);
+    }
+    info!("Loaded {} particles from {}.", particles.len(), path);
+    Ok(particles)
+}
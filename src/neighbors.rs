@@ -0,0 +1,92 @@
+use std::mem::size_of;
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::simulation::{compute_barrier, solver_constants, transfer_barrier};
+use crate::utils::{create_compute_pipeline, begin_single_time_commands, end_single_time_commands};
+
+/// Highlighting of the selected particle's neighbors: a pass after the solver steps runs the grid query
+/// the solver does from the selected particle and marks every particle it finds within the smoothing
+/// radius, so particles inside the radius left unmarked show the grid missing them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NeighborHighlight {
+    /// Color the neighbors are drawn in, in place of their own.
+    pub color: [f32; 3],
+}
+
+impl Default for NeighborHighlight {
+    fn default() -> Self {
+        Self { color: NEIGHBOR_COLOR }
+    }
+}
+
+/// Push constants of the neighbor pass, laid out to match its `Highlight` block.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct HighlightConstants {
+    /// Id of the particle whose neighbors are marked.
+    target: u32,
+    /// The neighbors' color packed as RGBA8, as the sprites read the highlight buffer.
+    color: u32,
+}
+
+impl NeighborHighlight {
+    fn packed_color(&self) -> u32 {
+        let [r, g, b] = self.color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u32);
+        r | g << 8 | b << 16 | 255 << 24
+    }
+}
+
+/// Creates the pass that marks the selected particle's neighbors in the highlight buffer.
+pub unsafe fn create_neighbor_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    // the solver's buffers and parameters, and the particle and color as push constants
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<HighlightConstants>() as u32);
+    let set_layouts = &[data.compute_descriptor_set_layout, data.sim_params_set_layout];
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.neighbor_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    data.neighbor_pipeline = create_compute_pipeline(device, data.pipeline_cache, data.neighbor_pipeline_layout,
+        &NEIGHBOR_SHADER.to_string(), &solver_constants(data, false))?;
+    Ok(())
+}
+
+pub unsafe fn destroy_neighbor_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.neighbor_pipeline, None);
+    device.destroy_pipeline_layout(data.neighbor_pipeline_layout, None);
+}
+
+/// Unmarks every particle, as when nothing is highlighted. Nothing may be in flight.
+pub unsafe fn clear_highlights(device: &Device, data: &AppData) -> Result<()> {
+    let command_buffer = begin_single_time_commands(device, data)?;
+    device.cmd_fill_buffer(command_buffer, data.highlight_buffer, 0, vk::WHOLE_SIZE as u64, 0);
+    end_single_time_commands(device, data, command_buffer)
+}
+
+/// Records the marking of the selected particle's neighbors among the latest particles
+/// `particle_buffers[parity]`, through the grid the last step sorted them into, for `frame`.
+pub unsafe fn record_neighbor_highlight(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    frame: usize, parity: usize) {
+    let Some(highlight) = &data.neighbor_highlight else { return };
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+    device.cmd_fill_buffer(command_buffer, data.highlight_buffer, 0, vk::WHOLE_SIZE as u64, 0);
+    transfer_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    let Some(target) = data.selected_id else { return };
+    let constants = HighlightConstants { target, color: highlight.packed_color() };
+    let sets = [data.compute_descriptor_sets[parity], data.sim_params_sets[frame]];
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, data.neighbor_pipeline_layout, 0,
+        &sets, &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const HighlightConstants).cast::<u8>(),
+        size_of::<HighlightConstants>());
+    device.cmd_push_constants(command_buffer, data.neighbor_pipeline_layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, data.neighbor_pipeline);
+    // a single workgroup finds the particle, then walks its neighborhood
+    device.cmd_dispatch(command_buffer, 1, 1, 1);
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+}
@@ -0,0 +1,233 @@
+use std::mem::size_of;
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::colormap::colormap_descriptor_set;
+use crate::config::*;
+use crate::simulation::{SimParams, compute_barrier, create_storage_buffer, solver_constants, transfer_barrier,
+    write_compute_descriptor_sets};
+use crate::utils::{compile_shader, create_compute_pipeline, create_shader_module, begin_single_time_commands,
+    end_single_time_commands};
+
+/// Debug view of the neighbor grid: each cell the counting sort put particles in drawn as a translucent
+/// cube, colored through the colormap by how many. A pass after the solver steps compacts the occupied
+/// cells out of the cell counts into a buffer the cubes are drawn from; the buffer takes 16 bytes per
+/// cell up to `max_cells`, and is only made once the view is first turned on.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GridOccupancy {
+    /// Cells drawn at most; those the pass finds past it are left out.
+    pub max_cells: u32,
+    /// Particles in a cell at the top of the colormap.
+    pub full: u32,
+    /// Opacity of each cube's faces. They are blended in no particular order, so overlaps come out
+    /// however the cells were compacted.
+    pub opacity: f32,
+}
+
+impl Default for GridOccupancy {
+    fn default() -> Self {
+        Self { max_cells: OCCUPANCY_MAX_CELLS, full: OCCUPANCY_FULL, opacity: OCCUPANCY_OPACITY }
+    }
+}
+
+/// Push constants of the occupancy pass and renderer, laid out to match their `OccupancyConstants`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct OccupancyConstants {
+    max_cells: u32,
+    full: f32,
+    opacity: f32,
+}
+
+impl OccupancyConstants {
+    fn new(data: &AppData, occupancy: &GridOccupancy) -> Self {
+        // cells past the buffer's room are dropped even if the settings ask for more
+        let max_cells = occupancy.max_cells.min(data.occupancy_capacity);
+        Self { max_cells, full: occupancy.full.max(1) as f32, opacity: occupancy.opacity }
+    }
+}
+
+/// Creates the pass that compacts the occupied cells into the occupancy buffer.
+pub unsafe fn create_occupancy_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    // the solver's buffers and parameters, and the cap as a push constant
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<OccupancyConstants>() as u32);
+    let set_layouts = &[data.compute_descriptor_set_layout, data.sim_params_set_layout];
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.occupancy_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let constants = solver_constants(data, false);
+    data.occupancy_pipeline = create_compute_pipeline(device, data.pipeline_cache, data.occupancy_pipeline_layout,
+        &OCCUPANCY_SHADER.to_string(), &constants)?;
+    Ok(())
+}
+
+pub unsafe fn destroy_occupancy_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.occupancy_pipeline, None);
+    device.destroy_pipeline_layout(data.occupancy_pipeline_layout, None);
+}
+
+/// Makes the occupancy buffer with room for `occupancy.max_cells` cells, unless the one there already
+/// has it, and binds it to the compute sets; it draws nothing until the pass first fills it. Nothing
+/// may be in flight.
+pub unsafe fn create_occupancy_buffer(instance: &Instance, device: &Device, data: &mut AppData,
+    occupancy: &GridOccupancy) -> Result<()> {
+    let capacity = occupancy.max_cells.max(1);
+    if data.occupancy_buffer != vk::Buffer::null() && data.occupancy_capacity == capacity {
+        return Ok(());
+    }
+    destroy_occupancy_buffer(device, data);
+    // an indirect draw and the cells' size, then the cells
+    let size = 32 + 16 * capacity as u64;
+    let (buffer, memory) = create_storage_buffer(instance, device, data, size,
+        vk::BufferUsageFlags::INDIRECT_BUFFER, false)?;
+    data.occupancy_buffer = buffer;
+    data.occupancy_buffer_memory = memory;
+    data.occupancy_capacity = capacity;
+    write_compute_descriptor_sets(device, data);
+    let command_buffer = begin_single_time_commands(device, data)?;
+    device.cmd_fill_buffer(command_buffer, data.occupancy_buffer, 0, vk::WHOLE_SIZE as u64, 0);
+    end_single_time_commands(device, data, command_buffer)
+}
+
+pub unsafe fn destroy_occupancy_buffer(device: &Device, data: &mut AppData) {
+    device.destroy_buffer(data.occupancy_buffer, None);
+    device.free_memory(data.occupancy_buffer_memory, None);
+    data.occupancy_buffer = vk::Buffer::null();
+    data.occupancy_buffer_memory = vk::DeviceMemory::null();
+    data.occupancy_capacity = 0;
+}
+
+/// Records the compaction of the cells the latest step's counting sort filled, for `frame`.
+pub unsafe fn record_occupancy(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, frame: usize,
+    sim: &SimParams, parity: usize, occupancy: &GridOccupancy) {
+    if data.occupancy_buffer == vk::Buffer::null() {
+        return;
+    }
+    let constants = OccupancyConstants::new(data, occupancy);
+    // the instance count the cells are appended with
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+    device.cmd_fill_buffer(command_buffer, data.occupancy_buffer, size_of::<u32>() as u64, size_of::<u32>() as u64, 0);
+    transfer_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    let sets = [data.compute_descriptor_sets[parity], data.sim_params_sets[frame]];
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, data.occupancy_pipeline_layout, 0,
+        &sets, &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const OccupancyConstants).cast::<u8>(),
+        size_of::<OccupancyConstants>());
+    device.cmd_push_constants(command_buffer, data.occupancy_pipeline_layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, data.occupancy_pipeline);
+    device.cmd_dispatch(command_buffer, sim.grid_dims[3].div_ceil(data.workgroup_size), 1, 1);
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+}
+
+/// Creates the pipeline that draws the occupied cells: a cube per instance, its corners made up in the
+/// vertex shader from the cell it stands for, alpha blended unsorted and depth tested without writing
+/// depth.
+pub unsafe fn create_occupancy_render_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&OCCUPANCY_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&OCCUPANCY_FRAGMENT_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    // both sides of every face, so the cubes read the same from inside the fluid
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true).depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+
+    // the scene's uniform buffer, the solver's buffers for the occupied cells, the colormap, and the
+    // cap, scale and opacity as push constants
+    let set_layouts = &[data.descriptor_set_layout, data.compute_descriptor_set_layout, data.colormap_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(size_of::<OccupancyConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.occupancy_render_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .dynamic_state(&dynamic_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.occupancy_render_pipeline_layout)
+        .render_pass(data.render_pass)
+        .subpass(0);
+    data.occupancy_render_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_occupancy_render_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.occupancy_render_pipeline, None);
+    device.destroy_pipeline_layout(data.occupancy_render_pipeline_layout, None);
+}
+
+/// Records the draw of the occupied cells into swapchain image `i`'s render pass, as many as the last
+/// occupancy pass found.
+pub unsafe fn record_occupancy_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize,
+    occupancy: &GridOccupancy) {
+    if data.occupancy_buffer == vk::Buffer::null() {
+        return;
+    }
+    let constants = OccupancyConstants::new(data, occupancy);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.occupancy_render_pipeline);
+    let sets = [data.descriptor_sets[i], data.compute_descriptor_sets[data.particle_parity], colormap_descriptor_set(data)];
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.occupancy_render_pipeline_layout, 0, &sets, &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const OccupancyConstants).cast::<u8>(),
+        size_of::<OccupancyConstants>());
+    device.cmd_push_constants(command_buffer, data.occupancy_render_pipeline_layout,
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0, bytes);
+    // the buffer starts with the cubes as a VkDrawIndirectCommand
+    device.cmd_draw_indirect(command_buffer, data.occupancy_buffer, 0, 1, size_of::<vk::DrawIndirectCommand>() as u32);
+}
@@ -0,0 +1,395 @@
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::{FULLSCREEN_VERTEX_SHADER, OIT_COMPOSITE_SHADER, OIT_DEPTH_SCALE};
+use crate::split::{full_viewport, viewport_scissor};
+use crate::sprites::record_particle_oit;
+use crate::tonemap::HDR_FORMAT;
+use crate::reconstruct::FluidRendering;
+use crate::utils::{allocate_image, compile_shader, create_image_view, create_shader_module,
+    get_depth_format, record_mesh_draws, scene_views, transparent_draws};
+
+/// The summed weighted colors, and the share of the scene the transparent fragments leave revealed.
+const ACCUM_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const REVEAL_FORMAT: vk::Format = vk::Format::R16_SFLOAT;
+
+/// Whether the frame draws its translucent parts into the transparency targets: with them on, and
+/// unless the screen-space fluid draws the scene through a pass of its own.
+pub(crate) fn oit_active(data: &AppData) -> bool {
+    let screen_space = data.fluid_rendering == FluidRendering::ScreenSpace && data.split_screen.is_none();
+    data.oit && !screen_space
+}
+
+/// The specialization constants that turn the weighting on in the mesh and particle fragment shaders,
+/// `OIT` and `OIT_DEPTH_SCALE`, and the bytes they are read from.
+pub(crate) fn oit_specialization() -> ([vk::SpecializationMapEntry; 2], [u8; 8]) {
+    let entry = |constant_id: u32, offset: u32| vk::SpecializationMapEntry::builder()
+        .constant_id(constant_id).offset(offset).size(4).build();
+    let mut bytes = [0; 8];
+    bytes[..4].copy_from_slice(&vk::TRUE.to_ne_bytes());
+    bytes[4..].copy_from_slice(&OIT_DEPTH_SCALE.to_ne_bytes());
+    ([entry(0, 0), entry(1, 4)], bytes)
+}
+
+/// Blending of the pipelines drawing into the transparency targets: the weighted colors summed, and
+/// the revealage multiplied by one minus each alpha.
+pub(crate) fn oit_blend_attachments() -> [vk::PipelineColorBlendAttachmentState; 2] {
+    let accum = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::ONE)
+        .dst_color_blend_factor(vk::BlendFactor::ONE)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .build();
+    let reveal = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::R)
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::ZERO)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_COLOR)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .build();
+    [accum, reveal]
+}
+
+/// Creates the pass drawing into the transparency targets, multisampled like the main pass and tested
+/// against the depth it left, then resolved for the composite to sample. Called with the main pass, as
+/// the pipelines drawing into it are made with theirs.
+pub unsafe fn create_oit_render_pass(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let multisampled = data.msaa_samples != vk::SampleCountFlags::_1;
+    let read = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+    let target = |format: vk::Format| {
+        let (store_op, final_layout) = if multisampled {
+            (vk::AttachmentStoreOp::DONT_CARE, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        } else {
+            (vk::AttachmentStoreOp::STORE, read)
+        };
+        vk::AttachmentDescription::builder()
+            .format(format).samples(data.msaa_samples)
+            .load_op(vk::AttachmentLoadOp::CLEAR).store_op(store_op)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE).stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::UNDEFINED).final_layout(final_layout)
+            .build()
+    };
+    let resolve = |format: vk::Format| vk::AttachmentDescription::builder()
+        .format(format).samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE).store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE).stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED).final_layout(read)
+        .build();
+    // the main pass's depth, kept for the test
+    let depth_layout = vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL;
+    let depth = vk::AttachmentDescription::builder()
+        .format(get_depth_format(instance, data)?).samples(data.msaa_samples)
+        .load_op(vk::AttachmentLoadOp::LOAD).store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE).stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(depth_layout).final_layout(depth_layout)
+        .build();
+    let reference = |attachment: u32| vk::AttachmentReference::builder().attachment(attachment)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL).build();
+    let color_attachments = &[reference(0), reference(1)];
+    let resolve_attachments = &[reference(3), reference(4)];
+    let depth_attachment = vk::AttachmentReference::builder().attachment(2).layout(depth_layout);
+    let mut subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(color_attachments)
+        .depth_stencil_attachment(&depth_attachment);
+    let mut attachments = vec![target(ACCUM_FORMAT), target(REVEAL_FORMAT), depth];
+    if multisampled {
+        subpass = subpass.resolve_attachments(resolve_attachments);
+        attachments.extend([resolve(ACCUM_FORMAT), resolve(REVEAL_FORMAT)]);
+    }
+    // after the main pass's depth is written and the last composite has read the targets, and read
+    // once drawn
+    let fragment_tests = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+    let before = vk::SubpassDependency::builder()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | fragment_tests)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | fragment_tests)
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ);
+    let after = vk::SubpassDependency::builder()
+        .src_subpass(0)
+        .dst_subpass(vk::SUBPASS_EXTERNAL)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ);
+    let subpasses = &[subpass];
+    let dependencies = &[before, after];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(&attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+    data.oit_render_pass = device.create_render_pass(&info, None)?;
+    Ok(())
+}
+
+/// Creates the composite of the transparency targets over the HDR image. Call after
+/// `create_oit_render_pass`.
+pub unsafe fn create_oit(device: &Device, data: &mut AppData) -> Result<()> {
+    data.oit_composite_render_pass = create_composite_render_pass(device)?;
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::NEAREST).min_filter(vk::Filter::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+    data.oit_sampler = device.create_sampler(&sampler_info, None)?;
+    let binding = |binding: u32| vk::DescriptorSetLayoutBinding::builder()
+        .binding(binding)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .build();
+    let bindings = &[binding(0), binding(1)];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+    data.oit_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    let pool_size = vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).descriptor_count(2);
+    let pool_sizes = &[pool_size];
+    let info = vk::DescriptorPoolCreateInfo::builder().pool_sizes(pool_sizes).max_sets(1);
+    data.oit_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    let layouts = &[data.oit_set_layout];
+    let info = vk::DescriptorSetAllocateInfo::builder().descriptor_pool(data.oit_descriptor_pool).set_layouts(layouts);
+    data.oit_descriptor_set = device.allocate_descriptor_sets(&info)?[0];
+
+    let set_layouts = &[data.oit_set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts);
+    data.oit_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    create_composite_pipeline(device, data)
+}
+
+/// Creates the transparency targets for the current swapchain, and binds them to the composite. Call
+/// after `create_oit`, the depth and multisampling objects and `create_tonemap_targets`.
+pub unsafe fn create_oit_targets(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let extent = data.swapchain_extent;
+    let multisampled = data.msaa_samples != vk::SampleCountFlags::_1;
+    // the resolved targets, then the multisampled ones drawn into if there are any
+    let mut targets = vec![(ACCUM_FORMAT, vk::SampleCountFlags::_1), (REVEAL_FORMAT, vk::SampleCountFlags::_1)];
+    if multisampled {
+        targets.extend([(ACCUM_FORMAT, data.msaa_samples), (REVEAL_FORMAT, data.msaa_samples)]);
+    }
+    for (format, samples) in targets {
+        let usage = if samples == vk::SampleCountFlags::_1 {
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED
+        } else {
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
+        };
+        let info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::_2D)
+            .extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+            .mip_levels(1).array_layers(1).format(format)
+            .tiling(vk::ImageTiling::OPTIMAL).initial_layout(vk::ImageLayout::UNDEFINED)
+            .usage(usage).samples(samples).sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (image, memory) = allocate_image(instance, device, data, &info, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+        data.oit_image_views.push(create_image_view(device, image, format, vk::ImageAspectFlags::COLOR)?);
+        data.oit_images.push(image);
+        data.oit_images_memory.push(memory);
+    }
+    let views = &data.oit_image_views;
+    let attachments = if multisampled {
+        vec![views[2], views[3], data.msaa_depth_image_view, views[0], views[1]]
+    } else {
+        vec![views[0], views[1], data.depth_image_view]
+    };
+    let framebuffer = |render_pass: vk::RenderPass, attachments: &[vk::ImageView]| {
+        let info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass).attachments(attachments).width(extent.width).height(extent.height).layers(1);
+        device.create_framebuffer(&info, None)
+    };
+    data.oit_framebuffer = framebuffer(data.oit_render_pass, &attachments)?;
+    data.oit_composite_framebuffer = framebuffer(data.oit_composite_render_pass, &[data.hdr_image_view])?;
+
+    let image_infos = data.oit_image_views[..2].iter().map(|view| [vk::DescriptorImageInfo::builder()
+        .sampler(data.oit_sampler).image_view(*view).image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL).build()])
+        .collect::<Vec<_>>();
+    let writes = image_infos.iter().enumerate().map(|(binding, image_info)| vk::WriteDescriptorSet::builder()
+        .dst_set(data.oit_descriptor_set).dst_binding(binding as u32).dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info).build())
+        .collect::<Vec<_>>();
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+    Ok(())
+}
+
+/// A pass blending over the finished HDR image, left where the passes after it sample it.
+unsafe fn create_composite_render_pass(device: &Device) -> Result<vk::RenderPass> {
+    let read = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+    let color_attachment = vk::AttachmentDescription::builder()
+        .format(HDR_FORMAT).samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::LOAD).store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE).stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(read).final_layout(read);
+    let color_attachment_ref = vk::AttachmentReference::builder().attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let color_attachments = &[color_attachment_ref];
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(color_attachments);
+    let before = vk::SubpassDependency::builder()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+    let after = vk::SubpassDependency::builder()
+        .src_subpass(0)
+        .dst_subpass(vk::SUBPASS_EXTERNAL)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ);
+    let attachments = &[color_attachment];
+    let subpasses = &[subpass];
+    let dependencies = &[before, after];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+    Ok(device.create_render_pass(&info, None)?)
+}
+
+unsafe fn create_composite_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&FULLSCREEN_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&OIT_COMPOSITE_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlags::_1);
+    // over the scene by the share of it the transparent fragments hide
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .dynamic_state(&dynamic_state)
+        .multisample_state(&multisample_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.oit_pipeline_layout)
+        .render_pass(data.oit_composite_render_pass)
+        .subpass(0);
+    data.oit_composite_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+/// Destroys what `create_oit` and `create_oit_render_pass` made.
+pub unsafe fn destroy_oit(device: &Device, data: &mut AppData) {
+    device.destroy_pipeline(data.oit_composite_pipeline, None);
+    device.destroy_pipeline_layout(data.oit_pipeline_layout, None);
+    device.destroy_descriptor_pool(data.oit_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(data.oit_set_layout, None);
+    device.destroy_sampler(data.oit_sampler, None);
+    device.destroy_render_pass(data.oit_render_pass, None);
+    device.destroy_render_pass(data.oit_composite_render_pass, None);
+}
+
+pub unsafe fn destroy_oit_targets(device: &Device, data: &mut AppData) {
+    device.destroy_framebuffer(data.oit_framebuffer, None);
+    device.destroy_framebuffer(data.oit_composite_framebuffer, None);
+    data.oit_image_views.drain(..).for_each(|v| device.destroy_image_view(v, None));
+    data.oit_images.drain(..).for_each(|i| device.destroy_image(i, None));
+    data.oit_images_memory.drain(..).for_each(|m| device.free_memory(m, None));
+}
+
+/// Records weighted blended order-independent transparency (McGuire and Bavoil) over swapchain image
+/// `i`'s opaque scene: its views' translucent meshes and particles drawn in any order, each fragment's
+/// premultiplied color added into the accumulation target weighted by its depth and its alpha
+/// multiplied into the revealage, then their weighted average blended over the HDR image by the share of
+/// it they hide. Nothing is recorded when none of the views has anything translucent.
+pub unsafe fn record_oit(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    if !oit_active(data) {
+        return;
+    }
+    let views = scene_views(data, i);
+    let particles = |rendering| data.translucency.is_some() && rendering == FluidRendering::Particles;
+    let draws = views.iter().map(|(_, _, rendering)| transparent_draws(data, *rendering)).collect::<Vec<_>>();
+    if draws.iter().all(Vec::is_empty) && !views.iter().any(|(_, _, rendering)| particles(*rendering)) {
+        return;
+    }
+    let area = vk::Rect2D::builder().offset(vk::Offset2D::default()).extent(data.swapchain_extent).build();
+    // nothing accumulated, and all of the scene revealed
+    let clear_values = &[
+        vk::ClearValue { color: vk::ClearColorValue { float32: [0.0; 4] } },
+        vk::ClearValue { color: vk::ClearColorValue { float32: [1.0; 4] } },
+    ];
+    let info = vk::RenderPassBeginInfo::builder()
+        .render_pass(data.oit_render_pass)
+        .framebuffer(data.oit_framebuffer)
+        .render_area(area)
+        .clear_values(clear_values);
+    device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+    for ((ubo, viewport, rendering), draws) in views.into_iter().zip(draws) {
+        device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+        device.cmd_set_scissor(command_buffer, 0, &[viewport_scissor(&viewport)]);
+        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+            data.pipeline_layout, 0, &[data.descriptor_sets[ubo]], &[]);
+        record_mesh_draws(device, data, command_buffer, i, draws.into_iter());
+        if particles(rendering) {
+            record_particle_oit(device, data, command_buffer, ubo);
+        }
+    }
+    device.cmd_end_render_pass(command_buffer);
+
+    let info = vk::RenderPassBeginInfo::builder()
+        .render_pass(data.oit_composite_render_pass)
+        .framebuffer(data.oit_composite_framebuffer)
+        .render_area(area);
+    device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+    let viewport = full_viewport(data.swapchain_extent);
+    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+    device.cmd_set_scissor(command_buffer, 0, &[viewport_scissor(&viewport)]);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.oit_composite_pipeline);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.oit_pipeline_layout, 0, &[data.oit_descriptor_set], &[]);
+    device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    device.cmd_end_render_pass(command_buffer);
+}
@@ -0,0 +1,113 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::simulation::{compute_barrier, download_from_buffer, solver_constants, transfer_barrier, Particle};
+use crate::utils::{create_compute_pipeline, begin_single_time_commands, end_single_time_commands};
+
+/// Push constants of the pick pass, laid out to match its `Pick` block.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct PickConstants {
+    view_proj: glm::Mat4,
+    cursor: glm::Vec2,
+    viewport: glm::Vec2,
+    radius: f32,
+    target: u32,
+    slot: u32,
+}
+
+/// Key and `target` of the pick pass for nothing.
+const NONE: u32 = u32::MAX;
+
+/// Pick helpers
+pub unsafe fn create_pick_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    // the solver's storage buffers, and the cursor or the id to look for as push constants
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<PickConstants>() as u32);
+    let set_layouts = &[data.compute_descriptor_set_layout];
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.pick_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    data.pick_pipeline = create_compute_pipeline(device, data.pipeline_cache, data.pick_pipeline_layout, &PICK_SHADER.to_string(),
+        &solver_constants(data, false))?;
+    Ok(())
+}
+
+pub unsafe fn destroy_pick_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.pick_pipeline, None);
+    device.destroy_pipeline_layout(data.pick_pipeline_layout, None);
+}
+
+/// Slot in the latest particle buffer of the particle nearest the camera among those `view_proj` draws
+/// within `radius` pixels of window pixel `cursor`, if any. Nothing may be in flight.
+pub unsafe fn pick_particle(instance: &Instance, device: &Device, data: &AppData, view_proj: &glm::Mat4,
+    cursor: glm::Vec2, radius: f32) -> Result<Option<u32>> {
+    let extent = data.swapchain_extent;
+    run_pick(instance, device, data, PickConstants { view_proj: *view_proj, cursor,
+        viewport: glm::vec2(extent.width as f32, extent.height as f32), radius, target: NONE, slot: NONE })
+}
+
+/// Particles each frame in flight reads back by id, in the order of their slots in the selection buffer.
+pub const SELECTION_SLOTS: usize = 2;
+pub const SELECTED_SLOT: usize = 0;
+pub const WATCHED_SLOT: usize = 1;
+
+/// Records the copies of the particles with `selected_id` and `watched_id` among the latest particles
+/// `particle_buffers[parity]` into `frame`'s slots of the selection buffer. A slot is left all ones if
+/// no live particle has its id.
+pub unsafe fn record_selection_readback(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    frame: usize, parity: usize) {
+    let ids = [(SELECTED_SLOT, data.selected_id), (WATCHED_SLOT, data.watched_id)];
+    if ids.iter().all(|(_, id)| id.is_none()) {
+        return;
+    }
+    let size = size_of::<Particle>() as u64;
+    let first = (frame * SELECTION_SLOTS) as u64;
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+    device.cmd_fill_buffer(command_buffer, data.selection_buffer, size * first, size * SELECTION_SLOTS as u64, NONE);
+    transfer_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER, vk::AccessFlags::SHADER_WRITE);
+    let groups = data.particle_capacity.div_ceil(data.workgroup_size).max(1);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, data.pick_pipeline);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, data.pick_pipeline_layout, 0,
+        &[data.compute_descriptor_sets[parity]], &[]);
+    for (slot, id) in ids {
+        let Some(id) = id else { continue };
+        let constants = PickConstants { view_proj: glm::identity(), cursor: glm::Vec2::zeros(),
+            viewport: glm::Vec2::zeros(), radius: 0.0, target: id, slot: (frame * SELECTION_SLOTS + slot) as u32 };
+        let bytes = std::slice::from_raw_parts((&constants as *const PickConstants).cast::<u8>(), size_of::<PickConstants>());
+        device.cmd_push_constants(command_buffer, data.pick_pipeline_layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+        device.cmd_dispatch(command_buffer, groups, 1, 1);
+        // each pass also rewrites the per-workgroup picks
+        compute_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    }
+}
+
+/// The particle in `slot` as the step last submitted for `frame` left it, or `None` if it was not live
+/// then. The caller must have waited on that frame's fence, and the step must have had an id for the slot.
+pub unsafe fn read_selection(data: &AppData, frame: usize, slot: usize) -> Option<Particle> {
+    let particle = *data.selection_staging?.add(frame * SELECTION_SLOTS + slot);
+    (particle.id != NONE).then_some(particle)
+}
+
+/// Runs the pick pass over the latest particle buffer and takes the lowest of its per-workgroup keys.
+unsafe fn run_pick(instance: &Instance, device: &Device, data: &AppData, constants: PickConstants) -> Result<Option<u32>> {
+    let groups = data.particle_capacity.div_ceil(data.workgroup_size).max(1);
+    let command_buffer = begin_single_time_commands(device, data)?;
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, data.pick_pipeline);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, data.pick_pipeline_layout, 0,
+        &[data.compute_descriptor_sets[data.particle_parity]], &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const PickConstants).cast::<u8>(), size_of::<PickConstants>());
+    device.cmd_push_constants(command_buffer, data.pick_pipeline_layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+    device.cmd_dispatch(command_buffer, groups, 1, 1);
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ);
+    end_single_time_commands(device, data, command_buffer)?;
+    let picks = download_from_buffer::<[u32; 2]>(instance, device, data, data.pick_buffer, groups as usize)?;
+    Ok(picks.iter().filter(|pick| pick[0] != NONE).min_by_key(|pick| pick[0]).map(|pick| pick[1]))
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use anyhow::Result;
+use log::*;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::PIPELINE_CACHE_PATH;
+
+/// What the cache file starts with.
+const MAGIC: &[u8; 4] = b"SPHC";
+/// The magic, the driver version, the vendor and device ids, the device's pipeline cache UUID, then the
+/// length and checksum of the cache data following.
+const HEADER_SIZE: usize = 4 + 4 + 4 + 4 + 16 + 8 + 8;
+
+/// The device and driver a cache file was saved on, which must be the ones it is loaded on.
+unsafe fn device_key(instance: &Instance, data: &AppData) -> Vec<u8> {
+    let properties = instance.get_physical_device_properties(data.physical_device);
+    let mut key = Vec::with_capacity(HEADER_SIZE - 16);
+    key.extend_from_slice(MAGIC);
+    key.extend_from_slice(&properties.driver_version.to_le_bytes());
+    key.extend_from_slice(&properties.vendor_id.to_le_bytes());
+    key.extend_from_slice(&properties.device_id.to_le_bytes());
+    key.extend_from_slice(&properties.pipeline_cache_uuid.0);
+    key
+}
+
+/// FNV-1a over `bytes`, for the file's checksum and the shader cache's keys.
+pub(crate) fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// The cache data of the file at `PIPELINE_CACHE_PATH`, if it was saved on this device and driver and
+/// is whole; anything else is left unread.
+unsafe fn load_cache_data(instance: &Instance, data: &AppData) -> Option<Vec<u8>> {
+    let bytes = fs::read(PIPELINE_CACHE_PATH).ok()?;
+    cache_data(&bytes, &device_key(instance, data)).map(<[u8]>::to_vec)
+}
+
+/// The cache data in the file `bytes`, if the file was saved on the device and driver of `key` and the
+/// data is whole.
+fn cache_data<'a>(bytes: &'a [u8], key: &[u8]) -> Option<&'a [u8]> {
+    if bytes.len() < HEADER_SIZE || bytes[..key.len()] != key[..] {
+        return None;
+    }
+    let field = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+    let (size, sum) = (field(key.len()), field(key.len() + 8));
+    let cache = &bytes[HEADER_SIZE..];
+    if cache.len() as u64 != size || checksum(cache) != sum {
+        return None;
+    }
+    // the header Vulkan puts in front of the data: its length, version one, the vendor and device ids
+    // and the UUID, which it would ignore the data over anyway; the length word is only what the data
+    // claims, so the slice is taken checked
+    let word = |at: usize| cache.get(at..at + 4).map(|w| u32::from_le_bytes(w.try_into().unwrap()));
+    if word(0)? < 32 || word(4)? != 1 || cache.get(8..32)? != &key[8..32] {
+        return None;
+    }
+    Some(cache)
+}
+
+/// Creates the cache every pipeline is made through, primed with what the last run saved if it ran on
+/// this device and driver.
+pub unsafe fn create_pipeline_cache(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let initial = load_cache_data(instance, data);
+    let info = vk::PipelineCacheCreateInfo::builder().initial_data(initial.as_deref().unwrap_or(&[]));
+    data.pipeline_cache = device.create_pipeline_cache(&info, None)?;
+    data.pipeline_cache_warm = initial.is_some();
+    match initial {
+        Some(bytes) => info!("Loaded {} bytes of cached pipelines from {}.", bytes.len(), PIPELINE_CACHE_PATH),
+        None => info!("No usable pipeline cache at {}; starting cold.", PIPELINE_CACHE_PATH),
+    }
+    Ok(())
+}
+
+/// Writes the cache to `PIPELINE_CACHE_PATH` for the next run, and destroys it.
+pub unsafe fn destroy_pipeline_cache(instance: &Instance, device: &Device, data: &AppData) {
+    let saved = device.get_pipeline_cache_data(data.pipeline_cache).map_err(anyhow::Error::from).and_then(|cache| {
+        let mut bytes = device_key(instance, data);
+        bytes.extend_from_slice(&(cache.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&checksum(&cache).to_le_bytes());
+        bytes.extend_from_slice(&cache);
+        Ok(fs::write(PIPELINE_CACHE_PATH, bytes)?)
+    });
+    if let Err(e) = saved {
+        warn!("Could not save the pipeline cache to {}: {}", PIPELINE_CACHE_PATH, e);
+    }
+    device.destroy_pipeline_cache(data.pipeline_cache, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> Vec<u8> {
+        let mut key = MAGIC.to_vec();
+        key.extend((0..HEADER_SIZE as u8 - 16 - 4).map(|b| b + 1));
+        key
+    }
+
+    /// A cache file for `key` around `cache`, as `destroy_pipeline_cache` writes it.
+    fn file(key: &[u8], cache: &[u8]) -> Vec<u8> {
+        let mut bytes = key.to_vec();
+        bytes.extend_from_slice(&(cache.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&checksum(cache).to_le_bytes());
+        bytes.extend_from_slice(cache);
+        bytes
+    }
+
+    /// Vulkan's own header for `key`'s device, then `extra` bytes of pipelines.
+    fn vulkan_data(key: &[u8], extra: usize) -> Vec<u8> {
+        let mut cache = 32u32.to_le_bytes().to_vec();
+        cache.extend_from_slice(&1u32.to_le_bytes());
+        cache.extend_from_slice(&key[8..32]);
+        cache.resize(cache.len() + extra, 7);
+        cache
+    }
+
+    #[test]
+    fn accepts_data_saved_on_the_same_device() {
+        let key = key();
+        let cache = vulkan_data(&key, 100);
+        assert_eq!(cache_data(&file(&key, &cache), &key), Some(&cache[..]));
+    }
+
+    #[test]
+    fn rejects_data_of_another_device() {
+        let key = key();
+        let mut other = key.clone();
+        other[12] ^= 1;
+        assert_eq!(cache_data(&file(&other, &vulkan_data(&other, 10)), &key), None);
+    }
+
+    #[test]
+    fn rejects_data_shorter_than_its_header() {
+        let key = key();
+        for len in [0, 4, 8, 20, 31] {
+            let cache = vulkan_data(&key, 0)[..len].to_vec();
+            assert_eq!(cache_data(&file(&key, &cache), &key), None, "{} bytes of data", len);
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_data() {
+        let key = key();
+        let mut bytes = file(&key, &vulkan_data(&key, 10));
+        *bytes.last_mut().unwrap() ^= 1;
+        assert_eq!(cache_data(&bytes, &key), None);
+    }
+}
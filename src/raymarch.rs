@@ -0,0 +1,233 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::camera::UniformBufferObject;
+use crate::config::*;
+use crate::reconstruct::FieldGrid;
+use crate::utils::{compile_shader, create_shader_module, create_texture, upload_texture};
+
+/// How finely the raymarcher samples the reconstruction's volume: a full-screen pass casts a ray per
+/// pixel through the splatted volume fraction, steps along it until it crosses the iso level, and
+/// shades the crossing as the surface, in place of the mesh `marching_cubes` would build.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Raymarching {
+    /// Samples along the longest ray through the grid; shorter rays take proportionally fewer.
+    pub steps: u32,
+    /// Bisections between the last sample outside the fluid and the first inside.
+    pub refinements: u32,
+}
+
+impl Default for Raymarching {
+    fn default() -> Self {
+        Self { steps: RAYMARCH_STEPS, refinements: RAYMARCH_REFINEMENTS }
+    }
+}
+
+/// Push constants of the raymarcher, laid out like `RaymarchConstants` in `raymarch.frag`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct RaymarchConstants {
+    /// Position of the grid's first node, and the spacing of the nodes in w.
+    origin: glm::Vec4,
+    /// Nodes along each axis, and the samples along the longest ray in w.
+    dims: [u32; 4],
+    /// Color of the fluid, and the iso level in w.
+    color: glm::Vec4,
+    refinements: u32,
+    _pad: [u32; 3],
+}
+
+/// Creates the full-screen pipeline that raymarches the field volume, with its descriptor set layout
+/// and a sampler for the volume.
+pub unsafe fn create_raymarch_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    // the slice plane draws through the same sets, placing its corners with the uniform buffer
+    let ubo_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
+    let volume_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(1)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+    let bindings = &[ubo_binding, volume_binding];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+    data.raymarch_descriptor_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    // the shader interpolates between nodes itself, since not every device filters 32-bit floats
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::NEAREST).min_filter(vk::Filter::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+    data.raymarch_sampler = device.create_sampler(&sampler_info, None)?;
+
+    let vshader = compile_shader(&FULLSCREEN_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&RAYMARCH_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    // one triangle over the screen, made up from the vertex indices alone
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(false);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    // the shader writes the depth of the surface it hits, so the scene hides it and it hides the scene
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true).depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+
+    let set_layouts = &[data.raymarch_descriptor_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(size_of::<RaymarchConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.raymarch_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .dynamic_state(&dynamic_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.raymarch_pipeline_layout)
+        .render_pass(data.render_pass)
+        .subpass(0);
+    data.raymarch_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+/// Destroys the raymarcher's pipeline along with its sampler and descriptors, which live as long.
+pub unsafe fn destroy_raymarch_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.raymarch_pipeline, None);
+    device.destroy_pipeline_layout(data.raymarch_pipeline_layout, None);
+    device.destroy_sampler(data.raymarch_sampler, None);
+    device.destroy_descriptor_pool(data.raymarch_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(data.raymarch_descriptor_set_layout, None);
+}
+
+/// Allocates a raymarcher set per uniform buffer, on it and the field volume if there is one yet; call
+/// after `create_uniform_buffers`.
+pub unsafe fn create_raymarch_descriptor_sets(device: &Device, data: &mut AppData) -> Result<()> {
+    let count = data.uniform_buffers.len() as u32;
+    let ubo_size = vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::UNIFORM_BUFFER).descriptor_count(count);
+    let sampler_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).descriptor_count(count);
+    let pool_sizes = &[ubo_size, sampler_size];
+    let info = vk::DescriptorPoolCreateInfo::builder().pool_sizes(pool_sizes).max_sets(count);
+    data.raymarch_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    let layouts = vec![data.raymarch_descriptor_set_layout; count as usize];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(data.raymarch_descriptor_pool).set_layouts(&layouts);
+    data.raymarch_descriptor_sets = device.allocate_descriptor_sets(&info)?;
+    for (set, buffer) in data.raymarch_descriptor_sets.iter().zip(&data.uniform_buffers) {
+        let buffer_info = &[vk::DescriptorBufferInfo::builder()
+            .buffer(*buffer).offset(0).range(size_of::<UniformBufferObject>() as u64)];
+        let ubo_write = vk::WriteDescriptorSet::builder()
+            .dst_set(*set).dst_binding(0).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER).buffer_info(buffer_info);
+        device.update_descriptor_sets(&[ubo_write], &[] as &[vk::CopyDescriptorSet]);
+    }
+    if data.field_volume_grid.is_some() {
+        write_volume_descriptors(device, data);
+    }
+    Ok(())
+}
+
+unsafe fn write_volume_descriptors(device: &Device, data: &AppData) {
+    let image_info = &[vk::DescriptorImageInfo::builder()
+        .sampler(data.raymarch_sampler).image_view(data.field_volume_view)
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+    for set in &data.raymarch_descriptor_sets {
+        let volume_write = vk::WriteDescriptorSet::builder()
+            .dst_set(*set).dst_binding(1).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info);
+        device.update_descriptor_sets(&[volume_write], &[] as &[vk::CopyDescriptorSet]);
+    }
+}
+
+/// Uploads `field`, sampled at the nodes of `grid`, for the raymarcher to find the `iso` level set of,
+/// making the volume anew when the grid's dimensions changed. Nothing may be in flight.
+pub unsafe fn upload_field_volume(instance: &Instance, device: &Device, data: &mut AppData, grid: &FieldGrid,
+    field: &[f32], iso: f32) -> Result<()> {
+    if data.field_volume_grid.map(|g| g.dims) != Some(grid.dims) {
+        destroy_field_volume(device, data);
+        let (image, memory, view) = create_texture(instance, device, data, vk::ImageViewType::_3D, grid.dims,
+            vk::Format::R32_SFLOAT, 1, vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)?;
+        data.field_volume = image;
+        data.field_volume_memory = memory;
+        data.field_volume_view = view;
+        write_volume_descriptors(device, data);
+    }
+    upload_texture(instance, device, data, data.field_volume, vk::Format::R32_SFLOAT, grid.dims, 1, 1, field)?;
+    data.field_volume_grid = Some(*grid);
+    data.field_volume_iso = iso;
+    Ok(())
+}
+
+pub unsafe fn destroy_field_volume(device: &Device, data: &mut AppData) {
+    device.destroy_image_view(data.field_volume_view, None);
+    device.destroy_image(data.field_volume, None);
+    device.free_memory(data.field_volume_memory, None);
+    data.field_volume_grid = None;
+}
+
+/// Records the raymarched surface into swapchain image `i`'s render pass, if a volume was uploaded.
+pub unsafe fn record_raymarch_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    let Some(grid) = &data.field_volume_grid else { return };
+    let color = data.phase_colors[0];
+    let constants = RaymarchConstants {
+        origin: glm::vec4(grid.origin.x, grid.origin.y, grid.origin.z, grid.spacing),
+        dims: [grid.dims[0], grid.dims[1], grid.dims[2], data.raymarching.steps],
+        color: glm::vec4(color.x, color.y, color.z, data.field_volume_iso),
+        refinements: data.raymarching.refinements,
+        _pad: [0; 3],
+    };
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.raymarch_pipeline);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.raymarch_pipeline_layout, 0, &[data.raymarch_descriptor_sets[i]], &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const RaymarchConstants).cast::<u8>(),
+        size_of::<RaymarchConstants>());
+    device.cmd_push_constants(command_buffer, data.raymarch_pipeline_layout, vk::ShaderStageFlags::FRAGMENT, 0, bytes);
+    device.cmd_draw(command_buffer, 3, 1, 0, 0);
+}
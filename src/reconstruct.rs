@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use rayon::prelude::*;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::kernel::Kernel;
+use crate::model::Vertex;
+use crate::simulation::{Particle, SimParams, compute_barrier, create_storage_buffer, download_from_buffer, solver_constants,
+    write_compute_descriptor_sets};
+use crate::solver::CellList;
+use crate::utils::{create_compute_pipeline, begin_single_time_commands, end_single_time_commands};
+
+/// How the fluid is drawn.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum FluidRendering {
+    /// As particles.
+    #[default]
+    Particles,
+    /// As the surface `marching_cubes` reconstructs from the splatted volume fraction.
+    Surface,
+    /// As a surface drawn from the particles' depth on screen, smoothed; see `ScreenSpaceFluid`.
+    ScreenSpace,
+    /// As the surface a ray per pixel finds in the splatted volume fraction; see `Raymarching`.
+    Raymarched,
+}
+
+/// Anisotropic kernels for the surface reconstruction (Yu & Turk 2013): each particle splats a kernel
+/// stretched along the spread of its neighbors, so sheets and filaments come out thin rather than as
+/// strings of blobs, centered between the particle and its neighbors so flat surfaces come out flat.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Anisotropy {
+    /// How far, from 0 to 1, each kernel center moves from its particle to the weighted mean of the
+    /// particles around it.
+    pub smoothing: f32,
+    /// The most a kernel's longest axis may outgrow its shortest.
+    pub max_stretch: f32,
+    /// Neighbors within `h` below which a particle keeps the round kernel.
+    pub min_neighbors: u32,
+}
+
+impl Default for Anisotropy {
+    fn default() -> Self {
+        Self { smoothing: ANISOTROPY_SMOOTHING, max_stretch: ANISOTROPY_MAX_STRETCH, min_neighbors: ANISOTROPY_MIN_NEIGHBORS }
+    }
+}
+
+/// A particle's kernel as `anisotropic_kernels` shapes it: the round kernel of radius `h` seen through
+/// `stretch`.
+#[derive(Copy, Clone, Debug)]
+pub struct Ellipsoid {
+    pub center: glm::Vec3,
+    /// Maps offsets from the center onto the round kernel's; its determinant is 1, so the kernel keeps
+    /// its volume.
+    pub stretch: glm::Mat3,
+    /// Half extents of the box around the kernel's support.
+    pub extent: glm::Vec3,
+    /// Volume of the particle, its mass over its phase's rest density.
+    pub volume: f32,
+}
+
+/// Nodes the splat pass samples the fluid's volume fraction at, laid out x fastest.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FieldGrid {
+    pub origin: glm::Vec3,
+    pub spacing: f32,
+    pub dims: [u32; 3],
+}
+
+/// Push constants of the splat pass, laid out to match its `FieldGrid` block.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct FieldConstants {
+    origin: glm::Vec4,
+    dims: [u32; 4],
+}
+
+impl FieldGrid {
+    /// Spans the neighbor grid of `sim`, where every particle is, with `resolution` nodes along its
+    /// longest side.
+    pub fn covering(sim: &SimParams, resolution: u32) -> Self {
+        let extent = glm::vec3(sim.grid_dims[0] as f32, sim.grid_dims[1] as f32, sim.grid_dims[2] as f32)
+            .component_mul(&sim.grid_cell.xyz());
+        let spacing = extent.max() / (resolution.max(2) - 1) as f32;
+        let dims = extent.map(|e| (e / spacing).ceil() as u32 + 1);
+        Self { origin: sim.grid_origin.xyz(), spacing, dims: [dims.x, dims.y, dims.z] }
+    }
+
+    pub fn node_count(&self) -> u32 {
+        self.dims[0] * self.dims[1] * self.dims[2]
+    }
+
+    fn index(&self, [x, y, z]: [u32; 3]) -> usize {
+        ((z * self.dims[1] + y) * self.dims[0] + x) as usize
+    }
+
+    fn position(&self, node: [u32; 3]) -> glm::Vec3 {
+        self.origin + self.spacing * glm::vec3(node[0] as f32, node[1] as f32, node[2] as f32)
+    }
+
+    /// Central difference gradient of `field` at `node`, one-sided at the grid's faces.
+    fn gradient(&self, field: &[f32], node: [u32; 3]) -> glm::Vec3 {
+        let mut gradient = glm::Vec3::zeros();
+        for a in 0..3 {
+            let (mut lo, mut hi) = (node, node);
+            lo[a] = node[a].saturating_sub(1);
+            hi[a] = (node[a] + 1).min(self.dims[a] - 1);
+            if hi[a] > lo[a] {
+                gradient[a] = (field[self.index(hi)] - field[self.index(lo)]) / ((hi[a] - lo[a]) as f32 * self.spacing);
+            }
+        }
+        gradient
+    }
+}
+
+/// Surface reconstruction helpers
+pub unsafe fn create_splat_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    // the solver's buffers and parameters, and the node grid as push constants
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<FieldConstants>() as u32);
+    let set_layouts = &[data.compute_descriptor_set_layout, data.sim_params_set_layout];
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.splat_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    data.splat_pipeline = create_compute_pipeline(device, data.pipeline_cache, data.splat_pipeline_layout, &SPLAT_SHADER.to_string(),
+        &solver_constants(data, false))?;
+    Ok(())
+}
+
+pub unsafe fn destroy_splat_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.splat_pipeline, None);
+    device.destroy_pipeline_layout(data.splat_pipeline_layout, None);
+}
+
+/// Creates the buffer the splat pass writes, with room for `nodes` nodes.
+pub unsafe fn create_field_buffer(instance: &Instance, device: &Device, data: &mut AppData, nodes: u32) -> Result<()> {
+    let nodes = nodes.max(1);
+    let (buffer, memory) = create_storage_buffer(instance, device, data,
+        (size_of::<f32>() as u32 * nodes) as u64, vk::BufferUsageFlags::empty(), false)?;
+    data.field_buffer = buffer;
+    data.field_buffer_memory = memory;
+    data.field_capacity = nodes;
+    Ok(())
+}
+
+pub unsafe fn destroy_field_buffer(device: &Device, data: &AppData) {
+    device.destroy_buffer(data.field_buffer, None);
+    device.free_memory(data.field_buffer_memory, None);
+}
+
+/// Splats the latest particle buffer onto `grid` and reads the volume fractions back, growing the field
+/// buffer first if the grid outgrew it. `frame`'s uniform buffer must hold the parameters of the step
+/// that sorted the particles, and nothing may be in flight.
+pub unsafe fn splat_field(instance: &Instance, device: &Device, data: &mut AppData, grid: &FieldGrid,
+    frame: usize) -> Result<Vec<f32>> {
+    let nodes = grid.node_count();
+    if nodes > data.field_capacity {
+        destroy_field_buffer(device, data);
+        create_field_buffer(instance, device, data, nodes)?;
+        write_compute_descriptor_sets(device, data);
+    }
+    let constants = FieldConstants { origin: glm::vec4(grid.origin.x, grid.origin.y, grid.origin.z, grid.spacing),
+        dims: [grid.dims[0], grid.dims[1], grid.dims[2], nodes] };
+    let command_buffer = begin_single_time_commands(device, data)?;
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, data.splat_pipeline);
+    let sets = [data.compute_descriptor_sets[data.particle_parity], data.sim_params_sets[frame]];
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, data.splat_pipeline_layout, 0,
+        &sets, &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const FieldConstants).cast::<u8>(), size_of::<FieldConstants>());
+    device.cmd_push_constants(command_buffer, data.splat_pipeline_layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+    device.cmd_dispatch(command_buffer, nodes.div_ceil(data.workgroup_size), 1, 1);
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ);
+    end_single_time_commands(device, data, command_buffer)?;
+    download_from_buffer(instance, device, data, data.field_buffer, nodes as usize)
+}
+
+/// Corner `k` of a grid cube as its offset from the cube's lowest node, x in bit 0, y in bit 1, z in bit 2.
+fn corner_offset(k: usize) -> [u32; 3] {
+    [(k & 1) as u32, (k >> 1 & 1) as u32, (k >> 2 & 1) as u32]
+}
+
+/// Triangulates the `iso` level set of `field` sampled at the nodes of `grid`, as indexed vertices of
+/// `color` with normals down the field's gradient, wound counter-clockwise seen from the side below
+/// `iso`.
+///
+/// Rather than a case table, each cube's polygons are traced from its faces: the crossings on a face
+/// pair up into segments, settled by the face center where a face is ambiguous, and the segments of
+/// the six faces close into loops that are fanned into triangles. Neighboring cubes see a shared face
+/// alike, so the surface has no cracks, crossings on shared edges share a vertex and the winding agrees
+/// across cubes.
+pub fn marching_cubes(field: &[f32], grid: &FieldGrid, iso: f32, color: glm::Vec3) -> (Vec<Vertex>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    // vertex of each crossed grid edge, by its lower node and axis
+    let mut crossings: HashMap<(usize, usize), u32> = HashMap::new();
+    let [nx, ny, nz] = grid.dims;
+    for z in 0..nz.saturating_sub(1) {
+        for y in 0..ny.saturating_sub(1) {
+            for x in 0..nx.saturating_sub(1) {
+                let node = |k: usize| {
+                    let o = corner_offset(k);
+                    [x + o[0], y + o[1], z + o[2]]
+                };
+                let values: [f32; 8] = std::array::from_fn(|k| field[grid.index(node(k))]);
+                let inside = values.map(|v| v >= iso);
+                if inside.iter().all(|&i| i) || !inside.iter().any(|&i| i) {
+                    continue;
+                }
+                let mut vertex = |a: usize, b: usize| {
+                    let (lo, hi) = if a < b { (a, b) } else { (b, a) };
+                    let axis = (hi ^ lo).trailing_zeros() as usize;
+                    *crossings.entry((grid.index(node(lo)), axis)).or_insert_with(|| {
+                        let t = ((iso - values[lo]) / (values[hi] - values[lo])).clamp(0.0, 1.0);
+                        let pos = glm::lerp(&grid.position(node(lo)), &grid.position(node(hi)), t);
+                        let gradient = glm::lerp(&grid.gradient(field, node(lo)), &grid.gradient(field, node(hi)), t);
+                        let normal = if gradient.norm() > 0.0 { -gradient.normalize() } else { glm::Vec3::zeros() };
+                        vertices.push(Vertex::new(pos, color, normal));
+                        (vertices.len() - 1) as u32
+                    })
+                };
+                let mut segments = Vec::new();
+                for axis in 0..3 {
+                    let (u, v) = (1 << ((axis + 1) % 3), 1 << ((axis + 2) % 3));
+                    for side in [0, 1 << axis] {
+                        // counter-clockwise about the axis, so seen from outside the cube on the far side;
+                        // edge i of the face runs from cycle[i] to cycle[i + 1]
+                        let cycle = [side, side | u, side | u | v, side | v];
+                        let leaves = |i: usize| (inside[cycle[i]] && !inside[cycle[(i + 1) % 4]]) == (side != 0);
+                        let crossed = (0..4).filter(|&i| inside[cycle[i]] != inside[cycle[(i + 1) % 4]]).collect::<Vec<_>>();
+                        let pairs = if crossed.len() == 2 {
+                            vec![(crossed[0], crossed[1])]
+                        } else if crossed.len() == 4 {
+                            // the corners on the center's side join up across it; cut off the other two
+                            let center = cycle.iter().map(|&c| values[c]).sum::<f32>() / 4.0 >= iso;
+                            (0..4).filter(|&i| inside[cycle[i]] != center).map(|i| ((i + 3) % 4, i)).collect()
+                        } else {
+                            Vec::new()
+                        };
+                        // each segment runs from where the fluid leaves the face, seen from outside the cube,
+                        // to where it comes back, so neighboring cubes run a shared segment in opposite ways
+                        for (i, j) in pairs {
+                            let (i, j) = if leaves(i) { (i, j) } else { (j, i) };
+                            segments.push([vertex(cycle[i], cycle[(i + 1) % 4]), vertex(cycle[j], cycle[(j + 1) % 4])]);
+                        }
+                    }
+                }
+                // every crossed edge borders two faces, so the segments close into loops
+                while let Some([start, mut next]) = segments.pop() {
+                    let mut polygon = vec![start];
+                    while next != start {
+                        polygon.push(next);
+                        let Some(k) = segments.iter().position(|s| s[0] == next) else { break };
+                        next = segments.swap_remove(k)[1];
+                    }
+                    fan(&mut vertices, &polygon, &mut indices);
+                }
+            }
+        }
+    }
+    (vertices, indices)
+}
+
+/// Triangulates a loop `marching_cubes` traced, which runs clockwise seen from the side below the iso
+/// value. Longer loops fan out from a vertex of their own at their center: a fan from one of their
+/// corners could lay a diagonal along a cube face that the neighboring cube's fan lays too.
+fn fan(vertices: &mut Vec<Vertex>, polygon: &[u32], indices: &mut Vec<u32>) {
+    match polygon.len() {
+        0..=2 => {}
+        3 => indices.extend([polygon[0], polygon[2], polygon[1]]),
+        n => {
+            let center = vertices.len() as u32;
+            let (pos, normal) = polygon.iter().map(|&i| &vertices[i as usize])
+                .fold((glm::Vec3::zeros(), glm::Vec3::zeros()), |(p, n), v| (p + v.pos, n + v.normal));
+            let normal = if normal.norm() > 0.0 { normal.normalize() } else { normal };
+            vertices.push(Vertex::new(pos / n as f32, vertices[polygon[0] as usize].color, normal));
+            for k in 0..n {
+                indices.extend([center, polygon[(k + 1) % n], polygon[k]]);
+            }
+        }
+    }
+}
+
+/// Shapes a kernel for every particle from the covariance of the particles within `h` of it, weighted
+/// to fade out towards `h`: the kernel's axes follow the covariance's principal axes and its singular
+/// values, the short ones clamped to `max_stretch` times shorter than the longest.
+pub fn anisotropic_kernels(particles: &[Particle], sim: &SimParams, anisotropy: &Anisotropy) -> Vec<Ellipsoid> {
+    let cells = CellList::build(particles, sim);
+    particles.par_iter().enumerate().map(|(i, p)| {
+        let mut weight = 0.0;
+        let mut mean = glm::Vec3::zeros();
+        let mut neighbors = 0;
+        let mut offsets = Vec::new();
+        cells.for_each_near(sim, &p.pos, |j| {
+            let offset = sim.separation(&particles[j].pos, &p.pos);
+            let dist = glm::length(&offset);
+            if dist < sim.h {
+                let w = 1.0 - (dist / sim.h).powi(3);
+                weight += w;
+                mean += w * offset;
+                neighbors += (j != i) as u32;
+                offsets.push((w, offset));
+            }
+        });
+        mean /= weight;
+        let rest = sim.phases[(p.phase as usize).min(MAX_PHASES - 1)].x;
+        let round = Ellipsoid { center: p.pos + anisotropy.smoothing * mean, stretch: glm::Mat3::identity(),
+            extent: glm::vec3(sim.h, sim.h, sim.h), volume: p.mass / rest };
+        if neighbors < anisotropy.min_neighbors {
+            return round;
+        }
+        let covariance = offsets.iter().fold(glm::Mat3::zeros(), |c, (w, offset)| {
+            let d = offset - mean;
+            c + *w * d * d.transpose()
+        }) / weight;
+        // the covariance is symmetric, so its singular value decomposition is its eigendecomposition
+        let eigen = covariance.symmetric_eigen();
+        let longest = eigen.eigenvalues.max();
+        if longest <= 0.0 {
+            return round;
+        }
+        let sigma = eigen.eigenvalues.map(|s| s.max(longest / anisotropy.max_stretch.max(1.0)));
+        let sigma = sigma / sigma.product().cbrt();
+        let axes = eigen.eigenvectors;
+        let stretch = axes * glm::Mat3::from_diagonal(&sigma.map(|s| 1.0 / s)) * axes.transpose();
+        let inverse = axes * glm::Mat3::from_diagonal(&sigma) * axes.transpose();
+        let extent = glm::vec3(inverse.row(0).norm(), inverse.row(1).norm(), inverse.row(2).norm()) * sim.h;
+        Ellipsoid { stretch, extent, ..round }
+    }).collect()
+}
+
+/// The volume fraction at the nodes of `grid` of the particles `kernels` shape, the CPU counterpart of
+/// the splat pass. Slabs of nodes along z fill in parallel, each summing its kernels in particle order.
+/// Kernels are not wrapped around periodic axes.
+pub fn splat_kernels(kernels: &[Ellipsoid], grid: &FieldGrid, kernel: &Kernel) -> Vec<f32> {
+    let [nx, ny, nz] = grid.dims;
+    // nodes from the first at or past `lo` to the last at or before `hi` along `axis`
+    let nodes = |axis: usize, lo: f32, hi: f32| {
+        let first = ((lo - grid.origin[axis]) / grid.spacing).ceil().max(0.0) as u32;
+        let last = ((hi - grid.origin[axis]) / grid.spacing).floor().min(grid.dims[axis] as f32 - 1.0);
+        first..(last + 1.0).max(0.0) as u32
+    };
+    let mut slabs = vec![Vec::new(); nz as usize];
+    for (k, e) in kernels.iter().enumerate() {
+        for z in nodes(2, e.center.z - e.extent.z, e.center.z + e.extent.z) {
+            slabs[z as usize].push(k);
+        }
+    }
+    let mut field = vec![0.0; grid.node_count() as usize];
+    field.par_chunks_mut((nx * ny) as usize).zip(slabs).enumerate().for_each(|(z, (slab, inside))| {
+        for e in inside.into_iter().map(|k| &kernels[k]) {
+            for y in nodes(1, e.center.y - e.extent.y, e.center.y + e.extent.y) {
+                for x in nodes(0, e.center.x - e.extent.x, e.center.x + e.extent.x) {
+                    let offset = grid.position([x, y, z as u32]) - e.center;
+                    slab[(y * nx + x) as usize] += e.volume * kernel.w(glm::length(&(e.stretch * offset)));
+                }
+            }
+        }
+    });
+    field
+}
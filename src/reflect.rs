@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use anyhow::{anyhow, bail, Result};
+use vulkanalia::prelude::v1_0::*;
+
+use crate::shader_cache::Spirv;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+// the opcodes read
+const OP_NAME: u32 = 5;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// the decorations
+const BLOCK: u32 = 2;
+const BUFFER_BLOCK: u32 = 3;
+const ARRAY_STRIDE: u32 = 6;
+const MATRIX_STRIDE: u32 = 7;
+const BINDING: u32 = 33;
+const DESCRIPTOR_SET: u32 = 34;
+const OFFSET: u32 = 35;
+
+// the storage classes descriptors are in
+const UNIFORM_CONSTANT: u32 = 0;
+const UNIFORM: u32 = 2;
+const STORAGE_BUFFER: u32 = 12;
+
+// the image dimensions that are not sampled images
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+/// A descriptor binding as the shaders declare it, merged over those that share it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// Its array's length, 0 for one left unsized.
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+    /// The bytes of a buffer's block up to its runtime array, if it ends in one.
+    pub block_size: Option<u64>,
+    /// The variable's or its block's name, for the errors.
+    pub name: String,
+}
+
+impl DescriptorBinding {
+    pub fn layout_binding(&self) -> vk::DescriptorSetLayoutBinding {
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(self.binding)
+            .descriptor_type(self.descriptor_type)
+            .descriptor_count(self.count)
+            .stage_flags(self.stages)
+            .build()
+    }
+
+    /// Whether `declared` can be bound through this binding of a layout: the same kind of descriptor,
+    /// as many of them, in a block no bigger.
+    fn admits(&self, declared: &DescriptorBinding) -> bool {
+        let dynamic = |t| match t {
+            vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC => vk::DescriptorType::UNIFORM_BUFFER,
+            vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => vk::DescriptorType::STORAGE_BUFFER,
+            t => t,
+        };
+        dynamic(self.descriptor_type) == dynamic(declared.descriptor_type) && self.count == declared.count
+            && self.stages.contains(declared.stages) && declared.block_size <= self.block_size
+    }
+}
+
+/// What the app writes to a binding, for `check_writes` to hold the shaders' declarations to.
+#[derive(Copy, Clone, Debug)]
+pub struct BindingWrite {
+    pub set: u32,
+    pub binding: u32,
+    pub count: u32,
+    /// The bytes of buffer bound, for those with a fixed range.
+    pub range: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Scalar(u32),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array(u32, u32),
+    RuntimeArray(u32),
+    Struct(Vec<u32>),
+    Pointer(u32),
+}
+
+/// What `reflect_bindings` reads off a module's instructions.
+#[derive(Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<(u32, u32), u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+    /// Each variable's pointer type and storage class.
+    variables: Vec<(u32, u32, u32)>,
+}
+
+impl Module {
+    fn parse(words: &[u32]) -> Result<Self> {
+        let mut module = Module::default();
+        let mut at = 5;
+        while at < words.len() {
+            let (count, opcode) = ((words[at] >> 16) as usize, words[at] & 0xffff);
+            let operands = words.get(at + 1..at + count.max(1)).ok_or_else(|| anyhow!("Truncated SPIR-V instruction."))?;
+            let word = |i: usize| operands.get(i).copied().ok_or_else(|| anyhow!("Truncated SPIR-V instruction {}.", opcode));
+            let ty = match opcode {
+                OP_TYPE_INT | OP_TYPE_FLOAT => Some(Type::Scalar(word(1)? / 8)),
+                OP_TYPE_VECTOR => Some(Type::Vector(word(1)?, word(2)?)),
+                OP_TYPE_MATRIX => Some(Type::Matrix(word(1)?, word(2)?)),
+                OP_TYPE_IMAGE => Some(Type::Image { dim: word(2)?, sampled: word(6)? }),
+                OP_TYPE_SAMPLER => Some(Type::Sampler),
+                OP_TYPE_SAMPLED_IMAGE => Some(Type::SampledImage),
+                OP_TYPE_ARRAY => Some(Type::Array(word(1)?, word(2)?)),
+                OP_TYPE_RUNTIME_ARRAY => Some(Type::RuntimeArray(word(1)?)),
+                OP_TYPE_STRUCT => Some(Type::Struct(operands.get(1..).unwrap_or_default().to_vec())),
+                OP_TYPE_POINTER => Some(Type::Pointer(word(2)?)),
+                _ => None,
+            };
+            if let Some(ty) = ty {
+                module.types.insert(word(0)?, ty);
+            }
+            match opcode {
+                OP_NAME => {
+                    let bytes = operands.get(1..).unwrap_or_default().iter().flat_map(|w| w.to_le_bytes())
+                        .take_while(|b| *b != 0).collect();
+                    module.names.insert(word(0)?, String::from_utf8(bytes)?);
+                }
+                // an array's length, the specialization constants' by their defaults
+                OP_CONSTANT | OP_SPEC_CONSTANT => {
+                    module.constants.insert(word(1)?, word(2)?);
+                }
+                OP_VARIABLE => module.variables.push((word(0)?, word(1)?, word(2)?)),
+                OP_DECORATE => {
+                    module.decorations.insert((word(0)?, word(1)?), operands.get(2).copied().unwrap_or(0));
+                }
+                OP_MEMBER_DECORATE => {
+                    module.member_decorations.insert((word(0)?, word(1)?, word(2)?), operands.get(3).copied().unwrap_or(0));
+                }
+                _ => {}
+            }
+            at += count.max(1);
+        }
+        Ok(module)
+    }
+
+    fn ty(&self, id: u32) -> Result<&Type> {
+        self.types.get(&id).ok_or_else(|| anyhow!("SPIR-V type {} is not declared.", id))
+    }
+
+    /// The bytes of type `id` in a block, its matrices `matrix_stride` apart, up to any runtime array.
+    fn size(&self, id: u32, matrix_stride: Option<u32>) -> Result<u64> {
+        Ok(match self.ty(id)? {
+            Type::Scalar(bytes) => *bytes as u64,
+            Type::Vector(component, n) => self.size(*component, None)? * *n as u64,
+            Type::Matrix(_, columns) => {
+                let stride = matrix_stride.ok_or_else(|| anyhow!("SPIR-V matrix {} has no stride.", id))?;
+                stride as u64 * *columns as u64
+            }
+            Type::Array(_, length) => {
+                let stride = self.decorations.get(&(id, ARRAY_STRIDE)).copied().unwrap_or(0);
+                stride as u64 * self.constants.get(length).copied().unwrap_or(0) as u64
+            }
+            Type::RuntimeArray(_) => 0,
+            Type::Struct(members) => {
+                let mut size = 0;
+                for (i, member) in members.iter().enumerate() {
+                    let offset = self.member_decorations.get(&(id, i as u32, OFFSET)).copied().unwrap_or(0);
+                    let stride = self.member_decorations.get(&(id, i as u32, MATRIX_STRIDE)).copied();
+                    size = size.max(offset as u64 + self.size(*member, stride)?);
+                }
+                size
+            }
+            _ => bail!("SPIR-V type {} has no size in a block.", id),
+        })
+    }
+}
+
+/// Every descriptor binding the SPIR-V of a shader of `stage` declares.
+pub fn reflect_bindings(spirv: &Spirv, stage: vk::ShaderStageFlags) -> Result<Vec<DescriptorBinding>> {
+    let bytes = spirv.as_binary_u8();
+    let mut words = bytes.chunks_exact(4).map(|w| u32::from_le_bytes(w.try_into().unwrap())).collect::<Vec<_>>();
+    if words.first() == Some(&SPIRV_MAGIC.swap_bytes()) {
+        words.iter_mut().for_each(|w| *w = w.swap_bytes());
+    }
+    if !bytes.len().is_multiple_of(4) || words.len() < 5 || words[0] != SPIRV_MAGIC {
+        bail!("Not a SPIR-V module.");
+    }
+    let module = Module::parse(&words)?;
+    let mut bindings = Vec::new();
+    for &(pointer, id, class) in &module.variables {
+        let Some(&binding) = module.decorations.get(&(id, BINDING)) else {
+            continue;
+        };
+        let set = module.decorations.get(&(id, DESCRIPTOR_SET)).copied().unwrap_or(0);
+        let Type::Pointer(mut ty) = *module.ty(pointer)? else {
+            bail!("SPIR-V variable {} is not a pointer.", id);
+        };
+        let mut count = 1;
+        loop {
+            match *module.ty(ty)? {
+                Type::Array(element, length) => {
+                    count *= module.constants.get(&length).copied().unwrap_or(1);
+                    ty = element;
+                }
+                Type::RuntimeArray(element) => {
+                    count = 0;
+                    ty = element;
+                }
+                _ => break,
+            }
+        }
+        let descriptor_type = match (class, module.ty(ty)?) {
+            (UNIFORM, Type::Struct(_)) if module.decorations.contains_key(&(ty, BUFFER_BLOCK)) => vk::DescriptorType::STORAGE_BUFFER,
+            (UNIFORM, Type::Struct(_)) if module.decorations.contains_key(&(ty, BLOCK)) => vk::DescriptorType::UNIFORM_BUFFER,
+            (STORAGE_BUFFER, Type::Struct(_)) => vk::DescriptorType::STORAGE_BUFFER,
+            (UNIFORM_CONSTANT, Type::SampledImage) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (UNIFORM_CONSTANT, Type::Sampler) => vk::DescriptorType::SAMPLER,
+            (UNIFORM_CONSTANT, Type::Image { dim: DIM_BUFFER, sampled: 2 }) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+            (UNIFORM_CONSTANT, Type::Image { dim: DIM_BUFFER, .. }) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            (UNIFORM_CONSTANT, Type::Image { dim: DIM_SUBPASS_DATA, .. }) => vk::DescriptorType::INPUT_ATTACHMENT,
+            (UNIFORM_CONSTANT, Type::Image { sampled: 2, .. }) => vk::DescriptorType::STORAGE_IMAGE,
+            (UNIFORM_CONSTANT, Type::Image { .. }) => vk::DescriptorType::SAMPLED_IMAGE,
+            _ => continue,
+        };
+        let block_size = match module.ty(ty)? {
+            Type::Struct(_) => Some(module.size(ty, None)?),
+            _ => None,
+        };
+        let name = module.names.get(&id).filter(|n| !n.is_empty()).or_else(|| module.names.get(&ty)).cloned().unwrap_or_default();
+        bindings.push(DescriptorBinding { set, binding, descriptor_type, count, stages: stage, block_size, name });
+    }
+    Ok(bindings)
+}
+
+/// Adds the bindings `path` declares to `bindings`, widening the stages of those already there, and
+/// fails on one it declares differently from another shader.
+pub fn merge_bindings(bindings: &mut Vec<DescriptorBinding>, declared: Vec<DescriptorBinding>, path: &str) -> Result<()> {
+    for declared in declared {
+        match bindings.iter_mut().find(|b| (b.set, b.binding) == (declared.set, declared.binding)) {
+            Some(b) if (b.descriptor_type, b.count) != (declared.descriptor_type, declared.count) => bail!(
+                "{} declares set {} binding {} ({}) as {} of {:?}, but another shader as {} of {:?}.", path, declared.set,
+                declared.binding, declared.name, declared.count, declared.descriptor_type, b.count, b.descriptor_type),
+            Some(b) => {
+                b.stages |= declared.stages;
+                b.block_size = b.block_size.max(declared.block_size);
+            }
+            None => bindings.push(declared),
+        }
+    }
+    bindings.sort_by_key(|b| (b.set, b.binding));
+    Ok(())
+}
+
+/// Binds the uniform buffer of `set`'s `binding` among `bindings` at an offset given as it is bound.
+pub fn make_dynamic(bindings: &mut [DescriptorBinding], set: u32, binding: u32) {
+    for b in bindings.iter_mut().filter(|b| (b.set, b.binding) == (set, binding)) {
+        if b.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER {
+            b.descriptor_type = vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC;
+        }
+    }
+}
+
+/// The layout bindings of `set` among `bindings`.
+pub fn set_layout_bindings(bindings: &[DescriptorBinding], set: u32) -> Vec<vk::DescriptorSetLayoutBinding> {
+    bindings.iter().filter(|b| b.set == set).map(DescriptorBinding::layout_binding).collect()
+}
+
+/// The descriptors `sets` sets of `set` among `bindings` take from a pool, by type.
+pub fn pool_sizes(bindings: &[DescriptorBinding], set: u32, sets: u32) -> Vec<vk::DescriptorPoolSize> {
+    let mut sizes = Vec::<vk::DescriptorPoolSize>::new();
+    for b in bindings.iter().filter(|b| b.set == set) {
+        match sizes.iter_mut().find(|s| s.type_ == b.descriptor_type) {
+            Some(size) => size.descriptor_count += b.count * sets,
+            None => sizes.push(vk::DescriptorPoolSize::builder().type_(b.descriptor_type).descriptor_count(b.count * sets).build()),
+        }
+    }
+    sizes
+}
+
+/// Fails, naming the binding, on a write the shaders' `bindings` do not take: to a binding none of them
+/// declares, of another number of descriptors, or of a buffer range shorter than its block.
+pub fn check_writes(bindings: &[DescriptorBinding], writes: &[BindingWrite]) -> Result<()> {
+    for write in writes {
+        let b = bindings.iter().find(|b| (b.set, b.binding) == (write.set, write.binding))
+            .ok_or_else(|| anyhow!("The app writes set {} binding {}, which no shader declares.", write.set, write.binding))?;
+        if b.count != write.count {
+            bail!("Set {} binding {} ({}) is declared with {} descriptors, but the app writes {}.",
+                b.set, b.binding, b.name, b.count, write.count);
+        }
+        if let (Some(block), Some(range)) = (b.block_size, write.range) {
+            if block > range {
+                bail!("Set {} binding {} ({}) is declared as a {}-byte block, but the app binds {} bytes of it.",
+                    b.set, b.binding, b.name, block, range);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fails, naming the binding, if the shader at `path` declares one in the first `sets` sets that the
+/// layout made for `layout` does not take as declared.
+pub fn check_layout(layout: &[DescriptorBinding], declared: &[DescriptorBinding], sets: u32, path: &str) -> Result<()> {
+    for d in declared.iter().filter(|d| d.set < sets) {
+        match layout.iter().find(|b| (b.set, b.binding) == (d.set, d.binding)) {
+            Some(b) if b.admits(d) => {}
+            Some(b) => bail!("{} declares set {} binding {} ({}) as {} of {:?}{} in {:?}, but its layout takes {} of {:?}{} in {:?}.",
+                path, d.set, d.binding, d.name, d.count, d.descriptor_type, block_text(d.block_size), d.stages,
+                b.count, b.descriptor_type, block_text(b.block_size), b.stages),
+            None => bail!("{} declares set {} binding {} ({}), which its layout does not have.", path, d.set, d.binding, d.name),
+        }
+    }
+    Ok(())
+}
+
+fn block_text(size: Option<u64>) -> String {
+    size.map(|size| format!(" ({} bytes)", size)).unwrap_or_default()
+}
@@ -0,0 +1,309 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use anyhow::{anyhow, Result};
+use log::*;
+use nalgebra_glm as glm;
+
+use crate::simulation::Particle;
+
+const MAGIC: &[u8; 8] = b"SPHREPL\0";
+/// Bump whenever the layout below changes.
+const VERSION: u32 = 1;
+/// Bytes of the header: the magic, then the version, the largest particle count, the frame count, the
+/// mean simulated seconds between frames, the keyframe interval and the quantization box.
+const HEADER_SIZE: u64 = 8 + 4 * 5 + 4 * 6;
+/// Largest quantized coordinate; positions are stored as 16-bit fractions of the box.
+const QUANTA: f32 = u16::MAX as f32;
+
+/// What a replay does once it runs past its last frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ReplayEnd {
+    /// Start over from the first frame.
+    #[default]
+    Loop,
+    /// Keep showing the last frame.
+    Hold,
+}
+
+/// Streams recorded particle positions to a file, one frame per solver frame.
+///
+/// A frame is its payload size, simulated time and particle count, then one LEB128 varint per
+/// coordinate: the zigzagged difference of the 16-bit quantized position from that of the same index in
+/// the previous frame, or from zero in a keyframe. The grid sort keeps particles roughly in place in
+/// the buffer, so most differences fit in a byte or two. The header's counts and time step are only
+/// filled in by `finish`; a reader copes without them.
+#[derive(Debug)]
+pub struct Recorder {
+    writer: BufWriter<File>,
+    min: glm::Vec3,
+    max: glm::Vec3,
+    keyframe_interval: u32,
+    previous: Vec<[u16; 3]>,
+    frames: u32,
+    largest: u32,
+    first_time: f32,
+    last_time: f32,
+    payload: Vec<u8>,
+}
+
+impl Recorder {
+    /// Creates `path`, quantizing positions to the box from `min` to `max` and writing every
+    /// `keyframe_interval`-th frame whole so a replay can seek to it.
+    pub fn create(path: impl AsRef<Path>, min: glm::Vec3, max: glm::Vec3, keyframe_interval: u32) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let keyframe_interval = keyframe_interval.max(1);
+        write_header(&mut writer, 0, 0, 0.0, keyframe_interval, min, max)?;
+        Ok(Self { writer, min, max, keyframe_interval, previous: Vec::new(), frames: 0, largest: 0,
+            first_time: 0.0, last_time: 0.0, payload: Vec::new() })
+    }
+
+    /// Appends the positions of `particles` at simulated time `time`.
+    pub fn write(&mut self, particles: &[Particle], time: f32) -> Result<()> {
+        let keyframe = self.frames.is_multiple_of(self.keyframe_interval);
+        if keyframe {
+            self.previous.clear();
+        }
+        self.previous.resize(particles.len(), [0; 3]);
+        self.payload.clear();
+        let scale = glm::vec3(QUANTA, QUANTA, QUANTA).component_div(&(self.max - self.min));
+        for (p, previous) in particles.iter().zip(self.previous.iter_mut()) {
+            let q = (p.pos - self.min).component_mul(&scale);
+            for a in 0..3 {
+                let value = q[a].round().clamp(0.0, QUANTA) as u16;
+                write_varint(&mut self.payload, zigzag(value.wrapping_sub(previous[a]) as i16));
+                previous[a] = value;
+            }
+        }
+        self.writer.write_all(&(self.payload.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&time.to_le_bytes())?;
+        self.writer.write_all(&(particles.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&self.payload)?;
+        if self.frames == 0 {
+            self.first_time = time;
+        }
+        self.last_time = time;
+        self.frames += 1;
+        self.largest = self.largest.max(particles.len() as u32);
+        Ok(())
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Fills in the header and closes the file.
+    pub fn finish(mut self) -> Result<()> {
+        let dt = if self.frames > 1 { (self.last_time - self.first_time) / (self.frames - 1) as f32 } else { 0.0 };
+        self.writer.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.writer, self.largest, self.frames, dt, self.keyframe_interval, self.min, self.max)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// A recording opened for playback, read frame by frame from disk.
+#[derive(Debug)]
+pub struct Replay {
+    reader: BufReader<File>,
+    /// Largest particle count of any frame.
+    pub particle_count: u32,
+    /// Mean simulated seconds between frames.
+    pub dt: f32,
+    min: glm::Vec3,
+    max: glm::Vec3,
+    keyframe_interval: u32,
+    /// File offset of every complete frame.
+    offsets: Vec<u64>,
+    /// Frame the quantized positions below belong to.
+    decoded: Option<u32>,
+    positions: Vec<[u16; 3]>,
+    time: f32,
+}
+
+impl Replay {
+    /// Opens a recording and indexes its frames. A file the recorder never finished, or whose last
+    /// frame was cut short, plays the complete frames it has.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(anyhow!("Not a replay file."));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != VERSION {
+            return Err(anyhow!("Replay version {} is not supported (expected {}).", version, VERSION));
+        }
+        let (particle_count, frames) = (read_u32(&mut reader)?, read_u32(&mut reader)?);
+        let dt = f32::from_bits(read_u32(&mut reader)?);
+        let keyframe_interval = read_u32(&mut reader)?.max(1);
+        let mut bounds = [0.0f32; 6];
+        for b in &mut bounds {
+            *b = f32::from_bits(read_u32(&mut reader)?);
+        }
+        let end = reader.seek(SeekFrom::End(0))?;
+        let mut offsets = Vec::new();
+        let mut offset = HEADER_SIZE;
+        let mut largest = 0;
+        while offset + 12 <= end {
+            reader.seek(SeekFrom::Start(offset))?;
+            let size = read_u32(&mut reader)? as u64;
+            reader.seek_relative(4)?;
+            let count = read_u32(&mut reader)?;
+            if offset + 12 + size > end {
+                break;
+            }
+            offsets.push(offset);
+            largest = largest.max(count);
+            offset += 12 + size;
+        }
+        if offsets.is_empty() {
+            return Err(anyhow!("The replay holds no complete frame."));
+        }
+        if offsets.len() as u32 != frames {
+            warn!("The replay header promises {} frames but {} are complete; playing those.", frames, offsets.len());
+        }
+        Ok(Self { reader, particle_count: largest.max(particle_count), dt,
+            min: glm::make_vec3(&bounds[..3]), max: glm::make_vec3(&bounds[3..]), keyframe_interval, offsets,
+            decoded: None, positions: Vec::new(), time: 0.0 })
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.offsets.len() as u32
+    }
+
+    /// Positions and simulated time of `frame`, decoded on from the frame read last when that is less
+    /// than a keyframe interval behind, else from the nearest keyframe before it.
+    pub fn frame(&mut self, frame: u32) -> Result<(Vec<glm::Vec3>, f32)> {
+        let frame = frame.min(self.frames() - 1);
+        let start = match self.decoded {
+            Some(decoded) if decoded <= frame && frame - decoded < self.keyframe_interval => decoded + 1,
+            _ => frame - frame % self.keyframe_interval,
+        };
+        for k in start..=frame {
+            self.decode(k)?;
+        }
+        let step = (self.max - self.min) / QUANTA;
+        let positions = self.positions.iter()
+            .map(|q| self.min + glm::vec3(q[0] as f32, q[1] as f32, q[2] as f32).component_mul(&step))
+            .collect();
+        Ok((positions, self.time))
+    }
+
+    fn decode(&mut self, frame: u32) -> Result<()> {
+        self.reader.seek(SeekFrom::Start(self.offsets[frame as usize]))?;
+        let size = read_u32(&mut self.reader)? as usize;
+        self.time = f32::from_bits(read_u32(&mut self.reader)?);
+        let count = read_u32(&mut self.reader)? as usize;
+        let mut payload = vec![0u8; size];
+        self.reader.read_exact(&mut payload)?;
+        if frame.is_multiple_of(self.keyframe_interval) {
+            self.positions.clear();
+        }
+        self.positions.resize(count, [0; 3]);
+        let mut bytes = payload.iter();
+        for q in &mut self.positions {
+            for value in q.iter_mut() {
+                let delta = read_varint(&mut bytes).ok_or_else(|| anyhow!("Replay frame {} is corrupt.", frame))?;
+                *value = value.wrapping_add(unzigzag(delta) as u16);
+            }
+        }
+        self.decoded = Some(frame);
+        Ok(())
+    }
+}
+
+/// Where a replay is and how fast it moves, in recorded frames per rendered frame.
+#[derive(Debug)]
+pub struct Playback {
+    pub replay: Replay,
+    pub end: ReplayEnd,
+    /// Recorded frames the cursor moves per rendered frame; negative plays backwards.
+    pub rate: f64,
+    cursor: f64,
+    shown: Option<u32>,
+}
+
+impl Playback {
+    pub fn new(replay: Replay, end: ReplayEnd) -> Self {
+        Self { replay, end, rate: 1.0, cursor: 0.0, shown: None }
+    }
+
+    /// Moves the cursor on by `rate` unless `paused`, wrapping or holding at either end, and returns
+    /// the frame to show if it is not the one already shown.
+    pub fn advance(&mut self, paused: bool) -> Option<u32> {
+        if !paused && self.shown.is_some() {
+            self.cursor += self.rate;
+        }
+        let frames = self.replay.frames() as f64;
+        if self.cursor < 0.0 || self.cursor >= frames {
+            self.cursor = match self.end {
+                ReplayEnd::Loop => self.cursor.rem_euclid(frames),
+                ReplayEnd::Hold => self.cursor.clamp(0.0, frames - 1.0),
+            };
+        }
+        let frame = self.cursor as u32;
+        (self.shown != Some(frame)).then(|| {
+            self.shown = Some(frame);
+            frame
+        })
+    }
+
+    /// Jumps to `frame`, clamped to the recording, shown from the next `advance` on.
+    pub fn seek(&mut self, frame: u32) {
+        self.cursor = frame.min(self.replay.frames() - 1) as f64;
+        self.shown = None;
+    }
+
+    /// The frame shown last.
+    pub fn frame(&self) -> u32 {
+        self.shown.unwrap_or(0)
+    }
+}
+
+fn write_header(writer: &mut impl Write, particle_count: u32, frames: u32, dt: f32, keyframe_interval: u32,
+    min: glm::Vec3, max: glm::Vec3) -> Result<()> {
+    writer.write_all(MAGIC)?;
+    for word in [VERSION, particle_count, frames, dt.to_bits(), keyframe_interval] {
+        writer.write_all(&word.to_le_bytes())?;
+    }
+    for value in min.iter().chain(max.iter()) {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn zigzag(value: i16) -> u32 {
+    ((value << 1) ^ (value >> 15)) as u16 as u32
+}
+
+fn unzigzag(value: u32) -> i16 {
+    ((value >> 1) as i16) ^ -((value & 1) as i16)
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint<'a>(bytes: &mut impl Iterator<Item = &'a u8>) -> Option<u32> {
+    let mut value = 0;
+    for shift in (0..21).step_by(7) {
+        let byte = *bytes.next()?;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
@@ -0,0 +1,104 @@
+use nalgebra_glm as glm;
+
+/// Per-body state the boundary passes read, matching the std430 `Body` in the shaders.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct BodyState {
+    /// Maps the body's rest-frame boundary samples to world space.
+    pub model: glm::Mat4,
+    /// Current center of mass; `w` unused.
+    pub center: glm::Vec4,
+    /// First boundary particle and number of boundary particles owned by the body.
+    pub first: u32,
+    pub count: u32,
+    pub _pad: [u32; 2],
+}
+
+/// Net fluid force and torque on a body, as reduced on the GPU.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BodyForce {
+    pub force: glm::Vec4,
+    pub torque: glm::Vec4,
+}
+
+/// A rigid body driven by the fluid, attached to one of the loaded objects.
+#[derive(Copy, Clone, Debug)]
+pub struct RigidBody {
+    pub object: usize,
+    pub position: glm::Vec3,
+    pub orientation: glm::Quat,
+    pub linear_velocity: glm::Vec3,
+    pub angular_velocity: glm::Vec3,
+    pub mass: f32,
+    /// Diagonal of the body-space inertia tensor.
+    pub inertia: glm::Vec3,
+    /// Half extents of the body's bounding box, used to keep it inside the domain.
+    pub half_extent: glm::Vec3,
+    /// Maps the object's model space to the body frame at the current state.
+    pub rest_transform: glm::Mat4,
+    pub first: u32,
+    pub count: u32,
+}
+
+impl RigidBody {
+    /// A body filling the world-space box `[min, max]` at `density`, with a solid-box inertia tensor.
+    pub fn from_bounds(object: usize, min: glm::Vec3, max: glm::Vec3, density: f32) -> Self {
+        let size = max - min;
+        let mass = density * size.x * size.y * size.z;
+        let sq = size.component_mul(&size);
+        let inertia = glm::vec3(sq.y + sq.z, sq.x + sq.z, sq.x + sq.y) * (mass / 12.0);
+        Self {
+            object, position: (min + max) * 0.5, orientation: glm::quat_identity(),
+            linear_velocity: glm::Vec3::zeros(), angular_velocity: glm::Vec3::zeros(),
+            mass, inertia, half_extent: size * 0.5, rest_transform: glm::identity(), first: 0, count: 0,
+        }
+    }
+
+    /// Body frame to world.
+    pub fn matrix(&self) -> glm::Mat4 {
+        glm::translation(&self.position) * glm::quat_to_mat4(&self.orientation)
+    }
+
+    /// World to body frame, for turning world-space samples into rest positions.
+    pub fn inverse_matrix(&self) -> glm::Mat4 {
+        glm::quat_to_mat4(&glm::quat_conjugate(&self.orientation)) * glm::translation(&-self.position)
+    }
+
+    pub fn state(&self) -> BodyState {
+        BodyState {
+            model: self.matrix(), center: glm::vec3_to_vec4(&self.position),
+            first: self.first, count: self.count, _pad: [0; 2],
+        }
+    }
+
+    /// Semi-implicit Euler step under the fluid force and torque plus gravity, then keeps the body's
+    /// bounding box inside the domain.
+    pub fn step(&mut self, fluid: &BodyForce, gravity: &glm::Vec3, dt: f32, domain_min: &glm::Vec3,
+        domain_max: &glm::Vec3) {
+        let force = fluid.force.xyz() + gravity * self.mass;
+        self.linear_velocity += force / self.mass * dt;
+        self.position += self.linear_velocity * dt;
+
+        // angular momentum in world space with the rotated inertia tensor, including the gyroscopic term
+        let rotation = glm::quat_to_mat3(&self.orientation);
+        let inertia = rotation * glm::diagonal3x3(&self.inertia) * rotation.transpose();
+        let inverse = rotation * glm::diagonal3x3(&self.inertia.map(|i| 1.0 / i)) * rotation.transpose();
+        let w = self.angular_velocity;
+        let torque = fluid.torque.xyz() - glm::cross(&w, &(inertia * w));
+        self.angular_velocity += inverse * torque * dt;
+        let spin = glm::quat(self.angular_velocity.x, self.angular_velocity.y, self.angular_velocity.z, 0.0);
+        self.orientation = glm::quat_normalize(&(self.orientation + spin * self.orientation * (0.5 * dt)));
+
+        for a in 0..3 {
+            let (lo, hi) = (domain_min[a] + self.half_extent[a], domain_max[a] - self.half_extent[a]);
+            if self.position[a] < lo {
+                self.position[a] = lo;
+                self.linear_velocity[a] = self.linear_velocity[a].max(0.0);
+            } else if self.position[a] > hi {
+                self.position[a] = hi;
+                self.linear_velocity[a] = self.linear_velocity[a].min(0.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::mem::size_of;
+use anyhow::{anyhow, Result};
+use log::*;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::simulation::{create_storage_buffer, upload_to_buffer, download_from_buffer, compute_barrier};
+use crate::utils::create_compute_pipeline;
+
+/// Buffers that can be registered for scanning at once.
+const MAX_SCAN_TARGETS: u32 = 8;
+
+/// A `u32` storage buffer registered for `gpu_exclusive_scan`, with its own descriptor set and scratch
+/// space for the per-workgroup sums.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ScanTarget {
+    pub buffer: vk::Buffer,
+    /// Most values a single scan of the buffer may cover.
+    pub capacity: u32,
+    set: vk::DescriptorSet,
+    block_sums: vk::Buffer,
+    block_sums_memory: vk::DeviceMemory,
+}
+
+/// Scan helpers
+pub unsafe fn create_scan_pipelines(device: &Device, data: &mut AppData) -> Result<()> {
+    // binding 0: the values, scanned in place; binding 1: per-workgroup sums
+    let bindings = (0..2).map(|i| {
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(i)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE)
+            .build()
+    }).collect::<Vec<_>>();
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+    data.scan_descriptor_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    // the value count is a push constant, so one set serves scans of any length
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<u32>() as u32);
+    let set_layouts = &[data.scan_descriptor_set_layout];
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.scan_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let layout = data.scan_pipeline_layout;
+    let constants = [data.workgroup_size];
+    data.scan_blocks_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &SCAN_BLOCKS_SHADER.to_string(), &constants)?;
+    data.scan_block_sums_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &SCAN_BLOCK_SUMS_SHADER.to_string(), &constants)?;
+    data.add_block_offsets_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &ADD_BLOCK_OFFSETS_SHADER.to_string(), &constants)?;
+    let pool_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(2 * MAX_SCAN_TARGETS);
+    let pool_sizes = &[pool_size];
+    let info = vk::DescriptorPoolCreateInfo::builder()
+        .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+        .pool_sizes(pool_sizes)
+        .max_sets(MAX_SCAN_TARGETS);
+    data.scan_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    Ok(())
+}
+
+/// Makes `buffer` scannable by `gpu_exclusive_scan` for up to `capacity` values.
+pub unsafe fn register_scan_buffer(instance: &Instance, device: &Device, data: &mut AppData,
+    buffer: vk::Buffer, capacity: u32) -> Result<()> {
+    unregister_scan_buffer(device, data, buffer)?;
+    let layouts = &[data.scan_descriptor_set_layout];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(data.scan_descriptor_pool)
+        .set_layouts(layouts);
+    let set = device.allocate_descriptor_sets(&info)?[0];
+    let groups = capacity.div_ceil(data.workgroup_size).max(1) as u64;
+    let (block_sums, block_sums_memory) = create_storage_buffer(instance, device, data,
+        size_of::<u32>() as u64 * groups, vk::BufferUsageFlags::empty(), false)?;
+    for (binding, buffer) in [buffer, block_sums].iter().enumerate() {
+        let info = vk::DescriptorBufferInfo::builder().buffer(*buffer).offset(0).range(vk::WHOLE_SIZE as u64);
+        let buffer_info = &[info];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(set).dst_binding(binding as u32).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER).buffer_info(buffer_info);
+        device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+    }
+    data.scan_targets.push(ScanTarget { buffer, capacity, set, block_sums, block_sums_memory });
+    Ok(())
+}
+
+/// Releases what `register_scan_buffer` made for `buffer`, if it was registered; call it before
+/// destroying the buffer. Nothing using it may be in flight.
+pub unsafe fn unregister_scan_buffer(device: &Device, data: &mut AppData, buffer: vk::Buffer) -> Result<()> {
+    if let Some(index) = data.scan_targets.iter().position(|t| t.buffer == buffer) {
+        let target = data.scan_targets.swap_remove(index);
+        device.free_descriptor_sets(data.scan_descriptor_pool, &[target.set])?;
+        device.destroy_buffer(target.block_sums, None);
+        device.free_memory(target.block_sums_memory, None);
+    }
+    Ok(())
+}
+
+/// Records an in-place exclusive prefix sum over the first `count` `u32`s of a registered buffer: each
+/// workgroup scans its block and emits the block total, one workgroup scans the totals, and a last pass
+/// adds them back. Earlier compute writes to the buffer must already be visible; afterwards the result
+/// is visible to compute shaders and transfers. This binds its own pipeline layout, so callers rebind
+/// their descriptor sets afterwards.
+pub unsafe fn gpu_exclusive_scan(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    buffer: vk::Buffer, count: u32) -> Result<()> {
+    let target = data.scan_targets.iter().find(|t| t.buffer == buffer)
+        .ok_or_else(|| anyhow!("Buffer {:?} is not registered for scanning.", buffer))?;
+    if count > target.capacity {
+        return Err(anyhow!("Cannot scan {} values in a buffer registered for {}.", count, target.capacity));
+    }
+    if count == 0 {
+        return Ok(());
+    }
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE,
+        data.scan_pipeline_layout, 0, &[target.set], &[]);
+    device.cmd_push_constants(command_buffer, data.scan_pipeline_layout, vk::ShaderStageFlags::COMPUTE,
+        0, &count.to_ne_bytes());
+    let groups = count.div_ceil(data.workgroup_size);
+    let passes = [
+        (data.scan_blocks_pipeline, groups),
+        (data.scan_block_sums_pipeline, 1),
+        (data.add_block_offsets_pipeline, groups),
+    ];
+    for (pipeline, group_count) in passes {
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+        device.cmd_dispatch(command_buffer, group_count, 1, 1);
+        compute_barrier(device, command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_READ);
+    }
+    Ok(())
+}
+
+/// Lengths around the workgroup size and one much larger, where blocked scans tend to go wrong.
+const VERIFY_COUNTS: [u32; 5] = [1, 1023, 1024, 1025, 1 << 20];
+
+/// Small pseudo-random values, so the sums of a million of them still fit in a `u32`.
+fn verify_values(count: u32) -> Vec<u32> {
+    (0..count).map(|i| i.wrapping_mul(2654435761) >> 28).collect()
+}
+
+/// The CPU reference the GPU scan is checked against.
+fn exclusive_scan(values: &[u32]) -> Vec<u32> {
+    values.iter().scan(0u32, |sum, v| {
+        let start = *sum;
+        *sum += v;
+        Some(start)
+    }).collect()
+}
+
+/// Scans known values of awkward lengths on the GPU and compares them with the CPU result.
+pub unsafe fn verify_scan(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    for count in VERIFY_COUNTS {
+        let values = verify_values(count);
+        let expected = exclusive_scan(&values);
+        let (buffer, buffer_memory) = create_storage_buffer(instance, device, data,
+            size_of::<u32>() as u64 * count as u64, vk::BufferUsageFlags::empty(), false)?;
+        upload_to_buffer(instance, device, data, &values, buffer)?;
+        register_scan_buffer(instance, device, data, buffer, count)?;
+        // one-off submission on the compute queue, which the scan pipelines are made for
+        let info = vk::CommandBufferAllocateInfo::builder()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_pool(data.compute_command_pool)
+            .command_buffer_count(1);
+        let command_buffer = device.allocate_command_buffers(&info)?[0];
+        let info = vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        device.begin_command_buffer(command_buffer, &info)?;
+        gpu_exclusive_scan(device, data, command_buffer, buffer, count)?;
+        device.end_command_buffer(command_buffer)?;
+        let command_buffers = &[command_buffer];
+        let info = vk::SubmitInfo::builder().command_buffers(command_buffers);
+        device.queue_submit(data.compute_queue, &[info], vk::Fence::null())?;
+        device.queue_wait_idle(data.compute_queue)?;
+        device.free_command_buffers(data.compute_command_pool, &[command_buffer]);
+        let scanned = download_from_buffer::<u32>(instance, device, data, buffer, count as usize)?;
+        unregister_scan_buffer(device, data, buffer)?;
+        device.destroy_buffer(buffer, None);
+        device.free_memory(buffer_memory, None);
+        if let Some(i) = (0..count as usize).find(|&i| scanned[i] != expected[i]) {
+            return Err(anyhow!("GPU scan of {} values differs at {}: {} instead of {}.",
+                count, i, scanned[i], expected[i]));
+        }
+    }
+    info!("GPU exclusive scan matches the CPU.");
+    Ok(())
+}
+
+/// Destroys the scan pipelines and every registered buffer's scratch space; freeing the pool frees the sets.
+pub unsafe fn destroy_scan(device: &Device, data: &AppData) {
+    for target in &data.scan_targets {
+        device.destroy_buffer(target.block_sums, None);
+        device.free_memory(target.block_sums_memory, None);
+    }
+    device.destroy_pipeline(data.scan_blocks_pipeline, None);
+    device.destroy_pipeline(data.scan_block_sums_pipeline, None);
+    device.destroy_pipeline(data.add_block_offsets_pipeline, None);
+    device.destroy_pipeline_layout(data.scan_pipeline_layout, None);
+    device.destroy_descriptor_pool(data.scan_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(data.scan_descriptor_set_layout, None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The three scan passes done on the CPU block by block, as the shaders split the work.
+    fn blocked_exclusive_scan(values: &[u32], workgroup: usize) -> Vec<u32> {
+        let mut scanned = Vec::with_capacity(values.len());
+        let mut block_sums = Vec::new();
+        for block in values.chunks(workgroup) {
+            scanned.extend(exclusive_scan(block));
+            block_sums.push(block.iter().sum::<u32>());
+        }
+        let mut offsets = Vec::with_capacity(block_sums.len());
+        let mut carry = 0;
+        for chunk in block_sums.chunks(workgroup) {
+            offsets.extend(exclusive_scan(chunk).iter().map(|offset| carry + offset));
+            carry += chunk.iter().sum::<u32>();
+        }
+        for (i, value) in scanned.iter_mut().enumerate() {
+            *value += offsets[i / workgroup];
+        }
+        scanned
+    }
+
+    #[test]
+    fn reference_scan_is_exclusive() {
+        assert_eq!(exclusive_scan(&[3, 1, 4, 1, 5]), [0, 3, 4, 8, 9]);
+        assert!(exclusive_scan(&[]).is_empty());
+    }
+
+    /// Checks how the work is split into blocks, not the shaders: the tests have no device, so the GPU
+    /// scan itself is only checked at runtime, by `verify_scan` under `SPH_VERIFY_SCAN`.
+    #[test]
+    fn blocked_scan_model_matches_reference() {
+        for count in VERIFY_COUNTS {
+            let values = verify_values(count);
+            let expected = exclusive_scan(&values);
+            assert_eq!(expected.len(), count as usize);
+            for workgroup in [64, 256, 1024] {
+                assert_eq!(blocked_exclusive_scan(&values, workgroup), expected,
+                    "{} values in blocks of {}", count, workgroup);
+            }
+        }
+    }
+}
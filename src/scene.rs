@@ -0,0 +1,632 @@
+use std::fs;
+use anyhow::{anyhow, Context, Result};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::scene_model;
+use crate::config::{DAM_BREAK_SIZE, MAX_OPENINGS, MAX_PHASES, SDF_RESOLUTION, SURFACE_THRESHOLD};
+use crate::emitter::{Emitter, Inflow, Outflow, Sink};
+use crate::heightfield::Heightfield;
+use crate::kernel::KernelKind;
+use crate::model::{load_particles, MaterialKind, Obstacle};
+use crate::simulation::{spawn_block, BoundaryKind, BoundaryMode, ContainerMotion, Particle, Phase, Refinement, SimParams, SurfaceDetection, TimeMode};
+
+/// Size of the shaking tank, its depth of water, and how far it shakes.
+const SHAKING_TANK_SIZE: [f32; 3] = [1.0, 0.5, 0.3];
+const SHAKING_TANK_DEPTH: f32 = 0.2;
+const SHAKING_TANK_AMPLITUDE: f32 = 0.02;
+/// Size of the open channel, its depth of water and the speed and depth in lattice layers of its inflow.
+const CHANNEL_SIZE: [f32; 3] = [1.6, 0.4, 0.3];
+const CHANNEL_DEPTH: f32 = 0.15;
+const CHANNEL_SPEED: f32 = 0.4;
+const CHANNEL_BUFFER_LAYERS: u32 = 3;
+/// Where along x the cylinder in the channel stands, and its radius.
+const CHANNEL_CYLINDER_X: f32 = -0.3;
+const CHANNEL_CYLINDER_RADIUS: f32 = 0.05;
+/// Size of the crown splash tank and its depth of water, and the edge of the block of water dropped into
+/// it and how high above the water its bottom starts.
+const CROWN_SPLASH_SIZE: [f32; 3] = [0.6, 0.5, 0.6];
+const CROWN_SPLASH_DEPTH: f32 = 0.06;
+const CROWN_SPLASH_DROP: f32 = 0.16;
+const CROWN_SPLASH_HEIGHT: f32 = 0.2;
+/// Size of the river's domain, how high its terrain rises above the floor, and the speed, depth and
+/// length in lattice layers of the inflow feeding it.
+const RIVER_SIZE: [f32; 3] = [1.6, 0.5, 0.6];
+const RIVER_RELIEF: f32 = 0.3;
+const RIVER_SPEED: f32 = 0.5;
+const RIVER_DEPTH: f32 = 0.08;
+const RIVER_BUFFER_LAYERS: u32 = 3;
+/// Samples along x and z of the built-in valley.
+const VALLEY_SAMPLES: [u32; 2] = [129, 49];
+/// Of the relief, the drop of the valley floor from one end to the other and the height of the banks;
+/// and as fractions of the width, how far the river meanders either side of the middle, its half width
+/// and the width of its banks.
+const VALLEY_DROP: f32 = 0.3;
+const VALLEY_BANKS: f32 = 0.7;
+const VALLEY_MEANDER: f32 = 0.2;
+const VALLEY_HALF_WIDTH: f32 = 0.18;
+const VALLEY_BANK_WIDTH: f32 = 0.12;
+/// Tank length per column width along x; the water has three widths to run into.
+const DAM_BREAK_TANK_LENGTH: f32 = 4.0;
+/// Tank height per column height, leaving room for the splash against the far wall.
+const DAM_BREAK_TANK_HEIGHT: f32 = 2.0;
+/// WCSPH settings the dam break runs stably with under the CFL time step: smoothing radius (about
+/// 1.7 particle spacings), equation of state stiffness, rest density and viscosity. They are also the
+/// defaults of every scene file.
+const DAM_BREAK_H: f32 = 0.0457;
+const DAM_BREAK_STIFFNESS: f32 = 3.0;
+const DAM_BREAK_REST_DENSITY: f32 = 998.29;
+const DAM_BREAK_VISCOSITY: f32 = 3.5;
+
+/// Everything a simulation starts from: fluid, obstacles, emitters, sinks, the domain and solver
+/// settings, and how to look at it all. Read from and written to TOML; whatever a file leaves out is
+/// taken from the default dam break.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scene {
+    /// World-space radius to draw particles at.
+    pub particle_radius: f32,
+    /// Particle file loaded on top of the fluid blocks, in the format `load_particles` reads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub particle_file: Option<String>,
+    pub domain: Domain,
+    pub solver: Solver,
+    pub camera: CameraPose,
+    /// Fluid phases by index; the first sets the solver's reference density and viscosity.
+    pub phases: Vec<PhaseSpec>,
+    pub fluid: Vec<FluidBlock>,
+    pub emitters: Vec<EmitterSpec>,
+    pub sinks: Vec<SinkSpec>,
+    pub inflows: Vec<InflowSpec>,
+    pub outflows: Vec<OutflowSpec>,
+    pub obstacles: Vec<ObstacleSpec>,
+    pub terrains: Vec<TerrainSpec>,
+}
+
+/// The box particles live in; those leaving it by more than the margin are removed, except along
+/// periodic axes, where they come back in on the other side. The box may move as a whole.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Domain {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    #[serde(default)]
+    pub boundaries: [BoundaryKind; 3],
+    /// How the walls move, starting with the run; see `App::set_container_motion`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motion: Option<ContainerMotion>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Solver {
+    pub kernel: KernelKind,
+    /// Smoothing radius.
+    pub h: f32,
+    pub stiffness: f32,
+    pub gravity: [f32; 3],
+    pub boundary: BoundaryMode,
+    pub time: TimeMode,
+    /// See `App::set_strict_order`.
+    pub strict_order: bool,
+    /// See `App::set_vorticity_epsilon`; zero leaves it off.
+    pub vorticity_epsilon: f32,
+    /// See `App::set_surface_correction`.
+    pub surface_correction: bool,
+    /// See `App::set_refinement`; absent leaves it off.
+    pub refinement: Option<Refinement>,
+    /// See `App::set_surface_detection`; absent leaves it off.
+    pub surface_detection: Option<SurfaceDetection>,
+}
+
+/// Orbit of the camera around the origin: distance and degrees of yaw and pitch.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraPose {
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PhaseSpec {
+    pub rest_density: f32,
+    pub viscosity: f32,
+    pub color: [f32; 3],
+}
+
+/// A box filled with particles of one phase, one at the center of each cube of the given spacing.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FluidBlock {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    #[serde(default = "default_spacing")]
+    pub spacing: f32,
+    #[serde(default)]
+    pub phase: u32,
+}
+
+/// See `Emitter`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmitterSpec {
+    pub position: [f32; 3],
+    pub direction: [f32; 3],
+    pub radius: f32,
+    pub speed: f32,
+    pub rate: f32,
+    #[serde(default)]
+    pub phase: u32,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+/// See `Sink`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SinkSpec {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+/// See `Inflow`; the flow runs along the axis `velocity` is largest in.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InflowSpec {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub velocity: [f32; 3],
+    #[serde(default)]
+    pub phase: u32,
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+/// See `Outflow`; `direction` points out of the box along an axis.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutflowSpec {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    pub direction: [f32; 3],
+    #[serde(default = "default_active")]
+    pub active: bool,
+}
+
+/// A mesh placed in the world by scaling, then rotating about x, y and z in turn (degrees), then translating.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObstacleSpec {
+    /// OBJ file, or a built-in mesh; see `Object::new`.
+    pub mesh: String,
+    #[serde(default)]
+    pub translation: [f32; 3],
+    #[serde(default)]
+    pub rotation: [f32; 3],
+    #[serde(default = "default_scale")]
+    pub scale: [f32; 3],
+    #[serde(default)]
+    pub collision: Obstacle,
+    #[serde(default = "default_sdf_resolution")]
+    pub sdf_resolution: u32,
+    /// Whether particles near the obstacle split; see `Solver::refinement`.
+    #[serde(default)]
+    pub refine: bool,
+    #[serde(default)]
+    pub shading: MaterialKind,
+    /// Opacity the whole mesh is drawn with in place of its materials'; see `Object::opacity`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f32>,
+}
+
+/// A heightfield spanning `min` to `max`, from a grayscale PNG or the built-in valley; see `Heightfield`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TerrainSpec {
+    /// PNG file, or `builtin:valley` for a river valley falling along x.
+    pub image: String,
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    #[serde(default)]
+    pub collision: Obstacle,
+    #[serde(default = "default_terrain_color")]
+    pub color: [f32; 3],
+    /// Whether particles near the terrain split; see `Solver::refinement`.
+    #[serde(default)]
+    pub refine: bool,
+    #[serde(default)]
+    pub shading: MaterialKind,
+}
+
+fn default_spacing() -> f32 {
+    SimParams::PARTICLE_SPACING
+}
+
+fn default_active() -> bool {
+    true
+}
+
+fn default_scale() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn default_sdf_resolution() -> u32 {
+    SDF_RESOLUTION
+}
+
+fn default_terrain_color() -> [f32; 3] {
+    [0.45, 0.38, 0.28]
+}
+
+/// Height of the built-in valley, as a fraction of its relief, at the fractions `(u, v)` of the way
+/// along x and z: a floor falling along x, cut by a meandering river between smooth banks.
+fn valley(u: f32, v: f32) -> f32 {
+    let middle = 0.5 - VALLEY_MEANDER * (std::f32::consts::TAU * u).cos();
+    let t = (((v - middle).abs() - VALLEY_HALF_WIDTH) / VALLEY_BANK_WIDTH).clamp(0.0, 1.0);
+    VALLEY_DROP * (1.0 - u) + VALLEY_BANKS * t * t * (3.0 - 2.0 * t)
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        let [nx, ny, nz] = DAM_BREAK_SIZE;
+        Self::dam_break(nx, ny, nz)
+    }
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self { kernel: KernelKind::default(), h: DAM_BREAK_H, stiffness: DAM_BREAK_STIFFNESS,
+            gravity: [0.0, -9.81, 0.0], boundary: BoundaryMode::default(), time: TimeMode::default(),
+            strict_order: false, vorticity_epsilon: 0.0, surface_correction: false, refinement: None,
+            surface_detection: None }
+    }
+}
+
+impl Default for PhaseSpec {
+    fn default() -> Self {
+        Self { rest_density: DAM_BREAK_REST_DENSITY, viscosity: DAM_BREAK_VISCOSITY, color: [0.2, 0.45, 0.9] }
+    }
+}
+
+impl ObstacleSpec {
+    /// `mesh` as the plain mesh viewer places it: turned by the scene model and colliding through its SDF.
+    pub fn viewed(mesh: String) -> Self {
+        Self { mesh, translation: [0.0; 3], rotation: [0.0, 0.0, 90.0], scale: default_scale(),
+            collision: Obstacle::default(), sdf_resolution: SDF_RESOLUTION, refine: false,
+            shading: MaterialKind::default(), opacity: None }
+    }
+
+    /// The object transform that puts the mesh where the spec says, under the scene model matrix.
+    pub fn transform(&self) -> glm::Mat4 {
+        let [rx, ry, rz] = self.rotation.map(f32::to_radians);
+        let world = glm::translation(&glm::make_vec3(&self.translation))
+            * glm::rotation(rz, &glm::vec3(0.0, 0.0, 1.0))
+            * glm::rotation(ry, &glm::vec3(0.0, 1.0, 0.0))
+            * glm::rotation(rx, &glm::vec3(1.0, 0.0, 0.0))
+            * glm::scaling(&glm::make_vec3(&self.scale));
+        glm::inverse(&scene_model()) * world
+    }
+}
+
+impl TerrainSpec {
+    pub fn heightfield(&self) -> Result<Heightfield> {
+        let (min, max) = (glm::make_vec3(&self.min), glm::make_vec3(&self.max));
+        match self.image.as_str() {
+            "builtin:valley" => Ok(Heightfield::from_fn(&min, &max, VALLEY_SAMPLES, valley)),
+            path => Heightfield::load_png(path, &min, &max),
+        }
+    }
+}
+
+impl Scene {
+    /// A column of `nx * ny * nz` particles at rest against the low-x end of a tank centered on the
+    /// origin, which collapses once gravity takes it. The tank is as deep as the column along z.
+    pub fn dam_break(nx: u32, ny: u32, nz: u32) -> Self {
+        let spacing = SimParams::PARTICLE_SPACING;
+        let column = glm::vec3(nx as f32, ny as f32, nz as f32) * spacing;
+        let tank = column.component_mul(&glm::vec3(DAM_BREAK_TANK_LENGTH, DAM_BREAK_TANK_HEIGHT, 1.0));
+        let min = -tank / 2.0;
+        let block = FluidBlock { min: min.into(), max: (min + column).into(), spacing, phase: 0 };
+        Self {
+            particle_radius: spacing / 2.0,
+            particle_file: None,
+            domain: Domain { min: min.into(), max: (tank / 2.0).into(), boundaries: Default::default(), motion: None },
+            solver: Solver::default(),
+            camera: CameraPose { distance: 1.8 * tank.max(), yaw: 90.0, pitch: 25.0 },
+            phases: vec![PhaseSpec::default()],
+            fluid: vec![block],
+            emitters: Vec::new(),
+            sinks: Vec::new(),
+            inflows: Vec::new(),
+            outflows: Vec::new(),
+            obstacles: Vec::new(),
+            terrains: Vec::new(),
+        }
+    }
+
+    /// A long, shallow tank of water shaken along x at the resonance of its first sloshing mode, so
+    /// the waves build up from one shake to the next.
+    pub fn shaking_tank() -> Self {
+        let spacing = SimParams::PARTICLE_SPACING;
+        let tank = glm::make_vec3(&SHAKING_TANK_SIZE);
+        let min = -tank / 2.0;
+        let water = glm::vec3(tank.x, SHAKING_TANK_DEPTH, tank.z);
+        // shallow water waves of wavelength twice the tank
+        let k = std::f32::consts::PI / tank.x;
+        let frequency = (9.81 * k * (k * SHAKING_TANK_DEPTH).tanh()).sqrt() / std::f32::consts::TAU;
+        let motion = ContainerMotion::Shake { axis: [1.0, 0.0, 0.0], amplitude: SHAKING_TANK_AMPLITUDE, frequency };
+        Self {
+            domain: Domain { min: min.into(), max: (tank / 2.0).into(), boundaries: Default::default(), motion: Some(motion) },
+            camera: CameraPose { distance: 1.8 * tank.max(), yaw: 90.0, pitch: 20.0 },
+            fluid: vec![FluidBlock { min: min.into(), max: (min + water).into(), spacing, phase: 0 }],
+            ..Self::dam_break(1, 1, 1)
+        }
+    }
+
+    /// Water streaming down an open channel from an inflow at the low-x end to an outflow at the high-x
+    /// end, past an upright cylinder that sheds a wake.
+    pub fn channel_flow() -> Self {
+        let spacing = SimParams::PARTICLE_SPACING;
+        let channel = glm::make_vec3(&CHANNEL_SIZE);
+        let (min, max) = (-channel / 2.0, channel / 2.0);
+        let surface = min.y + CHANNEL_DEPTH;
+        let buffer = CHANNEL_BUFFER_LAYERS as f32 * spacing;
+        let inflow = InflowSpec { min: min.into(), max: [min.x + buffer, surface, max.z],
+            velocity: [CHANNEL_SPEED, 0.0, 0.0], phase: 0, active: true };
+        let outflow = OutflowSpec { min: [max.x - 2.0 * DAM_BREAK_H, min.y, min.z], max: max.into(),
+            direction: [1.0, 0.0, 0.0], active: true };
+        let cylinder = ObstacleSpec { mesh: "builtin:cylinder".to_string(), translation: [CHANNEL_CYLINDER_X, 0.0, 0.0],
+            rotation: [0.0; 3], scale: [CHANNEL_CYLINDER_RADIUS, channel.y / 2.0, CHANNEL_CYLINDER_RADIUS],
+            collision: Obstacle::Sdf, sdf_resolution: SDF_RESOLUTION, refine: false, shading: MaterialKind::Lit,
+            opacity: None };
+        Self {
+            domain: Domain { min: min.into(), max: max.into(), boundaries: Default::default(), motion: None },
+            camera: CameraPose { distance: 1.5 * channel.max(), yaw: 60.0, pitch: 45.0 },
+            // the channel starts full up to the inflow, so the wake forms without a dam break first
+            fluid: vec![FluidBlock { min: [min.x + buffer, min.y, min.z], max: [max.x, surface, max.z], spacing, phase: 0 }],
+            inflows: vec![inflow],
+            outflows: vec![outflow],
+            obstacles: vec![cylinder],
+            ..Self::dam_break(1, 1, 1)
+        }
+    }
+
+    /// A block of water dropped into a thin layer of water, which throws up a thin crown of a sheet where
+    /// it lands.
+    pub fn crown_splash() -> Self {
+        let spacing = SimParams::PARTICLE_SPACING;
+        let tank = glm::make_vec3(&CROWN_SPLASH_SIZE);
+        let min = -tank / 2.0;
+        let surface = min.y + CROWN_SPLASH_DEPTH;
+        let drop = glm::vec3(-CROWN_SPLASH_DROP / 2.0, surface + CROWN_SPLASH_HEIGHT, -CROWN_SPLASH_DROP / 2.0);
+        Self {
+            domain: Domain { min: min.into(), max: (tank / 2.0).into(), boundaries: Default::default(), motion: None },
+            camera: CameraPose { distance: 1.8 * tank.max(), yaw: 90.0, pitch: 30.0 },
+            fluid: vec![
+                FluidBlock { min: min.into(), max: [-min.x, surface, -min.z], spacing, phase: 0 },
+                FluidBlock { min: drop.into(), max: (drop + glm::vec3(1.0, 1.0, 1.0) * CROWN_SPLASH_DROP).into(), spacing, phase: 0 },
+            ],
+            ..Self::dam_break(1, 1, 1)
+        }
+    }
+
+    /// A river fed at the low-x end winding down a valley of heightfield terrain to an outflow at the
+    /// high-x end.
+    pub fn river() -> Self {
+        let spacing = SimParams::PARTICLE_SPACING;
+        let size = glm::make_vec3(&RIVER_SIZE);
+        let (min, max) = (-size / 2.0, size / 2.0);
+        let terrain = TerrainSpec { image: "builtin:valley".to_string(), min: min.into(),
+            max: [max.x, min.y + RIVER_RELIEF, max.z], collision: Obstacle::Sdf, color: default_terrain_color(), refine: false,
+            shading: MaterialKind::Lit };
+        // the inflow sits in the river bed, clear of the ground anywhere under it
+        let buffer = RIVER_BUFFER_LAYERS as f32 * spacing;
+        let (u, v) = (buffer / size.x, VALLEY_HALF_WIDTH / 2.0);
+        let middle = 0.5 - VALLEY_MEANDER;
+        let ground = min.y + RIVER_RELIEF * valley(0.0, middle).max(valley(u, middle - v)).max(valley(u, middle + v));
+        let inflow = InflowSpec { min: [min.x, ground + spacing / 2.0, min.z + (middle - v) * size.z],
+            max: [min.x + buffer, ground + RIVER_DEPTH, min.z + (middle + v) * size.z],
+            velocity: [RIVER_SPEED, 0.0, 0.0], phase: 0, active: true };
+        let outflow = OutflowSpec { min: [max.x - 2.0 * DAM_BREAK_H, min.y, min.z], max: max.into(),
+            direction: [1.0, 0.0, 0.0], active: true };
+        Self {
+            domain: Domain { min: min.into(), max: max.into(), boundaries: Default::default(), motion: None },
+            camera: CameraPose { distance: 1.5 * size.max(), yaw: 60.0, pitch: 40.0 },
+            fluid: Vec::new(),
+            inflows: vec![inflow],
+            outflows: vec![outflow],
+            terrains: vec![terrain],
+            ..Self::dam_break(1, 1, 1)
+        }
+    }
+
+    /// Reads a scene file and checks it makes sense, naming the offending line or field on failure.
+    pub fn from_path(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Cannot read scene file {}.", path))?;
+        let scene: Self = toml::from_str(&text).with_context(|| format!("Cannot parse scene file {}.", path))?;
+        scene.validate().with_context(|| format!("Invalid scene file {}.", path))?;
+        Ok(scene)
+    }
+
+    /// The scene as TOML, with every default filled in.
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        fs::write(path, self.to_toml()?).with_context(|| format!("Cannot write scene file {}.", path))
+    }
+
+    /// Checks everything `sim` and `particles` rely on.
+    pub fn validate(&self) -> Result<()> {
+        let ordered = |name: String, min: &[f32; 3], max: &[f32; 3]| {
+            if (0..3).all(|a| min[a] < max[a]) {
+                Ok(())
+            } else {
+                Err(anyhow!("{}: min {:?} is not below max {:?}.", name, min, max))
+            }
+        };
+        ordered("domain".to_string(), &self.domain.min, &self.domain.max)?;
+        for a in 0..3 {
+            let extent = self.domain.max[a] - self.domain.min[a];
+            if self.domain.boundaries[a] == BoundaryKind::Periodic && extent < 3.0 * self.solver.h {
+                return Err(anyhow!("domain.boundaries[{}]: a periodic axis must be at least 3 h = {} long, not {}.",
+                    a, 3.0 * self.solver.h, extent));
+            }
+        }
+        if let Some(motion) = &self.domain.motion {
+            if self.domain.boundaries.contains(&BoundaryKind::Periodic) {
+                return Err(anyhow!("domain.motion: periodic boundaries cannot move with the container."));
+            }
+            let (ContainerMotion::Shake { axis, frequency, .. } | ContainerMotion::Rock { axis, frequency, .. }) = motion;
+            if glm::length(&glm::make_vec3(axis)) == 0.0 || *frequency < 0.0 {
+                return Err(anyhow!("domain.motion: needs a nonzero axis and a frequency of at least 0, not {:?}.", motion));
+            }
+        }
+        if self.solver.h <= 0.0 {
+            return Err(anyhow!("solver.h: the smoothing radius must be positive, not {}.", self.solver.h));
+        }
+        if self.solver.vorticity_epsilon < 0.0 {
+            return Err(anyhow!("solver.vorticity_epsilon: must not be negative, not {}.", self.solver.vorticity_epsilon));
+        }
+        if self.phases.is_empty() || self.phases.len() > MAX_PHASES {
+            return Err(anyhow!("phases: there must be 1 to {} phases, not {}.", MAX_PHASES, self.phases.len()));
+        }
+        let phase = |name: String, phase: u32| {
+            if (phase as usize) < self.phases.len() {
+                Ok(())
+            } else {
+                Err(anyhow!("{}.phase: there is no phase {} among the {} given.", name, phase, self.phases.len()))
+            }
+        };
+        for (i, block) in self.fluid.iter().enumerate() {
+            ordered(format!("fluid[{}]", i), &block.min, &block.max)?;
+            phase(format!("fluid[{}]", i), block.phase)?;
+            if block.spacing <= 0.0 {
+                return Err(anyhow!("fluid[{}].spacing: must be positive, not {}.", i, block.spacing));
+            }
+            if (0..3).any(|a| block.min[a] < self.domain.min[a] || block.max[a] > self.domain.max[a]) {
+                return Err(anyhow!("fluid[{}]: the block {:?}..{:?} is not inside the domain.", i, block.min, block.max));
+            }
+        }
+        for (i, emitter) in self.emitters.iter().enumerate() {
+            phase(format!("emitters[{}]", i), emitter.phase)?;
+            if glm::length(&glm::make_vec3(&emitter.direction)) == 0.0 {
+                return Err(anyhow!("emitters[{}].direction: must not be zero.", i));
+            }
+        }
+        for (i, sink) in self.sinks.iter().enumerate() {
+            ordered(format!("sinks[{}]", i), &sink.min, &sink.max)?;
+        }
+        if self.inflows.len() + self.outflows.len() > MAX_OPENINGS {
+            return Err(anyhow!("inflows, outflows: at most {} together, not {}.", MAX_OPENINGS,
+                self.inflows.len() + self.outflows.len()));
+        }
+        // the ghosts must reach a whole kernel's width back from where they let go of the fluid
+        let depth = |min: &[f32; 3], max: &[f32; 3], flow: &[f32; 3]| {
+            let a = glm::make_vec3(flow).abs().imax();
+            max[a] - min[a]
+        };
+        for (i, inflow) in self.inflows.iter().enumerate() {
+            ordered(format!("inflows[{}]", i), &inflow.min, &inflow.max)?;
+            phase(format!("inflows[{}]", i), inflow.phase)?;
+            if glm::length(&glm::make_vec3(&inflow.velocity)) == 0.0 {
+                return Err(anyhow!("inflows[{}].velocity: must not be zero.", i));
+            }
+            if depth(&inflow.min, &inflow.max, &inflow.velocity) < self.solver.h {
+                return Err(anyhow!("inflows[{}]: must be at least h = {} deep along the flow.", i, self.solver.h));
+            }
+        }
+        for (i, outflow) in self.outflows.iter().enumerate() {
+            ordered(format!("outflows[{}]", i), &outflow.min, &outflow.max)?;
+            if glm::length(&glm::make_vec3(&outflow.direction)) == 0.0 {
+                return Err(anyhow!("outflows[{}].direction: must not be zero.", i));
+            }
+        }
+        for (i, obstacle) in self.obstacles.iter().enumerate() {
+            if obstacle.sdf_resolution == 0 {
+                return Err(anyhow!("obstacles[{}].sdf_resolution: must be positive.", i));
+            }
+        }
+        for (i, terrain) in self.terrains.iter().enumerate() {
+            ordered(format!("terrains[{}]", i), &terrain.min, &terrain.max)?;
+        }
+        Ok(())
+    }
+
+    /// The solver parameters the scene describes, with no particles yet.
+    pub fn sim(&self) -> SimParams {
+        let mut sim = SimParams::new();
+        sim.domain_min = glm::vec3_to_vec4(&glm::make_vec3(&self.domain.min));
+        sim.domain_max = glm::vec3_to_vec4(&glm::make_vec3(&self.domain.max));
+        sim.gravity = glm::vec3_to_vec4(&glm::make_vec3(&self.solver.gravity));
+        sim.stiffness = self.solver.stiffness;
+        sim.vorticity_epsilon = self.solver.vorticity_epsilon;
+        sim.surface_threshold = if self.solver.surface_correction { SURFACE_THRESHOLD } else { 0.0 };
+        sim.set_refinement(self.solver.refinement.as_ref());
+        sim.set_surface_detection(self.solver.surface_detection.as_ref());
+        sim.set_kernel(self.solver.kernel, self.solver.h);
+        // validate() turns away periodic axes too short to wrap
+        sim.set_boundary_kinds(self.domain.boundaries).ok();
+        sim.set_container(self.domain.motion.as_ref(), 0.0, true);
+        for (i, phase) in self.phases.iter().enumerate() {
+            sim.set_phase(i, &Phase { rest_density: phase.rest_density, viscosity: phase.viscosity,
+                color: glm::make_vec3(&phase.color) });
+        }
+        sim
+    }
+
+    /// The fluid blocks' particles followed by the particle file's, if any; a block's particles are as
+    /// heavy as its spacing makes them at their phase's rest density.
+    pub fn particles(&self, sim: &SimParams) -> Result<Vec<Particle>> {
+        let mut particles = Vec::new();
+        for block in &self.fluid {
+            let mass = sim.phase_mass(block.phase) * (block.spacing / SimParams::PARTICLE_SPACING).powi(3);
+            // half a spacing in from the sides, so a block against a wall does not start on it
+            let (min, max) = (glm::make_vec3(&block.min), glm::make_vec3(&block.max));
+            particles.extend(spawn_block(min.add_scalar(block.spacing / 2.0), max.add_scalar(block.spacing / 2.0),
+                block.spacing, mass, block.phase));
+        }
+        for inflow in self.make_inflows() {
+            particles.extend(inflow.fill(SimParams::PARTICLE_SPACING, sim.phase_mass(inflow.phase)));
+        }
+        if let Some(path) = &self.particle_file {
+            particles.extend(load_particles(path, sim.phase_mass(0), &sim.domain_min.xyz(), &sim.domain_max.xyz())?);
+        }
+        Ok(particles)
+    }
+
+    pub fn make_emitters(&self) -> Vec<Emitter> {
+        self.emitters.iter().map(|e| {
+            let mut emitter = Emitter::new(glm::make_vec3(&e.position), glm::make_vec3(&e.direction),
+                e.radius, e.speed, e.rate);
+            emitter.phase = e.phase;
+            if !e.active {
+                emitter.stop();
+            }
+            emitter
+        }).collect()
+    }
+
+    pub fn make_sinks(&self) -> Vec<Sink> {
+        self.sinks.iter().map(|s| Sink { active: s.active, ..Sink::new(glm::make_vec3(&s.min), glm::make_vec3(&s.max)) }).collect()
+    }
+
+    pub fn make_inflows(&self) -> Vec<Inflow> {
+        self.inflows.iter().map(|i| {
+            let mut inflow = Inflow::new(glm::make_vec3(&i.min), glm::make_vec3(&i.max), glm::make_vec3(&i.velocity));
+            inflow.phase = i.phase;
+            inflow.active = i.active;
+            inflow
+        }).collect()
+    }
+
+    pub fn make_outflows(&self) -> Vec<Outflow> {
+        self.outflows.iter().map(|o| Outflow { active: o.active, ..Outflow::new(glm::make_vec3(&o.min),
+            glm::make_vec3(&o.max), glm::normalize(&glm::make_vec3(&o.direction))) }).collect()
+    }
+}
@@ -0,0 +1,464 @@
+use std::mem::size_of;
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::camera::{projection, UniformBufferObject};
+use crate::config::*;
+use crate::simulation::{latest_particle_buffer, Particle};
+use crate::cull::record_live_draw;
+use crate::split::{full_viewport, viewport_scissor};
+use crate::tonemap::HDR_FORMAT;
+use crate::utils::{compile_shader, create_image, create_image_view, create_shader_module, get_depth_format};
+
+/// Screen-space fluid rendering (van der Laan et al. 2009): the particles are drawn as spheres into an
+/// offscreen eye-space depth image, which a bilateral filter smooths without blurring across the
+/// fluid's silhouette, and into a thickness image they add up in. The smoothed depth is then shaded as
+/// a surface, its normals taken from the depth differences between neighboring pixels, through which
+/// the scene behind shows refracted and darkened by the thickness of fluid in between.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ScreenSpaceFluid {
+    /// Smoothing passes over the depth; none draws the bumpy spheres as they are.
+    pub iterations: u32,
+    /// World-space radius of the filter, which covers fewer pixels the farther the fluid is.
+    pub filter_radius: f32,
+    /// Depth difference, in meters, over which the filter stops mixing neighbors in.
+    pub depth_falloff: f32,
+    /// Light absorbed per meter of fluid, red, green and blue, by Beer-Lambert's law.
+    pub absorption: [f32; 3],
+    pub index_of_refraction: f32,
+}
+
+impl Default for ScreenSpaceFluid {
+    fn default() -> Self {
+        Self { iterations: SCREEN_SPACE_ITERATIONS, filter_radius: SCREEN_SPACE_FILTER_RADIUS,
+            depth_falloff: SCREEN_SPACE_DEPTH_FALLOFF, absorption: SCREEN_SPACE_ABSORPTION,
+            index_of_refraction: SCREEN_SPACE_IOR }
+    }
+}
+
+/// Push constants of the depth and thickness passes, laid out like `DepthConstants` in `fluid_depth.vert`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct DepthConstants {
+    radius: f32,
+    viewport_height: f32,
+    point_size_range: [f32; 2],
+    /// Rest mass of each phase, which a particle of that mass is drawn at `radius` for, as the sprites are.
+    phase_masses: [f32; MAX_PHASES],
+}
+
+/// Push constants of a smoothing pass, laid out like `SmoothConstants` in `fluid_smooth.frag`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct SmoothConstants {
+    filter_radius: f32,
+    depth_falloff: f32,
+    /// Pixels a meter covers at a meter from the eye.
+    pixels_per_meter: f32,
+    max_pixels: f32,
+}
+
+/// Push constants of the composite, laid out like `CompositeConstants` in `fluid_composite.frag`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct CompositeConstants {
+    absorption: [f32; 3],
+    index_of_refraction: f32,
+}
+
+/// The passes share one layout, and every set of push constants fits in 32 bytes.
+const PUSH_CONSTANT_SIZE: u32 = 32;
+const DEPTH_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+/// Half floats, which unlike 32-bit ones every device can blend the thickness into.
+const THICKNESS_FORMAT: vk::Format = vk::Format::R16_SFLOAT;
+
+/// Creates the passes, sets and pipelines the screen-space fluid draws with; call after
+/// `create_uniform_buffers`, since the passes read the scene's cameras.
+pub unsafe fn create_screen_space_fluid(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    // the scene clears the depth buffer the fluid passes then test against
+    let z_format = get_depth_format(instance, data)?;
+    let read = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+    use vk::AttachmentLoadOp as Load;
+    data.scene_render_pass = create_fluid_render_pass(device, HDR_FORMAT, Load::CLEAR, read,
+        Some((z_format, Load::CLEAR)))?;
+    data.fluid_thickness_render_pass = create_fluid_render_pass(device, THICKNESS_FORMAT, Load::CLEAR, read,
+        Some((z_format, Load::LOAD)))?;
+    data.fluid_depth_render_pass = create_fluid_render_pass(device, DEPTH_FORMAT, Load::CLEAR, read,
+        Some((z_format, Load::LOAD)))?;
+    data.fluid_smooth_render_pass = create_fluid_render_pass(device, DEPTH_FORMAT, Load::DONT_CARE, read, None)?;
+    data.fluid_composite_render_pass = create_fluid_render_pass(device, HDR_FORMAT, Load::DONT_CARE, read, None)?;
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::NEAREST).min_filter(vk::Filter::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+    data.fluid_sampler = device.create_sampler(&sampler_info, None)?;
+    create_fluid_descriptor_sets(device, data)?;
+    create_fluid_pipelines(device, data)
+}
+
+pub unsafe fn destroy_screen_space_fluid(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.fluid_thickness_pipeline, None);
+    device.destroy_pipeline(data.fluid_depth_pipeline, None);
+    device.destroy_pipeline(data.fluid_smooth_pipeline, None);
+    device.destroy_pipeline(data.fluid_composite_pipeline, None);
+    device.destroy_pipeline_layout(data.fluid_pipeline_layout, None);
+    device.destroy_descriptor_pool(data.fluid_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(data.fluid_descriptor_set_layout, None);
+    device.destroy_sampler(data.fluid_sampler, None);
+    for render_pass in [data.scene_render_pass, data.fluid_thickness_render_pass, data.fluid_depth_render_pass,
+        data.fluid_smooth_render_pass, data.fluid_composite_render_pass] {
+        device.destroy_render_pass(render_pass, None);
+    }
+}
+
+/// Creates the images the screen-space fluid draws into, for the current swapchain's extent, and binds
+/// them to its sets; call after `create_depth_objects` and `create_tonemap_targets`, since the passes
+/// test against the scene's depth and composite into the HDR image.
+pub unsafe fn create_fluid_targets(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let (width, height) = (data.swapchain_extent.width, data.swapchain_extent.height);
+    let target = |format: vk::Format| -> Result<(vk::Image, vk::DeviceMemory, vk::ImageView)> {
+        let (image, memory) = create_image(instance, device, data, width, height, format, vk::ImageTiling::OPTIMAL,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+        Ok((image, memory, create_image_view(device, image, format, vk::ImageAspectFlags::COLOR)?))
+    };
+    let scene = target(HDR_FORMAT)?;
+    let thickness = target(THICKNESS_FORMAT)?;
+    let depth_targets = (0..2).map(|_| target(DEPTH_FORMAT)).collect::<Result<Vec<_>>>()?;
+    (data.scene_color_image, data.scene_color_image_memory, data.scene_color_image_view) = scene;
+    (data.fluid_thickness_image, data.fluid_thickness_image_memory, data.fluid_thickness_image_view) = thickness;
+    data.fluid_depth_images = depth_targets.iter().map(|t| t.0).collect();
+    data.fluid_depth_images_memory = depth_targets.iter().map(|t| t.1).collect();
+    data.fluid_depth_image_views = depth_targets.iter().map(|t| t.2).collect();
+
+    let framebuffer = |render_pass: vk::RenderPass, attachments: &[vk::ImageView]| {
+        let info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass).attachments(attachments).width(width).height(height).layers(1);
+        device.create_framebuffer(&info, None)
+    };
+    data.scene_framebuffer = framebuffer(data.scene_render_pass, &[data.scene_color_image_view, data.depth_image_view])?;
+    data.fluid_thickness_framebuffer = framebuffer(data.fluid_thickness_render_pass,
+        &[data.fluid_thickness_image_view, data.depth_image_view])?;
+    data.fluid_depth_framebuffer = framebuffer(data.fluid_depth_render_pass,
+        &[data.fluid_depth_image_views[0], data.depth_image_view])?;
+    data.fluid_smooth_framebuffers = data.fluid_depth_image_views.iter()
+        .map(|v| framebuffer(data.fluid_smooth_render_pass, &[*v]))
+        .collect::<Result<Vec<_>, _>>()?;
+    data.fluid_composite_framebuffer = framebuffer(data.fluid_composite_render_pass, &[data.hdr_image_view])?;
+
+    for (n, set) in data.fluid_descriptor_sets.iter().enumerate() {
+        let image_infos = [data.fluid_depth_image_views[n & 1], data.fluid_thickness_image_view,
+            data.scene_color_image_view].map(|view| [vk::DescriptorImageInfo::builder()
+                .sampler(data.fluid_sampler).image_view(view)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .build()]);
+        let writes = image_infos.iter().enumerate().map(|(b, info)| vk::WriteDescriptorSet::builder()
+            .dst_set(*set).dst_binding(b as u32 + 1).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(info)
+            .build())
+            .collect::<Vec<_>>();
+        device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+    }
+    Ok(())
+}
+
+pub unsafe fn destroy_fluid_targets(device: &Device, data: &AppData) {
+    device.destroy_framebuffer(data.scene_framebuffer, None);
+    device.destroy_framebuffer(data.fluid_thickness_framebuffer, None);
+    device.destroy_framebuffer(data.fluid_depth_framebuffer, None);
+    data.fluid_smooth_framebuffers.iter().for_each(|f| device.destroy_framebuffer(*f, None));
+    device.destroy_framebuffer(data.fluid_composite_framebuffer, None);
+    let views = data.fluid_depth_image_views.iter().chain([&data.scene_color_image_view, &data.fluid_thickness_image_view]);
+    views.for_each(|v| device.destroy_image_view(*v, None));
+    let images = data.fluid_depth_images.iter().chain([&data.scene_color_image, &data.fluid_thickness_image]);
+    images.for_each(|i| device.destroy_image(*i, None));
+    let memory = data.fluid_depth_images_memory.iter().chain([&data.scene_color_image_memory,
+        &data.fluid_thickness_image_memory]);
+    memory.for_each(|m| device.free_memory(*m, None));
+}
+
+/// A pass drawing into one color image, left in `final_layout`, and into the scene's depth buffer if
+/// `depth` gives its format and whether to clear it or test against what the scene left in it. The
+/// scene's pass matches `render_pass` but for its layouts and store ops, so the pipelines made for the
+/// one draw in the other.
+pub(crate) unsafe fn create_fluid_render_pass(device: &Device, format: vk::Format, load_op: vk::AttachmentLoadOp,
+    final_layout: vk::ImageLayout, depth: Option<(vk::Format, vk::AttachmentLoadOp)>) -> Result<vk::RenderPass> {
+    let color_attachment = vk::AttachmentDescription::builder()
+        .format(format).samples(vk::SampleCountFlags::_1)
+        .load_op(load_op).store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE).stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED).final_layout(final_layout)
+        .build();
+    let color_attachment_ref = vk::AttachmentReference::builder().attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let color_attachments = &[color_attachment_ref];
+    let z_attachment_ref = vk::AttachmentReference::builder().attachment(1)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let mut attachments = vec![color_attachment];
+    let mut subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(color_attachments);
+    if let Some((z_format, z_load_op)) = depth {
+        let initial_layout = if z_load_op == vk::AttachmentLoadOp::LOAD {
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        } else {
+            vk::ImageLayout::UNDEFINED
+        };
+        attachments.push(vk::AttachmentDescription::builder()
+            .format(z_format).samples(vk::SampleCountFlags::_1)
+            .load_op(z_load_op).store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE).stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(initial_layout).final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .build());
+        subpass = subpass.depth_stencil_attachment(&z_attachment_ref);
+    }
+    // the images are drawn over only once the passes before, this frame's or the last one's, are done
+    // with them, and read only once drawn
+    let fragment_tests = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+    let before = vk::SubpassDependency::builder()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | fragment_tests)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | fragment_tests)
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE);
+    let after = vk::SubpassDependency::builder()
+        .src_subpass(0)
+        .dst_subpass(vk::SUBPASS_EXTERNAL)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ);
+    let subpasses = &[subpass];
+    let dependencies = &[before, after];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(&attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+    Ok(device.create_render_pass(&info, None)?)
+}
+
+/// Two sets per swapchain image, one for each depth image the smoothing reads, whose images
+/// `create_fluid_targets` binds.
+unsafe fn create_fluid_descriptor_sets(device: &Device, data: &mut AppData) -> Result<()> {
+    let ubo_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
+    let image_binding = |binding: u32| vk::DescriptorSetLayoutBinding::builder()
+        .binding(binding)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .build();
+    let bindings = &[ubo_binding.build(), image_binding(1), image_binding(2), image_binding(3)];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+    data.fluid_descriptor_set_layout = device.create_descriptor_set_layout(&info, None)?;
+
+    let count = 2 * data.swapchain_images.len() as u32;
+    let ubo_size = vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::UNIFORM_BUFFER).descriptor_count(count);
+    let sampler_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).descriptor_count(3 * count);
+    let pool_sizes = &[ubo_size, sampler_size];
+    let info = vk::DescriptorPoolCreateInfo::builder().pool_sizes(pool_sizes).max_sets(count);
+    data.fluid_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    let layouts = vec![data.fluid_descriptor_set_layout; count as usize];
+    let info = vk::DescriptorSetAllocateInfo::builder().descriptor_pool(data.fluid_descriptor_pool).set_layouts(&layouts);
+    data.fluid_descriptor_sets = device.allocate_descriptor_sets(&info)?;
+    for (n, set) in data.fluid_descriptor_sets.iter().enumerate() {
+        let buffer_info = &[vk::DescriptorBufferInfo::builder()
+            .buffer(data.uniform_buffers[n / 2]).offset(0).range(size_of::<UniformBufferObject>() as u64)];
+        let ubo_write = vk::WriteDescriptorSet::builder()
+            .dst_set(*set).dst_binding(0).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER).buffer_info(buffer_info);
+        device.update_descriptor_sets(&[ubo_write], &[] as &[vk::CopyDescriptorSet]);
+    }
+    Ok(())
+}
+
+/// What sets the fluid pipelines apart.
+struct FluidPipeline<'a> {
+    vertex_shader: &'a str,
+    fragment_shader: &'a str,
+    render_pass: vk::RenderPass,
+    /// Whether it draws a point per particle rather than a full-screen triangle.
+    particles: bool,
+    depth_test: bool,
+    depth_write: bool,
+    /// Whether it adds to the color attachment rather than replacing it.
+    additive: bool,
+}
+
+unsafe fn create_fluid_pipelines(device: &Device, data: &mut AppData) -> Result<()> {
+    let set_layouts = &[data.fluid_descriptor_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(PUSH_CONSTANT_SIZE);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.fluid_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    // every sphere in front of the scene adds its thickness, and only the nearest leaves its depth
+    data.fluid_thickness_pipeline = create_fluid_pipeline(device, data, &FluidPipeline {
+        vertex_shader: FLUID_DEPTH_VERTEX_SHADER, fragment_shader: FLUID_THICKNESS_SHADER,
+        render_pass: data.fluid_thickness_render_pass, particles: true, depth_test: true, depth_write: false,
+        additive: true })?;
+    data.fluid_depth_pipeline = create_fluid_pipeline(device, data, &FluidPipeline {
+        vertex_shader: FLUID_DEPTH_VERTEX_SHADER, fragment_shader: FLUID_DEPTH_FRAGMENT_SHADER,
+        render_pass: data.fluid_depth_render_pass, particles: true, depth_test: true, depth_write: true,
+        additive: false })?;
+    data.fluid_smooth_pipeline = create_fluid_pipeline(device, data, &FluidPipeline {
+        vertex_shader: FULLSCREEN_VERTEX_SHADER, fragment_shader: FLUID_SMOOTH_SHADER,
+        render_pass: data.fluid_smooth_render_pass, particles: false, depth_test: false, depth_write: false,
+        additive: false })?;
+    data.fluid_composite_pipeline = create_fluid_pipeline(device, data, &FluidPipeline {
+        vertex_shader: FULLSCREEN_VERTEX_SHADER, fragment_shader: FLUID_COMPOSITE_SHADER,
+        render_pass: data.fluid_composite_render_pass, particles: false, depth_test: false, depth_write: false,
+        additive: false })?;
+    Ok(())
+}
+
+unsafe fn create_fluid_pipeline(device: &Device, data: &AppData, desc: &FluidPipeline) -> Result<vk::Pipeline> {
+    let vshader = compile_shader(&desc.vertex_shader.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&desc.fragment_shader.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let binding_descs = &[Particle::binding_description()];
+    let attribute_descs = &Particle::attribute_descriptions();
+    let mut vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    if desc.particles {
+        vertex_input_state = vertex_input_state
+            .vertex_binding_descriptions(binding_descs)
+            .vertex_attribute_descriptions(attribute_descs);
+    }
+    let topology = if desc.particles { vk::PrimitiveTopology::POINT_LIST } else { vk::PrimitiveTopology::TRIANGLE_LIST };
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(topology)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlags::_1);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(desc.additive)
+        .src_color_blend_factor(vk::BlendFactor::ONE)
+        .dst_color_blend_factor(vk::BlendFactor::ONE)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(desc.depth_test).depth_write_enable(desc.depth_write)
+        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .dynamic_state(&dynamic_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.fluid_pipeline_layout)
+        .render_pass(desc.render_pass)
+        .subpass(0);
+    let pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(pipeline)
+}
+
+unsafe fn push_fluid_constants<T>(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, constants: &T) {
+    let bytes = std::slice::from_raw_parts((constants as *const T).cast::<u8>(), size_of::<T>());
+    device.cmd_push_constants(command_buffer, data.fluid_pipeline_layout,
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0, bytes);
+}
+
+/// Records the screen-space fluid over swapchain image `i`, once the scene's pass has drawn the scene
+/// into `scene_color_image` and left its depth: the thickness and depth of the particles in front of
+/// the scene, the smoothing passes over the depth, and the fluid shaded into the swapchain image with the
+/// scene showing through.
+pub unsafe fn record_screen_space_fluid(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    let extent = data.swapchain_extent;
+    let render_area = vk::Rect2D::builder().offset(vk::Offset2D::default()).extent(extent);
+    let clear_values = &[vk::ClearValue { color: vk::ClearColorValue { float32: [0.0; 4] } }];
+    let viewport = full_viewport(extent);
+    let begin = |render_pass: vk::RenderPass, framebuffer: vk::Framebuffer, pipeline: vk::Pipeline, set: usize| {
+        let info = vk::RenderPassBeginInfo::builder()
+            .render_pass(render_pass)
+            .framebuffer(framebuffer)
+            .render_area(render_area)
+            .clear_values(clear_values);
+        device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+        device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+        device.cmd_set_scissor(command_buffer, 0, &[viewport_scissor(&viewport)]);
+        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+            data.fluid_pipeline_layout, 0, &[data.fluid_descriptor_sets[set]], &[]);
+    };
+    let particles = DepthConstants { radius: data.particle_radius * data.radius_scale,
+        viewport_height: extent.height as f32, point_size_range: data.point_size_range,
+        phase_masses: data.phase_masses };
+    for (render_pass, framebuffer, pipeline) in [
+        (data.fluid_thickness_render_pass, data.fluid_thickness_framebuffer, data.fluid_thickness_pipeline),
+        (data.fluid_depth_render_pass, data.fluid_depth_framebuffer, data.fluid_depth_pipeline),
+    ] {
+        begin(render_pass, framebuffer, pipeline, 2 * i);
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[latest_particle_buffer(data)], &[0]);
+        push_fluid_constants(device, data, command_buffer, &particles);
+        record_live_draw(device, data, command_buffer, None);
+        device.cmd_end_render_pass(command_buffer);
+    }
+
+    // back and forth between the two depth images, each pass reading the one the last drew
+    let params = &data.screen_space;
+    let constants = SmoothConstants { filter_radius: params.filter_radius, depth_falloff: params.depth_falloff,
+        pixels_per_meter: projection(extent)[(1, 1)].abs() * extent.height as f32 / 2.0,
+        max_pixels: SCREEN_SPACE_MAX_FILTER_PIXELS };
+    for pass in 0..params.iterations as usize {
+        let source = pass & 1;
+        begin(data.fluid_smooth_render_pass, data.fluid_smooth_framebuffers[1 - source], data.fluid_smooth_pipeline,
+            2 * i + source);
+        push_fluid_constants(device, data, command_buffer, &constants);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        device.cmd_end_render_pass(command_buffer);
+    }
+
+    let smoothed = params.iterations as usize & 1;
+    begin(data.fluid_composite_render_pass, data.fluid_composite_framebuffer, data.fluid_composite_pipeline,
+        2 * i + smoothed);
+    push_fluid_constants(device, data, command_buffer,
+        &CompositeConstants { absorption: params.absorption, index_of_refraction: params.index_of_refraction });
+    device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    device.cmd_end_render_pass(command_buffer);
+}
@@ -0,0 +1,152 @@
+use nalgebra_glm as glm;
+
+/// Layout of one baked field inside the shared SDF value buffer, matching the std430 `SdfInfo` in the shaders.
+/// A heightfield keeps its heights there instead, x fastest, with its sample spacing along z in `origin.y`
+/// and one sample along the z of `dims`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SdfInfo {
+    /// World-space position of voxel (0, 0, 0); `w` is the voxel size.
+    pub origin: glm::Vec4,
+    /// Voxel counts per axis; `w` is the offset of the first value in the value buffer.
+    pub dims: [u32; 4],
+    /// Nonzero x if particles near the surface split, see `Refinement`; `HEIGHTFIELD` in y for a
+    /// heightfield. The rest pads the struct.
+    pub flags: [u32; 4],
+}
+
+impl SdfInfo {
+    pub const HEIGHTFIELD: u32 = 1;
+}
+
+/// A voxelized signed distance field, negative inside the surface.
+/// Only a narrow band around the surface is exact; everything further out reads as `band` voxels away.
+#[derive(Clone, Debug, Default)]
+pub struct Sdf {
+    pub origin: glm::Vec3,
+    pub cell: f32,
+    pub dims: [u32; 3],
+    pub values: Vec<f32>,
+}
+
+impl Sdf {
+    /// Voxels around the surface that get exact distances.
+    pub const BAND: i32 = 3;
+
+    /// Bakes the field of an indexed triangle mesh after applying `transform`, with `resolution`
+    /// voxels along the longest side of the mesh bounds.
+    pub fn bake(positions: &[glm::Vec3], indices: &[u32], transform: &glm::Mat4, resolution: u32) -> Self {
+        let world = positions.iter()
+            .map(|p| (transform * glm::vec4(p.x, p.y, p.z, 1.0)).xyz())
+            .collect::<Vec<_>>();
+        if world.is_empty() || indices.len() < 3 {
+            return Self::default();
+        }
+        let (mut lo, mut hi) = (world[0], world[0]);
+        for p in &world {
+            lo = glm::min2(&lo, p);
+            hi = glm::max2(&hi, p);
+        }
+        let cell = (hi - lo).max().max(f32::EPSILON) / resolution.max(1) as f32;
+        let pad = Self::BAND as f32 * cell;
+        let origin = lo - glm::vec3(pad, pad, pad);
+        let extent = hi - lo + glm::vec3(2.0 * pad, 2.0 * pad, 2.0 * pad);
+        let dims = [0, 1, 2].map(|a| (extent[a] / cell).ceil() as u32 + 1);
+        let mut sdf = Self { origin, cell, dims, values: vec![pad; (dims[0] * dims[1] * dims[2]) as usize] };
+
+        // splat every triangle into the voxels within the band around it
+        for tri in indices.chunks_exact(3) {
+            let (a, b, c) = (world[tri[0] as usize], world[tri[1] as usize], world[tri[2] as usize]);
+            let normal = glm::cross(&(b - a), &(c - a));
+            if glm::length2(&normal) == 0.0 {
+                continue;
+            }
+            let tri_lo = glm::min2(&glm::min2(&a, &b), &c);
+            let tri_hi = glm::max2(&glm::max2(&a, &b), &c);
+            let first = [0, 1, 2].map(|k| ((tri_lo[k] - origin[k]) / cell).floor() as i32 - Self::BAND);
+            let last = [0, 1, 2].map(|k| ((tri_hi[k] - origin[k]) / cell).ceil() as i32 + Self::BAND);
+            for z in first[2].max(0)..=last[2].min(dims[2] as i32 - 1) {
+                for y in first[1].max(0)..=last[1].min(dims[1] as i32 - 1) {
+                    for x in first[0].max(0)..=last[0].min(dims[0] as i32 - 1) {
+                        let p = origin + glm::vec3(x as f32, y as f32, z as f32) * cell;
+                        let closest = closest_point_on_triangle(&p, &a, &b, &c);
+                        let distance = glm::distance(&p, &closest);
+                        let index = sdf.index(x as u32, y as u32, z as u32);
+                        if distance < sdf.values[index].abs() {
+                            // the side of the nearest face decides the sign
+                            let sign = if glm::dot(&(p - closest), &normal) < 0.0 { -1.0 } else { 1.0 };
+                            sdf.values[index] = sign * distance;
+                        }
+                    }
+                }
+            }
+        }
+        sdf
+    }
+
+    fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        ((z * self.dims[1] + y) * self.dims[0] + x) as usize
+    }
+
+    /// Trilinearly interpolated distance at `p`; points outside the grid read as far away.
+    pub fn sample(&self, p: &glm::Vec3) -> f32 {
+        let far = Self::BAND as f32 * self.cell;
+        let g = (p - self.origin) / self.cell.max(f32::EPSILON);
+        if (0..3).any(|a| g[a] < 0.0 || g[a] > (self.dims[a].max(1) - 1) as f32) {
+            return far;
+        }
+        let base = [0, 1, 2].map(|a| (g[a].floor() as u32).min(self.dims[a].saturating_sub(2)));
+        let t = glm::vec3(g.x - base[0] as f32, g.y - base[1] as f32, g.z - base[2] as f32);
+        let mut d = 0.0;
+        for corner in 0..8u32 {
+            let (dx, dy, dz) = (corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+            let weight = (if dx == 1 { t.x } else { 1.0 - t.x })
+                * (if dy == 1 { t.y } else { 1.0 - t.y })
+                * (if dz == 1 { t.z } else { 1.0 - t.z });
+            d += weight * self.values[self.index(base[0] + dx, base[1] + dy, base[2] + dz)];
+        }
+        d
+    }
+
+    /// GPU header for this field, whose values start at `offset` in the shared value buffer.
+    pub fn info(&self, offset: u32, refine: bool) -> SdfInfo {
+        SdfInfo {
+            origin: glm::vec4(self.origin.x, self.origin.y, self.origin.z, self.cell),
+            dims: [self.dims[0], self.dims[1], self.dims[2], offset],
+            flags: [refine as u32, 0, 0, 0],
+        }
+    }
+}
+
+/// Closest point to `p` on triangle `abc` (Ericson, Real-Time Collision Detection 5.1.5).
+fn closest_point_on_triangle(p: &glm::Vec3, a: &glm::Vec3, b: &glm::Vec3, c: &glm::Vec3) -> glm::Vec3 {
+    let (ab, ac, ap) = (b - a, c - a, p - a);
+    let (d1, d2) = (glm::dot(&ab, &ap), glm::dot(&ac, &ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return *a;
+    }
+    let bp = p - b;
+    let (d3, d4) = (glm::dot(&ab, &bp), glm::dot(&ac, &bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return *b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let (d5, d6) = (glm::dot(&ab, &cp), glm::dot(&ac, &cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return *c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
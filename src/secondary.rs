@@ -0,0 +1,225 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use anyhow::Result;
+use rayon::prelude::*;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::model::Object;
+use crate::oit::oit_active;
+use crate::reconstruct::FluidRendering;
+use crate::split::{full_viewport, viewport_scissor};
+use crate::utils::{MeshPipeline, QueueFamilyIndices, bind_scene_set, direct_draws, prepass_draws, record_mesh_draws,
+    record_pass_overlays, record_view_background, record_view_overlays, record_view_prepass, scene_target, scene_views};
+
+/// What a secondary command buffer of the main pass draws, within the view of that index among
+/// `scene_views`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum Slot {
+    Background(usize),
+    /// A mesh's draws, the mesh being an element of the objects followed by the fluid surface and the
+    /// selection markers, in the depth pre-pass or shaded.
+    Mesh { view: usize, mesh: usize, prepass: bool },
+    Prepass(usize),
+    Overlays(usize),
+    /// The legend and gizmo over every view.
+    PassOverlays,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Secondary {
+    buffer: vk::CommandBuffer,
+    /// The command pool of the thread recording it.
+    pool: usize,
+    /// A hash of everything the commands last recorded bake in, `None` for those recorded anew next frame.
+    recorded: Option<u64>,
+}
+
+/// The secondary command buffers of a swapchain image's main pass.
+#[derive(Clone, Debug, Default)]
+pub struct Secondaries {
+    buffers: HashMap<Slot, Secondary>,
+    /// The buffers the main pass executes this frame, in order.
+    pub order: Vec<vk::CommandBuffer>,
+}
+
+/// Milliseconds recording a frame's commands took with `objects` more objects in the scene, by way of
+/// `App::time_recording`.
+#[derive(Copy, Clone, Debug)]
+pub struct RecordingTimes {
+    pub objects: usize,
+    pub threads: usize,
+    pub inline: f64,
+    /// Every secondary command buffer recorded anew each frame.
+    pub anew: f64,
+    /// The objects' secondaries kept from the frame before.
+    pub kept: f64,
+}
+
+/// A secondary command buffer to record, with the view it draws in: its uniform buffer, viewport and
+/// how it draws the fluid.
+struct Recording<'a> {
+    slot: Slot,
+    ubo: usize,
+    viewport: vk::Viewport,
+    rendering: FluidRendering,
+    mesh: Option<(MeshPipeline, &'a Object)>,
+}
+
+/// Creates a command pool per recording thread for each swapchain image, whose buffers are recorded
+/// while the image's frame is not in flight.
+pub unsafe fn create_secondaries(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
+    let info = vk::CommandPoolCreateInfo::builder()
+        .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+        .queue_family_index(indices.graphics);
+    data.secondary_pools = (0..data.swapchain_images.len())
+        .map(|_| (0..rayon::current_num_threads()).map(|_| device.create_command_pool(&info, None)).collect())
+        .collect::<Result<_, _>>()?;
+    data.secondaries = vec![Secondaries::default(); data.swapchain_images.len()];
+    Ok(())
+}
+
+pub unsafe fn destroy_secondaries(device: &Device, data: &mut AppData) {
+    data.secondary_pools.drain(..).flatten().for_each(|pool| device.destroy_command_pool(pool, None));
+    data.secondaries.clear();
+}
+
+/// Has every secondary command buffer re-recorded, after something they use was destroyed, since a
+/// handle made after may be equal to the one they were recorded with, or a set they bind was written.
+pub fn invalidate_secondaries(data: &mut AppData) {
+    data.secondaries.iter_mut().flat_map(|s| s.buffers.values_mut()).for_each(|s| s.recorded = None);
+}
+
+/// Records the secondary command buffers of swapchain image `i`'s main pass, if it runs them, spread
+/// over the threads: the objects' only once something their commands bake in changed, the rest anew.
+pub unsafe fn record_secondaries(device: &Device, data: &mut AppData, i: usize) -> Result<()> {
+    if !data.parallel_recording {
+        return Ok(());
+    }
+    let mut secondaries = std::mem::take(&mut data.secondaries[i]);
+    let result = record_into(device, data, i, &mut secondaries);
+    if result.is_err() {
+        secondaries.buffers.values_mut().for_each(|s| s.recorded = None);
+    }
+    data.secondaries[i] = secondaries;
+    result
+}
+
+unsafe fn record_into(device: &Device, data: &AppData, i: usize, secondaries: &mut Secondaries) -> Result<()> {
+    let meshes = data.objects.iter().chain([&data.fluid_surface, &data.selection_marker, &data.smoothing_sphere_marker])
+        .enumerate().map(|(k, obj)| (obj as *const Object, k)).collect::<HashMap<_, _>>();
+    // in the order `record_view` draws them
+    let mut recordings = Vec::new();
+    let views = scene_views(data, i);
+    for (view, &(ubo, viewport, rendering)) in views.iter().enumerate() {
+        let recording = |slot, mesh| Recording { slot, ubo, viewport, rendering, mesh };
+        let mesh_slot = |obj: &Object, prepass| Slot::Mesh { view, mesh: meshes[&(obj as *const Object)], prepass };
+        let draws = direct_draws(data, rendering);
+        recordings.push(recording(Slot::Background(view), None));
+        if data.depth_prepass {
+            recordings.extend(prepass_draws(&draws).map(|(key, obj)| recording(mesh_slot(obj, true), Some((key, obj)))));
+            recordings.push(recording(Slot::Prepass(view), None));
+        }
+        recordings.extend(draws.iter().map(|&(key, obj)| recording(mesh_slot(obj, false), Some((key, obj)))));
+        recordings.push(recording(Slot::Overlays(view), None));
+    }
+    // over the whole window, with the last view's uniforms
+    let &(ubo, _, rendering) = views.last().unwrap();
+    let viewport = full_viewport(data.swapchain_extent);
+    recordings.push(Recording { slot: Slot::PassOverlays, ubo, viewport, rendering, mesh: None });
+
+    let (render_pass, framebuffer) = scene_target(data, i);
+    let pools = &data.secondary_pools[i];
+    let mut jobs = vec![Vec::new(); pools.len()];
+    secondaries.order.clear();
+    for recording in &recordings {
+        let secondary = match secondaries.buffers.get_mut(&recording.slot) {
+            Some(secondary) => secondary,
+            None => {
+                let pool = secondaries.buffers.len() % pools.len();
+                let info = vk::CommandBufferAllocateInfo::builder()
+                    .command_pool(pools[pool])
+                    .level(vk::CommandBufferLevel::SECONDARY)
+                    .command_buffer_count(1);
+                let buffer = device.allocate_command_buffers(&info)?[0];
+                secondaries.buffers.entry(recording.slot).or_insert(Secondary { buffer, pool, recorded: None })
+            }
+        };
+        // the fluid surface and markers are remade as they change, so only the objects' are kept
+        let recorded = recording.mesh.filter(|_| matches!(recording.slot, Slot::Mesh { mesh, .. } if mesh < data.objects.len()))
+            .map(|(key, obj)| mesh_hash(data, i, render_pass, framebuffer, recording, key, obj));
+        if recorded.is_none() || recorded != secondary.recorded {
+            jobs[secondary.pool].push((secondary.buffer, recording));
+        }
+        secondary.recorded = recorded;
+        secondaries.order.push(secondary.buffer);
+    }
+    // each pool recorded into by one thread at a time
+    let shared = Shared(data);
+    jobs.par_iter().try_for_each(|jobs| jobs.iter().try_for_each(|&(buffer, recording)|
+        record_secondary(device, shared.data(), i, render_pass, framebuffer, buffer, recording)))
+}
+
+/// The app's data as the recording threads see it: they read its handles and settings, never the
+/// memory its mapped pointers point to, which is what keeps it from being `Sync`.
+struct Shared<'a>(&'a AppData);
+
+unsafe impl Sync for Shared<'_> {}
+
+impl<'a> Shared<'a> {
+    fn data(&self) -> &'a AppData {
+        self.0
+    }
+}
+
+/// A hash of what a mesh's commands in `recording` bake in: the pipeline, buffers, blocks and sets they
+/// bind, the submeshes they draw and the target they draw into; the mesh's transform and material are
+/// read from its blocks, so a moved object draws as recorded.
+fn mesh_hash(data: &AppData, i: usize, render_pass: vk::RenderPass, framebuffer: vk::Framebuffer,
+    recording: &Recording, key: MeshPipeline, obj: &Object) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let viewport = recording.viewport;
+    (key, data.mesh_pipelines.get(&key), render_pass, framebuffer, data.descriptor_sets[recording.ubo],
+        data.object_sets[i]).hash(&mut hasher);
+    [viewport.x, viewport.y, viewport.width, viewport.height, viewport.min_depth, viewport.max_depth]
+        .map(f32::to_bits).hash(&mut hasher);
+    data.mesh_wireframe.map(|wireframe| wireframe.width.to_bits()).hash(&mut hasher);
+    (obj.vertex_buffer, obj.index_buffer, obj.base_vertex, obj.base_index, &obj.block_offsets).hash(&mut hasher);
+    let weighted = oit_active(data);
+    for submesh in obj.draws() {
+        (submesh.first_index, submesh.index_count, weighted && obj.translucent(&submesh)).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+unsafe fn record_secondary(device: &Device, data: &AppData, i: usize, render_pass: vk::RenderPass,
+    framebuffer: vk::Framebuffer, buffer: vk::CommandBuffer, recording: &Recording) -> Result<()> {
+    let inheritance = vk::CommandBufferInheritanceInfo::builder()
+        .render_pass(render_pass)
+        .subpass(0)
+        .framebuffer(framebuffer);
+    let info = vk::CommandBufferBeginInfo::builder()
+        .flags(vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE)
+        .inheritance_info(&inheritance);
+    device.begin_command_buffer(buffer, &info)?;
+    // the viewport and bound sets are not inherited, so each buffer sets its own
+    let (ubo, viewport, rendering) = (recording.ubo, &recording.viewport, recording.rendering);
+    if let Slot::Background(_) = recording.slot {
+        record_view_background(device, data, buffer, ubo, viewport);
+    } else {
+        device.cmd_set_viewport(buffer, 0, &[*viewport]);
+        device.cmd_set_scissor(buffer, 0, &[viewport_scissor(viewport)]);
+        bind_scene_set(device, data, buffer, ubo);
+    }
+    match recording.slot {
+        Slot::Background(_) => {}
+        Slot::Mesh { .. } => record_mesh_draws(device, data, buffer, i, recording.mesh.into_iter()),
+        Slot::Prepass(_) => record_view_prepass(device, data, buffer, ubo, rendering),
+        Slot::Overlays(_) => record_view_overlays(device, data, buffer, ubo, rendering),
+        Slot::PassOverlays => record_pass_overlays(device, data, buffer, i),
+    }
+    device.end_command_buffer(buffer)?;
+    Ok(())
+}
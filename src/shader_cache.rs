@@ -0,0 +1,103 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use anyhow::Result;
+use log::*;
+
+use crate::config::{SHADER_CACHE_DIR, shader_cache_enabled};
+use crate::pipeline_cache::checksum;
+use crate::utils::shader_includes;
+
+/// The first word of every SPIR-V module.
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+/// Words in a SPIR-V module's header.
+const SPIRV_HEADER_WORDS: usize = 5;
+
+/// Tells apart the files cached entries are written to before they are moved into place.
+static PENDING: AtomicU32 = AtomicU32::new(0);
+
+/// The SPIR-V a shader compiled to, now or in an earlier run.
+#[derive(Clone, Debug)]
+pub struct Spirv(Vec<u8>);
+
+impl Spirv {
+    pub fn as_binary_u8(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Where the shader at `shader_path` is cached compiled as `kind` with `defines`: named by a hash of its
+/// source, of every file it includes and of the options, so an edit to any of them misses.
+fn cache_path(shader_path: &str, kind: shaderc::ShaderKind, defines: &[&str]) -> Result<PathBuf> {
+    let mut includes = shader_includes(shader_path).into_iter().collect::<Vec<_>>();
+    includes.sort();
+    let mut key = Vec::new();
+    let mut part = |bytes: &[u8]| {
+        key.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+        key.extend_from_slice(bytes);
+    };
+    part(format!("{:?} {:?}", kind, defines).as_bytes());
+    part(&fs::read(shader_path)?);
+    for include in includes {
+        // one gone missing hashes as empty, and fails to compile rather than hitting
+        part(include.as_bytes());
+        part(&fs::read(&include).unwrap_or_default());
+    }
+    Ok(Path::new(SHADER_CACHE_DIR).join(format!("{:016x}.spv", checksum(&key))))
+}
+
+/// The SPIR-V cached at `path`, if it is whole enough to pass for some: a multiple of four bytes long,
+/// holding at least the header and starting with the magic number.
+fn load(path: &Path) -> Option<Spirv> {
+    let bytes = fs::read(path).ok()?;
+    let magic = u32::from_le_bytes(bytes.get(..4)?.try_into().unwrap());
+    (bytes.len() % 4 == 0 && bytes.len() >= 4 * SPIRV_HEADER_WORDS && magic == SPIRV_MAGIC).then_some(Spirv(bytes))
+}
+
+/// Writes `spirv` to `path` by way of another file, so a thread compiling the same shader never reads
+/// it half written.
+fn store(path: &Path, spirv: &Spirv) -> std::io::Result<()> {
+    fs::create_dir_all(SHADER_CACHE_DIR)?;
+    let pending = path.with_extension(format!("{}-{}.tmp", std::process::id(), PENDING.fetch_add(1, Ordering::Relaxed)));
+    fs::write(&pending, &spirv.0)?;
+    fs::rename(&pending, path)
+}
+
+/// What the shader at `shader_path` compiles to as `kind` with `defines`, from `SHADER_CACHE_DIR` if an
+/// earlier compile of the same sources and options left it there, otherwise from `compile`, whose
+/// result is cached for the next. Anything in the way of the cache only costs the compile.
+pub fn compile_cached(shader_path: &str, kind: shaderc::ShaderKind, defines: &[&str],
+    compile: impl FnOnce() -> Result<Vec<u8>>) -> Result<Spirv> {
+    if !shader_cache_enabled() {
+        return compile().map(Spirv);
+    }
+    let path = cache_path(shader_path, kind, defines)?;
+    if let Some(spirv) = load(&path) {
+        return Ok(spirv);
+    }
+    let spirv = Spirv(compile()?);
+    if let Err(e) = store(&path, &spirv) {
+        warn!("Could not cache {} at {}: {}", shader_path, path.display(), e);
+    }
+    Ok(spirv)
+}
+
+/// Deletes every shader cached in `SHADER_CACHE_DIR`, including those no source hashes to anymore,
+/// returning how many there were.
+pub fn clean_shader_cache() -> Result<usize> {
+    let entries = match fs::read_dir(SHADER_CACHE_DIR) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut removed = 0;
+    for entry in entries {
+        let path = entry?.path();
+        if matches!(path.extension().and_then(|e| e.to_str()), Some("spv" | "tmp")) {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
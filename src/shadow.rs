@@ -0,0 +1,444 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::model::Vertex;
+use crate::utils::{MeshPrimitive, allocate_image, begin_single_time_commands, compile_shader, create_image,
+    create_image_view, create_shader_module, end_single_time_commands, mesh_draws};
+
+/// Shadows of the directional light, cast by the objects and the fluid surface onto the meshes drawn
+/// through the main shader: a depth map of the scene as the light sees it, filtered over 3x3 texels.
+/// Raising `bias` or `normal_offset` clears shadow acne off lit surfaces, but too far and the shadows
+/// come loose from the feet of what casts them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShadowMapping {
+    /// Texels across the square shadow map.
+    pub resolution: u32,
+    /// Depth bias the rasterizer adds drawing the shadow map, a constant in units of the map's depth
+    /// precision and one scaled by the slope of each triangle.
+    pub depth_bias_constant: f32,
+    pub depth_bias_slope: f32,
+    /// Light-space depth subtracted from each shaded point before it is compared with the map.
+    pub bias: f32,
+    /// Meters each shaded point is pushed along its normal before it is looked up in the map.
+    pub normal_offset: f32,
+}
+
+impl Default for ShadowMapping {
+    fn default() -> Self {
+        Self { resolution: SHADOW_RESOLUTION, depth_bias_constant: SHADOW_DEPTH_BIAS_CONSTANT,
+            depth_bias_slope: SHADOW_DEPTH_BIAS_SLOPE, bias: SHADOW_BIAS, normal_offset: SHADOW_NORMAL_OFFSET }
+    }
+}
+
+/// Shadows of the light as a point at its position, cast in every direction: a cube map of each
+/// direction's distance to the nearest caster, looked up along the direction from the light to each
+/// shaded point. They take the place of `ShadowMapping`'s while on. The cube is only redrawn when the
+/// light, these settings, the objects or the fluid moved since it last was.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PointShadows {
+    /// Texels across each face of the cube.
+    pub resolution: u32,
+    /// Meters from the light that casters are drawn from and to; nothing past `far` is shadowed.
+    pub near: f32,
+    pub far: f32,
+    /// Meters nearer the light each shaded point is taken to be than it is, against shadow acne.
+    pub bias: f32,
+}
+
+impl Default for PointShadows {
+    fn default() -> Self {
+        Self { resolution: POINT_SHADOW_RESOLUTION, near: POINT_SHADOW_NEAR, far: POINT_SHADOW_FAR,
+            bias: POINT_SHADOW_BIAS }
+    }
+}
+
+/// Push constants of the cube shadow map's passes, laid out to match their `Face` block.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct CubeFaceConstants {
+    view_proj: glm::Mat4,
+    light: glm::Vec3,
+    far: f32,
+}
+
+/// The light's view and orthographic projection from along `light_dir`, toward the light, fit around
+/// the sphere of `radius` about `center` that the shadows are cast within.
+pub fn shadow_view_proj(light_dir: &glm::Vec3, center: &glm::Vec3, radius: f32) -> glm::Mat4 {
+    let radius = radius.max(1e-3);
+    let dir = glm::normalize(light_dir);
+    let up = if dir.y.abs() > 0.99 { glm::vec3(1.0, 0.0, 0.0) } else { glm::vec3(0.0, 1.0, 0.0) };
+    let view = glm::look_at(&(center + dir * 2.0 * radius), center, &up);
+    glm::ortho_rh_zo(-radius, radius, -radius, radius, 0.0, 4.0 * radius) * view
+}
+
+/// Creates the depth-only render pass, sampler and pipelines the shadow maps are drawn and sampled
+/// with, none of which depend on their resolution.
+pub unsafe fn create_shadow_pass(device: &Device, data: &mut AppData) -> Result<()> {
+    // cleared, and left for the main pass's fragment shader to read
+    let attachment = vk::AttachmentDescription::builder()
+        .format(vk::Format::D32_SFLOAT)
+        .samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
+    let depth_ref = vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(&depth_ref);
+    let fragment_tests = vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS;
+    // the last frame's lookups are done before the map is cleared, and this frame's wait for it drawn
+    let before = vk::SubpassDependency::builder()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .src_access_mask(vk::AccessFlags::empty())
+        .dst_stage_mask(fragment_tests)
+        .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE);
+    let after = vk::SubpassDependency::builder()
+        .src_subpass(0)
+        .dst_subpass(vk::SUBPASS_EXTERNAL)
+        .src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+        .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ);
+    let attachments = &[attachment];
+    let subpasses = &[subpass];
+    let dependencies = &[before, after];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+    data.shadow_render_pass = device.create_render_pass(&info, None)?;
+
+    // compares as it samples, lit past the map's edges; a cube has none, so it serves both maps
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::NEAREST).min_filter(vk::Filter::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+        .compare_enable(true)
+        .compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+    data.shadow_sampler = device.create_sampler(&sampler_info, None)?;
+
+    // the light's matrix in the scene's uniform buffer, each draw's transform in the object set
+    let set_layouts = &[data.descriptor_set_layout, data.object_set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts);
+    data.shadow_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    data.shadow_pipeline = create_shadow_pipeline(device, data, data.shadow_pipeline_layout, SHADOW_VERTEX_SHADER,
+        None)?;
+    // each face's view-projection and the light pushed instead
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(size_of::<CubeFaceConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.point_shadow_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    data.point_shadow_pipeline = create_shadow_pipeline(device, data, data.point_shadow_pipeline_layout,
+        POINT_SHADOW_VERTEX_SHADER, Some(POINT_SHADOW_FRAGMENT_SHADER))?;
+    Ok(())
+}
+
+/// A pipeline drawing the depth of the meshes into a shadow map through `layout`, with the rasterizer's
+/// depth bias as it is set while drawing, and a fragment shader if it writes its own depth.
+unsafe fn create_shadow_pipeline(device: &Device, data: &AppData, layout: vk::PipelineLayout, vertex_shader: &str,
+    fragment_shader: Option<&str>) -> Result<vk::Pipeline> {
+    let vshader = compile_shader(&vertex_shader.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = fragment_shader.map(|path| compile_shader(&path.to_string(), shaderc::ShaderKind::Fragment))
+        .transpose()?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = fshader.map(|fshader| create_shader_module(device, fshader.as_binary_u8())).transpose()?;
+    let mut stages = vec![vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0")
+        .build()];
+    if let Some(module) = frag_shader_module {
+        stages.push(vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .module(module)
+            .name(b"main\0")
+            .build());
+    }
+    let binding_descs = &[Vertex::binding_description()];
+    let attribute_descs = &Vertex::attribute_descriptions();
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(binding_descs)
+        .vertex_attribute_descriptions(attribute_descs);
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+    // the map's size and the bias are set as it is drawn, so none of it is rebuilt when they change
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR, vk::DynamicState::DEPTH_BIAS];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    // both faces, for meshes that are not closed
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .depth_bias_enable(true);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlags::_1);
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder();
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true).depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .dynamic_state(&dynamic_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(layout)
+        .render_pass(data.shadow_render_pass)
+        .subpass(0);
+    let pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    if let Some(module) = frag_shader_module {
+        device.destroy_shader_module(module, None);
+    }
+    Ok(pipeline)
+}
+
+pub unsafe fn destroy_shadow_pass(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.shadow_pipeline, None);
+    device.destroy_pipeline_layout(data.shadow_pipeline_layout, None);
+    device.destroy_pipeline(data.point_shadow_pipeline, None);
+    device.destroy_pipeline_layout(data.point_shadow_pipeline_layout, None);
+    device.destroy_sampler(data.shadow_sampler, None);
+    device.destroy_render_pass(data.shadow_render_pass, None);
+}
+
+/// Makes the shadow map `resolution` texels square and the framebuffer it is drawn through, cleared to
+/// lit everywhere so the main shader may sample it before shadows are first drawn. Nothing may be in
+/// flight.
+pub unsafe fn create_shadow_map(instance: &Instance, device: &Device, data: &mut AppData, resolution: u32)
+    -> Result<()> {
+    let resolution = resolution.max(1);
+    let format = vk::Format::D32_SFLOAT;
+    let (image, memory) = create_image(instance, device, data, resolution, resolution, format,
+        vk::ImageTiling::OPTIMAL, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+    data.shadow_image = image;
+    data.shadow_image_memory = memory;
+    data.shadow_image_view = create_image_view(device, image, format, vk::ImageAspectFlags::DEPTH)?;
+    data.shadow_resolution = resolution;
+    let attachments = &[data.shadow_image_view];
+    let info = vk::FramebufferCreateInfo::builder()
+        .render_pass(data.shadow_render_pass)
+        .attachments(attachments)
+        .width(resolution)
+        .height(resolution)
+        .layers(1);
+    data.shadow_framebuffer = device.create_framebuffer(&info, None)?;
+    let command_buffer = begin_single_time_commands(device, data)?;
+    begin_shadow_pass(device, data, command_buffer, data.shadow_framebuffer, resolution);
+    device.cmd_end_render_pass(command_buffer);
+    end_single_time_commands(device, data, command_buffer)
+}
+
+pub unsafe fn destroy_shadow_map(device: &Device, data: &AppData) {
+    device.destroy_framebuffer(data.shadow_framebuffer, None);
+    device.destroy_image_view(data.shadow_image_view, None);
+    device.destroy_image(data.shadow_image, None);
+    device.free_memory(data.shadow_image_memory, None);
+}
+
+/// Binds the shadow map to every set of `descriptor_sets`.
+pub unsafe fn write_shadow_map(device: &Device, data: &AppData) {
+    let image_info = &[vk::DescriptorImageInfo::builder()
+        .sampler(data.shadow_sampler).image_view(data.shadow_image_view)
+        .image_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)];
+    let writes = data.descriptor_sets.iter().map(|set| vk::WriteDescriptorSet::builder()
+        .dst_set(*set).dst_binding(3).dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info).build())
+        .collect::<Vec<_>>();
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+}
+
+/// Begins the shadow pass into `framebuffer`, `size` texels square, with the viewport and scissor
+/// covering it.
+unsafe fn begin_shadow_pass(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    framebuffer: vk::Framebuffer, size: u32) {
+    let extent = vk::Extent2D { width: size, height: size };
+    let render_area = vk::Rect2D::builder().offset(vk::Offset2D::default()).extent(extent);
+    let clear_values = &[vk::ClearValue {
+        depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 },
+    }];
+    let info = vk::RenderPassBeginInfo::builder()
+        .render_pass(data.shadow_render_pass)
+        .framebuffer(framebuffer)
+        .render_area(render_area)
+        .clear_values(clear_values);
+    device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+    let viewport = vk::Viewport { x: 0.0, y: 0.0, width: size as f32, height: size as f32, min_depth: 0.0,
+        max_depth: 1.0 };
+    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+    device.cmd_set_scissor(command_buffer, 0, &[vk::Rect2D { offset: vk::Offset2D::default(), extent }]);
+}
+
+/// Records the draws of every mesh the left or only view draws but the markers' lines, through the
+/// object blocks written for swapchain image `i` and a shadow pipeline of `layout` already bound.
+unsafe fn record_casters(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    layout: vk::PipelineLayout, i: usize) {
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, layout, 0,
+        &[data.descriptor_sets[i]], &[]);
+    for (key, obj) in mesh_draws(data, data.fluid_rendering) {
+        if key.primitive == MeshPrimitive::Lines {
+            continue;
+        }
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[obj.vertex_buffer], &[0]);
+        device.cmd_bind_index_buffer(command_buffer, obj.index_buffer, 0, vk::IndexType::UINT32);
+        for (submesh, offset) in obj.draws().iter().zip(&obj.block_offsets) {
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, layout, 1,
+                &[data.object_sets[i]], &[*offset]);
+            device.cmd_draw_indexed(command_buffer, submesh.index_count, 1, obj.base_index + submesh.first_index,
+                obj.base_vertex, 0);
+        }
+    }
+}
+
+/// Records the shadow map's pass for swapchain image `i`, if shadows are drawn: the depth of the
+/// casters as the light in its uniform buffer sees it.
+pub unsafe fn record_shadow_pass(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    let Some(shadows) = &data.shadows else {
+        return;
+    };
+    begin_shadow_pass(device, data, command_buffer, data.shadow_framebuffer, data.shadow_resolution);
+    device.cmd_set_depth_bias(command_buffer, shadows.depth_bias_constant, 0.0, shadows.depth_bias_slope);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.shadow_pipeline);
+    record_casters(device, data, command_buffer, data.shadow_pipeline_layout, i);
+    device.cmd_end_render_pass(command_buffer);
+}
+
+/// Makes the cube shadow map `resolution` texels square a face, a view of it to sample and one of each
+/// face to draw into, cleared to as far as the light reaches. Nothing may be in flight.
+pub unsafe fn create_point_shadow_map(instance: &Instance, device: &Device, data: &mut AppData, resolution: u32)
+    -> Result<()> {
+    let resolution = resolution.max(1);
+    let format = vk::Format::D32_SFLOAT;
+    let info = vk::ImageCreateInfo::builder()
+        .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+        .image_type(vk::ImageType::_2D)
+        .extent(vk::Extent3D { width: resolution, height: resolution, depth: 1 })
+        .mip_levels(1).array_layers(6).format(format)
+        .tiling(vk::ImageTiling::OPTIMAL).initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED)
+        .samples(vk::SampleCountFlags::_1)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    let (image, memory) = allocate_image(instance, device, data, &info, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+    data.point_shadow_image = image;
+    data.point_shadow_image_memory = memory;
+    data.point_shadow_resolution = resolution;
+    let view = |view_type: vk::ImageViewType, base_array_layer: u32, layer_count: u32| {
+        let subresource_range = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::DEPTH)
+            .base_mip_level(0).level_count(1)
+            .base_array_layer(base_array_layer).layer_count(layer_count);
+        let info = vk::ImageViewCreateInfo::builder()
+            .image(image).view_type(view_type).format(format).subresource_range(subresource_range);
+        device.create_image_view(&info, None)
+    };
+    data.point_shadow_image_view = view(vk::ImageViewType::CUBE, 0, 6)?;
+    data.point_shadow_face_views = (0..6).map(|face| view(vk::ImageViewType::_2D, face, 1)).collect::<Result<_, _>>()?;
+    data.point_shadow_framebuffers = data.point_shadow_face_views.iter().map(|face| {
+        let attachments = &[*face];
+        let info = vk::FramebufferCreateInfo::builder()
+            .render_pass(data.shadow_render_pass)
+            .attachments(attachments)
+            .width(resolution)
+            .height(resolution)
+            .layers(1);
+        device.create_framebuffer(&info, None)
+    }).collect::<Result<_, _>>()?;
+    let command_buffer = begin_single_time_commands(device, data)?;
+    for framebuffer in &data.point_shadow_framebuffers {
+        begin_shadow_pass(device, data, command_buffer, *framebuffer, resolution);
+        device.cmd_end_render_pass(command_buffer);
+    }
+    end_single_time_commands(device, data, command_buffer)
+}
+
+pub unsafe fn destroy_point_shadow_map(device: &Device, data: &AppData) {
+    data.point_shadow_framebuffers.iter().for_each(|f| device.destroy_framebuffer(*f, None));
+    data.point_shadow_face_views.iter().for_each(|v| device.destroy_image_view(*v, None));
+    device.destroy_image_view(data.point_shadow_image_view, None);
+    device.destroy_image(data.point_shadow_image, None);
+    device.free_memory(data.point_shadow_image_memory, None);
+}
+
+/// Binds the cube shadow map to every set of `descriptor_sets`.
+pub unsafe fn write_point_shadow_map(device: &Device, data: &AppData) {
+    let image_info = &[vk::DescriptorImageInfo::builder()
+        .sampler(data.shadow_sampler).image_view(data.point_shadow_image_view)
+        .image_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)];
+    let writes = data.descriptor_sets.iter().map(|set| vk::WriteDescriptorSet::builder()
+        .dst_set(*set).dst_binding(4).dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info).build())
+        .collect::<Vec<_>>();
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+}
+
+/// The view-projections of the cube's faces about the light at `light`, in the order of its layers, each
+/// a quarter turn wide from `near` to `far` meters and laid out as Vulkan samples cubemaps.
+pub fn cube_face_view_projs(light: &glm::Vec3, near: f32, far: f32) -> [glm::Mat4; 6] {
+    // the viewport's downward y turns each face over as cubemaps have them
+    let proj = glm::perspective_rh_zo(1.0, std::f32::consts::FRAC_PI_2, near, far);
+    let faces = [
+        (glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, -1.0, 0.0)),
+        (glm::vec3(-1.0, 0.0, 0.0), glm::vec3(0.0, -1.0, 0.0)),
+        (glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 0.0, 1.0)),
+        (glm::vec3(0.0, -1.0, 0.0), glm::vec3(0.0, 0.0, -1.0)),
+        (glm::vec3(0.0, 0.0, 1.0), glm::vec3(0.0, -1.0, 0.0)),
+        (glm::vec3(0.0, 0.0, -1.0), glm::vec3(0.0, -1.0, 0.0)),
+    ];
+    faces.map(|(dir, up)| proj * glm::look_at(light, &(light + dir), &up))
+}
+
+/// Records the cube shadow map's six passes for swapchain image `i`, if point shadows are drawn and it
+/// must be redrawn this frame: each face's distances of the casters from the light.
+pub unsafe fn record_point_shadow_pass(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    i: usize) {
+    let Some(shadows) = &data.point_shadows else {
+        return;
+    };
+    if !data.point_shadow_redraw {
+        return;
+    }
+    let light = data.point_shadow_light;
+    let layout = data.point_shadow_pipeline_layout;
+    for (face, view_proj) in cube_face_view_projs(&light, shadows.near, shadows.far).into_iter().enumerate() {
+        begin_shadow_pass(device, data, command_buffer, data.point_shadow_framebuffers[face],
+            data.point_shadow_resolution);
+        // the distance is written as it is, unbiased
+        device.cmd_set_depth_bias(command_buffer, 0.0, 0.0, 0.0);
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.point_shadow_pipeline);
+        let constants = CubeFaceConstants { view_proj, light, far: shadows.far };
+        let bytes = std::slice::from_raw_parts((&constants as *const CubeFaceConstants).cast::<u8>(),
+            size_of::<CubeFaceConstants>());
+        device.cmd_push_constants(command_buffer, layout,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0, bytes);
+        record_casters(device, data, command_buffer, layout, i);
+        device.cmd_end_render_pass(command_buffer);
+    }
+}
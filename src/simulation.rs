@@ -0,0 +1,1750 @@
+use std::fmt;
+use std::mem::{size_of, size_of_val};
+use std::ops::Range;
+use std::ptr::copy_nonoverlapping as memcpy;
+use anyhow::{anyhow, Result};
+use nalgebra_glm as glm;
+use serde::{Deserialize, Serialize};
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::camera::scene_model;
+use crate::config::*;
+use crate::sdf::SdfInfo;
+use crate::boundary::{boundary_particles, BoundaryParticle};
+use crate::rigid::{BodyForce, BodyState};
+use crate::kernel::{Kernel, KernelKind};
+use crate::model::Obstacle;
+use crate::scan::{gpu_exclusive_scan, register_scan_buffer, destroy_scan};
+use crate::diagnostics::Diagnostics;
+use crate::pick::{create_pick_pipeline, destroy_pick_pipeline, record_selection_readback, SELECTION_SLOTS};
+use crate::neighbors::{clear_highlights, create_neighbor_pipeline, destroy_neighbor_pipeline, record_neighbor_highlight};
+use crate::foam::{clear_foam, create_foam_buffer, create_foam_pipelines, destroy_foam_buffer, destroy_foam_pipelines,
+    record_foam};
+use crate::trails::{clear_trails, create_trail_pipeline, destroy_trail_pipeline, record_trails};
+use crate::occupancy::{create_occupancy_pipeline, destroy_occupancy_pipeline, record_occupancy};
+use crate::depth_sort::{create_depth_sort_pipelines, destroy_depth_sort_pipelines, record_depth_sort};
+use crate::cull::{create_cull_pipeline, destroy_cull_pipeline, record_cull};
+use crate::reconstruct::{create_field_buffer, create_splat_pipeline, destroy_field_buffer, destroy_splat_pipeline};
+use crate::conservation::DensityHistogram;
+use crate::timing::{Stamp, reset_step_timestamps, write_step_timestamp, destroy_timestamp_queries};
+use crate::utils::{create_buffer, create_shared_buffer, copy_buffer, create_compute_pipeline, QueueFamilyIndices,
+    begin_single_time_commands, end_single_time_commands};
+
+/// How particles meet the domain walls. The integrate pass selects it with specialization constant 3
+/// set to the discriminant, so keep the values in sync with `BOUNDARY_*` in the shader.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    /// Bounce back, losing some of the normal speed.
+    #[default]
+    Reflect = 0,
+    /// Stop against the wall and keep sliding along it.
+    Slip = 1,
+}
+
+/// What lies past the domain along one axis. `SimParams::boundary_kinds` holds one per axis, read by
+/// the shaders as `BOUNDARY_*` values, so keep the discriminants in sync.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryKind {
+    /// Walls, which particles meet as `BoundaryMode` says.
+    #[default]
+    Wall = 0,
+    /// The opposite side of the domain: particles leaving through one side come back in through the
+    /// other, and see the particles near the other side as neighbors.
+    Periodic = 1,
+}
+
+/// A single SPH particle, laid out to match the std430 `Particle` struct in the compute shaders.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Particle {
+    pub pos: glm::Vec3,
+    pub density: f32,
+    pub vel: glm::Vec3,
+    /// Set by the density pass from the equation of state, afresh each step: there is no pressure
+    /// iteration to warm-start. The scatter carries it through the grid reordering with the rest of the
+    /// particle, so an iterative solver could start from last step's value without a buffer of its own.
+    pub pressure: f32,
+    pub force: glm::Vec3,
+    pub mass: f32,
+    /// Index into `SimParams::phases`.
+    pub phase: u32,
+    /// `Particle::SURFACE`, set by the surface pass while the surface detection is on. It lives in the
+    /// particle rather than a buffer of its own so compaction carries it along with the particle.
+    pub flags: u32,
+    /// Names the particle for as long as it lives, while compaction and sorting move it between buffer
+    /// slots. Particles uploaded or emitted from the CPU count up from zero, split halves count up from
+    /// `Particle::SPLIT_ID`.
+    pub id: u32,
+    /// SPH divergence of the velocity around the particle, as of the last step that measured it; see
+    /// `AppData::measure_divergence`.
+    pub divergence: f32,
+}
+
+/// Solver parameters, read by every compute pass from a per-frame uniform buffer.
+/// Laid out to match the std140 `SimParams` block in the shaders.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SimParams {
+    pub domain_min: glm::Vec4,
+    pub domain_max: glm::Vec4,
+    pub gravity: glm::Vec4,
+    pub h: f32,
+    /// Rest density and viscosity of phase 0; boundary volumes are scaled by this rest density.
+    pub rest_density: f32,
+    pub stiffness: f32,
+    pub viscosity: f32,
+    pub dt: f32,
+    /// Upper bound on the live count, used to size dispatches; the GPU owns the exact count.
+    pub particle_count: u32,
+    pub frame: u32,
+    pub emit_count: u32,
+    pub sink_count: u32,
+    /// How far outside the domain a particle may stray before it is deleted.
+    pub domain_margin: f32,
+    /// Number of baked obstacle fields the integrate pass collides against.
+    pub sdf_count: u32,
+    /// Number of static boundary particles sampled from obstacle surfaces.
+    pub boundary_count: u32,
+    /// Number of rigid bodies whose boundary particles move and collect fluid forces.
+    pub body_count: u32,
+    pub phase_count: u32,
+    /// Strength of the vorticity confinement force, in m/s; zero skips its passes.
+    pub vorticity_epsilon: f32,
+    /// Color field gradient, in units of `1 / h`, past which a particle counts as near the free surface
+    /// and has its density renormalized; zero skips the correction.
+    pub surface_threshold: f32,
+    /// Split and merge criteria of the adaptive resolution, see `Refinement::params`; zero leaves it off.
+    pub refine: glm::Vec4,
+    /// Surface detection thresholds, see `SurfaceDetection::params`; zero leaves it off.
+    pub surface_detection: glm::Vec4,
+    /// Per axis; `w` unused. Set through `set_boundary_kinds`, which fits the grid to them.
+    pub boundary_kinds: [BoundaryKind; 4],
+    /// Displacement of the walls from rest; `w` is their angle about `container_spin`. Set through
+    /// `set_container`, as are the next two.
+    pub container_offset: glm::Vec4,
+    /// Unit axis the walls turn about, through the center of the domain; `w` is their angular velocity.
+    pub container_spin: glm::Vec4,
+    /// Velocity the walls move at; `w` is how far the motion may carry them, which the grid covers.
+    pub container_velocity: glm::Vec4,
+    /// Normalization constants of the selected kernel, see `Kernel::coefficients`.
+    pub kernel: glm::Vec4,
+    /// Per phase `(rest_density, viscosity, particle mass, 0)`.
+    pub phases: [glm::Vec4; MAX_PHASES],
+    /// Per phase render color; `w` unused.
+    pub phase_colors: [glm::Vec4; MAX_PHASES],
+    /// Corner of the neighbor grid; `w` unused.
+    pub grid_origin: glm::Vec4,
+    /// Cells along each axis; `w` is the total.
+    pub grid_dims: [u32; 4],
+    /// Cell size along each axis, at least `h`; `w` unused.
+    pub grid_cell: glm::Vec4,
+    /// Smallest and largest point size the particle renderer draws at, as `AppData::point_size_range`;
+    /// `zw` unused. Not the solver's, but bound to the renderer alongside the rest.
+    pub point_size: glm::Vec4,
+}
+
+/// One fluid in a multi-phase simulation.
+#[derive(Copy, Clone, Debug)]
+pub struct Phase {
+    pub rest_density: f32,
+    pub viscosity: f32,
+    pub color: glm::Vec3,
+}
+
+/// Gravity that turns about `axis` once every `period` simulated seconds, starting from `base` at time zero.
+#[derive(Copy, Clone, Debug)]
+pub struct RotatingGravity {
+    pub base: glm::Vec3,
+    pub axis: glm::Vec3,
+    pub period: f32,
+}
+
+impl RotatingGravity {
+    /// The rotation about `axis` that passes through `gravity` at `time`.
+    pub fn through(gravity: glm::Vec3, axis: glm::Vec3, period: f32, time: f64) -> Self {
+        let base = glm::rotate_vec3(&gravity, -Self::angle(time, period), &glm::normalize(&axis));
+        Self { base, axis, period }
+    }
+
+    pub fn at(&self, time: f64) -> glm::Vec3 {
+        glm::rotate_vec3(&self.base, Self::angle(time, self.period), &glm::normalize(&self.axis))
+    }
+
+    fn angle(time: f64, period: f32) -> f32 {
+        (time / period as f64).fract() as f32 * std::f32::consts::TAU
+    }
+}
+
+/// Prescribed motion of the domain walls, for sloshing: `amplitude * sin(2π frequency t)` of a clock
+/// that runs only while the motion does. A shake displaces the walls that many meters along `axis`, a
+/// rock turns them that many radians about `axis` through the center of the domain.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind", deny_unknown_fields)]
+pub enum ContainerMotion {
+    Shake { axis: [f32; 3], amplitude: f32, frequency: f32 },
+    Rock { axis: [f32; 3], amplitude: f32, frequency: f32 },
+}
+
+impl ContainerMotion {
+    pub fn axis(&self) -> glm::Vec3 {
+        let (Self::Shake { axis, .. } | Self::Rock { axis, .. }) = self;
+        glm::normalize(&glm::make_vec3(axis))
+    }
+
+    /// Displacement or angle at `time` on the motion clock, and its rate of change.
+    pub fn at(&self, time: f64) -> (f32, f32) {
+        let (Self::Shake { amplitude, frequency, .. } | Self::Rock { amplitude, frequency, .. }) = *self;
+        let phase = (time * frequency as f64).fract() as f32 * std::f32::consts::TAU;
+        (amplitude * phase.sin(), amplitude * frequency * std::f32::consts::TAU * phase.cos())
+    }
+
+    /// How far the motion can carry any point of the walls of the box `min..max` from where it rests.
+    pub fn reach(&self, min: &glm::Vec3, max: &glm::Vec3) -> f32 {
+        match *self {
+            Self::Shake { amplitude, .. } => amplitude.abs(),
+            // the chord a corner sweeps out turning by the largest angle
+            Self::Rock { amplitude, .. } => glm::length(&(max - min)) * (amplitude.abs().min(std::f32::consts::PI) / 2.0).sin(),
+        }
+    }
+}
+
+/// When particles split in two and when pairs of split particles merge back, for more resolution near
+/// the free surface and refining obstacles at no extra cost in the bulk. A particle of its phase's
+/// mass splits into two halves where either criterion holds, and two halves merge where neither is
+/// close to holding, so a particle is never split twice. The merge thresholds lie on the far side of
+/// the split thresholds so particles do not flicker between the two. Criteria left at zero are off.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Refinement {
+    /// Color field gradient, in units of `1 / h` as for `SimParams::surface_threshold`, past which a
+    /// particle counts as near the free surface and splits.
+    pub split_surface: f32,
+    /// Color field gradient below which two halves may merge.
+    pub merge_surface: f32,
+    /// Distance to the surface of an obstacle marked `refine` within which a particle splits.
+    pub split_distance: f32,
+    /// Distance to the surface of every such obstacle past which two halves may merge.
+    pub merge_distance: f32,
+}
+
+impl Refinement {
+    /// The criteria as the shaders read them, with each merge threshold moved past its split threshold
+    /// if it is not already.
+    pub fn params(&self) -> glm::Vec4 {
+        glm::vec4(self.split_surface.max(0.0), self.merge_surface.clamp(0.0, self.split_surface.max(0.0)),
+            self.split_distance.max(0.0), self.merge_distance.max(self.split_distance))
+    }
+}
+
+impl Default for Refinement {
+    fn default() -> Self {
+        Self { split_surface: REFINE_SPLIT_SURFACE, merge_surface: REFINE_MERGE_SURFACE,
+            split_distance: REFINE_SPLIT_DISTANCE, merge_distance: REFINE_MERGE_DISTANCE }
+    }
+}
+
+/// Which particles the surface pass flags `Particle::SURFACE`: those whose color field gradient passes
+/// `gradient` or that have fewer than `neighbors` neighbors, fluid or boundary samples, within `h`.
+/// Either test at zero is skipped. The domain walls hold no boundary samples, so particles against
+/// them read as surface unless boundary particles line them.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SurfaceDetection {
+    /// Color field gradient, in units of `1 / h` as for `SimParams::surface_threshold`.
+    pub gradient: f32,
+    pub neighbors: u32,
+}
+
+impl SurfaceDetection {
+    /// The thresholds as the shaders read them.
+    pub fn params(&self) -> glm::Vec4 {
+        glm::vec4(self.gradient.max(0.0), self.neighbors as f32, 0.0, 0.0)
+    }
+}
+
+impl Default for SurfaceDetection {
+    fn default() -> Self {
+        Self { gradient: SURFACE_DETECTION_GRADIENT, neighbors: SURFACE_DETECTION_NEIGHBORS }
+    }
+}
+
+/// How the particle renderer colors particles.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ParticleColoring {
+    /// By `SimParams::phase_colors`.
+    #[default]
+    Phase,
+    /// As `Phase`, tinting those the surface detection flags.
+    Surface,
+    /// Through the colormap, by density, speed or pressure over the `ColorRange`.
+    Density,
+    Speed,
+    Pressure,
+    /// Through the colormap by velocity divergence, over a range centered on zero unless fixed.
+    Divergence,
+}
+
+impl ParticleColoring {
+    /// The scalar the particle shader maps through the colormap, as its `scalar` push constant numbers
+    /// them: 0 for none.
+    pub fn scalar(&self) -> u32 {
+        match self {
+            Self::Phase | Self::Surface => 0,
+            Self::Density => 1,
+            Self::Speed => 2,
+            Self::Pressure => 3,
+            Self::Divergence => 4,
+        }
+    }
+}
+
+/// Values of the coloring scalar that map to the ends of the colormap.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ColorRange {
+    /// The scalar's range over the particles, from the diagnostics reduction, eased towards over a few
+    /// frames so the colors do not flicker.
+    #[default]
+    Auto,
+    Fixed { min: f32, max: f32 },
+}
+
+/// CFL-based adaptive time step: `dt = safety_factor * h / max_speed`, clamped to `[min_dt, max_dt]`.
+#[derive(Copy, Clone, Debug)]
+pub struct TimeStep {
+    pub safety_factor: f32,
+    pub min_dt: f32,
+    pub max_dt: f32,
+    pub dt: f32,
+}
+
+impl Particle {
+    /// Flag of a particle the surface detection finds on the free surface.
+    pub const SURFACE: u32 = 1;
+    /// First id the refine pass hands out to split halves, clear of the ids the CPU hands out.
+    pub const SPLIT_ID: u32 = 1 << 31;
+
+    pub fn new(pos: glm::Vec3, mass: f32) -> Self {
+        Self { pos, mass, ..Default::default() }
+    }
+
+    /// The particle buffer read as a vertex buffer by the sprite pipeline, a vertex per particle.
+    pub fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Particle>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()
+    }
+
+    /// Position, phase, flags and id, then density, velocity, pressure, mass and divergence.
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 9] {
+        let pos = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(0).format(vk::Format::R32G32B32_SFLOAT).offset(0).build();
+        let phase = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(1).format(vk::Format::R32_UINT).offset(48).build();
+        let flags = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(2).format(vk::Format::R32_UINT).offset(52).build();
+        let id = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(3).format(vk::Format::R32_UINT).offset(56).build();
+        let density = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(4).format(vk::Format::R32_SFLOAT).offset(12).build();
+        let vel = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(5).format(vk::Format::R32G32B32_SFLOAT).offset(16).build();
+        let pressure = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(6).format(vk::Format::R32_SFLOAT).offset(28).build();
+        let mass = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(7).format(vk::Format::R32_SFLOAT).offset(44).build();
+        let divergence = vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(8).format(vk::Format::R32_SFLOAT).offset(60).build();
+        [pos, phase, flags, id, density, vel, pressure, mass, divergence]
+    }
+}
+
+impl fmt::Display for Particle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pos ({:.4}, {:.4}, {:.4}), vel ({:.4}, {:.4}, {:.4}), density {:.2}, pressure {:.3}, \
+            force ({:.3}, {:.3}, {:.3}), mass {:.5}, phase {}{}", self.pos.x, self.pos.y, self.pos.z,
+            self.vel.x, self.vel.y, self.vel.z, self.density, self.pressure,
+            self.force.x, self.force.y, self.force.z, self.mass, self.phase,
+            if self.flags & Self::SURFACE != 0 { ", on the surface" } else { "" })
+    }
+}
+
+impl SimParams {
+    pub const PARTICLE_MASS: f32 = 0.02;
+    pub const PARTICLE_SPACING: f32 = 0.0272;
+
+    pub fn new() -> Self {
+        let h = 0.0457;
+        let mut sim = Self {
+            domain_min: glm::vec4(-0.5, -0.5, -0.5, 0.0), domain_max: glm::vec4(0.5, 0.5, 0.5, 0.0),
+            gravity: glm::vec4(0.0, -9.81, 0.0, 0.0),
+            h, rest_density: 998.29, stiffness: 3.0, viscosity: 3.5,
+            dt: 0.0, particle_count: 0, frame: 0, emit_count: 0, sink_count: 0, domain_margin: 0.05,
+            sdf_count: 0, boundary_count: 0, body_count: 0, phase_count: 0, vorticity_epsilon: 0.0, surface_threshold: 0.0,
+            refine: glm::Vec4::zeros(), surface_detection: glm::Vec4::zeros(), boundary_kinds: [BoundaryKind::Wall; 4],
+            container_offset: glm::Vec4::zeros(), container_spin: glm::Vec4::zeros(), container_velocity: glm::Vec4::zeros(),
+            kernel: Kernel::new(KernelKind::default(), h).coefficients(),
+            phases: [glm::Vec4::zeros(); MAX_PHASES], phase_colors: [glm::Vec4::zeros(); MAX_PHASES],
+            grid_origin: glm::Vec4::zeros(), grid_dims: [0; 4], grid_cell: glm::Vec4::zeros(),
+            point_size: glm::vec4(1.0, 1.0, 0.0, 0.0),
+        };
+        sim.update_grid();
+        sim.set_phase(0, &Phase { rest_density: sim.rest_density, viscosity: sim.viscosity,
+            color: glm::vec3(0.2, 0.45, 0.9) });
+        sim
+    }
+
+    /// Sets phase `index`'s parameters, adding it if it is the next free slot.
+    /// Returns false if the index is past the phases in use or `MAX_PHASES`.
+    pub fn set_phase(&mut self, index: usize, phase: &Phase) -> bool {
+        if index > self.phase_count as usize || index >= MAX_PHASES {
+            return false;
+        }
+        let mass = phase.rest_density * Self::PARTICLE_SPACING.powi(3);
+        self.phases[index] = glm::vec4(phase.rest_density, phase.viscosity, mass, 0.0);
+        self.phase_colors[index] = glm::vec3_to_vec4(&phase.color);
+        self.phase_count = self.phase_count.max(index as u32 + 1);
+        if index == 0 {
+            self.rest_density = phase.rest_density;
+            self.viscosity = phase.viscosity;
+        }
+        true
+    }
+
+    /// Mass of a particle of `phase` at the rest spacing, so every phase fills space at its rest density.
+    pub fn phase_mass(&self, phase: u32) -> f32 {
+        self.phases.get(phase as usize).map_or(Self::PARTICLE_MASS, |p| p.z)
+    }
+
+    /// Radius of the sphere that holds `p`'s share of the fluid at rest: half the spacing of a lattice of
+    /// such particles. Particles carry no radius of their own, as it follows from their mass.
+    pub fn particle_radius(&self, p: &Particle) -> f32 {
+        0.5 * (p.mass / self.phases[(p.phase as usize).min(MAX_PHASES - 1)].x).cbrt()
+    }
+
+    /// Turns the adaptive resolution on with the given criteria, or off.
+    pub fn set_refinement(&mut self, refinement: Option<&Refinement>) {
+        self.refine = refinement.map_or(glm::Vec4::zeros(), Refinement::params);
+    }
+
+    pub fn refinement(&self) -> Option<Refinement> {
+        (self.refine.x > 0.0 || self.refine.z > 0.0).then(|| Refinement {
+            split_surface: self.refine.x, merge_surface: self.refine.y,
+            split_distance: self.refine.z, merge_distance: self.refine.w,
+        })
+    }
+
+    /// Turns the surface detection on with the given thresholds, or off.
+    pub fn set_surface_detection(&mut self, detection: Option<&SurfaceDetection>) {
+        self.surface_detection = detection.map_or(glm::Vec4::zeros(), SurfaceDetection::params);
+    }
+
+    pub fn surface_detection(&self) -> Option<SurfaceDetection> {
+        (self.surface_detection.x > 0.0 || self.surface_detection.y > 0.0).then(|| SurfaceDetection {
+            gradient: self.surface_detection.x, neighbors: self.surface_detection.y as u32,
+        })
+    }
+
+    /// Whether the surface pass runs: for the density correction, the refinement or the detection.
+    pub fn needs_color_field(&self) -> bool {
+        self.surface_threshold > 0.0 || self.refine.x > 0.0 || self.surface_detection().is_some()
+    }
+
+    /// Sets the smoothing kernel family and radius, updating the normalization constants to match.
+    pub fn set_kernel(&mut self, kind: KernelKind, h: f32) {
+        self.h = h;
+        self.kernel = Kernel::new(kind, h).coefficients();
+        self.update_grid();
+    }
+
+    /// Sets what lies past the domain along each axis, failing if a periodic axis is too short for the
+    /// grid to wrap: it needs three cells of at least `h`, or the 27 cells around a particle repeat.
+    pub fn set_boundary_kinds(&mut self, kinds: [BoundaryKind; 3]) -> Result<()> {
+        for (a, kind) in kinds.iter().enumerate() {
+            let extent = self.domain_max[a] - self.domain_min[a];
+            if *kind == BoundaryKind::Periodic && extent < 3.0 * self.h {
+                return Err(anyhow!("A periodic axis must be at least 3 h = {} long, but axis {} is {}.",
+                    3.0 * self.h, a, extent));
+            }
+        }
+        self.boundary_kinds = [kinds[0], kinds[1], kinds[2], BoundaryKind::Wall];
+        self.update_grid();
+        Ok(())
+    }
+
+    pub fn periodic(&self) -> bool {
+        self.boundary_kinds.contains(&BoundaryKind::Periodic)
+    }
+
+    /// `a - b`, taking the nearest periodic image of `b` along periodic axes.
+    pub fn separation(&self, a: &glm::Vec3, b: &glm::Vec3) -> glm::Vec3 {
+        let mut r = a - b;
+        for k in 0..3 {
+            if self.boundary_kinds[k] == BoundaryKind::Periodic {
+                let extent = self.domain_max[k] - self.domain_min[k];
+                r[k] -= extent * (r[k] / extent).round();
+            }
+        }
+        r
+    }
+
+    /// Puts the walls where `motion` has them at `time` on its clock, moving if `running` and standing
+    /// still otherwise, or at rest without a motion. Refits the grid to what the motion can reach.
+    pub fn set_container(&mut self, motion: Option<&ContainerMotion>, time: f64, running: bool) {
+        let (min, max) = (self.domain_min.xyz(), self.domain_max.xyz());
+        let reach = motion.map_or(0.0, |m| m.reach(&min, &max));
+        let (offset, rate) = motion.map_or((0.0, 0.0), |m| m.at(time));
+        let rate = if running { rate } else { 0.0 };
+        let axis = motion.map_or(glm::Vec3::zeros(), ContainerMotion::axis);
+        (self.container_offset, self.container_spin, self.container_velocity) = match motion {
+            Some(ContainerMotion::Shake { .. }) => (glm::vec3_to_vec4(&(axis * offset)), glm::Vec4::zeros(),
+                glm::vec4(axis.x * rate, axis.y * rate, axis.z * rate, reach)),
+            Some(ContainerMotion::Rock { .. }) => (glm::vec4(0.0, 0.0, 0.0, offset), glm::vec4(axis.x, axis.y, axis.z, rate),
+                glm::vec4(0.0, 0.0, 0.0, reach)),
+            None => (glm::Vec4::zeros(), glm::Vec4::zeros(), glm::Vec4::zeros()),
+        };
+        self.update_grid();
+    }
+
+    /// Rotation of the walls from rest.
+    pub fn container_rotation(&self) -> glm::Mat3 {
+        match self.container_offset.w {
+            0.0 => glm::Mat3::identity(),
+            angle => glm::mat4_to_mat3(&glm::rotation(angle, &self.container_spin.xyz())),
+        }
+    }
+
+    /// Where the walls carry a point that would be at `rest` with the container at rest.
+    pub fn from_container(&self, rest: &glm::Vec3) -> glm::Vec3 {
+        let center = (self.domain_min.xyz() + self.domain_max.xyz()) / 2.0;
+        center + self.container_offset.xyz() + self.container_rotation() * (rest - center)
+    }
+
+    /// Where `pos` would be with the container at rest, the inverse of `from_container`.
+    pub fn to_container(&self, pos: &glm::Vec3) -> glm::Vec3 {
+        let center = (self.domain_min.xyz() + self.domain_max.xyz()) / 2.0;
+        center + self.container_rotation().transpose() * (pos - center - self.container_offset.xyz())
+    }
+
+    /// Velocity of the walls at `pos`, were they to reach it.
+    pub fn wall_velocity(&self, pos: &glm::Vec3) -> glm::Vec3 {
+        let center = (self.domain_min.xyz() + self.domain_max.xyz()) / 2.0 + self.container_offset.xyz();
+        self.container_velocity.xyz() + self.container_spin.w * glm::cross(&self.container_spin.xyz(), &(pos - center))
+    }
+
+    /// Fits the neighbor grid to the domain plus its margin and whatever the walls' motion can reach,
+    /// with cells one smoothing radius wide. Along periodic axes the grid spans the domain alone, in
+    /// whole cells stretched to at least `h`, so that it can wrap.
+    pub fn update_grid(&mut self) {
+        let margin = self.domain_margin + self.container_velocity.w;
+        let mut min = self.domain_min.xyz().add_scalar(-margin);
+        let extent = self.domain_max.xyz().add_scalar(margin) - min;
+        let mut dims = extent.map(|e| ((e / self.h).ceil() as u32).max(1));
+        let mut cell = glm::vec3(self.h, self.h, self.h);
+        for a in 0..3 {
+            if self.boundary_kinds[a] == BoundaryKind::Periodic {
+                let extent = self.domain_max[a] - self.domain_min[a];
+                min[a] = self.domain_min[a];
+                dims[a] = ((extent / self.h).floor() as u32).max(1);
+                cell[a] = extent / dims[a] as f32;
+            }
+        }
+        self.grid_origin = glm::vec4(min.x, min.y, min.z, 0.0);
+        self.grid_cell = glm::vec4(cell.x, cell.y, cell.z, 0.0);
+        self.grid_dims = [dims.x, dims.y, dims.z, dims.x * dims.y * dims.z];
+    }
+
+    /// Grid cell holding `pos`, clamped to the grid like the compute shaders do.
+    pub fn cell_of(&self, pos: &glm::Vec3) -> u32 {
+        self.cell_index(self.cell_coords(pos))
+    }
+
+    /// Coordinates of the grid cell holding `pos`, clamped to the grid.
+    pub fn cell_coords(&self, pos: &glm::Vec3) -> [u32; 3] {
+        let c = (pos - self.grid_origin.xyz()).component_div(&self.grid_cell.xyz());
+        let clamp = |v: f32, n: u32| (v.floor().max(0.0) as u32).min(n - 1);
+        [clamp(c.x, self.grid_dims[0]), clamp(c.y, self.grid_dims[1]), clamp(c.z, self.grid_dims[2])]
+    }
+
+    pub fn cell_index(&self, [x, y, z]: [u32; 3]) -> u32 {
+        (z * self.grid_dims[1] + y) * self.grid_dims[0] + x
+    }
+
+    /// Copies the parameters into `frame`'s uniform buffer.
+    pub unsafe fn update(&self, frame: usize, data: &AppData, device: &Device) -> Result<()> {
+        let memory = device.map_memory(
+            data.sim_params_buffers_memory[frame], 0,
+            size_of::<SimParams>() as u64, vk::MemoryMapFlags::empty())?;
+        memcpy(self, memory.cast(), 1);
+        device.unmap_memory(data.sim_params_buffers_memory[frame]);
+        Ok(())
+    }
+
+    /// Number of workgroups of `workgroup_size` needed to cover every particle once.
+    pub fn workgroups(&self, workgroup_size: u32) -> u32 {
+        self.particle_count.div_ceil(workgroup_size)
+    }
+}
+
+impl Default for SimParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeStep {
+    pub fn new(safety_factor: f32, min_dt: f32, max_dt: f32) -> Self {
+        Self { safety_factor, min_dt, max_dt, dt: max_dt }
+    }
+
+    pub fn update(&mut self, max_speed: f32, h: f32) -> f32 {
+        self.dt = if max_speed > f32::EPSILON {
+            (self.safety_factor * h / max_speed).clamp(self.min_dt, self.max_dt)
+        } else {
+            self.max_dt
+        };
+        self.dt
+    }
+}
+
+/// How the render loop advances the solver each frame.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum TimeMode {
+    /// Keep simulated time in step with the wall clock, taking CFL-sized steps, at most `max_substeps`
+    /// per frame; when that is not enough the simulation falls behind rather than piling up work.
+    Realtime { max_substeps: u32 },
+    /// Exactly `substeps` steps of `dt` seconds per frame, however long frames take, so a run depends
+    /// only on how many frames it has rendered. `dt` is not checked against the CFL condition.
+    Fixed { dt: f32, substeps: u32 },
+}
+
+impl Default for TimeMode {
+    fn default() -> Self {
+        TimeMode::Realtime { max_substeps: MAX_SUBSTEPS }
+    }
+}
+
+/// Fills the box `[min, max]` with particles on a regular lattice.
+/// A side within rounding of a whole number of spacings gets that many particles.
+pub fn spawn_block(min: glm::Vec3, max: glm::Vec3, spacing: f32, mass: f32, phase: u32) -> Vec<Particle> {
+    let counts = ((max - min) / spacing).add_scalar(1e-3);
+    let mut particles = Vec::new();
+    for x in 0..counts.x as u32 {
+        for y in 0..counts.y as u32 {
+            for z in 0..counts.z as u32 {
+                let offset = glm::vec3(x as f32, y as f32, z as f32) * spacing;
+                particles.push(Particle { phase, ..Particle::new(min + offset, mass) });
+            }
+        }
+    }
+    particles
+}
+
+/// Particle buffer helpers
+pub unsafe fn create_particle_buffers(instance: &Instance, device: &Device, data: &mut AppData,
+    particles: &[Particle]) -> Result<()> {
+    // the buffer holds MAX_PARTICLES so emitters can append without reallocating
+    data.particle_capacity = MAX_PARTICLES.max(particles.len() as u32);
+    let capacity = data.particle_capacity as u64;
+    let particle_size = size_of::<Particle>() as u64;
+
+    // compaction scatters the survivors of one buffer into the other, which becomes the latest state
+    data.particle_buffers.clear();
+    data.particle_buffers_memory.clear();
+    for _ in 0..2 {
+        let (buffer, memory) = create_storage_buffer(instance, device, data,
+            particle_size * capacity, vk::BufferUsageFlags::VERTEX_BUFFER, false)?;
+        data.particle_buffers.push(buffer);
+        data.particle_buffers_memory.push(memory);
+    }
+
+    // GPU-owned live count, laid out as a VkDrawIndirectCommand followed by the compacted count, the
+    // number of particles in the neighbor grid, the number of particles split this step, the number
+    // of split ids handed out, the foam particles ever seeded and the foam updates run
+    let (counter_buffer, counter_buffer_memory) = create_storage_buffer(instance, device, data,
+        size_of::<[u32; 10]>() as u64, vk::BufferUsageFlags::INDIRECT_BUFFER, false)?;
+    data.counter_buffer = counter_buffer;
+    data.counter_buffer_memory = counter_buffer_memory;
+    let (live_count_buffer, live_count_buffer_memory) = create_storage_buffer(instance, device, data,
+        (size_of::<u32>() * MAX_FRAMES_IN_FLIGHT) as u64, vk::BufferUsageFlags::TRANSFER_DST, true)?;
+    data.live_count_buffer = live_count_buffer;
+    data.live_count_buffer_memory = live_count_buffer_memory;
+
+    // persistently mapped staging regions for emitted particles and sink boxes, one slice per frame in flight
+    let size = (size_of::<Particle>() * MAX_EMITTED_PER_FRAME * MAX_FRAMES_IN_FLIGHT) as u64;
+    let (emit_staging_buffer, emit_staging_buffer_memory) = create_storage_buffer(instance, device, data,
+        size, vk::BufferUsageFlags::empty(), true)?;
+    let memory = device.map_memory(emit_staging_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    data.emit_staging_buffer = emit_staging_buffer;
+    data.emit_staging_buffer_memory = emit_staging_buffer_memory;
+    data.emit_staging = Some(memory.cast());
+    let size = (size_of::<glm::Vec4>() * 2 * MAX_SINKS * MAX_FRAMES_IN_FLIGHT) as u64;
+    let (sink_buffer, sink_buffer_memory) = create_storage_buffer(instance, device, data,
+        size, vk::BufferUsageFlags::empty(), true)?;
+    let memory = device.map_memory(sink_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    data.sink_buffer = sink_buffer;
+    data.sink_buffer_memory = sink_buffer_memory;
+    data.sink_staging = Some(memory.cast());
+    let size = (size_of::<glm::Vec4>() * 3 * MAX_OPENINGS * MAX_FRAMES_IN_FLIGHT) as u64;
+    let (opening_buffer, opening_buffer_memory) = create_storage_buffer(instance, device, data,
+        size, vk::BufferUsageFlags::empty(), true)?;
+    let memory = device.map_memory(opening_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    data.opening_buffer = opening_buffer;
+    data.opening_buffer_memory = opening_buffer_memory;
+    data.opening_staging = Some(memory.cast());
+    let size = (size_of::<glm::Vec4>() * 3 * MAX_FRAMES_IN_FLIGHT) as u64;
+    let (mouse_force_buffer, mouse_force_buffer_memory) = create_storage_buffer(instance, device, data,
+        size, vk::BufferUsageFlags::empty(), true)?;
+    let memory = device.map_memory(mouse_force_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    data.mouse_force_buffer = mouse_force_buffer;
+    data.mouse_force_buffer_memory = mouse_force_buffer_memory;
+    data.mouse_force_staging = Some(memory.cast());
+    let size = particle_size * capacity * MAX_FRAMES_IN_FLIGHT as u64;
+    let (export_buffer, export_buffer_memory) = create_storage_buffer(instance, device, data,
+        size, vk::BufferUsageFlags::TRANSFER_DST, true)?;
+    let memory = device.map_memory(export_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    data.export_buffer = export_buffer;
+    data.export_buffer_memory = export_buffer_memory;
+    data.export_staging = Some(memory.cast());
+
+    // each survivor's cell and its rank within the cell, for the counting sort
+    let groups = data.particle_capacity.div_ceil(data.workgroup_size).max(1) as u64;
+    let uint_size = size_of::<u32>() as u64;
+    let (cell_ids_buffer, cell_ids_buffer_memory) = create_storage_buffer(instance, device, data,
+        uint_size * capacity, vk::BufferUsageFlags::empty(), false)?;
+    let (cell_ranks_buffer, cell_ranks_buffer_memory) = create_storage_buffer(instance, device, data,
+        uint_size * capacity, vk::BufferUsageFlags::empty(), false)?;
+    data.cell_ids_buffer = cell_ids_buffer;
+    data.cell_ids_buffer_memory = cell_ids_buffer_memory;
+    data.cell_ranks_buffer = cell_ranks_buffer;
+    data.cell_ranks_buffer_memory = cell_ranks_buffer_memory;
+
+    let (vorticity_buffer, vorticity_buffer_memory) = create_storage_buffer(instance, device, data,
+        size_of::<glm::Vec4>() as u64 * capacity, vk::BufferUsageFlags::empty(), false)?;
+    data.vorticity_buffer = vorticity_buffer;
+    data.vorticity_buffer_memory = vorticity_buffer_memory;
+    let (surface_buffer, surface_buffer_memory) = create_storage_buffer(instance, device, data,
+        size_of::<glm::Vec4>() as u64 * capacity, vk::BufferUsageFlags::empty(), false)?;
+    data.surface_buffer = surface_buffer;
+    data.surface_buffer_memory = surface_buffer_memory;
+    let (partner_buffer, partner_buffer_memory) = create_storage_buffer(instance, device, data,
+        uint_size * capacity, vk::BufferUsageFlags::empty(), false)?;
+    data.partner_buffer = partner_buffer;
+    data.partner_buffer_memory = partner_buffer_memory;
+    let (impulse_buffer, impulse_buffer_memory) = create_storage_buffer(instance, device, data,
+        size_of::<glm::Vec4>() as u64 * capacity, vk::BufferUsageFlags::empty(), false)?;
+    data.impulse_buffer = impulse_buffer;
+    data.impulse_buffer_memory = impulse_buffer_memory;
+    // read by the sprites as a second vertex buffer, alongside the particles
+    let (highlight_buffer, highlight_buffer_memory) = create_storage_buffer(instance, device, data,
+        uint_size * capacity, vk::BufferUsageFlags::VERTEX_BUFFER, false)?;
+    data.highlight_buffer = highlight_buffer;
+    data.highlight_buffer_memory = highlight_buffer_memory;
+
+    // three partials per first-level workgroup, one final set of diagnostics per frame in flight
+    let (partials_buffer, partials_buffer_memory) = create_storage_buffer(instance, device, data,
+        3 * size_of::<glm::Vec4>() as u64 * groups, vk::BufferUsageFlags::empty(), false)?;
+    data.reduce_partials_buffer = partials_buffer;
+    data.reduce_partials_buffer_memory = partials_buffer_memory;
+    let (pick_buffer, pick_buffer_memory) = create_storage_buffer(instance, device, data,
+        size_of::<[u32; 2]>() as u64 * groups, vk::BufferUsageFlags::empty(), false)?;
+    data.pick_buffer = pick_buffer;
+    data.pick_buffer_memory = pick_buffer_memory;
+    let size = (size_of::<Particle>() * SELECTION_SLOTS * MAX_FRAMES_IN_FLIGHT) as u64;
+    let (selection_buffer, selection_buffer_memory) = create_storage_buffer(instance, device, data,
+        size, vk::BufferUsageFlags::TRANSFER_DST, true)?;
+    let memory = device.map_memory(selection_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    data.selection_buffer = selection_buffer;
+    data.selection_buffer_memory = selection_buffer_memory;
+    data.selection_staging = Some(memory.cast());
+    // the surface reconstruction grows it to its grid on first use
+    create_field_buffer(instance, device, data, 1)?;
+    create_foam_buffer(instance, device, data)?;
+    let (diagnostics_buffer, diagnostics_buffer_memory) = create_storage_buffer(instance, device, data,
+        (size_of::<Diagnostics>() * MAX_FRAMES_IN_FLIGHT) as u64, vk::BufferUsageFlags::TRANSFER_DST, true)?;
+    data.diagnostics_buffer = diagnostics_buffer;
+    data.diagnostics_buffer_memory = diagnostics_buffer_memory;
+    let (histogram_buffer, histogram_buffer_memory) = create_storage_buffer(instance, device, data,
+        (size_of::<DensityHistogram>() * MAX_FRAMES_IN_FLIGHT) as u64, vk::BufferUsageFlags::TRANSFER_DST, true)?;
+    data.density_histogram_buffer = histogram_buffer;
+    data.density_histogram_buffer_memory = histogram_buffer_memory;
+    reset_particles(instance, device, data, particles)
+}
+
+/// Replaces the live particles with `particles`, numbered afresh, and resets the GPU count and every
+/// frame's read-backs to match. Nothing may be in flight.
+pub unsafe fn reset_particles(instance: &Instance, device: &Device, data: &mut AppData,
+    particles: &[Particle]) -> Result<()> {
+    data.particle_parity = 0;
+    let particles = particles.iter().enumerate().map(|(i, p)| Particle { id: i as u32, ..*p }).collect::<Vec<_>>();
+    data.next_particle_id = particles.len() as u32;
+    if !particles.is_empty() {
+        upload_to_buffer(instance, device, data, &particles, data.particle_buffers[0])?;
+    }
+    let count = particles.len() as u32;
+    // nothing is in the grid until the first step sorts the particles
+    upload_to_buffer(instance, device, data, &[count, 1, 0, 0, count, 0, 0, 0, 0, 0], data.counter_buffer)?;
+    clear_foam(device, data)?;
+    clear_trails(device, data)?;
+    clear_highlights(device, data)?;
+    write_mapped(device, data.live_count_buffer_memory, &[count; MAX_FRAMES_IN_FLIGHT])?;
+    write_mapped(device, data.diagnostics_buffer_memory, &[Diagnostics::default(); MAX_FRAMES_IN_FLIGHT])
+}
+
+/// Creates the neighbor grid's per-cell counts and starts. The starts hold one more value than there
+/// are cells, which the scan of the counts turns into the number of particles in the grid.
+pub unsafe fn create_grid_buffers(instance: &Instance, device: &Device, data: &mut AppData,
+    sim: &SimParams) -> Result<()> {
+    data.grid_cells = sim.grid_dims[3];
+    let uint_size = size_of::<u32>() as u64;
+    let cells = data.grid_cells as u64;
+    let (cell_counts_buffer, cell_counts_buffer_memory) = create_storage_buffer(instance, device, data,
+        uint_size * cells, vk::BufferUsageFlags::empty(), false)?;
+    let (cell_starts_buffer, cell_starts_buffer_memory) = create_storage_buffer(instance, device, data,
+        uint_size * (cells + 1), vk::BufferUsageFlags::empty(), false)?;
+    data.cell_counts_buffer = cell_counts_buffer;
+    data.cell_counts_buffer_memory = cell_counts_buffer_memory;
+    data.cell_starts_buffer = cell_starts_buffer;
+    data.cell_starts_buffer_memory = cell_starts_buffer_memory;
+    register_scan_buffer(instance, device, data, cell_starts_buffer, data.grid_cells + 1)
+}
+
+/// Destroys the grid buffers; unregister the cell starts from scanning first when the scan pipelines stay.
+pub unsafe fn destroy_grid_buffers(device: &Device, data: &AppData) {
+    device.destroy_buffer(data.cell_counts_buffer, None);
+    device.free_memory(data.cell_counts_buffer_memory, None);
+    device.destroy_buffer(data.cell_starts_buffer, None);
+    device.free_memory(data.cell_starts_buffer_memory, None);
+}
+
+/// Reads back the neighbor grid of the latest step: the sorted particles, and each cell's start and count.
+/// Nothing may be in flight.
+pub unsafe fn read_grid(instance: &Instance, device: &Device, data: &AppData)
+-> Result<(Vec<Particle>, Vec<u32>, Vec<u32>)> {
+    let sorted = download_from_buffer::<u32>(instance, device, data, data.counter_buffer, 6)?[5];
+    let particles = download_from_buffer(instance, device, data, latest_particle_buffer(data), sorted as usize)?;
+    let cells = data.grid_cells as usize;
+    let starts = download_from_buffer(instance, device, data, data.cell_starts_buffer, cells)?;
+    let counts = download_from_buffer(instance, device, data, data.cell_counts_buffer, cells)?;
+    Ok((particles, starts, counts))
+}
+
+pub(crate) unsafe fn create_storage_buffer(instance: &Instance, device: &Device, data: &AppData,
+    size: vk::DeviceSize, usage: vk::BufferUsageFlags, host_visible: bool,
+) -> Result<(vk::Buffer, vk::DeviceMemory)> {
+    let (usage, properties) = if host_visible {
+        (usage, vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE)
+    } else {
+        (usage | vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL)
+    };
+    create_shared_buffer(instance, device, data, size, usage | vk::BufferUsageFlags::STORAGE_BUFFER, properties)
+}
+
+/// Copies `values` into the start of a device-local buffer through a temporary staging buffer.
+pub(crate) unsafe fn upload_to_buffer<T: Copy>(instance: &Instance, device: &Device, data: &AppData,
+    values: &[T], buffer: vk::Buffer) -> Result<()> {
+    let size = size_of_val(values) as u64;
+    let (staging_buffer, staging_buffer_memory) = create_buffer(
+        instance, device, data, size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+    )?;
+    write_mapped(device, staging_buffer_memory, values)?;
+    copy_buffer(device, data, staging_buffer, buffer, size)?;
+    device.destroy_buffer(staging_buffer, None);
+    device.free_memory(staging_buffer_memory, None);
+    Ok(())
+}
+
+/// Copies the first `count` values of a device-local buffer back to the host through a staging buffer.
+pub(crate) unsafe fn download_from_buffer<T: Copy + Default>(instance: &Instance, device: &Device, data: &AppData,
+    buffer: vk::Buffer, count: usize) -> Result<Vec<T>> {
+    let mut values = vec![T::default(); count];
+    let size = size_of_val(values.as_slice()) as u64;
+    if size == 0 {
+        return Ok(values);
+    }
+    let (staging_buffer, staging_buffer_memory) = create_buffer(
+        instance, device, data, size,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+    )?;
+    copy_buffer(device, data, buffer, staging_buffer, size)?;
+    let mapped = device.map_memory(staging_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    memcpy(mapped.cast(), values.as_mut_ptr(), count);
+    device.unmap_memory(staging_buffer_memory);
+    device.destroy_buffer(staging_buffer, None);
+    device.free_memory(staging_buffer_memory, None);
+    Ok(values)
+}
+
+pub(crate) unsafe fn write_mapped<T: Copy>(device: &Device, memory: vk::DeviceMemory, values: &[T]) -> Result<()> {
+    let size = size_of_val(values) as u64;
+    let mapped = device.map_memory(memory, 0, size, vk::MemoryMapFlags::empty())?;
+    memcpy(values.as_ptr(), mapped.cast(), values.len());
+    device.unmap_memory(memory);
+    Ok(())
+}
+
+/// Two layers filling the bottom of the domain: phase 1 sits on top of phase 0, so if phase 1 is the
+/// heavier fluid the layers swap places.
+pub fn layered_tank(sim: &SimParams) -> Vec<Particle> {
+    let (min, max) = (sim.domain_min.xyz(), sim.domain_max.xyz());
+    let spacing = SimParams::PARTICLE_SPACING;
+    let (low, high) = (min.y + (max.y - min.y) * 0.25, min.y + (max.y - min.y) * 0.5);
+    let mut particles = spawn_block(min, glm::vec3(max.x, low, max.z), spacing, sim.phase_mass(0), 0);
+    particles.extend(spawn_block(glm::vec3(min.x, low, min.z), glm::vec3(max.x, high, max.z),
+        spacing, sim.phase_mass(1), 1));
+    particles
+}
+
+/// Runs every active emitter for `dt` seconds and stages the new particles in `frame`'s slice of the
+/// emission buffer. `live_count` must be an upper bound on the GPU's count so capacity is never exceeded.
+pub unsafe fn stage_emitted_particles(data: &mut AppData, frame: usize, sim: &SimParams, dt: f32, live_count: u32) -> u32 {
+    let free = data.particle_capacity.saturating_sub(live_count) as usize;
+    let mut limit = free.min(MAX_EMITTED_PER_FRAME);
+    let mut emitted = Vec::new();
+    for emitter in data.emitters.iter_mut() {
+        let before = emitted.len();
+        let mass = sim.phase_mass(emitter.phase);
+        emitter.emit(dt, SimParams::PARTICLE_SPACING, mass, limit, &mut emitted);
+        limit -= emitted.len() - before;
+    }
+    for inflow in data.inflows.iter_mut() {
+        let before = emitted.len();
+        inflow.feed(dt, SimParams::PARTICLE_SPACING, sim.phase_mass(inflow.phase), limit, &mut emitted);
+        limit -= emitted.len() - before;
+    }
+    for p in emitted.iter_mut() {
+        p.id = data.next_particle_id;
+        data.next_particle_id = (data.next_particle_id + 1) % Particle::SPLIT_ID;
+    }
+    if let Some(staging) = data.emit_staging {
+        memcpy(emitted.as_ptr(), staging.add(frame * MAX_EMITTED_PER_FRAME), emitted.len());
+    }
+    emitted.len() as u32
+}
+
+/// Writes the active sink boxes into `frame`'s slice of the sink buffer and returns how many there are.
+pub unsafe fn stage_sinks(data: &AppData, frame: usize) -> u32 {
+    let boxes = data.sinks.iter().filter(|s| s.active).take(MAX_SINKS)
+        .flat_map(|s| [glm::vec3_to_vec4(&s.min), glm::vec3_to_vec4(&s.max)])
+        .collect::<Vec<_>>();
+    if let Some(staging) = data.sink_staging {
+        memcpy(boxes.as_ptr(), staging.add(frame * MAX_SINKS * 2), boxes.len());
+    }
+    (boxes.len() / 2) as u32
+}
+
+/// Writes the active inflows and outflows into `frame`'s slice of the opening buffer, each as its box
+/// corners, the first tagged with `OPENING_INFLOW` or `OPENING_OUTFLOW` in `w`, then the inflow velocity
+/// or outflow direction. The rest of the slice is zeroed, which the shaders read as no opening.
+pub unsafe fn stage_openings(data: &AppData, frame: usize) {
+    let opening = |min: &glm::Vec3, max: &glm::Vec3, kind: f32, flow: &glm::Vec3|
+        [glm::vec4(min.x, min.y, min.z, kind), glm::vec3_to_vec4(max), glm::vec3_to_vec4(flow)];
+    let mut entries = data.inflows.iter().filter(|i| i.active)
+        .map(|i| opening(&i.min, &i.max, OPENING_INFLOW, &i.velocity))
+        .chain(data.outflows.iter().filter(|o| o.active).map(|o| opening(&o.min, &o.max, OPENING_OUTFLOW, &o.direction)))
+        .take(MAX_OPENINGS).flatten().collect::<Vec<_>>();
+    entries.resize(3 * MAX_OPENINGS, glm::Vec4::zeros());
+    if let Some(staging) = data.opening_staging {
+        memcpy(entries.as_ptr(), staging.add(frame * MAX_OPENINGS * 3), entries.len());
+    }
+}
+
+/// Opening tags the shaders read, see `stage_openings`.
+const OPENING_INFLOW: f32 = 1.0;
+const OPENING_OUTFLOW: f32 = 2.0;
+
+/// A push of the mouse force tool on the particles near a ray, in the frame the fluid is simulated in.
+#[derive(Copy, Clone, Debug)]
+pub struct MouseForce {
+    pub origin: glm::Vec3,
+    pub direction: glm::Vec3,
+    pub radius: f32,
+    /// Acceleration of the particles on the ray, fading to nothing `radius` away from it.
+    pub acceleration: glm::Vec3,
+}
+
+/// Writes the mouse force tool's push, if any, into `frame`'s slice of the mouse force buffer: the ray
+/// origin (w: radius), its direction and the acceleration (w: 1 while pushing, 0 otherwise).
+pub unsafe fn stage_mouse_force(data: &AppData, frame: usize) {
+    let entries = match data.mouse_force {
+        Some(push) => [glm::vec4(push.origin.x, push.origin.y, push.origin.z, push.radius),
+            glm::vec3_to_vec4(&push.direction), glm::vec4(push.acceleration.x, push.acceleration.y, push.acceleration.z, 1.0)],
+        None => [glm::Vec4::zeros(); 3],
+    };
+    if let Some(staging) = data.mouse_force_staging {
+        memcpy(entries.as_ptr(), staging.add(frame * 3), entries.len());
+    }
+}
+
+/// Bakes a signed distance field for every loaded object and uploads them as one header buffer
+/// and one value buffer. Returns how many fields the integrate pass should collide against.
+pub unsafe fn create_sdf_buffers(instance: &Instance, device: &Device, data: &mut AppData) -> Result<u32> {
+    let model = scene_model();
+    let mut infos = Vec::new();
+    let mut values = Vec::new();
+    for obj in data.objects.iter().filter(|o| o.obstacle == Obstacle::Sdf) {
+        // terrain is its own distance field, placed where its mesh is
+        if let Some(heightfield) = &obj.heightfield {
+            let heightfield = heightfield.translated(&(model * obj.transform).column(3).xyz());
+            infos.push(heightfield.info(values.len() as u32, obj.refine));
+            values.extend_from_slice(&heightfield.heights);
+            continue;
+        }
+        let sdf = obj.bake_sdf(&model);
+        if sdf.values.is_empty() {
+            continue;
+        }
+        infos.push(sdf.info(values.len() as u32, obj.refine));
+        values.extend_from_slice(&sdf.values);
+    }
+    let count = infos.len() as u32;
+    // descriptors need a buffer even when there is nothing to collide with
+    infos.resize(infos.len().max(1), SdfInfo::default());
+    values.resize(values.len().max(1), 0.0);
+    let (info_buffer, info_buffer_memory) = create_storage_buffer(instance, device, data,
+        size_of_val(infos.as_slice()) as u64, vk::BufferUsageFlags::empty(), false)?;
+    upload_to_buffer(instance, device, data, &infos, info_buffer)?;
+    let (value_buffer, value_buffer_memory) = create_storage_buffer(instance, device, data,
+        size_of_val(values.as_slice()) as u64, vk::BufferUsageFlags::empty(), false)?;
+    upload_to_buffer(instance, device, data, &values, value_buffer)?;
+    data.sdf_info_buffer = info_buffer;
+    data.sdf_info_buffer_memory = info_buffer_memory;
+    data.sdf_value_buffer = value_buffer;
+    data.sdf_value_buffer_memory = value_buffer_memory;
+    Ok(count)
+}
+
+/// Samples boundary particles on every object that uses them and uploads them with their volumes.
+/// Static boundaries come first, followed by each rigid body's particles in body order.
+/// Returns the number of boundary particles.
+pub unsafe fn create_boundary_buffer(instance: &Instance, device: &Device, data: &mut AppData,
+    sim: &SimParams) -> Result<u32> {
+    let model = scene_model();
+    let samples = data.objects.iter().enumerate()
+        .filter(|(i, o)| o.obstacle == Obstacle::Particles && !data.bodies.iter().any(|b| b.object == *i))
+        .flat_map(|(_, o)| o.sample_boundary(&model, SimParams::PARTICLE_SPACING))
+        .collect::<Vec<_>>();
+    // volumes are computed over all samples so touching obstacles do not double count
+    let kernel = Kernel::new(data.kernel_kind, sim.h);
+    let mut particles = boundary_particles(&samples, &kernel, sim.rest_density);
+    for (index, body) in data.bodies.iter_mut().enumerate() {
+        let obj = &data.objects[body.object];
+        let samples = obj.sample_boundary(&model, SimParams::PARTICLE_SPACING);
+        let to_body = body.inverse_matrix();
+        body.rest_transform = to_body * model * obj.transform;
+        body.first = particles.len() as u32;
+        body.count = samples.len() as u32;
+        particles.extend(boundary_particles(&samples, &kernel, sim.rest_density).into_iter().map(|mut b| {
+            b.rest = (to_body * glm::vec4(b.pos.x, b.pos.y, b.pos.z, 1.0)).xyz();
+            b.body = index as u32;
+            b
+        }));
+    }
+    let count = particles.len() as u32;
+    particles.resize(particles.len().max(1), BoundaryParticle::default());
+    let (buffer, buffer_memory) = create_storage_buffer(instance, device, data,
+        size_of_val(particles.as_slice()) as u64, vk::BufferUsageFlags::empty(), false)?;
+    upload_to_buffer(instance, device, data, &particles, buffer)?;
+    data.boundary_buffer = buffer;
+    data.boundary_buffer_memory = buffer_memory;
+    let (buffer, buffer_memory) = create_storage_buffer(instance, device, data,
+        (size_of::<glm::Vec4>() * particles.len()) as u64, vk::BufferUsageFlags::empty(), false)?;
+    data.boundary_force_buffer = buffer;
+    data.boundary_force_buffer_memory = buffer_memory;
+    Ok(count)
+}
+
+/// Creates the per-frame rigid body state and net force buffers, both persistently host visible.
+pub unsafe fn create_body_buffers(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let size = (size_of::<BodyState>() * MAX_BODIES * MAX_FRAMES_IN_FLIGHT) as u64;
+    let (body_buffer, body_buffer_memory) = create_storage_buffer(instance, device, data,
+        size, vk::BufferUsageFlags::empty(), true)?;
+    let memory = device.map_memory(body_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    data.body_buffer = body_buffer;
+    data.body_buffer_memory = body_buffer_memory;
+    data.body_staging = Some(memory.cast());
+    let forces = [BodyForce::default(); MAX_BODIES * MAX_FRAMES_IN_FLIGHT];
+    let (body_force_buffer, body_force_buffer_memory) = create_storage_buffer(instance, device, data,
+        size_of_val(&forces) as u64, vk::BufferUsageFlags::empty(), true)?;
+    write_mapped(device, body_force_buffer_memory, &forces)?;
+    data.body_force_buffer = body_force_buffer;
+    data.body_force_buffer_memory = body_force_buffer_memory;
+    Ok(())
+}
+
+/// Writes every rigid body's current state into `frame`'s slice of the body buffer and returns how many there are.
+pub unsafe fn stage_bodies(data: &AppData, frame: usize) -> u32 {
+    let states = data.bodies.iter().take(MAX_BODIES).map(|b| b.state()).collect::<Vec<_>>();
+    if let Some(staging) = data.body_staging {
+        memcpy(states.as_ptr(), staging.add(frame * MAX_BODIES), states.len());
+    }
+    states.len() as u32
+}
+
+/// Reads back the net fluid force and torque on each body from the step last submitted for `frame`.
+/// The caller must have waited on that frame's fence.
+pub unsafe fn read_body_forces(device: &Device, data: &AppData, frame: usize) -> Result<Vec<BodyForce>> {
+    let count = data.bodies.len().min(MAX_BODIES);
+    let mut forces = vec![BodyForce::default(); count];
+    if count > 0 {
+        let offset = (size_of::<BodyForce>() * MAX_BODIES * frame) as u64;
+        let memory = device.map_memory(data.body_force_buffer_memory, offset,
+            size_of_val(forces.as_slice()) as u64, vk::MemoryMapFlags::empty())?;
+        memcpy(memory.cast(), forces.as_mut_ptr(), count);
+        device.unmap_memory(data.body_force_buffer_memory);
+    }
+    Ok(forces)
+}
+
+pub unsafe fn destroy_obstacle_buffers(device: &Device, data: &AppData) {
+    device.destroy_buffer(data.sdf_info_buffer, None);
+    device.free_memory(data.sdf_info_buffer_memory, None);
+    device.destroy_buffer(data.sdf_value_buffer, None);
+    device.free_memory(data.sdf_value_buffer_memory, None);
+    device.destroy_buffer(data.boundary_buffer, None);
+    device.free_memory(data.boundary_buffer_memory, None);
+    device.destroy_buffer(data.boundary_force_buffer, None);
+    device.free_memory(data.boundary_force_buffer_memory, None);
+}
+
+/// Reads every live particle of the latest solver step back to the host. Nothing may be in flight.
+pub unsafe fn read_particles(instance: &Instance, device: &Device, data: &AppData) -> Result<Vec<Particle>> {
+    read_particle_range(instance, device, data, 0..u32::MAX)
+}
+
+/// Reads the live particles in `range` of the latest solver step back to the host, the range clipped to
+/// the live count, copying just those out of the device-local buffer. Nothing may be in flight.
+pub unsafe fn read_particle_range(instance: &Instance, device: &Device, data: &AppData, range: Range<u32>)
+-> Result<Vec<Particle>> {
+    let count = download_from_buffer::<u32>(instance, device, data, data.counter_buffer, 1)?[0];
+    let (start, end) = (range.start.min(count), range.end.min(count));
+    if start >= end {
+        return Ok(Vec::new());
+    }
+    let particle_size = size_of::<Particle>() as u64;
+    let size = particle_size * (end - start) as u64;
+    let (staging_buffer, staging_buffer_memory) = create_buffer(
+        instance, device, data, size,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+    )?;
+    let command_buffer = begin_single_time_commands(device, data)?;
+    let region = vk::BufferCopy::builder().src_offset(particle_size * start as u64).size(size);
+    device.cmd_copy_buffer(command_buffer, latest_particle_buffer(data), staging_buffer, &[region]);
+    end_single_time_commands(device, data, command_buffer)?;
+    let mut particles = vec![Particle::default(); (end - start) as usize];
+    let mapped = device.map_memory(staging_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    memcpy(mapped.cast(), particles.as_mut_ptr(), particles.len());
+    device.unmap_memory(staging_buffer_memory);
+    device.destroy_buffer(staging_buffer, None);
+    device.free_memory(staging_buffer_memory, None);
+    Ok(particles)
+}
+
+/// Copies out the particles `frame`'s last step exported. The caller must have waited on that frame's
+/// fence, and the step must have been recorded with `export_enabled`.
+pub unsafe fn read_exported_particles(device: &Device, data: &AppData, frame: usize) -> Result<Vec<Particle>> {
+    let count = read_live_count(device, data, frame)?.min(data.particle_capacity) as usize;
+    let Some(staging) = data.export_staging else { return Ok(Vec::new()) };
+    let slot = staging.add(data.particle_capacity as usize * frame);
+    Ok(std::slice::from_raw_parts(slot, count).to_vec())
+}
+
+/// Reads back the live particle count at the end of the step last submitted for `frame`.
+/// The caller must have waited on that frame's fence.
+pub unsafe fn read_live_count(device: &Device, data: &AppData, frame: usize) -> Result<u32> {
+    let offset = (size_of::<u32>() * frame) as u64;
+    let memory = device.map_memory(data.live_count_buffer_memory, offset,
+        size_of::<u32>() as u64, vk::MemoryMapFlags::empty())?;
+    let count = *(memory as *const u32);
+    device.unmap_memory(data.live_count_buffer_memory);
+    Ok(count)
+}
+
+/// Storage buffers bound to every compute pass, in binding order.
+const COMPUTE_BINDINGS: u32 = 34;
+
+/// Compute descriptor helpers
+pub unsafe fn create_compute_descriptor_set_layout(device: &Device, data: &mut AppData) -> Result<()> {
+    let bindings = (0..COMPUTE_BINDINGS).map(|i| {
+        vk::DescriptorSetLayoutBinding::builder()
+            .binding(i)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .descriptor_count(1)
+            // the velocity glyphs read the particles and their count in the vertex shader
+            .stage_flags(vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::VERTEX)
+            .build()
+    }).collect::<Vec<_>>();
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+    data.compute_descriptor_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    // simulation parameters, shared by the solver passes and the particle renderer
+    let params_binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
+    let bindings = &[params_binding];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+    data.sim_params_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    Ok(())
+}
+
+pub unsafe fn create_sim_params_buffers(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    data.sim_params_buffers.clear();
+    data.sim_params_buffers_memory.clear();
+    for _ in 0..MAX_FRAMES_IN_FLIGHT {
+        let (buffer, buffer_memory) = create_shared_buffer(
+            instance, device, data,
+            size_of::<SimParams>() as u64,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        )?;
+        data.sim_params_buffers.push(buffer);
+        data.sim_params_buffers_memory.push(buffer_memory);
+    }
+    Ok(())
+}
+
+pub unsafe fn create_compute_descriptor_pool(device: &Device, data: &mut AppData) -> Result<()> {
+    let storage_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(2 * COMPUTE_BINDINGS);
+    // one parameter set per frame in flight next to the two ping-pong storage sets
+    let ubo_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::UNIFORM_BUFFER)
+        .descriptor_count(MAX_FRAMES_IN_FLIGHT as u32);
+    let pool_sizes = &[storage_size, ubo_size];
+    let info = vk::DescriptorPoolCreateInfo::builder()
+        .pool_sizes(pool_sizes)
+        .max_sets(2 + MAX_FRAMES_IN_FLIGHT as u32);
+    data.compute_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    Ok(())
+}
+
+pub unsafe fn create_compute_descriptor_sets(device: &Device, data: &mut AppData) -> Result<()> {
+    let layouts = &[data.compute_descriptor_set_layout; 2];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(data.compute_descriptor_pool)
+        .set_layouts(layouts);
+    data.compute_descriptor_sets = device.allocate_descriptor_sets(&info)?;
+    write_compute_descriptor_sets(device, data);
+    let layouts = vec![data.sim_params_set_layout; MAX_FRAMES_IN_FLIGHT];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(data.compute_descriptor_pool)
+        .set_layouts(&layouts);
+    data.sim_params_sets = device.allocate_descriptor_sets(&info)?;
+    for (set, buffer) in data.sim_params_sets.iter().zip(&data.sim_params_buffers) {
+        let info = vk::DescriptorBufferInfo::builder()
+            .buffer(*buffer)
+            .offset(0)
+            .range(size_of::<SimParams>() as u64);
+        let buffer_info = &[info];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(*set).dst_binding(0).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER).buffer_info(buffer_info);
+        device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+    }
+    Ok(())
+}
+
+/// Points every binding of both compute descriptor sets at the current buffers, with the particle
+/// buffers swapped between bindings 0 and 9 in the second set.
+pub unsafe fn write_compute_descriptor_sets(device: &Device, data: &AppData) {
+    for (k, set) in data.compute_descriptor_sets.iter().enumerate() {
+        let (current, next) = (data.particle_buffers[k], data.particle_buffers[1 - k]);
+        let buffers = [
+            current, data.reduce_partials_buffer, data.diagnostics_buffer, data.counter_buffer,
+            data.emit_staging_buffer, data.sink_buffer, data.cell_ids_buffer, data.cell_ranks_buffer,
+            data.cell_starts_buffer, next, data.sdf_info_buffer, data.sdf_value_buffer,
+            data.boundary_buffer, data.boundary_force_buffer, data.body_buffer, data.body_force_buffer,
+            data.cell_counts_buffer, data.density_histogram_buffer, data.vorticity_buffer, data.surface_buffer,
+            data.partner_buffer, data.opening_buffer, data.mouse_force_buffer, data.impulse_buffer,
+            data.pick_buffer, data.field_buffer, data.foam_buffer, data.trail_buffer, data.occupancy_buffer,
+            data.depth_sort_buffer, data.depth_buckets_buffer, data.highlight_buffer, data.cull_buffer,
+            data.selection_buffer,
+        ];
+        // the trail, occupancy, depth sort and cull buffers are only made once their views are turned on,
+        // and only their own passes use them
+        for (binding, buffer) in buffers.iter().enumerate().filter(|(_, b)| **b != vk::Buffer::null()) {
+            let info = vk::DescriptorBufferInfo::builder().buffer(*buffer).offset(0).range(vk::WHOLE_SIZE as u64);
+            let buffer_info = &[info];
+            let write = vk::WriteDescriptorSet::builder()
+                .dst_set(*set).dst_binding(binding as u32).dst_array_element(0)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER).buffer_info(buffer_info);
+            device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+        }
+    }
+}
+
+/// The particle buffer holding the most recently finished solver step, which is what the renderer draws.
+pub fn latest_particle_buffer(data: &AppData) -> vk::Buffer {
+    data.particle_buffers[data.particle_parity]
+}
+
+/// Shared memory one particle takes in the tiled force pass, the larger of the two tiled passes.
+const TILED_PARTICLE_SIZE: u32 = 40;
+/// Smaller tiles are not worth their barriers.
+const MIN_TILE_SIZE: u32 = 32;
+
+/// Sizes the shared-memory tiles of the tiled density and force passes to the device.
+pub unsafe fn query_tile_size(instance: &Instance, data: &mut AppData) {
+    let limit = instance.get_physical_device_properties(data.physical_device).limits.max_compute_shared_memory_size;
+    // the group cell bounds share the space
+    let tile_size = (limit.saturating_sub(8) / TILED_PARTICLE_SIZE).min(data.workgroup_size);
+    data.tile_size = if tile_size >= MIN_TILE_SIZE { tile_size } else { 0 };
+}
+
+/// Specialization constants of the solver passes, by `constant_id`: the smoothing kernel, whether the
+/// density and force passes tile, the tile size, the domain boundary mode and the workgroup size.
+pub(crate) fn solver_constants(data: &AppData, tiled: bool) -> [u32; 5] {
+    [data.kernel_kind as u32, tiled as u32, data.tile_size.max(1), data.boundary_mode as u32, data.workgroup_size]
+}
+
+/// Compute pipeline helpers
+pub unsafe fn create_compute_pipelines(device: &Device, data: &mut AppData) -> Result<()> {
+    // set 0: solver storage buffers, set 1: simulation parameters
+    let set_layouts = &[data.compute_descriptor_set_layout, data.sim_params_set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts);
+    data.compute_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let layout = data.compute_pipeline_layout;
+    let constants = solver_constants(data, false);
+    data.density_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &DENSITY_SHADER.to_string(), &constants)?;
+    data.force_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &FORCE_SHADER.to_string(), &constants)?;
+    if data.tile_size > 0 {
+        let tiled = solver_constants(data, true);
+        data.density_tiled_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &DENSITY_SHADER.to_string(), &tiled)?;
+        data.force_tiled_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &FORCE_SHADER.to_string(), &tiled)?;
+    }
+    data.speed_reduce_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &SPEED_REDUCE_SHADER.to_string(), &constants)?;
+    data.max_reduce_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &MAX_REDUCE_SHADER.to_string(), &constants)?;
+    data.vorticity_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &VORTICITY_SHADER.to_string(), &constants)?;
+    data.divergence_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &DIVERGENCE_SHADER.to_string(), &constants)?;
+    data.vorticity_force_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &VORTICITY_FORCE_SHADER.to_string(), &constants)?;
+    data.density_histogram_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &DENSITY_HISTOGRAM_SHADER.to_string(), &constants)?;
+    data.surface_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &SURFACE_SHADER.to_string(), &constants)?;
+    data.refine_pair_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &REFINE_PAIR_SHADER.to_string(), &constants)?;
+    data.refine_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &REFINE_SHADER.to_string(), &constants)?;
+    data.integrate_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &INTEGRATE_SHADER.to_string(), &constants)?;
+    data.append_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &APPEND_SHADER.to_string(), &constants)?;
+    data.mark_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &MARK_SHADER.to_string(), &constants)?;
+    data.scatter_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &SCATTER_SHADER.to_string(), &constants)?;
+    data.sort_cells_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &SORT_CELLS_SHADER.to_string(), &constants)?;
+    data.boundary_update_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &BOUNDARY_UPDATE_SHADER.to_string(), &constants)?;
+    data.boundary_force_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &BOUNDARY_FORCE_SHADER.to_string(), &constants)?;
+    data.body_reduce_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &BODY_REDUCE_SHADER.to_string(), &constants)?;
+    data.mouse_force_pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &MOUSE_FORCE_SHADER.to_string(), &constants)?;
+    create_pick_pipeline(device, data)?;
+    create_neighbor_pipeline(device, data)?;
+    create_splat_pipeline(device, data)?;
+    create_trail_pipeline(device, data)?;
+    create_occupancy_pipeline(device, data)?;
+    create_depth_sort_pipelines(device, data)?;
+    create_cull_pipeline(device, data)?;
+    create_foam_pipelines(device, data)
+}
+
+/// Compute command helpers
+pub unsafe fn create_compute_command_buffers(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
+    let info = vk::CommandPoolCreateInfo::builder()
+        .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+        .queue_family_index(indices.compute);
+    data.compute_command_pool = device.create_command_pool(&info, None)?;
+    let allocate_info = vk::CommandBufferAllocateInfo::builder()
+        .command_pool(data.compute_command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(MAX_FRAMES_IN_FLIGHT as u32);
+    data.compute_command_buffers = device.allocate_command_buffers(&allocate_info)?;
+    Ok(())
+}
+
+/// Records `substeps` solver steps for `frame`, whose parameters must already be in its uniform buffer.
+/// Each step runs the color field pass (when the density correction, the refinement or the surface detection is on), density, mouse force (while the tool pushes), force, vorticity confinement, divergence (while `measure_divergence` is set), diagnostics reduction and integrate passes,
+/// splits and merges particles (when the refinement is on), then compacts away particles that left the domain or hit a sink, counting-sorting the survivors by
+/// neighbor grid cell on the way; only the first appends the staged emitted particles and, with
+/// `bin_densities` set, bins the densities into `frame`'s histogram. Step `k` updates
+/// `particle_buffers[particle_parity ^ (k & 1)]` and compacts it into the other buffer, so the caller
+/// flips `particle_parity` once per substep once the commands are submitted. Only the first step's
+/// passes are timed, but the step timing spans them all. With foam on, the foam is seeded and moved
+/// once after the last step, with the occupancy view on the occupied grid cells are compacted, and with
+/// translucent particles the final particles are sorted by depth; with neighbor highlighting on, the
+/// selected particle's neighbors are marked.
+pub unsafe fn record_simulation_commands(device: &Device, data: &AppData, frame: usize, sim: &SimParams,
+    substeps: u32) -> Result<vk::CommandBuffer> {
+    let command_buffer = data.compute_command_buffers[frame];
+    let info = vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    device.begin_command_buffer(command_buffer, &info)?;
+    reset_step_timestamps(device, data, command_buffer, frame);
+    write_step_timestamp(device, data, command_buffer, frame, Stamp::StepBegin);
+    if data.bin_densities {
+        let size = size_of::<DensityHistogram>() as u64;
+        device.cmd_fill_buffer(command_buffer, data.density_histogram_buffer, size * frame as u64, size, 0);
+        transfer_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    }
+    let mut parity = data.particle_parity;
+    for substep in 0..substeps.max(1) {
+        record_substep(device, data, command_buffer, frame, sim, parity, substep == 0)?;
+        parity = 1 - parity;
+    }
+    // the foam follows the fluid once per frame, over the final particles
+    if let Some(foam) = &data.foam {
+        record_foam(device, data, command_buffer, frame, sim, parity, foam, sim.dt * substeps.max(1) as f32);
+    }
+    if data.sample_trails {
+        record_trails(device, data, command_buffer, sim, parity);
+    }
+    // the grid the last step sorted the final particles into
+    if let Some(occupancy) = &data.grid_occupancy {
+        record_occupancy(device, data, command_buffer, frame, sim, parity, occupancy);
+    }
+    // translucent particles are drawn back to front, in the order of the final particles' depths
+    if data.translucency.is_some() {
+        record_depth_sort(device, data, command_buffer, parity)?;
+    }
+    // the selected particle's neighbors through the grid the final particles were sorted into
+    record_neighbor_highlight(device, data, command_buffer, frame, parity);
+    // the selected and watched particles as they end the frame, for the host to follow once the frame is done
+    record_selection_readback(device, data, command_buffer, frame, parity);
+    // the final particles the camera sees
+    record_cull(device, data, command_buffer, parity);
+
+    // hand the live count to the host, and the final particles to the exporter
+    let uint_size = size_of::<u32>() as u64;
+    let readback = vk::BufferCopy::builder().src_offset(0).dst_offset(uint_size * frame as u64).size(uint_size);
+    device.cmd_copy_buffer(command_buffer, data.counter_buffer, data.live_count_buffer, &[readback]);
+    if data.export_enabled {
+        let particle_size = size_of::<Particle>() as u64;
+        let export = vk::BufferCopy::builder()
+            .dst_offset(particle_size * data.particle_capacity as u64 * frame as u64)
+            .size(particle_size * sim.particle_count.clamp(1, data.particle_capacity) as u64);
+        device.cmd_copy_buffer(command_buffer, data.particle_buffers[parity], data.export_buffer, &[export]);
+    }
+
+    // the particle buffer and count are read by the next step and the host; the renderer on the
+    // graphics queue is ordered after this by a semaphore, so only compute-queue stages appear here
+    let stages = vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::DRAW_INDIRECT
+        | vk::PipelineStageFlags::HOST;
+    let access = vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE
+        | vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::HOST_READ;
+    compute_barrier(device, command_buffer, stages, access);
+    transfer_barrier(device, command_buffer, stages, access);
+    write_step_timestamp(device, data, command_buffer, frame, Stamp::StepEnd);
+    device.end_command_buffer(command_buffer)?;
+    Ok(command_buffer)
+}
+
+/// Records one solver step on `particle_buffers[parity]`, leaving the survivors sorted in the other
+/// buffer and their count live in the counter. The first step of a frame appends the emitted particles
+/// and writes the pass timestamps.
+unsafe fn record_substep(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, frame: usize,
+    sim: &SimParams, parity: usize, first: bool) -> Result<()> {
+    let sets = [data.compute_descriptor_sets[parity], data.sim_params_sets[frame]];
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE,
+        data.compute_pipeline_layout, 0, &sets, &[]);
+
+    let groups = sim.workgroups(data.workgroup_size);
+    // boundary particles only need touching when some of them belong to a moving body
+    let boundary_groups = if sim.body_count > 0 { sim.boundary_count.div_ceil(data.workgroup_size) } else { 0 };
+    let emit_groups = if first { sim.emit_count.div_ceil(data.workgroup_size) } else { 0 };
+    let histogram_groups = if first && data.bin_densities { groups } else { 0 };
+    let vorticity_groups = if sim.vorticity_epsilon != 0.0 { groups } else { 0 };
+    let refine_groups = if sim.refinement().is_some() { groups } else { 0 };
+    let surface_groups = if sim.needs_color_field() { groups } else { 0 };
+    let mouse_force_groups = if data.mouse_force.is_some() { groups } else { 0 };
+    let divergence_groups = if data.measure_divergence { groups } else { 0 };
+    let dispatch = |passes: &[(vk::Pipeline, u32)]| {
+        for &(pipeline, group_count) in passes {
+            if group_count == 0 {
+                continue;
+            }
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+            device.cmd_dispatch(command_buffer, group_count, 1, 1);
+            compute_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+        }
+    };
+    // the tiles load runs of neighboring cells, which do not wrap around a periodic grid
+    let (density, force) = if data.tiled && data.tile_size > 0 && !sim.periodic() {
+        (data.density_tiled_pipeline, data.force_tiled_pipeline)
+    } else {
+        (data.density_pipeline, data.force_pipeline)
+    };
+    let stamp = |stamp: Stamp| {
+        if first {
+            write_step_timestamp(device, data, command_buffer, frame, stamp);
+        }
+    };
+    // the surface pass counts what it flags into the frame's diagnostics afresh every step, and the
+    // first step clears the count even with the detection off
+    if first || sim.surface_detection().is_some() {
+        let size = size_of::<Diagnostics>() as u64;
+        compute_barrier(device, command_buffer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+        device.cmd_fill_buffer(command_buffer, data.diagnostics_buffer, size * frame as u64 + 16, 4, 0);
+        transfer_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    }
+    dispatch(&[
+        (data.append_pipeline, emit_groups),
+        (data.boundary_update_pipeline, boundary_groups),
+    ]);
+    stamp(Stamp::DensityBegin);
+    dispatch(&[
+        // the free-surface correction, the refinement and the surface detection need the color field,
+        // from the densities before they are overwritten
+        (data.surface_pipeline, surface_groups),
+        (density, groups),
+        // the mouse force scales with the density, and the force pass adds it
+        (data.mouse_force_pipeline, mouse_force_groups),
+    ]);
+    stamp(Stamp::ForceBegin);
+    dispatch(&[
+        (force, groups),
+        // vorticity confinement adds to the forces, from the vorticity of every particle
+        (data.vorticity_pipeline, vorticity_groups),
+        (data.vorticity_force_pipeline, vorticity_groups),
+    ]);
+    stamp(Stamp::ForceEnd);
+    dispatch(&[
+        // reaction of the pressure forces on each body, reduced to one force and torque per body
+        (data.boundary_force_pipeline, boundary_groups),
+        (data.body_reduce_pipeline, sim.body_count),
+        // the velocities' divergence, for the reduction to take in
+        (data.divergence_pipeline, divergence_groups),
+        // two-level reduction of the diagnostics: per-workgroup partials, then a single workgroup over them
+        (data.speed_reduce_pipeline, groups),
+        (data.max_reduce_pipeline, 1),
+        (data.density_histogram_pipeline, histogram_groups),
+    ]);
+    stamp(Stamp::IntegrateBegin);
+    dispatch(&[(data.integrate_pipeline, groups)]);
+    stamp(Stamp::IntegrateEnd);
+    // merge partners come from the grid of this step, before it is cleared
+    dispatch(&[(data.refine_pair_pipeline, refine_groups)]);
+
+    // the next step's neighbor grid: count the survivors per cell, scan the counts into cell starts,
+    // then counting-sort the survivors densely into the other buffer
+    let uint_size = size_of::<u32>() as u64;
+    let cells = data.grid_cells as u64;
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_WRITE);
+    device.cmd_fill_buffer(command_buffer, data.cell_counts_buffer, 0, vk::WHOLE_SIZE as u64, 0);
+    // the scan turns this trailing zero into the total
+    device.cmd_fill_buffer(command_buffer, data.cell_starts_buffer, uint_size * cells, uint_size, 0);
+    // the mark pass takes in whatever the split count says, so it is cleared with or without refinement
+    device.cmd_fill_buffer(command_buffer, data.counter_buffer, 6 * uint_size, uint_size, 0);
+    transfer_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    dispatch(&[
+        // merged particles are left massless and split ones appended past the live count, for the mark
+        // pass to drop and take in
+        (data.refine_pipeline, refine_groups),
+        (data.mark_pipeline, groups),
+    ]);
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ);
+    let counts = vk::BufferCopy::builder().size(uint_size * cells);
+    device.cmd_copy_buffer(command_buffer, data.cell_counts_buffer, data.cell_starts_buffer, &[counts]);
+    transfer_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+    gpu_exclusive_scan(device, data, command_buffer, data.cell_starts_buffer, data.grid_cells + 1)?;
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE,
+        data.compute_pipeline_layout, 0, &sets, &[]);
+    dispatch(&[(data.scatter_pipeline, groups)]);
+    // the atomics in the mark pass rank particles within a cell in no fixed order
+    if data.strict_order {
+        dispatch(&[(data.sort_cells_pipeline, data.grid_cells.div_ceil(data.workgroup_size))]);
+    }
+    stamp(Stamp::GridEnd);
+    let total = vk::BufferCopy::builder().src_offset(uint_size * cells).dst_offset(4 * uint_size).size(uint_size);
+    device.cmd_copy_buffer(command_buffer, data.cell_starts_buffer, data.counter_buffer, &[total]);
+    transfer_barrier(device, command_buffer, vk::PipelineStageFlags::TRANSFER, vk::AccessFlags::TRANSFER_READ);
+
+    // make the compacted count live and the grid's; the survivors are already in the other buffer
+    let live = vk::BufferCopy::builder().src_offset(4 * uint_size).dst_offset(0).size(uint_size);
+    let sorted = vk::BufferCopy::builder().src_offset(4 * uint_size).dst_offset(5 * uint_size).size(uint_size);
+    device.cmd_copy_buffer(command_buffer, data.counter_buffer, data.counter_buffer, &[live, sorted]);
+    transfer_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::TRANSFER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE | vk::AccessFlags::TRANSFER_READ);
+    Ok(())
+}
+
+/// Records the stand-in for a solver step while paused: the particles are left alone and only `frame`'s
+/// live count read-back is refreshed, so the semaphores between the queues keep their usual pattern.
+/// Translucent particles are still sorted and culled ones culled, for the camera's view of them, and
+/// neighbors still marked.
+pub unsafe fn record_paused_commands(device: &Device, data: &AppData, frame: usize) -> Result<vk::CommandBuffer> {
+    let command_buffer = data.compute_command_buffers[frame];
+    let info = vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    device.begin_command_buffer(command_buffer, &info)?;
+    // the camera may turn while the particles stand still, and another particle be selected
+    if data.translucency.is_some() {
+        record_depth_sort(device, data, command_buffer, data.particle_parity)?;
+    }
+    record_neighbor_highlight(device, data, command_buffer, frame, data.particle_parity);
+    record_cull(device, data, command_buffer, data.particle_parity);
+    let uint_size = size_of::<u32>() as u64;
+    let readback = vk::BufferCopy::builder().src_offset(0).dst_offset(uint_size * frame as u64).size(uint_size);
+    device.cmd_copy_buffer(command_buffer, data.counter_buffer, data.live_count_buffer, &[readback]);
+    transfer_barrier(device, command_buffer, vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ);
+    device.end_command_buffer(command_buffer)?;
+    Ok(command_buffer)
+}
+
+pub(crate) unsafe fn transfer_barrier(device: &Device, command_buffer: vk::CommandBuffer,
+    dst_stage_mask: vk::PipelineStageFlags, dst_access_mask: vk::AccessFlags) {
+    let barrier = vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(dst_access_mask);
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::TRANSFER,
+        dst_stage_mask,
+        vk::DependencyFlags::empty(),
+        &[barrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[] as &[vk::ImageMemoryBarrier],
+    );
+}
+
+pub(crate) unsafe fn compute_barrier(device: &Device, command_buffer: vk::CommandBuffer,
+    dst_stage_mask: vk::PipelineStageFlags, dst_access_mask: vk::AccessFlags) {
+    let barrier = vk::MemoryBarrier::builder()
+        .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+        .dst_access_mask(dst_access_mask);
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        vk::PipelineStageFlags::COMPUTE_SHADER,
+        dst_stage_mask,
+        vk::DependencyFlags::empty(),
+        &[barrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[] as &[vk::ImageMemoryBarrier],
+    );
+}
+
+pub unsafe fn destroy_compute_pipelines(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.density_pipeline, None);
+    device.destroy_pipeline(data.force_pipeline, None);
+    device.destroy_pipeline(data.density_tiled_pipeline, None);
+    device.destroy_pipeline(data.force_tiled_pipeline, None);
+    device.destroy_pipeline(data.speed_reduce_pipeline, None);
+    device.destroy_pipeline(data.max_reduce_pipeline, None);
+    device.destroy_pipeline(data.density_histogram_pipeline, None);
+    device.destroy_pipeline(data.vorticity_pipeline, None);
+    device.destroy_pipeline(data.divergence_pipeline, None);
+    device.destroy_pipeline(data.vorticity_force_pipeline, None);
+    device.destroy_pipeline(data.surface_pipeline, None);
+    device.destroy_pipeline(data.refine_pair_pipeline, None);
+    device.destroy_pipeline(data.refine_pipeline, None);
+    device.destroy_pipeline(data.integrate_pipeline, None);
+    device.destroy_pipeline(data.append_pipeline, None);
+    device.destroy_pipeline(data.mark_pipeline, None);
+    device.destroy_pipeline(data.scatter_pipeline, None);
+    device.destroy_pipeline(data.sort_cells_pipeline, None);
+    device.destroy_pipeline(data.boundary_update_pipeline, None);
+    device.destroy_pipeline(data.boundary_force_pipeline, None);
+    device.destroy_pipeline(data.body_reduce_pipeline, None);
+    device.destroy_pipeline(data.mouse_force_pipeline, None);
+    destroy_pick_pipeline(device, data);
+    destroy_neighbor_pipeline(device, data);
+    destroy_splat_pipeline(device, data);
+    destroy_foam_pipelines(device, data);
+    destroy_trail_pipeline(device, data);
+    destroy_occupancy_pipeline(device, data);
+    destroy_depth_sort_pipelines(device, data);
+    destroy_cull_pipeline(device, data);
+    device.destroy_pipeline_layout(data.compute_pipeline_layout, None);
+}
+
+pub unsafe fn destroy_simulation(device: &Device, data: &AppData) {
+    destroy_compute_pipelines(device, data);
+    destroy_timestamp_queries(device, data);
+    device.destroy_descriptor_pool(data.compute_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(data.compute_descriptor_set_layout, None);
+    device.destroy_descriptor_set_layout(data.sim_params_set_layout, None);
+    data.sim_params_buffers.iter().for_each(|b| device.destroy_buffer(*b, None));
+    data.sim_params_buffers_memory.iter().for_each(|m| device.free_memory(*m, None));
+    device.destroy_command_pool(data.compute_command_pool, None);
+    device.unmap_memory(data.body_buffer_memory);
+    device.destroy_buffer(data.body_buffer, None);
+    device.free_memory(data.body_buffer_memory, None);
+    device.destroy_buffer(data.body_force_buffer, None);
+    device.free_memory(data.body_force_buffer_memory, None);
+    destroy_obstacle_buffers(device, data);
+    destroy_grid_buffers(device, data);
+    destroy_scan(device, data);
+    destroy_particle_buffers(device, data);
+    // the trails outlive particle buffers made anew, their rings shared out by id whatever the capacity
+    device.destroy_buffer(data.trail_buffer, None);
+    device.free_memory(data.trail_buffer_memory, None);
+    device.destroy_buffer(data.occupancy_buffer, None);
+    device.free_memory(data.occupancy_buffer_memory, None);
+    device.destroy_buffer(data.depth_sort_buffer, None);
+    device.free_memory(data.depth_sort_buffer_memory, None);
+    device.destroy_buffer(data.depth_buckets_buffer, None);
+    device.free_memory(data.depth_buckets_buffer_memory, None);
+    device.destroy_buffer(data.cull_buffer, None);
+    device.free_memory(data.cull_buffer_memory, None);
+}
+
+/// Destroys everything `create_particle_buffers` made.
+pub unsafe fn destroy_particle_buffers(device: &Device, data: &AppData) {
+    device.destroy_buffer(data.vorticity_buffer, None);
+    device.free_memory(data.vorticity_buffer_memory, None);
+    device.destroy_buffer(data.surface_buffer, None);
+    device.free_memory(data.surface_buffer_memory, None);
+    device.destroy_buffer(data.partner_buffer, None);
+    device.free_memory(data.partner_buffer_memory, None);
+    device.destroy_buffer(data.impulse_buffer, None);
+    device.free_memory(data.impulse_buffer_memory, None);
+    device.destroy_buffer(data.highlight_buffer, None);
+    device.free_memory(data.highlight_buffer_memory, None);
+    device.destroy_buffer(data.density_histogram_buffer, None);
+    device.free_memory(data.density_histogram_buffer_memory, None);
+    device.destroy_buffer(data.diagnostics_buffer, None);
+    device.free_memory(data.diagnostics_buffer_memory, None);
+    device.destroy_buffer(data.reduce_partials_buffer, None);
+    device.free_memory(data.reduce_partials_buffer_memory, None);
+    device.destroy_buffer(data.pick_buffer, None);
+    device.free_memory(data.pick_buffer_memory, None);
+    device.unmap_memory(data.selection_buffer_memory);
+    device.destroy_buffer(data.selection_buffer, None);
+    device.free_memory(data.selection_buffer_memory, None);
+    destroy_field_buffer(device, data);
+    destroy_foam_buffer(device, data);
+    device.unmap_memory(data.sink_buffer_memory);
+    device.destroy_buffer(data.sink_buffer, None);
+    device.free_memory(data.sink_buffer_memory, None);
+    device.unmap_memory(data.opening_buffer_memory);
+    device.destroy_buffer(data.opening_buffer, None);
+    device.free_memory(data.opening_buffer_memory, None);
+    device.unmap_memory(data.mouse_force_buffer_memory);
+    device.destroy_buffer(data.mouse_force_buffer, None);
+    device.free_memory(data.mouse_force_buffer_memory, None);
+    device.unmap_memory(data.export_buffer_memory);
+    device.destroy_buffer(data.export_buffer, None);
+    device.free_memory(data.export_buffer_memory, None);
+    device.destroy_buffer(data.cell_ids_buffer, None);
+    device.free_memory(data.cell_ids_buffer_memory, None);
+    device.destroy_buffer(data.cell_ranks_buffer, None);
+    device.free_memory(data.cell_ranks_buffer_memory, None);
+    device.destroy_buffer(data.counter_buffer, None);
+    device.free_memory(data.counter_buffer_memory, None);
+    device.destroy_buffer(data.live_count_buffer, None);
+    device.free_memory(data.live_count_buffer_memory, None);
+    device.unmap_memory(data.emit_staging_buffer_memory);
+    device.destroy_buffer(data.emit_staging_buffer, None);
+    device.free_memory(data.emit_staging_buffer_memory, None);
+    data.particle_buffers.iter().for_each(|b| device.destroy_buffer(*b, None));
+    data.particle_buffers_memory.iter().for_each(|m| device.free_memory(*m, None));
+}
@@ -0,0 +1,229 @@
+use std::f32::consts::PI;
+use std::path::Path;
+use anyhow::{anyhow, Context, Result};
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::reflect::{check_layout, reflect_bindings};
+use crate::utils::{compile_shader, create_shader_module, create_texture, upload_texture};
+
+/// Names the six face images of a skybox directory go by, whatever their extension, in the order of the
+/// cube's layers: +x, -x, +y, -y, +z and -z.
+const FACE_NAMES: [&str; 6] = ["px", "nx", "py", "ny", "pz", "nz"];
+
+/// The cubemap drawn behind everything along each pixel's view direction: six face images, one
+/// equirectangular panorama resampled onto the cube, or a gradient from the ground up to the zenith.
+/// Its texels are taken as sRGB, so they show as they are stored.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SkyboxTexture {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+}
+
+impl SkyboxTexture {
+    /// Loads the faces in the directory at `path`, or the panorama there if it is a file, or makes the
+    /// gradient with `None`; call after `create_command_pool`.
+    pub unsafe fn load(instance: &Instance, device: &Device, data: &AppData, path: Option<&str>) -> Result<Self> {
+        let (size, texels) = match path {
+            Some(path) if Path::new(path).is_dir() => load_faces(path)?,
+            Some(path) => load_panorama(path)?,
+            None => (SKY_GRADIENT_SIZE, cube_texels(SKY_GRADIENT_SIZE, gradient)),
+        };
+        let format = vk::Format::R8G8B8A8_SRGB;
+        let dims = [size, size, 1];
+        let (image, memory, view) = create_texture(instance, device, data, vk::ImageViewType::CUBE, dims, format, 1,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)?;
+        upload_texture(instance, device, data, image, format, dims, 6, 1, &texels)?;
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR).min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+        let sampler = device.create_sampler(&sampler_info, None)?;
+        Ok(Self { image, memory, view, sampler })
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_sampler(self.sampler, None);
+        device.destroy_image_view(self.view, None);
+        device.destroy_image(self.image, None);
+        device.free_memory(self.memory, None);
+    }
+}
+
+/// The six square face images of the same size in directory `path`, their texels one face after another.
+fn load_faces(path: &str) -> Result<(u32, Vec<u8>)> {
+    let entries = std::fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+    let mut size = None;
+    let mut texels = Vec::new();
+    for name in FACE_NAMES {
+        let face = entries.iter().map(|e| e.path()).find(|p| p.file_stem().is_some_and(|s| s == name))
+            .ok_or_else(|| anyhow!("{}: no skybox face named {}.", path, name))?;
+        let image = image::open(&face).with_context(|| format!("Cannot load the skybox face {}.", face.display()))?
+            .to_rgba8();
+        if image.width() != image.height() || size.is_some_and(|size| size != image.width()) {
+            return Err(anyhow!("{}: skybox faces must be square and all the same size.", face.display()));
+        }
+        size = Some(image.width());
+        texels.extend(image.into_raw());
+    }
+    Ok((size.unwrap_or(1), texels))
+}
+
+/// The equirectangular panorama at `path` looked up along each texel's direction of a cube a quarter of
+/// its width across, its middle straight down -z.
+fn load_panorama(path: &str) -> Result<(u32, Vec<u8>)> {
+    let image = image::open(path).with_context(|| format!("Cannot load the skybox {}.", path))?.to_rgba8();
+    let (width, height) = (image.width(), image.height());
+    let size = (width / 4).max(1);
+    let texels = cube_texels(size, |dir| {
+        let u = dir.x.atan2(-dir.z) / (2.0 * PI) + 0.5;
+        let v = dir.y.clamp(-1.0, 1.0).acos() / PI;
+        let x = ((u * width as f32) as u32).min(width - 1);
+        let y = ((v * height as f32) as u32).min(height - 1);
+        image.get_pixel(x, y).0
+    });
+    Ok((size, texels))
+}
+
+/// The sky's color along `dir` without an image: the horizon's blending up into the zenith's, and
+/// quickly down into the ground's below it.
+fn gradient(dir: glm::Vec3) -> [u8; 4] {
+    let (horizon, zenith, ground) = (glm::make_vec3(&SKY_HORIZON_COLOR), glm::make_vec3(&SKY_ZENITH_COLOR),
+        glm::make_vec3(&SKY_GROUND_COLOR));
+    let color = if dir.y >= 0.0 {
+        glm::mix(&horizon, &zenith, dir.y.sqrt())
+    } else {
+        glm::mix(&horizon, &ground, (-8.0 * dir.y).min(1.0))
+    };
+    let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [byte(color.x), byte(color.y), byte(color.z), 255]
+}
+
+/// The texels of a cube `size` across, face after face in the order of its layers, each the color
+/// `color` gives the direction through it.
+fn cube_texels(size: u32, color: impl Fn(glm::Vec3) -> [u8; 4]) -> Vec<u8> {
+    (0..6).flat_map(|face| (0..size).flat_map(move |j| (0..size).map(move |i| (face, i, j))))
+        .flat_map(|(face, i, j)| color(face_direction(face, i, j, size)))
+        .collect()
+}
+
+/// The direction through the middle of texel `(i, j)` of layer `face` of a cube `size` texels across, as
+/// Vulkan samples cubemaps.
+fn face_direction(face: u32, i: u32, j: u32, size: u32) -> glm::Vec3 {
+    let s = 2.0 * (i as f32 + 0.5) / size as f32 - 1.0;
+    let t = 2.0 * (j as f32 + 0.5) / size as f32 - 1.0;
+    glm::normalize(&match face {
+        0 => glm::vec3(1.0, -t, -s),
+        1 => glm::vec3(-1.0, -t, s),
+        2 => glm::vec3(s, 1.0, t),
+        3 => glm::vec3(s, -1.0, -t),
+        4 => glm::vec3(s, -t, 1.0),
+        _ => glm::vec3(-s, -t, -1.0),
+    })
+}
+
+/// Binds the skybox to every set of `descriptor_sets`.
+pub unsafe fn write_skybox(device: &Device, data: &AppData) {
+    let image_info = &[vk::DescriptorImageInfo::builder()
+        .sampler(data.skybox_texture.sampler).image_view(data.skybox_texture.view)
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+    let writes = data.descriptor_sets.iter().map(|set| vk::WriteDescriptorSet::builder()
+        .dst_set(*set).dst_binding(2).dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info).build())
+        .collect::<Vec<_>>();
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+}
+
+/// Creates the pipeline that draws the skybox: a cube about the camera, turned with it but never moved,
+/// put at the far plane so it passes the depth test only where nothing else was drawn.
+pub unsafe fn create_skybox_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&SKYBOX_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&SKYBOX_FRAGMENT_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    check_layout(&data.scene_bindings, &reflect_bindings(&vshader, vk::ShaderStageFlags::VERTEX)?, 1, SKYBOX_VERTEX_SHADER)?;
+    check_layout(&data.scene_bindings, &reflect_bindings(&fshader, vk::ShaderStageFlags::FRAGMENT)?, 1, SKYBOX_FRAGMENT_SHADER)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    // the cube's corners as a strip, made up from the vertex indices alone
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_STRIP)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(false);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    // at the depth the pass clears to, and leaving it there
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true).depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+
+    // the scene's uniform buffer and the cubemap in it
+    let set_layouts = &[data.descriptor_set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts);
+    data.skybox_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .dynamic_state(&dynamic_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.skybox_pipeline_layout)
+        .render_pass(data.render_pass)
+        .subpass(0);
+    data.skybox_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_skybox_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.skybox_pipeline, None);
+    device.destroy_pipeline_layout(data.skybox_pipeline_layout, None);
+}
+
+/// Records the skybox into swapchain image `i`'s render pass, if it is drawn.
+pub unsafe fn record_skybox_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    if !data.skybox {
+        return;
+    }
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.skybox_pipeline);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.skybox_pipeline_layout, 0, &[data.descriptor_sets[i]], &[]);
+    device.cmd_draw(command_buffer, 14, 1, 0, 0);
+}
@@ -0,0 +1,171 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::colormap::colormap_descriptor_set;
+use crate::config::*;
+use crate::utils::{compile_shader, create_shader_module};
+
+/// Axis a slice plane is perpendicular to.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SliceAxis {
+    X,
+    #[default]
+    Y,
+    Z,
+}
+
+impl SliceAxis {
+    /// The axis after this one, wrapping around.
+    pub fn next(self) -> Self {
+        match self {
+            SliceAxis::X => SliceAxis::Y,
+            SliceAxis::Y => SliceAxis::Z,
+            SliceAxis::Z => SliceAxis::X,
+        }
+    }
+}
+
+/// A cutting plane through the reconstruction's field, the volume fraction the surface is built from,
+/// drawn through the colormap so what lies inside the fluid can be seen.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DensitySlice {
+    pub visible: bool,
+    pub axis: SliceAxis,
+    /// Where the plane cuts the field's grid along `axis`, from 0 at its low side to 1 at its high side.
+    pub offset: f32,
+    /// Values of the field at the ends of the colormap.
+    pub range: [f32; 2],
+    /// Opacity of the plane over the scene behind it.
+    pub opacity: f32,
+}
+
+impl Default for DensitySlice {
+    fn default() -> Self {
+        Self { visible: false, axis: SliceAxis::default(), offset: SLICE_OFFSET, range: SLICE_RANGE,
+            opacity: SLICE_OPACITY }
+    }
+}
+
+/// Push constants of the slice pipeline, laid out like `SliceConstants` in `slice.vert`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct SliceConstants {
+    /// Position of the grid's first node, and the spacing of the nodes in w.
+    origin: glm::Vec4,
+    /// Nodes along each axis, and the axis the plane is perpendicular to in w.
+    dims: [u32; 4],
+    offset: f32,
+    opacity: f32,
+    range: [f32; 2],
+}
+
+/// Creates the pipeline that draws the slice plane: a quad across the field's grid, sampling the field
+/// volume through the raymarcher's sets and coloring it through the colormap, blended over the scene.
+pub unsafe fn create_slice_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&SLICE_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&SLICE_FRAGMENT_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    // the plane's four corners as a strip, made up from the vertex indices alone
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_STRIP)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    // hidden behind the opaque scene, without hiding what is blended after it
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true).depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+
+    // the raymarcher's uniform buffer and field volume, the colormap, and the plane as push constants
+    let set_layouts = &[data.raymarch_descriptor_set_layout, data.colormap_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(size_of::<SliceConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.slice_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .dynamic_state(&dynamic_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.slice_pipeline_layout)
+        .render_pass(data.render_pass)
+        .subpass(0);
+    data.slice_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_slice_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.slice_pipeline, None);
+    device.destroy_pipeline_layout(data.slice_pipeline_layout, None);
+}
+
+/// Records the slice plane into swapchain image `i`'s render pass, if it is visible and a field volume
+/// was uploaded.
+pub unsafe fn record_slice_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    let (Some(grid), true) = (&data.field_volume_grid, data.density_slice.visible) else { return };
+    let slice = &data.density_slice;
+    let constants = SliceConstants {
+        origin: glm::vec4(grid.origin.x, grid.origin.y, grid.origin.z, grid.spacing),
+        dims: [grid.dims[0], grid.dims[1], grid.dims[2], slice.axis as u32],
+        offset: slice.offset,
+        opacity: slice.opacity,
+        range: slice.range,
+    };
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.slice_pipeline);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.slice_pipeline_layout, 0,
+        &[data.raymarch_descriptor_sets[i], colormap_descriptor_set(data)], &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const SliceConstants).cast::<u8>(),
+        size_of::<SliceConstants>());
+    device.cmd_push_constants(command_buffer, data.slice_pipeline_layout,
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT, 0, bytes);
+    device.cmd_draw(command_buffer, 4, 1, 0, 0);
+}
@@ -0,0 +1,383 @@
+use std::time::Instant;
+use anyhow::{anyhow, Result};
+use nalgebra_glm as glm;
+use rayon::prelude::*;
+
+use crate::config::MAX_PHASES;
+use crate::grid::random_particles;
+use crate::kernel::Kernel;
+use crate::simulation::{BoundaryKind, Particle, SimParams};
+
+/// Velocity kept (and reversed) by a particle bouncing off a domain wall; matches the integrate pass.
+pub const WALL_DAMPING: f32 = 0.5;
+
+/// Pressure solves per substep. Pressure comes straight from the equation of state here, so there is
+/// only ever the one; the statistics file records it to line up with runs of iterative solvers.
+pub const PRESSURE_ITERATIONS: u32 = 1;
+
+/// Particles bucketed by neighbor grid cell, the CPU counterpart of the GPU's counting sort.
+pub struct CellList {
+    /// Particle indices in cell order, and in index order within a cell.
+    order: Vec<u32>,
+    /// Where each cell's run of `order` starts, with the particle count appended.
+    starts: Vec<u32>,
+}
+
+impl CellList {
+    /// Sorts the particles by `sim`'s grid cell in parallel. Ties break on the index, so the list, and
+    /// every sum over it, is the same however many threads build it.
+    pub fn build(particles: &[Particle], sim: &SimParams) -> Self {
+        let mut keyed = particles.par_iter().enumerate()
+            .map(|(i, p)| (sim.cell_of(&p.pos), i as u32))
+            .collect::<Vec<_>>();
+        keyed.par_sort_unstable();
+        let mut starts = vec![0u32; sim.grid_dims[3] as usize + 1];
+        for &(cell, _) in &keyed {
+            starts[cell as usize + 1] += 1;
+        }
+        for cell in 0..sim.grid_dims[3] as usize {
+            starts[cell + 1] += starts[cell];
+        }
+        Self { order: keyed.into_iter().map(|(_, i)| i).collect(), starts }
+    }
+
+    /// Calls `f` with every particle in the 27 cells around `pos`, wrapping around periodic axes, which
+    /// covers all those within `h`, always in the same order.
+    pub fn for_each_near(&self, sim: &SimParams, pos: &glm::Vec3, mut f: impl FnMut(usize)) {
+        let [cx, cy, cz] = sim.cell_coords(pos);
+        let range = |a: usize, c: u32| {
+            let n = sim.grid_dims[a] as i32;
+            let periodic = sim.boundary_kinds[a] == BoundaryKind::Periodic;
+            (c as i32 - 1..=c as i32 + 1).filter_map(move |v| match periodic {
+                true => Some(v.rem_euclid(n) as u32),
+                false => (0..n).contains(&v).then_some(v as u32),
+            })
+        };
+        for z in range(2, cz) {
+            for y in range(1, cy) {
+                for x in range(0, cx) {
+                    let cell = sim.cell_index([x, y, z]) as usize;
+                    let run = self.starts[cell] as usize..self.starts[cell + 1] as usize;
+                    self.order[run].iter().for_each(|&j| f(j as usize));
+                }
+            }
+        }
+    }
+}
+
+/// Reference CPU version of one solver step, mirroring the density, force and integrate compute passes
+/// for the fluid alone (no boundaries, obstacles or sinks). Every loop runs in parallel, but each
+/// particle gathers its own sums in a fixed neighbor order, so results do not depend on the thread count.
+pub fn step(particles: &mut [Particle], sim: &SimParams, kernel: &Kernel) {
+    let cells = CellList::build(particles, sim);
+    compute_density(particles, sim, kernel, &cells);
+    compute_forces(particles, sim, kernel, &cells);
+    integrate(particles, sim);
+}
+
+/// Runs `steps` solver steps and returns the wall-clock milliseconds each took on average.
+pub fn time_steps(particles: &mut [Particle], sim: &SimParams, kernel: &Kernel, steps: u32) -> f32 {
+    let start = Instant::now();
+    (0..steps).for_each(|_| step(particles, sim, kernel));
+    start.elapsed().as_secs_f32() * 1e3 / steps.max(1) as f32
+}
+
+/// Runs `steps` solver steps of `sim.dt` from `count` particles seeded with `seed`, twice over, and
+/// checks that the two runs end bit for bit the same. Every sum here runs in a fixed order, so the
+/// CPU solver is deterministic by construction; this guards against that changing.
+pub fn check_deterministic(sim: &SimParams, kernel: &Kernel, count: usize, seed: u32, steps: u32) -> Result<()> {
+    let run = || {
+        let mut particles = random_particles(sim, count, seed);
+        (0..steps).for_each(|_| step(&mut particles, sim, kernel));
+        particles
+    };
+    let (first, second) = (run(), run());
+    let bits = |p: &Particle| [p.pos.x, p.pos.y, p.pos.z, p.vel.x, p.vel.y, p.vel.z].map(f32::to_bits);
+    match first.iter().zip(&second).position(|(a, b)| bits(a) != bits(b)) {
+        Some(i) => Err(anyhow!("CPU solver runs differ at particle {}: {:?} and {:?}.", i, first[i].pos, second[i].pos)),
+        None => Ok(()),
+    }
+}
+
+/// Fluid the hydrostatic check puts around the measured columns and under them in place of walls, in
+/// particles; it must reach past `h`.
+const HYDROSTATIC_PADDING: u32 = 2;
+/// Density evaluations the hydrostatic check runs, enough for the free-surface correction to settle.
+const HYDROSTATIC_PASSES: u32 = 20;
+/// How much the equation of state squeezes the bottom of the hydrostatic check's column.
+const HYDROSTATIC_COMPRESSION: f32 = 0.05;
+
+/// Builds a still column of fluid `layers` particles deep, each layer squeezed to the density that holds
+/// up the fluid above it, with more of it around and below so that only the free surface cuts kernels
+/// off, and evaluates the densities `HYDROSTATIC_PASSES` times over so the free-surface correction (on
+/// if `sim.surface_threshold` is set) settles as it would in a resting fluid. Returns the RMS difference of the
+/// column's pressures from the analytic `rho0 |g| depth`, relative to the pressure at the bottom. The
+/// stiffness is picked to squeeze the bottom by `HYDROSTATIC_COMPRESSION`; the rest of `sim` is kept.
+pub fn hydrostatic_pressure_error(sim: &SimParams, kernel: &Kernel, layers: u32) -> f32 {
+    let spacing = SimParams::PARTICLE_SPACING;
+    let rest = sim.phases[0].x;
+    let weight = rest * glm::length(&sim.gravity.xyz());
+    let mut sim = *sim;
+    sim.stiffness = weight * layers as f32 * spacing / (HYDROSTATIC_COMPRESSION * rest);
+    // number density of a lattice with its layers `thickness` apart, which sets the particle mass so
+    // the unsqueezed lattice sits at rest density, and then how thin each layer must be
+    let lattice = |thickness: f32| {
+        let (reach, layers_reach) = ((sim.h / spacing) as i32, (sim.h / thickness) as i32);
+        let mut sum = 0.0;
+        for x in -reach..=reach {
+            for y in -layers_reach..=layers_reach {
+                for z in -reach..=reach {
+                    let (x, y, z) = (x as f32 * spacing, y as f32 * thickness, z as f32 * spacing);
+                    sum += kernel.w((x * x + y * y + z * z).sqrt());
+                }
+            }
+        }
+        sum
+    };
+    let mass = rest / lattice(spacing);
+    let squeezed = |depth: f32| {
+        let target = (rest + weight * depth / sim.stiffness) / mass;
+        let (mut thin, mut thick) = (spacing / 2.0, spacing);
+        for _ in 0..30 {
+            let mid = 0.5 * (thin + thick);
+            if lattice(mid) > target { thin = mid } else { thick = mid }
+        }
+        0.5 * (thin + thick)
+    };
+    // layer centers from the surface at y = 0 down
+    let mut depth = 0.0;
+    let heights = (0..layers + HYDROSTATIC_PADDING).map(|_| {
+        let thickness = squeezed(depth + squeezed(depth) / 2.0);
+        depth += thickness;
+        -(depth - thickness / 2.0)
+    }).collect::<Vec<_>>();
+    let columns = 2 + 2 * HYDROSTATIC_PADDING;
+    let width = columns as f32 * spacing;
+    sim.domain_min = glm::vec4(-width / 2.0, -depth - spacing, -width / 2.0, 0.0);
+    sim.domain_max = glm::vec4(width / 2.0, spacing, width / 2.0, 0.0);
+    sim.update_grid();
+    let mut particles = Vec::new();
+    let mut measured = Vec::new();
+    for (layer, &y) in heights.iter().enumerate() {
+        for x in 0..columns {
+            for z in 0..columns {
+                let inner = |c: u32| (HYDROSTATIC_PADDING..columns - HYDROSTATIC_PADDING).contains(&c);
+                if inner(x) && inner(z) && (layer as u32) < layers {
+                    measured.push(particles.len());
+                }
+                let pos = glm::vec3((x as f32 + 0.5) * spacing - width / 2.0, y, (z as f32 + 0.5) * spacing - width / 2.0);
+                particles.push(Particle::new(pos, mass));
+            }
+        }
+    }
+    for _ in 0..HYDROSTATIC_PASSES {
+        let cells = CellList::build(&particles, &sim);
+        compute_density(&mut particles, &sim, kernel, &cells);
+    }
+    let bottom = weight * -heights[layers as usize - 1];
+    let squares = measured.iter().map(|&i| (particles[i].pressure - weight * -particles[i].pos.y).powi(2)).sum::<f32>();
+    (squares / measured.len().max(1) as f32).sqrt() / bottom
+}
+
+/// Compares `hydrostatic_pressure_error` without the free-surface correction and with it at
+/// `threshold`, failing unless the correction at least halves the error; returns both errors.
+pub fn check_free_surface_correction(sim: &SimParams, kernel: &Kernel, layers: u32, threshold: f32) -> Result<[f32; 2]> {
+    let errors = [0.0, threshold].map(|t| hydrostatic_pressure_error(&SimParams { surface_threshold: t, ..*sim }, kernel, layers));
+    if errors[1] > 0.5 * errors[0] {
+        return Err(anyhow!("The free-surface correction leaves a hydrostatic pressure error of {:.2}%, against {:.2}% without it.",
+            100.0 * errors[1], 100.0 * errors[0]));
+    }
+    Ok(errors)
+}
+
+/// Adapted density summation (Solenthaler & Pajarola 2008): each particle's density is the particle
+/// mass of its phase times the number density `sum_j nu_j W`, so a light phase next to a heavy one is
+/// not overestimated. `nu_j`, a neighbor's mass over its phase's, weighs split and merged particles by
+/// the fluid they carry.
+///
+/// With `sim.surface_threshold` set, the densities near the free surface, where the kernel sticks out
+/// of the fluid and the sum comes out low, get a Shepard (zeroth-order) renormalization: a particle
+/// whose color field gradient `|sum_j V_j grad W|` passes the threshold over `h` is divided by
+/// `sum_j V_j W`, the share of its kernel the neighbors fill. The neighbors' volumes come from their
+/// densities of the step before, so the correction carries over between steps; the particle's own
+/// term takes its rest volume, without which it would drift to the density of the fluid below.
+/// `cells` must be built from the current positions.
+pub fn compute_density(particles: &mut [Particle], sim: &SimParams, kernel: &Kernel, cells: &CellList) {
+    let snapshot = particles.to_vec();
+    let correct = sim.surface_threshold > 0.0;
+    particles.par_iter_mut().enumerate().for_each(|(i, p)| {
+        let mut number_density = 0.0;
+        let mut gradient = glm::Vec3::zeros();
+        let mut filled = 0.0;
+        cells.for_each_near(sim, &p.pos, |j| {
+            let q = &snapshot[j];
+            let r = sim.separation(&p.pos, &q.pos);
+            let w = kernel.w(glm::length(&r));
+            number_density += mass_ratio(sim, q) * w;
+            if correct {
+                let density = if i == j || q.density <= 0.0 { phase(sim, q).x } else { q.density };
+                filled += q.mass / density * w;
+                gradient += kernel.grad(&r) * (q.mass / density);
+            }
+        });
+        p.density = phase(sim, p).z * number_density;
+        if correct && glm::length(&gradient) * sim.h > sim.surface_threshold {
+            p.density /= filled.max(1e-6);
+        }
+        p.pressure = equation_of_state(sim, p);
+    });
+}
+
+/// Particles along each side of the periodic advection check's box.
+const PERIODIC_SIDE: u32 = 8;
+
+/// Fills a box periodic along every axis with a lattice at rest density, all moving at `velocity`, and
+/// runs `steps` steps of `sim.dt` without gravity. With no walls and every particle seeing the same
+/// neighbors, the fluid should carry on as it started; this fails once a density strays from the rest
+/// density, or a velocity from `velocity`, by more than `tolerance` of it. Returns the largest density
+/// deviation seen.
+pub fn check_periodic_advection(sim: &SimParams, kernel: &Kernel, velocity: glm::Vec3, steps: u32, tolerance: f32)
+-> Result<f32> {
+    let spacing = SimParams::PARTICLE_SPACING;
+    let side = PERIODIC_SIDE as f32 * spacing;
+    let mut sim = *sim;
+    sim.gravity = glm::Vec4::zeros();
+    sim.surface_threshold = 0.0;
+    sim.domain_min = glm::vec4(-side / 2.0, -side / 2.0, -side / 2.0, 0.0);
+    sim.domain_max = glm::vec4(side / 2.0, side / 2.0, side / 2.0, 0.0);
+    sim.set_boundary_kinds([BoundaryKind::Periodic; 3])?;
+    // the number density of the infinite lattice sets the mass that puts it at rest density
+    let reach = (sim.h / spacing).ceil() as i32;
+    let mut lattice = 0.0;
+    for x in -reach..=reach {
+        for y in -reach..=reach {
+            for z in -reach..=reach {
+                lattice += kernel.w(spacing * ((x * x + y * y + z * z) as f32).sqrt());
+            }
+        }
+    }
+    let rest = sim.phases[0].x;
+    let mass = rest / lattice;
+    let n = PERIODIC_SIDE;
+    let mut particles = (0..n * n * n).map(|k| {
+        let pos = glm::vec3((k % n) as f32, (k / n % n) as f32, (k / n / n) as f32).add_scalar(0.5) * spacing;
+        Particle { vel: velocity, ..Particle::new(pos.add_scalar(-side / 2.0), mass) }
+    }).collect::<Vec<_>>();
+    let mut worst = 0.0f32;
+    for s in 1..=steps {
+        step(&mut particles, &sim, kernel);
+        let density = particles.iter().map(|p| (p.density - rest).abs() / rest).fold(0.0, f32::max);
+        let drift = particles.iter().map(|p| glm::length(&(p.vel - velocity))).fold(0.0, f32::max);
+        if density > tolerance || drift > tolerance * glm::length(&velocity).max(1e-3) {
+            return Err(anyhow!("Step {} of uniform flow through a periodic box: density off by {:.3}%, velocity by {:.3e} m/s.",
+                s, 100.0 * density, drift));
+        }
+        worst = worst.max(density);
+    }
+    Ok(worst)
+}
+
+/// Pressure from density, clamped so the fluid never pulls itself together.
+fn equation_of_state(sim: &SimParams, p: &Particle) -> f32 {
+    (sim.stiffness * (p.density - phase(sim, p).x)).max(0.0)
+}
+
+/// Pressure and viscosity force densities in the number-density form that matches `compute_density`.
+/// `cells` must be built from the current positions.
+pub fn compute_forces(particles: &mut [Particle], sim: &SimParams, kernel: &Kernel, cells: &CellList) {
+    let snapshot = particles.to_vec();
+    particles.par_iter_mut().enumerate().for_each(|(i, p)| {
+        let delta_i = p.density / phase(sim, p).z;
+        let mut pressure = glm::Vec3::zeros();
+        let mut viscosity = glm::Vec3::zeros();
+        cells.for_each_near(sim, &p.pos, |j| {
+            let q = &snapshot[j];
+            let r = sim.separation(&p.pos, &q.pos);
+            let dist = glm::length(&r);
+            if i == j || dist >= sim.h || dist <= 1e-6 {
+                return;
+            }
+            let (delta_j, nu_j) = (q.density / phase(sim, q).z, mass_ratio(sim, q));
+            pressure -= kernel.grad(&r) * (nu_j * (p.pressure / (delta_i * delta_i) + q.pressure / (delta_j * delta_j)));
+            let mu = 0.5 * (phase(sim, p).y + phase(sim, q).y);
+            viscosity += (q.vel - p.vel) * (mu * nu_j / delta_j * kernel.viscosity_laplacian(dist));
+        });
+        p.force = pressure * delta_i + viscosity + sim.gravity.xyz() * p.density;
+    });
+}
+
+/// `(rest_density, viscosity, mass, 0)` of the particle's phase.
+fn phase(sim: &SimParams, p: &Particle) -> glm::Vec4 {
+    sim.phases[(p.phase as usize).min(MAX_PHASES - 1)]
+}
+
+/// The particle's mass over the particle mass of its phase: 1 for a particle as emitted, less once
+/// split and more once merged.
+pub fn mass_ratio(sim: &SimParams, p: &Particle) -> f32 {
+    p.mass / phase(sim, p).z
+}
+
+/// Semi-implicit Euler with reflecting, damped domain walls, wrapping around periodic axes. The walls
+/// are met in the frame of the container, with the velocity relative to theirs.
+pub fn integrate(particles: &mut [Particle], sim: &SimParams) {
+    let rotation = sim.container_rotation();
+    particles.par_iter_mut().for_each(|p| {
+        p.vel += p.force / p.density.max(1e-6) * sim.dt;
+        p.pos += p.vel * sim.dt;
+        let mut q = sim.to_container(&p.pos);
+        let mut u = rotation.transpose() * (p.vel - sim.wall_velocity(&p.pos));
+        let mut hit = false;
+        for a in 0..3 {
+            if sim.boundary_kinds[a] == BoundaryKind::Periodic {
+                let extent = sim.domain_max[a] - sim.domain_min[a];
+                let wrapped = sim.domain_min[a] + (q[a] - sim.domain_min[a]).rem_euclid(extent);
+                hit |= wrapped != q[a];
+                q[a] = wrapped;
+            } else if q[a] < sim.domain_min[a] || q[a] > sim.domain_max[a] {
+                q[a] = q[a].clamp(sim.domain_min[a], sim.domain_max[a]);
+                u[a] *= -WALL_DAMPING;
+                hit = true;
+            }
+        }
+        if hit {
+            p.pos = sim.from_container(&q);
+            p.vel = rotation * u + sim.wall_velocity(&p.pos);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{HYDROSTATIC_LAYERS, MAX_TIMESTEP, PERIODIC_CHECK_STEPS, PERIODIC_CHECK_TOLERANCE,
+        PERIODIC_CHECK_VELOCITY, SURFACE_THRESHOLD};
+    use crate::scene::Scene;
+
+    /// The demo's parameters and kernel, stepped at the largest time step.
+    fn demo() -> (SimParams, Kernel) {
+        let scene = Scene::dam_break(6, 12, 6);
+        let sim = SimParams { dt: MAX_TIMESTEP, ..scene.sim() };
+        (sim, Kernel::new(scene.solver.kernel, sim.h))
+    }
+
+    #[test]
+    fn seeded_runs_agree_bit_for_bit() {
+        let (sim, kernel) = demo();
+        check_deterministic(&sim, &kernel, 300, 0x5eed, 20).unwrap();
+    }
+
+    #[test]
+    fn free_surface_correction_halves_hydrostatic_error() {
+        let (sim, kernel) = demo();
+        let [plain, corrected] = check_free_surface_correction(&sim, &kernel, HYDROSTATIC_LAYERS, SURFACE_THRESHOLD).unwrap();
+        assert!(corrected <= 0.5 * plain, "{} against {}", corrected, plain);
+    }
+
+    #[test]
+    fn uniform_flow_through_periodic_box_keeps_density() {
+        let (sim, kernel) = demo();
+        let worst = check_periodic_advection(&sim, &kernel, glm::make_vec3(&PERIODIC_CHECK_VELOCITY),
+            PERIODIC_CHECK_STEPS, PERIODIC_CHECK_TOLERANCE).unwrap();
+        assert!(worst <= PERIODIC_CHECK_TOLERANCE);
+    }
+}
@@ -0,0 +1,61 @@
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::config::*;
+use crate::reconstruct::FluidRendering;
+
+/// The window split down the middle into two views of the same scene, each seen through its own camera:
+/// the left one draws the fluid as `AppData::fluid_rendering` says, the right one as `right` does. The
+/// screen-space surface is composited over the whole window, so a view set to it draws particles
+/// instead; translucent particles are sorted for the left view's camera.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SplitScreen {
+    pub right: FluidRendering,
+}
+
+impl Default for SplitScreen {
+    fn default() -> Self {
+        Self { right: SPLIT_RENDERING }
+    }
+}
+
+impl SplitScreen {
+    /// The viewports of the left and right halves of a swapchain of `extent`.
+    pub fn viewports(&self, extent: vk::Extent2D) -> [vk::Viewport; 2] {
+        let half = (extent.width / 2) as f32;
+        let left = vk::Viewport { width: half, ..full_viewport(extent) };
+        let right = vk::Viewport { x: half, width: extent.width as f32 - half, ..full_viewport(extent) };
+        [left, right]
+    }
+
+    /// The half the window pixel column `x` falls in, 0 for the left and 1 for the right.
+    pub fn side_at(&self, extent: vk::Extent2D, x: f32) -> usize {
+        (x >= (extent.width / 2) as f32) as usize
+    }
+}
+
+/// The viewport covering the whole of a swapchain of `extent`.
+pub fn full_viewport(extent: vk::Extent2D) -> vk::Viewport {
+    vk::Viewport { x: 0.0, y: 0.0, width: extent.width as f32, height: extent.height as f32,
+        min_depth: 0.0, max_depth: 1.0 }
+}
+
+/// The size of `viewport` in pixels, which the projection of the view drawn into it is made for.
+pub fn viewport_extent(viewport: &vk::Viewport) -> vk::Extent2D {
+    vk::Extent2D { width: viewport.width as u32, height: viewport.height as u32 }
+}
+
+/// The scissor rectangle that keeps draws within `viewport`.
+pub fn viewport_scissor(viewport: &vk::Viewport) -> vk::Rect2D {
+    vk::Rect2D { offset: vk::Offset2D { x: viewport.x as i32, y: viewport.y as i32 }, extent: viewport_extent(viewport) }
+}
+
+/// Takes clip space of a view drawn into `viewport`, a full-height column of a swapchain of `extent`, to
+/// clip space of the whole window, for what projects into the window as a whole, like particle picking.
+pub fn to_window(viewport: &vk::Viewport, extent: vk::Extent2D) -> glm::Mat4 {
+    let width = extent.width as f32;
+    let mut transform = glm::Mat4::identity();
+    transform[(0, 0)] = viewport.width / width;
+    transform[(0, 3)] = (2.0 * viewport.x + viewport.width) / width - 1.0;
+    transform
+}
@@ -0,0 +1,312 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::colormap::colormap_descriptor_set;
+use crate::depth_sort::{DEPTH_SORT_HEADER, Translucency};
+use crate::camera::projection;
+use crate::cull::record_live_draw;
+use crate::config::{MAX_PHASES, PARTICLE_DEPTH_FRAGMENT_SHADER, PARTICLE_FAR_FRAGMENT_SHADER, PARTICLE_FRAGMENT_SHADER,
+    PARTICLE_VERTEX_SHADER, SELECTED_COLOR, SURFACE_TINT};
+use crate::oit::{oit_active, oit_blend_attachments, oit_specialization};
+use crate::simulation::{latest_particle_buffer, Particle, ParticleColoring};
+use crate::utils::{compile_shader_with, create_shader_module, MeshDepth};
+
+/// Push constants of the sprite pipeline, laid out like `SpriteConstants` in `particle.vert`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct SpriteConstants {
+    /// Colors of the phases, with their rest mass in w for the particles' radii to follow their mass.
+    phase_colors: [glm::Vec4; MAX_PHASES],
+    /// Colors of surface particles (w 1 to tint them) and of the selected particle, with the particles'
+    /// opacity in the latter's w.
+    surface_tint: glm::Vec4,
+    selected_color: glm::Vec4,
+    /// World-space radius times the pixels the viewport is high, which turns it into a point size.
+    pixel_radius: f32,
+    /// `Translucency::full_density`, 0 for opaque particles.
+    full_density: f32,
+    /// For the quads to span as many pixels as the points would.
+    viewport_height: f32,
+    /// Id of the selected particle, `u32::MAX` for none.
+    selected: u32,
+    /// `ParticleColoring::scalar`, and its values at the ends of the colormap.
+    scalar: u32,
+    _pad: u32,
+    scalar_range: [f32; 2],
+}
+
+/// Creates the pipelines that draw each particle of the latest buffer as a shaded disc: a point of the
+/// size its radius covers on screen, with a sphere's normal faked across it. The opaque one writes
+/// depth; the translucent one blends over what is behind without writing it, for sorted draws; and the
+/// depth pre-pass's variants write the opaque ones' depth alone, then shade them where it is theirs; and
+/// the transparency targets' one weighs the translucent ones into them, unsorted. Each is made again
+/// drawing a quad for each slot of the buffer in place of the point, for particles nearer than the
+/// device's largest point can draw.
+pub unsafe fn create_particle_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    // the scene's uniform buffer, the colormap, the solver's parameters for the point sizes and its
+    // particles and their count for the quads, and the colors and sizes as push constants
+    let set_layouts = &[data.descriptor_set_layout, data.colormap_set_layout, data.sim_params_set_layout,
+        data.compute_descriptor_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(size_of::<SpriteConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.particle_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    create_particle_variants(device, data, false)?;
+    create_particle_variants(device, data, true)
+}
+
+/// Creates the variants of `create_particle_pipeline` drawing points, or with `spheres` the quads.
+unsafe fn create_particle_variants(device: &Device, data: &mut AppData, spheres: bool) -> Result<()> {
+    let defines: &[&str] = if spheres { &["SPHERES"] } else { &[] };
+    let compile = |path: &str, kind| compile_shader_with(&path.to_string(), kind, defines);
+    let vshader = compile(PARTICLE_VERTEX_SHADER, shaderc::ShaderKind::Vertex)?;
+    let fshader = compile(PARTICLE_FRAGMENT_SHADER, shaderc::ShaderKind::Fragment)?;
+    let far_fshader = compile(PARTICLE_FAR_FRAGMENT_SHADER, shaderc::ShaderKind::Fragment)?;
+    let depth_fshader = compile(PARTICLE_DEPTH_FRAGMENT_SHADER, shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let far_frag_shader_module = create_shader_module(device, far_fshader.as_binary_u8())?;
+    let depth_frag_shader_module = create_shader_module(device, depth_fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+    let (entries, constants) = oit_specialization();
+    let specialization = vk::SpecializationInfo::builder().map_entries(&entries).data(&constants);
+    let oit_frag_stage = frag_stage.specialization_info(&specialization);
+    let far_frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(far_frag_shader_module)
+        .name(b"main\0");
+    let depth_frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(depth_frag_shader_module)
+        .name(b"main\0");
+
+    // the particles, and the color the neighbor pass highlights each in from the highlight buffer; the
+    // quads read the particles out of the solver's set, four corners of a strip to each
+    let highlight_binding = vk::VertexInputBindingDescription::builder()
+        .binding(1)
+        .stride(size_of::<u32>() as u32)
+        .input_rate(if spheres { vk::VertexInputRate::INSTANCE } else { vk::VertexInputRate::VERTEX })
+        .build();
+    let highlight = vk::VertexInputAttributeDescription::builder()
+        .binding(1).location(9).format(vk::Format::R8G8B8A8_UNORM).offset(0).build();
+    let (binding_descs, attribute_descs) = if spheres {
+        (vec![highlight_binding], vec![highlight])
+    } else {
+        (vec![Particle::binding_description(), highlight_binding],
+            [Particle::attribute_descriptions().as_slice(), &[highlight]].concat())
+    };
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&binding_descs)
+        .vertex_attribute_descriptions(&attribute_descs);
+    let topology = if spheres { vk::PrimitiveTopology::TRIANGLE_STRIP } else { vk::PrimitiveTopology::POINT_LIST };
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(topology)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    // opaque, translucent, and opaque and flat for the far particles; then the depth pre-pass's, near and
+    // far alike, and the opaque ones over it; then the translucent ones weighted
+    let variants = [(false, false, MeshDepth::Test), (true, false, MeshDepth::Test), (false, true, MeshDepth::Test),
+        (false, false, MeshDepth::Prepass), (false, false, MeshDepth::Equal), (false, true, MeshDepth::Equal),
+        (true, false, MeshDepth::Transparent)];
+    for (translucent, far, depth) in variants {
+        let fragment = match (depth, far) {
+            (MeshDepth::Prepass, _) => depth_frag_stage,
+            (MeshDepth::Transparent, _) => oit_frag_stage,
+            (_, true) => far_frag_stage,
+            (_, false) => frag_stage,
+        };
+        let stages = &[vert_stage, fragment];
+        let color_write_mask = match depth {
+            MeshDepth::Prepass => vk::ColorComponentFlags::empty(),
+            _ => vk::ColorComponentFlags::all(),
+        };
+        // opaque, the fragment shader discards the corners instead of blending them away
+        let attachment = vk::PipelineColorBlendAttachmentState::builder()
+            .color_write_mask(color_write_mask)
+            .blend_enable(translucent)
+            .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+            .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+            .alpha_blend_op(vk::BlendOp::ADD);
+        let attachments = match depth {
+            MeshDepth::Transparent => oit_blend_attachments().to_vec(),
+            _ => vec![attachment.build()],
+        };
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+            .logic_op_enable(false)
+            .logic_op(vk::LogicOp::COPY)
+            .attachments(&attachments)
+            .blend_constants([0.0, 0.0, 0.0, 0.0]);
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true).depth_write_enable(!translucent && depth != MeshDepth::Equal)
+            .depth_compare_op(if depth == MeshDepth::Equal { vk::CompareOp::EQUAL } else { vk::CompareOp::LESS })
+            .depth_bounds_test_enable(false)
+            .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+        let info = vk::GraphicsPipelineCreateInfo::builder()
+            .stages(stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_state)
+            .viewport_state(&viewport_state)
+            .dynamic_state(&dynamic_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample_state)
+            .depth_stencil_state(&depth_stencil_state)
+            .color_blend_state(&color_blend_state)
+            .layout(data.particle_pipeline_layout)
+            .render_pass(if depth == MeshDepth::Transparent { data.oit_render_pass } else { data.render_pass })
+            .subpass(0);
+        let pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+        let slot = match (translucent, far, depth) {
+            (_, _, MeshDepth::Transparent) => &mut data.oit_particle_pipeline,
+            (true, _, _) => &mut data.translucent_particle_pipeline,
+            (_, _, MeshDepth::Prepass) => &mut data.prepass_particle_pipeline,
+            (_, true, MeshDepth::Equal) => &mut data.equal_far_particle_pipeline,
+            (_, false, MeshDepth::Equal) => &mut data.equal_particle_pipeline,
+            (_, true, MeshDepth::Test) => &mut data.far_particle_pipeline,
+            (_, false, MeshDepth::Test) => &mut data.particle_pipeline,
+        };
+        slot[spheres as usize] = pipeline;
+    }
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    device.destroy_shader_module(far_frag_shader_module, None);
+    device.destroy_shader_module(depth_frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_particle_pipeline(device: &Device, data: &AppData) {
+    let pipelines = [data.particle_pipeline, data.translucent_particle_pipeline, data.far_particle_pipeline,
+        data.prepass_particle_pipeline, data.equal_particle_pipeline, data.equal_far_particle_pipeline,
+        data.oit_particle_pipeline];
+    pipelines.iter().flatten().for_each(|&pipeline| device.destroy_pipeline(pipeline, None));
+    device.destroy_pipeline_layout(data.particle_pipeline_layout, None);
+}
+
+/// The diameter, in pixels, the sprites ask for a particle of its phase's rest mass `depth` meters in
+/// front of the camera, before particle.vert clamps it to `SimParams::point_size`.
+pub fn point_size_at(data: &AppData, depth: f32) -> f32 {
+    let focal = projection(data.swapchain_extent)[(1, 1)].abs();
+    data.particle_radius * data.radius_scale * data.swapchain_extent.height as f32 * focal / depth
+}
+
+/// Whether the particles are drawn translucent, sorted back to front, and how; opaque until the sort has
+/// somewhere to put the indices.
+fn sorted_translucency(data: &AppData) -> Option<Translucency> {
+    data.translucency.filter(|_| data.depth_sort_buffer != vk::Buffer::null())
+}
+
+/// Records the draw of the live particles into swapchain image `i`'s render pass, as many as the
+/// counter buffer says the last step left, or those the cull left; translucent ones back to front, as
+/// the depth sort left them, unless drawn as quads, which come in the order they are in.
+pub unsafe fn record_particle_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    if weighted(data) {
+        return;
+    }
+    let sorted = sorted_translucency(data);
+    let k = data.particle_spheres as usize;
+    let (pipeline, far_pipeline) = match (sorted, data.depth_prepass) {
+        (Some(_), _) => (data.translucent_particle_pipeline[k], data.far_particle_pipeline[k]),
+        (None, true) => (data.equal_particle_pipeline[k], data.equal_far_particle_pipeline[k]),
+        (None, false) => (data.particle_pipeline[k], data.far_particle_pipeline[k]),
+    };
+    bind_particles(device, data, command_buffer, i, pipeline, sorted);
+    if sorted.is_some() && !data.particle_spheres {
+        // the sort buffer starts with the sorted count as a VkDrawIndexedIndirectCommand, then the indices
+        device.cmd_bind_index_buffer(command_buffer, data.depth_sort_buffer, DEPTH_SORT_HEADER, vk::IndexType::UINT32);
+        device.cmd_draw_indexed_indirect(command_buffer, data.depth_sort_buffer, 0, 1,
+            size_of::<vk::DrawIndexedIndirectCommand>() as u32);
+    } else {
+        record_particles(device, data, command_buffer, Some(far_pipeline));
+    }
+}
+
+/// Records the draw of the live particles, through the cull's indices if they are culled; or of a quad
+/// for each slot of the particle buffer, those past the live count dropped by the vertex shader.
+unsafe fn record_particles(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    far_pipeline: Option<vk::Pipeline>) {
+    if data.particle_spheres {
+        device.cmd_draw(command_buffer, 4, data.particle_capacity, 0, 0);
+    } else {
+        record_live_draw(device, data, command_buffer, far_pipeline);
+    }
+}
+
+/// Records the depth pre-pass's draw of the live particles, near and far alike, unless they are
+/// translucent and leave the depth to what is behind them.
+pub unsafe fn record_particle_prepass(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    if sorted_translucency(data).is_none() && !weighted(data) {
+        let pipeline = data.prepass_particle_pipeline[data.particle_spheres as usize];
+        bind_particles(device, data, command_buffer, i, pipeline, None);
+        record_particles(device, data, command_buffer, None);
+    }
+}
+
+/// Whether the translucent particles are weighted into the transparency targets instead of drawn with
+/// the opaque scene.
+fn weighted(data: &AppData) -> bool {
+    data.translucency.is_some() && oit_active(data)
+}
+
+/// Records the translucent particles weighted into the transparency targets, near and far alike, in
+/// the order they are in.
+pub unsafe fn record_particle_oit(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    let pipeline = data.oit_particle_pipeline[data.particle_spheres as usize];
+    bind_particles(device, data, command_buffer, i, pipeline, data.translucency);
+    record_particles(device, data, command_buffer, None);
+}
+
+/// Binds `pipeline` with the sets, buffers and constants the particle draws share.
+unsafe fn bind_particles(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize,
+    pipeline: vk::Pipeline, sorted: Option<Translucency>) {
+    let [r, g, b] = SURFACE_TINT;
+    let tint = data.particle_coloring == ParticleColoring::Surface;
+    let [sr, sg, sb] = SELECTED_COLOR;
+    let constants = SpriteConstants {
+        phase_colors: std::array::from_fn(|k| {
+            let color = data.phase_colors[k];
+            glm::vec4(color.x, color.y, color.z, data.phase_masses[k])
+        }),
+        surface_tint: glm::vec4(r, g, b, tint as u32 as f32),
+        selected_color: glm::vec4(sr, sg, sb, sorted.map_or(1.0, |t| t.opacity)),
+        pixel_radius: data.particle_radius * data.radius_scale * data.swapchain_extent.height as f32,
+        full_density: sorted.map_or(0.0, |t| t.full_density),
+        viewport_height: data.swapchain_extent.height as f32,
+        selected: data.selected_id.unwrap_or(u32::MAX),
+        scalar: data.particle_coloring.scalar(),
+        _pad: 0,
+        scalar_range: data.color_range,
+    };
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+    let sets = [data.descriptor_sets[i], colormap_descriptor_set(data), data.sim_params_sets[data.sim_params_frame],
+        data.compute_descriptor_sets[data.particle_parity]];
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.particle_pipeline_layout, 0, &sets, &[]);
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[latest_particle_buffer(data), data.highlight_buffer], &[0, 0]);
+    let bytes = std::slice::from_raw_parts((&constants as *const SpriteConstants).cast::<u8>(),
+        size_of::<SpriteConstants>());
+    device.cmd_push_constants(command_buffer, data.particle_pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, bytes);
+}
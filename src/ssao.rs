@@ -0,0 +1,421 @@
+use std::mem::size_of;
+use std::ptr::copy_nonoverlapping as memcpy;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::boundary::XorShift;
+use crate::camera::UniformBufferObject;
+use crate::config::*;
+use crate::model::Vertex;
+use crate::screen_space::create_fluid_render_pass;
+use crate::split::{full_viewport, viewport_scissor};
+use crate::utils::{MeshPrimitive, begin_single_time_commands, compile_shader, create_buffer, create_image, create_image_view,
+    create_shader_module, create_texture, end_single_time_commands, get_depth_format, record_object_block, view_draws,
+    scene_views, upload_texture};
+
+/// Screen-space ambient occlusion (Crytek's, with Chapman's normal-oriented hemisphere): the meshes and
+/// the reconstructed fluid surface are drawn once more into an image of their view-space normals and
+/// depth, around each pixel of which samples of a hemisphere kernel, turned by a tiled noise texture,
+/// are tested against the depth drawn there. The share of them hidden, blurred over the noise's tile,
+/// darkens the ambient light the meshes are shaded with; the particles are left out.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ssao {
+    /// View-space radius of the hemisphere.
+    pub radius: f32,
+    /// Samples of the kernel taken, up to `SSAO_MAX_SAMPLES`.
+    pub samples: u32,
+    /// The exponent the unoccluded share is raised to.
+    pub intensity: f32,
+}
+
+impl Default for Ssao {
+    fn default() -> Self {
+        Self { radius: SSAO_RADIUS, samples: SSAO_SAMPLES, intensity: SSAO_INTENSITY }
+    }
+}
+
+/// Push constants of the occlusion pass, laid out like `Ssao` in `ssao.frag`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct SsaoConstants {
+    /// The view's viewport, x, y, width and height in pixels.
+    viewport: [f32; 4],
+    radius: f32,
+    intensity: f32,
+    samples: u32,
+}
+
+/// View-space normals in rgb and the distance in front of the camera in a, 0 where nothing is drawn.
+const NORMAL_DEPTH_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const OCCLUSION_FORMAT: vk::Format = vk::Format::R8_UNORM;
+/// Texels along each side of the noise texture, and so of the blur undoing its pattern.
+const NOISE_SIZE: u32 = 4;
+
+/// Creates the ambient occlusion's kernel and noise and the passes drawing it. Call after
+/// `create_pipeline` and `create_uniform_buffers`, since the normals are drawn through the mesh layout
+/// and the occlusion reads the cameras.
+pub unsafe fn create_ssao(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let mut rng = XorShift(0x5a0);
+    // a random rotation about the normal per texel, tiled over the window
+    let noise = (0..NOISE_SIZE * NOISE_SIZE)
+        .map(|_| [rng.next() * 2.0 - 1.0, rng.next() * 2.0 - 1.0, 0.0, 0.0]).collect::<Vec<[f32; 4]>>();
+    let dims = [NOISE_SIZE, NOISE_SIZE, 1];
+    let format = vk::Format::R32G32B32A32_SFLOAT;
+    (data.ssao_noise_image, data.ssao_noise_image_memory, data.ssao_noise_image_view) = create_texture(instance, device,
+        data, vk::ImageViewType::_2D, dims, format, 1, vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)?;
+    upload_texture(instance, device, data, data.ssao_noise_image, format, dims, 1, 1, &noise)?;
+
+    // points in the hemisphere about +z, crowded toward its center; their lengths follow the bit-reversed
+    // sequence, so however few are taken they reach through the whole radius
+    let kernel = (0..SSAO_MAX_SAMPLES).map(|k| {
+        let direction = glm::normalize(&glm::vec3(rng.next() * 2.0 - 1.0, rng.next() * 2.0 - 1.0, rng.next().max(0.05)));
+        let t = (k as u32).reverse_bits() as f32 / 2f32.powi(32);
+        let length = glm::lerp_scalar(0.1, 1.0, t * t);
+        glm::vec4(direction.x, direction.y, direction.z, 0.0) * length
+    }).collect::<Vec<_>>();
+    let size = (size_of::<glm::Vec4>() * SSAO_MAX_SAMPLES) as u64;
+    (data.ssao_kernel_buffer, data.ssao_kernel_buffer_memory) = create_buffer(instance, device, data, size,
+        vk::BufferUsageFlags::UNIFORM_BUFFER, vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE)?;
+    let memory = device.map_memory(data.ssao_kernel_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    memcpy(kernel.as_ptr(), memory.cast(), kernel.len());
+    device.unmap_memory(data.ssao_kernel_buffer_memory);
+
+    let sampler = |address_mode: vk::SamplerAddressMode| {
+        let info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::NEAREST).min_filter(vk::Filter::NEAREST)
+            .address_mode_u(address_mode).address_mode_v(address_mode).address_mode_w(address_mode)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+        device.create_sampler(&info, None)
+    };
+    data.ssao_sampler = sampler(vk::SamplerAddressMode::CLAMP_TO_EDGE)?;
+    data.ssao_noise_sampler = sampler(vk::SamplerAddressMode::REPEAT)?;
+
+    use vk::AttachmentLoadOp as Load;
+    let z_format = get_depth_format(instance, data)?;
+    let read = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+    data.ssao_normal_render_pass = create_fluid_render_pass(device, NORMAL_DEPTH_FORMAT, Load::CLEAR, read,
+        Some((z_format, Load::CLEAR)))?;
+    // cleared unoccluded, for what no view covers
+    data.ssao_render_pass = create_fluid_render_pass(device, OCCLUSION_FORMAT, Load::CLEAR, read, None)?;
+    create_ssao_descriptor_sets(device, data)?;
+    create_ssao_pipelines(device, data)
+}
+
+/// Creates the ambient occlusion's targets for the current swapchain, binds them to its sets and the
+/// meshes', and leaves the blurred occlusion unoccluded until first drawn. Call after `create_ssao` and
+/// `create_descriptor_sets`.
+pub unsafe fn create_ssao_targets(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let (width, height) = (data.swapchain_extent.width, data.swapchain_extent.height);
+    let target = |format: vk::Format, usage: vk::ImageUsageFlags, aspects: vk::ImageAspectFlags|
+    -> Result<(vk::Image, vk::DeviceMemory, vk::ImageView)> {
+        let (image, memory) = create_image(instance, device, data, width, height, format, vk::ImageTiling::OPTIMAL,
+            usage, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+        Ok((image, memory, create_image_view(device, image, format, aspects)?))
+    };
+    let color = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+    let z_format = get_depth_format(instance, data)?;
+    let normals = target(NORMAL_DEPTH_FORMAT, color, vk::ImageAspectFlags::COLOR)?;
+    let depth = target(z_format, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, vk::ImageAspectFlags::DEPTH)?;
+    // the occlusion as sampled, then blurred
+    let occlusion = (0..2).map(|_| target(OCCLUSION_FORMAT, color, vk::ImageAspectFlags::COLOR))
+        .collect::<Result<Vec<_>>>()?;
+    (data.ssao_normal_image, data.ssao_normal_image_memory, data.ssao_normal_image_view) = normals;
+    (data.ssao_depth_image, data.ssao_depth_image_memory, data.ssao_depth_image_view) = depth;
+    data.ssao_images = occlusion.iter().map(|t| t.0).collect();
+    data.ssao_images_memory = occlusion.iter().map(|t| t.1).collect();
+    data.ssao_image_views = occlusion.iter().map(|t| t.2).collect();
+
+    let framebuffer = |render_pass: vk::RenderPass, attachments: &[vk::ImageView]| {
+        let info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass).attachments(attachments).width(width).height(height).layers(1);
+        device.create_framebuffer(&info, None)
+    };
+    data.ssao_normal_framebuffer = framebuffer(data.ssao_normal_render_pass,
+        &[data.ssao_normal_image_view, data.ssao_depth_image_view])?;
+    data.ssao_framebuffers = data.ssao_image_views.iter()
+        .map(|v| framebuffer(data.ssao_render_pass, &[*v]))
+        .collect::<Result<Vec<_>, _>>()?;
+    write_ssao_targets(device, data);
+    write_ambient_occlusion(device, data);
+
+    let command_buffer = begin_single_time_commands(device, data)?;
+    for framebuffer in &data.ssao_framebuffers {
+        begin_occlusion_pass(device, data, command_buffer, *framebuffer);
+        device.cmd_end_render_pass(command_buffer);
+    }
+    end_single_time_commands(device, data, command_buffer)
+}
+
+pub unsafe fn destroy_ssao(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.ssao_normal_pipeline, None);
+    device.destroy_pipeline(data.ssao_pipeline, None);
+    device.destroy_pipeline(data.ssao_blur_pipeline, None);
+    device.destroy_pipeline_layout(data.ssao_pipeline_layout, None);
+    device.destroy_descriptor_pool(data.ssao_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(data.ssao_set_layout, None);
+    device.destroy_render_pass(data.ssao_normal_render_pass, None);
+    device.destroy_render_pass(data.ssao_render_pass, None);
+    device.destroy_sampler(data.ssao_sampler, None);
+    device.destroy_sampler(data.ssao_noise_sampler, None);
+    device.destroy_buffer(data.ssao_kernel_buffer, None);
+    device.free_memory(data.ssao_kernel_buffer_memory, None);
+    device.destroy_image_view(data.ssao_noise_image_view, None);
+    device.destroy_image(data.ssao_noise_image, None);
+    device.free_memory(data.ssao_noise_image_memory, None);
+}
+
+pub unsafe fn destroy_ssao_targets(device: &Device, data: &AppData) {
+    device.destroy_framebuffer(data.ssao_normal_framebuffer, None);
+    data.ssao_framebuffers.iter().for_each(|f| device.destroy_framebuffer(*f, None));
+    let views = data.ssao_image_views.iter().chain([&data.ssao_normal_image_view, &data.ssao_depth_image_view]);
+    views.for_each(|v| device.destroy_image_view(*v, None));
+    let images = data.ssao_images.iter().chain([&data.ssao_normal_image, &data.ssao_depth_image]);
+    images.for_each(|i| device.destroy_image(*i, None));
+    let memory = data.ssao_images_memory.iter().chain([&data.ssao_normal_image_memory, &data.ssao_depth_image_memory]);
+    memory.for_each(|m| device.free_memory(*m, None));
+}
+
+/// A set per uniform buffer for the occlusion pass, reading its camera, the normals and depth, the noise
+/// and the kernel, then one for the blur, reading the occlusion.
+unsafe fn create_ssao_descriptor_sets(device: &Device, data: &mut AppData) -> Result<()> {
+    let binding = |binding: u32, descriptor_type: vk::DescriptorType| vk::DescriptorSetLayoutBinding::builder()
+        .binding(binding)
+        .descriptor_type(descriptor_type)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .build();
+    let bindings = &[binding(0, vk::DescriptorType::UNIFORM_BUFFER), binding(1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        binding(2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER), binding(3, vk::DescriptorType::UNIFORM_BUFFER)];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+    data.ssao_set_layout = device.create_descriptor_set_layout(&info, None)?;
+
+    let count = data.uniform_buffers.len() as u32 + 1;
+    let ubo_size = vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::UNIFORM_BUFFER).descriptor_count(2 * count);
+    let sampler_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).descriptor_count(2 * count);
+    let pool_sizes = &[ubo_size, sampler_size];
+    let info = vk::DescriptorPoolCreateInfo::builder().pool_sizes(pool_sizes).max_sets(count);
+    data.ssao_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    let layouts = vec![data.ssao_set_layout; count as usize];
+    let info = vk::DescriptorSetAllocateInfo::builder().descriptor_pool(data.ssao_descriptor_pool).set_layouts(&layouts);
+    data.ssao_descriptor_sets = device.allocate_descriptor_sets(&info)?;
+
+    let image_info = |sampler: vk::Sampler, view: vk::ImageView| [vk::DescriptorImageInfo::builder()
+        .sampler(sampler).image_view(view).image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL).build()];
+    let noise = image_info(data.ssao_noise_sampler, data.ssao_noise_image_view);
+    let kernel = [vk::DescriptorBufferInfo::builder()
+        .buffer(data.ssao_kernel_buffer).offset(0).range(vk::WHOLE_SIZE as u64).build()];
+    let cameras = data.uniform_buffers.iter().map(|buffer| [vk::DescriptorBufferInfo::builder()
+        .buffer(*buffer).offset(0).range(size_of::<UniformBufferObject>() as u64).build()]).collect::<Vec<_>>();
+    let mut writes = Vec::new();
+    for (set, camera) in data.ssao_descriptor_sets.iter().zip(&cameras) {
+        let write = |binding: u32| vk::WriteDescriptorSet::builder().dst_set(*set).dst_binding(binding).dst_array_element(0);
+        writes.push(write(0).descriptor_type(vk::DescriptorType::UNIFORM_BUFFER).buffer_info(camera).build());
+        writes.push(write(2).descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(&noise).build());
+        writes.push(write(3).descriptor_type(vk::DescriptorType::UNIFORM_BUFFER).buffer_info(&kernel).build());
+    }
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+    Ok(())
+}
+
+/// Binds the normals and depth to the occlusion pass's sets and the occlusion to the blur's.
+unsafe fn write_ssao_targets(device: &Device, data: &AppData) {
+    let image_info = |view: vk::ImageView| [vk::DescriptorImageInfo::builder()
+        .sampler(data.ssao_sampler).image_view(view).image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL).build()];
+    let normals = image_info(data.ssao_normal_image_view);
+    let occlusion = image_info(data.ssao_image_views[0]);
+    let (blur_set, occlusion_sets) = data.ssao_descriptor_sets.split_last().unwrap();
+    let writes = occlusion_sets.iter().map(|set| (set, &normals)).chain([(blur_set, &occlusion)])
+        .map(|(set, image_info)| vk::WriteDescriptorSet::builder()
+            .dst_set(*set).dst_binding(1).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info).build())
+        .collect::<Vec<_>>();
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+}
+
+/// Binds the blurred occlusion to the meshes' sets.
+pub unsafe fn write_ambient_occlusion(device: &Device, data: &AppData) {
+    let image_info = &[vk::DescriptorImageInfo::builder()
+        .sampler(data.ssao_sampler).image_view(data.ssao_image_views[1])
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+    let writes = data.descriptor_sets.iter().map(|set| vk::WriteDescriptorSet::builder()
+        .dst_set(*set).dst_binding(6).dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info).build())
+        .collect::<Vec<_>>();
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+}
+
+unsafe fn create_ssao_pipelines(device: &Device, data: &mut AppData) -> Result<()> {
+    let set_layouts = &[data.ssao_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(size_of::<SsaoConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.ssao_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    data.ssao_normal_pipeline = create_ssao_pipeline(device, data, &data.vshader_path, SSAO_NORMAL_SHADER, true)?;
+    data.ssao_pipeline = create_ssao_pipeline(device, data, FULLSCREEN_VERTEX_SHADER, SSAO_SHADER, false)?;
+    data.ssao_blur_pipeline = create_ssao_pipeline(device, data, FULLSCREEN_VERTEX_SHADER, SSAO_BLUR_SHADER, false)?;
+    Ok(())
+}
+
+/// Draws the meshes through the mesh layout into the normals and depth if `meshes`, otherwise a full-screen
+/// triangle into the occlusion.
+unsafe fn create_ssao_pipeline(device: &Device, data: &AppData, vertex_shader: &str, fragment_shader: &str, meshes: bool)
+-> Result<vk::Pipeline> {
+    let vshader = compile_shader(&vertex_shader.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&fragment_shader.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let binding_descs = &[Vertex::binding_description()];
+    let attribute_descs = &Vertex::attribute_descriptions();
+    let mut vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    if meshes {
+        vertex_input_state = vertex_input_state
+            .vertex_binding_descriptions(binding_descs)
+            .vertex_attribute_descriptions(attribute_descs);
+    }
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+    // the viewport and scissor are set to each view as it is drawn
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(if meshes { vk::CullModeFlags::BACK } else { vk::CullModeFlags::NONE })
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlags::_1);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(false);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(meshes).depth_write_enable(meshes)
+        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+    let (layout, render_pass) = if meshes {
+        (data.pipeline_layout, data.ssao_normal_render_pass)
+    } else {
+        (data.ssao_pipeline_layout, data.ssao_render_pass)
+    };
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .dynamic_state(&dynamic_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(layout)
+        .render_pass(render_pass)
+        .subpass(0);
+    let pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(pipeline)
+}
+
+/// Begins an occlusion pass into `framebuffer`, cleared unoccluded.
+unsafe fn begin_occlusion_pass(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    framebuffer: vk::Framebuffer) {
+    let clear_values = &[vk::ClearValue { color: vk::ClearColorValue { float32: [1.0; 4] } }];
+    let info = vk::RenderPassBeginInfo::builder()
+        .render_pass(data.ssao_render_pass)
+        .framebuffer(framebuffer)
+        .render_area(vk::Rect2D::builder().offset(vk::Offset2D::default()).extent(data.swapchain_extent))
+        .clear_values(clear_values);
+    device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+}
+
+/// Records the ambient occlusion of swapchain image `i`'s views, before the main pass shades with it: their
+/// meshes' normals and depth, the occlusion of each and its blur. Without it, the blurred occlusion is only
+/// cleared, so nothing is darkened.
+pub unsafe fn record_ssao(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    let Some(ssao) = &data.ssao else {
+        begin_occlusion_pass(device, data, command_buffer, data.ssao_framebuffers[1]);
+        device.cmd_end_render_pass(command_buffer);
+        return;
+    };
+    let image = i % data.swapchain_images.len();
+    let views = scene_views(data, i);
+
+    let clear_values = &[vk::ClearValue { color: vk::ClearColorValue { float32: [0.0; 4] } },
+        vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } }];
+    let info = vk::RenderPassBeginInfo::builder()
+        .render_pass(data.ssao_normal_render_pass)
+        .framebuffer(data.ssao_normal_framebuffer)
+        .render_area(vk::Rect2D::builder().offset(vk::Offset2D::default()).extent(data.swapchain_extent))
+        .clear_values(clear_values);
+    device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.ssao_normal_pipeline);
+    for (ubo, viewport, rendering) in &views {
+        device.cmd_set_viewport(command_buffer, 0, &[*viewport]);
+        device.cmd_set_scissor(command_buffer, 0, &[viewport_scissor(viewport)]);
+        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+            data.pipeline_layout, 0, &[data.descriptor_sets[*ubo]], &[]);
+        // every filled mesh occludes, even those drawn as wireframes or unlit
+        for (_, obj) in view_draws(data, *rendering).into_iter().filter(|(key, _)| key.primitive != MeshPrimitive::Lines) {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[obj.vertex_buffer], &[0]);
+            device.cmd_bind_index_buffer(command_buffer, obj.index_buffer, 0, vk::IndexType::UINT32);
+            for (submesh, offset) in obj.draws().iter().zip(&obj.block_offsets) {
+                record_object_block(device, data, command_buffer, image, *offset);
+                device.cmd_draw_indexed(command_buffer, submesh.index_count, 1, obj.base_index + submesh.first_index,
+                    obj.base_vertex, 0);
+            }
+        }
+    }
+    device.cmd_end_render_pass(command_buffer);
+
+    begin_occlusion_pass(device, data, command_buffer, data.ssao_framebuffers[0]);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.ssao_pipeline);
+    for (ubo, viewport, _) in &views {
+        device.cmd_set_viewport(command_buffer, 0, &[*viewport]);
+        device.cmd_set_scissor(command_buffer, 0, &[viewport_scissor(viewport)]);
+        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+            data.ssao_pipeline_layout, 0, &[data.ssao_descriptor_sets[*ubo]], &[]);
+        let constants = SsaoConstants { viewport: [viewport.x, viewport.y, viewport.width, viewport.height],
+            radius: ssao.radius, intensity: ssao.intensity, samples: ssao.samples.min(SSAO_MAX_SAMPLES as u32) };
+        let bytes = std::slice::from_raw_parts((&constants as *const SsaoConstants).cast::<u8>(),
+            size_of::<SsaoConstants>());
+        device.cmd_push_constants(command_buffer, data.ssao_pipeline_layout, vk::ShaderStageFlags::FRAGMENT, 0, bytes);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    }
+    device.cmd_end_render_pass(command_buffer);
+
+    // the blur over the whole window at once
+    let blur_set = *data.ssao_descriptor_sets.last().unwrap();
+    let viewport = full_viewport(data.swapchain_extent);
+    begin_occlusion_pass(device, data, command_buffer, data.ssao_framebuffers[1]);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.ssao_blur_pipeline);
+    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+    device.cmd_set_scissor(command_buffer, 0, &[viewport_scissor(&viewport)]);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.ssao_pipeline_layout, 0, &[blur_set], &[]);
+    device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    device.cmd_end_render_pass(command_buffer);
+}
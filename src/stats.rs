@@ -0,0 +1,76 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use anyhow::Result;
+
+use crate::diagnostics::Diagnostics;
+use crate::timing::FrameTimings;
+
+/// Columns of the statistics file, in the order `StatsWriter::write` fills them.
+const HEADER: &str = "frame,time,dt,particles,kinetic_energy,max_speed,mean_density_error,max_density_error,\
+    max_divergence,mean_divergence,substeps,solver_iterations,step_ms,grid_ms,density_ms,force_ms,integrate_ms,render_ms,\
+    visible_objects,objects";
+/// Rows between flushes, so a crash loses little and the file can be watched while the app runs.
+const FLUSH_ROWS: u32 = 60;
+
+/// What one stepped frame did, as the statistics file records it.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FrameStats {
+    /// Frames stepped since the last reset, this one included.
+    pub frame: u64,
+    /// Simulated seconds at the end of the frame.
+    pub time: f64,
+    pub dt: f32,
+    pub substeps: u32,
+    /// Pressure solver iterations per substep, `PRESSURE_ITERATIONS` for the weakly compressible solver.
+    pub solver_iterations: u32,
+    /// Live particles at the end of the frame.
+    pub particles: u32,
+    /// Objects the frame's cull left to draw, of `objects`.
+    pub visible_objects: u32,
+    pub objects: u32,
+    pub diagnostics: Diagnostics,
+    pub timings: FrameTimings,
+}
+
+/// Appends a CSV row of `FrameStats` per stepped frame to a file, after a header row.
+#[derive(Debug)]
+pub struct StatsWriter {
+    writer: BufWriter<File>,
+    unflushed: u32,
+    rows: u64,
+}
+
+impl StatsWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HEADER)?;
+        Ok(Self { writer, unflushed: 0, rows: 0 })
+    }
+
+    pub fn write(&mut self, stats: &FrameStats) -> Result<()> {
+        let (d, t) = (&stats.diagnostics, &stats.timings);
+        writeln!(self.writer, "{},{:.6},{:.6e},{},{:.6e},{:.6e},{:.6e},{:.6e},{:.6e},{:.6e},{},{},{:.4},{:.4},{:.4},{:.4},\
+            {:.4},{:.4},{},{}", stats.frame, stats.time, stats.dt, stats.particles, d.kinetic_energy, d.max_speed,
+            d.mean_density_error, d.max_density_error, d.max_divergence, d.mean_divergence, stats.substeps, stats.solver_iterations, t.step,
+            t.grid_build, t.density, t.force, t.integrate, t.render,
+            stats.visible_objects, stats.objects)?;
+        self.rows += 1;
+        self.unflushed += 1;
+        if self.unflushed >= FLUSH_ROWS {
+            self.writer.flush()?;
+            self.unflushed = 0;
+        }
+        Ok(())
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// Flushes what is left and closes the file.
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use std::path::Path;
+use anyhow::{Context, Result};
+use vulkanalia::prelude::v1_0::*;
+use log::*;
+
+use crate::appdata::AppData;
+use crate::config::NORMAL_MAP_SLOTS;
+use crate::utils::{create_texture, mip_level_count, mipmaps_supported, upload_texture};
+
+/// Color textures store sRGB-encoded texels, which sampling decodes into the linear light the shaders
+/// work in; normal maps store vectors, taken as they are.
+pub const COLOR_TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+pub const NORMAL_MAP_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// The texture the meshes with texture coordinates are drawn with, repeated past the edges and
+/// filtered trilinearly through a full mip chain, where the device can blit one. The normal maps are
+/// loaded the same way, in their own format.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MeshTexture {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+}
+
+impl MeshTexture {
+    /// Loads the PNG or JPEG at `path` into a texture of `format`, or makes a single white texel that
+    /// leaves the meshes as they are with `None`; call after `create_command_pool`.
+    pub unsafe fn load(instance: &Instance, device: &Device, data: &AppData, path: Option<&str>, format: vk::Format)
+    -> Result<Self> {
+        match path {
+            Some(path) => {
+                let image = image::open(path).with_context(|| format!("Cannot load the texture {}.", path))?
+                    .to_rgba8();
+                Self::from_texels(instance, device, data, [image.width(), image.height(), 1], image.as_raw(), format)
+            }
+            None => Self::from_texels(instance, device, data, [1, 1, 1], &[255; 4], format),
+        }
+    }
+
+    /// A texture of `format` of the RGBA `texels` of an image `dims` in size, row by row.
+    pub unsafe fn from_texels(instance: &Instance, device: &Device, data: &AppData, dims: [u32; 3], texels: &[u8],
+        format: vk::Format) -> Result<Self> {
+        let mip_levels = if mipmaps_supported(instance, data, format) { mip_level_count(dims) } else { 1 };
+        let (image, memory, view) = create_texture(instance, device, data, vk::ImageViewType::_2D, dims, format, mip_levels,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::TRANSFER_SRC)?;
+        upload_texture(instance, device, data, image, format, dims, 1, mip_levels, texels)?;
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR).min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .min_lod(0.0).max_lod(mip_levels as f32);
+        let sampler = device.create_sampler(&sampler_info, None)?;
+        Ok(Self { image, memory, view, sampler })
+    }
+
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_sampler(self.sampler, None);
+        device.destroy_image_view(self.view, None);
+        device.destroy_image(self.image, None);
+        device.free_memory(self.memory, None);
+    }
+}
+
+/// The single texel of a normal map pointing straight out of the surface, in the slots of
+/// `AppData::normal_maps` no material fills.
+pub unsafe fn create_flat_normal_map(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    data.flat_normal_map = MeshTexture::from_texels(instance, device, data, [1, 1, 1], &[128, 128, 255, 255],
+        NORMAL_MAP_FORMAT)?;
+    Ok(())
+}
+
+/// The slot of `AppData::normal_maps` the normal map at `path` is in, loaded into the next free one the
+/// first time it is asked for. `None`, with a warning, when it cannot be loaded, all `NORMAL_MAP_SLOTS`
+/// are taken or the device cannot pick one out in the shader.
+pub unsafe fn normal_map_slot(instance: &Instance, device: &Device, data: &mut AppData, path: &Path) -> Option<u32> {
+    if let Some(slot) = data.normal_maps.iter().position(|(loaded, _)| loaded == path) {
+        return Some(slot as u32);
+    }
+    if !data.sampler_array_indexing {
+        warn!("{}: the device cannot index an array of textures, so its normal map is ignored.", path.display());
+        return None;
+    }
+    if data.normal_maps.len() == NORMAL_MAP_SLOTS {
+        warn!("{}: all {} normal map slots are taken, so it is ignored.", path.display(), NORMAL_MAP_SLOTS);
+        return None;
+    }
+    match MeshTexture::load(instance, device, data, path.to_str(), NORMAL_MAP_FORMAT) {
+        Ok(texture) => {
+            data.normal_maps.push((path.to_path_buf(), texture));
+            Some(data.normal_maps.len() as u32 - 1)
+        }
+        Err(e) => {
+            warn!("{:#}", e);
+            None
+        }
+    }
+}
+
+/// Binds the normal maps to the slots of every set of `descriptor_sets`, and the flat one to the rest.
+pub unsafe fn write_normal_maps(device: &Device, data: &AppData) {
+    let image_info = (0..NORMAL_MAP_SLOTS).map(|slot| {
+        let texture = data.normal_maps.get(slot).map_or(&data.flat_normal_map, |(_, texture)| texture);
+        vk::DescriptorImageInfo::builder().sampler(texture.sampler).image_view(texture.view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL).build()
+    }).collect::<Vec<_>>();
+    let writes = data.descriptor_sets.iter().map(|set| vk::WriteDescriptorSet::builder()
+        .dst_set(*set).dst_binding(5).dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(&image_info).build())
+        .collect::<Vec<_>>();
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+}
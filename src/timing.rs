@@ -0,0 +1,157 @@
+use std::fmt;
+use std::mem::size_of;
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::MAX_FRAMES_IN_FLIGHT;
+use crate::utils::QueueFamilyIndices;
+
+/// Timestamps a solver step writes, in the order it writes them.
+#[repr(u32)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stamp {
+    StepBegin = 0,
+    DensityBegin,
+    ForceBegin,
+    ForceEnd,
+    IntegrateBegin,
+    IntegrateEnd,
+    GridEnd,
+    StepEnd,
+}
+
+/// Timestamps per solver step, one query range per frame in flight.
+const STEP_STAMPS: u32 = 8;
+/// Swapchain images whose render pass is timed; later images go untimed.
+pub const TIMED_IMAGES: usize = 8;
+
+/// GPU milliseconds of one frame's passes.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FrameTimings {
+    /// Counting the survivors per cell, scanning and scattering them.
+    pub grid_build: f32,
+    pub density: f32,
+    pub force: f32,
+    pub integrate: f32,
+    /// The whole solver step, including the passes not broken out above.
+    pub step: f32,
+    pub render: f32,
+}
+
+impl FrameTimings {
+    /// Moves a rolling average `weight` of the way towards `latest`.
+    pub fn blend(&mut self, latest: &FrameTimings, weight: f32) {
+        let mix = |a: &mut f32, b: f32| *a += weight * (b - *a);
+        mix(&mut self.grid_build, latest.grid_build);
+        mix(&mut self.density, latest.density);
+        mix(&mut self.force, latest.force);
+        mix(&mut self.integrate, latest.integrate);
+        mix(&mut self.step, latest.step);
+        mix(&mut self.render, latest.render);
+    }
+}
+
+impl fmt::Display for FrameTimings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "step {:.3} ms (grid {:.3}, density {:.3}, force {:.3}, integrate {:.3}), render {:.3} ms",
+            self.step, self.grid_build, self.density, self.force, self.integrate, self.render)
+    }
+}
+
+/// Timing helpers
+/// Creates the timestamp queries of the solver steps and render passes, leaving either pool null when
+/// its queue cannot write timestamps.
+pub unsafe fn create_timestamp_queries(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
+    let families = instance.get_physical_device_queue_family_properties(data.physical_device);
+    data.timestamp_period = instance.get_physical_device_properties(data.physical_device).limits.timestamp_period;
+    let pool = |family: u32, count: u32| -> Result<vk::QueryPool> {
+        if families[family as usize].timestamp_valid_bits == 0 {
+            return Ok(vk::QueryPool::null());
+        }
+        let info = vk::QueryPoolCreateInfo::builder().query_type(vk::QueryType::TIMESTAMP).query_count(count);
+        Ok(device.create_query_pool(&info, None)?)
+    };
+    data.step_query_pool = pool(indices.compute, STEP_STAMPS * MAX_FRAMES_IN_FLIGHT as u32)?;
+    data.render_query_pool = pool(indices.graphics, 2 * TIMED_IMAGES as u32)?;
+    Ok(())
+}
+
+pub unsafe fn destroy_timestamp_queries(device: &Device, data: &AppData) {
+    device.destroy_query_pool(data.step_query_pool, None);
+    device.destroy_query_pool(data.render_query_pool, None);
+}
+
+/// Records the reset of `frame`'s step timestamps; do this before writing any of them.
+pub unsafe fn reset_step_timestamps(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, frame: usize) {
+    if !data.step_query_pool.is_null() {
+        device.cmd_reset_query_pool(command_buffer, data.step_query_pool, STEP_STAMPS * frame as u32, STEP_STAMPS);
+    }
+}
+
+/// Records a timestamp once everything before it in the step has got past its compute shaders.
+pub unsafe fn write_step_timestamp(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    frame: usize, stamp: Stamp) {
+    if data.step_query_pool.is_null() {
+        return;
+    }
+    let stage = match stamp {
+        Stamp::StepBegin => vk::PipelineStageFlags::TOP_OF_PIPE,
+        Stamp::StepEnd => vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+        _ => vk::PipelineStageFlags::COMPUTE_SHADER,
+    };
+    device.cmd_write_timestamp(command_buffer, stage, data.step_query_pool, STEP_STAMPS * frame as u32 + stamp as u32);
+}
+
+/// Records the reset and the first timestamp of swapchain image `image`'s render pass, before it begins.
+pub unsafe fn begin_render_timestamps(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, image: usize) {
+    if !data.render_query_pool.is_null() && image < TIMED_IMAGES {
+        device.cmd_reset_query_pool(command_buffer, data.render_query_pool, 2 * image as u32, 2);
+        device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::TOP_OF_PIPE, data.render_query_pool, 2 * image as u32);
+    }
+}
+
+/// Records the last timestamp of swapchain image `image`'s render pass, after it ends.
+pub unsafe fn end_render_timestamps(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, image: usize) {
+    if !data.render_query_pool.is_null() && image < TIMED_IMAGES {
+        device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::BOTTOM_OF_PIPE, data.render_query_pool, 2 * image as u32 + 1);
+    }
+}
+
+/// The passes of `frame`'s last solver step, with `render` left at zero; `None` while unavailable.
+pub unsafe fn read_step_timings(device: &Device, data: &AppData, frame: usize) -> Result<Option<FrameTimings>> {
+    let Some(ticks) = read_ticks::<{ STEP_STAMPS as usize }>(device, data.step_query_pool, STEP_STAMPS * frame as u32)? else {
+        return Ok(None);
+    };
+    let ms = |from: Stamp, to: Stamp| ticks[to as usize].wrapping_sub(ticks[from as usize]) as f32 * data.timestamp_period * 1e-6;
+    Ok(Some(FrameTimings {
+        grid_build: ms(Stamp::IntegrateEnd, Stamp::GridEnd),
+        density: ms(Stamp::DensityBegin, Stamp::ForceBegin),
+        force: ms(Stamp::ForceBegin, Stamp::ForceEnd),
+        integrate: ms(Stamp::IntegrateBegin, Stamp::IntegrateEnd),
+        step: ms(Stamp::StepBegin, Stamp::StepEnd),
+        render: 0.0,
+    }))
+}
+
+/// Milliseconds the render pass of swapchain image `image` last took; `None` while unavailable.
+pub unsafe fn read_render_time(device: &Device, data: &AppData, image: usize) -> Result<Option<f32>> {
+    if image >= TIMED_IMAGES {
+        return Ok(None);
+    }
+    let ticks = read_ticks::<2>(device, data.render_query_pool, 2 * image as u32)?;
+    Ok(ticks.map(|t| t[1].wrapping_sub(t[0]) as f32 * data.timestamp_period * 1e-6))
+}
+
+unsafe fn read_ticks<const N: usize>(device: &Device, pool: vk::QueryPool, first: u32)
+-> Result<Option<[u64; N]>> {
+    if pool.is_null() {
+        return Ok(None);
+    }
+    let mut ticks = [0u64; N];
+    let bytes = std::slice::from_raw_parts_mut(ticks.as_mut_ptr().cast::<u8>(), size_of::<u64>() * N);
+    let result = device.get_query_pool_results(pool, first, N as u32, bytes, size_of::<u64>() as u64,
+        vk::QueryResultFlags::_64)?;
+    Ok((result != vk::SuccessCode::NOT_READY).then_some(ticks))
+}
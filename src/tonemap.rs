@@ -0,0 +1,246 @@
+use std::mem::size_of;
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::{FULLSCREEN_VERTEX_SHADER, TONEMAP_EXPOSURE, TONEMAP_SHADER};
+use crate::split::{full_viewport, viewport_scissor};
+use crate::utils::{compile_shader, create_image, create_image_view, create_shader_module};
+
+/// What the scene is drawn into before it is tonemapped into the swapchain: half floats, which hold
+/// light brighter than the display shows.
+pub const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// The curve that brings the HDR scene's colors into the display's range.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TonemapOperator {
+    /// `c / (1 + c)`, which never clips but washes bright colors out.
+    Reinhard,
+    /// A fit of the ACES filmic curve, with more contrast and highlights that roll off to white.
+    #[default]
+    Aces,
+}
+
+impl TonemapOperator {
+    pub fn next(self) -> Self {
+        match self {
+            TonemapOperator::Reinhard => TonemapOperator::Aces,
+            TonemapOperator::Aces => TonemapOperator::Reinhard,
+        }
+    }
+}
+
+/// How the HDR scene is drawn into the swapchain image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tonemapping {
+    pub operator: TonemapOperator,
+    /// What the scene's colors are multiplied by before the curve.
+    pub exposure: f32,
+}
+
+impl Default for Tonemapping {
+    fn default() -> Self {
+        Self { operator: TonemapOperator::default(), exposure: TONEMAP_EXPOSURE }
+    }
+}
+
+/// Push constants of the tonemapping pass, laid out like `Tonemap` in `tonemap.frag`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct TonemapConstants {
+    exposure: f32,
+    /// 0 Reinhard, 1 ACES.
+    operator: u32,
+    /// How much of the bloom is added over the scene, 0 without it.
+    bloom: f32,
+}
+
+/// Creates what tonemaps the HDR scene into each swapchain image: a pass, a set sampling the HDR image
+/// and a pipeline drawing a full-screen triangle. The set is left for `create_tonemap_targets` and
+/// `create_bloom_targets` to write.
+pub unsafe fn create_tonemap(device: &Device, data: &mut AppData) -> Result<()> {
+    let sampler_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::NEAREST).min_filter(vk::Filter::NEAREST)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST);
+    data.hdr_sampler = device.create_sampler(&sampler_info, None)?;
+
+    // the whole presentable image is drawn over, and left presentable for the debug view to load
+    let color_attachment = vk::AttachmentDescription::builder()
+        .format(data.swapchain_format).samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE).store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE).stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED).final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+    let color_attachment_ref = vk::AttachmentReference::builder().attachment(0)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let color_attachments = &[color_attachment_ref];
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .color_attachments(color_attachments);
+    // the HDR image is read once the scene's passes are done drawing it
+    let dependency = vk::SubpassDependency::builder()
+        .src_subpass(vk::SUBPASS_EXTERNAL)
+        .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+    let attachments = &[color_attachment];
+    let subpasses = &[subpass];
+    let dependencies = &[dependency];
+    let info = vk::RenderPassCreateInfo::builder()
+        .attachments(attachments)
+        .subpasses(subpasses)
+        .dependencies(dependencies);
+    data.tonemap_render_pass = device.create_render_pass(&info, None)?;
+
+    // the HDR image, then the bloom over it
+    let bindings = [0, 1].map(|binding| vk::DescriptorSetLayoutBinding::builder()
+        .binding(binding)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT));
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+    data.tonemap_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    let pool_size = vk::DescriptorPoolSize::builder()
+        .type_(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).descriptor_count(2);
+    let pool_sizes = &[pool_size];
+    let info = vk::DescriptorPoolCreateInfo::builder().pool_sizes(pool_sizes).max_sets(1);
+    data.tonemap_descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    let layouts = &[data.tonemap_set_layout];
+    let info = vk::DescriptorSetAllocateInfo::builder().descriptor_pool(data.tonemap_descriptor_pool).set_layouts(layouts);
+    data.tonemap_descriptor_set = device.allocate_descriptor_sets(&info)?[0];
+    create_tonemap_pipeline(device, data)
+}
+
+/// Creates the HDR image the scene is drawn into and the framebuffers tonemapping it into each
+/// swapchain image, for the current swapchain's extent. Call before `create_framebuffers`, whose
+/// framebuffers draw into the image.
+pub unsafe fn create_tonemap_targets(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    let extent = data.swapchain_extent;
+    let (image, memory) = create_image(instance, device, data, extent.width, extent.height, HDR_FORMAT,
+        vk::ImageTiling::OPTIMAL, vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+        vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+    (data.hdr_image, data.hdr_image_memory) = (image, memory);
+    data.hdr_image_view = create_image_view(device, image, HDR_FORMAT, vk::ImageAspectFlags::COLOR)?;
+    data.tonemap_framebuffers = data.swapchain_image_views.iter().map(|v| {
+        let attachments = &[*v];
+        let info = vk::FramebufferCreateInfo::builder().render_pass(data.tonemap_render_pass)
+            .attachments(attachments).width(extent.width).height(extent.height).layers(1);
+        device.create_framebuffer(&info, None)
+    }).collect::<Result<Vec<_>, _>>()?;
+    let image_info = &[vk::DescriptorImageInfo::builder()
+        .sampler(data.hdr_sampler).image_view(data.hdr_image_view)
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+    let write = vk::WriteDescriptorSet::builder()
+        .dst_set(data.tonemap_descriptor_set).dst_binding(0).dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info);
+    device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+    Ok(())
+}
+
+pub unsafe fn destroy_tonemap_targets(device: &Device, data: &AppData) {
+    data.tonemap_framebuffers.iter().for_each(|f| device.destroy_framebuffer(*f, None));
+    device.destroy_image_view(data.hdr_image_view, None);
+    device.destroy_image(data.hdr_image, None);
+    device.free_memory(data.hdr_image_memory, None);
+}
+
+unsafe fn create_tonemap_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&FULLSCREEN_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&TONEMAP_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(vk::SampleCountFlags::_1);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(false);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+
+    let set_layouts = &[data.tonemap_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        .offset(0)
+        .size(size_of::<TonemapConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.tonemap_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .dynamic_state(&dynamic_state)
+        .multisample_state(&multisample_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.tonemap_pipeline_layout)
+        .render_pass(data.tonemap_render_pass)
+        .subpass(0);
+    data.tonemap_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_tonemap(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.tonemap_pipeline, None);
+    device.destroy_pipeline_layout(data.tonemap_pipeline_layout, None);
+    device.destroy_descriptor_pool(data.tonemap_descriptor_pool, None);
+    device.destroy_descriptor_set_layout(data.tonemap_set_layout, None);
+    device.destroy_render_pass(data.tonemap_render_pass, None);
+    device.destroy_sampler(data.hdr_sampler, None);
+}
+
+/// Records the tonemapping of the finished HDR scene into swapchain image `i`.
+pub unsafe fn record_tonemap(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    let info = vk::RenderPassBeginInfo::builder()
+        .render_pass(data.tonemap_render_pass)
+        .framebuffer(data.tonemap_framebuffers[i])
+        .render_area(vk::Rect2D::builder().offset(vk::Offset2D::default()).extent(data.swapchain_extent));
+    device.cmd_begin_render_pass(command_buffer, &info, vk::SubpassContents::INLINE);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.tonemap_pipeline);
+    let viewport = full_viewport(data.swapchain_extent);
+    device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+    device.cmd_set_scissor(command_buffer, 0, &[viewport_scissor(&viewport)]);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.tonemap_pipeline_layout, 0, &[data.tonemap_descriptor_set], &[]);
+    let tonemapping = data.tonemapping;
+    let constants = TonemapConstants { exposure: tonemapping.exposure, operator: tonemapping.operator as u32,
+        bloom: data.bloom.map_or(0.0, |b| b.intensity) };
+    let bytes = std::slice::from_raw_parts((&constants as *const TonemapConstants).cast::<u8>(),
+        size_of::<TonemapConstants>());
+    device.cmd_push_constants(command_buffer, data.tonemap_pipeline_layout, vk::ShaderStageFlags::FRAGMENT, 0, bytes);
+    device.cmd_draw(command_buffer, 3, 1, 0, 0);
+    device.cmd_end_render_pass(command_buffer);
+}
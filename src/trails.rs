@@ -0,0 +1,236 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::config::*;
+use crate::simulation::{SimParams, compute_barrier, create_storage_buffer, solver_constants,
+    write_compute_descriptor_sets};
+use crate::utils::{compile_shader, create_compute_pipeline, create_shader_module, begin_single_time_commands,
+    end_single_time_commands};
+
+/// Particle trails: every `interval` frames a pass records where every `stride`th particle, by id, is,
+/// into a ring of its last `length` positions, which the renderer draws as a line strip fading towards
+/// the oldest. The ring buffer takes `length + 1` points of 16 bytes per traced particle, and is only
+/// made once trails are first turned on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Trails {
+    /// Positions each trail keeps, at least two.
+    pub length: u32,
+    /// Particles whose id is a multiple of it are traced.
+    pub stride: u32,
+    /// Frames stepped between recorded positions.
+    pub interval: u32,
+}
+
+impl Default for Trails {
+    fn default() -> Self {
+        Self { length: TRAIL_LENGTH, stride: TRAIL_STRIDE, interval: TRAIL_INTERVAL }
+    }
+}
+
+impl Trails {
+    /// Rings a trail buffer for `capacity` particles holds: one per traced particle there is room for.
+    /// Ids past them share rings, the ring's current owner keeping it while it lives.
+    pub fn slots(&self, capacity: u32) -> u32 {
+        capacity.div_ceil(self.stride.max(1)).max(1)
+    }
+}
+
+/// Push constants of the trail pass and renderer, laid out to match their `Trail` blocks.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct TrailConstants {
+    /// Number of the sample the pass records, counting from 1 since the trails were cleared.
+    stamp: u32,
+    length: u32,
+    stride: u32,
+    slots: u32,
+    /// Color of the trails, with the opacity at their head in w.
+    color: glm::Vec4,
+}
+
+impl TrailConstants {
+    fn new(data: &AppData) -> Self {
+        let [length, stride, slots] = data.trail_shape;
+        let [r, g, b, a] = TRAIL_COLOR;
+        Self { stamp: data.trail_stamp, length, stride, slots, color: glm::vec4(r, g, b, a) }
+    }
+}
+
+/// Creates the pass that records the traced particles' positions into their rings.
+pub unsafe fn create_trail_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<TrailConstants>() as u32);
+    let set_layouts = &[data.compute_descriptor_set_layout];
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.trail_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let constants = solver_constants(data, false);
+    data.trail_pipeline = create_compute_pipeline(device, data.pipeline_cache, data.trail_pipeline_layout, &TRAIL_SHADER.to_string(), &constants)?;
+    Ok(())
+}
+
+pub unsafe fn destroy_trail_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.trail_pipeline, None);
+    device.destroy_pipeline_layout(data.trail_pipeline_layout, None);
+}
+
+/// Makes the trail buffer for `trails`, cleared, unless the one there is already shaped for them, and
+/// binds it to the compute sets. Nothing may be in flight.
+pub unsafe fn create_trail_buffer(instance: &Instance, device: &Device, data: &mut AppData, trails: &Trails)
+    -> Result<()> {
+    let shape = [trails.length.max(2), trails.stride.max(1), trails.slots(data.particle_capacity)];
+    if data.trail_buffer != vk::Buffer::null() && data.trail_shape == shape {
+        return Ok(());
+    }
+    destroy_trail_buffer(device, data);
+    // a header, then each ring's points and its owner record
+    let [length, _, slots] = shape;
+    let size = 16 * (1 + slots as u64 * (length as u64 + 1));
+    let (buffer, memory) = create_storage_buffer(instance, device, data, size, vk::BufferUsageFlags::empty(), false)?;
+    data.trail_buffer = buffer;
+    data.trail_buffer_memory = memory;
+    data.trail_shape = shape;
+    write_compute_descriptor_sets(device, data);
+    clear_trails(device, data)
+}
+
+pub unsafe fn destroy_trail_buffer(device: &Device, data: &mut AppData) {
+    device.destroy_buffer(data.trail_buffer, None);
+    device.free_memory(data.trail_buffer_memory, None);
+    data.trail_buffer = vk::Buffer::null();
+    data.trail_buffer_memory = vk::DeviceMemory::null();
+}
+
+/// Forgets every trail, so none runs across a jump of the particles. Nothing may be in flight.
+pub unsafe fn clear_trails(device: &Device, data: &mut AppData) -> Result<()> {
+    data.trail_stamp = 0;
+    if data.trail_buffer == vk::Buffer::null() {
+        return Ok(());
+    }
+    let command_buffer = begin_single_time_commands(device, data)?;
+    device.cmd_fill_buffer(command_buffer, data.trail_buffer, 0, vk::WHOLE_SIZE as u64, 0);
+    end_single_time_commands(device, data, command_buffer)
+}
+
+/// Records the latest particles `particle_buffers[parity]` into the rings as sample `data.trail_stamp`.
+pub unsafe fn record_trails(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, sim: &SimParams,
+    parity: usize) {
+    let constants = TrailConstants::new(data);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, data.trail_pipeline_layout, 0,
+        &[data.compute_descriptor_sets[parity]], &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const TrailConstants).cast::<u8>(),
+        size_of::<TrailConstants>());
+    device.cmd_push_constants(command_buffer, data.trail_pipeline_layout, vk::ShaderStageFlags::COMPUTE, 0, bytes);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, data.trail_pipeline);
+    device.cmd_dispatch(command_buffer, sim.workgroups(data.workgroup_size), 1, 1);
+    compute_barrier(device, command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+        vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+}
+
+/// Creates the pipeline that draws the trails: a line strip per ring, its vertices read out of the
+/// trail buffer, alpha blended and depth tested without writing depth.
+pub unsafe fn create_trail_render_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&TRAIL_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&TRAIL_FRAGMENT_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    // each instance its own strip, so the rings need no restarts between them
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::LINE_STRIP)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true).depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+
+    // the scene's uniform buffer, the solver's buffers for the trail buffer, and the ring shape as
+    // push constants
+    let set_layouts = &[data.descriptor_set_layout, data.compute_descriptor_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(size_of::<TrailConstants>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.trail_render_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .dynamic_state(&dynamic_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.trail_render_pipeline_layout)
+        .render_pass(data.render_pass)
+        .subpass(0);
+    data.trail_render_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_trail_render_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.trail_render_pipeline, None);
+    device.destroy_pipeline_layout(data.trail_render_pipeline_layout, None);
+}
+
+/// Records the draw of every ring into swapchain image `i`'s render pass; rings with no recent samples
+/// draw nothing.
+pub unsafe fn record_trail_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    if data.trail_buffer == vk::Buffer::null() {
+        return;
+    }
+    let constants = TrailConstants::new(data);
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.trail_render_pipeline);
+    let sets = [data.descriptor_sets[i], data.compute_descriptor_sets[data.particle_parity]];
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.trail_render_pipeline_layout, 0, &sets, &[]);
+    let bytes = std::slice::from_raw_parts((&constants as *const TrailConstants).cast::<u8>(),
+        size_of::<TrailConstants>());
+    device.cmd_push_constants(command_buffer, data.trail_render_pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, bytes);
+    device.cmd_draw(command_buffer, constants.length, constants.slots, 0, 0);
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+use nalgebra_glm as glm;
+
+use crate::simulation::SimParams;
+
+/// A solver parameter that can be tuned at runtime, with the range it is clamped to. Changes go into
+/// `App`'s `SimParams`, which is copied whole into the frame's uniform buffer before every step, so
+/// they take effect on the next frame.
+#[derive(Copy, Clone)]
+pub struct Tunable {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub min: f32,
+    pub max: f32,
+    get: fn(&SimParams) -> f32,
+    set: fn(&mut SimParams, f32),
+}
+
+impl Tunable {
+    pub fn get(&self, sim: &SimParams) -> f32 {
+        (self.get)(sim)
+    }
+
+    /// Sets the parameter to `value` clamped to its range, returning what it was set to.
+    pub fn set(&self, sim: &mut SimParams, value: f32) -> f32 {
+        let value = value.clamp(self.min, self.max);
+        (self.set)(sim, value);
+        value
+    }
+
+    /// Multiplies the parameter by `factor`, within its range.
+    pub fn scale(&self, sim: &mut SimParams, factor: f32) -> f32 {
+        self.set(sim, self.get(sim) * factor)
+    }
+}
+
+impl fmt::Debug for Tunable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} [{}, {}] {}", self.name, self.min, self.max, self.unit)
+    }
+}
+
+/// The parameters the keyboard cycles through, in order. Rest density and viscosity are those of phase
+/// 0; a new rest density also sets the mass new particles of the phase get, but not that of the
+/// particles already there. Gravity keeps its direction.
+pub const TUNABLES: [Tunable; 4] = [
+    Tunable { name: "stiffness", unit: "Pa m^3/kg", min: 0.1, max: 1e5,
+        get: |sim| sim.stiffness, set: |sim, v| sim.stiffness = v },
+    Tunable { name: "viscosity", unit: "Pa s", min: 1e-3, max: 100.0,
+        get: |sim| sim.phases[0].y, set: |sim, v| {
+            sim.phases[0].y = v;
+            sim.viscosity = v;
+        } },
+    Tunable { name: "rest density", unit: "kg/m^3", min: 10.0, max: 1e4,
+        get: |sim| sim.phases[0].x, set: |sim, v| {
+            sim.phases[0].x = v;
+            sim.phases[0].z = v * SimParams::PARTICLE_SPACING.powi(3);
+            sim.rest_density = v;
+        } },
+    Tunable { name: "gravity", unit: "m/s^2", min: 0.01, max: 100.0,
+        get: |sim| glm::length(&sim.gravity.xyz()), set: |sim, v| {
+            let g = sim.gravity.xyz();
+            let direction = if glm::length(&g) > 0.0 { glm::normalize(&g) } else { glm::vec3(0.0, -1.0, 0.0) };
+            sim.gravity = glm::vec3_to_vec4(&(direction * v));
+        } },
+];
@@ -0,0 +1,126 @@
+use std::mem::size_of;
+use anyhow::Result;
+use log::*;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::boundary::XorShift;
+use crate::config::*;
+use crate::simulation::{create_storage_buffer, upload_to_buffer};
+use crate::utils::{create_compute_pipeline, QueueFamilyIndices};
+
+/// Points in the stand-in dispatch, about a full particle buffer.
+const TUNE_POINTS: u32 = MAX_PARTICLES;
+/// Timed repeats per candidate, after one untimed warm-up.
+const TUNE_REPEATS: u32 = 4;
+
+/// Times `tune.comp` at every candidate workgroup size the device allows and returns the fastest, or
+/// `WORKGROUP_SIZE` when the compute queue cannot write timestamps. Run it before creating the compute
+/// pipelines and buffers, which are sized by the result.
+pub unsafe fn autotune_workgroup_size(instance: &Instance, device: &Device, data: &AppData) -> Result<u32> {
+    let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
+    let families = instance.get_physical_device_queue_family_properties(data.physical_device);
+    if families[indices.compute as usize].timestamp_valid_bits == 0 {
+        warn!("The compute queue has no timestamps; keeping workgroup size {}.", WORKGROUP_SIZE);
+        return Ok(WORKGROUP_SIZE);
+    }
+    let limits = instance.get_physical_device_properties(data.physical_device).limits;
+    // the stand-in data
+    let mut rng = XorShift(0x7e57);
+    let points = (0..TUNE_POINTS).map(|_| glm::vec4(rng.next(), rng.next(), rng.next(), 0.0)).collect::<Vec<_>>();
+    let (buffer, buffer_memory) = create_storage_buffer(instance, device, data,
+        (size_of::<glm::Vec4>() * points.len()) as u64, vk::BufferUsageFlags::empty(), false)?;
+    upload_to_buffer(instance, device, data, &points, buffer)?;
+    // one storage buffer and the point count as a push constant
+    let binding = vk::DescriptorSetLayoutBinding::builder()
+        .binding(0)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .descriptor_count(1)
+        .stage_flags(vk::ShaderStageFlags::COMPUTE);
+    let bindings = &[binding];
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+    let set_layout = device.create_descriptor_set_layout(&info, None)?;
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::COMPUTE)
+        .offset(0)
+        .size(size_of::<u32>() as u32);
+    let set_layouts = &[set_layout];
+    let push_ranges = &[push_range];
+    let info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    let layout = device.create_pipeline_layout(&info, None)?;
+    let pool_size = vk::DescriptorPoolSize::builder().type_(vk::DescriptorType::STORAGE_BUFFER).descriptor_count(1);
+    let pool_sizes = &[pool_size];
+    let info = vk::DescriptorPoolCreateInfo::builder().pool_sizes(pool_sizes).max_sets(1);
+    let descriptor_pool = device.create_descriptor_pool(&info, None)?;
+    let info = vk::DescriptorSetAllocateInfo::builder().descriptor_pool(descriptor_pool).set_layouts(set_layouts);
+    let set = device.allocate_descriptor_sets(&info)?[0];
+    let info = vk::DescriptorBufferInfo::builder().buffer(buffer).offset(0).range(vk::WHOLE_SIZE as u64);
+    let buffer_info = &[info];
+    let write = vk::WriteDescriptorSet::builder()
+        .dst_set(set).dst_binding(0).dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER).buffer_info(buffer_info);
+    device.update_descriptor_sets(&[write], &[] as &[vk::CopyDescriptorSet]);
+    let info = vk::QueryPoolCreateInfo::builder().query_type(vk::QueryType::TIMESTAMP).query_count(2);
+    let query_pool = device.create_query_pool(&info, None)?;
+    let info = vk::CommandPoolCreateInfo::builder().queue_family_index(indices.compute);
+    let command_pool = device.create_command_pool(&info, None)?;
+
+    let mut best = (WORKGROUP_SIZE, f32::MAX);
+    for size in WORKGROUP_SIZE_CANDIDATES {
+        if size > limits.max_compute_work_group_size[0] || size > limits.max_compute_work_group_invocations {
+            continue;
+        }
+        let pipeline = create_compute_pipeline(device, data.pipeline_cache, layout, &TUNE_SHADER.to_string(), &[size])?;
+        let info = vk::CommandBufferAllocateInfo::builder()
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_pool(command_pool)
+            .command_buffer_count(1);
+        let command_buffer = device.allocate_command_buffers(&info)?[0];
+        let info = vk::CommandBufferBeginInfo::builder().flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        device.begin_command_buffer(command_buffer, &info)?;
+        device.cmd_reset_query_pool(command_buffer, query_pool, 0, 2);
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+        device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::COMPUTE, layout, 0, &[set], &[]);
+        device.cmd_push_constants(command_buffer, layout, vk::ShaderStageFlags::COMPUTE, 0, &TUNE_POINTS.to_ne_bytes());
+        for repeat in 0..=TUNE_REPEATS {
+            if repeat == 1 {
+                device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER, query_pool, 0);
+            }
+            device.cmd_dispatch(command_buffer, TUNE_POINTS.div_ceil(size), 1, 1);
+            let barrier = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE);
+            device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER, vk::DependencyFlags::empty(), &[barrier],
+                &[] as &[vk::BufferMemoryBarrier], &[] as &[vk::ImageMemoryBarrier]);
+        }
+        device.cmd_write_timestamp(command_buffer, vk::PipelineStageFlags::COMPUTE_SHADER, query_pool, 1);
+        device.end_command_buffer(command_buffer)?;
+        let command_buffers = &[command_buffer];
+        let info = vk::SubmitInfo::builder().command_buffers(command_buffers);
+        device.queue_submit(data.compute_queue, &[info], vk::Fence::null())?;
+        device.queue_wait_idle(data.compute_queue)?;
+        device.free_command_buffers(command_pool, &[command_buffer]);
+        device.destroy_pipeline(pipeline, None);
+        let mut ticks = [0u64; 2];
+        let bytes = std::slice::from_raw_parts_mut(ticks.as_mut_ptr().cast::<u8>(), size_of::<[u64; 2]>());
+        device.get_query_pool_results(query_pool, 0, 2, bytes, size_of::<u64>() as u64,
+            vk::QueryResultFlags::_64 | vk::QueryResultFlags::WAIT)?;
+        let ms = ticks[1].wrapping_sub(ticks[0]) as f32 * limits.timestamp_period * 1e-6 / TUNE_REPEATS as f32;
+        debug!("Workgroup size {}: {:.3} ms per dispatch.", size, ms);
+        if ms < best.1 {
+            best = (size, ms);
+        }
+    }
+
+    device.destroy_command_pool(command_pool, None);
+    device.destroy_query_pool(query_pool, None);
+    device.destroy_descriptor_pool(descriptor_pool, None);
+    device.destroy_pipeline_layout(layout, None);
+    device.destroy_descriptor_set_layout(set_layout, None);
+    device.destroy_buffer(buffer, None);
+    device.free_memory(buffer_memory, None);
+    info!("Picked workgroup size {} ({:.3} ms per tuning dispatch).", best.0, best.1);
+    Ok(best.0)
+}
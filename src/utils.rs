@@ -1,13 +1,14 @@
-use std::fs::File;
+use std::cell::RefCell;
+use std::fs::{self, File};
 use std::path::Path;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::Read;
 use std::mem::size_of;
 use std::ptr::copy_nonoverlapping as memcpy;
 
 use anyhow::{anyhow, Result};
+use nalgebra_glm as glm;
 use log::*;
-use shaderc::CompilationArtifact;
 use vulkanalia::vk::{KhrSurfaceExtension, KhrSwapchainExtension, ShaderModule, PhysicalDevice};
 use vulkanalia::prelude::v1_0::*;
 use thiserror::Error;
@@ -17,7 +18,36 @@ use crate::config::*;
 use crate::appdata::AppData;
 use crate::config::DEVICE_EXTENSIONS;
 use crate::camera::UniformBufferObject;
-use crate::model::{Vertex, Object};
+use crate::model::{MaterialKind, Vertex, Object};
+use crate::timing::{begin_render_timestamps, end_render_timestamps};
+use crate::reconstruct::FluidRendering;
+use crate::foam::{create_foam_render_pipeline, record_foam_draw};
+use crate::sprites::{create_particle_pipeline, record_particle_draw, record_particle_prepass};
+use crate::screen_space::record_screen_space_fluid;
+use crate::secondary::record_secondaries;
+use crate::shader_cache::{Spirv, compile_cached};
+use crate::reflect::{BindingWrite, check_layout, check_writes, make_dynamic, merge_bindings, pool_sizes, reflect_bindings,
+    set_layout_bindings};
+use crate::raymarch::{create_raymarch_pipeline, record_raymarch_draw};
+use crate::colormap::{create_legend_pipeline, record_legend_draw};
+use crate::glyphs::{create_glyph_pipeline, record_glyph_draw};
+use crate::trails::{create_trail_render_pipeline, record_trail_draw};
+use crate::slice::{create_slice_pipeline, record_slice_draw};
+use crate::wireframe::{create_wire_pipeline, record_normal_draw, record_wire_draw};
+use crate::ground::{create_ground_pipeline, record_ground_draw};
+use crate::skybox::{create_skybox_pipeline, record_skybox_draw, write_skybox};
+use crate::shadow::{record_point_shadow_pass, record_shadow_pass, write_point_shadow_map, write_shadow_map};
+use crate::texture::write_normal_maps;
+use crate::tonemap::{record_tonemap, HDR_FORMAT};
+use crate::bloom::record_bloom;
+use crate::ssao::record_ssao;
+use crate::indirect::{drawn_indirectly, indirect_pipelines, record_indirect_draws,
+    record_mesh_cull};
+use crate::oit::{create_oit_render_pass, oit_active, oit_blend_attachments, record_oit};
+use crate::gizmo::{create_gizmo_pipeline, record_gizmo_draw};
+use crate::debug_view::record_debug_view;
+use crate::split::{full_viewport, viewport_scissor};
+use crate::occupancy::{create_occupancy_render_pipeline, record_occupancy_draw};
 
 
 /// Structures
@@ -25,10 +55,41 @@ use crate::model::{Vertex, Object};
 #[error("Missing {0}")]
 pub struct SuitabilityError(pub &'static str);
 
+/// A shader that failed to compile, with shaderc's diagnostics and where they place the first error.
+#[derive(Debug, Error)]
+#[error("Failed to compile {path}{}:\n{diagnostics}", error_location(.line, .column))]
+pub struct ShaderCompileError {
+    pub path: String,
+    pub diagnostics: String,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
+fn error_location(line: &Option<u32>, column: &Option<u32>) -> String {
+    match (line, column) {
+        (Some(line), Some(column)) => format!(" at line {}, column {}", line, column),
+        (Some(line), None) => format!(" at line {}", line),
+        _ => String::new(),
+    }
+}
+
+impl ShaderCompileError {
+    /// Reads the line, and the column if given, off the first error of `diagnostics`, which glslang
+    /// words as `file:line: error: ...` or `file:line:column: error: ...`.
+    fn new(path: &str, diagnostics: String) -> Self {
+        let first = diagnostics.lines().find(|l| l.contains("error")).unwrap_or_default();
+        let mut numbers = first.split(':').skip_while(|f| f.trim().parse::<u32>().is_err()).map_while(|f| f.trim().parse().ok());
+        let (line, column) = (numbers.next(), numbers.next());
+        Self { path: path.to_string(), diagnostics, line, column }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct QueueFamilyIndices {
     pub graphics: u32,
     pub present: u32,
+    /// A compute-only family for async compute when the device has one, otherwise `graphics`.
+    pub compute: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -52,8 +113,11 @@ impl QueueFamilyIndices {
                 break;
             }
         }
+        let compute = props.iter()
+            .position(|x| x.queue_flags.contains(vk::QueueFlags::COMPUTE) && !x.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+            .map(|x| x as u32);
         if let (Some(graphics), Some(present)) = (graphics, present) {
-            Ok(Self{ graphics, present })
+            Ok(Self{ graphics, present, compute: compute.unwrap_or(graphics) })
         } else {
             Err(anyhow!(SuitabilityError("SBBB")))
         }
@@ -116,6 +180,7 @@ pub unsafe fn create_logical_device(instance: &Instance, data: &mut AppData) ->
     let mut unique_indices = HashSet::new();
     unique_indices.insert(indices.graphics);
     unique_indices.insert(indices.present);
+    unique_indices.insert(indices.compute);
 
     let queue_priorities = &[1.0];
     let queue_infos = unique_indices
@@ -132,9 +197,32 @@ pub unsafe fn create_logical_device(instance: &Instance, data: &mut AppData) ->
         vec![]
     };
 
-    let extensions = DEVICE_EXTENSIONS.iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
-    
-    let features = vk::PhysicalDeviceFeatures::builder();
+    // the draw count of the indirect draws from the GPU, where the device takes it
+    let available = instance.enumerate_device_extension_properties(data.physical_device, None)?
+        .iter().map(|e| e.extension_name).collect::<HashSet<_>>();
+    data.draw_indirect_count = available.contains(&vk::KHR_DRAW_INDIRECT_COUNT_EXTENSION.name);
+    let extensions = DEVICE_EXTENSIONS.iter().chain(data.draw_indirect_count
+        .then_some(&vk::KHR_DRAW_INDIRECT_COUNT_EXTENSION.name)).map(|e| e.as_ptr()).collect::<Vec<_>>();
+
+    // wide points for the particle sprites and wide lines for the velocity glyphs, where the device
+    // draws them; otherwise they stay a pixel. Polygons drawn as lines for the mesh wireframe, and
+    // samplers picked out of an array for the normal maps, which are off without them. The objects' draws
+    // from the GPU take many indirect draws at once, each from its own instance
+    let supported = instance.get_physical_device_features(data.physical_device);
+    let large_points = supported.large_points == vk::TRUE;
+    let wide_lines = supported.wide_lines == vk::TRUE;
+    data.fill_mode_non_solid = supported.fill_mode_non_solid == vk::TRUE;
+    data.sampler_array_indexing = supported.shader_sampled_image_array_dynamic_indexing == vk::TRUE;
+    data.multi_draw_indirect = supported.multi_draw_indirect == vk::TRUE
+        && supported.draw_indirect_first_instance == vk::TRUE;
+    let features = vk::PhysicalDeviceFeatures::builder().large_points(large_points).wide_lines(wide_lines)
+        .fill_mode_non_solid(data.fill_mode_non_solid)
+        .shader_sampled_image_array_dynamic_indexing(data.sampler_array_indexing)
+        .multi_draw_indirect(data.multi_draw_indirect).draw_indirect_first_instance(data.multi_draw_indirect);
+    let limits = instance.get_physical_device_properties(data.physical_device).limits;
+    data.point_size_range = if large_points { limits.point_size_range } else { [1.0, 1.0] };
+    data.line_width_range = if wide_lines { limits.line_width_range } else { [1.0, 1.0] };
+    info!("Particle points up to {} px, lines up to {} px wide.", data.point_size_range[1], data.line_width_range[1]);
     let info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_infos)
         .enabled_layer_names(&layers)
@@ -143,6 +231,11 @@ pub unsafe fn create_logical_device(instance: &Instance, data: &mut AppData) ->
     let device = instance.create_device(data.physical_device, &info, None)?;
     data.graphics_queue = device.get_device_queue(indices.graphics, 0);
     data.present_queue = device.get_device_queue(indices.present, 0);
+    data.compute_queue = device.get_device_queue(indices.compute, 0);
+    if indices.compute != indices.graphics {
+        info!("Using queue family {} for async compute.", indices.compute);
+        data.shared_queue_families = vec![indices.graphics, indices.compute];
+    }
     Ok(device)
 }
 
@@ -153,9 +246,15 @@ pub fn get_swapchain_surface_format(formats: &[vk::SurfaceFormatKHR]) -> vk::Sur
         .unwrap_or_else(|| formats[0])
 }
 
-pub fn get_swapchain_present_mode(present_modes: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
-    present_modes.iter().cloned()
-        .find(|m| *m == vk::PresentModeKHR::MAILBOX)
+/// MAILBOX if available, FIFO otherwise; IMMEDIATE ahead of both when `uncapped`.
+pub fn get_swapchain_present_mode(present_modes: &[vk::PresentModeKHR], uncapped: bool) -> vk::PresentModeKHR {
+    let preferred: &[vk::PresentModeKHR] = if uncapped {
+        &[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX]
+    } else {
+        &[vk::PresentModeKHR::MAILBOX]
+    };
+    preferred.iter().cloned()
+        .find(|m| present_modes.contains(m))
         .unwrap_or(vk::PresentModeKHR::FIFO)
 }
 
@@ -184,7 +283,7 @@ pub unsafe fn create_swapchain(window: &Window, instance: &Instance, device: &De
     let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
     let support = SwapchainSupport::get(instance, data, data.physical_device)?;
     let surface_format = get_swapchain_surface_format(&support.formats);
-    let present_mode = get_swapchain_present_mode(&support.present_modes);
+    let present_mode = get_swapchain_present_mode(&support.present_modes, data.uncapped_present);
     let extent = get_swapchain_extent(window, support.capabilities);
     let mut img_cnt = support.capabilities.min_image_count + 1;
     if support.capabilities.max_image_count != 0 && img_cnt > support.capabilities.max_image_count {
@@ -247,57 +346,216 @@ pub unsafe fn create_swapchain_image_views(device: &Device, data: &mut AppData)
 }
 
 /// Pipeline helpers
+/// Creates the layout the mesh pipelines share, the scene's set then the object set, and the rest of the
+/// main pass's pipelines; the mesh pipelines themselves are made as draws first need them, see
+/// `prepare_mesh_pipelines`.
 pub unsafe fn create_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    // each draw's transform, whether to texture it and its material are in the object set
+    let set_layouts = &[data.descriptor_set_layout, data.object_set_layout];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts);
+    data.pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    create_particle_pipeline(device, data)?;
+    create_raymarch_pipeline(device, data)?;
+    create_legend_pipeline(device, data)?;
+    create_glyph_pipeline(device, data)?;
+    create_trail_render_pipeline(device, data)?;
+    create_slice_pipeline(device, data)?;
+    create_wire_pipeline(device, data)?;
+    create_ground_pipeline(device, data)?;
+    create_skybox_pipeline(device, data)?;
+    create_gizmo_pipeline(device, data)?;
+    create_occupancy_render_pipeline(device, data)?;
+    create_foam_render_pipeline(device, data)
+}
+
+/// How a mesh pipeline draws a mesh's primitives.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MeshPrimitive {
+    Triangles,
+    /// The triangles' edges, for `AppData::mesh_wireframe`.
+    Wireframe,
+    /// A line list, for the selection markers.
+    Lines,
+}
+
+/// How a mesh pipeline tests and writes depth.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MeshDepth {
+    /// Nearer than what is there, writing its own.
+    Test,
+    /// Depth alone, for `AppData::depth_prepass`.
+    Prepass,
+    /// Shading only where the depth pre-pass left its depth, without writing it.
+    Equal,
+    /// Nearer than the opaque scene without hiding what is behind, weighted into the transparency targets
+    /// of `AppData::oit`.
+    Transparent,
+}
+
+/// What a pipeline of `AppData::mesh_pipelines` is made for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshPipeline {
+    pub kind: MaterialKind,
+    pub primitive: MeshPrimitive,
+    pub depth: MeshDepth,
+    /// Whether the pipeline draws the indirect draws, which find their constants by instance.
+    pub indirect: bool,
+    pub config: ShaderConfig,
+}
+
+/// The features the mesh fragment shader is specialized for, so that what a pipeline leaves off is
+/// compiled out of it rather than branched around; pipelines of different configs coexist in
+/// `AppData::mesh_pipelines`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ShaderConfig {
+    /// Whether the key light's shadow maps are looked up, `SHADOWS`.
+    pub shadows: bool,
+    /// Whether the normal maps turn the normals, `NORMAL_MAPPING`.
+    pub normal_mapping: bool,
+    /// Lights shaded, `LIGHTS`, up to `MAX_LIGHTS`.
+    pub lights: u32,
+}
+
+impl Default for ShaderConfig {
+    fn default() -> Self {
+        Self { shadows: true, normal_mapping: true, lights: MAX_LIGHTS as u32 }
+    }
+}
+
+impl ShaderConfig {
+    /// What the meshes of `kind` are drawn with in `data`'s scene: only the features it has on. The
+    /// unlit shader has none of them, so its pipelines keep the default.
+    pub fn of(data: &AppData, kind: MaterialKind) -> Self {
+        match kind {
+            MaterialKind::Lit => Self {
+                shadows: data.shadows.is_some() || data.point_shadows.is_some(),
+                normal_mapping: !data.normal_maps.is_empty(),
+                lights: data.lights.len().min(MAX_LIGHTS) as u32,
+            },
+            MaterialKind::Unlit => Self::default(),
+        }
+    }
+}
+
+/// The specialization constants of the mesh shaders that `key` draws with, for both stages: `OIT` and
+/// `OIT_DEPTH_SCALE`, `INDIRECT`, then `key.config`'s, and the bytes they are read from.
+fn mesh_specialization(key: MeshPipeline) -> ([vk::SpecializationMapEntry; 6], [u8; 24]) {
+    let flag = |on: bool| if on { vk::TRUE } else { vk::FALSE };
+    let words = [flag(key.depth == MeshDepth::Transparent), OIT_DEPTH_SCALE.to_bits(), flag(key.indirect),
+        flag(key.config.shadows), flag(key.config.normal_mapping), key.config.lights];
+    let mut bytes = [0; 24];
+    words.iter().enumerate().for_each(|(k, word)| bytes[4 * k..4 * k + 4].copy_from_slice(&word.to_ne_bytes()));
+    let entries = [0, 1, 2, 3, 4, 5].map(|k| vk::SpecializationMapEntry::builder()
+        .constant_id(k).offset(4 * k).size(4).build());
+    (entries, bytes)
+}
+
+/// Makes the pipeline of every mesh draw the next frame may record that has none yet.
+pub unsafe fn prepare_mesh_pipelines(device: &Device, data: &mut AppData) -> Result<()> {
+    // the surface in case a view draws it, the depth pre-pass's and transparency's variants if on, and
+    // the indirect draws'
+    let draws = mesh_draws(data, FluidRendering::Surface);
+    let missing = draws.iter().map(|(key, _)| *key).chain(prepass_draws(&draws).map(|(key, _)| key))
+        .chain(transparent_draws(data, FluidRendering::Surface).into_iter().map(|(key, _)| key))
+        .chain(indirect_pipelines(data))
+        .filter(|key| !data.mesh_pipelines.contains_key(key)).collect::<HashSet<_>>();
+    for key in missing {
+        let pipeline = create_mesh_pipeline(device, data, key)?;
+        data.mesh_pipelines.insert(key, pipeline);
+    }
+    Ok(())
+}
+
+pub unsafe fn destroy_mesh_pipelines(device: &Device, data: &mut AppData) {
+    data.mesh_pipelines.drain().for_each(|(_, pipeline)| device.destroy_pipeline(pipeline, None));
+}
+
+/// Remakes every mesh pipeline there is from its shaders as they are now, keeping the old ones if any
+/// of them fails. Nothing may be in flight.
+pub unsafe fn reload_mesh_pipelines(device: &Device, data: &mut AppData) -> Result<()> {
+    let mut fresh = HashMap::new();
+    for &key in data.mesh_pipelines.keys() {
+        match create_mesh_pipeline(device, data, key) {
+            Ok(pipeline) => fresh.insert(key, pipeline),
+            Err(e) => {
+                fresh.values().for_each(|pipeline| device.destroy_pipeline(*pipeline, None));
+                return Err(e);
+            }
+        };
+    }
+    destroy_mesh_pipelines(device, data);
+    data.mesh_pipelines = fresh;
+    Ok(())
+}
+
+unsafe fn create_mesh_pipeline(device: &Device, data: &AppData, key: MeshPipeline) -> Result<vk::Pipeline> {
     // compile shaders
-    let vshader = compile_shader(&data.vshader_path, shaderc::ShaderKind::Vertex)?;
-    let fshader = compile_shader(&data.fshader_path, shaderc::ShaderKind::Fragment)?;
+    let (vshader_path, fshader_path) = key.kind.shaders(data);
+    let vshader = compile_shader(&vshader_path, shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader_with(&fshader_path, shaderc::ShaderKind::Fragment, &[&max_lights_define()])?;
+    // held to the layout made at startup, which a reloaded shader may no longer fit
+    check_layout(&data.scene_bindings, &reflect_bindings(&vshader, vk::ShaderStageFlags::VERTEX)?, 2, &vshader_path)?;
+    check_layout(&data.scene_bindings, &reflect_bindings(&fshader, vk::ShaderStageFlags::FRAGMENT)?, 2, &fshader_path)?;
     let vert_shader_module = create_shader_module(device, &vshader.as_binary_u8()[..])?;
     let frag_shader_module = create_shader_module(device, &fshader.as_binary_u8()[..])?;
 
-    // build shader stages
+    // build shader stages, both specialized by the same constants
+    let (entries, constants) = mesh_specialization(key);
+    let specialization = vk::SpecializationInfo::builder().map_entries(&entries).data(&constants);
     let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
         .stage(vk::ShaderStageFlags::VERTEX)
         .module(vert_shader_module)
+        .specialization_info(&specialization)
         .name(b"main\0");
     let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
         .stage(vk::ShaderStageFlags::FRAGMENT)
         .module(frag_shader_module)
+        .specialization_info(&specialization)
         .name(b"main\0");
-    
-    // vertex input state
+
+    // vertex input state, as triangles or, for the selection markers, lines
     let binding_descs = &[Vertex::binding_description()];
     let attribute_descs = &Vertex::attribute_descriptions();
     let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
         .vertex_binding_descriptions(binding_descs)
         .vertex_attribute_descriptions(attribute_descs);
+    let topology = match key.primitive {
+        MeshPrimitive::Lines => vk::PrimitiveTopology::LINE_LIST,
+        _ => vk::PrimitiveTopology::TRIANGLE_LIST,
+    };
     let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+        .topology(topology)
         .primitive_restart_enable(false);
-    
-    // viewport & scissor
-    let viewport = vk::Viewport::builder().x(0.0).y(0.0)
-        .width(data.swapchain_extent.width as f32).height(data.swapchain_extent.height as f32)
-        .min_depth(0.0).max_depth(1.0);
-    let scissor = vk::Rect2D::builder().offset(vk::Offset2D{x: 0, y: 0}).extent(data.swapchain_extent);
-    let (viewports,  scissors) = (&[viewport], &[scissor]);
-    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewports(viewports).scissors(scissors);
-
-    // rasterization & multisample
+
+    // viewport & scissor, set as each view is drawn
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+
+    // rasterization & multisample; the wireframe draws the triangles' edges from both sides, as wide
+    // as it says
+    let (polygon_mode, cull_mode) = match key.primitive {
+        MeshPrimitive::Triangles => (vk::PolygonMode::FILL, vk::CullModeFlags::BACK),
+        MeshPrimitive::Wireframe => (vk::PolygonMode::LINE, vk::CullModeFlags::NONE),
+        MeshPrimitive::Lines => (vk::PolygonMode::FILL, vk::CullModeFlags::NONE),
+    };
     let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
         .depth_clamp_enable(false)
         .rasterizer_discard_enable(false)
-        .polygon_mode(vk::PolygonMode::FILL)
+        .polygon_mode(polygon_mode)
         .line_width(1.0)
-        .cull_mode(vk::CullModeFlags::BACK)
+        .cull_mode(cull_mode)
         .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
         .depth_bias_enable(false);
     let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
         .sample_shading_enable(false)
-        .rasterization_samples(vk::SampleCountFlags::_1);
-    
-    // blend
+        .rasterization_samples(data.msaa_samples);
+
+    // blend, writing no color in the depth pre-pass
+    let color_write_mask = match key.depth {
+        MeshDepth::Prepass => vk::ColorComponentFlags::empty(),
+        _ => vk::ColorComponentFlags::all(),
+    };
     let attachment = vk::PipelineColorBlendAttachmentState::builder()
-        .color_write_mask(vk::ColorComponentFlags::all())
+        .color_write_mask(color_write_mask)
         .blend_enable(true)
         .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
         .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
@@ -305,68 +563,177 @@ pub unsafe fn create_pipeline(device: &Device, data: &mut AppData) -> Result<()>
         .src_alpha_blend_factor(vk::BlendFactor::ONE)
         .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
         .alpha_blend_op(vk::BlendOp::ADD);
-    let attachments = &[attachment];
+    let attachments = match key.depth {
+        MeshDepth::Transparent => oit_blend_attachments().to_vec(),
+        _ => vec![attachment.build()],
+    };
     let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
         .logic_op_enable(false)
         .logic_op(vk::LogicOp::COPY)
-        .attachments(attachments)
+        .attachments(&attachments)
         .blend_constants([0.0, 0.0, 0.0, 0.0]);
-    
+
     // depth test
+    let (depth_write, compare_op) = match key.depth {
+        MeshDepth::Test | MeshDepth::Prepass => (true, vk::CompareOp::LESS),
+        MeshDepth::Equal => (false, vk::CompareOp::EQUAL),
+        MeshDepth::Transparent => (false, vk::CompareOp::LESS),
+    };
     let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
-        .depth_test_enable(true).depth_write_enable(true)
-        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .depth_test_enable(true).depth_write_enable(depth_write)
+        .depth_compare_op(compare_op).depth_bounds_test_enable(false)
         .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
 
-    
     // dynamic attrs
-    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::LINE_WIDTH];
+    let dynamic_states: &[vk::DynamicState] = match key.primitive {
+        MeshPrimitive::Wireframe => &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR, vk::DynamicState::LINE_WIDTH],
+        _ => &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR],
+    };
     let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
 
-    // create pipeline layout & pipeline
-    let set_layouts = &[data.descriptor_set_layout];
-    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts);
-    data.pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
-    let stages = &[vert_stage, frag_stage];
+    // the depth pre-pass needs no fragment shader
+    let stages: &[_] = if key.depth == MeshDepth::Prepass { &[vert_stage] } else { &[vert_stage, frag_stage] };
     let info = vk::GraphicsPipelineCreateInfo::builder()
         .stages(stages)
         .vertex_input_state(&vertex_input_state)
         .input_assembly_state(&input_assembly_state)
         .viewport_state(&viewport_state)
+        .dynamic_state(&dynamic_state)
         .rasterization_state(&rasterization_state)
         .multisample_state(&multisample_state)
         .depth_stencil_state(&depth_stencil_state)
         .color_blend_state(&color_blend_state)
         .layout(data.pipeline_layout)
-        .render_pass(data.render_pass)
+        .render_pass(if key.depth == MeshDepth::Transparent { data.oit_render_pass } else { data.render_pass })
         .subpass(0);
-    data.pipeline = device.create_graphics_pipelines(vk::PipelineCache::null(), &[info], None)?.0;
-    
+    let pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+
     // now, shaders is not used
     device.destroy_shader_module(vert_shader_module, None);
     device.destroy_shader_module(frag_shader_module, None);
-    Ok(())
+    Ok(pipeline)
+}
+
+/// Sizes `shader.frag`'s lights to the uniform's.
+pub(crate) fn max_lights_define() -> String {
+    format!("MAX_LIGHTS={}u", MAX_LIGHTS)
+}
+
+/// Compiles the GLSL at `shader_path` to SPIR-V, failing with shaderc's diagnostics if it does not.
+pub(crate) fn compile_shader(shader_path: &String, shader_kind: shaderc::ShaderKind) -> Result<Spirv>{
+    compile_shader_with(shader_path, shader_kind, &[])
 }
 
-fn compile_shader(shader_path: &String, shader_kind: shaderc::ShaderKind) -> Result<CompilationArtifact>{
+/// Compiles as `compile_shader` does, with `defines` given as `-D` would take them: `NAME` or `NAME=VALUE`.
+/// `#include "file"` is looked up next to the file including it, `#include <file>` in `SHADER_DIR`.
+pub(crate) fn compile_shader_with(shader_path: &String, shader_kind: shaderc::ShaderKind, defines: &[&str])
+    -> Result<Spirv> {
+    compile_cached(shader_path, shader_kind, defines, || compile_glsl(shader_path, shader_kind, defines))
+}
+
+fn compile_glsl(shader_path: &String, shader_kind: shaderc::ShaderKind, defines: &[&str]) -> Result<Vec<u8>> {
     let mut shader_file = File::open(Path::new(shader_path))?;
     let mut shader_buffer = String::new();
     shader_file.read_to_string(&mut shader_buffer)?;
-    let compiler = shaderc::Compiler::new().unwrap();
-    let options = shaderc::CompileOptions::new().unwrap();
-    let binary_result = compiler.compile_into_spirv(&shader_buffer, shader_kind, &shader_path, "main", Some(&options));
-    let binary_result = match binary_result {
-        Ok(binary_result) => binary_result,
-        Err(e) => {
-            let error_lines = e.to_string().split('\n').for_each(|line| println!("{}", line));
-            panic!("Compilation Error!");
+    // the file each include was last reached from, to name the chain of the one being resolved
+    let includers = RefCell::new(HashMap::<String, String>::new());
+    let compiler = shaderc::Compiler::new().ok_or_else(|| anyhow!("Failed to create the shader compiler."))?;
+    let mut options = shaderc::CompileOptions::new().ok_or_else(|| anyhow!("Failed to create the shader compile options."))?;
+    for define in defines {
+        let (name, value) = define.split_once('=').map_or((*define, None), |(name, value)| (name, Some(value)));
+        options.add_macro_definition(name, value);
+    }
+    options.set_include_callback(|name, kind, includer, _| {
+        let mut includers = includers.borrow_mut();
+        let mut chain = vec![includer.to_string()];
+        while let Some(parent) = includers.get(chain.last().unwrap()) {
+            chain.push(parent.clone());
+        }
+        chain.reverse();
+        let resolved = resolve_include(includer, name, kind);
+        if chain.contains(&resolved) {
+            return Err(format!("#include cycle: {} -> {}", chain.join(" -> "), resolved));
         }
+        let content = fs::read_to_string(&resolved)
+            .map_err(|e| format!("Cannot include {} from {}: {}", resolved, chain.join(" -> "), e))?;
+        includers.insert(resolved.clone(), includer.to_string());
+        Ok(shaderc::ResolvedInclude { resolved_name: resolved, content })
+    });
+    compiler.compile_into_spirv(&shader_buffer, shader_kind, shader_path, "main", Some(&options))
+        .map(|artifact| artifact.as_binary_u8().to_vec())
+        .map_err(|e| match e {
+            shaderc::Error::CompilationError(_, diagnostics) => ShaderCompileError::new(shader_path, diagnostics),
+            e => ShaderCompileError::new(shader_path, e.to_string()),
+        }.into())
+}
+
+/// The path `#include`d `name` is read from, in `includer`.
+fn resolve_include(includer: &str, name: &str, kind: shaderc::IncludeType) -> String {
+    let dir = match kind {
+        shaderc::IncludeType::Relative => Path::new(includer).parent().unwrap_or(Path::new("")),
+        shaderc::IncludeType::Standard => Path::new(SHADER_DIR),
     };
-    Ok(binary_result)
+    dir.join(name).to_string_lossy().into_owned()
+}
+
+/// Every file the shader at `shader_path` includes, directly or through another, as `compile_shader`
+/// resolves them.
+pub(crate) fn shader_includes(shader_path: &str) -> HashSet<String> {
+    let mut found = HashSet::new();
+    let mut pending = vec![shader_path.to_string()];
+    while let Some(path) = pending.pop() {
+        let Ok(source) = fs::read_to_string(&path) else {
+            continue;
+        };
+        for line in source.lines() {
+            let Some(include) = line.trim_start().strip_prefix('#').and_then(|l| l.trim_start().strip_prefix("include")) else {
+                continue;
+            };
+            let include = include.trim();
+            let name = match (include.strip_prefix('"'), include.strip_prefix('<')) {
+                (Some(name), _) => name.strip_suffix('"').map(|name| (name, shaderc::IncludeType::Relative)),
+                (_, Some(name)) => name.strip_suffix('>').map(|name| (name, shaderc::IncludeType::Standard)),
+                _ => None,
+            };
+            if let Some((name, kind)) = name {
+                let resolved = resolve_include(&path, name, kind);
+                if found.insert(resolved.clone()) {
+                    pending.push(resolved);
+                }
+            }
+        }
+    }
+    found
 }
 
 
-unsafe fn create_shader_module(device: &Device, bytecode: &[u8],) -> Result<ShaderModule> {
+/// Compute pipeline helpers
+/// `constants` specialize the shader's `constant_id = 0, 1, ...` in order.
+pub unsafe fn create_compute_pipeline(device: &Device, cache: vk::PipelineCache, layout: vk::PipelineLayout,
+    shader_path: &String, constants: &[u32]) -> Result<vk::Pipeline> {
+    let shader = compile_shader(shader_path, shaderc::ShaderKind::Compute)?;
+    let shader_module = create_shader_module(device, shader.as_binary_u8())?;
+    let map_entries = (0..constants.len()).map(|i| {
+        vk::SpecializationMapEntry::builder()
+            .constant_id(i as u32)
+            .offset((i * size_of::<u32>()) as u32)
+            .size(size_of::<u32>())
+            .build()
+    }).collect::<Vec<_>>();
+    let constant_bytes = std::slice::from_raw_parts(constants.as_ptr().cast::<u8>(), std::mem::size_of_val(constants));
+    let specialization = vk::SpecializationInfo::builder().map_entries(&map_entries).data(constant_bytes);
+    let stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::COMPUTE)
+        .module(shader_module)
+        .specialization_info(&specialization)
+        .name(b"main\0");
+    let info = vk::ComputePipelineCreateInfo::builder().stage(stage).layout(layout);
+    let pipeline = device.create_compute_pipelines(cache, &[info], None)?.0;
+    device.destroy_shader_module(shader_module, None);
+    Ok(pipeline)
+}
+
+pub(crate) unsafe fn create_shader_module(device: &Device, bytecode: &[u8],) -> Result<ShaderModule> {
     let bytecode = Vec::from(bytecode);
     let (prefix, code, suffix) = bytecode.align_to::<u32>();
     if !prefix.is_empty() || !suffix.is_empty() {
@@ -377,23 +744,38 @@ unsafe fn create_shader_module(device: &Device, bytecode: &[u8],) -> Result<Shad
 }
 
 pub unsafe fn create_render_pass(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
-    // Color attachment
+    // Color attachment, multisampled and resolved into the HDR image if `msaa_samples` says so, which
+    // the tonemapping pass then samples
+    let multisampled = data.msaa_samples != vk::SampleCountFlags::_1;
+    let (store_op, final_layout) = if multisampled {
+        (vk::AttachmentStoreOp::DONT_CARE, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+    } else {
+        (vk::AttachmentStoreOp::STORE, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    };
     let color_attachment = vk::AttachmentDescription::builder()
-        .format(data.swapchain_format).samples(vk::SampleCountFlags::_1)
-        .load_op(vk::AttachmentLoadOp::CLEAR).store_op(vk::AttachmentStoreOp::STORE)
+        .format(HDR_FORMAT).samples(data.msaa_samples)
+        .load_op(vk::AttachmentLoadOp::CLEAR).store_op(store_op)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE).stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED).final_layout(final_layout);
+    let resolve_attachment = vk::AttachmentDescription::builder()
+        .format(HDR_FORMAT).samples(vk::SampleCountFlags::_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE).store_op(vk::AttachmentStoreOp::STORE)
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE).stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-        .initial_layout(vk::ImageLayout::UNDEFINED).final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+        .initial_layout(vk::ImageLayout::UNDEFINED).final_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+    let resolve_attachment_ref = vk::AttachmentReference::builder().attachment(2)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+    let resolve_attachments = &[resolve_attachment_ref];
 
     let color_attachment_ref = vk::AttachmentReference::builder().attachment(0)
         .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
     let color_attachments = &[color_attachment_ref];
     
-    // Depth test
+    // Depth test, kept for the transparency pass to test against
     let depth_stencil_attachment = vk::AttachmentDescription::builder()
         .format(get_depth_format(instance, data)?)
-        .samples(vk::SampleCountFlags::_1)
+        .samples(data.msaa_samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
-        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::STORE)
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
         .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
@@ -403,10 +785,13 @@ pub unsafe fn create_render_pass(instance: &Instance, device: &Device, data: &mu
         .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
     
     // Subpasses
-    let subpass = vk::SubpassDescription::builder()
+    let mut subpass = vk::SubpassDescription::builder()
         .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
         .color_attachments(color_attachments)
         .depth_stencil_attachment(&depth_stencil_attachment_ref);
+    if multisampled {
+        subpass = subpass.resolve_attachments(resolve_attachments);
+    }
     let dependency = vk::SubpassDependency::builder()
         .src_subpass(vk::SUBPASS_EXTERNAL)
         .dst_subpass(0)
@@ -414,29 +799,41 @@ pub unsafe fn create_render_pass(instance: &Instance, device: &Device, data: &mu
         .src_access_mask(vk::AccessFlags::empty())
         .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
         .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE);
+    let after = vk::SubpassDependency::builder()
+        .src_subpass(0)
+        .dst_subpass(vk::SUBPASS_EXTERNAL)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+        .dst_access_mask(vk::AccessFlags::SHADER_READ);
 
     // Create
-    let attachments = &[color_attachment, depth_stencil_attachment];
+    let mut attachments = vec![color_attachment.build(), depth_stencil_attachment.build()];
+    if multisampled {
+        attachments.push(resolve_attachment.build());
+    }
     let subpasses = &[subpass];
-    let dependencies = &[dependency];
+    let dependencies = &[dependency, after];
     let info = vk::RenderPassCreateInfo::builder()
-        .attachments(attachments)
+        .attachments(&attachments)
         .subpasses(subpasses)
         .dependencies(dependencies);
     data.render_pass = device.create_render_pass(&info, None)?;
-
-
-    Ok(())
+    create_oit_render_pass(instance, device, data)
 }
 
-/// Frambebuffer helpers
+/// Frambebuffer helpers: one per swapchain image, though they all draw into the one HDR image
 pub unsafe fn create_framebuffers(device: &Device, data: &mut AppData) -> Result<()> {
     data.framebuffers = data.swapchain_image_views.iter()
-        .map(|i| {
-            let attachments = &[*i, data.depth_image_view];
+        .map(|_| {
+            let attachments = if data.msaa_samples == vk::SampleCountFlags::_1 {
+                vec![data.hdr_image_view, data.depth_image_view]
+            } else {
+                vec![data.msaa_color_image_view, data.msaa_depth_image_view, data.hdr_image_view]
+            };
             let create_info = vk::FramebufferCreateInfo::builder()
                 .render_pass(data.render_pass)
-                .attachments(attachments)
+                .attachments(&attachments)
                 .width(data.swapchain_extent.width)
                 .height(data.swapchain_extent.height)
                 .layers(1);
@@ -450,7 +847,7 @@ pub unsafe fn create_framebuffers(device: &Device, data: &mut AppData) -> Result
 pub unsafe fn create_command_pool(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
     let indices = QueueFamilyIndices::get(instance, data, data.physical_device)?;
     let info = vk::CommandPoolCreateInfo::builder()
-        .flags(vk::CommandPoolCreateFlags::empty())
+        .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
         .queue_family_index(indices.graphics);
     data.command_pool = device.create_command_pool(&info, None)?;
     Ok(())
@@ -462,45 +859,372 @@ pub unsafe fn create_command_buffers(device: &Device, data: &mut AppData) -> Res
         .level(vk::CommandBufferLevel::PRIMARY)
         .command_buffer_count(data.framebuffers.len() as u32);
     data.command_buffers = device.allocate_command_buffers(&allocate_info)?;
+    prepare_mesh_pipelines(device, data)?;
+    for i in 0..data.command_buffers.len() {
+        record_secondaries(device, data, i)?;
+        record_command_buffer(device, data, i)?;
+    }
+    Ok(())
+}
 
-    for (i, command_buffer) in data.command_buffers.iter().enumerate() {
-        let inheritance = vk::CommandBufferInheritanceInfo::builder();
-    
-        let inherit_info = vk::CommandBufferBeginInfo::builder()
-            .flags(vk::CommandBufferUsageFlags::empty())
-            .inheritance_info(&inheritance);
+/// Records the draw commands for swapchain image `i`. Re-run whenever object transforms change.
+pub unsafe fn record_command_buffer(device: &Device, data: &AppData, i: usize) -> Result<()> {
+    let command_buffer = &data.command_buffers[i];
+    let inheritance = vk::CommandBufferInheritanceInfo::builder();
+
+    let inherit_info = vk::CommandBufferBeginInfo::builder()
+        .flags(vk::CommandBufferUsageFlags::empty())
+        .inheritance_info(&inheritance);
+
+    let render_area = vk::Rect2D::builder().offset(vk::Offset2D::default()).extent(data.swapchain_extent);
+    // a grey of 0.2 in sRGB, in the linear light the HDR image holds
+    let color_clear_value = vk::ClearValue {
+        color: vk::ClearColorValue { float32: [0.029, 0.029, 0.029, 1.0] },
+    };
+    let depth_clear_value = vk::ClearValue {
+        depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 },
+    };
+    let clear_values = &[color_clear_value, depth_clear_value];
+    let screen_space = screen_space_drawn(data);
+    let (render_pass, framebuffer) = scene_target(data, i);
+    let render_info = vk::RenderPassBeginInfo::builder()
+        .render_pass(render_pass)
+        .framebuffer(framebuffer)
+        .render_area(render_area)
+        .clear_values(clear_values);
     
-        let render_area = vk::Rect2D::builder().offset(vk::Offset2D::default()).extent(data.swapchain_extent);
-        let color_clear_value = vk::ClearValue {
-            color: vk::ClearColorValue { float32: [0.2, 0.2, 0.2, 1.0] },
-        };
-        let depth_clear_value = vk::ClearValue {
-            depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 },
-        };
-        let clear_values = &[color_clear_value, depth_clear_value];
-        let render_info = vk::RenderPassBeginInfo::builder()
-            .render_pass(data.render_pass)
-            .framebuffer(data.framebuffers[i])
-            .render_area(render_area)
-            .clear_values(clear_values);
-        
-        // render pass!!
-        device.begin_command_buffer(*command_buffer, &inherit_info)?;
+    // render pass!!
+    device.begin_command_buffer(*command_buffer, &inherit_info)?;
+    begin_render_timestamps(device, data, *command_buffer, i);
+    // the cull of the objects' indirect draws, before any pass draws them
+    record_mesh_cull(device, data, *command_buffer, i);
+    // the shadow maps first, for the main pass to sample
+    record_shadow_pass(device, data, *command_buffer, i);
+    record_point_shadow_pass(device, data, *command_buffer, i);
+    // the occlusion of the ambient light the main pass shades with
+    record_ssao(device, data, *command_buffer, i);
+    if data.parallel_recording {
+        // the pass's draws recorded by `record_secondaries`
+        device.cmd_begin_render_pass(*command_buffer, &render_info, vk::SubpassContents::SECONDARY_COMMAND_BUFFERS);
+        device.cmd_execute_commands(*command_buffer, &data.secondaries[i].order);
+    } else {
         device.cmd_begin_render_pass(*command_buffer, &render_info, vk::SubpassContents::INLINE);
-        device.cmd_bind_pipeline(*command_buffer, vk::PipelineBindPoint::GRAPHICS, data.pipeline);
-        device.cmd_bind_descriptor_sets(*command_buffer, vk::PipelineBindPoint::GRAPHICS,
-            data.pipeline_layout, 0, &[data.descriptor_sets[i]], &[]);
-        for obj in &data.objects {
-            device.cmd_bind_vertex_buffers(*command_buffer, 0, &[obj.vertex_buffer], &[0]);
-            device.cmd_bind_index_buffer(*command_buffer, obj.index_buffer, 0, vk::IndexType::UINT32);
-            device.cmd_draw_indexed(*command_buffer, obj.indices.len() as u32, 1, 0, 0, 0);
+        for (ubo, viewport, rendering) in scene_views(data, i) {
+            record_view(device, data, *command_buffer, ubo, &viewport, rendering);
         }
-        device.cmd_end_render_pass(*command_buffer);  // device.cmd_begin_render_pass
-        device.end_command_buffer(*command_buffer)?;  // device.begin_command_buffer
+        record_pass_overlays(device, data, *command_buffer, i);
+    }
+    device.cmd_end_render_pass(*command_buffer);  // device.cmd_begin_render_pass
+    // the translucent meshes and particles over the opaque scene, if weighted rather than blended in it
+    record_oit(device, data, *command_buffer, i);
+    if screen_space {
+        record_screen_space_fluid(device, data, *command_buffer, i);
     }
+    record_bloom(device, data, *command_buffer);
+    record_tonemap(device, data, *command_buffer, i);
+    // the debug view over the finished frame
+    record_debug_view(device, data, *command_buffer, i);
+    end_render_timestamps(device, data, *command_buffer, i);
+    device.end_command_buffer(*command_buffer)?;  // device.begin_command_buffer
     Ok(())
 }
 
+/// Records what is drawn over every view: the colormap's legend, when the particles are drawn through
+/// it, then the axis gizmo in its own corner viewport.
+pub(crate) unsafe fn record_pass_overlays(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    record_legend_draw(device, data, command_buffer);
+    record_gizmo_draw(device, data, command_buffer, i);
+}
+
+/// Whether the screen-space fluid is drawn, which needs the scene drawn somewhere it can sample and
+/// draws over it itself.
+pub(crate) fn screen_space_drawn(data: &AppData) -> bool {
+    data.fluid_rendering == FluidRendering::ScreenSpace && data.split_screen.is_none()
+}
+
+/// The render pass the scene of swapchain image `i` is drawn in and the framebuffer it draws into.
+pub(crate) fn scene_target(data: &AppData, i: usize) -> (vk::RenderPass, vk::Framebuffer) {
+    if screen_space_drawn(data) {
+        (data.scene_render_pass, data.scene_framebuffer)
+    } else {
+        (data.render_pass, data.framebuffers[i])
+    }
+}
+
+/// A block of the object buffer, laid out like `ObjectConstants` in `shader.vert` and `shader.frag`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct ObjectConstants {
+    transform: glm::Mat4,
+    diffuse: glm::Vec3,
+    textured: u32,
+    ambient: glm::Vec3,
+    shininess: f32,
+    specular: glm::Vec3,
+    /// Slot of `normalMaps` the draw's normals are perturbed by, or -1 for none.
+    normal_map: i32,
+    opacity: f32,
+}
+
+/// Bytes of an `ObjectConstants` in the shaders' std430 array of them, which the object buffer is
+/// also bound as.
+pub(crate) const OBJECT_ELEMENT: usize = 128;
+
+/// Hands out the blocks of a frame's object buffer one after another, each aligned for a dynamic offset.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ObjectBlocks {
+    /// Bytes from one block to the next.
+    pub stride: u64,
+    pub capacity: u32,
+    /// Blocks handed out since the last `reset`.
+    pub used: u32,
+}
+
+impl ObjectBlocks {
+    /// Blocks of `ObjectConstants` at offsets that are multiples of `alignment`, `capacity` of them, and
+    /// of `OBJECT_ELEMENT` for the shaders to index the buffer by.
+    pub fn new(alignment: u64, capacity: u32) -> Self {
+        let alignment = alignment.max(OBJECT_ELEMENT as u64);
+        let stride = (size_of::<ObjectConstants>() as u64).div_ceil(alignment) * alignment;
+        Self { stride, capacity, used: 0 }
+    }
+
+    /// Bytes of the buffer the blocks are in.
+    pub fn size(&self) -> u64 {
+        self.stride * self.capacity as u64
+    }
+
+    pub fn reset(&mut self) {
+        self.used = 0;
+    }
+
+    /// The offset of the next free block, or `None` when they are all taken.
+    pub fn allocate(&mut self) -> Option<u32> {
+        (self.used < self.capacity).then(|| {
+            self.used += 1;
+            ((self.used - 1) as u64 * self.stride) as u32
+        })
+    }
+}
+
+/// Writes the transform and material of every mesh draw, the objects', the fluid surface's and the
+/// selection markers', to blocks of swapchain image `image`'s object buffer, noting where on each mesh.
+pub unsafe fn update_object_blocks(device: &Device, data: &mut AppData, image: usize) -> Result<()> {
+    data.object_blocks.reset();
+    let blocks = &mut data.object_blocks;
+    let memory = data.object_buffers_memory[image];
+    let base = device.map_memory(memory, 0, blocks.size(), vk::MemoryMapFlags::empty())?.cast::<u8>();
+    let meshes = data.objects.iter_mut().chain([&mut data.fluid_surface, &mut data.selection_marker,
+        &mut data.smoothing_sphere_marker]);
+    let mut result = Ok(());
+    for obj in meshes {
+        obj.block_offsets.clear();
+        for submesh in obj.draws() {
+            let Some(offset) = blocks.allocate() else {
+                result = Err(anyhow!("More than {} mesh draws in a frame.", blocks.capacity));
+                break;
+            };
+            let material = submesh.material;
+            let constants = ObjectConstants { transform: obj.transform, diffuse: material.diffuse,
+                textured: obj.textured as u32, ambient: material.ambient, shininess: material.shininess,
+                specular: material.specular, normal_map: material.normal_map.map_or(-1, |slot| slot as i32),
+                opacity: obj.opacity.unwrap_or(material.opacity) };
+            memcpy(&constants, base.add(offset as usize).cast(), 1);
+            obj.block_offsets.push(offset);
+        }
+    }
+    device.unmap_memory(memory);
+    result
+}
+
+/// Binds the object set of the frame being drawn into swapchain image `image` at `offset`, for the draw
+/// whose constants are there.
+pub(crate) unsafe fn record_object_block(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, image: usize,
+    offset: u32) {
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.pipeline_layout, 1,
+        &[data.object_sets[image]], &[offset]);
+}
+
+/// The meshes drawn into a view with the fluid drawn as `rendering`, the objects', the fluid surface's
+/// and the selection markers', each with the pipeline it is drawn through and sorted by it.
+pub(crate) fn mesh_draws(data: &AppData, rendering: FluidRendering) -> Vec<(MeshPipeline, &Object)> {
+    // the fluid surface sits in the frame the fluid is simulated in, undoing the scene model the mesh shader applies
+    let surface = (rendering == FluidRendering::Surface).then_some(&data.fluid_surface);
+    // the box around the selected particle if there is one, and the sphere its neighbors lie within
+    let sphere = data.smoothing_sphere.then_some(&data.smoothing_sphere_marker);
+    let markers = data.selected_id.map(|_| [Some(&data.selection_marker), sphere]).into_iter().flatten().flatten();
+    let wireframe = data.mesh_wireframe.is_some() && data.fill_mode_non_solid;
+    let filled = if data.depth_prepass { MeshDepth::Equal } else { MeshDepth::Test };
+    let mut draws = data.objects.iter().chain(surface).chain(markers).filter(|o| !o.indices.is_empty())
+        .map(|obj| {
+            let (primitive, depth) = match (obj.line_list, wireframe) {
+                (true, _) => (MeshPrimitive::Lines, MeshDepth::Test),
+                (false, true) => (MeshPrimitive::Wireframe, MeshDepth::Test),
+                (false, false) => (MeshPrimitive::Triangles, filled),
+            };
+            (MeshPipeline { kind: obj.material_kind, primitive, depth, indirect: false,
+                config: ShaderConfig::of(data, obj.material_kind) }, obj)
+        })
+        .collect::<Vec<_>>();
+    draws.sort_by_key(|(key, _)| *key);
+    draws
+}
+
+/// The draws of `mesh_draws` the camera's views make, those of the culled objects left out.
+pub(crate) fn view_draws(data: &AppData, rendering: FluidRendering) -> Vec<(MeshPipeline, &Object)> {
+    mesh_draws(data, rendering).into_iter().filter(|(_, obj)| !obj.culled).collect()
+}
+
+/// The draws weighted into the transparency targets of the objects among `view_draws` with translucent
+/// submeshes, none unless the targets are drawn into.
+pub(crate) fn transparent_draws(data: &AppData, rendering: FluidRendering) -> Vec<(MeshPipeline, &Object)> {
+    if !oit_active(data) {
+        return Vec::new();
+    }
+    view_draws(data, rendering).into_iter()
+        .filter(|(key, obj)| key.primitive == MeshPrimitive::Triangles && obj.draws().iter().any(|s| obj.translucent(s)))
+        .map(|(key, obj)| (MeshPipeline { depth: MeshDepth::Transparent, ..key }, obj))
+        .collect()
+}
+
+/// The depth pre-pass's draws of `draws`, the filled meshes shaded over the depth it leaves.
+pub(crate) fn prepass_draws<'a: 'b, 'b>(draws: &'b [(MeshPipeline, &'a Object)])
+    -> impl Iterator<Item = (MeshPipeline, &'a Object)> + 'b {
+    draws.iter().filter(|(key, _)| key.depth == MeshDepth::Equal)
+        .map(|&(key, obj)| (MeshPipeline { depth: MeshDepth::Prepass, ..key }, obj))
+}
+
+/// The views swapchain image `i` is drawn as: the uniform buffer of each one's camera, the viewport it
+/// fills and how it draws the fluid.
+pub(crate) fn scene_views(data: &AppData, i: usize) -> Vec<(usize, vk::Viewport, FluidRendering)> {
+    match &data.split_screen {
+        None => vec![(i, full_viewport(data.swapchain_extent), data.fluid_rendering)],
+        Some(split) => {
+            // each half through its own camera, the right one's in the uniform buffers after the left's
+            let [left, right] = split.viewports(data.swapchain_extent);
+            let shown = |rendering| match rendering {
+                FluidRendering::ScreenSpace => FluidRendering::Particles,
+                rendering => rendering,
+            };
+            vec![(i, left, shown(data.fluid_rendering)),
+                (data.swapchain_images.len() + i, right, shown(split.right))]
+        }
+    }
+}
+
+/// Records the draws of the scene as seen through the camera in uniform buffer `i`, into `viewport` of
+/// the render pass begun, with the fluid drawn as `rendering`.
+unsafe fn record_view(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize,
+    viewport: &vk::Viewport, rendering: FluidRendering) {
+    // the right view's uniform buffers follow the left's, but both draw the image's objects
+    let image = i % data.swapchain_images.len();
+    record_view_background(device, data, command_buffer, i, viewport);
+    let draws = direct_draws(data, rendering);
+    if data.depth_prepass {
+        // the opaque meshes' and particles' depth first, for each pixel to be shaded once below
+        bind_scene_set(device, data, command_buffer, i);
+        record_mesh_draws(device, data, command_buffer, image, prepass_draws(&draws));
+        record_view_prepass(device, data, command_buffer, i, rendering);
+    }
+    bind_scene_set(device, data, command_buffer, i);
+    record_mesh_draws(device, data, command_buffer, image, draws.into_iter());
+    record_view_overlays(device, data, command_buffer, i, rendering);
+}
+
+/// The draws of `view_draws` recorded one by one, the objects' filled meshes left out if they are among
+/// the indirect draws, which come after the rest.
+pub(crate) fn direct_draws(data: &AppData, rendering: FluidRendering) -> Vec<(MeshPipeline, &Object)> {
+    view_draws(data, rendering).into_iter().filter(|(key, obj)| !drawn_indirectly(data, key, obj)).collect()
+}
+
+pub(crate) unsafe fn bind_scene_set(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.pipeline_layout, 0, &[data.descriptor_sets[i]], &[]);
+}
+
+/// Sets `viewport` and records what is drawn behind the meshes in it: the sky behind everything, then
+/// the ground under everything else.
+pub(crate) unsafe fn record_view_background(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    i: usize, viewport: &vk::Viewport) {
+    device.cmd_set_viewport(command_buffer, 0, &[*viewport]);
+    device.cmd_set_scissor(command_buffer, 0, &[viewport_scissor(viewport)]);
+    record_skybox_draw(device, data, command_buffer, i);
+    record_ground_draw(device, data, command_buffer, i);
+}
+
+/// Records the depth pre-pass's draws after the meshes', the indirect draws' and the particles'; the
+/// scene's set is bound already.
+pub(crate) unsafe fn record_view_prepass(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    i: usize, rendering: FluidRendering) {
+    record_indirect_draws(device, data, command_buffer, i % data.swapchain_images.len(), true);
+    if rendering == FluidRendering::Particles {
+        record_particle_prepass(device, data, command_buffer, i);
+    }
+}
+
+/// Records everything a view draws after the meshes: the indirect draws, the fluid and what is laid
+/// over it; the scene's set is bound already.
+pub(crate) unsafe fn record_view_overlays(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer,
+    i: usize, rendering: FluidRendering) {
+    record_indirect_draws(device, data, command_buffer, i % data.swapchain_images.len(), false);
+    // the particles, after the draws sharing `pipeline_layout`, or the raymarched surface in their place
+    if rendering == FluidRendering::Particles {
+        record_particle_draw(device, data, command_buffer, i);
+    } else if rendering == FluidRendering::Raymarched {
+        record_raymarch_draw(device, data, command_buffer, i);
+    }
+    // the domain and region boxes, which test the scene's depth without hiding the fluid inside them
+    record_wire_draw(device, data, command_buffer, i);
+    record_normal_draw(device, data, command_buffer, i, rendering);
+    // the slice plane blended over the opaque scene, then the trails and velocity glyphs over whichever
+    // fluid is drawn
+    record_slice_draw(device, data, command_buffer, i);
+    if data.trails.is_some() {
+        record_trail_draw(device, data, command_buffer, i);
+    }
+    if let Some(glyphs) = &data.velocity_glyphs {
+        record_glyph_draw(device, data, command_buffer, i, glyphs);
+    }
+    // the occupied grid cells, over the fluid they hold
+    if let Some(occupancy) = &data.grid_occupancy {
+        record_occupancy_draw(device, data, command_buffer, i, occupancy);
+    }
+    // the foam last, its light added over the scene but hidden behind what stands in front of it, and
+    // seen through the screen-space fluid like the rest of the scene
+    if let Some(foam) = &data.foam {
+        record_foam_draw(device, data, command_buffer, i, foam);
+    }
+}
+
+/// Records `draws` into swapchain image `image`'s frame, binding each one's pipeline as it changes; the
+/// scene's set is bound already.
+pub(crate) unsafe fn record_mesh_draws<'a>(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, image: usize,
+    draws: impl Iterator<Item = (MeshPipeline, &'a Object)>) {
+    let mut bound = None;
+    for (key, obj) in draws {
+        if bound != Some(key) {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.mesh_pipelines[&key]);
+            if let (MeshPrimitive::Wireframe, Some(wireframe)) = (key.primitive, data.mesh_wireframe) {
+                let [min_width, max_width] = data.line_width_range;
+                device.cmd_set_line_width(command_buffer, wireframe.width.clamp(min_width, max_width));
+            }
+            bound = Some(key);
+        }
+        device.cmd_bind_vertex_buffers(command_buffer, 0, &[obj.vertex_buffer], &[0]);
+        device.cmd_bind_index_buffer(command_buffer, obj.index_buffer, 0, vk::IndexType::UINT32);
+        // with the transparency targets drawn into, the translucent submeshes are drawn there alone
+        let weighted = key.primitive == MeshPrimitive::Triangles && oit_active(data);
+        for (submesh, offset) in obj.draws().iter().zip(&obj.block_offsets) {
+            if weighted && obj.translucent(submesh) != (key.depth == MeshDepth::Transparent) {
+                continue;
+            }
+            record_object_block(device, data, command_buffer, image, *offset);
+            device.cmd_draw_indexed(command_buffer, submesh.index_count, 1, obj.base_index + submesh.first_index,
+                obj.base_vertex, 0);
+        }
+    }
+}
+
 pub unsafe fn create_sync_objects(device: &Device, data: &mut AppData) -> Result<()> {
     let semaphore_info = vk::SemaphoreCreateInfo::builder();
     let fence_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
@@ -509,6 +1233,10 @@ pub unsafe fn create_sync_objects(device: &Device, data: &mut AppData) -> Result
             .push(device.create_semaphore(&semaphore_info, None)?);
         data.render_finished_semaphores
             .push(device.create_semaphore(&semaphore_info, None)?);
+        data.compute_finished_semaphores
+            .push(device.create_semaphore(&semaphore_info, None)?);
+        data.graphics_finished_semaphores
+            .push(device.create_semaphore(&semaphore_info, None)?);
         data.in_flight_fences.push(device.create_fence(&fence_info, None)?);
     }
     data.images_in_flight = data.swapchain_images.iter().map(|_| vk::Fence::null()).collect();
@@ -516,14 +1244,38 @@ pub unsafe fn create_sync_objects(device: &Device, data: &mut AppData) -> Result
 }
 
 /// Vertex buffer helpers
-unsafe fn create_buffer(instance: &Instance, device: &Device, data: &AppData,
+pub unsafe fn create_buffer(instance: &Instance, device: &Device, data: &AppData,
     size: vk::DeviceSize, usage: vk::BufferUsageFlags, properties: vk::MemoryPropertyFlags,
 ) -> Result<(vk::Buffer, vk::DeviceMemory)> {
     let buffer_info = vk::BufferCreateInfo::builder()
         .size(size)
         .usage(usage)
         .sharing_mode(vk::SharingMode::EXCLUSIVE);
-    let buffer = device.create_buffer(&buffer_info, None)?;
+    allocate_buffer(instance, device, data, &buffer_info, properties)
+}
+
+/// Like `create_buffer`, but usable from both the graphics and the async compute queue without
+/// ownership transfers (CONCURRENT sharing when the two are different families).
+pub unsafe fn create_shared_buffer(instance: &Instance, device: &Device, data: &AppData,
+    size: vk::DeviceSize, usage: vk::BufferUsageFlags, properties: vk::MemoryPropertyFlags,
+) -> Result<(vk::Buffer, vk::DeviceMemory)> {
+    let sharing_mode = if data.shared_queue_families.len() > 1 {
+        vk::SharingMode::CONCURRENT
+    } else {
+        vk::SharingMode::EXCLUSIVE
+    };
+    let buffer_info = vk::BufferCreateInfo::builder()
+        .size(size)
+        .usage(usage)
+        .sharing_mode(sharing_mode)
+        .queue_family_indices(&data.shared_queue_families);
+    allocate_buffer(instance, device, data, &buffer_info, properties)
+}
+
+unsafe fn allocate_buffer(instance: &Instance, device: &Device, data: &AppData,
+    buffer_info: &vk::BufferCreateInfo, properties: vk::MemoryPropertyFlags,
+) -> Result<(vk::Buffer, vk::DeviceMemory)> {
+    let buffer = device.create_buffer(buffer_info, None)?;
     let requirements = device.get_buffer_memory_requirements(buffer);
     let memory_info = vk::MemoryAllocateInfo::builder()
         .allocation_size(requirements.size)
@@ -593,7 +1345,7 @@ pub unsafe fn create_vertex_buffer(instance: &Instance, device: &Device, data: &
     Ok(())
 }
 
-unsafe fn copy_buffer(device: &Device, data: &AppData,
+pub unsafe fn copy_buffer(device: &Device, data: &AppData,
     source: vk::Buffer, destination: vk::Buffer, size: vk::DeviceSize,
 ) -> Result<()> {
     let command_buffer = begin_single_time_commands(device, data)?;
@@ -617,15 +1369,48 @@ unsafe fn get_memory_type_index(instance: &Instance, data: &AppData,
 }
 
 /// Uniform buffer helpers
+/// The shaders the scene's and object sets' layouts are made from, between them declaring every binding
+/// of either: the mesh pipelines' and the skybox's. The rest binding the scene's set only read its uniforms.
+fn scene_set_shaders(data: &AppData) -> Vec<(String, shaderc::ShaderKind, vk::ShaderStageFlags)> {
+    let mut shaders = vec![
+        (SKYBOX_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex, vk::ShaderStageFlags::VERTEX),
+        (SKYBOX_FRAGMENT_SHADER.to_string(), shaderc::ShaderKind::Fragment, vk::ShaderStageFlags::FRAGMENT),
+    ];
+    for kind in [MaterialKind::Lit, MaterialKind::Unlit] {
+        let (vshader_path, fshader_path) = kind.shaders(data);
+        shaders.push((vshader_path, shaderc::ShaderKind::Vertex, vk::ShaderStageFlags::VERTEX));
+        shaders.push((fshader_path, shaderc::ShaderKind::Fragment, vk::ShaderStageFlags::FRAGMENT));
+    }
+    shaders
+}
+
+/// What the scene's sets and the object sets are written with; see `create_descriptor_sets`.
+fn scene_set_writes() -> Vec<BindingWrite> {
+    let write = |set, binding, count, range: Option<usize>| BindingWrite { set, binding, count, range: range.map(|r| r as u64) };
+    let mut writes = vec![write(0, 0, 1, Some(size_of::<UniformBufferObject>()))];
+    // the mesh texture, the skybox, the two shadow maps, the normal maps and the ambient occlusion
+    writes.extend((1..=6).map(|binding| write(0, binding, if binding == 5 { NORMAL_MAP_SLOTS as u32 } else { 1 }, None)));
+    writes.extend([write(1, 0, 1, Some(size_of::<ObjectConstants>())), write(1, 1, 1, None)]);
+    writes
+}
+
+/// Makes the scene's set and the object set layouts from the bindings `scene_set_shaders` declare, the
+/// object constants bound at each draw's offset, after checking them against what the app writes.
 pub unsafe fn create_descriptor_set_layout(device: &Device, data: &mut AppData) -> Result<()> {
-    let ubo_binding = vk::DescriptorSetLayoutBinding::builder()
-        .binding(0)
-        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-        .descriptor_count(1)
-        .stage_flags(vk::ShaderStageFlags::VERTEX);
-    let bindings = &[ubo_binding];
-    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(bindings);
+    let mut bindings = Vec::new();
+    for (path, kind, stage) in scene_set_shaders(data) {
+        let spirv = compile_shader_with(&path, kind, &[&max_lights_define()])?;
+        merge_bindings(&mut bindings, reflect_bindings(&spirv, stage)?, &path)?;
+    }
+    make_dynamic(&mut bindings, 1, 0);
+    check_writes(&bindings, &scene_set_writes())?;
+    let scene_bindings = set_layout_bindings(&bindings, 0);
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&scene_bindings);
     data.descriptor_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    let object_bindings = set_layout_bindings(&bindings, 1);
+    let info = vk::DescriptorSetLayoutCreateInfo::builder().bindings(&object_bindings);
+    data.object_set_layout = device.create_descriptor_set_layout(&info, None)?;
+    data.scene_bindings = bindings;
     Ok(())
 }
 
@@ -633,7 +1418,8 @@ pub unsafe fn create_uniform_buffers(instance: &Instance, device: &Device, data:
     data.uniform_buffers.clear();
     data.uniform_buffers_memory.clear();
 
-    for _ in 0..data.swapchain_images.len() {
+    // the split screen's right view's after the rest
+    for _ in 0..2 * data.swapchain_images.len() {
         let (uniform_buffer, uniform_buffer_memory) = create_buffer(
             instance, device, data,
             size_of::<UniformBufferObject>() as u64,
@@ -644,28 +1430,43 @@ pub unsafe fn create_uniform_buffers(instance: &Instance, device: &Device, data:
         data.uniform_buffers_memory.push(uniform_buffer_memory);
     }
 
+    // the objects are the same in both halves of the split screen, so one object buffer per image
+    let alignment = instance.get_physical_device_properties(data.physical_device).limits.min_uniform_buffer_offset_alignment;
+    data.object_blocks = ObjectBlocks::new(alignment, OBJECT_BLOCKS);
+    data.object_buffers.clear();
+    data.object_buffers_memory.clear();
+    for _ in 0..data.swapchain_images.len() {
+        let (buffer, memory) = create_buffer(instance, device, data, data.object_blocks.size(),
+            vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE)?;
+        data.object_buffers.push(buffer);
+        data.object_buffers_memory.push(memory);
+    }
     Ok(())
 }
 
 pub unsafe fn create_descriptor_pool(device: &Device, data: &mut AppData) -> Result<()> {
-    let ubo_size = vk::DescriptorPoolSize::builder()
-        .type_(vk::DescriptorType::UNIFORM_BUFFER)
-        .descriptor_count(data.swapchain_images.len() as u32);
-    let pool_sizes = &[ubo_size];
+    let mut sizes = pool_sizes(&data.scene_bindings, 0, data.uniform_buffers.len() as u32);
+    sizes.extend(pool_sizes(&data.scene_bindings, 1, data.object_buffers.len() as u32));
     let info = vk::DescriptorPoolCreateInfo::builder()
-        .pool_sizes(pool_sizes)
-        .max_sets(data.swapchain_images.len() as u32);
+        .pool_sizes(&sizes)
+        .max_sets((data.uniform_buffers.len() + data.object_buffers.len()) as u32);
     data.descriptor_pool = device.create_descriptor_pool(&info, None)?;
     Ok(())
 }
 
 pub unsafe fn create_descriptor_sets(device: &Device, data: &mut AppData) -> Result<()> {
-    let layouts = vec![data.descriptor_set_layout; data.swapchain_images.len()];
+    let layouts = vec![data.descriptor_set_layout; data.uniform_buffers.len()];
     let info = vk::DescriptorSetAllocateInfo::builder()
         .descriptor_pool(data.descriptor_pool)
         .set_layouts(&layouts);
     data.descriptor_sets = device.allocate_descriptor_sets(&info)?;
-    for i in 0..data.swapchain_images.len() {
+    write_mesh_texture(device, data);
+    write_skybox(device, data);
+    write_shadow_map(device, data);
+    write_point_shadow_map(device, data);
+    write_normal_maps(device, data);
+    for i in 0..data.uniform_buffers.len() {
         let info = vk::DescriptorBufferInfo::builder()
             .buffer(data.uniform_buffers[i])
             .offset(0)
@@ -676,15 +1477,45 @@ pub unsafe fn create_descriptor_sets(device: &Device, data: &mut AppData) -> Res
             .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER).buffer_info(buffer_info);
         device.update_descriptor_sets(&[ubo_write], &[] as &[vk::CopyDescriptorSet]);
     }
+    let layouts = vec![data.object_set_layout; data.object_buffers.len()];
+    let info = vk::DescriptorSetAllocateInfo::builder()
+        .descriptor_pool(data.descriptor_pool)
+        .set_layouts(&layouts);
+    data.object_sets = device.allocate_descriptor_sets(&info)?;
+    // a block's worth, at whichever offset each draw binds
+    for (set, buffer) in data.object_sets.iter().zip(&data.object_buffers) {
+        let buffer_info = &[vk::DescriptorBufferInfo::builder()
+            .buffer(*buffer).offset(0).range(size_of::<ObjectConstants>() as u64)];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(*set).dst_binding(0).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC).buffer_info(buffer_info);
+        let blocks_info = &[vk::DescriptorBufferInfo::builder().buffer(*buffer).offset(0).range(vk::WHOLE_SIZE as u64)];
+        let blocks_write = vk::WriteDescriptorSet::builder()
+            .dst_set(*set).dst_binding(1).dst_array_element(0)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER).buffer_info(blocks_info);
+        device.update_descriptor_sets(&[write, blocks_write], &[] as &[vk::CopyDescriptorSet]);
+    }
     Ok(())
 }
 
+/// Binds the mesh texture to every set of `descriptor_sets`.
+pub unsafe fn write_mesh_texture(device: &Device, data: &AppData) {
+    let image_info = &[vk::DescriptorImageInfo::builder()
+        .sampler(data.mesh_texture.sampler).image_view(data.mesh_texture.view)
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)];
+    let writes = data.descriptor_sets.iter().map(|set| vk::WriteDescriptorSet::builder()
+        .dst_set(*set).dst_binding(1).dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER).image_info(image_info).build())
+        .collect::<Vec<_>>();
+    device.update_descriptor_sets(&writes, &[] as &[vk::CopyDescriptorSet]);
+}
+
 /// Depth test helpers
 pub unsafe fn create_depth_objects(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
     let format = get_depth_format(instance, data)?;
     let (depth_image, depth_image_memory) = create_image(
         instance, device, data, data.swapchain_extent.width, data.swapchain_extent.height,
-        format, vk::ImageTiling::OPTIMAL, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        format, vk::ImageTiling::OPTIMAL, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
         vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
     
     data.depth_image = depth_image;
@@ -694,10 +1525,65 @@ pub unsafe fn create_depth_objects(instance: &Instance, device: &Device, data: &
     Ok(())
 }
 
-unsafe fn get_depth_format(instance: &Instance, data: &AppData) -> Result<vk::Format> {
+/// Makes the multisampled color and depth targets the main pass draws into and resolves from, unless
+/// `msaa_samples` is 1 and it draws straight into the swapchain image and `depth_image`.
+pub unsafe fn create_msaa_objects(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    if data.msaa_samples == vk::SampleCountFlags::_1 {
+        return Ok(());
+    }
+    let target = |format: vk::Format, usage: vk::ImageUsageFlags, aspect: vk::ImageAspectFlags| {
+        let info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::_2D)
+            .extent(vk::Extent3D { width: data.swapchain_extent.width, height: data.swapchain_extent.height, depth: 1 })
+            .mip_levels(1).array_layers(1).format(format)
+            .tiling(vk::ImageTiling::OPTIMAL).initial_layout(vk::ImageLayout::UNDEFINED)
+            .usage(usage | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT).samples(data.msaa_samples)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let (image, memory) = allocate_image(instance, device, data, &info, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+        Ok::<_, anyhow::Error>((image, memory, create_image_view(device, image, format, aspect)?))
+    };
+    let color = target(HDR_FORMAT, vk::ImageUsageFlags::COLOR_ATTACHMENT, vk::ImageAspectFlags::COLOR)?;
+    let depth = target(get_depth_format(instance, data)?, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        vk::ImageAspectFlags::DEPTH)?;
+    (data.msaa_color_image, data.msaa_color_image_memory, data.msaa_color_image_view) = color;
+    (data.msaa_depth_image, data.msaa_depth_image_memory, data.msaa_depth_image_view) = depth;
+    Ok(())
+}
+
+pub unsafe fn destroy_msaa_objects(device: &Device, data: &mut AppData) {
+    for view in [data.msaa_color_image_view, data.msaa_depth_image_view] {
+        device.destroy_image_view(view, None);
+    }
+    for image in [data.msaa_color_image, data.msaa_depth_image] {
+        device.destroy_image(image, None);
+    }
+    for memory in [data.msaa_color_image_memory, data.msaa_depth_image_memory] {
+        device.free_memory(memory, None);
+    }
+    data.msaa_color_image_view = vk::ImageView::null();
+    data.msaa_depth_image_view = vk::ImageView::null();
+    data.msaa_color_image = vk::Image::null();
+    data.msaa_depth_image = vk::Image::null();
+    data.msaa_color_image_memory = vk::DeviceMemory::null();
+    data.msaa_depth_image_memory = vk::DeviceMemory::null();
+}
+
+/// The sample count the main pass multisamples with for `samples` per pixel: the most up to it that the
+/// device supports for both color and depth attachments.
+pub unsafe fn msaa_sample_count(instance: &Instance, data: &AppData, samples: u32) -> vk::SampleCountFlags {
+    let limits = instance.get_physical_device_properties(data.physical_device).limits;
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+    use vk::SampleCountFlags as Count;
+    [Count::_64, Count::_32, Count::_16, Count::_8, Count::_4, Count::_2].into_iter()
+        .find(|count| count.bits() <= samples && supported.contains(*count))
+        .unwrap_or(Count::_1)
+}
+
+pub(crate) unsafe fn get_depth_format(instance: &Instance, data: &AppData) -> Result<vk::Format> {
+    // sampled too, for the debug view
     let candidates = &[vk::Format::D32_SFLOAT, vk::Format::D32_SFLOAT_S8_UINT, vk::Format::D24_UNORM_S8_UINT];
-    get_supported_format(instance, data, candidates,
-        vk::ImageTiling::OPTIMAL, vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+    get_supported_format(instance, data, candidates, vk::ImageTiling::OPTIMAL,
+        vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT | vk::FormatFeatureFlags::SAMPLED_IMAGE)
 }
 
 unsafe fn get_supported_format(instance: &Instance, data: &AppData,
@@ -720,7 +1606,6 @@ pub unsafe fn create_image(instance: &Instance, device: &Device, data: &AppData,
     width: u32, height: u32, format: vk::Format, tiling: vk::ImageTiling,
     usage: vk::ImageUsageFlags, properties: vk::MemoryPropertyFlags
 ) -> Result<(vk::Image, vk::DeviceMemory)> {
-    // Image
     let info = vk::ImageCreateInfo::builder()
         .image_type(vk::ImageType::_2D)
         .extent(vk::Extent3D { width, height, depth: 1 })
@@ -728,7 +1613,55 @@ pub unsafe fn create_image(instance: &Instance, device: &Device, data: &AppData,
         .tiling(tiling).initial_layout(vk::ImageLayout::UNDEFINED)
         .usage(usage).samples(vk::SampleCountFlags::_1)
         .sharing_mode(vk::SharingMode::EXCLUSIVE);
-    let image = device.create_image(&info, None)?;
+    allocate_image(instance, device, data, &info, properties)
+}
+
+/// Like `create_image`, but of any dimensionality, `dims` texels in device-local memory and `mip_levels`
+/// levels, with a view of them all: a 1D image for a lookup table, a 3D one for a volume.
+pub unsafe fn create_texture(instance: &Instance, device: &Device, data: &AppData, view_type: vk::ImageViewType,
+    [width, height, depth]: [u32; 3], format: vk::Format, mip_levels: u32, usage: vk::ImageUsageFlags,
+) -> Result<(vk::Image, vk::DeviceMemory, vk::ImageView)> {
+    let image_type = match view_type {
+        vk::ImageViewType::_1D => vk::ImageType::_1D,
+        vk::ImageViewType::_3D => vk::ImageType::_3D,
+        _ => vk::ImageType::_2D,
+    };
+    // a cubemap's six faces are layers of a 2D image
+    let (layers, flags) = match view_type {
+        vk::ImageViewType::CUBE => (6, vk::ImageCreateFlags::CUBE_COMPATIBLE),
+        _ => (1, vk::ImageCreateFlags::empty()),
+    };
+    let info = vk::ImageCreateInfo::builder()
+        .flags(flags)
+        .image_type(image_type)
+        .extent(vk::Extent3D { width, height, depth })
+        .mip_levels(mip_levels).array_layers(layers).format(format)
+        .tiling(vk::ImageTiling::OPTIMAL).initial_layout(vk::ImageLayout::UNDEFINED)
+        .usage(usage).samples(vk::SampleCountFlags::_1)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    let (image, memory) = allocate_image(instance, device, data, &info, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+    let view = create_image_view_of_type(device, image, view_type, format, vk::ImageAspectFlags::COLOR, mip_levels,
+        layers)?;
+    Ok((image, memory, view))
+}
+
+/// Levels of a full mip chain for an image of `dims` texels, down to a single texel.
+pub fn mip_level_count([width, height, depth]: [u32; 3]) -> u32 {
+    u32::BITS - width.max(height).max(depth).max(1).leading_zeros()
+}
+
+/// Whether the device can blit images of `format` into their smaller mip levels with linear filtering.
+pub unsafe fn mipmaps_supported(instance: &Instance, data: &AppData, format: vk::Format) -> bool {
+    let properties = instance.get_physical_device_format_properties(data.physical_device, format);
+    properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR
+        | vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST)
+}
+
+pub(crate) unsafe fn allocate_image(instance: &Instance, device: &Device, data: &AppData,
+    info: &vk::ImageCreateInfo, properties: vk::MemoryPropertyFlags,
+) -> Result<(vk::Image, vk::DeviceMemory)> {
+    // Image
+    let image = device.create_image(info, None)?;
 
     // Memory
     let requirements = device.get_image_memory_requirements(image);
@@ -741,27 +1674,116 @@ pub unsafe fn create_image(instance: &Instance, device: &Device, data: &AppData,
     Ok((image, image_memory))
 }
 
-unsafe fn create_image_view(device: &Device, image: vk::Image,
+/// Fills an image made by `create_texture` of `dims` texels with `texels`, laid out x fastest in its
+/// format, and leaves it ready for fragment shaders to sample; whatever it held before is dropped. Past
+/// one of its `mip_levels`, the rest are blitted down from the first, which takes a 2D image of a format
+/// `mipmaps_supported` says can be.
+pub unsafe fn upload_texture<T: Copy>(instance: &Instance, device: &Device, data: &AppData, image: vk::Image,
+    format: vk::Format, [width, height, depth]: [u32; 3], layers: u32, mip_levels: u32, texels: &[T],
+) -> Result<()> {
+    let size = std::mem::size_of_val(texels) as u64;
+    let (staging_buffer, staging_buffer_memory) = create_buffer(
+        instance, device, data, size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+    )?;
+    let memory = device.map_memory(staging_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    memcpy(texels.as_ptr(), memory.cast(), texels.len());
+    device.unmap_memory(staging_buffer_memory);
+
+    transition_image_layout(device, data, image, format, vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        0, mip_levels, layers)?;
+    let command_buffer = begin_single_time_commands(device, data)?;
+    let subresource = vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR).mip_level(0).base_array_layer(0).layer_count(layers);
+    let region = vk::BufferImageCopy::builder()
+        .buffer_offset(0).buffer_row_length(0).buffer_image_height(0)
+        .image_subresource(subresource)
+        .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+        .image_extent(vk::Extent3D { width, height, depth });
+    device.cmd_copy_buffer_to_image(command_buffer, staging_buffer, image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[region]);
+    end_single_time_commands(device, data, command_buffer)?;
+    if mip_levels > 1 {
+        generate_mipmaps(device, data, image, [width, height], mip_levels)?;
+    } else {
+        transition_image_layout(device, data, image, format,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL, 0, 1, layers)?;
+    }
+
+    device.destroy_buffer(staging_buffer, None);
+    device.free_memory(staging_buffer_memory, None);
+    Ok(())
+}
+
+/// Blits each mip level of a 2D `image` of `dims` texels down from the one before, starting from the
+/// first, which must be in TRANSFER_DST_OPTIMAL like the rest, and leaves them all ready for fragment
+/// shaders to sample.
+unsafe fn generate_mipmaps(device: &Device, data: &AppData, image: vk::Image, [width, height]: [u32; 2],
+    mip_levels: u32) -> Result<()> {
+    let command_buffer = begin_single_time_commands(device, data)?;
+    let barrier = |level: u32, old_layout, new_layout, src_access_mask, dst_access_mask, dst_stage_mask| {
+        let subresource = vk::ImageSubresourceRange::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR).base_mip_level(level)
+            .level_count(1).base_array_layer(0).layer_count(1);
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout).new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED).dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image).subresource_range(subresource)
+            .src_access_mask(src_access_mask).dst_access_mask(dst_access_mask);
+        device.cmd_pipeline_barrier(command_buffer, vk::PipelineStageFlags::TRANSFER, dst_stage_mask,
+            vk::DependencyFlags::empty(), &[] as &[vk::MemoryBarrier], &[] as &[vk::BufferMemoryBarrier], &[barrier]);
+    };
+    let layers = |level: u32| vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR).mip_level(level).base_array_layer(0).layer_count(1).build();
+    let corner = |level: u32| vk::Offset3D {
+        x: (width >> level).max(1) as i32, y: (height >> level).max(1) as i32, z: 1 };
+    for level in 1..mip_levels {
+        // the level above is written, and read from here on
+        barrier(level - 1, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::TRANSFER_READ, vk::PipelineStageFlags::TRANSFER);
+        let blit = vk::ImageBlit::builder()
+            .src_subresource(layers(level - 1)).src_offsets([vk::Offset3D::default(), corner(level - 1)])
+            .dst_subresource(layers(level)).dst_offsets([vk::Offset3D::default(), corner(level)]);
+        device.cmd_blit_image(command_buffer, image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[blit], vk::Filter::LINEAR);
+        barrier(level - 1, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::AccessFlags::TRANSFER_READ, vk::AccessFlags::SHADER_READ, vk::PipelineStageFlags::FRAGMENT_SHADER);
+    }
+    barrier(mip_levels - 1, vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::SHADER_READ, vk::PipelineStageFlags::FRAGMENT_SHADER);
+    end_single_time_commands(device, data, command_buffer)
+}
+
+pub(crate) unsafe fn create_image_view(device: &Device, image: vk::Image,
     format: vk::Format, aspects: vk::ImageAspectFlags,
+) -> Result<vk::ImageView> {
+    create_image_view_of_type(device, image, vk::ImageViewType::_2D, format, aspects, 1, 1)
+}
+
+unsafe fn create_image_view_of_type(device: &Device, image: vk::Image, view_type: vk::ImageViewType,
+    format: vk::Format, aspects: vk::ImageAspectFlags, mip_levels: u32, layers: u32,
 ) -> Result<vk::ImageView> {
     let subresource_range = vk::ImageSubresourceRange::builder()
         .aspect_mask(aspects)
         .base_mip_level(0)
-        .level_count(1)
+        .level_count(mip_levels)
         .base_array_layer(0)
-        .layer_count(1);
+        .layer_count(layers);
 
     let info = vk::ImageViewCreateInfo::builder()
         .image(image)
-        .view_type(vk::ImageViewType::_2D)
+        .view_type(view_type)
         .format(format)
         .subresource_range(subresource_range);
 
     Ok(device.create_image_view(&info, None)?)
 }
 
+/// Moves mip levels `base_level` on, `level_count` of them, of the first `layers` layers of `image` from
+/// `old_layout` to `new_layout`.
 unsafe fn transition_image_layout(device: &Device, data: &AppData, image: vk::Image,
-    format: vk::Format, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout
+    format: vk::Format, old_layout: vk::ImageLayout, new_layout: vk::ImageLayout, base_level: u32, level_count: u32,
+    layers: u32,
 ) -> Result<()> {
     let (src_access_mask, dst_access_mask, src_stage_mask, dst_stage_mask) = match (old_layout, new_layout) {
         (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => (
@@ -798,8 +1820,8 @@ unsafe fn transition_image_layout(device: &Device, data: &AppData, image: vk::Im
     };
 
     let subresource = vk::ImageSubresourceRange::builder()
-        .aspect_mask(aspect_mask).base_mip_level(0)
-        .level_count(1).base_array_layer(0).layer_count(1);
+        .aspect_mask(aspect_mask).base_mip_level(base_level)
+        .level_count(level_count).base_array_layer(0).layer_count(layers);
 
     let barrier = vk::ImageMemoryBarrier::builder()
         .old_layout(old_layout).new_layout(new_layout)
@@ -842,3 +1864,22 @@ pub unsafe fn end_single_time_commands(device: &Device, data: &AppData, command_
     device.free_command_buffers(data.command_pool, &[command_buffer]);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_errors_name_their_line() {
+        let path = std::env::temp_dir().join(format!("sph_bad_shader_{}.comp", std::process::id()));
+        fs::write(&path, "#version 450\nlayout(local_size_x = 1) in;\n#error deliberately broken\nvoid main() {}\n").unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let result = compile_glsl(&path, shaderc::ShaderKind::Compute, &[]);
+        fs::remove_file(&path).unwrap();
+        let error = result.unwrap_err();
+        let error = error.downcast_ref::<ShaderCompileError>().expect("a ShaderCompileError");
+        assert_eq!(error.path, path);
+        assert_eq!(error.line, Some(3), "{}", error);
+        assert!(error.to_string().contains("at line 3"), "{}", error);
+    }
+}
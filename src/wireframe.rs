@@ -0,0 +1,318 @@
+use std::mem::size_of;
+use anyhow::Result;
+use nalgebra_glm as glm;
+use vulkanalia::prelude::v1_0::*;
+
+use crate::appdata::AppData;
+use crate::camera::scene_model;
+use crate::config::*;
+use crate::model::Object;
+use crate::reconstruct::FluidRendering;
+use crate::utils::{compile_shader, create_buffer, create_shader_module};
+
+/// What a wireframe box outlines, which picks its color.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WireKind {
+    Domain,
+    Emitter,
+    Sink,
+    Inflow,
+    Outflow,
+}
+
+impl WireKind {
+    pub fn color(self) -> glm::Vec3 {
+        glm::make_vec3(match self {
+            WireKind::Domain => &DOMAIN_WIRE_COLOR,
+            WireKind::Emitter => &EMITTER_WIRE_COLOR,
+            WireKind::Sink => &SINK_WIRE_COLOR,
+            WireKind::Inflow => &INFLOW_WIRE_COLOR,
+            WireKind::Outflow => &OUTFLOW_WIRE_COLOR,
+        })
+    }
+}
+
+/// An axis-aligned box outlined in the frame the fluid is simulated in.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WireBox {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+    pub kind: WireKind,
+}
+
+/// Vertex of the wireframe pipeline, laid out like the inputs of `wire.vert`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct WireVertex {
+    pos: glm::Vec3,
+    color: glm::Vec3,
+}
+
+/// Vertices each box's twelve edges take as a line list.
+const BOX_VERTICES: u32 = 24;
+
+impl WireBox {
+    /// The twelve edges of the box, as a line list.
+    fn edges(&self) -> impl Iterator<Item = WireVertex> + '_ {
+        let color = self.kind.color();
+        let corner = move |k: u32| glm::vec3(
+            if k & 1 == 0 { self.min.x } else { self.max.x },
+            if k & 2 == 0 { self.min.y } else { self.max.y },
+            if k & 4 == 0 { self.min.z } else { self.max.z });
+        // corners one bit apart share an edge
+        (0..8u32).flat_map(|k| [1, 2, 4].into_iter().filter(move |bit| k & bit == 0).map(move |bit| (k, k | bit)))
+            .flat_map(move |(a, b)| [a, b].map(|k| WireVertex { pos: corner(k), color }))
+    }
+}
+
+/// The objects drawn as the edges of their triangles instead of filled, back faces and all, where the
+/// device has `fillModeNonSolid`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MeshWireframe {
+    /// Line width in pixels, within what the device draws; 1 where it has no wide lines.
+    pub width: f32,
+}
+
+impl Default for MeshWireframe {
+    fn default() -> Self {
+        Self { width: MESH_WIREFRAME_WIDTH }
+    }
+}
+
+/// Short lines out of the vertices of the objects and the fluid surface along their normals, colored by
+/// direction, for checking the normals meshes were loaded or reconstructed with. Built when turned on
+/// and whenever the meshes change, not every frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NormalLines {
+    /// Meters each line is long.
+    pub length: f32,
+    /// A line is drawn from every this many vertices of a mesh.
+    pub stride: u32,
+}
+
+impl Default for NormalLines {
+    fn default() -> Self {
+        Self { length: NORMAL_LINE_LENGTH, stride: NORMAL_LINE_STRIDE }
+    }
+}
+
+impl NormalLines {
+    /// The lines of `object`'s vertices, in its own frame, sized so its transform draws them `length`
+    /// long. Vertices without a normal get none.
+    fn lines<'a>(&self, object: &'a Object) -> impl Iterator<Item = WireVertex> + 'a {
+        let scale = glm::determinant(&glm::mat4_to_mat3(&object.transform)).abs().cbrt();
+        let length = if scale > 0.0 { self.length / scale } else { 0.0 };
+        object.vertices.iter().step_by(self.stride.max(1) as usize).flat_map(move |vertex| {
+            let normal = vertex.normal.try_normalize(f32::EPSILON).unwrap_or_else(glm::Vec3::zeros);
+            let color = normal.add_scalar(1.0) / 2.0;
+            [vertex.pos, vertex.pos + normal * length].map(|pos| WireVertex { pos, color })
+        })
+    }
+}
+
+/// Creates the pipeline that draws the wireframe boxes: lines tested against the scene's depth without
+/// writing it, so the boxes never hide the particles or surface inside them.
+pub unsafe fn create_wire_pipeline(device: &Device, data: &mut AppData) -> Result<()> {
+    let vshader = compile_shader(&WIRE_VERTEX_SHADER.to_string(), shaderc::ShaderKind::Vertex)?;
+    let fshader = compile_shader(&WIRE_FRAGMENT_SHADER.to_string(), shaderc::ShaderKind::Fragment)?;
+    let vert_shader_module = create_shader_module(device, vshader.as_binary_u8())?;
+    let frag_shader_module = create_shader_module(device, fshader.as_binary_u8())?;
+    let vert_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::VERTEX)
+        .module(vert_shader_module)
+        .name(b"main\0");
+    let frag_stage = vk::PipelineShaderStageCreateInfo::builder()
+        .stage(vk::ShaderStageFlags::FRAGMENT)
+        .module(frag_shader_module)
+        .name(b"main\0");
+
+    let binding_descs = &[vk::VertexInputBindingDescription::builder()
+        .binding(0)
+        .stride(size_of::<WireVertex>() as u32)
+        .input_rate(vk::VertexInputRate::VERTEX)
+        .build()];
+    let attribute_descs = &[
+        vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(0).format(vk::Format::R32G32B32_SFLOAT).offset(0).build(),
+        vk::VertexInputAttributeDescription::builder()
+            .binding(0).location(1).format(vk::Format::R32G32B32_SFLOAT).offset(size_of::<glm::Vec3>() as u32).build(),
+    ];
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(binding_descs)
+        .vertex_attribute_descriptions(attribute_descs);
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::LINE_LIST)
+        .primitive_restart_enable(false);
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder().viewport_count(1).scissor_count(1);
+    let dynamic_states = &[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state = vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(dynamic_states);
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE);
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .sample_shading_enable(false)
+        .rasterization_samples(data.msaa_samples);
+    let attachment = vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::all())
+        .blend_enable(false);
+    let attachments = &[attachment];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .logic_op(vk::LogicOp::COPY)
+        .attachments(attachments)
+        .blend_constants([0.0, 0.0, 0.0, 0.0]);
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true).depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS).depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0).max_depth_bounds(1.0).stencil_test_enable(false);
+
+    // the scene's uniform buffer, and the transform of the boxes being drawn as a push constant
+    let set_layouts = &[data.descriptor_set_layout];
+    let push_range = vk::PushConstantRange::builder()
+        .stage_flags(vk::ShaderStageFlags::VERTEX)
+        .offset(0)
+        .size(size_of::<glm::Mat4>() as u32);
+    let push_ranges = &[push_range];
+    let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(set_layouts).push_constant_ranges(push_ranges);
+    data.wire_pipeline_layout = device.create_pipeline_layout(&layout_info, None)?;
+    let stages = &[vert_stage, frag_stage];
+    let info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .dynamic_state(&dynamic_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(data.wire_pipeline_layout)
+        .render_pass(data.render_pass)
+        .subpass(0);
+    data.wire_pipeline = device.create_graphics_pipelines(data.pipeline_cache, &[info], None)?.0;
+    device.destroy_shader_module(vert_shader_module, None);
+    device.destroy_shader_module(frag_shader_module, None);
+    Ok(())
+}
+
+pub unsafe fn destroy_wire_pipeline(device: &Device, data: &AppData) {
+    device.destroy_pipeline(data.wire_pipeline, None);
+    device.destroy_pipeline_layout(data.wire_pipeline_layout, None);
+}
+
+/// Rebuilds the vertex buffer of the wireframe boxes if `boxes` differ from the ones it holds. The
+/// domain's box comes first, as `record_wire_draw` draws it with the container's transform.
+pub unsafe fn update_wire_boxes(instance: &Instance, device: &Device, data: &mut AppData, boxes: Vec<WireBox>)
+-> Result<()> {
+    if boxes == data.wire_boxes {
+        return Ok(());
+    }
+    // the frames in flight may still draw from the old buffer
+    device.device_wait_idle()?;
+    destroy_wire_buffer(device, data);
+    let vertices = boxes.iter().flat_map(WireBox::edges).collect::<Vec<_>>();
+    data.wire_boxes = boxes;
+    if vertices.is_empty() {
+        return Ok(());
+    }
+    let size = (size_of::<WireVertex>() * vertices.len()) as u64;
+    (data.wire_buffer, data.wire_buffer_memory) = create_buffer(instance, device, data, size,
+        vk::BufferUsageFlags::VERTEX_BUFFER,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE)?;
+    let memory = device.map_memory(data.wire_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    std::ptr::copy_nonoverlapping(vertices.as_ptr(), memory.cast(), vertices.len());
+    device.unmap_memory(data.wire_buffer_memory);
+    Ok(())
+}
+
+pub unsafe fn destroy_wire_buffer(device: &Device, data: &mut AppData) {
+    device.destroy_buffer(data.wire_buffer, None);
+    device.free_memory(data.wire_buffer_memory, None);
+    data.wire_buffer = vk::Buffer::null();
+    data.wire_buffer_memory = vk::DeviceMemory::null();
+    data.wire_boxes.clear();
+}
+
+/// Records the wireframe boxes into swapchain image `i`'s render pass: the domain's where the container
+/// motion has it, the regions' where they are.
+pub unsafe fn record_wire_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize) {
+    if data.wire_boxes.is_empty() {
+        return;
+    }
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.wire_pipeline);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.wire_pipeline_layout, 0, &[data.descriptor_sets[i]], &[]);
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[data.wire_buffer], &[0]);
+    let identity = glm::Mat4::identity();
+    let count = data.wire_boxes.len() as u32 * BOX_VERTICES;
+    for (transform, vertices) in [(&data.domain_transform, 0..BOX_VERTICES), (&identity, BOX_VERTICES..count)] {
+        if vertices.is_empty() {
+            continue;
+        }
+        device.cmd_push_constants(command_buffer, data.wire_pipeline_layout, vk::ShaderStageFlags::VERTEX, 0,
+            std::slice::from_raw_parts(transform.as_ptr().cast::<u8>(), size_of::<glm::Mat4>()));
+        device.cmd_draw(command_buffer, vertices.len() as u32, 1, vertices.start, 0);
+    }
+}
+
+/// Rebuilds the vertex buffer of the normal lines from the objects and the fluid surface as they are now,
+/// or frees it if none are drawn.
+pub unsafe fn update_normal_lines(instance: &Instance, device: &Device, data: &mut AppData) -> Result<()> {
+    // the frames in flight may still draw from the old buffer
+    device.device_wait_idle()?;
+    destroy_normal_buffer(device, data);
+    let Some(lines) = data.normal_lines else { return Ok(()) };
+    let meshes = data.objects.iter().enumerate().map(|(k, object)| (Some(k), object))
+        .chain(std::iter::once((None, &data.fluid_surface)));
+    let mut vertices = Vec::new();
+    for (object, mesh) in meshes {
+        let first = vertices.len() as u32;
+        vertices.extend(lines.lines(mesh));
+        if vertices.len() as u32 > first {
+            data.normal_ranges.push((object, first..vertices.len() as u32));
+        }
+    }
+    if vertices.is_empty() {
+        return Ok(());
+    }
+    let size = (size_of::<WireVertex>() * vertices.len()) as u64;
+    (data.normal_buffer, data.normal_buffer_memory) = create_buffer(instance, device, data, size,
+        vk::BufferUsageFlags::VERTEX_BUFFER,
+        vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE)?;
+    let memory = device.map_memory(data.normal_buffer_memory, 0, size, vk::MemoryMapFlags::empty())?;
+    std::ptr::copy_nonoverlapping(vertices.as_ptr(), memory.cast(), vertices.len());
+    device.unmap_memory(data.normal_buffer_memory);
+    Ok(())
+}
+
+pub unsafe fn destroy_normal_buffer(device: &Device, data: &mut AppData) {
+    device.destroy_buffer(data.normal_buffer, None);
+    device.free_memory(data.normal_buffer_memory, None);
+    data.normal_buffer = vk::Buffer::null();
+    data.normal_buffer_memory = vk::DeviceMemory::null();
+    data.normal_ranges.clear();
+}
+
+/// Records the normal lines into swapchain image `i`'s render pass, each mesh's where it is drawn now
+/// with the fluid drawn as `rendering`.
+pub unsafe fn record_normal_draw(device: &Device, data: &AppData, command_buffer: vk::CommandBuffer, i: usize,
+    rendering: FluidRendering) {
+    if data.normal_lines.is_none() || data.normal_ranges.is_empty() {
+        return;
+    }
+    device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, data.wire_pipeline);
+    device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS,
+        data.wire_pipeline_layout, 0, &[data.descriptor_sets[i]], &[]);
+    device.cmd_bind_vertex_buffers(command_buffer, 0, &[data.normal_buffer], &[0]);
+    for (object, vertices) in &data.normal_ranges {
+        // the surface's lines only while the surface itself is drawn
+        let surface = (rendering == FluidRendering::Surface).then_some(&data.fluid_surface);
+        let Some(mesh) = object.map_or(surface, |k| data.objects.get(k)) else { continue };
+        // the meshes' transforms go before the scene model, which the wire shader leaves out
+        let transform = scene_model() * mesh.transform;
+        device.cmd_push_constants(command_buffer, data.wire_pipeline_layout, vk::ShaderStageFlags::VERTEX, 0,
+            std::slice::from_raw_parts(transform.as_ptr().cast::<u8>(), size_of::<glm::Mat4>()));
+        device.cmd_draw(command_buffer, vertices.len() as u32, 1, vertices.start, 0);
+    }
+}